@@ -0,0 +1,377 @@
+//! Checks of hook scripts and commands for common security issues
+
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How serious an audit finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuditSeverity {
+    /// Worth a look, but usually harmless
+    Low,
+    /// Can be misused, such as downloads or writes outside the repository
+    Medium,
+    /// Runs untrusted code; `hookmaster audit` fails on these
+    High,
+}
+
+impl AuditSeverity {
+    /// Name used in audit output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditSeverity::Low => "low",
+            AuditSeverity::Medium => "medium",
+            AuditSeverity::High => "high",
+        }
+    }
+}
+
+impl fmt::Display for AuditSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A potential security issue in a hook
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditFinding {
+    /// Hook (or `hook/command`) the issue was found in
+    pub hook: String,
+    /// How serious the issue is
+    pub severity: AuditSeverity,
+    /// What the hook does, e.g. `downloads files at runtime (curl)`
+    pub description: String,
+}
+
+/// Programs that download files
+const DOWNLOADERS: [&str; 3] = ["curl", "wget", "fetch"];
+
+/// Programs that run code read from stdin
+const INTERPRETERS: [&str; 9] = [
+    "sh", "bash", "zsh", "dash", "python", "python3", "node", "perl", "ruby",
+];
+
+/// Words that run the following command, so the program is the word after them
+const PREFIXES: [&str; 5] = ["sudo", "exec", "command", "env", "nohup"];
+
+/// Well-known world-writable directories
+const WORLD_WRITABLE: [&str; 3] = ["/tmp", "/var/tmp", "/dev/shm"];
+
+/// Files outside the repository that are fine to write to
+const HARMLESS_TARGETS: [&str; 4] = ["/dev/null", "/dev/stdout", "/dev/stderr", "/dev/tty"];
+
+/// Check a hook script or command for code downloaded at runtime, `eval` of
+/// variable input, writes outside the repository and world-writable
+/// directories on `PATH`. Comment lines are ignored.
+pub fn audit_script(hook: &str, script: &str) -> Vec<AuditFinding> {
+    let mut findings = Vec::new();
+    let mut add = |severity, description: String| {
+        let finding = AuditFinding {
+            hook: hook.to_string(),
+            severity,
+            description,
+        };
+        if !findings.contains(&finding) {
+            findings.push(finding);
+        }
+    };
+
+    for line in script.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let segments = split_commands(line);
+        for (i, segment) in segments.iter().enumerate() {
+            let words: Vec<&str> = segment.text.split_whitespace().collect();
+            let Some(invocation) = Invocation::parse(&words) else {
+                continue;
+            };
+            let program = invocation.program;
+
+            if DOWNLOADERS.contains(&program) {
+                let piped_to = segments
+                    .get(i + 1)
+                    .filter(|next| next.piped)
+                    .and_then(|next| {
+                        let words: Vec<&str> = next.text.split_whitespace().collect();
+                        Invocation::parse(&words).map(|next| next.program.to_string())
+                    })
+                    .filter(|next| INTERPRETERS.contains(&next.as_str()));
+                match piped_to {
+                    Some(interpreter) => add(
+                        AuditSeverity::High,
+                        format!(
+                            "runs code downloaded at runtime ({program} piped to {interpreter})"
+                        ),
+                    ),
+                    None => add(
+                        AuditSeverity::Medium,
+                        format!("downloads files at runtime ({program})"),
+                    ),
+                }
+            }
+
+            // The evaluated text may be a `$(...)` spanning later segments
+            if program == "eval" && segment.rest.contains('$') {
+                add(
+                    AuditSeverity::High,
+                    "uses eval with variable input".to_string(),
+                );
+            }
+
+            if program == "tee" {
+                for target in invocation.args.iter().filter(|arg| !arg.starts_with('-')) {
+                    if is_outside_repo(target) {
+                        add(
+                            AuditSeverity::Medium,
+                            format!("writes outside the repository ({target})"),
+                        );
+                    }
+                }
+            }
+        }
+
+        for target in redirect_targets(line) {
+            if is_outside_repo(&target) {
+                add(
+                    AuditSeverity::Medium,
+                    format!("writes outside the repository ({target})"),
+                );
+            }
+        }
+
+        for dir in path_assignments(line) {
+            if is_world_writable(&dir) {
+                let dir = if dir.is_empty() { "." } else { dir.as_str() };
+                add(
+                    AuditSeverity::High,
+                    format!("adds a world-writable directory to PATH ({dir})"),
+                );
+            }
+        }
+    }
+
+    findings
+}
+
+/// A simple command in a shell line
+pub(crate) struct Segment<'a> {
+    /// The command's words, up to the next separator
+    pub(crate) text: &'a str,
+    /// The line from the start of this command
+    rest: &'a str,
+    /// The previous command's output is piped into this one
+    piped: bool,
+}
+
+/// Split a shell line into simple commands at `;`, `&&`, `||`, `|`, `&`,
+/// `$(`, `(`, `)` and backticks. Quoting is not taken into account.
+pub(crate) fn split_commands(line: &str) -> Vec<Segment<'_>> {
+    static SEPARATOR: OnceLock<Regex> = OnceLock::new();
+    let separator = SEPARATOR.get_or_init(|| Regex::new(r"\|\||&&|\$\(|[;|&()`]").unwrap());
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut piped = false;
+    for m in separator.find_iter(line) {
+        segments.push(Segment {
+            text: &line[start..m.start()],
+            rest: &line[start..],
+            piped,
+        });
+        piped = m.as_str() == "|";
+        start = m.end();
+    }
+    segments.push(Segment {
+        text: &line[start..],
+        rest: &line[start..],
+        piped,
+    });
+    segments
+}
+
+/// The program a simple command runs and its arguments
+struct Invocation<'a> {
+    /// File name of the program
+    program: &'a str,
+    args: &'a [&'a str],
+}
+
+impl<'a> Invocation<'a> {
+    /// Find the program in a simple command's words, skipping variable
+    /// assignments and prefixes such as `sudo`
+    fn parse(words: &'a [&'a str]) -> Option<Self> {
+        let start = words
+            .iter()
+            .position(|word| !is_assignment(word) && !PREFIXES.contains(word))?;
+        let program = words[start].rsplit('/').next().unwrap_or(words[start]);
+        Some(Self {
+            program,
+            args: &words[start + 1..],
+        })
+    }
+}
+
+/// Whether `word` is a `NAME=value` variable assignment rather than a
+/// program or an argument such as `--flag=value`
+pub(crate) fn is_assignment(word: &str) -> bool {
+    word.split_once('=')
+        .is_some_and(|(name, _)| !name.is_empty() && !name.starts_with('-'))
+}
+
+/// Targets of output redirections (`>`, `>>`, `2>`, `&>`) in a line
+fn redirect_targets(line: &str) -> Vec<String> {
+    static REDIRECT: OnceLock<Regex> = OnceLock::new();
+    let redirect = REDIRECT
+        .get_or_init(|| Regex::new(r#"(?:^|[^<>&])(?:[0-9]?>>?|&>>?)\s*([^\s;&|()<>]+)"#).unwrap());
+    redirect
+        .captures_iter(line)
+        .map(|c| c[1].trim_matches(|ch| ch == '"' || ch == '\'').to_string())
+        .filter(|target| !target.starts_with('&'))
+        .collect()
+}
+
+/// Directories added by `PATH=...` assignments in a line
+fn path_assignments(line: &str) -> Vec<String> {
+    static ASSIGNMENT: OnceLock<Regex> = OnceLock::new();
+    let assignment = ASSIGNMENT.get_or_init(|| {
+        Regex::new(r#"(?:^|[\s;])(?:export\s+)?PATH=("[^"]*"|'[^']*'|\S*)"#).unwrap()
+    });
+    assignment
+        .captures_iter(line)
+        .flat_map(|c| {
+            let value = c[1].trim_matches(|ch| ch == '"' || ch == '\'').to_string();
+            value
+                .split(':')
+                .filter(|dir| !dir.starts_with('$'))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Whether a write to `target` would land outside the repository
+fn is_outside_repo(target: &str) -> bool {
+    if HARMLESS_TARGETS.contains(&target) || target.starts_with("/dev/fd/") {
+        return false;
+    }
+    target.starts_with('/')
+        || target.starts_with('~')
+        || target.starts_with("$HOME")
+        || target.starts_with("${HOME}")
+        || target == ".."
+        || target.starts_with("../")
+}
+
+/// Whether `dir` on `PATH` can be written by other users. An empty entry
+/// or `.` means the current directory.
+fn is_world_writable(dir: &str) -> bool {
+    if dir.is_empty() || dir == "." {
+        return true;
+    }
+    if WORLD_WRITABLE
+        .iter()
+        .any(|w| dir == *w || dir.starts_with(&format!("{w}/")))
+    {
+        return true;
+    }
+    has_world_writable_mode(Path::new(dir))
+}
+
+#[cfg(unix)]
+fn has_world_writable_mode(dir: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    dir.is_absolute()
+        && std::fs::metadata(dir).is_ok_and(|m| m.is_dir() && m.permissions().mode() & 0o002 != 0)
+}
+
+#[cfg(not(unix))]
+fn has_world_writable_mode(_dir: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Findings for a script as `severity: description`
+    fn findings(script: &str) -> Vec<String> {
+        audit_script("pre-commit", script)
+            .into_iter()
+            .map(|f| format!("{}: {}", f.severity, f.description))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_scripts() {
+        for script in [
+            "#!/bin/sh\nhookmaster run pre-commit \"$@\"\n",
+            "cargo fmt --check && cargo clippy -- -D warnings",
+            "git fetch origin && cargo test 2>&1 > target/test.log",
+            "npm test > /dev/null 2>&1",
+            "# curl https://example.com | sh",
+            "export PATH=\"$HOME/.cargo/bin:$PATH\"",
+        ] {
+            assert!(findings(script).is_empty(), "{script}");
+        }
+    }
+
+    #[test]
+    fn test_downloads() {
+        assert_eq!(
+            findings("curl -fsSL https://example.com/install.sh | sh"),
+            vec!["high: runs code downloaded at runtime (curl piped to sh)"]
+        );
+        assert_eq!(
+            findings("wget -q https://example.com/tool.tar.gz && tar xf tool.tar.gz"),
+            vec!["medium: downloads files at runtime (wget)"]
+        );
+        assert_eq!(
+            findings("/usr/bin/fetch http://example.com/x"),
+            vec!["medium: downloads files at runtime (fetch)"]
+        );
+    }
+
+    #[test]
+    fn test_eval_with_variable_input() {
+        assert_eq!(
+            findings("eval \"$(cat .hookrc)\""),
+            vec!["high: uses eval with variable input"]
+        );
+        assert!(findings("eval echo hello").is_empty());
+    }
+
+    #[test]
+    fn test_writes_outside_repo() {
+        assert_eq!(
+            findings("echo done >> ~/.bashrc; cargo test | tee /var/log/hooks.log"),
+            vec![
+                "medium: writes outside the repository (/var/log/hooks.log)",
+                "medium: writes outside the repository (~/.bashrc)",
+            ]
+        );
+        assert_eq!(
+            findings("cargo build 2> \"../err.log\""),
+            vec!["medium: writes outside the repository (../err.log)"]
+        );
+    }
+
+    #[test]
+    fn test_world_writable_path() {
+        assert_eq!(
+            findings("PATH=/tmp/bin:$PATH make check"),
+            vec!["high: adds a world-writable directory to PATH (/tmp/bin)"]
+        );
+        assert_eq!(
+            findings("export PATH=\".:$PATH\""),
+            vec!["high: adds a world-writable directory to PATH (.)"]
+        );
+        assert_eq!(
+            findings("export PATH=$PATH:"),
+            vec!["high: adds a world-writable directory to PATH (.)"]
+        );
+    }
+}
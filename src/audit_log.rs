@@ -0,0 +1,217 @@
+//! The audit log `audit-log` in `[settings]` turns on: a JSON line for each
+//! command a hook runs, for environments that need a record of them
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::formatter;
+use crate::lock::{InstallLock, DEFAULT_LOCK_TIMEOUT};
+
+/// One command a hook ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// When the command finished, as an RFC 3339 UTC timestamp
+    pub timestamp: String,
+    /// Who ran it, from `USER` or `USERNAME`
+    pub user: String,
+    /// Root of the repository the hook ran in
+    pub repo: String,
+    /// Name of the hook, e.g. `pre-commit`
+    pub hook: String,
+    /// The command, as configured
+    pub command: String,
+    /// Exit code of the command
+    pub exit_code: i32,
+    /// How long the command ran, in milliseconds
+    pub duration_ms: u64,
+}
+
+impl AuditEntry {
+    /// An entry for `command` of `hook` in `repo`, finishing now
+    pub fn new(repo: &Path, hook: &str, command: &str, exit_code: i32, duration_ms: u64) -> Self {
+        Self {
+            timestamp: formatter::timestamp(SystemTime::now()),
+            user: env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_default(),
+            repo: repo.display().to_string(),
+            hook: hook.to_string(),
+            command: command.to_string(),
+            exit_code,
+            duration_ms,
+        }
+    }
+
+    fn to_json_line(&self) -> String {
+        json!({
+            "timestamp": self.timestamp,
+            "user": self.user,
+            "repo": self.repo,
+            "hook": self.hook,
+            "command": self.command,
+            "exit_code": self.exit_code,
+            "duration_ms": self.duration_ms,
+        })
+        .to_string()
+    }
+}
+
+/// Appends entries to the audit log file
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditLogger {
+    /// Path of the log file
+    pub path: PathBuf,
+}
+
+impl AuditLogger {
+    /// Logger for the file at `path`
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Logger for the `audit-log` setting: a leading `~` is the home
+    /// directory, and a relative path is taken from `root`
+    pub fn for_setting(setting: &str, root: &Path) -> Result<Self> {
+        let path = match setting.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+                let home = env::var_os("HOME")
+                    .or_else(|| env::var_os("USERPROFILE"))
+                    .ok_or_else(|| anyhow!("No home directory for audit-log '{setting}'"))?;
+                PathBuf::from(home).join(rest.trim_start_matches(['/', '\\']))
+            }
+            _ => root.join(setting),
+        };
+        Ok(Self::at(path))
+    }
+
+    /// Append `entry` to the log. The log is rewritten to a temporary file
+    /// that replaces it, while holding a lock next to it, so hooks running
+    /// at the same time neither corrupt it nor lose each other's entries.
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let _lock = InstallLock::acquire_file(self.sibling("lock"), DEFAULT_LOCK_TIMEOUT)?;
+
+        let mut content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+        // A line cut short, such as by a full disk, doesn't run into the entry
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&entry.to_json_line());
+        content.push('\n');
+
+        let temp = self.sibling("tmp");
+        fs::write(&temp, content).with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Path next to the log with `extension` added to its file name
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{extension}"));
+        self.path.with_file_name(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value as JsonValue;
+    use std::thread;
+    use tempfile::TempDir;
+
+    fn entry(command: &str, exit_code: i32) -> AuditEntry {
+        AuditEntry::new(Path::new("/repo"), "pre-commit", command, exit_code, 12)
+    }
+
+    #[test]
+    fn test_append() {
+        let temp_dir = TempDir::new().unwrap();
+        let logger = AuditLogger::at(temp_dir.path().join("logs").join("audit.log"));
+        logger.append(&entry("cargo test", 0)).unwrap();
+        logger.append(&entry("cargo clippy", 101)).unwrap();
+
+        let content = fs::read_to_string(&logger.path).unwrap();
+        let lines: Vec<JsonValue> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["command"], "cargo test");
+        assert_eq!(lines[1]["exit_code"], 101);
+        assert_eq!(lines[1]["hook"], "pre-commit");
+        assert_eq!(lines[1]["repo"], "/repo");
+        assert_eq!(lines[1]["duration_ms"], 12);
+        assert!(lines[1]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(lines[1]["user"].is_string());
+        // Neither the lock nor the temporary file is left behind
+        assert_eq!(
+            fs::read_dir(temp_dir.path().join("logs")).unwrap().count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_concurrent_appends_keep_every_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let logger = AuditLogger::at(temp_dir.path().join("audit.log"));
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let logger = logger.clone();
+                thread::spawn(move || logger.append(&entry(&format!("command {i}"), 0)).unwrap())
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let content = fs::read_to_string(&logger.path).unwrap();
+        assert_eq!(content.lines().count(), 8);
+        for line in content.lines() {
+            serde_json::from_str::<JsonValue>(line).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_for_setting() {
+        let root = Path::new("/repo");
+        assert_eq!(
+            AuditLogger::for_setting("/var/log/hookmaster/audit.log", root)
+                .unwrap()
+                .path,
+            PathBuf::from("/var/log/hookmaster/audit.log")
+        );
+        assert_eq!(
+            AuditLogger::for_setting("logs/audit.log", root)
+                .unwrap()
+                .path,
+            root.join("logs/audit.log")
+        );
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(
+                AuditLogger::for_setting("~/audit.log", root).unwrap().path,
+                PathBuf::from(home).join("audit.log")
+            );
+        }
+        // Only `~` on its own is the home directory
+        assert_eq!(
+            AuditLogger::for_setting("~user/audit.log", root)
+                .unwrap()
+                .path,
+            root.join("~user/audit.log")
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::CgroupConfig;
+
+/// Where the cgroup v2 hierarchy is mounted
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Period `cpu.max` quotas are given in, in microseconds
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// Bytes in a megabyte, as `max-memory-mb` counts them
+const MEGABYTE: u64 = 1024 * 1024;
+
+/// The `memory.max` of `megabytes`, in bytes; `None` if it doesn't fit
+pub fn memory_max(megabytes: u64) -> Option<u64> {
+    megabytes.checked_mul(MEGABYTE)
+}
+
+/// A cgroup v2 group at `<root>/hookmaster/<hook>` limiting the memory and
+/// CPU time of a hook's commands. The group is removed when dropped.
+#[derive(Debug)]
+pub struct Cgroup {
+    dir: PathBuf,
+}
+
+impl Cgroup {
+    /// Create the group for the commands of `hook_name`, if `limits` sets
+    /// any. Where cgroups are not supported or the group can't be set up, a
+    /// warning is printed and the commands run without limits.
+    pub fn for_limits(limits: &CgroupConfig, hook_name: &str) -> Option<Self> {
+        if limits.is_empty() {
+            return None;
+        }
+        if !cfg!(target_os = "linux") {
+            eprintln!(
+                "Warning: 'max-memory-mb' and 'max-cpu-percent' need cgroups, which are not supported on {}; running hook '{hook_name}' without limits",
+                std::env::consts::OS
+            );
+            return None;
+        }
+
+        match Self::create_in(Path::new(CGROUP_ROOT), hook_name, limits) {
+            Ok(cgroup) => Some(cgroup),
+            Err(err) => {
+                eprintln!(
+                    "Warning: failed to set up resource limits for hook '{hook_name}' ({err}); running it without them"
+                );
+                None
+            }
+        }
+    }
+
+    /// Create the group under the cgroup v2 hierarchy at `root` and write its limits
+    fn create_in(root: &Path, hook_name: &str, limits: &CgroupConfig) -> io::Result<Self> {
+        if !root.join("cgroup.controllers").is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no cgroup v2 hierarchy at {}", root.display()),
+            ));
+        }
+
+        // Controllers must be enabled in every parent before a group can use them
+        let parent = root.join("hookmaster");
+        fs::create_dir_all(&parent)?;
+        fs::write(root.join("cgroup.subtree_control"), "+memory +cpu")?;
+        fs::write(parent.join("cgroup.subtree_control"), "+memory +cpu")?;
+
+        let dir = parent.join(hook_name);
+        fs::create_dir_all(&dir)?;
+        let cgroup = Cgroup { dir };
+        if let Some(megabytes) = limits.max_memory_mb {
+            let bytes = memory_max(megabytes).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("max-memory-mb {megabytes} is too large"),
+                )
+            })?;
+            fs::write(cgroup.dir.join("memory.max"), bytes.to_string())?;
+            // Without swap, going over the limit gets the commands killed
+            // rather than slowed down; kernels without swap accounting lack the file
+            let _ = fs::write(cgroup.dir.join("memory.swap.max"), "0");
+            let _ = fs::write(cgroup.dir.join("memory.oom.group"), "1");
+        }
+        if let Some(percent) = limits.max_cpu_percent {
+            let quota = (percent / 100.0 * CPU_PERIOD_US as f64).round().max(1.0) as u64;
+            fs::write(
+                cgroup.dir.join("cpu.max"),
+                format!("{quota} {CPU_PERIOD_US}"),
+            )?;
+        }
+        Ok(cgroup)
+    }
+
+    /// Have `command` start in the group: between fork and exec, the new
+    /// process moves itself into it, before it can allocate memory or start
+    /// processes of its own. The returned `cgroup.procs` must stay open
+    /// until `command` has been spawned, which fails if the move does.
+    #[cfg(unix)]
+    pub fn add_on_spawn(&self, command: &mut Command) -> io::Result<fs::File> {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::process::CommandExt;
+
+        let procs = fs::OpenOptions::new()
+            .write(true)
+            .open(self.dir.join("cgroup.procs"))?;
+        let fd = procs.as_raw_fd();
+        // SAFETY: the closure only makes the write system call, which is
+        // async-signal-safe, and allocates nothing
+        unsafe {
+            command.pre_exec(move || {
+                // "0" stands for the process writing it
+                match libc::write(fd, b"0".as_ptr().cast(), 1) {
+                    1 => Ok(()),
+                    _ => Err(io::Error::last_os_error()),
+                }
+            });
+        }
+        Ok(procs)
+    }
+
+    /// Cgroups are only set up on Linux, so this is never called elsewhere
+    #[cfg(not(unix))]
+    pub fn add_on_spawn(&self, _command: &mut Command) -> io::Result<fs::File> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // Fails while processes are left in the group, which the kernel then
+        // keeps until they exit
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_create_writes_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let limits = CgroupConfig {
+            max_memory_mb: Some(512),
+            max_cpu_percent: Some(150.0),
+        };
+        assert!(Cgroup::create_in(root, "pre-commit", &limits).is_err());
+
+        fs::write(root.join("cgroup.controllers"), "cpu memory").unwrap();
+        let cgroup = Cgroup::create_in(root, "pre-commit", &limits).unwrap();
+        let dir = root.join("hookmaster").join("pre-commit");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("memory.max"), "536870912");
+        assert_eq!(read("memory.swap.max"), "0");
+        assert_eq!(read("cpu.max"), "150000 100000");
+        assert_eq!(
+            fs::read_to_string(root.join("cgroup.subtree_control")).unwrap(),
+            "+memory +cpu"
+        );
+
+        fs::write(dir.join("cgroup.procs"), "").unwrap();
+        let mut command = Command::new("true");
+        let procs = cgroup.add_on_spawn(&mut command).unwrap();
+        assert!(command.status().unwrap().success());
+        drop(procs);
+        assert_eq!(read("cgroup.procs"), "0");
+
+        let limits = CgroupConfig {
+            max_memory_mb: Some(u64::MAX / 1024),
+            max_cpu_percent: None,
+        };
+        let err = Cgroup::create_in(root, "pre-push", &limits).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_no_group_without_limits() {
+        assert!(Cgroup::for_limits(&CgroupConfig::default(), "pre-commit").is_none());
+    }
+}
@@ -0,0 +1,201 @@
+//! CI workflows running the same hooks as the local checks, for
+//! `hookmaster ci generate`, and detecting the CI service hookmaster runs on
+
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+use crate::config::GitHooksConfig;
+
+/// Hooks that check the code, and so make sense to run in CI, in the order
+/// the workflows list them. Hooks such as `commit-msg` need input only a
+/// local commit has.
+pub const CI_HOOKS: [&str; 2] = ["pre-commit", "pre-push"];
+
+/// Release archive the workflows install hookmaster from
+const DOWNLOAD_URL: &str =
+    "https://github.com/vivainio/hookmaster-rs/releases/latest/download/hookmaster-linux.tar.gz";
+
+/// CI service `hookmaster ci generate` writes a workflow for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CiProvider {
+    /// A GitHub Actions workflow run on pull requests
+    GitHub,
+    /// A GitLab CI job run on merge requests, for `.gitlab-ci.yml` to include
+    GitLab,
+}
+
+impl CiProvider {
+    /// Names accepted by `ci generate`
+    pub const NAMES: [&'static str; 2] = ["github", "gitlab"];
+
+    /// Parse a provider name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "github" => Some(CiProvider::GitHub),
+            "gitlab" => Some(CiProvider::GitLab),
+            _ => None,
+        }
+    }
+
+    /// Where the workflow goes, relative to the repository root
+    pub fn workflow_path(self) -> &'static Path {
+        match self {
+            CiProvider::GitHub => Path::new(".github/workflows/hookmaster.yml"),
+            CiProvider::GitLab => Path::new(".gitlab/hookmaster.yml"),
+        }
+    }
+
+    fn template(self) -> &'static str {
+        match self {
+            CiProvider::GitHub => include_str!("github.yml"),
+            CiProvider::GitLab => include_str!("gitlab.yml"),
+        }
+    }
+}
+
+/// CI service detected from the environment variables its runners set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CiEnvironment {
+    /// GitHub Actions, which sets `GITHUB_ACTIONS`
+    GitHub,
+    /// GitLab CI, which sets `GITLAB_CI`
+    GitLab,
+    /// Travis CI, which sets `TRAVIS`
+    Travis,
+    /// Another service setting `CI`, as most do
+    Generic,
+}
+
+impl CiEnvironment {
+    /// Name of the service, for messages
+    pub fn name(self) -> &'static str {
+        match self {
+            CiEnvironment::GitHub => "GitHub Actions",
+            CiEnvironment::GitLab => "GitLab CI",
+            CiEnvironment::Travis => "Travis CI",
+            CiEnvironment::Generic => "CI",
+        }
+    }
+}
+
+/// The file GitHub Actions shows as the summary of the step running
+/// hookmaster, `GITHUB_STEP_SUMMARY`; `None` outside GitHub Actions
+pub fn github_step_summary() -> Option<PathBuf> {
+    std::env::var_os("GITHUB_STEP_SUMMARY")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The CI service hookmaster runs on, if any. The variables of a specific
+/// service are checked before the generic `CI`; one set to `false` or `0`
+/// counts as unset.
+pub fn detect_ci_environment() -> Option<CiEnvironment> {
+    detect_from(|name| std::env::var(name).ok())
+}
+
+/// [`detect_ci_environment`] with the variables from `var`
+fn detect_from(var: impl Fn(&str) -> Option<String>) -> Option<CiEnvironment> {
+    let is_set = |name: &str| {
+        var(name).is_some_and(|value| !matches!(value.trim(), "" | "0" | "false" | "FALSE"))
+    };
+    [
+        ("GITHUB_ACTIONS", CiEnvironment::GitHub),
+        ("GITLAB_CI", CiEnvironment::GitLab),
+        ("TRAVIS", CiEnvironment::Travis),
+        ("CI", CiEnvironment::Generic),
+    ]
+    .into_iter()
+    .find(|(name, _)| is_set(name))
+    .map(|(_, environment)| environment)
+}
+
+/// The workflow for `provider` running the [`CI_HOOKS`] `config` defines,
+/// one job for each, with `source` naming the configuration file in its
+/// header
+pub fn generate(config: &GitHooksConfig, provider: CiProvider, source: &str) -> Result<String> {
+    let hooks: Vec<&str> = CI_HOOKS
+        .into_iter()
+        .filter(|hook| config.has_active_hook(hook))
+        .collect();
+    if hooks.is_empty() {
+        return Err(anyhow!(
+            "{source} defines none of the hooks CI can run: {}",
+            CI_HOOKS.join(", ")
+        ));
+    }
+    Ok(provider
+        .template()
+        .replace("{{source}}", source)
+        .replace("{{hooks}}", &hooks.join(", "))
+        .replace("{{download_url}}", DOWNLOAD_URL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_golden() {
+        let config = GitHooksConfig::parse_toml(
+            "pre-push = \"cargo test\"\ncommit-msg = \"check-msg\"\n\n[hooks.pre-commit]\nfmt = { command = \"cargo fmt --check\" }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            generate(&config, CiProvider::GitHub, "hookmaster.toml").unwrap(),
+            include_str!("../../tests/golden/ci/github.yml")
+        );
+        assert_eq!(
+            generate(&config, CiProvider::GitLab, "hookmaster.toml").unwrap(),
+            include_str!("../../tests/golden/ci/gitlab.yml")
+        );
+    }
+
+    #[test]
+    fn test_generate_only_configured_hooks() {
+        let config = GitHooksConfig::parse_toml("pre-push = \"cargo test\"\n").unwrap();
+        let workflow = generate(&config, CiProvider::GitHub, "githooks.toml").unwrap();
+        assert!(workflow.contains("        hook: [pre-push]\n"));
+        assert!(workflow
+            .starts_with("# Generated by `hookmaster ci generate github` from githooks.toml.\n"));
+
+        let config = GitHooksConfig::parse_toml("commit-msg = \"check-msg\"\n").unwrap();
+        let err = generate(&config, CiProvider::GitLab, "hookmaster.toml").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "hookmaster.toml defines none of the hooks CI can run: pre-commit, pre-push"
+        );
+    }
+
+    #[test]
+    fn test_detect_ci_environment() {
+        // `vars` as `NAME=value` separated by spaces
+        let detect = |vars: &str| {
+            detect_from(|name| {
+                vars.split_whitespace()
+                    .filter_map(|var| var.split_once('='))
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+        assert_eq!(detect(""), None);
+        assert_eq!(
+            detect("CI=true GITHUB_ACTIONS=true"),
+            Some(CiEnvironment::GitHub)
+        );
+        assert_eq!(
+            detect("CI=true GITLAB_CI=true"),
+            Some(CiEnvironment::GitLab)
+        );
+        assert_eq!(detect("TRAVIS=true"), Some(CiEnvironment::Travis));
+        assert_eq!(detect("CI=1"), Some(CiEnvironment::Generic));
+        assert_eq!(detect("CI=false"), None);
+        assert_eq!(detect("CI="), None);
+    }
+
+    #[test]
+    fn test_parse_provider() {
+        assert_eq!(CiProvider::parse("github"), Some(CiProvider::GitHub));
+        assert_eq!(CiProvider::parse("gitlab"), Some(CiProvider::GitLab));
+        assert_eq!(CiProvider::parse("jenkins"), None);
+    }
+}
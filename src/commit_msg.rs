@@ -1,13 +1,98 @@
+//! Prefixing commit messages with the ticket from the branch name
+
 use anyhow::{Context, Result};
 use regex::Regex;
-use std::fs;
-use std::path::Path;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::config::{CommitMsgSettings, Settings};
+use crate::placeholder;
+use crate::wrap;
+
+/// Separators recognized when the branch separator is `auto`, in order of preference
+const AUTO_SEPARATORS: [char; 3] = ['-', '_', '.'];
+
+/// Words joining the tickets of a branch naming several, as in
+/// `JIRA-123-and-JIRA-456`, which aren't part of the description
+const TICKET_CONNECTORS: [&str; 3] = ["and", "&", "+"];
+
+/// Environment variable with the ticket for branches without one, unless the
+/// `ticket-env-var` setting names another
+pub const DEFAULT_TICKET_ENV_VAR: &str = "JIRA_TICKET";
+
+/// Separator between the ticket number and the description in a branch name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchSeparator {
+    /// A fixed separator character (`-`, `_` or `.`)
+    Char(char),
+    /// Detect the separator from the branch name, trying `-`, `_` and `.` in order
+    Auto,
+}
+
+impl Default for BranchSeparator {
+    fn default() -> Self {
+        BranchSeparator::Char('-')
+    }
+}
+
+impl BranchSeparator {
+    /// Parse from the `branch-separator` setting value
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(BranchSeparator::Auto),
+            "-" | "_" | "." => s.chars().next().map(BranchSeparator::Char),
+            _ => None,
+        }
+    }
+}
+
 /// Commit message processor that formats messages based on branch names
+#[derive(Clone)]
 pub struct CommitMessageProcessor {
     ticket_regex: Regex,
     branch_cleanup_regex: Regex,
+    /// Prefix messages with every ticket in the branch name, not just the first
+    multi_ticket: bool,
+    /// Settings [`render`](Self::render) formats messages with
+    settings: Settings,
+    /// Settings of the `[commit-msg]` section, for the smart commit commands
+    commit_msg: CommitMsgSettings,
+    /// Character starting comment lines of commit messages, `core.commentChar`
+    comment_char: char,
+    /// Lines of `commit.template`, which messages started from it contain
+    /// without them being content
+    template_lines: Vec<String>,
+}
+
+/// The rule that gives the message `prepare-commit-msg` starts commits on a
+/// branch with. The rules are tried in the order of the variants, and the
+/// first that applies wins.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageRule {
+    /// The prefix of the first `[commit-msg.branch_prefixes]` pattern the
+    /// branch matches
+    BranchPrefix(String),
+    /// The ticket in the branch name, formatted as `[settings]` say
+    BranchTicket,
+    /// The ticket in the environment variable, on branches without one
+    TicketFromEnv(String),
+    /// `fallback_template` of `[commit-msg]`
+    FallbackTemplate,
+}
+
+impl fmt::Display for MessageRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageRule::BranchPrefix(pattern) => write!(f, "the branch prefix for '{pattern}'"),
+            MessageRule::BranchTicket => write!(f, "the ticket in the branch name"),
+            MessageRule::TicketFromEnv(name) => write!(f, "the ticket in ${name}"),
+            MessageRule::FallbackTemplate => write!(f, "fallback_template"),
+        }
+    }
 }
 
 impl Default for CommitMessageProcessor {
@@ -22,24 +107,128 @@ impl CommitMessageProcessor {
         // Regex to extract ticket numbers like SOMETICKET-123
         let ticket_regex = Regex::new(r"([A-Z][A-Z0-9]+-\d+)").expect("Invalid ticket regex");
 
-        // Regex to clean up branch names (remove common prefixes and convert to title case)
+        // Regex to clean up branch names (remove common prefixes and convert to title case).
+        // The separator following the ticket is captured so it can be checked against settings.
         let branch_cleanup_regex =
-            Regex::new(r"^(?:feature/|bugfix/|hotfix/|fix/)?[A-Z][A-Z0-9]+-\d+(?:-(.+))?$")
+            Regex::new(r"^(?:feature/|bugfix/|hotfix/|fix/)?[A-Z][A-Z0-9]+-\d+(?:([-_.])(.+))?$")
                 .expect("Invalid branch cleanup regex");
 
         Self {
             ticket_regex,
             branch_cleanup_regex,
+            multi_ticket: false,
+            settings: Settings::default(),
+            commit_msg: CommitMsgSettings::default(),
+            comment_char: '#',
+            template_lines: Vec::new(),
         }
     }
 
-    /// Process commit message file for prepare-commit-msg hook
+    /// Prefix messages with every ticket the branch name mentions, as in
+    /// `JIRA-123 JIRA-456: Shared Work` for `feature/JIRA-123-and-JIRA-456-shared-work`,
+    /// instead of only the first. `multi-ticket` in `[settings]` turns it on
+    /// for `prepare-commit-msg` as well.
+    pub fn with_multi_ticket(mut self, multi_ticket: bool) -> Self {
+        self.multi_ticket = multi_ticket;
+        self
+    }
+
+    /// Format the messages of [`render`](Self::render) as the `[settings]`
+    /// section `settings` came from says
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Add the Jira smart commit commands of the `[commit-msg]` section
+    /// `commit_msg` came from to the body of the messages started, asking
+    /// for the time spent if `prompt_time` is set
+    pub fn with_commit_msg_settings(mut self, commit_msg: CommitMsgSettings) -> Self {
+        self.commit_msg = commit_msg;
+        self
+    }
+
+    /// Take lines starting with `comment_char` instead of `#` as comments,
+    /// as git does with `core.commentChar`, and start the comment lines of
+    /// `fallback_template` with it
+    pub fn with_comment_char(mut self, comment_char: char) -> Self {
+        self.comment_char = comment_char;
+        self
+    }
+
+    /// Take the lines of `template`, the `commit.template` messages whose
+    /// source is `template` start from, as no more content than comments,
+    /// so that those messages still get the ticket
+    pub fn with_commit_template(mut self, template: &str) -> Self {
+        self.template_lines = template.lines().map(str::to_string).collect();
+        self
+    }
+
+    /// Process commit message file for prepare-commit-msg hook. The branch
+    /// is that of the repository the file belongs to, which may not be the
+    /// one in the current directory.
     pub fn process_commit_msg_file(
         &self,
         commit_msg_file: &Path,
-        _commit_source: Option<&str>,
-        _commit_sha: Option<&str>,
+        commit_source: Option<&str>,
+        commit_sha: Option<&str>,
+        settings: &Settings,
     ) -> Result<()> {
+        let repo = repository_of(commit_msg_file);
+        self.process_commit_msg_file_in(
+            repo.as_deref().unwrap_or(Path::new(".")),
+            commit_msg_file,
+            commit_source,
+            commit_sha,
+            None,
+            settings,
+        )
+    }
+
+    /// `process_commit_msg_file` as if the commit were made on `branch_name`,
+    /// without asking git for the current branch or whether there are commits
+    pub fn process_commit_msg_file_on_branch(
+        &self,
+        commit_msg_file: &Path,
+        commit_source: Option<&str>,
+        commit_sha: Option<&str>,
+        branch_name: &str,
+        settings: &Settings,
+    ) -> Result<()> {
+        self.process_commit_msg_file_in(
+            Path::new("."),
+            commit_msg_file,
+            commit_source,
+            commit_sha,
+            Some(branch_name),
+            settings,
+        )
+    }
+
+    /// `process_commit_msg_file` for the repository in `dir`, on `branch_name`
+    /// if given instead of its current branch
+    fn process_commit_msg_file_in(
+        &self,
+        dir: &Path,
+        commit_msg_file: &Path,
+        commit_source: Option<&str>,
+        commit_sha: Option<&str>,
+        branch_name: Option<&str>,
+        settings: &Settings,
+    ) -> Result<()> {
+        // With no commits there is no branch to read the ticket from, and
+        // `git rev-parse HEAD` fails
+        if branch_name.is_none() && has_no_commits(dir) {
+            eprintln!("Initial commit, not adding a ticket to the commit message");
+            return Ok(());
+        }
+
+        // `-c`/`-C`/`--amend` reuse an existing message; without the commit
+        // it came from there is nothing to reuse
+        if commit_source == Some("commit") && commit_sha.is_none() {
+            return Ok(());
+        }
+
         // Read current commit message
         let current_msg = fs::read_to_string(commit_msg_file).with_context(|| {
             format!(
@@ -48,24 +237,55 @@ impl CommitMessageProcessor {
             )
         })?;
 
-        // Skip if message already has content (not just comments)
-        if !current_msg
-            .lines()
-            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
-            .collect::<Vec<_>>()
-            .is_empty()
-        {
-            return Ok(());
+        let branch_name = match branch_name {
+            Some(branch_name) => Cow::Borrowed(branch_name),
+            None => Cow::Owned(self.get_current_branch_name(dir)?),
+        };
+        let mut processor = self.clone().with_comment_char(git_comment_char(dir));
+        // Without the template, its lines are content as any others
+        if commit_source == Some("template") {
+            if let Some(template) = commit_template(dir) {
+                processor = processor.with_commit_template(&template);
+            }
         }
 
-        // Get current branch name
-        let branch_name = self.get_current_branch_name()?;
+        // Only asked for when the message would have a type to scope
+        let prefixed = processor.branch_prefix(&branch_name).is_some();
+        let scope =
+            if settings.conventional_commits && commit_type(&branch_name).is_some() && !prefixed {
+                staged_scope(dir)
+            } else {
+                None
+            };
+
+        // Only read when a message is started, which the description is the body of
+        let starts_message = !processor.leaves_alone(&current_msg, commit_source);
+        let description = if processor.commit_msg.include_branch_description && starts_message {
+            branch_description(dir, &branch_name)
+        } else {
+            None
+        };
 
-        // Generate formatted message
-        if let Some(formatted_msg) = self.format_commit_message_from_branch(&branch_name) {
-            // Prepend the formatted message to existing content
-            let new_content = format!("{formatted_msg}\n\n{current_msg}");
+        // Only asked for when a message with tickets is started, which the time goes in
+        let time = match processor.message_with_scope(&branch_name, scope.as_deref(), settings) {
+            Some(subject) if processor.commit_msg.prompt_time && !prefixed && starts_message => {
+                time_on_terminal(tickets_of(&subject))
+            }
+            _ => None,
+        };
 
+        let lookups = Lookups {
+            scope: scope.as_deref(),
+            time: time.as_deref(),
+            description: description.as_deref(),
+        };
+        if let Some(new_content) = processor.render_with(
+            &branch_name,
+            &current_msg,
+            commit_source,
+            &lookups,
+            settings,
+        ) {
             fs::write(commit_msg_file, new_content).with_context(|| {
                 format!(
                     "Failed to write commit message file: {}",
@@ -77,10 +297,195 @@ impl CommitMessageProcessor {
         Ok(())
     }
 
-    /// Get current branch name from git repository
-    fn get_current_branch_name(&self) -> Result<String> {
+    /// The commit message `prepare-commit-msg` leaves for a commit on `branch`
+    /// whose message is `existing_message` so far and which git says comes
+    /// from `source` (`message`, `template`, `merge`, `squash` or `commit`),
+    /// or `None` if the message should be left as it is.
+    ///
+    /// Formats as the settings given to [`with_settings`](Self::with_settings)
+    /// say. Neither files nor git are touched; on branches without a ticket
+    /// the ticket environment variable is read.
+    pub fn render(
+        &self,
+        branch: &str,
+        existing_message: &str,
+        source: Option<&str>,
+    ) -> Option<String> {
+        let lookups = Lookups::default();
+        self.render_with(branch, existing_message, source, &lookups, &self.settings)
+    }
+
+    /// [`render`](Self::render) formatting as `settings` say, with what
+    /// `lookups` found added to the message
+    fn render_with(
+        &self,
+        branch: &str,
+        existing_message: &str,
+        source: Option<&str>,
+        lookups: &Lookups,
+        settings: &Settings,
+    ) -> Option<String> {
+        if self.leaves_alone(existing_message, source) {
+            return None;
+        }
+
+        // Prepend the formatted message to existing content
+        let Some(formatted_msg) = self.message_with_scope(branch, lookups.scope, settings) else {
+            return self
+                .fallback_template()
+                .map(|template| format!("{template}\n\n{existing_message}"));
+        };
+        // A branch prefix leaves no ticket for smart commit commands to go to
+        let smart_commit = match self.branch_prefix(branch) {
+            Some(_) => None,
+            None => smart_commit_line(
+                self.commit_msg.smart_commit.as_deref(),
+                &formatted_msg,
+                branch,
+                lookups.time,
+            ),
+        };
+        // The body goes between the subject and the existing content, which
+        // is no more than comments and trailers
+        let mut parts = vec![formatted_msg];
+        parts.extend(lookups.description.map(str::to_string));
+        parts.extend(smart_commit);
+        parts.push(existing_message.to_string());
+        Some(parts.join("\n\n"))
+    }
+
+    /// The message `prepare-commit-msg` starts commits on `branch_name` with:
+    /// the prefix of the first `[commit-msg.branch_prefixes]` pattern the
+    /// branch matches or else its ticket, formatted as `settings` say, falling
+    /// back to the ticket from the environment on branches such as `main`
+    /// that have none
+    pub fn message_for_branch(&self, branch_name: &str, settings: &Settings) -> Option<String> {
+        self.message_with_scope(branch_name, None, settings)
+    }
+
+    /// [`message_for_branch`](Self::message_for_branch), with `scope` as the
+    /// Conventional Commits scope when `conventional-commits` is set and the
+    /// branch prefix gives a type, as in `JIRA-123: feat(auth): Add Login`
+    fn message_with_scope(
+        &self,
+        branch_name: &str,
+        scope: Option<&str>,
+        settings: &Settings,
+    ) -> Option<String> {
+        if let Some(prefix) = self.branch_prefix(branch_name) {
+            return Some(prefix);
+        }
+        let multi_ticket = self.multi_ticket || settings.multi_ticket;
+        let Some(message) =
+            self.format_message(branch_name, settings.branch_separator, multi_ticket)
+        else {
+            return ticket_from_env(settings).map(|ticket| format!("{ticket}: "));
+        };
+        let commit_type = commit_type(branch_name).filter(|_| settings.conventional_commits);
+        match (commit_type, message.split_once(": ")) {
+            (Some(commit_type), Some((tickets, description))) => {
+                let scope = scope.map(|scope| format!("({scope})")).unwrap_or_default();
+                Some(format!("{tickets}: {commit_type}{scope}: {description}"))
+            }
+            _ => Some(message),
+        }
+    }
+
+    /// Which rule gives the message `prepare-commit-msg` starts commits on
+    /// `branch_name` with, formatted as `settings` say; `None` if no rule
+    /// applies and the message is left as it is
+    pub fn explain(&self, branch_name: &str, settings: &Settings) -> Option<MessageRule> {
+        if let Some(entry) = self
+            .commit_msg
+            .branch_prefixes
+            .iter()
+            .find(|entry| glob_captures(&entry.pattern, branch_name).is_some())
+        {
+            return Some(MessageRule::BranchPrefix(entry.pattern.clone()));
+        }
+        let multi_ticket = self.multi_ticket || settings.multi_ticket;
+        if self
+            .format_message(branch_name, settings.branch_separator, multi_ticket)
+            .is_some()
+        {
+            return Some(MessageRule::BranchTicket);
+        }
+        if ticket_from_env(settings).is_some() {
+            let name = settings
+                .ticket_env_var
+                .as_deref()
+                .unwrap_or(DEFAULT_TICKET_ENV_VAR);
+            return Some(MessageRule::TicketFromEnv(name.to_string()));
+        }
+        self.fallback_template()
+            .map(|_| MessageRule::FallbackTemplate)
+    }
+
+    /// `fallback_template`, with its comment lines starting with the comment
+    /// character; `None` if it is unset or empty
+    pub fn fallback_template(&self) -> Option<String> {
+        let template = self
+            .commit_msg
+            .fallback_template
+            .as_deref()
+            .filter(|template| !template.is_empty())?;
+        let lines: Vec<String> = template
+            .split('\n')
+            .map(|line| match line.strip_prefix('#') {
+                Some(comment) => format!("{}{comment}", self.comment_char),
+                None => line.to_string(),
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    /// Whether `prepare-commit-msg` leaves `existing_message`, which git says
+    /// comes from `source`, as it is: merges and squashes come with a message
+    /// of their own, and a message with content other than comments is kept.
+    /// Without a source, trailers such as the `Signed-off-by` of `git commit
+    /// -s` are not content either, and from a `template`, neither are the
+    /// lines of [`with_commit_template`](Self::with_commit_template).
+    fn leaves_alone(&self, existing_message: &str, source: Option<&str>) -> bool {
+        let from_template = source == Some("template");
+        matches!(source, Some("merge" | "squash"))
+            || existing_message
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !line.starts_with(self.comment_char))
+                .filter(|line| !from_template || !self.template_lines.iter().any(|t| t == line))
+                .any(|line| source.is_some() || !wrap::is_trailer(line))
+    }
+
+    /// The prefix of the first `[commit-msg.branch_prefixes]` entry whose
+    /// pattern `branch_name` matches, with what its `*`s matched filled in
+    fn branch_prefix(&self, branch_name: &str) -> Option<String> {
+        self.commit_msg.branch_prefixes.iter().find_map(|entry| {
+            let captures = glob_captures(&entry.pattern, branch_name)?;
+            Some(fill_captures(&entry.prefix, &captures))
+        })
+    }
+
+    /// The Conventional Commits scope for the changes staged in the
+    /// repository in `dir`: their top-level directory, if they are all in
+    /// the same one
+    pub fn suggest_scope_from_staged_files(&self, dir: &Path) -> Option<String> {
+        staged_scope(dir)
+    }
+
+    /// Whether `message` references a ticket such as `JIRA-123`
+    pub fn has_ticket(&self, message: &str) -> bool {
+        self.ticket_regex.is_match(message)
+    }
+
+    /// Whether the repository in `dir` has no commits yet
+    pub fn is_initial_commit(&self, dir: &Path) -> bool {
+        has_no_commits(dir)
+    }
+
+    /// Get current branch name from the git repository in `dir`
+    fn get_current_branch_name(&self, dir: &Path) -> Result<String> {
         let output = Command::new("git")
             .args(["rev-parse", "--abbrev-ref", "HEAD"])
+            .current_dir(dir)
             .output()
             .with_context(|| "Failed to execute git command")?;
 
@@ -98,30 +503,110 @@ impl CommitMessageProcessor {
     }
 
     /// Format commit message based on branch name
-    /// Converts something like "/bugfixes/SOMETICKET-123-do-stuff" to "SOMETICKET-123: Do stuff"
+    /// Converts something like "/bugfixes/SOMETICKET-123-do-stuff" to "SOMETICKET-123: Do stuff",
+    /// or with [`with_multi_ticket`](Self::with_multi_ticket) "feature/AB-1-and-CD-2-do-stuff"
+    /// to "AB-1 CD-2: Do Stuff"
     pub fn format_commit_message_from_branch(&self, branch_name: &str) -> Option<String> {
+        self.format_commit_message_with_separator(branch_name, BranchSeparator::default())
+    }
+
+    /// Format commit message based on branch name, using the given separator
+    /// between the ticket and the description
+    pub fn format_commit_message_with_separator(
+        &self,
+        branch_name: &str,
+        separator: BranchSeparator,
+    ) -> Option<String> {
+        self.format_message(branch_name, separator, self.multi_ticket)
+    }
+
+    /// Format the message for `branch_name`, with every ticket in it when `multi_ticket` is set
+    fn format_message(
+        &self,
+        branch_name: &str,
+        separator: BranchSeparator,
+        multi_ticket: bool,
+    ) -> Option<String> {
         // Extract ticket number
         let ticket = self.ticket_regex.find(branch_name)?;
-        let ticket_id = ticket.as_str();
+        let (ticket_id, branch_name) = if multi_ticket {
+            let mut tickets: Vec<&str> = Vec::new();
+            for ticket in self.ticket_regex.find_iter(branch_name) {
+                if !tickets.contains(&ticket.as_str()) {
+                    tickets.push(ticket.as_str());
+                }
+            }
+            (
+                Cow::Owned(tickets.join(" ")),
+                Cow::Owned(self.without_later_tickets(branch_name)),
+            )
+        } else {
+            (Cow::Borrowed(ticket.as_str()), Cow::Borrowed(branch_name))
+        };
 
         // Extract and clean up the description part
-        let description = self
-            .branch_cleanup_regex
-            .captures(branch_name)
-            .and_then(|caps| caps.get(1))
-            .map(|m| m.as_str())
-            .unwrap_or("");
+        let description = self.extract_description(&branch_name, separator);
 
         if description.is_empty() {
             return Some(format!("{ticket_id}: "));
         }
 
-        // Convert to title case and replace hyphens/underscores with spaces
-        let formatted_description = self.to_title_case(&description.replace(['-', '_'], " "));
+        // Convert to title case and replace separators with spaces
+        let word_separators: &[char] = match separator {
+            // Underscores have always been accepted between words of hyphenated branches
+            BranchSeparator::Char('-') => &['-', '_'],
+            BranchSeparator::Char(ref c) => std::slice::from_ref(c),
+            BranchSeparator::Auto => &AUTO_SEPARATORS,
+        };
+        let formatted_description = self.to_title_case(&description.replace(word_separators, " "));
 
         Some(format!("{ticket_id}: {formatted_description}"))
     }
 
+    /// `branch_name` without the tickets after the first one, nor the words
+    /// such as `and` joining them to the ticket before, so that the
+    /// description is all that follows the first ticket
+    fn without_later_tickets(&self, branch_name: &str) -> String {
+        let mut result = String::new();
+        let mut last_end = 0;
+        for (i, ticket) in self.ticket_regex.find_iter(branch_name).enumerate() {
+            let between = &branch_name[last_end..ticket.start()];
+            if i == 0 {
+                result.push_str(&branch_name[..ticket.end()]);
+            } else if !between.split(AUTO_SEPARATORS).all(|word| {
+                word.is_empty() || TICKET_CONNECTORS.contains(&word.to_lowercase().as_str())
+            }) {
+                result.push_str(between);
+            }
+            last_end = ticket.end();
+        }
+        result.push_str(&branch_name[last_end..]);
+        result
+    }
+
+    /// Extract the description part of a branch name
+    fn extract_description<'a>(&self, branch_name: &'a str, separator: BranchSeparator) -> &'a str {
+        let Some(caps) = self.branch_cleanup_regex.captures(branch_name) else {
+            return "";
+        };
+        let (Some(found), Some(description)) = (caps.get(1), caps.get(2)) else {
+            return "";
+        };
+
+        // Only one character can follow the ticket, so in auto mode trying the
+        // candidates in order amounts to accepting whichever of them is present
+        let accepted = match separator {
+            BranchSeparator::Char(c) => found.as_str().starts_with(c),
+            BranchSeparator::Auto => true,
+        };
+
+        if accepted {
+            description.as_str()
+        } else {
+            ""
+        }
+    }
+
     /// Convert string to title case
     fn to_title_case(&self, s: &str) -> String {
         s.split_whitespace()
@@ -139,9 +624,282 @@ impl CommitMessageProcessor {
     }
 }
 
+/// What `prepare-commit-msg` looked up for a message besides the branch
+#[derive(Debug, Default)]
+struct Lookups<'a> {
+    /// Conventional Commits scope of the staged changes
+    scope: Option<&'a str>,
+    /// Time spent, for a `#time` command
+    time: Option<&'a str>,
+    /// Description of the branch, for the body
+    description: Option<&'a str>,
+}
+
+/// The tickets at the start of the generated message `subject`
+fn tickets_of(subject: &str) -> &str {
+    subject
+        .split_once(": ")
+        .map_or(subject, |(tickets, _)| tickets)
+}
+
+/// The Jira smart commit commands for the message starting with `subject`
+/// on `branch`: `#time` with `time`, if one was given, followed by
+/// `template` with its `{ticket}`, `{description}` and `{branch}`
+/// placeholders filled in. `None` when there are no commands.
+fn smart_commit_line(
+    template: Option<&str>,
+    subject: &str,
+    branch: &str,
+    time: Option<&str>,
+) -> Option<String> {
+    let mut commands = Vec::new();
+    if let Some(time) = time.map(str::trim).filter(|time| !time.is_empty()) {
+        commands.push(format!("#time {time}"));
+    }
+    if let Some(template) = template {
+        // The description is what follows the ticket and any type and scope
+        let description = subject.rsplit_once(": ").map_or("", |(_, rest)| rest);
+        let rendered = placeholder::replace(template, "ticket", tickets_of(subject));
+        let rendered = placeholder::replace(&rendered, "description", description);
+        commands.push(placeholder::replace(&rendered, "branch", branch));
+    }
+    let line = commands.join(" ");
+    let line = line.trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+/// Ask on `output` for the time spent on `tickets`, for a `#time` command,
+/// reading the answer from `input`. Nothing is asked when there is no one to
+/// ask (`interactive` is false), and an empty answer adds no command.
+fn ask_time(
+    tickets: &str,
+    interactive: bool,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Option<String> {
+    if !interactive {
+        return None;
+    }
+    write!(
+        output,
+        "Time spent on {tickets} (e.g. 2h 30m, empty to skip): "
+    )
+    .ok()?;
+    output.flush().ok()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer).ok()?;
+    let answer = answer.trim();
+    (!answer.is_empty()).then(|| answer.to_string())
+}
+
+/// [`ask_time`] on the terminal. Git gives `prepare-commit-msg` no stdin,
+/// so the terminal is opened directly, when stderr shows there is one.
+fn time_on_terminal(tickets: &str) -> Option<String> {
+    let interactive = io::stderr().is_terminal();
+    if !interactive {
+        return None;
+    }
+    let (input, output) = if cfg!(windows) {
+        ("CONIN$", "CONOUT$")
+    } else {
+        ("/dev/tty", "/dev/tty")
+    };
+    let input = OpenOptions::new().read(true).open(input).ok()?;
+    let output = OpenOptions::new().write(true).open(output).ok()?;
+    ask_time(tickets, interactive, BufReader::new(input), output)
+}
+
+/// What the `*`s of a branch pattern matched, in order
+type Captures<'a> = Vec<&'a str>;
+
+/// What each `*` of `pattern` matched in `branch_name`, if it matches. A `*`
+/// matches one or more characters other than `/`, so `release/*` matches
+/// `release/2.3` but not `release/2.3/rc1`; other characters match themselves.
+/// [`glob::Pattern`] can't say what its wildcards matched, so the pattern is
+/// turned into a regex with a group for each `*`.
+fn glob_captures<'a>(pattern: &str, branch_name: &'a str) -> Option<Captures<'a>> {
+    let literals: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    // Lazy groups give each `*` the shortest match that lets the rest match
+    let regex = Regex::new(&format!("^{}$", literals.join("([^/]+?)"))).ok()?;
+    let captures = regex.captures(branch_name)?;
+    Some(
+        captures
+            .iter()
+            .skip(1)
+            .flatten()
+            .map(|capture| capture.as_str())
+            .collect(),
+    )
+}
+
+/// `template` with `{1}`, `{2}`, ... replaced by the first, second, ... of
+/// `captures`. Other braces are kept as written.
+fn fill_captures(template: &str, captures: &[&str]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let placeholder = rest.split_once('}').and_then(|(index, after)| {
+            let capture = captures.get(index.parse::<usize>().ok()?.checked_sub(1)?)?;
+            Some((*capture, after))
+        });
+        match placeholder {
+            Some((capture, after)) => {
+                result.push_str(capture);
+                rest = after;
+            }
+            None => result.push('{'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// The description of `branch` in the repository in `dir`, as `git branch
+/// --edit-description` sets it; `None` if it has none
+fn branch_description(dir: &Path, branch: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", &format!("branch.{branch}.description")])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let description = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    (!description.is_empty()).then_some(description)
+}
+
+/// The content of `commit.template` of the repository in `dir`, relative to
+/// which a relative path is taken; `None` if it is unset or can't be read
+fn commit_template(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "--path", "commit.template"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim_end());
+    fs::read_to_string(dir.join(path)).ok()
+}
+
+/// The comment character git uses in the repository in `dir`:
+/// `core.commentChar`, or `#` when it is unset or `auto`
+fn git_comment_char(dir: &Path) -> char {
+    Command::new("git")
+        .args(["config", "core.commentChar"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (value != "auto").then(|| value.chars().next()).flatten()
+        })
+        .unwrap_or('#')
+}
+
+/// Conventional Commits type for the prefix of `branch_name`, such as `feat`
+/// for `feature/JIRA-123-add-login`
+fn commit_type(branch_name: &str) -> Option<&'static str> {
+    match branch_name.split_once('/')?.0 {
+        "feature" => Some("feat"),
+        "bugfix" | "hotfix" | "fix" => Some("fix"),
+        _ => None,
+    }
+}
+
+/// [`scope_of`] the changes staged in the repository in `dir`. If git
+/// can't list them, the message goes without a scope.
+fn staged_scope(dir: &Path) -> Option<String> {
+    match staged_names(dir) {
+        Ok(files) => scope_of(files.split('\0').filter(|name| !name.is_empty())),
+        Err(e) => {
+            eprintln!("Warning: not adding a scope to the commit message: {e:#}");
+            None
+        }
+    }
+}
+
+/// Names of the files staged in the repository in `dir`, NUL-separated
+fn staged_names(dir: &Path) -> Result<String> {
+    let output = Command::new("git")
+        .args(["diff", "--cached", "--name-only", "-z"])
+        .current_dir(dir)
+        .output()
+        .with_context(|| "Failed to execute git command")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Git command failed: {}", stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The top-level directory of `files`, paths relative to the repository
+/// root, if they are all under the same one. Files in the root itself have
+/// no directory to give, so they leave changes without a scope.
+fn scope_of<'a>(files: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut scope = None;
+    for file in files {
+        let (dir, _) = file.split_once('/')?;
+        match scope {
+            None => scope = Some(dir),
+            Some(scope) if scope != dir => return None,
+            Some(_) => {}
+        }
+    }
+    scope.map(str::to_string)
+}
+
+/// The ticket from the environment variable named by the `ticket-env-var`
+/// setting, `JIRA_TICKET` by default, if it is set and not blank
+fn ticket_from_env(settings: &Settings) -> Option<String> {
+    let name = settings
+        .ticket_env_var
+        .as_deref()
+        .unwrap_or(DEFAULT_TICKET_ENV_VAR);
+    let ticket = std::env::var(name).ok()?;
+    let ticket = ticket.trim();
+    (!ticket.is_empty()).then(|| ticket.to_string())
+}
+
+/// Working tree of the repository whose git directory holds
+/// `commit_msg_file`, as `.git/COMMIT_EDITMSG` does, or
+/// `.git/worktrees/<name>/COMMIT_EDITMSG` for a linked worktree. `None` if
+/// the file isn't in a git directory.
+pub fn repository_of(commit_msg_file: &Path) -> Option<PathBuf> {
+    let git_dir = fs::canonicalize(commit_msg_file)
+        .ok()?
+        .parent()?
+        .to_path_buf();
+    if !git_dir.join("HEAD").is_file() {
+        return None;
+    }
+    // A linked worktree's git directory names the `.git` file in the worktree
+    let dot_git = match fs::read_to_string(git_dir.join("gitdir")) {
+        Ok(content) => PathBuf::from(content.trim()),
+        Err(_) => git_dir,
+    };
+    if dot_git.file_name()? != ".git" {
+        return None;
+    }
+    dot_git.parent().map(Path::to_path_buf)
+}
+
+/// Whether `git log` fails in `dir` because there are no commits yet
+fn has_no_commits(dir: &Path) -> bool {
+    Command::new("git")
+        .args(["log", "--oneline", "-1"])
+        .current_dir(dir)
+        .output()
+        .is_ok_and(|output| !output.status.success())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::BranchPrefix;
 
     #[test]
     fn test_format_commit_message_from_branch() {
@@ -178,6 +936,176 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_format_with_multiple_tickets() {
+        let single = CommitMessageProcessor::new();
+        let processor = CommitMessageProcessor::new().with_multi_ticket(true);
+        let branch = "feature/JIRA-123-and-JIRA-456-shared-work";
+
+        assert_eq!(
+            single.format_commit_message_from_branch(branch),
+            Some("JIRA-123: And Jira 456 Shared Work".to_string())
+        );
+        assert_eq!(
+            processor.format_commit_message_from_branch(branch),
+            Some("JIRA-123 JIRA-456: Shared Work".to_string())
+        );
+
+        // Tickets that aren't joined by a connector drop out of the description too
+        assert_eq!(
+            processor.format_commit_message_from_branch("JIRA-1-fix-login-OPS-2-tidy"),
+            Some("JIRA-1 OPS-2: Fix Login Tidy".to_string())
+        );
+        assert_eq!(
+            processor.format_commit_message_from_branch("bugfix/ABC-7_ABC-7-repeat"),
+            Some("ABC-7: Repeat".to_string())
+        );
+        assert_eq!(
+            processor.format_commit_message_from_branch("feature/JIRA-123-add-new-feature"),
+            Some("JIRA-123: Add New Feature".to_string())
+        );
+        assert_eq!(
+            processor.format_commit_message_with_separator(
+                "feature/AB-1_and_CD-2_do_it",
+                BranchSeparator::Char('_')
+            ),
+            Some("AB-1 CD-2: Do It".to_string())
+        );
+        assert_eq!(processor.format_commit_message_from_branch("main"), None);
+    }
+
+    #[test]
+    fn test_format_with_underscore_separator() {
+        let processor = CommitMessageProcessor::new();
+        let separator = BranchSeparator::Char('_');
+
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123_do_the_thing", separator),
+            Some("JIRA-123: Do The Thing".to_string())
+        );
+
+        // Hyphenated descriptions are not recognized with an underscore separator
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123-do-the-thing", separator),
+            Some("JIRA-123: ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_with_dot_separator() {
+        let processor = CommitMessageProcessor::new();
+        let separator = BranchSeparator::Char('.');
+
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123.do.the.thing", separator),
+            Some("JIRA-123: Do The Thing".to_string())
+        );
+        assert_eq!(
+            processor.format_commit_message_with_separator("JIRA-123", separator),
+            Some("JIRA-123: ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_with_hyphen_separator() {
+        let processor = CommitMessageProcessor::new();
+        let separator = BranchSeparator::Char('-');
+
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123-do_the-thing", separator),
+            Some("JIRA-123: Do The Thing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_with_auto_separator() {
+        let processor = CommitMessageProcessor::new();
+        let separator = BranchSeparator::Auto;
+
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123-do-the-thing", separator),
+            Some("JIRA-123: Do The Thing".to_string())
+        );
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123_do_the_thing", separator),
+            Some("JIRA-123: Do The Thing".to_string())
+        );
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("feature/JIRA-123.do.the.thing", separator),
+            Some("JIRA-123: Do The Thing".to_string())
+        );
+
+        // Mixed separators: the one following the ticket is detected, all are split on
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("bugfix/JIRA-123_fix-the.login", separator),
+            Some("JIRA-123: Fix The Login".to_string())
+        );
+        assert_eq!(
+            processor
+                .format_commit_message_with_separator("bugfix/JIRA-123.fix_the-login", separator),
+            Some("JIRA-123: Fix The Login".to_string())
+        );
+    }
+
+    #[test]
+    fn test_message_for_branch() {
+        let processor = CommitMessageProcessor::new();
+        let settings = Settings::default();
+        let cases = [
+            ("feature/JIRA-999-my-work", Some("JIRA-999: My Work")),
+            ("JIRA-999", Some("JIRA-999: ")),
+            ("bugfix/ABC-12-fix-the-login", Some("ABC-12: Fix The Login")),
+            ("feature/JIRA-1-JIRA-2-both", Some("JIRA-1: Jira 2 Both")),
+            ("main", None),
+            ("feature/no-ticket-here", None),
+        ];
+        for (branch, expected) in cases {
+            assert_eq!(
+                processor.message_for_branch(branch, &settings).as_deref(),
+                expected,
+                "{branch}"
+            );
+        }
+
+        let settings = Settings {
+            multi_ticket: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            processor
+                .message_for_branch("feature/JIRA-1-JIRA-2-both", &settings)
+                .as_deref(),
+            Some("JIRA-1 JIRA-2: Both")
+        );
+    }
+
+    #[test]
+    fn test_branch_separator_parse() {
+        assert_eq!(
+            BranchSeparator::parse("-"),
+            Some(BranchSeparator::Char('-'))
+        );
+        assert_eq!(
+            BranchSeparator::parse("_"),
+            Some(BranchSeparator::Char('_'))
+        );
+        assert_eq!(
+            BranchSeparator::parse("."),
+            Some(BranchSeparator::Char('.'))
+        );
+        assert_eq!(BranchSeparator::parse("auto"), Some(BranchSeparator::Auto));
+        assert_eq!(BranchSeparator::parse("/"), None);
+        assert_eq!(BranchSeparator::parse(""), None);
+    }
+
     #[test]
     fn test_to_title_case() {
         let processor = CommitMessageProcessor::new();
@@ -188,4 +1116,628 @@ mod tests {
         assert_eq!(processor.to_title_case("single"), "Single");
         assert_eq!(processor.to_title_case(""), "");
     }
+
+    #[test]
+    fn test_has_no_commits() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(repo)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        assert!(has_no_commits(repo));
+
+        git(&["commit", "-q", "--allow-empty", "-m", "Initial commit"]);
+        assert!(!has_no_commits(repo));
+    }
+
+    #[test]
+    fn test_render() {
+        let processor = CommitMessageProcessor::new();
+        let template = "\n# Please enter the commit message\n";
+
+        // An empty message or one of only comments gets the ticket
+        assert_eq!(
+            processor.render("feature/JIRA-123-add-login", template, None),
+            Some(format!("JIRA-123: Add Login\n\n{template}"))
+        );
+        assert_eq!(
+            processor.render("JIRA-123", "", Some("template")),
+            Some("JIRA-123: \n\n".to_string())
+        );
+
+        // A message with content is left alone, as are merges and squashes
+        assert_eq!(
+            processor.render("JIRA-123-x", "Fix the build\n", Some("message")),
+            None
+        );
+        assert_eq!(
+            processor.render("JIRA-123-x", template, Some("merge")),
+            None
+        );
+        assert_eq!(
+            processor.render("JIRA-123-x", template, Some("squash")),
+            None
+        );
+
+        // Without a ticket in the branch nor the environment there is nothing to add
+        let processor = processor.with_settings(Settings {
+            ticket_env_var: Some("HOOKMASTER_TEST_RENDER_TICKET".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(processor.render("main", template, None), None);
+    }
+
+    #[test]
+    fn test_render_with_settings() {
+        let processor = CommitMessageProcessor::new().with_settings(Settings {
+            branch_separator: BranchSeparator::Char('_'),
+            multi_ticket: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            processor.render("feature/AB-1_and_CD-2_shared_work", "", None),
+            Some("AB-1 CD-2: Shared Work\n\n".to_string())
+        );
+        assert_eq!(
+            processor.render("feature/AB-1-hyphenated", "", None),
+            Some("AB-1: \n\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repository_of_message_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        let repo = root.join("repo");
+        fs::create_dir(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&repo)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Initial commit"]);
+        git(&["worktree", "add", "-q", "-b", "JIRA-1-wt", "../wt"]);
+
+        let msg_file = repo.join(".git/COMMIT_EDITMSG");
+        fs::write(&msg_file, "").unwrap();
+        assert_eq!(repository_of(&msg_file), Some(repo.clone()));
+
+        let msg_file = repo.join(".git/worktrees/wt/COMMIT_EDITMSG");
+        fs::write(&msg_file, "").unwrap();
+        assert_eq!(repository_of(&msg_file), Some(root.join("wt")));
+
+        let msg_file = root.join("COMMIT_EDITMSG");
+        fs::write(&msg_file, "").unwrap();
+        assert_eq!(repository_of(&msg_file), None);
+        assert_eq!(repository_of(&repo.join(".git/MISSING")), None);
+    }
+
+    #[test]
+    fn test_ticket_from_env_on_branch_without_ticket() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(repo)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q", "-b", "main"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Initial commit"]);
+
+        // A variable of this test's own, so tests running in parallel don't see it
+        let settings = Settings {
+            ticket_env_var: Some("HOOKMASTER_TEST_TICKET".to_string()),
+            ..Default::default()
+        };
+        let processor = CommitMessageProcessor::new();
+        let msg_file = repo.join("COMMIT_EDITMSG");
+        let process = || {
+            fs::write(&msg_file, "# Please enter the commit message\n").unwrap();
+            processor
+                .process_commit_msg_file_in(repo, &msg_file, None, None, None, &settings)
+                .unwrap();
+            fs::read_to_string(&msg_file).unwrap()
+        };
+
+        std::env::remove_var("HOOKMASTER_TEST_TICKET");
+        assert_eq!(process(), "# Please enter the commit message\n");
+
+        std::env::set_var("HOOKMASTER_TEST_TICKET", " HOT-999 ");
+        assert_eq!(
+            process(),
+            "HOT-999: \n\n# Please enter the commit message\n"
+        );
+
+        // The branch's own ticket wins over the environment
+        git(&["checkout", "-q", "-b", "feature/JIRA-123-add-login"]);
+        assert_eq!(
+            process(),
+            "JIRA-123: Add Login\n\n# Please enter the commit message\n"
+        );
+        std::env::remove_var("HOOKMASTER_TEST_TICKET");
+    }
+
+    #[test]
+    fn test_conventional_commits() {
+        let processor = CommitMessageProcessor::new().with_settings(Settings {
+            conventional_commits: true,
+            ..Default::default()
+        });
+        let cases = [
+            ("feature/JIRA-123-add-login", "JIRA-123: feat: Add Login"),
+            ("bugfix/JIRA-7-crash", "JIRA-7: fix: Crash"),
+            ("hotfix/JIRA-8", "JIRA-8: fix: "),
+            // Other branches have no type to give
+            ("JIRA-9-tidy-up", "JIRA-9: Tidy Up"),
+        ];
+        for (branch, expected) in cases {
+            assert_eq!(
+                processor.render(branch, "", None),
+                Some(format!("{expected}\n\n")),
+                "{branch}"
+            );
+        }
+        assert_eq!(
+            processor.message_with_scope(
+                "feature/JIRA-123-add-login",
+                Some("auth"),
+                &processor.settings
+            ),
+            Some("JIRA-123: feat(auth): Add Login".to_string())
+        );
+        // Without the setting the scope isn't used
+        assert_eq!(
+            processor.message_with_scope(
+                "feature/JIRA-123-add-login",
+                Some("auth"),
+                &Settings::default()
+            ),
+            Some("JIRA-123: Add Login".to_string())
+        );
+    }
+
+    #[test]
+    fn test_scope_of() {
+        assert_eq!(
+            scope_of(["auth/login.rs", "auth/tests/login.rs"]),
+            Some("auth".to_string())
+        );
+        assert_eq!(scope_of(["auth/login.rs", "api/routes.rs"]), None);
+        assert_eq!(scope_of(["auth/login.rs", "README.md"]), None);
+        assert_eq!(scope_of([]), None);
+    }
+
+    #[test]
+    fn test_scope_from_staged_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(repo)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q", "-b", "feature/JIRA-123-add-login"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Initial commit"]);
+        fs::create_dir(repo.join("auth")).unwrap();
+        fs::write(repo.join("auth/login.rs"), "").unwrap();
+        fs::write(repo.join("README.md"), "").unwrap();
+        git(&["add", "auth"]);
+
+        let settings = Settings {
+            conventional_commits: true,
+            ..Default::default()
+        };
+        let processor = CommitMessageProcessor::new();
+        let msg_file = repo.join("COMMIT_EDITMSG");
+        let process = || {
+            fs::write(&msg_file, "").unwrap();
+            processor
+                .process_commit_msg_file_in(repo, &msg_file, None, None, None, &settings)
+                .unwrap();
+            fs::read_to_string(&msg_file).unwrap()
+        };
+        assert_eq!(process(), "JIRA-123: feat(auth): Add Login\n\n");
+
+        assert_eq!(
+            processor.suggest_scope_from_staged_files(repo),
+            Some("auth".to_string())
+        );
+        assert!(!processor.is_initial_commit(repo));
+
+        // Unstaged files don't count, but staged ones outside the directory do
+        git(&["add", "README.md"]);
+        assert_eq!(process(), "JIRA-123: feat: Add Login\n\n");
+        assert_eq!(processor.suggest_scope_from_staged_files(repo), None);
+
+        // Names git would quote are read as they are
+        git(&["reset", "-q"]);
+        fs::create_dir(repo.join("käyttäjä")).unwrap();
+        fs::write(repo.join("käyttäjä/tili.rs"), "").unwrap();
+        git(&["add", "käyttäjä"]);
+        assert_eq!(process(), "JIRA-123: feat(käyttäjä): Add Login\n\n");
+
+        // Without a repository to list the files of there is no scope
+        let outside = tempfile::TempDir::new().unwrap();
+        assert_eq!(
+            processor.suggest_scope_from_staged_files(outside.path()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_smart_commit_line() {
+        let template = Some("#comment {description}");
+        assert_eq!(
+            smart_commit_line(template, "JIRA-123: Add Login", "JIRA-123-add-login", None),
+            Some("#comment Add Login".to_string())
+        );
+        assert_eq!(
+            smart_commit_line(
+                template,
+                "JIRA-123: feat(auth): Add Login",
+                "feature/JIRA-123-add-login",
+                Some(" 2h 30m\n")
+            ),
+            Some("#time 2h 30m #comment Add Login".to_string())
+        );
+        assert_eq!(
+            smart_commit_line(
+                Some("{ticket} #done {{branch}} on {branch}"),
+                "AB-1 CD-2: ",
+                "AB-1-and-CD-2",
+                None
+            ),
+            Some("AB-1 CD-2 #done {branch} on AB-1-and-CD-2".to_string())
+        );
+        // The time alone, and nothing without a time or template
+        assert_eq!(
+            smart_commit_line(None, "JIRA-1: ", "JIRA-1", Some("1d")),
+            Some("#time 1d".to_string())
+        );
+        assert_eq!(
+            smart_commit_line(None, "JIRA-1: ", "JIRA-1", Some(" ")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_with_smart_commit() {
+        let processor = CommitMessageProcessor::new().with_commit_msg_settings(CommitMsgSettings {
+            smart_commit: Some("#comment {description}".to_string()),
+            ..Default::default()
+        });
+        let template = "# Please enter the commit message\n";
+        assert_eq!(
+            processor.render("feature/JIRA-123-add-login", template, None),
+            Some(format!(
+                "JIRA-123: Add Login\n\n#comment Add Login\n\n{template}"
+            ))
+        );
+        assert_eq!(
+            processor.render("JIRA-123-x", "Fix the build\n", Some("message")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_branch_prefixes() {
+        let prefix = |pattern: &str, prefix: &str| BranchPrefix {
+            pattern: pattern.to_string(),
+            prefix: prefix.to_string(),
+        };
+        let processor = CommitMessageProcessor::new().with_commit_msg_settings(CommitMsgSettings {
+            smart_commit: Some("#comment {description}".to_string()),
+            branch_prefixes: vec![
+                prefix("release/*", "Release {1}: "),
+                prefix("hotfix/*.*.*", "Hotfix {1}.{2} patch {3}: "),
+            ],
+            ..Default::default()
+        });
+        let settings = Settings::default();
+        assert_eq!(
+            processor.render("release/2.3", "", None),
+            Some("Release 2.3: \n\n".to_string())
+        );
+        assert_eq!(
+            processor.message_for_branch("hotfix/2.3.1", &settings),
+            Some("Hotfix 2.3 patch 1: ".to_string())
+        );
+        // Matched before the ticket logic
+        assert_eq!(
+            processor.message_for_branch("release/JIRA-123", &settings),
+            Some("Release JIRA-123: ".to_string())
+        );
+        // Branches matching no pattern get their ticket
+        for branch in ["feature/JIRA-123-add-login", "hotfix/JIRA-123-add-login"] {
+            assert_eq!(
+                processor.message_for_branch(branch, &settings),
+                Some("JIRA-123: Add Login".to_string()),
+                "{branch}"
+            );
+        }
+        assert_eq!(processor.message_for_branch("hotfix/2.3", &settings), None);
+    }
+
+    #[test]
+    fn test_fallback_template() {
+        let processor = CommitMessageProcessor::new().with_commit_msg_settings(CommitMsgSettings {
+            fallback_template: Some("type: \n\n# - [ ] tests".to_string()),
+            branch_prefixes: vec![BranchPrefix {
+                pattern: "release/*".to_string(),
+                prefix: "Release {1}: ".to_string(),
+            }],
+            ..Default::default()
+        });
+        let template = "# Please enter the commit message\n";
+        assert_eq!(
+            processor.render("main", template, None),
+            Some(format!("type: \n\n# - [ ] tests\n\n{template}"))
+        );
+        // Existing content and merges are left alone as without the fallback
+        assert_eq!(processor.render("main", "Fix it\n", Some("message")), None);
+        assert_eq!(processor.render("main", template, Some("merge")), None);
+
+        // Branch prefixes and tickets come before the fallback
+        assert_eq!(
+            processor.render("release/2.3", "", None),
+            Some("Release 2.3: \n\n".to_string())
+        );
+        assert_eq!(
+            processor.render("feature/JIRA-123-add-login", "", None),
+            Some("JIRA-123: Add Login\n\n".to_string())
+        );
+
+        let settings = Settings {
+            ticket_env_var: Some("HOOKMASTER_TEST_FALLBACK_TICKET".to_string()),
+            ..Default::default()
+        };
+        let explain = |branch| processor.explain(branch, &settings);
+        assert_eq!(
+            explain("release/2.3"),
+            Some(MessageRule::BranchPrefix("release/*".to_string()))
+        );
+        assert_eq!(
+            explain("feature/JIRA-123-add-login"),
+            Some(MessageRule::BranchTicket)
+        );
+        assert_eq!(explain("main"), Some(MessageRule::FallbackTemplate));
+        std::env::set_var("HOOKMASTER_TEST_FALLBACK_TICKET", "HOT-1");
+        assert_eq!(
+            explain("main"),
+            Some(MessageRule::TicketFromEnv(
+                "HOOKMASTER_TEST_FALLBACK_TICKET".to_string()
+            ))
+        );
+        std::env::remove_var("HOOKMASTER_TEST_FALLBACK_TICKET");
+
+        // An empty fallback is no fallback
+        let processor = CommitMessageProcessor::new().with_commit_msg_settings(CommitMsgSettings {
+            fallback_template: Some(String::new()),
+            ..Default::default()
+        });
+        assert_eq!(processor.render("main", template, None), None);
+        assert_eq!(processor.explain("main", &settings), None);
+    }
+
+    #[test]
+    fn test_fallback_template_uses_comment_char() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(repo)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        git(&["config", "core.commentChar", ";"]);
+
+        let processor = CommitMessageProcessor::new().with_commit_msg_settings(CommitMsgSettings {
+            fallback_template: Some("# Why:\n# What:".to_string()),
+            ..Default::default()
+        });
+        let msg_file = repo.join("COMMIT_EDITMSG");
+        fs::write(&msg_file, "; Please enter the commit message\n").unwrap();
+        processor
+            .process_commit_msg_file_in(
+                repo,
+                &msg_file,
+                None,
+                None,
+                Some("main"),
+                &Settings::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&msg_file).unwrap(),
+            "; Why:\n; What:\n\n; Please enter the commit message\n"
+        );
+    }
+
+    #[test]
+    fn test_commit_template() {
+        let template = include_str!("../tests/fixtures/commit-template/gitmessage");
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(repo)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        fs::write(repo.join(".gitmessage"), template).unwrap();
+
+        let msg_file = repo.join("COMMIT_EDITMSG");
+        let message = format!("{template}\n# Please enter the commit message\n");
+        let prepare = |message: &str, source: Option<&str>| {
+            fs::write(&msg_file, message).unwrap();
+            CommitMessageProcessor::new()
+                .process_commit_msg_file_in(
+                    repo,
+                    &msg_file,
+                    source,
+                    None,
+                    Some("feature/JIRA-123-add-login"),
+                    &Settings::default(),
+                )
+                .unwrap();
+            fs::read_to_string(&msg_file).unwrap()
+        };
+
+        // Without commit.template, its lines are content
+        assert_eq!(prepare(&message, Some("template")), message);
+
+        // With it, the subject goes above the template
+        git(&["config", "commit.template", ".gitmessage"]);
+        assert_eq!(
+            prepare(&message, Some("template")),
+            format!("JIRA-123: Add Login\n\n{message}")
+        );
+
+        // Lines added to the template are content, and so are the
+        // template's lines in a message not started from it
+        let edited = format!("Fix the login form\n\n{message}");
+        assert_eq!(prepare(&edited, Some("template")), edited);
+        assert_eq!(prepare(&message, Some("message")), message);
+
+        // A template that can't be read leaves the lines as content
+        git(&["config", "commit.template", "missing.txt"]);
+        assert_eq!(prepare(&message, Some("template")), message);
+    }
+
+    #[test]
+    fn test_include_branch_description() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(repo)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        git(&[
+            "config",
+            "branch.feature/JIRA-1-login.description",
+            "Adds the login page.\n\nSee the design doc.  \n\n",
+        ]);
+        git(&["config", "branch.feature/JIRA-2-empty.description", " \n"]);
+
+        let processor = CommitMessageProcessor::new().with_commit_msg_settings(CommitMsgSettings {
+            include_branch_description: true,
+            ..Default::default()
+        });
+        let msg_file = repo.join("COMMIT_EDITMSG");
+        let process = |existing: &str, source: Option<&str>, branch: &str| {
+            fs::write(&msg_file, existing).unwrap();
+            processor
+                .process_commit_msg_file_in(
+                    repo,
+                    &msg_file,
+                    source,
+                    None,
+                    Some(branch),
+                    &Settings::default(),
+                )
+                .unwrap();
+            fs::read_to_string(&msg_file).unwrap()
+        };
+
+        assert_eq!(
+            process("# Please enter\n", None, "feature/JIRA-1-login"),
+            "JIRA-1: Login\n\nAdds the login page.\n\nSee the design doc.\n\n# Please enter\n"
+        );
+        // The trailer `git commit -s` adds stays below the body
+        assert_eq!(
+            process(
+                "\nSigned-off-by: A <a@example.com>\n# Please enter\n",
+                None,
+                "feature/JIRA-1-login"
+            ),
+            "JIRA-1: Login\n\nAdds the login page.\n\nSee the design doc.\n\n\nSigned-off-by: A <a@example.com>\n# Please enter\n"
+        );
+        // An empty description is no body
+        assert_eq!(
+            process("# Please enter\n", None, "feature/JIRA-2-empty"),
+            "JIRA-2: Empty\n\n# Please enter\n"
+        );
+        // A message with a body of its own is kept
+        assert_eq!(
+            process(
+                "Fix login\n\nDetails\n",
+                Some("message"),
+                "feature/JIRA-1-login"
+            ),
+            "Fix login\n\nDetails\n"
+        );
+    }
+
+    #[test]
+    fn test_glob_captures() {
+        assert_eq!(glob_captures("release/*", "release/2.3"), Some(vec!["2.3"]));
+        assert_eq!(
+            glob_captures("*/v*-rc*", "release/v2.3-rc1"),
+            Some(vec!["release", "2.3", "1"])
+        );
+        assert_eq!(glob_captures("main", "main"), Some(vec![]));
+        assert_eq!(glob_captures("release/*", "release/"), None);
+        assert_eq!(glob_captures("release/*", "release/2.3/rc1"), None);
+        assert_eq!(glob_captures("release/*", "releases/2.3"), None);
+
+        assert_eq!(
+            fill_captures("Release {1} ({2}) {3} {x}: ", &["2.3", "rc1"]),
+            "Release 2.3 (rc1) {3} {x}: "
+        );
+    }
+
+    #[test]
+    fn test_ask_time() {
+        let mut output = Vec::new();
+        assert_eq!(
+            ask_time("JIRA-1", true, &b"2h\n"[..], &mut output),
+            Some("2h".to_string())
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Time spent on JIRA-1 (e.g. 2h 30m, empty to skip): "
+        );
+        assert_eq!(ask_time("JIRA-1", true, &b"\n"[..], Vec::new()), None);
+        assert_eq!(ask_time("JIRA-1", true, &b""[..], Vec::new()), None);
+
+        // Without a terminal nothing is asked nor read
+        let mut output = Vec::new();
+        assert_eq!(ask_time("JIRA-1", false, &b"2h\n"[..], &mut output), None);
+        assert!(output.is_empty());
+    }
 }
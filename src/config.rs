@@ -1,216 +1,3489 @@
+//! Hookmaster configuration files and their parser
+
 use anyhow::{anyhow, Context, Result};
-use std::collections::HashMap;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::cgroup;
+use crate::ci::CiEnvironment;
+use crate::commit_msg::BranchSeparator;
+use crate::config_source::{ConfigSource, CONFIG_ENV_VAR};
+use crate::dag;
+use crate::fanout;
+use crate::git_hooks::ScriptFormat;
+use crate::placeholder;
+use crate::remote::{self, CommandFetcher};
+use crate::resolve;
+use crate::scheduler;
+
+/// Lines written as inline tables must be shorter than this
+const INLINE_TABLE_MAX_WIDTH: usize = 80;
+
+/// Seconds a command has to exit after the timeout signal, unless the
+/// `timeout-kill-grace` setting says otherwise
+pub const DEFAULT_TIMEOUT_KILL_GRACE: u64 = 5;
+
+/// Configuration for git hooks
+#[derive(Debug, Clone, Default)]
+pub struct GitHooksConfig {
+    /// Map of hook names to their definitions
+    pub hooks: HashMap<String, HookSpec>,
+    /// Settings from the `[settings]` section
+    pub settings: Settings,
+    /// Settings from the `[sandbox]` section
+    pub sandbox: SandboxSettings,
+    /// Variables from the `[vars]` section, used as `{vars.<name>}` in commands
+    pub vars: BTreeMap<String, String>,
+    /// Settings from the `[run]` section
+    pub run: ProjectRunSettings,
+    /// Settings from the `[commit-msg]` section
+    pub commit_msg: CommitMsgSettings,
+    /// Settings from the `[protect]` section
+    pub protect: ProtectSettings,
+    /// Where the configuration was loaded from, if anywhere
+    pub source: Option<ConfigSource>,
+    /// `extends`: the remote configuration this one is merged over, as an
+    /// http(s) URL or `<repository>.git//<path>[@<rev>]`
+    pub extends: Option<String>,
+    /// `includes`: files merged over this configuration, as paths or globs
+    /// relative to its directory
+    pub includes: Vec<String>,
+}
+
+/// Definition of a hook: the commands it runs and how
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HookSpec {
+    /// Commands in declaration order
+    pub commands: Vec<HookEntry>,
+    /// Run commands concurrently, subject to their dependencies
+    pub parallel: bool,
+    /// `env-inherit`: whether commands get hookmaster's whole environment
+    pub env_inherit: Option<bool>,
+    /// `env-allow`: the only environment variables commands get, as names
+    /// or globs such as `GIT_*`
+    pub env_allow: Option<Vec<String>>,
+    /// `max-memory-mb` and `max-cpu-percent`: resource limits for the hook's commands
+    pub limits: CgroupConfig,
+    /// `deny-network`: run the hook's commands without network access
+    pub deny_network: bool,
+    /// `only-remotes`: for `pre-push`, the remotes the hook runs for, by
+    /// name, part of the URL or a glob; all if empty
+    pub only_remotes: Vec<String>,
+    /// `skip-remotes`: for `pre-push`, remotes the hook doesn't run for
+    pub skip_remotes: Vec<String>,
+}
+
+/// Resource limits for the commands of a hook, enforced on Linux by a
+/// cgroup v2 group the commands run in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CgroupConfig {
+    /// `max-memory-mb`: memory the commands may use together, in megabytes
+    pub max_memory_mb: Option<u64>,
+    /// `max-cpu-percent`: CPU time the commands may use, in percent of one
+    /// core; above 100 allows several cores
+    pub max_cpu_percent: Option<f64>,
+}
+
+impl CgroupConfig {
+    /// Check if no limits are set
+    pub fn is_empty(&self) -> bool {
+        self.max_memory_mb.is_none() && self.max_cpu_percent.is_none()
+    }
+}
+
+/// Which environment variables a hook's commands get
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum EnvPolicy {
+    /// All of hookmaster's environment
+    #[default]
+    Inherit,
+    /// Only the variables matching the listed names or globs, for hooks
+    /// that shouldn't see secrets such as `AWS_SECRET_ACCESS_KEY` or
+    /// settings an IDE leaves behind such as `NODE_OPTIONS`
+    Allow(Vec<String>),
+}
+
+impl EnvPolicy {
+    /// Whether commands get the variable `name`
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            EnvPolicy::Inherit => true,
+            EnvPolicy::Allow(patterns) => patterns
+                .iter()
+                .any(|pattern| fanout::wildcard_match(pattern, name)),
+        }
+    }
+}
+
+/// A single command run by a hook
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HookEntry {
+    /// Name of the command; hooks defined as a plain string use the hook name
+    pub name: String,
+    /// Shell command to execute
+    pub command: String,
+    /// Names of commands in the same hook that must succeed first
+    pub depends_on: Vec<String>,
+    /// Names of commands in the same hook that only start once this one has
+    /// succeeded, as if they listed it in `depends_on`
+    pub before: Vec<String>,
+    /// Names of commands in the same hook this one runs after; the same as
+    /// `depends_on`
+    pub after: Vec<String>,
+    /// Directory glob (e.g. `packages/*/`); the command runs once in each match
+    pub foreach_dir: Option<String>,
+    /// Only run in `foreach_dir` matches that contain staged changes
+    pub only_changed: bool,
+    /// For `pre-push`, the remotes the command runs for, by name, part of
+    /// the URL or a glob; all if empty
+    pub only_remotes: Vec<String>,
+    /// For `pre-push`, remotes the command doesn't run for
+    pub skip_remotes: Vec<String>,
+    /// Kill the command if it runs longer than this many seconds
+    pub timeout: Option<u64>,
+    /// How to install the command's program, shown when it isn't found
+    pub install_hint: Option<String>,
+    /// How the command is run
+    pub runner: Runner,
+    /// Container image for the `docker` runner
+    pub image: Option<String>,
+    /// Non-zero exit codes that count as passing with warnings
+    pub allow_exit_codes: Vec<i32>,
+    /// Count any non-zero exit code as passing with warnings
+    pub allow_failure: bool,
+}
+
+/// How a command is run
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Runner {
+    /// Through the platform shell
+    #[default]
+    Shell,
+    /// In a throwaway Docker container of the command's `image`, with the
+    /// repository mounted at `/work`
+    Docker,
+}
+
+impl Runner {
+    /// Parse a `runner` value
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "shell" => Some(Runner::Shell),
+            "docker" => Some(Runner::Docker),
+            _ => None,
+        }
+    }
+
+    /// Name used in configuration files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Runner::Shell => "shell",
+            Runner::Docker => "docker",
+        }
+    }
+}
+
+impl HookEntry {
+    /// Whether the non-zero exit code `code` counts as passing with warnings
+    pub fn allows_exit_code(&self, code: i32) -> bool {
+        self.allow_failure || self.allow_exit_codes.contains(&code)
+    }
+
+    /// Name of the hook this entry refers to, for `@hook` entries
+    pub fn reference(&self) -> Option<&str> {
+        self.command.trim().strip_prefix('@')
+    }
+
+    /// Apply a single `key = value` pair of a command in `hook`
+    fn apply(&mut self, hook: &str, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "command" => self.command = value.into_string(key, line_num)?,
+            "depends_on" => self.depends_on = value.into_string_array(key, line_num)?,
+            "before" => self.before = value.into_names(key, line_num)?,
+            "after" => self.after = value.into_names(key, line_num)?,
+            "foreach_dir" => self.foreach_dir = Some(value.into_string(key, line_num)?),
+            "only_changed" => self.only_changed = value.into_bool(key, line_num)?,
+            "only_remotes" => self.only_remotes = value.into_string_array(key, line_num)?,
+            "skip_remotes" => self.skip_remotes = value.into_string_array(key, line_num)?,
+            "timeout" => {
+                let seconds = value.into_integer(key, line_num)?;
+                if seconds <= 0 {
+                    return Err(anyhow!(
+                        "Invalid timeout {} on line {}. Expected a positive number of seconds.",
+                        seconds,
+                        line_num
+                    ));
+                }
+                self.timeout = Some(seconds as u64);
+            }
+            "install_hint" => self.install_hint = Some(value.into_string(key, line_num)?),
+            "runner" => {
+                let value = value.into_string(key, line_num)?;
+                self.runner = Runner::parse(&value).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid runner '{}' on line {}. Expected \"shell\" or \"docker\".",
+                        value,
+                        line_num
+                    )
+                })?;
+            }
+            "image" => self.image = Some(value.into_string(key, line_num)?),
+            "allow_exit_codes" => {
+                self.allow_exit_codes = value
+                    .into_integer_array(key, line_num)?
+                    .into_iter()
+                    .map(|code| {
+                        i32::try_from(code).map_err(|_| {
+                            anyhow!("Invalid exit code {} on line {}.", code, line_num)
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+            }
+            "allow_failure" => self.allow_failure = value.into_bool(key, line_num)?,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown key '{}' for command '{}' in hook '{}' on line {}.",
+                    key,
+                    self.name,
+                    hook,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a command from an inline table such as `{ command = "make", timeout = 30 }`
+    fn from_table(
+        hook: &str,
+        name: &str,
+        fields: BTreeMap<String, Value>,
+        line_num: usize,
+    ) -> Result<Self> {
+        let mut entry = HookEntry {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        if !fields.contains_key("command") {
+            return Err(anyhow!(
+                "Missing 'command' for command '{}' in hook '{}' on line {}.",
+                name,
+                hook,
+                line_num
+            ));
+        }
+        for (key, value) in fields {
+            entry.apply(hook, &key, value, line_num)?;
+        }
+
+        Ok(entry)
+    }
+
+    /// Settings as `key = value` strings, in the order they are written out
+    fn toml_fields(&self) -> Vec<String> {
+        let mut fields = vec![format!("command = {}", quote(&self.command))];
+        if !self.depends_on.is_empty() {
+            let deps: Vec<String> = self.depends_on.iter().map(|d| quote(d)).collect();
+            fields.push(format!("depends_on = [{}]", deps.join(", ")));
+        }
+        for (key, names) in [("before", &self.before), ("after", &self.after)] {
+            match names.as_slice() {
+                [] => {}
+                [name] => fields.push(format!("{key} = {}", quote(name))),
+                names => {
+                    let names: Vec<String> = names.iter().map(|n| quote(n)).collect();
+                    fields.push(format!("{key} = [{}]", names.join(", ")));
+                }
+            }
+        }
+        if let Some(foreach_dir) = &self.foreach_dir {
+            fields.push(format!("foreach_dir = {}", quote(foreach_dir)));
+        }
+        if self.only_changed {
+            fields.push("only_changed = true".to_string());
+        }
+        for (key, remotes) in [
+            ("only_remotes", &self.only_remotes),
+            ("skip_remotes", &self.skip_remotes),
+        ] {
+            if !remotes.is_empty() {
+                let remotes: Vec<String> = remotes.iter().map(|r| quote(r)).collect();
+                fields.push(format!("{key} = [{}]", remotes.join(", ")));
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            fields.push(format!("timeout = {timeout}"));
+        }
+        if let Some(install_hint) = &self.install_hint {
+            fields.push(format!("install_hint = {}", quote(install_hint)));
+        }
+        if self.runner != Runner::default() {
+            fields.push(format!("runner = {}", quote(self.runner.as_str())));
+        }
+        if let Some(image) = &self.image {
+            fields.push(format!("image = {}", quote(image)));
+        }
+        if !self.allow_exit_codes.is_empty() {
+            let codes: Vec<String> = self.allow_exit_codes.iter().map(i32::to_string).collect();
+            fields.push(format!("allow_exit_codes = [{}]", codes.join(", ")));
+        }
+        if self.allow_failure {
+            fields.push("allow_failure = true".to_string());
+        }
+        fields
+    }
+
+    /// Check if the command is ordered relative to others of its hook
+    fn has_ordering(&self) -> bool {
+        !self.depends_on.is_empty() || !self.before.is_empty() || !self.after.is_empty()
+    }
+
+    /// Check if the command has settings besides the command and its dependencies
+    fn has_settings(&self) -> bool {
+        self.foreach_dir.is_some()
+            || self.only_changed
+            || !self.only_remotes.is_empty()
+            || !self.skip_remotes.is_empty()
+            || self.timeout.is_some()
+            || self.install_hint.is_some()
+            || self.runner != Runner::default()
+            || self.image.is_some()
+            || !self.allow_exit_codes.is_empty()
+            || self.allow_failure
+    }
+
+    /// The command as an inline table
+    fn to_inline_table(&self) -> String {
+        format!("{{ {} }}", self.toml_fields().join(", "))
+    }
+}
+
+impl HookSpec {
+    /// Create a hook that runs a single command
+    pub fn from_command(hook_name: &str, command: &str) -> Self {
+        Self {
+            commands: vec![HookEntry {
+                name: hook_name.to_string(),
+                command: command.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Create a hook that runs a list of commands in order, named after their
+    /// command text (with a ` #n` suffix for repeated commands)
+    pub fn from_commands(commands: Vec<String>) -> Self {
+        let mut entries: Vec<HookEntry> = Vec::new();
+        for command in commands {
+            let mut name = command.clone();
+            let mut n = 1;
+            while entries.iter().any(|entry| entry.name == name) {
+                n += 1;
+                name = format!("{command} #{n}");
+            }
+            entries.push(HookEntry {
+                name,
+                command,
+                ..Default::default()
+            });
+        }
+
+        Self {
+            commands: entries,
+            ..Default::default()
+        }
+    }
+
+    /// The hook as a `[hooks.<key>]` table, followed by `[hooks.<key>.<name>]`
+    /// tables for the commands too long to write inline
+    pub(crate) fn table_lines(&self, key: &str) -> Vec<String> {
+        let mut lines = vec![format!("[hooks.{key}]")];
+        if self.parallel {
+            lines.push("parallel = true".to_string());
+        }
+        if let Some(inherit) = self.env_inherit {
+            lines.push(format!("env-inherit = {inherit}"));
+        }
+        if let Some(allow) = &self.env_allow {
+            let names: Vec<String> = allow.iter().map(|name| quote(name)).collect();
+            lines.push(format!("env-allow = [{}]", names.join(", ")));
+        }
+        if self.deny_network {
+            lines.push("deny-network = true".to_string());
+        }
+        for (key, remotes) in [
+            ("only-remotes", &self.only_remotes),
+            ("skip-remotes", &self.skip_remotes),
+        ] {
+            if !remotes.is_empty() {
+                let remotes: Vec<String> = remotes.iter().map(|r| quote(r)).collect();
+                lines.push(format!("{key} = [{}]", remotes.join(", ")));
+            }
+        }
+        if let Some(megabytes) = self.limits.max_memory_mb {
+            lines.push(format!("max-memory-mb = {megabytes}"));
+        }
+        if let Some(percent) = self.limits.max_cpu_percent {
+            lines.push(format!("max-cpu-percent = {percent}"));
+        }
+
+        // Short commands go inline in the hook's table, longer ones get their own
+        let mut long = Vec::new();
+        for entry in &self.commands {
+            let line = format!("{} = {}", entry.name, entry.to_inline_table());
+            let fits = line.len() < INLINE_TABLE_MAX_WIDTH;
+            let reserved = [
+                "parallel",
+                "env-inherit",
+                "env-allow",
+                "deny-network",
+                "only-remotes",
+                "skip-remotes",
+                "max-memory-mb",
+                "max-cpu-percent",
+            ];
+            if fits && is_bare_key(&entry.name) && !reserved.contains(&entry.name.as_str()) {
+                lines.push(line);
+            } else {
+                long.push(entry);
+            }
+        }
+        for entry in long {
+            lines.push(String::new());
+            lines.push(format!("[hooks.{key}.{}]", entry.name));
+            lines.extend(entry.toml_fields());
+        }
+        lines
+    }
+
+    /// The environment the hook's commands get: only the `env-allow`
+    /// variables (none if not given) when `env-inherit = false` or
+    /// `env-allow` is set, otherwise everything
+    pub fn env_policy(&self) -> EnvPolicy {
+        match (self.env_inherit, &self.env_allow) {
+            (Some(true), _) | (None, None) => EnvPolicy::Inherit,
+            (_, allow) => EnvPolicy::Allow(allow.clone().unwrap_or_default()),
+        }
+    }
+
+    /// Check if the hook has `env-inherit`, `env-allow`, resource limits,
+    /// `deny-network` or remotes to run for, which need the table form
+    fn has_hook_settings(&self) -> bool {
+        self.env_inherit.is_some()
+            || self.env_allow.is_some()
+            || !self.limits.is_empty()
+            || self.deny_network
+            || !self.only_remotes.is_empty()
+            || !self.skip_remotes.is_empty()
+    }
+
+    /// Check if the hook can be written as a plain `hook = "command"` line
+    fn is_simple(&self, hook_name: &str) -> bool {
+        self.single_entry(hook_name)
+            .is_some_and(|entry| !entry.has_settings())
+    }
+
+    /// The hook's only command, if the hook can be written as a single
+    /// `hook = "command"` or `hook = { ... }` line
+    fn single_entry(&self, hook_name: &str) -> Option<&HookEntry> {
+        match self.commands.as_slice() {
+            [entry]
+                if !self.parallel
+                    && !self.has_hook_settings()
+                    && entry.name == hook_name
+                    && !entry.has_ordering() =>
+            {
+                Some(entry)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check if the hook can be written as a `hook = ["command", ...]` list
+    fn is_list(&self) -> bool {
+        !self.parallel
+            && !self.has_hook_settings()
+            && self.commands.iter().all(|entry| {
+                !entry.has_ordering()
+                    && !entry.has_settings()
+                    && (entry.name == entry.command
+                        || entry
+                            .name
+                            .strip_prefix(entry.command.as_str())
+                            .is_some_and(|suffix| suffix.starts_with(" #")))
+            })
+    }
+
+    /// Check the hook's commands for missing commands, unknown dependencies and cycles
+    pub fn validate(&self, hook_name: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.env_inherit == Some(true) && self.env_allow.is_some() {
+            problems.push(format!(
+                "Hook '{hook_name}': 'env-allow' has no effect with 'env-inherit = true'"
+            ));
+        }
+
+        for entry in &self.commands {
+            if entry.command.trim().is_empty() && entry.name != hook_name {
+                problems.push(format!(
+                    "Hook '{hook_name}': command '{}' has no 'command' to run",
+                    entry.name
+                ));
+            }
+            if entry.only_changed && entry.foreach_dir.is_none() {
+                problems.push(format!(
+                    "Hook '{hook_name}': command '{}' sets 'only_changed' without 'foreach_dir'",
+                    entry.name
+                ));
+            }
+            match (entry.runner, &entry.image) {
+                (Runner::Docker, None) => problems.push(format!(
+                    "Hook '{hook_name}': command '{}' uses runner 'docker' without an 'image'",
+                    entry.name
+                )),
+                (Runner::Shell, Some(_)) => problems.push(format!(
+                    "Hook '{hook_name}': command '{}' sets 'image' without runner = \"docker\"",
+                    entry.name
+                )),
+                _ => {}
+            }
+        }
+
+        for entry in &self.commands {
+            for name in &entry.before {
+                if !self.commands.iter().any(|other| other.name == *name) {
+                    problems.push(format!(
+                        "Hook '{hook_name}': command '{}' runs before unknown command '{name}'",
+                        entry.name
+                    ));
+                }
+            }
+        }
+
+        let prerequisites = dag::prerequisites(&self.commands);
+        let tasks = dag::tasks(&self.commands, &prerequisites);
+        for unknown in scheduler::unknown_dependencies(&tasks) {
+            problems.push(format!(
+                "Hook '{hook_name}': command '{}' depends on unknown command '{}'",
+                unknown.task, unknown.dependency
+            ));
+        }
+        if let Some(cycle) = scheduler::find_cycle(&tasks) {
+            problems.push(format!(
+                "Hook '{hook_name}': dependency cycle: {}",
+                cycle.join(" -> ")
+            ));
+        }
+
+        problems
+    }
+}
+
+/// Settings from the `[settings]` section of githooks.toml
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Settings {
+    /// Separator between the ticket and the description in branch names
+    pub branch_separator: BranchSeparator,
+    /// Shell that runs hook commands instead of the platform default
+    pub shell: Option<String>,
+    /// Arguments to the shell before `-c`, such as `--noprofile`
+    pub shell_args: Vec<String>,
+    /// Environment variable with the ticket for branches without one,
+    /// instead of `JIRA_TICKET`
+    pub ticket_env_var: Option<String>,
+    /// Shell the hook scripts `add` installs are written for
+    pub script_format: ScriptFormat,
+    /// Prefix commit messages with every ticket in the branch name
+    pub multi_ticket: bool,
+    /// Give commit messages a Conventional Commits type from the branch
+    /// prefix, with a scope from the staged files
+    pub conventional_commits: bool,
+    /// Command `run` runs before each hook, with `HOOKMASTER_HOOK_NAME` set
+    pub pre_hook_command: Option<String>,
+    /// Command `run` runs after each hook, with `HOOKMASTER_HOOK_NAME` and
+    /// `HOOKMASTER_EXIT_CODE` set
+    pub post_hook_command: Option<String>,
+    /// Fail the hook without running it when the pre-hook command fails
+    pub pre_hook_fail_aborts: bool,
+    /// Fail the hook when the post-hook command fails
+    pub post_hook_fail_aborts: bool,
+    /// Stash unstaged changes while the `pre-commit` hook runs
+    pub pre_commit_stash_unstaged: bool,
+    /// Warn about or refuse files with changes both staged and not before
+    /// the `pre-commit` hook runs
+    pub pre_commit_partially_staged: Option<Severity>,
+    /// File each command a hook runs is logged to as a JSON line, with a
+    /// leading `~` for the home directory
+    pub audit_log: Option<String>,
+    /// Seconds a command that ran past its timeout has to exit after the
+    /// timeout signal before it is killed, instead of 5
+    pub timeout_kill_grace: Option<u64>,
+    /// Whether the arguments git gives a hook are appended to its commands
+    /// that don't refer to them; they are unless this is `false`
+    pub append_args: Option<bool>,
+}
+
+impl Settings {
+    /// Seconds a command has to exit after the timeout signal
+    pub fn timeout_kill_grace(&self) -> u64 {
+        self.timeout_kill_grace
+            .unwrap_or(DEFAULT_TIMEOUT_KILL_GRACE)
+    }
+
+    /// Whether the arguments git gives a hook are appended to its commands
+    /// that don't refer to them
+    pub fn append_args(&self) -> bool {
+        self.append_args.unwrap_or(true)
+    }
+
+    /// These settings with those left at their defaults taken from `base`
+    fn merged_over(self, base: Settings) -> Self {
+        let default = Settings::default();
+        Self {
+            branch_separator: if self.branch_separator == default.branch_separator {
+                base.branch_separator
+            } else {
+                self.branch_separator
+            },
+            shell: self.shell.or(base.shell),
+            shell_args: if self.shell_args.is_empty() {
+                base.shell_args
+            } else {
+                self.shell_args
+            },
+            ticket_env_var: self.ticket_env_var.or(base.ticket_env_var),
+            script_format: if self.script_format == default.script_format {
+                base.script_format
+            } else {
+                self.script_format
+            },
+            multi_ticket: self.multi_ticket || base.multi_ticket,
+            conventional_commits: self.conventional_commits || base.conventional_commits,
+            pre_hook_command: self.pre_hook_command.or(base.pre_hook_command),
+            post_hook_command: self.post_hook_command.or(base.post_hook_command),
+            pre_hook_fail_aborts: self.pre_hook_fail_aborts || base.pre_hook_fail_aborts,
+            post_hook_fail_aborts: self.post_hook_fail_aborts || base.post_hook_fail_aborts,
+            pre_commit_stash_unstaged: self.pre_commit_stash_unstaged
+                || base.pre_commit_stash_unstaged,
+            pre_commit_partially_staged: self
+                .pre_commit_partially_staged
+                .or(base.pre_commit_partially_staged),
+            audit_log: self.audit_log.or(base.audit_log),
+            timeout_kill_grace: self.timeout_kill_grace.or(base.timeout_kill_grace),
+            append_args: self.append_args.or(base.append_args),
+        }
+    }
+
+    /// Apply a single `key = value` pair from the `[settings]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "branch-separator" => {
+                let value = value.into_string(key, line_num)?;
+                self.branch_separator = BranchSeparator::parse(&value).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid branch-separator '{}' on line {}. Expected \"-\", \"_\", \".\" or \"auto\".",
+                        value,
+                        line_num
+                    )
+                })?;
+            }
+            "shell" => self.shell = Some(value.into_string(key, line_num)?),
+            "shell-args" => self.shell_args = value.into_string_array(key, line_num)?,
+            "ticket-env-var" => self.ticket_env_var = Some(value.into_string(key, line_num)?),
+            "multi-ticket" => self.multi_ticket = value.into_bool(key, line_num)?,
+            "conventional-commits" => self.conventional_commits = value.into_bool(key, line_num)?,
+            "pre-hook-command" => self.pre_hook_command = Some(value.into_string(key, line_num)?),
+            "post-hook-command" => self.post_hook_command = Some(value.into_string(key, line_num)?),
+            "pre-hook-fail-aborts" => self.pre_hook_fail_aborts = value.into_bool(key, line_num)?,
+            "post-hook-fail-aborts" => {
+                self.post_hook_fail_aborts = value.into_bool(key, line_num)?
+            }
+            "pre-commit-stash-unstaged" => {
+                self.pre_commit_stash_unstaged = value.into_bool(key, line_num)?
+            }
+            "pre-commit-partially-staged" => {
+                let value = value.into_string(key, line_num)?;
+                let severity = Severity::parse(&value).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid pre-commit-partially-staged '{}' on line {}. Expected \"warn\" or \"error\".",
+                        value,
+                        line_num
+                    )
+                })?;
+                self.pre_commit_partially_staged = Some(severity);
+            }
+            "audit-log" => self.audit_log = Some(value.into_string(key, line_num)?),
+            "timeout-kill-grace" => {
+                let seconds = value.into_integer(key, line_num)?;
+                if seconds < 0 {
+                    return Err(anyhow!(
+                        "Invalid timeout-kill-grace {} on line {}. Expected a number of seconds.",
+                        seconds,
+                        line_num
+                    ));
+                }
+                self.timeout_kill_grace = Some(seconds as u64);
+            }
+            "append-args" => self.append_args = Some(value.into_bool(key, line_num)?),
+            "script-format" => {
+                let value = value.into_string(key, line_num)?;
+                self.script_format = ScriptFormat::parse(&value).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid script-format '{}' on line {}. Expected \"sh\" or \"fish\".",
+                        value,
+                        line_num
+                    )
+                })?;
+            }
+            _ => {
+                return Err(anyhow!("Unknown setting '{}' on line {}.", key, line_num));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings from the `[sandbox]` section of githooks.toml
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SandboxSettings {
+    /// Run hook commands with restricted capabilities
+    pub enabled: bool,
+    /// Allow network access from sandboxed commands
+    pub allow_network: bool,
+}
+
+impl SandboxSettings {
+    /// Apply a single `key = value` pair from the `[sandbox]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "enabled" => self.enabled = value.into_bool(key, line_num)?,
+            "sandbox-allow-network" => self.allow_network = value.into_bool(key, line_num)?,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown sandbox setting '{}' on line {}.",
+                    key,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings from the `[run]` section of githooks.toml
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProjectRunSettings {
+    /// Directories prepended to `PATH` for hook commands, relative to the
+    /// repository root. When unset, tool directories such as
+    /// `node_modules/.bin` are added if the project uses them.
+    pub extra_paths: Option<Vec<String>>,
+    /// `record_timings`: whether hooks log how long each command took to
+    /// `.git/hookmaster-timings.jsonl`; they do unless set to `false`
+    pub record_timings: Option<bool>,
+    /// `timings_max_kb`: size the timings log grows to before it is rotated
+    pub timings_max_kb: Option<u64>,
+}
+
+impl ProjectRunSettings {
+    /// Apply a single `key = value` pair from the `[run]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "extra_paths" => self.extra_paths = Some(value.into_string_array(key, line_num)?),
+            "record_timings" => self.record_timings = Some(value.into_bool(key, line_num)?),
+            "timings_max_kb" => {
+                let size = value.into_integer(key, line_num)?;
+                if size <= 0 {
+                    return Err(anyhow!(
+                        "Invalid timings_max_kb {} on line {}. Expected a positive number of kilobytes.",
+                        size,
+                        line_num
+                    ));
+                }
+                self.timings_max_kb = Some(size as u64);
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unknown run setting '{}' on line {}.",
+                    key,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings from the `[commit-msg]` section of githooks.toml
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CommitMsgSettings {
+    /// `wrap_body`: width the `commit-msg` hook rewraps the paragraphs of
+    /// the message body to, leaving the subject and trailers alone
+    pub wrap_body: Option<usize>,
+    /// `smart_commit`: template of the Jira smart commit commands
+    /// `prepare-commit-msg` adds to the body of the messages it starts, such
+    /// as `#comment {description}`
+    pub smart_commit: Option<String>,
+    /// `prompt_time`: have `prepare-commit-msg` ask for the time spent on a
+    /// terminal and add it as a `#time` command
+    pub prompt_time: bool,
+    /// `fallback_template`: the message `prepare-commit-msg` starts commits
+    /// with on branches that give no ticket or branch prefix, such as a
+    /// commented checklist
+    pub fallback_template: Option<String>,
+    /// `include_branch_description`: have `prepare-commit-msg` add the
+    /// description `git branch --edit-description` gave the branch as the
+    /// body of the messages it starts
+    pub include_branch_description: bool,
+    /// `forbid_duplicate_subject_within`: have the `commit-msg` hook check
+    /// the subject against those of this many recent commits on the branch
+    pub forbid_duplicate_subject_within: Option<usize>,
+    /// `duplicate_subject_severity`: whether a duplicate subject is warned
+    /// about or fails the hook
+    pub duplicate_subject_severity: Severity,
+    /// `[commit-msg.branch_prefixes]`: the messages `prepare-commit-msg`
+    /// starts on branches matching a pattern, tried in order before the
+    /// ticket of the branch
+    pub branch_prefixes: Vec<BranchPrefix>,
+}
+
+/// An entry of `[commit-msg.branch_prefixes]`, such as
+/// `"release/*" = "Release {1}: "`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchPrefix {
+    /// Branch pattern, where each `*` matches part of a path segment
+    pub pattern: String,
+    /// Start of the message, with `{1}`, `{2}`, ... for what the first,
+    /// second, ... `*` matched
+    pub prefix: String,
+}
+
+/// What a check does when it fails, such as one of `[commit-msg]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Severity {
+    /// Print a warning and let the commit go ahead
+    #[default]
+    Warn,
+    /// Fail the hook, stopping the commit
+    Error,
+}
+
+impl Severity {
+    /// Parse `warn` or `error`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "warn" => Some(Severity::Warn),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+
+    /// The name [`parse`](Self::parse) accepts
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// Hash `config hash` prints of the configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashFormat {
+    /// SHA-256
+    #[default]
+    Sha256,
+    /// MD5, for CI caches keyed by it
+    Md5,
+}
+
+impl HashFormat {
+    /// Names accepted by `--format`
+    pub const NAMES: [&'static str; 2] = ["sha256", "md5"];
+
+    /// Parse a `--format` value
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashFormat::Sha256),
+            "md5" => Some(HashFormat::Md5),
+            _ => None,
+        }
+    }
+}
+
+impl CommitMsgSettings {
+    /// Apply a single `key = value` pair from the `[commit-msg]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "wrap_body" => {
+                let width = value.into_integer(key, line_num)?;
+                if width <= 0 {
+                    return Err(anyhow!(
+                        "Invalid wrap_body {} on line {}. Expected a positive number of characters.",
+                        width,
+                        line_num
+                    ));
+                }
+                self.wrap_body = Some(width as usize);
+            }
+            "smart_commit" => self.smart_commit = Some(value.into_string(key, line_num)?),
+            "prompt_time" => self.prompt_time = value.into_bool(key, line_num)?,
+            "fallback_template" => self.fallback_template = Some(value.into_string(key, line_num)?),
+            "include_branch_description" => {
+                self.include_branch_description = value.into_bool(key, line_num)?
+            }
+            "forbid_duplicate_subject_within" => {
+                let count = value.into_integer(key, line_num)?;
+                if count <= 0 {
+                    return Err(anyhow!(
+                        "Invalid forbid_duplicate_subject_within {} on line {}. Expected a positive number of commits.",
+                        count,
+                        line_num
+                    ));
+                }
+                self.forbid_duplicate_subject_within = Some(count as usize);
+            }
+            "duplicate_subject_severity" => {
+                let value = value.into_string(key, line_num)?;
+                self.duplicate_subject_severity = Severity::parse(&value).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid duplicate_subject_severity '{}' on line {}. Expected \"warn\" or \"error\".",
+                        value,
+                        line_num
+                    )
+                })?;
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unknown commit-msg setting '{}' on line {}.",
+                    key,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the `pattern = prefix` pair of the `[commit-msg.branch_prefixes]`
+    /// section
+    fn add_branch_prefix(&mut self, pattern: &str, value: Value, line_num: usize) -> Result<()> {
+        if self.branch_prefixes.iter().any(|p| p.pattern == pattern) {
+            return Err(anyhow!(
+                "Branch prefix '{}' is defined more than once on line {}.",
+                pattern,
+                line_num
+            ));
+        }
+        self.branch_prefixes.push(BranchPrefix {
+            pattern: pattern.to_string(),
+            prefix: value.into_string(pattern, line_num)?,
+        });
+        Ok(())
+    }
+}
+
+/// Settings from the `[protect]` section of githooks.toml
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProtectSettings {
+    /// `branches`: patterns with `*` and `?` of the branches the checks
+    /// apply to; `main` and `master` when unset
+    pub branches: Option<Vec<String>>,
+    /// `forbid_unfinished_commits`: refuse to push `fixup!`, `squash!`,
+    /// `amend!` and `WIP` commits to protected branches
+    pub forbid_unfinished_commits: bool,
+    /// `forbidden_subject_patterns`: regular expressions of further commit
+    /// subjects `forbid_unfinished_commits` refuses
+    pub forbidden_subject_patterns: Vec<String>,
+    /// `require_ticket_in_pushed_commits`: refuse to push commits to any
+    /// branch whose message references no ticket
+    pub require_ticket_in_pushed_commits: bool,
+}
+
+impl ProtectSettings {
+    /// Apply a single `key = value` pair from the `[protect]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "branches" => self.branches = Some(value.into_string_array(key, line_num)?),
+            "forbid_unfinished_commits" => {
+                self.forbid_unfinished_commits = value.into_bool(key, line_num)?
+            }
+            "require_ticket_in_pushed_commits" => {
+                self.require_ticket_in_pushed_commits = value.into_bool(key, line_num)?
+            }
+            "forbidden_subject_patterns" => {
+                let patterns = value.into_string_array(key, line_num)?;
+                for pattern in &patterns {
+                    regex::Regex::new(pattern).map_err(|e| {
+                        anyhow!(
+                            "Invalid pattern '{}' in forbidden_subject_patterns on line {}: {}",
+                            pattern,
+                            line_num,
+                            e
+                        )
+                    })?;
+                }
+                self.forbidden_subject_patterns = patterns;
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unknown protect setting '{}' on line {}.",
+                    key,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings from the `[run]` section of the user configuration
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunSettings {
+    /// Fail hooks when there is no configuration or the hook has no command
+    pub strict: bool,
+}
+
+impl RunSettings {
+    /// Apply a single `key = value` pair from the `[run]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "strict" => self.strict = value.into_bool(key, line_num)?,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown run setting '{}' on line {}.",
+                    key,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings from the `[add]` section of the user configuration
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AddSettings {
+    /// Install hooks only to repositories with a configuration of their own,
+    /// and only the hooks it configures
+    pub only_configured: bool,
+    /// Most repositories `add` installs to without asking for confirmation;
+    /// `None` for [`DEFAULT_CONFIRM_THRESHOLD`](crate::select::DEFAULT_CONFIRM_THRESHOLD)
+    pub confirm_threshold: Option<usize>,
+}
+
+impl AddSettings {
+    /// Apply a single `key = value` pair from the `[add]` section
+    fn apply(&mut self, key: &str, value: Value, line_num: usize) -> Result<()> {
+        match key {
+            "only-configured" => self.only_configured = value.into_bool(key, line_num)?,
+            "confirm-threshold" => {
+                let count = value.into_integer(key, line_num)?;
+                if count < 0 {
+                    return Err(anyhow!(
+                        "Invalid confirm-threshold {} on line {}. Expected a number of repositories.",
+                        count,
+                        line_num
+                    ));
+                }
+                self.confirm_threshold = Some(count as usize);
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unknown add setting '{}' on line {}.",
+                    key,
+                    line_num
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Per-user configuration, kept outside repositories so that it can't be
+/// changed by editing a repository's files
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UserConfig {
+    /// Settings from the `[run]` section
+    pub run: RunSettings,
+    /// Settings from the `[add]` section
+    pub add: AddSettings,
+}
+
+impl UserConfig {
+    /// Location of the user configuration: `hookmaster/config.toml` under
+    /// `%APPDATA%` on Windows, `$XDG_CONFIG_HOME` or `~/.config` elsewhere
+    pub fn path() -> Option<PathBuf> {
+        let dir = if cfg!(target_os = "windows") {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        }?;
+        Some(dir.join("hookmaster").join("config.toml"))
+    }
+
+    /// Load the user configuration, if there is one, with `HOOKMASTER_STRICT=1`
+    /// turning on strict mode
+    pub fn load() -> Result<Self> {
+        let mut config = match Self::path().filter(|path| path.is_file()) {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+                Self::parse(&content)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?
+            }
+            None => Self::default(),
+        };
+
+        // The environment can only turn strict mode on
+        if env::var("HOOKMASTER_STRICT").is_ok_and(|value| matches!(value.as_str(), "1" | "true")) {
+            config.run.strict = true;
+        }
+
+        Ok(config)
+    }
+
+    /// Parse the user configuration file
+    fn parse(content: &str) -> Result<Self> {
+        let mut config = Self::default();
+        let mut section = None;
+
+        for (line_num, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let header = line[1..line.len() - 1].trim();
+                if header != "run" && header != "add" {
+                    return Err(anyhow!(
+                        "Unknown section '[{}]' on line {}.",
+                        header,
+                        line_num + 1
+                    ));
+                }
+                section = Some(header);
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(anyhow!(
+                    "Invalid TOML syntax on line {}: '{}'. Expected 'key = value' format.",
+                    line_num + 1,
+                    line
+                ));
+            };
+            let key = key.trim();
+            let Some(section) = section else {
+                return Err(anyhow!(
+                    "Setting '{}' on line {} must be in a section such as [run].",
+                    key,
+                    line_num + 1
+                ));
+            };
+            let value = ValueParser::parse(value, line_num + 1)?;
+            match section {
+                "run" => config.run.apply(key, value, line_num + 1)?,
+                _ => config.add.apply(key, value, line_num + 1)?,
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Section of githooks.toml that `key = value` lines currently belong to
+enum Section {
+    /// Top level, where keys are hook names
+    Root,
+    /// `[settings]`
+    Settings,
+    /// `[sandbox]`
+    Sandbox,
+    /// `[vars]`
+    Vars,
+    /// `[run]`
+    Run,
+    /// `[commit-msg]`
+    CommitMsg,
+    /// `[commit-msg.branch_prefixes]`
+    BranchPrefixes,
+    /// `[protect]`
+    Protect,
+    /// `[hooks.<hook>]`
+    Hook(String),
+    /// `[hooks.<hook>.<command>]`, with the index of the command in the hook
+    HookCommand(String, usize),
+}
+
+/// Value on the right-hand side of `key = value`
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Name of the value's type for error messages
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+
+    fn into_string(self, key: &str, line_num: usize) -> Result<String> {
+        match self {
+            Value::String(s) => Ok(s),
+            other => Err(type_error(key, "a string", &other, line_num)),
+        }
+    }
+
+    fn into_integer(self, key: &str, line_num: usize) -> Result<i64> {
+        match self {
+            Value::Integer(i) => Ok(i),
+            other => Err(type_error(key, "an integer", &other, line_num)),
+        }
+    }
+
+    fn into_bool(self, key: &str, line_num: usize) -> Result<bool> {
+        match self {
+            Value::Boolean(b) => Ok(b),
+            other => Err(type_error(key, "a boolean", &other, line_num)),
+        }
+    }
+
+    fn into_integer_array(self, key: &str, line_num: usize) -> Result<Vec<i64>> {
+        match self {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::Integer(i) => Ok(i),
+                    other => Err(type_error(key, "an array of integers", &other, line_num)),
+                })
+                .collect(),
+            other => Err(type_error(key, "an array of integers", &other, line_num)),
+        }
+    }
+
+    /// A single name or an array of them
+    fn into_names(self, key: &str, line_num: usize) -> Result<Vec<String>> {
+        match self {
+            Value::String(name) => Ok(vec![name]),
+            Value::Array(_) => self.into_string_array(key, line_num),
+            other => Err(type_error(
+                key,
+                "a string or an array of strings",
+                &other,
+                line_num,
+            )),
+        }
+    }
+
+    fn into_string_array(self, key: &str, line_num: usize) -> Result<Vec<String>> {
+        match self {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s),
+                    other => Err(type_error(key, "an array of strings", &other, line_num)),
+                })
+                .collect(),
+            other => Err(type_error(key, "an array of strings", &other, line_num)),
+        }
+    }
+}
+
+fn type_error(key: &str, expected: &str, found: &Value, line_num: usize) -> anyhow::Error {
+    anyhow!(
+        "Invalid value for '{}' on line {}. Expected {}, found {}.",
+        key,
+        line_num,
+        expected,
+        found.type_name()
+    )
+}
+
+/// Parse a percentage such as `50` or `12.5`. The value parser has no
+/// floats, so a number with a fraction arrives as a string.
+fn parse_percent(key: &str, value: Value, line_num: usize) -> Result<f64> {
+    let percent = match value {
+        Value::Integer(i) => i as f64,
+        Value::String(s) => s.parse().map_err(|_| {
+            anyhow!(
+                "Invalid {} '{}' on line {}. Expected a number.",
+                key,
+                s,
+                line_num
+            )
+        })?,
+        other => return Err(type_error(key, "a number", &other, line_num)),
+    };
+    if !percent.is_finite() || percent <= 0.0 {
+        return Err(anyhow!(
+            "Invalid {} {} on line {}. Expected a positive percentage.",
+            key,
+            percent,
+            line_num
+        ));
+    }
+    Ok(percent)
+}
+
+/// Parser for a single value, with support for quoted strings, integers,
+/// booleans, arrays and inline tables
+struct ValueParser<'a> {
+    text: &'a str,
+    pos: usize,
+    line_num: usize,
+}
+
+impl<'a> ValueParser<'a> {
+    /// Parse the text after `=` on a line
+    fn parse(text: &'a str, line_num: usize) -> Result<Value> {
+        let text = text.trim();
+        let mut parser = ValueParser {
+            text,
+            pos: 0,
+            line_num,
+        };
+
+        match parser.peek() {
+            // Empty value (no quotes)
+            None => Ok(Value::String(String::new())),
+            Some('"') | Some('\'') | Some('[') | Some('{') => {
+                let value = parser.value()?;
+                parser.skip_whitespace();
+                if !parser.rest().is_empty() && !parser.rest().starts_with('#') {
+                    return Err(parser.error("Unexpected characters after value"));
+                }
+                Ok(value)
+            }
+            // Unquoted values are taken verbatim unless they are a boolean or an integer
+            Some(_) => Ok(match text {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                _ => text
+                    .parse()
+                    .map(Value::Integer)
+                    .unwrap_or_else(|_| Value::String(text.to_string())),
+            }),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("{} on line {}: '{}'.", message, self.line_num, self.text)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            _ => self.scalar(),
+        }
+    }
+
+    /// Double-quoted string with backslash escapes
+    fn basic_string(&mut self) -> Result<String> {
+        self.next();
+        let mut result = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    // Unknown escapes are kept as written
+                    Some(c) => {
+                        result.push('\\');
+                        result.push(c);
+                    }
+                    None => break,
+                },
+                Some(c) => result.push(c),
+                None => break,
+            }
+        }
+        Err(self.error("Unterminated string"))
+    }
+
+    /// Single-quoted string (literal)
+    fn literal_string(&mut self) -> Result<String> {
+        self.next();
+        let end = self
+            .rest()
+            .find('\'')
+            .ok_or_else(|| self.error("Unterminated string"))?;
+        let result = self.rest()[..end].to_string();
+        self.pos += end + 1;
+        Ok(result)
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.next();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("Expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    /// Inline table such as `{ command = "make", timeout = 30 }`
+    fn inline_table(&mut self) -> Result<Value> {
+        self.next();
+        let mut fields = BTreeMap::new();
+        loop {
+            self.skip_whitespace();
+            if fields.is_empty() && self.peek() == Some('}') {
+                self.next();
+                return Ok(Value::Table(fields));
+            }
+
+            let key = self.key()?;
+            self.skip_whitespace();
+            if self.next() != Some('=') {
+                return Err(self.error(&format!("Expected '=' after '{key}' in inline table")));
+            }
+            self.skip_whitespace();
+            let value = self.value()?;
+            if fields.insert(key.clone(), value).is_some() {
+                return Err(self.error(&format!("Duplicate key '{key}' in inline table")));
+            }
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Table(fields)),
+                _ => return Err(self.error("Expected ',' or '}' in inline table")),
+            }
+        }
+    }
+
+    /// Bare or quoted key inside an inline table
+    fn key(&mut self) -> Result<String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                    .unwrap_or(self.rest().len());
+                if len == 0 {
+                    return Err(self.error("Expected a key in inline table"));
+                }
+                let key = self.rest()[..len].to_string();
+                self.pos += len;
+                Ok(key)
+            }
+        }
+    }
+
+    /// Boolean or integer inside an array or inline table
+    fn scalar(&mut self) -> Result<Value> {
+        let len = self
+            .rest()
+            .find(|c: char| c == ',' || c == ']' || c == '}' || c == '#' || c.is_whitespace())
+            .unwrap_or(self.rest().len());
+        let token = &self.rest()[..len];
+        let value = match token {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ => token
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| self.error(&format!("Invalid value '{token}'")))?,
+        };
+        self.pos += len;
+        Ok(value)
+    }
+}
+
+/// Check if a key can be written without quotes
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The text of a key in double or single quotes, such as `"release/*"`;
+/// `None` for a bare key
+fn unquote_key(key: &str) -> Option<&str> {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|q| key.strip_prefix(q)?.strip_suffix(q))
+}
+
+/// Quote a string for TOML output, escaping quotes, backslashes, newlines and tabs
+pub(crate) fn quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', r"\\")
+            .replace('"', r#"\""#)
+            .replace('\n', r"\n")
+            .replace('\t', r"\t")
+    )
+}
+
+/// The files matching `patterns`, paths or globs relative to `base_dir`: the
+/// matches of each pattern sorted by name, in the order of the patterns. A
+/// path without wildcards must exist; a glob may match nothing.
+pub fn expand_globs(base_dir: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for pattern in patterns {
+        let full = base_dir.join(pattern);
+        let full = full.to_string_lossy();
+        let mut matches = glob::glob(&full)
+            .with_context(|| format!("Invalid include pattern '{pattern}'"))?
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .with_context(|| format!("Failed to expand include pattern '{pattern}'"))?;
+        if matches.is_empty() && !pattern.contains(['*', '?', '[']) {
+            return Err(anyhow!("Included file not found: {full}"));
+        }
+        matches.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        for file in matches {
+            if file.is_file() && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+impl GitHooksConfig {
+    /// Load configuration from a TOML file, with the files it includes
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.as_ref().display()))?;
+
+        let config = Self::parse_toml(&content).with_context(|| "Failed to parse githooks.toml")?;
+
+        config.with_includes(path.as_ref(), &mut HashSet::new())
+    }
+
+    /// This configuration, read from `path`, with the files named by its
+    /// `includes` merged over it in turn, so later files override earlier
+    /// ones. `including` holds the files whose includes are being merged,
+    /// to catch a file that ends up including itself.
+    fn with_includes(self, path: &Path, including: &mut HashSet<PathBuf>) -> Result<Self> {
+        if self.includes.is_empty() {
+            return Ok(self);
+        }
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if !including.insert(canonical.clone()) {
+            return Err(anyhow!("Circular include of {}", path.display()));
+        }
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        let mut merged = self;
+        for file in expand_globs(base_dir, &merged.includes)? {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read included file: {}", file.display()))?;
+            let included = Self::parse_toml(&content)
+                .with_context(|| format!("Failed to parse included file: {}", file.display()))?
+                .with_includes(&file, including)
+                .with_context(|| format!("Failed to include {}", file.display()))?;
+            let (source, extends, includes) = (
+                merged.source.take(),
+                merged.extends.take(),
+                std::mem::take(&mut merged.includes),
+            );
+            merged = included.merged_over(merged);
+            merged.source = source;
+            merged.extends = extends;
+            merged.includes = includes;
+        }
+
+        including.remove(&canonical);
+        Ok(merged)
+    }
+
+    /// Load configuration from the current directory, or from the file given
+    /// with `--config` (`config_path`) or `HOOKMASTER_CONFIG`
+    pub fn load(config_path: Option<&Path>) -> Result<Self> {
+        let explicit = ConfigSource::explicit(config_path, env::var_os(CONFIG_ENV_VAR));
+        Ok(Self::discover(Path::new("."), explicit)?.unwrap_or_default())
+    }
+
+    /// Load configuration from the working tree in `dir`, or from the file
+    /// given with `--config` (`config_path`) or `HOOKMASTER_CONFIG`, which
+    /// is relative to the current directory
+    pub fn load_in(dir: &Path, config_path: Option<&Path>) -> Result<Self> {
+        let explicit =
+            ConfigSource::explicit(config_path, env::var_os(CONFIG_ENV_VAR)).map(|source| {
+                match source {
+                    ConfigSource::File(path) if path.is_relative() => ConfigSource::File(
+                        env::current_dir()
+                            .map(|cwd| cwd.join(&path))
+                            .unwrap_or(path),
+                    ),
+                    source => source,
+                }
+            });
+        Ok(Self::discover(dir, explicit)?.unwrap_or_default())
+    }
+
+    /// Find and load the configuration in `dir` from the first source in
+    /// [`ConfigSource::PRECEDENCE`] that has one: hookmaster.toml (or the
+    /// shared configuration named by a `.hookmaster-config-link`), then
+    /// githooks.toml, Cargo.toml, package.json and pyproject.toml. An
+    /// `explicit` source is used instead of searching when given. The source
+    /// used is recorded in [`GitHooksConfig::source`].
+    pub fn discover(dir: &Path, explicit: Option<ConfigSource>) -> Result<Option<Self>> {
+        if let Some(source) = explicit {
+            return Self::load_source(dir, source);
+        }
+
+        // A symbolic link to a shared configuration is read like the file
+        // itself; the link file stands in for one where links aren't possible
+        if !ConfigSource::HookmasterToml.path(dir).is_file() {
+            if let Some(source) = ConfigSource::linked(dir)? {
+                return Self::load_source(dir, source);
+            }
+        }
+
+        if ConfigSource::HookmasterToml.path(dir).is_file()
+            && ConfigSource::GitHooksToml.path(dir).is_file()
+        {
+            eprintln!(
+                "Warning: both hookmaster.toml and githooks.toml exist; \
+                 using hookmaster.toml and ignoring githooks.toml"
+            );
+        }
+
+        for source in ConfigSource::PRECEDENCE {
+            if let Some(config) = Self::load_source(dir, source)? {
+                return Ok(Some(config));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Load the configuration from `source` in `dir`, if it has any
+    fn load_source(dir: &Path, source: ConfigSource) -> Result<Option<Self>> {
+        let Some(content) = source.read(dir)? else {
+            return Ok(None);
+        };
+
+        let mut config = Self::parse_toml(&content)
+            .with_context(|| format!("Failed to parse hookmaster configuration in {source}"))?
+            .with_includes(&source.path(dir), &mut HashSet::new())
+            .with_context(|| format!("Failed to load the files included by {source}"))?;
+        if let Some(extends) = &config.extends {
+            let base = remote::load_base(dir, extends, &CommandFetcher, SystemTime::now())
+                .with_context(|| format!("Failed to load {extends}, extended by {source}"))?;
+            config = config.merged_over(base);
+        }
+        config.source = Some(source);
+        Ok(Some(config))
+    }
+
+    /// This configuration with `base` filling in what it leaves out: hooks
+    /// and variables it doesn't define, and settings it leaves at their
+    /// defaults. A hook defined in both is this configuration's alone.
+    pub fn merged_over(self, base: GitHooksConfig) -> Self {
+        let mut hooks = base.hooks;
+        hooks.extend(self.hooks);
+        let mut vars = base.vars;
+        vars.extend(self.vars);
+        Self {
+            hooks,
+            settings: self.settings.merged_over(base.settings),
+            sandbox: if self.sandbox == SandboxSettings::default() {
+                base.sandbox
+            } else {
+                self.sandbox
+            },
+            vars,
+            run: ProjectRunSettings {
+                extra_paths: self.run.extra_paths.or(base.run.extra_paths),
+                record_timings: self.run.record_timings.or(base.run.record_timings),
+                timings_max_kb: self.run.timings_max_kb.or(base.run.timings_max_kb),
+            },
+            commit_msg: CommitMsgSettings {
+                wrap_body: self.commit_msg.wrap_body.or(base.commit_msg.wrap_body),
+                smart_commit: self
+                    .commit_msg
+                    .smart_commit
+                    .or(base.commit_msg.smart_commit),
+                prompt_time: self.commit_msg.prompt_time || base.commit_msg.prompt_time,
+                fallback_template: self
+                    .commit_msg
+                    .fallback_template
+                    .or(base.commit_msg.fallback_template),
+                include_branch_description: self.commit_msg.include_branch_description
+                    || base.commit_msg.include_branch_description,
+                forbid_duplicate_subject_within: self
+                    .commit_msg
+                    .forbid_duplicate_subject_within
+                    .or(base.commit_msg.forbid_duplicate_subject_within),
+                duplicate_subject_severity: if self.commit_msg.duplicate_subject_severity
+                    == Severity::default()
+                {
+                    base.commit_msg.duplicate_subject_severity
+                } else {
+                    self.commit_msg.duplicate_subject_severity
+                },
+                branch_prefixes: if self.commit_msg.branch_prefixes.is_empty() {
+                    base.commit_msg.branch_prefixes
+                } else {
+                    self.commit_msg.branch_prefixes
+                },
+            },
+            protect: if self.protect == ProtectSettings::default() {
+                base.protect
+            } else {
+                self.protect
+            },
+            source: self.source,
+            extends: self.extends,
+            includes: self.includes,
+        }
+    }
+
+    /// Save configuration to githooks.toml file
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let content = self.to_toml_string();
+
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write config file: {}", path.as_ref().display()))?;
+
+        Ok(())
+    }
+
+    /// Enhanced TOML parser for key = "value" pairs and sections with better error handling
+    pub(crate) fn parse_toml(content: &str) -> Result<Self> {
+        let mut hooks: HashMap<String, HookSpec> = HashMap::new();
+        let mut settings = Settings::default();
+        let mut sandbox = SandboxSettings::default();
+        let mut vars = BTreeMap::new();
+        let mut run = ProjectRunSettings::default();
+        let mut commit_msg = CommitMsgSettings::default();
+        let mut protect = ProtectSettings::default();
+        let mut extends = None;
+        let mut includes = Vec::new();
+        let mut section = Section::Root;
+
+        for (line_num, line) in content.lines().enumerate() {
+            let line = line.trim();
+
+            // Skip empty lines and comments
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Section headers; top-level keys are hooks
+            if line.starts_with('[') && line.ends_with(']') {
+                let header = line[1..line.len() - 1].trim();
+                let parts: Vec<&str> = header.split('.').map(str::trim).collect();
+
+                section = match parts.as_slice() {
+                    ["settings"] => Section::Settings,
+                    ["sandbox"] => Section::Sandbox,
+                    ["vars"] => Section::Vars,
+                    ["run"] => Section::Run,
+                    ["commit-msg"] => Section::CommitMsg,
+                    ["commit-msg", "branch_prefixes"] => Section::BranchPrefixes,
+                    ["protect"] => Section::Protect,
+                    ["hooks", hook] if !hook.is_empty() => {
+                        hooks.entry(hook.to_string()).or_default();
+                        Section::Hook(hook.to_string())
+                    }
+                    ["hooks", hook, command] if !hook.is_empty() && !command.is_empty() => {
+                        let spec = hooks.entry(hook.to_string()).or_default();
+                        if spec.commands.iter().any(|entry| entry.name == *command) {
+                            return Err(anyhow!(
+                                "Command '{}' is defined more than once in hook '{}' on line {}.",
+                                command,
+                                hook,
+                                line_num + 1
+                            ));
+                        }
+                        spec.commands.push(HookEntry {
+                            name: command.to_string(),
+                            ..Default::default()
+                        });
+                        Section::HookCommand(hook.to_string(), spec.commands.len() - 1)
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "Unknown section '[{}]' on line {}.",
+                            header,
+                            line_num + 1
+                        ));
+                    }
+                };
+                continue;
+            }
+
+            // Parse key = "value" or key = 'value'
+            if let Some(eq_pos) = line.find('=') {
+                let key = line[..eq_pos].trim();
+                // Quoted keys, such as the patterns of branch prefixes, may have spaces
+                let (key, quoted) = match unquote_key(key) {
+                    Some(key) => (key, true),
+                    None => (key, false),
+                };
+
+                // Validate key (no spaces, valid identifier)
+                if key.is_empty() || (!quoted && key.contains(' ')) {
+                    return Err(anyhow!(
+                        "Invalid key '{}' on line {}. Keys cannot be empty or contain spaces.",
+                        key,
+                        line_num + 1
+                    ));
+                }
+
+                let value = ValueParser::parse(&line[eq_pos + 1..], line_num + 1)?;
+
+                match &section {
+                    Section::Root if key == "extends" => {
+                        extends = Some(value.into_string(key, line_num + 1)?);
+                    }
+                    Section::Root if key == "includes" => {
+                        includes = value.into_string_array(key, line_num + 1)?;
+                    }
+                    Section::Root => {
+                        if hooks.contains_key(key) {
+                            return Err(anyhow!(
+                                "Hook '{}' is defined more than once on line {}.",
+                                key,
+                                line_num + 1
+                            ));
+                        }
+                        let spec = match value {
+                            Value::Array(_) => {
+                                HookSpec::from_commands(value.into_string_array(key, line_num + 1)?)
+                            }
+                            Value::Table(fields) => HookSpec {
+                                commands: vec![HookEntry::from_table(
+                                    key,
+                                    key,
+                                    fields,
+                                    line_num + 1,
+                                )?],
+                                ..Default::default()
+                            },
+                            // Bare words such as `true` are commands like any other
+                            Value::Boolean(_) | Value::Integer(_) => {
+                                HookSpec::from_command(key, line[eq_pos + 1..].trim())
+                            }
+                            value => {
+                                HookSpec::from_command(key, &value.into_string(key, line_num + 1)?)
+                            }
+                        };
+                        hooks.insert(key.to_string(), spec);
+                    }
+                    Section::Settings => settings.apply(key, value, line_num + 1)?,
+                    Section::Sandbox => sandbox.apply(key, value, line_num + 1)?,
+                    Section::Run => run.apply(key, value, line_num + 1)?,
+                    Section::CommitMsg => commit_msg.apply(key, value, line_num + 1)?,
+                    Section::BranchPrefixes => {
+                        commit_msg.add_branch_prefix(key, value, line_num + 1)?
+                    }
+                    Section::Protect => protect.apply(key, value, line_num + 1)?,
+                    Section::Vars => {
+                        let value = value.into_string(key, line_num + 1)?;
+                        if let Some(name) = placeholder::names(&value)
+                            .into_iter()
+                            .find(|name| name.starts_with("vars."))
+                        {
+                            return Err(anyhow!(
+                                "Variable '{}' on line {} refers to '{{{}}}'. Variables cannot refer to other variables.",
+                                key,
+                                line_num + 1,
+                                name
+                            ));
+                        }
+                        if vars.insert(key.to_string(), value).is_some() {
+                            return Err(anyhow!(
+                                "Variable '{}' is defined more than once on line {}.",
+                                key,
+                                line_num + 1
+                            ));
+                        }
+                    }
+                    Section::Hook(hook) => {
+                        let spec = hooks.entry(hook.clone()).or_default();
+                        match (key, value) {
+                            ("parallel", value) => {
+                                spec.parallel = value.into_bool(key, line_num + 1)?
+                            }
+                            ("env-inherit", value) => {
+                                spec.env_inherit = Some(value.into_bool(key, line_num + 1)?)
+                            }
+                            ("env-allow", value) => {
+                                spec.env_allow = Some(value.into_string_array(key, line_num + 1)?)
+                            }
+                            ("deny-network", value) => {
+                                spec.deny_network = value.into_bool(key, line_num + 1)?
+                            }
+                            ("only-remotes", value) => {
+                                spec.only_remotes = value.into_string_array(key, line_num + 1)?
+                            }
+                            ("skip-remotes", value) => {
+                                spec.skip_remotes = value.into_string_array(key, line_num + 1)?
+                            }
+                            ("max-memory-mb", value) => {
+                                let megabytes = value.into_integer(key, line_num + 1)?;
+                                let in_bytes =
+                                    u64::try_from(megabytes).ok().and_then(cgroup::memory_max);
+                                if megabytes <= 0 || in_bytes.is_none() {
+                                    return Err(anyhow!(
+                                        "Invalid max-memory-mb {} on line {}. Expected a positive number of megabytes.",
+                                        megabytes,
+                                        line_num + 1
+                                    ));
+                                }
+                                spec.limits.max_memory_mb = Some(megabytes as u64);
+                            }
+                            ("max-cpu-percent", value) => {
+                                spec.limits.max_cpu_percent =
+                                    Some(parse_percent(key, value, line_num + 1)?);
+                            }
+                            // `name = { command = ... }` defines a named command inline
+                            (_, Value::Table(fields)) => {
+                                if spec.commands.iter().any(|entry| entry.name == key) {
+                                    return Err(anyhow!(
+                                        "Command '{}' is defined more than once in hook '{}' on line {}.",
+                                        key,
+                                        hook,
+                                        line_num + 1
+                                    ));
+                                }
+                                spec.commands.push(HookEntry::from_table(
+                                    hook,
+                                    key,
+                                    fields,
+                                    line_num + 1,
+                                )?);
+                            }
+                            _ => {
+                                return Err(anyhow!(
+                                    "Unknown key '{}' for hook '{}' on line {}.",
+                                    key,
+                                    hook,
+                                    line_num + 1
+                                ));
+                            }
+                        }
+                    }
+                    Section::HookCommand(hook, index) => {
+                        let entry = &mut hooks.entry(hook.clone()).or_default().commands[*index];
+                        entry.apply(hook, key, value, line_num + 1)?;
+                    }
+                }
+            } else if !line.is_empty() {
+                return Err(anyhow!(
+                    "Invalid TOML syntax on line {}: '{}'. Expected 'key = value' format.",
+                    line_num + 1,
+                    line
+                ));
+            }
+        }
+
+        Ok(GitHooksConfig {
+            hooks,
+            settings,
+            sandbox,
+            vars,
+            run,
+            commit_msg,
+            protect,
+            source: None,
+            extends,
+            includes,
+        })
+    }
+
+    /// Convert to TOML string with proper escaping
+    pub(crate) fn to_toml_string(&self) -> String {
+        let mut lines = Vec::new();
+
+        if let Some(extends) = &self.extends {
+            lines.push(format!("extends = {}", quote(extends)));
+        }
+        if !self.includes.is_empty() {
+            let includes: Vec<String> = self.includes.iter().map(|file| quote(file)).collect();
+            lines.push(format!("includes = [{}]", includes.join(", ")));
+        }
+
+        // Sort keys for consistent output
+        let mut sorted_hooks: Vec<_> = self.hooks.iter().collect();
+        sorted_hooks.sort_by_key(|(k, _)| *k);
+
+        // Hooks that fit on a single line must come before any section
+        let mut tables = Vec::new();
+        for (key, spec) in &sorted_hooks {
+            let inline = spec
+                .single_entry(key)
+                .map(|entry| format!("{key} = {}", entry.to_inline_table()))
+                .filter(|line| line.len() < INLINE_TABLE_MAX_WIDTH);
+
+            if spec.is_simple(key) {
+                lines.push(format!("{key} = {}", quote(&spec.commands[0].command)));
+            } else if spec.is_list() {
+                let commands: Vec<String> =
+                    spec.commands.iter().map(|e| quote(&e.command)).collect();
+                lines.push(format!("{key} = [{}]", commands.join(", ")));
+            } else if let Some(line) = inline {
+                lines.push(line);
+            } else {
+                tables.push((key, spec));
+            }
+        }
+
+        if self.settings != Settings::default() {
+            lines.push(String::new());
+            lines.push("[settings]".to_string());
+        }
+        if self.settings.branch_separator != BranchSeparator::default() {
+            let separator = match self.settings.branch_separator {
+                BranchSeparator::Char(c) => c.to_string(),
+                BranchSeparator::Auto => "auto".to_string(),
+            };
+            lines.push(format!("branch-separator = {}", quote(&separator)));
+        }
+        if let Some(shell) = &self.settings.shell {
+            lines.push(format!("shell = {}", quote(shell)));
+        }
+        if !self.settings.shell_args.is_empty() {
+            let args: Vec<String> = self.settings.shell_args.iter().map(|a| quote(a)).collect();
+            lines.push(format!("shell-args = [{}]", args.join(", ")));
+        }
+        if let Some(name) = &self.settings.ticket_env_var {
+            lines.push(format!("ticket-env-var = {}", quote(name)));
+        }
+        if self.settings.script_format != ScriptFormat::default() {
+            let format = self.settings.script_format.as_str();
+            lines.push(format!("script-format = {}", quote(format)));
+        }
+        if self.settings.multi_ticket {
+            lines.push("multi-ticket = true".to_string());
+        }
+        if self.settings.conventional_commits {
+            lines.push("conventional-commits = true".to_string());
+        }
+        if let Some(command) = &self.settings.pre_hook_command {
+            lines.push(format!("pre-hook-command = {}", quote(command)));
+        }
+        if self.settings.pre_hook_fail_aborts {
+            lines.push("pre-hook-fail-aborts = true".to_string());
+        }
+        if let Some(command) = &self.settings.post_hook_command {
+            lines.push(format!("post-hook-command = {}", quote(command)));
+        }
+        if self.settings.post_hook_fail_aborts {
+            lines.push("post-hook-fail-aborts = true".to_string());
+        }
+        if self.settings.pre_commit_stash_unstaged {
+            lines.push("pre-commit-stash-unstaged = true".to_string());
+        }
+        if let Some(severity) = self.settings.pre_commit_partially_staged {
+            lines.push(format!(
+                "pre-commit-partially-staged = \"{}\"",
+                severity.as_str()
+            ));
+        }
+        if let Some(path) = &self.settings.audit_log {
+            lines.push(format!("audit-log = {}", quote(path)));
+        }
+        if let Some(seconds) = self.settings.timeout_kill_grace {
+            lines.push(format!("timeout-kill-grace = {seconds}"));
+        }
+        if let Some(append) = self.settings.append_args {
+            lines.push(format!("append-args = {append}"));
+        }
+
+        if self.sandbox != SandboxSettings::default() {
+            lines.push(String::new());
+            lines.push("[sandbox]".to_string());
+            lines.push(format!("enabled = {}", self.sandbox.enabled));
+            if self.sandbox.allow_network {
+                lines.push("sandbox-allow-network = true".to_string());
+            }
+        }
+
+        if !self.vars.is_empty() {
+            lines.push(String::new());
+            lines.push("[vars]".to_string());
+            for (name, value) in &self.vars {
+                lines.push(format!("{name} = {}", quote(value)));
+            }
+        }
+
+        if self.run != ProjectRunSettings::default() {
+            lines.push(String::new());
+            lines.push("[run]".to_string());
+            if let Some(extra_paths) = &self.run.extra_paths {
+                let dirs: Vec<String> = extra_paths.iter().map(|dir| quote(dir)).collect();
+                lines.push(format!("extra_paths = [{}]", dirs.join(", ")));
+            }
+            if let Some(record_timings) = self.run.record_timings {
+                lines.push(format!("record_timings = {record_timings}"));
+            }
+            if let Some(timings_max_kb) = self.run.timings_max_kb {
+                lines.push(format!("timings_max_kb = {timings_max_kb}"));
+            }
+        }
+
+        let commit_msg = CommitMsgSettings {
+            branch_prefixes: Vec::new(),
+            ..self.commit_msg.clone()
+        };
+        if commit_msg != CommitMsgSettings::default() {
+            lines.push(String::new());
+            lines.push("[commit-msg]".to_string());
+        }
+        if let Some(wrap_body) = self.commit_msg.wrap_body {
+            lines.push(format!("wrap_body = {wrap_body}"));
+        }
+        if let Some(template) = &self.commit_msg.smart_commit {
+            lines.push(format!("smart_commit = {}", quote(template)));
+        }
+        if self.commit_msg.prompt_time {
+            lines.push("prompt_time = true".to_string());
+        }
+        if let Some(template) = &self.commit_msg.fallback_template {
+            lines.push(format!("fallback_template = {}", quote(template)));
+        }
+        if self.commit_msg.include_branch_description {
+            lines.push("include_branch_description = true".to_string());
+        }
+        if let Some(count) = self.commit_msg.forbid_duplicate_subject_within {
+            lines.push(format!("forbid_duplicate_subject_within = {count}"));
+        }
+        if self.commit_msg.duplicate_subject_severity != Severity::default() {
+            let severity = self.commit_msg.duplicate_subject_severity.as_str();
+            lines.push(format!("duplicate_subject_severity = \"{severity}\""));
+        }
+        if !self.commit_msg.branch_prefixes.is_empty() {
+            lines.push(String::new());
+            lines.push("[commit-msg.branch_prefixes]".to_string());
+            for entry in &self.commit_msg.branch_prefixes {
+                lines.push(format!(
+                    "{} = {}",
+                    quote(&entry.pattern),
+                    quote(&entry.prefix)
+                ));
+            }
+        }
+
+        if self.protect != ProtectSettings::default() {
+            let list = |values: &[String]| {
+                let values: Vec<String> = values.iter().map(|v| quote(v)).collect();
+                format!("[{}]", values.join(", "))
+            };
+            lines.push(String::new());
+            lines.push("[protect]".to_string());
+            if let Some(branches) = &self.protect.branches {
+                lines.push(format!("branches = {}", list(branches)));
+            }
+            if self.protect.forbid_unfinished_commits {
+                lines.push("forbid_unfinished_commits = true".to_string());
+            }
+            if !self.protect.forbidden_subject_patterns.is_empty() {
+                let patterns = list(&self.protect.forbidden_subject_patterns);
+                lines.push(format!("forbidden_subject_patterns = {patterns}"));
+            }
+            if self.protect.require_ticket_in_pushed_commits {
+                lines.push("require_ticket_in_pushed_commits = true".to_string());
+            }
+        }
+
+        for (key, spec) in tables {
+            lines.push(String::new());
+            lines.extend(spec.table_lines(key));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// SHA-256 of the configuration written out in canonical form: hooks
+    /// and variables sorted by name and every setting in a fixed place, so
+    /// configurations that differ only in the order or layout of their
+    /// keys hash the same
+    pub fn content_hash(&self) -> [u8; 32] {
+        Sha256::digest(self.to_toml_string().as_bytes()).into()
+    }
+
+    /// The hash of the configuration in `format`, in lowercase hex
+    pub fn content_hash_hex(&self, format: HashFormat) -> String {
+        match format {
+            HashFormat::Sha256 => format!("{:x}", Sha256::digest(self.to_toml_string())),
+            HashFormat::Md5 => format!("{:x}", Md5::digest(self.to_toml_string())),
+        }
+    }
+
+    /// The environment the commands of `hook_name` get
+    pub fn get_hook_env_policy(&self, hook_name: &str) -> EnvPolicy {
+        self.hooks
+            .get(hook_name)
+            .map(HookSpec::env_policy)
+            .unwrap_or_default()
+    }
+
+    /// Resource limits for the commands of `hook_name`
+    pub fn get_hook_limits(&self, hook_name: &str) -> CgroupConfig {
+        self.hooks
+            .get(hook_name)
+            .map(|spec| spec.limits.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get command for a hook that runs a single command
+    pub fn get_hook_command(&self, hook_name: &str) -> Option<&str> {
+        match self.hooks.get(hook_name)?.commands.as_slice() {
+            [entry] => Some(entry.command.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Create a sample configuration. On `ci`, only the hooks that check
+    /// the code are in it, as [`CI_HOOKS`](crate::ci::CI_HOOKS) says.
+    pub fn create_sample(ci: Option<CiEnvironment>) -> Self {
+        let mut hooks = HashMap::new();
+        hooks.insert(
+            "pre-commit".to_string(),
+            HookSpec::from_command(
+                "pre-commit",
+                "cargo fmt --check && cargo clippy -- -D warnings",
+            ),
+        );
+        hooks.insert(
+            "pre-push".to_string(),
+            HookSpec::from_command("pre-push", "cargo test"),
+        );
+        // Empty string does nothing
+        if ci.is_none() {
+            hooks.insert(
+                "commit-msg".to_string(),
+                HookSpec::from_command("commit-msg", ""),
+            );
+        }
+
+        Self {
+            hooks,
+            ..Default::default()
+        }
+    }
+
+    /// Check if a hook is defined and has at least one non-empty command
+    pub fn has_active_hook(&self, hook_name: &str) -> bool {
+        self.hooks
+            .get(hook_name)
+            .map(|spec| {
+                spec.commands
+                    .iter()
+                    .any(|entry| !entry.command.trim().is_empty())
+            })
+            .unwrap_or(false)
+    }
+
+    /// Report `{vars.<name>}` placeholders in a hook's commands that name no variable
+    fn undefined_vars(&self, hook_name: &str, spec: &HookSpec) -> Vec<String> {
+        let mut problems = Vec::new();
+        for entry in &spec.commands {
+            for name in placeholder::names(&entry.command) {
+                let Some(var) = name.strip_prefix("vars.") else {
+                    continue;
+                };
+                if self.vars.contains_key(var) {
+                    continue;
+                }
+                if entry.name == hook_name {
+                    problems.push(format!(
+                        "Hook '{hook_name}' uses undefined variable '{var}'"
+                    ));
+                } else {
+                    problems.push(format!(
+                        "Hook '{hook_name}': command '{}' uses undefined variable '{var}'",
+                        entry.name
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Check the configuration for problems that parsing alone doesn't catch,
+    /// such as unknown command dependencies, dependency cycles, bad `@hook`
+    /// references and undefined variables
+    pub fn validate(&self) -> Vec<String> {
+        let mut sorted_hooks: Vec<_> = self.hooks.iter().collect();
+        sorted_hooks.sort_by_key(|(k, _)| *k);
+
+        let mut problems = Vec::new();
+        for (hook_name, spec) in sorted_hooks {
+            problems.extend(spec.validate(hook_name));
+            problems.extend(self.undefined_vars(hook_name, spec));
+            if let Err(e) = resolve::expand_references(self, hook_name) {
+                problems.push(e.to_string());
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml() {
+        let content = r#"
+# Comment
+pre-commit = "cargo fmt --check"
+pre-push = "cargo test"
+commit-msg = ""
+"#;
+
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("cargo fmt --check")
+        );
+        assert_eq!(config.get_hook_command("pre-push"), Some("cargo test"));
+        assert_eq!(config.get_hook_command("commit-msg"), Some(""));
+    }
+
+    #[test]
+    fn test_parse_escaped_quotes() {
+        let content = r#"test-hook = "echo \"Hello World\"""#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.get_hook_command("test-hook"),
+            Some("echo \"Hello World\"")
+        );
+    }
+
+    #[test]
+    fn test_parse_single_quotes() {
+        let content = r#"test-hook = 'echo "Hello World"'"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.get_hook_command("test-hook"),
+            Some("echo \"Hello World\"")
+        );
+    }
+
+    #[test]
+    fn test_parse_bare_words() {
+        let content = "pre-commit = true\npre-push = false\ncommit-msg = 007\npost-merge = make";
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.get_hook_command("pre-commit"), Some("true"));
+        assert_eq!(config.get_hook_command("pre-push"), Some("false"));
+        assert_eq!(config.get_hook_command("commit-msg"), Some("007"));
+        assert_eq!(config.get_hook_command("post-merge"), Some("make"));
+    }
+
+    #[test]
+    fn test_parse_invalid_key() {
+        let content = "invalid key = \"value\"";
+        let result = GitHooksConfig::parse_toml(content);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Invalid key"));
+    }
+
+    #[test]
+    fn test_to_toml_string() {
+        let mut hooks = HashMap::new();
+        hooks.insert(
+            "pre-commit".to_string(),
+            HookSpec::from_command("pre-commit", "test command"),
+        );
+        hooks.insert(
+            "pre-push".to_string(),
+            HookSpec::from_command("pre-push", "test2"),
+        );
+
+        let config = GitHooksConfig {
+            hooks,
+            ..Default::default()
+        };
+        let toml_str = config.to_toml_string();
+
+        assert!(toml_str.contains("pre-commit = \"test command\""));
+        assert!(toml_str.contains("pre-push = \"test2\""));
+        assert!(!toml_str.contains("[settings]"));
+    }
+
+    #[test]
+    fn test_merged_over_base() {
+        let base = GitHooksConfig::parse_toml(
+            "pre-commit = \"make lint\"\npre-push = \"make test\"\n\n[settings]\nbranch-separator = \"_\"\nticket-env-var = \"TICKET\"\n\n[vars]\nlevel = \"1\"\ntarget = \"all\"\n",
+        )
+        .unwrap();
+        let local = GitHooksConfig::parse_toml(
+            "extends = \"https://example.com/base.toml\"\npre-push = \"cargo test\"\n\n[settings]\nticket-env-var = \"ISSUE\"\n\n[vars]\nlevel = \"2\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            local.extends.as_deref(),
+            Some("https://example.com/base.toml")
+        );
+        assert!(!local.hooks.contains_key("extends"));
+        assert!(local
+            .to_toml_string()
+            .starts_with("extends = \"https://example.com/base.toml\"\n"));
+
+        let merged = local.merged_over(base);
+        assert_eq!(merged.get_hook_command("pre-commit"), Some("make lint"));
+        assert_eq!(merged.get_hook_command("pre-push"), Some("cargo test"));
+        assert_eq!(merged.settings.branch_separator, BranchSeparator::Char('_'));
+        assert_eq!(merged.settings.ticket_env_var.as_deref(), Some("ISSUE"));
+        assert_eq!(merged.vars["level"], "2");
+        assert_eq!(merged.vars["target"], "all");
+    }
+
+    #[test]
+    fn test_includes_override_in_order() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(
+            dir.join("githooks.toml"),
+            "includes = [\"hooks-*.toml\"]\npre-commit = \"make lint\"\npre-push = \"make test\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("hooks-1.toml"),
+            "pre-commit = \"cargo clippy\"\n\n[settings]\nticket-env-var = \"ISSUE\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("hooks-2.toml"),
+            "includes = [\"team/extra.toml\"]\npre-commit = \"cargo fmt --check\"\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.join("team")).unwrap();
+        fs::write(dir.join("team/extra.toml"), "commit-msg = \"check-msg\"\n").unwrap();
+
+        assert_eq!(
+            expand_globs(dir, &["hooks-*.toml".to_string()]).unwrap(),
+            vec![dir.join("hooks-1.toml"), dir.join("hooks-2.toml")]
+        );
+
+        let config = GitHooksConfig::load_from_file(dir.join("githooks.toml")).unwrap();
+        // The last file wins; what it leaves out comes from those before it
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("cargo fmt --check")
+        );
+        assert_eq!(config.get_hook_command("pre-push"), Some("make test"));
+        assert_eq!(config.get_hook_command("commit-msg"), Some("check-msg"));
+        assert_eq!(config.settings.ticket_env_var.as_deref(), Some("ISSUE"));
+        assert_eq!(config.includes, vec!["hooks-*.toml"]);
+
+        let config = GitHooksConfig::discover(dir, None).unwrap().unwrap();
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("cargo fmt --check")
+        );
+        assert_eq!(config.source, Some(ConfigSource::GitHooksToml));
+    }
+
+    #[test]
+    fn test_includes_errors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("a.toml"), "includes = [\"b.toml\"]\n").unwrap();
+        fs::write(dir.join("b.toml"), "includes = [\"a.toml\"]\n").unwrap();
+        let err = GitHooksConfig::load_from_file(dir.join("a.toml")).unwrap_err();
+        assert!(format!("{err:#}").contains("Circular include"), "{err:#}");
+
+        // A glob may match nothing, a path must exist
+        fs::write(dir.join("c.toml"), "includes = [\"extra-*.toml\"]\n").unwrap();
+        GitHooksConfig::load_from_file(dir.join("c.toml")).unwrap();
+        fs::write(dir.join("c.toml"), "includes = [\"extra.toml\"]\n").unwrap();
+        let err = GitHooksConfig::load_from_file(dir.join("c.toml")).unwrap_err();
+        assert!(err.to_string().contains("Included file not found"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_settings_section() {
+        let content = r#"
+pre-commit = "cargo fmt --check"
+
+[settings]
+branch-separator = "_"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.settings.branch_separator, BranchSeparator::Char('_'));
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("cargo fmt --check")
+        );
+        assert!(!config.hooks.contains_key("branch-separator"));
+
+        let config = GitHooksConfig::parse_toml("[settings]\nbranch-separator = \"auto\"").unwrap();
+        assert_eq!(config.settings.branch_separator, BranchSeparator::Auto);
+
+        let config = GitHooksConfig::parse_toml("pre-commit = \"x\"").unwrap();
+        assert_eq!(config.settings.branch_separator, BranchSeparator::Char('-'));
+        assert!(config.settings.shell_args.is_empty());
+        assert_eq!(config.settings.ticket_env_var, None);
+
+        let config = GitHooksConfig::parse_toml(
+            "[settings]\nticket-env-var = \"TICKET\"\nscript-format = \"fish\"\nmulti-ticket = true\nconventional-commits = true\naudit-log = \"~/audit.log\"\ntimeout-kill-grace = 0\npre-commit-partially-staged = \"error\"\nappend-args = false",
+        )
+        .unwrap();
+        assert_eq!(config.settings.script_format, ScriptFormat::Fish);
+        assert!(config.settings.multi_ticket);
+        assert!(config.settings.conventional_commits);
+        assert_eq!(config.settings.ticket_env_var.as_deref(), Some("TICKET"));
+        assert!(!config.settings.pre_commit_stash_unstaged);
+        assert_eq!(
+            config.settings.pre_commit_partially_staged,
+            Some(Severity::Error)
+        );
+        assert_eq!(config.settings.audit_log.as_deref(), Some("~/audit.log"));
+        assert_eq!(config.settings.timeout_kill_grace(), 0);
+        assert!(!config.settings.append_args());
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.settings, config.settings);
+        assert_eq!(
+            Settings::default().timeout_kill_grace(),
+            DEFAULT_TIMEOUT_KILL_GRACE
+        );
+        assert!(Settings::default().append_args());
+        assert!(GitHooksConfig::parse_toml("[settings]\ntimeout-kill-grace = -1").is_err());
+        assert!(
+            GitHooksConfig::parse_toml("[settings]\npre-commit-partially-staged = \"fail\"")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_hook_command_settings() {
+        let content = r#"
+[settings]
+pre-hook-command = "echo starting $HOOKMASTER_HOOK_NAME"
+post-hook-command = "notify $HOOKMASTER_HOOK_NAME $HOOKMASTER_EXIT_CODE"
+post-hook-fail-aborts = true
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.settings.pre_hook_command.as_deref(),
+            Some("echo starting $HOOKMASTER_HOOK_NAME")
+        );
+        assert_eq!(
+            config.settings.post_hook_command.as_deref(),
+            Some("notify $HOOKMASTER_HOOK_NAME $HOOKMASTER_EXIT_CODE")
+        );
+        assert!(!config.settings.pre_hook_fail_aborts);
+        assert!(config.settings.post_hook_fail_aborts);
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.settings, config.settings);
+    }
+
+    #[test]
+    fn test_parse_shell_settings() {
+        let content = r#"
+[settings]
+shell = "bash"
+shell-args = ["--noprofile", "--norc"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.settings.shell.as_deref(), Some("bash"));
+        assert_eq!(config.settings.shell_args, vec!["--noprofile", "--norc"]);
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.settings, config.settings);
+
+        let result = GitHooksConfig::parse_toml("[settings]\nshell-args = \"--norc\"");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("'shell-args' on line 2"));
+    }
+
+    #[test]
+    fn test_parse_invalid_settings() {
+        let result = GitHooksConfig::parse_toml("[settings]\nbranch-separator = \"/\"");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid branch-separator"));
+
+        let result = GitHooksConfig::parse_toml("[settings]\nscript-format = \"bash\"");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid script-format 'bash' on line 2"));
+
+        let result = GitHooksConfig::parse_toml("[settings]\nunknown = \"x\"");
+        assert!(result.unwrap_err().to_string().contains("Unknown setting"));
+
+        let result = GitHooksConfig::parse_toml("[other]\nkey = \"x\"");
+        assert!(result.unwrap_err().to_string().contains("Unknown section"));
+    }
+
+    #[test]
+    fn test_create_sample_for_ci() {
+        let config = GitHooksConfig::create_sample(None);
+        assert!(config.hooks.contains_key("commit-msg"));
+
+        let config = GitHooksConfig::create_sample(Some(CiEnvironment::GitHub));
+        let mut hooks: Vec<&str> = config.hooks.keys().map(String::as_str).collect();
+        hooks.sort();
+        assert_eq!(hooks, crate::ci::CI_HOOKS);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let mut config = GitHooksConfig::create_sample(None);
+        config.settings.branch_separator = BranchSeparator::Char('.');
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.settings, config.settings);
+        assert_eq!(parsed.hooks, config.hooks);
+    }
 
-/// Configuration for git hooks
-#[derive(Debug, Default)]
-pub struct GitHooksConfig {
-    /// Map of hook names to commands
-    pub hooks: HashMap<String, String>,
-}
+    #[test]
+    fn test_parse_trailing_comment() {
+        let content = r#"commit-msg = ""  # empty string does nothing"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.get_hook_command("commit-msg"), Some(""));
+        assert!(!config.has_active_hook("commit-msg"));
 
-impl GitHooksConfig {
-    /// Load configuration from a TOML file
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read config file: {}", path.as_ref().display()))?;
+        let result = GitHooksConfig::parse_toml(r#"pre-commit = "a" "b""#);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unexpected characters"));
+    }
 
-        let config = Self::parse_toml(&content).with_context(|| "Failed to parse githooks.toml")?;
+    #[test]
+    fn test_parse_named_commands() {
+        let content = r#"
+pre-push = "cargo test"
 
-        Ok(config)
+[hooks.pre-commit]
+parallel = true
+
+[hooks.pre-commit.generate-code]
+command = "npm run generate"
+
+[hooks.pre-commit.typecheck]
+command = "tsc --noEmit"
+depends_on = ["generate-code"]
+
+[hooks.pre-commit.lint]
+command = 'eslint .'
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let spec = &config.hooks["pre-commit"];
+
+        assert!(spec.parallel);
+        let names: Vec<&str> = spec.commands.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["generate-code", "typecheck", "lint"]);
+        assert_eq!(spec.commands[1].command, "tsc --noEmit");
+        assert_eq!(
+            spec.commands[1].depends_on,
+            vec!["generate-code".to_string()]
+        );
+        assert_eq!(spec.commands[2].command, "eslint .");
+        assert!(config.has_active_hook("pre-commit"));
+        assert_eq!(config.get_hook_command("pre-commit"), None);
+        assert_eq!(config.get_hook_command("pre-push"), Some("cargo test"));
+        assert!(config.validate().is_empty());
     }
 
-    /// Load configuration from current directory
-    pub fn load() -> Result<Self> {
-        let config_path = Path::new("githooks.toml");
+    #[test]
+    fn test_parse_before_and_after() {
+        let content = r#"
+[hooks.pre-commit.lint]
+command = "eslint ."
+after = "format"
 
-        if config_path.exists() {
-            Self::load_from_file(config_path)
-        } else {
-            Ok(Self::default())
-        }
+[hooks.pre-commit.format]
+command = "prettier --write ."
+before = ["lint", "typecheck"]
+
+[hooks.pre-commit.typecheck]
+command = "tsc --noEmit"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let spec = &config.hooks["pre-commit"];
+        assert_eq!(spec.commands[0].after, vec!["format"]);
+        assert_eq!(spec.commands[1].before, vec!["lint", "typecheck"]);
+        assert!(config.validate().is_empty());
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.hooks, config.hooks);
+
+        let unknown = "[hooks.pre-commit.fmt]\ncommand = \"true\"\nbefore = \"lint\"\n";
+        let config = GitHooksConfig::parse_toml(unknown).unwrap();
+        assert_eq!(
+            config.validate(),
+            vec!["Hook 'pre-commit': command 'fmt' runs before unknown command 'lint'"]
+        );
+
+        let wrong_type = "[hooks.pre-commit.fmt]\ncommand = \"true\"\nafter = 1\n";
+        let result = GitHooksConfig::parse_toml(wrong_type);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Expected a string or an array of strings"));
     }
 
-    /// Save configuration to githooks.toml file
-    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let content = self.to_toml_string();
+    #[test]
+    fn test_parse_named_command_errors() {
+        let duplicate = "[hooks.pre-commit.lint]\ncommand = \"a\"\n[hooks.pre-commit.lint]";
+        let result = GitHooksConfig::parse_toml(duplicate);
+        assert!(result.unwrap_err().to_string().contains("more than once"));
 
-        fs::write(&path, content)
-            .with_context(|| format!("Failed to write config file: {}", path.as_ref().display()))?;
+        let wrong_type = "[hooks.pre-commit.lint]\ndepends_on = \"fmt\"";
+        let result = GitHooksConfig::parse_toml(wrong_type);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Expected an array of strings"));
 
-        Ok(())
+        let unknown_key = "[hooks.pre-commit]\ncommand = \"a\"";
+        let result = GitHooksConfig::parse_toml(unknown_key);
+        assert!(result.unwrap_err().to_string().contains("Unknown key"));
+
+        let unterminated = "[hooks.pre-commit.lint]\ndepends_on = [\"fmt\"";
+        assert!(GitHooksConfig::parse_toml(unterminated).is_err());
     }
 
-    /// Enhanced TOML parser for key = "value" pairs with better error handling
-    fn parse_toml(content: &str) -> Result<Self> {
-        let mut hooks = HashMap::new();
+    #[test]
+    fn test_validate_reports_cycles_and_unknown_dependencies() {
+        let content = r#"
+[hooks.pre-commit.a]
+command = "echo a"
+depends_on = ["b"]
 
-        for (line_num, line) in content.lines().enumerate() {
-            let line = line.trim();
+[hooks.pre-commit.b]
+command = "echo b"
+depends_on = ["a"]
 
-            // Skip empty lines and comments
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
+[hooks.pre-push.test]
+command = "cargo test"
+depends_on = ["build"]
 
-            // Parse key = "value" or key = 'value'
-            if let Some(eq_pos) = line.find('=') {
-                let key = line[..eq_pos].trim();
-                let value_part = line[eq_pos + 1..].trim();
+[hooks.pre-push.empty]
 
-                // Validate key (no spaces, valid identifier)
-                if key.is_empty() || key.contains(' ') {
-                    return Err(anyhow!(
-                        "Invalid key '{}' on line {}. Keys cannot be empty or contain spaces.",
-                        key,
-                        line_num + 1
-                    ));
-                }
+[hooks.pre-push.lint]
+command = "npm run lint"
+only_changed = true
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let problems = config.validate();
 
-                // Parse value with proper quote handling
-                let value = if value_part.starts_with('"')
-                    && value_part.ends_with('"')
-                    && value_part.len() >= 2
-                {
-                    // Handle escaped quotes in double-quoted strings
-                    let inner = &value_part[1..value_part.len() - 1];
-                    inner.replace(r#"\""#, "\"").replace(r"\\", "\\")
-                } else if value_part.starts_with('\'')
-                    && value_part.ends_with('\'')
-                    && value_part.len() >= 2
-                {
-                    // Single-quoted strings (literal)
-                    value_part[1..value_part.len() - 1].to_string()
-                } else if value_part.is_empty() {
-                    // Empty value (no quotes)
-                    String::new()
-                } else {
-                    // Unquoted value
-                    value_part.to_string()
-                };
+        assert_eq!(
+            problems,
+            vec![
+                "Hook 'pre-commit': dependency cycle: a -> b -> a".to_string(),
+                "Hook 'pre-push': command 'empty' has no 'command' to run".to_string(),
+                "Hook 'pre-push': command 'lint' sets 'only_changed' without 'foreach_dir'"
+                    .to_string(),
+                "Hook 'pre-push': command 'test' depends on unknown command 'build'".to_string(),
+            ]
+        );
+    }
 
-                hooks.insert(key.to_string(), value);
-            } else if !line.is_empty() {
-                return Err(anyhow!(
-                    "Invalid TOML syntax on line {}: '{}'. Expected 'key = value' format.",
-                    line_num + 1,
-                    line
-                ));
-            }
+    #[test]
+    fn test_named_commands_round_trip() {
+        let content = r#"
+pre-push = "cargo test"
+
+[hooks.pre-commit]
+parallel = true
+
+[hooks.pre-commit.fmt]
+command = "cargo fmt --check"
+
+[hooks.pre-commit.clippy]
+command = "cargo clippy"
+depends_on = ["fmt"]
+
+[hooks.pre-commit.test]
+command = "npm test"
+foreach_dir = "packages/*/"
+only_changed = true
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let test = &config.hooks["pre-commit"].commands[2];
+        assert_eq!(test.foreach_dir.as_deref(), Some("packages/*/"));
+        assert!(test.only_changed);
+
+        let toml_str = config.to_toml_string();
+
+        assert!(toml_str.starts_with("pre-push = \"cargo test\"\n"));
+        let parsed = GitHooksConfig::parse_toml(&toml_str).unwrap();
+        assert_eq!(parsed.hooks, config.hooks);
+    }
+
+    #[test]
+    fn test_parse_command_list() {
+        let content = r#"pre-push = ["@pre-commit", "cargo test", "cargo test"]"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+
+        let names: Vec<&str> = config.hooks["pre-push"]
+            .commands
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["@pre-commit", "cargo test", "cargo test #2"]);
+        assert_eq!(
+            config.hooks["pre-push"].commands[0].reference(),
+            Some("pre-commit")
+        );
+        assert_eq!(config.get_hook_command("pre-push"), None);
+
+        let toml_str = config.to_toml_string();
+        assert_eq!(toml_str, format!("{content}\n"));
+        let parsed = GitHooksConfig::parse_toml(&toml_str).unwrap();
+        assert_eq!(parsed.hooks, config.hooks);
+    }
+
+    #[test]
+    fn test_parse_user_config() {
+        assert_eq!(UserConfig::parse("").unwrap(), UserConfig::default());
+        let config = UserConfig::parse("# Mandatory hooks\n[run]\nstrict = true\n").unwrap();
+        assert!(config.run.strict);
+        let config = UserConfig::parse("[add]\nonly-configured = true\n[run]\n").unwrap();
+        assert!(config.add.only_configured);
+        assert!(!config.run.strict);
+        assert_eq!(config.add.confirm_threshold, None);
+        let config = UserConfig::parse("[add]\nconfirm-threshold = 100\n").unwrap();
+        assert_eq!(config.add.confirm_threshold, Some(100));
+
+        for (content, error) in [
+            ("[run]\nstrict = \"yes\"", "strict"),
+            (
+                "[run]\nverbose = true",
+                "Unknown run setting 'verbose' on line 2.",
+            ),
+            ("strict = true", "must be in a section such as [run]"),
+            (
+                "[add]\nstrict = true",
+                "Unknown add setting 'strict' on line 2.",
+            ),
+            ("[hooks]\n", "Unknown section '[hooks]' on line 1."),
+            (
+                "[add]\nconfirm-threshold = -1",
+                "Invalid confirm-threshold -1 on line 2.",
+            ),
+        ] {
+            let err = UserConfig::parse(content).unwrap_err().to_string();
+            assert!(err.contains(error), "{content}: {err}");
         }
+    }
+
+    #[test]
+    fn test_parse_sandbox_section() {
+        let content = r#"
+pre-commit = "cargo test"
+
+[sandbox]
+enabled = true
+sandbox-allow-network = true
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert!(config.sandbox.enabled);
+        assert!(config.sandbox.allow_network);
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.sandbox, config.sandbox);
 
-        Ok(GitHooksConfig { hooks })
+        let result = GitHooksConfig::parse_toml("[sandbox]\nallow-writes = true");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown sandbox setting 'allow-writes'"));
+        let result = GitHooksConfig::parse_toml("[sandbox]\nenabled = \"yes\"");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Expected a boolean"));
     }
 
-    /// Convert to TOML string with proper escaping
-    fn to_toml_string(&self) -> String {
-        let mut lines = Vec::new();
+    #[test]
+    fn test_parse_inline_tables() {
+        let content = r#"
+pre-commit = { command = "cargo fmt --check", timeout = 30 }  # formatting only
+commit-msg = { "command" = 'echo "{ not, a = table }"' }
 
-        // Sort keys for consistent output
-        let mut sorted_hooks: Vec<_> = self.hooks.iter().collect();
-        sorted_hooks.sort_by_key(|(k, _)| *k);
+[hooks.pre-push]
+test = { command = "cargo test", depends_on = ["build"], timeout = 600 }
+build = {command="cargo build"}
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+
+        let pre_commit = &config.hooks["pre-commit"].commands[0];
+        assert_eq!(pre_commit.name, "pre-commit");
+        assert_eq!(pre_commit.command, "cargo fmt --check");
+        assert_eq!(pre_commit.timeout, Some(30));
+        assert_eq!(
+            config.get_hook_command("commit-msg"),
+            Some("echo \"{ not, a = table }\"")
+        );
+
+        let pre_push = &config.hooks["pre-push"];
+        assert_eq!(pre_push.commands[0].name, "test");
+        assert_eq!(pre_push.commands[0].depends_on, vec!["build"]);
+        assert_eq!(pre_push.commands[0].timeout, Some(600));
+        assert_eq!(pre_push.commands[1].command, "cargo build");
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn test_parse_nested_inline_tables() {
+        let value = ValueParser::parse(r#"{ a = { b = { c = 1 } }, d = "}" }"#, 1).unwrap();
+        let Value::Table(fields) = value else {
+            panic!("expected a table");
+        };
+        assert_eq!(fields["d"], Value::String("}".to_string()));
+        let Value::Table(a) = &fields["a"] else {
+            panic!("expected a nested table");
+        };
+        assert!(matches!(&a["b"], Value::Table(b) if b["c"] == Value::Integer(1)));
 
-        for (key, value) in sorted_hooks {
-            // Escape quotes and backslashes in values
-            let escaped_value = value.replace('\\', r"\\").replace('"', r#"\""#);
-            lines.push(format!("{key} = \"{escaped_value}\""));
+        // Nested tables aren't valid command settings
+        let result =
+            GitHooksConfig::parse_toml(r#"pre-commit = { command = "x", env = { A = 1 } }"#);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown key 'env'"));
+    }
+
+    #[test]
+    fn test_parse_inline_table_errors() {
+        for (content, message) in [
+            (r#"pre-commit = { timeout = 30 }"#, "Missing 'command'"),
+            (
+                r#"pre-commit = { command = "a", command = "b" }"#,
+                "Duplicate key",
+            ),
+            (r#"pre-commit = { command = "a" "#, "Expected ',' or '}'"),
+            (r#"pre-commit = { command "a" }"#, "Expected '='"),
+            (
+                r#"pre-commit = { command = "a", timeout = 0 }"#,
+                "Invalid timeout 0",
+            ),
+            (
+                r#"pre-commit = { command = "a", timeout = "5" }"#,
+                "Expected an integer",
+            ),
+        ] {
+            let err = GitHooksConfig::parse_toml(content).unwrap_err();
+            assert!(
+                err.to_string().contains(message),
+                "{content}: unexpected error {err}"
+            );
         }
+    }
 
-        lines.join("\n") + "\n"
+    #[test]
+    fn test_env_settings_round_trip() {
+        let content = r#"[hooks.pre-push]
+env-inherit = false
+env-allow = ["PATH", "HOME"]
+test = { command = "cargo test" }
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.get_hook_env_policy("pre-push"),
+            EnvPolicy::Allow(vec!["PATH".to_string(), "HOME".to_string()])
+        );
+        assert_eq!(config.get_hook_env_policy("pre-commit"), EnvPolicy::Inherit);
+        assert_eq!(config.to_toml_string().trim_start(), content);
+
+        let policy = EnvPolicy::Allow(vec!["PATH".to_string(), "GIT_*".to_string()]);
+        assert!(policy.allows("PATH"));
+        assert!(policy.allows("GIT_DIR"));
+        assert!(!policy.allows("GIT"));
+        assert!(!policy.allows("NODE_OPTIONS"));
+        assert!(EnvPolicy::Inherit.allows("NODE_OPTIONS"));
+
+        let config = GitHooksConfig::parse_toml(
+            "[hooks.pre-push]\nenv-inherit = true\nenv-allow = [\"PATH\"]\ntest = { command = \"cargo test\" }",
+        )
+        .unwrap();
+        assert_eq!(config.get_hook_env_policy("pre-push"), EnvPolicy::Inherit);
+        assert_eq!(
+            config.validate(),
+            vec!["Hook 'pre-push': 'env-allow' has no effect with 'env-inherit = true'"]
+        );
     }
 
-    /// Get command for a specific hook
-    pub fn get_hook_command(&self, hook_name: &str) -> Option<&str> {
-        self.hooks.get(hook_name).map(|s| s.as_str())
+    #[test]
+    fn test_remotes_round_trip() {
+        let content = r#"[hooks.pre-push]
+skip-remotes = ["gh-backup"]
+lint = { command = "cargo clippy" }
+test = { command = "cargo test", only_remotes = ["origin", "*gitlab*"] }
+audit = { command = "cargo audit", skip_remotes = ["mirror"] }
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let spec = &config.hooks["pre-push"];
+        assert_eq!(spec.skip_remotes, ["gh-backup"]);
+        assert!(spec.only_remotes.is_empty());
+        assert_eq!(spec.commands[1].only_remotes, ["origin", "*gitlab*"]);
+        assert_eq!(spec.commands[2].skip_remotes, ["mirror"]);
+        assert_eq!(config.to_toml_string().trim_start(), content);
+
+        let err = GitHooksConfig::parse_toml(
+            "[hooks.pre-push]
+only-remotes = \"origin\"",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Expected an array"), "{err}");
     }
 
-    /// Create a sample configuration
-    pub fn create_sample() -> Self {
-        let mut hooks = HashMap::new();
-        hooks.insert(
+    #[test]
+    fn test_restrictions_round_trip() {
+        let content = r#"[hooks.pre-push]
+deny-network = true
+max-memory-mb = 512
+max-cpu-percent = 12.5
+test = { command = "cargo test" }
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.get_hook_limits("pre-push"),
+            CgroupConfig {
+                max_memory_mb: Some(512),
+                max_cpu_percent: Some(12.5),
+            }
+        );
+        assert!(config.get_hook_limits("pre-commit").is_empty());
+        assert!(config.hooks["pre-push"].deny_network);
+        assert_eq!(config.to_toml_string().trim_start(), content);
+
+        for (content, expected) in [
+            ("max-memory-mb = 0", "Invalid max-memory-mb 0"),
+            (
+                "max-memory-mb = 9000000000000000000",
+                "Invalid max-memory-mb 9000000000000000000",
+            ),
+            ("max-memory-mb = \"512\"", "Expected an integer"),
+            ("max-cpu-percent = 0", "Invalid max-cpu-percent 0"),
+            ("max-cpu-percent = half", "Invalid max-cpu-percent 'half'"),
+            ("max-cpu-percent = true", "Expected a number"),
+        ] {
+            let err = GitHooksConfig::parse_toml(&format!("[hooks.pre-push]\n{content}"))
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains(expected), "{content}: unexpected error {err}");
+        }
+    }
+
+    #[test]
+    fn test_inline_tables_round_trip() {
+        let long_command = "cargo clippy --workspace --all-targets --all-features -- -D warnings";
+        let mut config = GitHooksConfig::default();
+        config.hooks.insert(
             "pre-commit".to_string(),
-            "cargo fmt --check && cargo clippy -- -D warnings".to_string(),
+            HookSpec {
+                commands: vec![HookEntry {
+                    name: "pre-commit".to_string(),
+                    command: "cargo fmt --check".to_string(),
+                    timeout: Some(30),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
         );
-        hooks.insert("pre-push".to_string(), "cargo test".to_string());
-        hooks.insert("commit-msg".to_string(), "".to_string()); // Empty string does nothing
+        config.hooks.insert(
+            "pre-push".to_string(),
+            HookSpec {
+                commands: vec![
+                    HookEntry {
+                        name: "test".to_string(),
+                        command: "cargo test".to_string(),
+                        ..Default::default()
+                    },
+                    HookEntry {
+                        name: "clippy".to_string(),
+                        command: long_command.to_string(),
+                        timeout: Some(300),
+                        install_hint: Some("rustup component add clippy".to_string()),
+                        ..Default::default()
+                    },
+                ],
+                parallel: true,
+                ..Default::default()
+            },
+        );
+
+        let toml_str = config.to_toml_string();
+        assert_eq!(
+            toml_str,
+            format!(
+                r#"pre-commit = {{ command = "cargo fmt --check", timeout = 30 }}
 
-        Self { hooks }
+[hooks.pre-push]
+parallel = true
+test = {{ command = "cargo test" }}
+
+[hooks.pre-push.clippy]
+command = "{long_command}"
+timeout = 300
+install_hint = "rustup component add clippy"
+"#
+            )
+        );
+        let parsed = GitHooksConfig::parse_toml(&toml_str).unwrap();
+        assert_eq!(parsed.hooks, config.hooks);
     }
 
-    /// Check if a hook is defined and not empty
-    pub fn has_active_hook(&self, hook_name: &str) -> bool {
-        self.hooks
-            .get(hook_name)
-            .map(|cmd| !cmd.trim().is_empty())
-            .unwrap_or(false)
+    #[test]
+    fn test_parse_vars() {
+        let content = r#"
+pre-commit = "cargo clippy {vars.flags} -- -D warnings"
+pre-push = "cargo test {vars.flags} {vars.locked}"
+
+[vars]
+flags = "--workspace --all-targets"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.vars["flags"], "--workspace --all-targets");
+        assert_eq!(
+            config.validate(),
+            vec!["Hook 'pre-push' uses undefined variable 'locked'".to_string()]
+        );
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.vars, config.vars);
+        assert_eq!(parsed.hooks, config.hooks);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_parse_vars_rejects_nested_references() {
+        let content = r#"
+[vars]
+flags = "--workspace"
+all = "{vars.flags} --locked"
+"#;
+        let err = GitHooksConfig::parse_toml(content).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Variable 'all' on line 4 refers to '{vars.flags}'"));
+
+        // Escaped references are literal text, not nesting
+        let content = "[vars]\nliteral = \"{{vars.flags}}\"";
+        assert!(GitHooksConfig::parse_toml(content).is_ok());
+    }
 
     #[test]
-    fn test_parse_toml() {
+    fn test_parse_run_section() {
         let content = r#"
-# Comment
-pre-commit = "cargo fmt --check"
-pre-push = "cargo test"
-commit-msg = ""
+pre-commit = "eslint ."
+
+[run]
+extra_paths = ["node_modules/.bin", "tools/bin"]
+record_timings = false
+timings_max_kb = 256
 "#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.run.extra_paths,
+            Some(vec![
+                "node_modules/.bin".to_string(),
+                "tools/bin".to_string()
+            ])
+        );
+        assert_eq!(config.run.record_timings, Some(false));
+        assert_eq!(config.run.timings_max_kb, Some(256));
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.run, config.run);
+
+        assert_eq!(
+            GitHooksConfig::parse_toml("pre-commit = \"true\"")
+                .unwrap()
+                .run
+                .extra_paths,
+            None
+        );
+        let err = GitHooksConfig::parse_toml("[run]\nstrict = true").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown run setting 'strict' on line 2.");
+        assert!(GitHooksConfig::parse_toml("[run]\ntimings_max_kb = 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_commit_msg_section() {
+        let content = "commit-msg = \"commitlint --edit\"\n\n[commit-msg]\nwrap_body = 72\n";
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.commit_msg.wrap_body, Some(72));
+        assert_eq!(
+            config.get_hook_command("commit-msg"),
+            Some("commitlint --edit")
+        );
+        assert_eq!(config.to_toml_string(), content);
+
+        let err = GitHooksConfig::parse_toml("[commit-msg]\nwrap = 72").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown commit-msg setting 'wrap' on line 2."
+        );
+        assert!(GitHooksConfig::parse_toml("[commit-msg]\nwrap_body = 0").is_err());
 
+        let content =
+            "[commit-msg]\nsmart_commit = \"#comment {description}\"\nprompt_time = true\n";
         let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(
+            config.commit_msg.smart_commit.as_deref(),
+            Some("#comment {description}")
+        );
+        assert!(config.commit_msg.prompt_time);
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.commit_msg, config.commit_msg);
+
+        let config =
+            GitHooksConfig::parse_toml("[commit-msg]\ninclude_branch_description = true\n")
+                .unwrap();
+        assert!(config.commit_msg.include_branch_description);
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.commit_msg, config.commit_msg);
 
+        let content = "[commit-msg]\nforbid_duplicate_subject_within = 20\nduplicate_subject_severity = \"error\"\n";
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        assert_eq!(config.commit_msg.forbid_duplicate_subject_within, Some(20));
+        assert_eq!(
+            config.commit_msg.duplicate_subject_severity,
+            Severity::Error
+        );
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.commit_msg, config.commit_msg);
+        assert!(
+            GitHooksConfig::parse_toml("[commit-msg]\nforbid_duplicate_subject_within = 0")
+                .is_err()
+        );
+        let err = GitHooksConfig::parse_toml("[commit-msg]\nduplicate_subject_severity = \"fail\"")
+            .unwrap_err();
         assert_eq!(
-            config.hooks.get("pre-commit"),
-            Some(&"cargo fmt --check".to_string())
+            err.to_string(),
+            "Invalid duplicate_subject_severity 'fail' on line 2. Expected \"warn\" or \"error\"."
         );
+
+        let content = "[commit-msg]\nfallback_template = \"type: \\n\\n# - [ ] tests\"\n";
+        let config = GitHooksConfig::parse_toml(content).unwrap();
         assert_eq!(
-            config.hooks.get("pre-push"),
-            Some(&"cargo test".to_string())
+            config.commit_msg.fallback_template.as_deref(),
+            Some("type: \n\n# - [ ] tests")
         );
-        assert_eq!(config.hooks.get("commit-msg"), Some(&"".to_string()));
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.commit_msg, config.commit_msg);
     }
 
     #[test]
-    fn test_parse_escaped_quotes() {
-        let content = r#"test-hook = "echo \"Hello World\"""#;
+    fn test_parse_branch_prefixes() {
+        let content = "[commit-msg.branch_prefixes]\n\"release/*\" = \"Release {1}: \"\n'hotfix/*' = \"Hotfix {1}: \"\n";
         let config = GitHooksConfig::parse_toml(content).unwrap();
         assert_eq!(
-            config.hooks.get("test-hook"),
-            Some(&"echo \"Hello World\"".to_string())
+            config.commit_msg.branch_prefixes,
+            vec![
+                BranchPrefix {
+                    pattern: "release/*".to_string(),
+                    prefix: "Release {1}: ".to_string(),
+                },
+                BranchPrefix {
+                    pattern: "hotfix/*".to_string(),
+                    prefix: "Hotfix {1}: ".to_string(),
+                },
+            ]
+        );
+        // Only the branch prefixes, without an empty `[commit-msg]`
+        let toml = config.to_toml_string();
+        assert!(!toml.contains("[commit-msg]\n"), "{toml}");
+        let parsed = GitHooksConfig::parse_toml(&toml).unwrap();
+        assert_eq!(parsed.commit_msg, config.commit_msg);
+
+        let err = GitHooksConfig::parse_toml(
+            "[commit-msg.branch_prefixes]\n\"release/*\" = \"a\"\n\"release/*\" = \"b\"\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Branch prefix 'release/*' is defined more than once on line 3."
         );
     }
 
     #[test]
-    fn test_parse_single_quotes() {
-        let content = r#"test-hook = 'echo "Hello World"'"#;
+    fn test_parse_protect_section() {
+        let content = r#"
+[protect]
+branches = ["main", "release/*"]
+forbid_unfinished_commits = true
+forbidden_subject_patterns = ["^tmp\\b", "(?i)do not merge"]
+require_ticket_in_pushed_commits = true
+"#;
         let config = GitHooksConfig::parse_toml(content).unwrap();
         assert_eq!(
-            config.hooks.get("test-hook"),
-            Some(&"echo \"Hello World\"".to_string())
+            config.protect.branches,
+            Some(vec!["main".to_string(), "release/*".to_string()])
+        );
+        assert!(config.protect.forbid_unfinished_commits);
+        assert!(config.protect.require_ticket_in_pushed_commits);
+        assert_eq!(
+            config.protect.forbidden_subject_patterns,
+            vec!["^tmp\\b".to_string(), "(?i)do not merge".to_string()]
         );
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.protect, config.protect);
+
+        let err = GitHooksConfig::parse_toml("[protect]\nforbidden_subject_patterns = [\"(\"]")
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid pattern '(' in forbidden_subject_patterns on line 2"));
     }
 
     #[test]
-    fn test_parse_invalid_key() {
-        let content = "invalid key = \"value\"";
-        let result = GitHooksConfig::parse_toml(content);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Invalid key"));
+    fn test_parse_docker_runner() {
+        let content = r#"
+[hooks.pre-push.licenses]
+command = "licensecheck --deny GPL"
+runner = "docker"
+image = "ghcr.io/acme/licensecheck:1"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let entry = &config.hooks["pre-push"].commands[0];
+        assert_eq!(entry.runner, Runner::Docker);
+        assert_eq!(entry.image.as_deref(), Some("ghcr.io/acme/licensecheck:1"));
+        assert!(config.validate().is_empty());
+
+        let parsed = GitHooksConfig::parse_toml(&config.to_toml_string()).unwrap();
+        assert_eq!(parsed.hooks, config.hooks);
+
+        let config = GitHooksConfig::parse_toml(
+            "a = { command = \"x\", runner = \"docker\" }\nb = { command = \"y\", image = \"z\" }",
+        )
+        .unwrap();
+        assert_eq!(
+            config.validate(),
+            vec![
+                "Hook 'a': command 'a' uses runner 'docker' without an 'image'".to_string(),
+                "Hook 'b': command 'b' sets 'image' without runner = \"docker\"".to_string(),
+            ]
+        );
+
+        let err =
+            GitHooksConfig::parse_toml("a = { command = \"x\", runner = \"podman\" }").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid runner 'podman' on line 1."));
     }
 
     #[test]
-    fn test_to_toml_string() {
-        let mut hooks = HashMap::new();
-        hooks.insert("pre-commit".to_string(), "test command".to_string());
-        hooks.insert("pre-push".to_string(), "test2".to_string());
+    fn test_content_hash() {
+        let config = GitHooksConfig::parse_toml(
+            r#"pre-commit = "cargo fmt --check"
+pre-push = "cargo test"
 
-        let config = GitHooksConfig { hooks };
-        let toml_str = config.to_toml_string();
+[settings]
+multi-ticket = true
+shell = "bash"
 
-        assert!(toml_str.contains("pre-commit = \"test command\""));
-        assert!(toml_str.contains("pre-push = \"test2\""));
+[vars]
+target = "x86_64"
+profile = "release"
+"#,
+        )
+        .unwrap();
+        let reordered = GitHooksConfig::parse_toml(
+            r#"pre-push = "cargo test"
+pre-commit   =   "cargo fmt --check"
+
+[vars]
+profile = "release"
+target = "x86_64"
+
+[settings]
+shell = "bash"
+multi-ticket = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.content_hash(), reordered.content_hash());
+        assert_eq!(
+            config.content_hash_hex(HashFormat::Sha256),
+            config
+                .content_hash()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>()
+        );
+        assert_eq!(config.content_hash_hex(HashFormat::Md5).len(), 32);
+        assert_eq!(
+            config.content_hash_hex(HashFormat::Md5),
+            reordered.content_hash_hex(HashFormat::Md5)
+        );
+
+        let changed = GitHooksConfig::parse_toml("pre-commit = \"cargo fmt\"").unwrap();
+        assert_ne!(config.content_hash(), changed.content_hash());
+        // The order of a hook's commands is the order they run in
+        let first = GitHooksConfig::parse_toml("pre-commit = [\"a\", \"b\"]").unwrap();
+        let second = GitHooksConfig::parse_toml("pre-commit = [\"b\", \"a\"]").unwrap();
+        assert_ne!(first.content_hash(), second.content_hash());
     }
 }
@@ -0,0 +1,544 @@
+//! Locating the configuration among the files of a repository
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value as JsonValue;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::quote;
+
+/// Environment variable naming the configuration file to use, like `--config`
+pub const CONFIG_ENV_VAR: &str = "HOOKMASTER_CONFIG";
+
+/// File in a repository naming a shared configuration file, written by
+/// `hookmaster add --link-config` where symbolic links can't be created
+pub const CONFIG_LINK_FILE: &str = ".hookmaster-config-link";
+
+/// File that hook configuration can be read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    /// `hookmaster.toml`
+    HookmasterToml,
+    /// `githooks.toml`, the name used before `hookmaster.toml`
+    GitHooksToml,
+    /// `[package.metadata.hookmaster]` in Cargo.toml
+    CargoToml,
+    /// `"hookmaster"` key in package.json
+    PackageJson,
+    /// `[tool.hookmaster]` in pyproject.toml
+    PyprojectToml,
+    /// A file given with `--config` or `HOOKMASTER_CONFIG`, in hookmaster.toml format
+    File(PathBuf),
+}
+
+impl ConfigSource {
+    /// Sources in order of precedence; the first one with configuration wins
+    pub const PRECEDENCE: [ConfigSource; 5] = [
+        ConfigSource::HookmasterToml,
+        ConfigSource::GitHooksToml,
+        ConfigSource::CargoToml,
+        ConfigSource::PackageJson,
+        ConfigSource::PyprojectToml,
+    ];
+
+    /// Name of the file this source reads
+    pub fn file_name(&self) -> &str {
+        match self {
+            ConfigSource::HookmasterToml => "hookmaster.toml",
+            ConfigSource::GitHooksToml => "githooks.toml",
+            ConfigSource::CargoToml => "Cargo.toml",
+            ConfigSource::PackageJson => "package.json",
+            ConfigSource::PyprojectToml => "pyproject.toml",
+            ConfigSource::File(path) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Path of the file this source reads in `dir`
+    pub fn path(&self, dir: &Path) -> PathBuf {
+        match self {
+            ConfigSource::File(path) => dir.join(path),
+            _ => dir.join(self.file_name()),
+        }
+    }
+
+    /// Read the configuration from this source in `dir`, in hookmaster.toml
+    /// format. Returns `None` if the file doesn't exist or has no hookmaster
+    /// configuration; a file given explicitly must exist.
+    pub fn read(&self, dir: &Path) -> Result<Option<String>> {
+        let path = self.path(dir);
+        if !path.is_file() {
+            return match self {
+                ConfigSource::File(_) => Err(anyhow!("Config file not found: {}", path.display())),
+                _ => Ok(None),
+            };
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+        match self {
+            ConfigSource::HookmasterToml | ConfigSource::GitHooksToml | ConfigSource::File(_) => {
+                Ok(Some(content))
+            }
+            ConfigSource::CargoToml => {
+                Ok(extract_toml_table(&content, "package.metadata.hookmaster"))
+            }
+            ConfigSource::PackageJson => package_json_config(&content)
+                .with_context(|| format!("Failed to read \"hookmaster\" from {}", path.display())),
+            ConfigSource::PyprojectToml => Ok(extract_toml_table(&content, "tool.hookmaster")),
+        }
+    }
+
+    /// The shared configuration file named by [`CONFIG_LINK_FILE`] in `dir`,
+    /// if there is one. A relative path is relative to `dir`.
+    pub fn linked(dir: &Path) -> Result<Option<Self>> {
+        let link = dir.join(CONFIG_LINK_FILE);
+        if !link.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&link)
+            .with_context(|| format!("Failed to read config link: {}", link.display()))?;
+        let target = content.trim();
+        if target.is_empty() {
+            return Err(anyhow!("Config link is empty: {}", link.display()));
+        }
+        Ok(Some(ConfigSource::File(PathBuf::from(target))))
+    }
+
+    /// The explicitly chosen configuration file: `--config` if given,
+    /// otherwise a non-empty `HOOKMASTER_CONFIG`
+    pub fn explicit(flag: Option<&Path>, env: Option<OsString>) -> Option<Self> {
+        flag.map(Path::to_path_buf)
+            .or_else(|| env.filter(|value| !value.is_empty()).map(PathBuf::from))
+            .map(ConfigSource::File)
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::HookmasterToml => write!(f, "hookmaster.toml"),
+            ConfigSource::GitHooksToml => write!(f, "githooks.toml"),
+            ConfigSource::CargoToml => write!(f, "Cargo.toml [package.metadata.hookmaster]"),
+            ConfigSource::PackageJson => write!(f, "package.json \"hookmaster\""),
+            ConfigSource::PyprojectToml => write!(f, "pyproject.toml [tool.hookmaster]"),
+            ConfigSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Extract a table and its sub-tables from a TOML document, with the table's
+/// name stripped from the headers: `[tool.hookmaster.settings]` becomes
+/// `[settings]`. Returns `None` if the document has no such table.
+pub fn extract_toml_table(content: &str, table: &str) -> Option<String> {
+    let prefix: Vec<&str> = table.split('.').collect();
+    let mut found = false;
+    let mut in_table = false;
+    let mut lines = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("[[") {
+            // Array of tables, never part of hookmaster's configuration
+            in_table = false;
+            continue;
+        }
+
+        let header = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .filter(|(_, after)| after.trim().is_empty() || after.trim().starts_with('#'));
+        let Some((header, _)) = header else {
+            if in_table {
+                lines.push(line.to_string());
+            }
+            continue;
+        };
+
+        let parts: Vec<&str> = header
+            .split('.')
+            .map(|part| part.trim().trim_matches('"'))
+            .collect();
+        in_table = parts.starts_with(&prefix);
+        if in_table {
+            found = true;
+            if parts.len() > prefix.len() {
+                lines.push(String::new());
+                lines.push(format!("[{}]", parts[prefix.len()..].join(".")));
+            }
+        }
+    }
+
+    found.then(|| lines.join("\n") + "\n")
+}
+
+/// Convert the `"hookmaster"` object of a package.json into githooks.toml format
+fn package_json_config(content: &str) -> Result<Option<String>> {
+    let json: JsonValue = serde_json::from_str(content).with_context(|| "Invalid JSON")?;
+    let Some(config) = json.get("hookmaster") else {
+        return Ok(None);
+    };
+    let JsonValue::Object(config) = config else {
+        return Err(anyhow!("\"hookmaster\" must be an object"));
+    };
+
+    let mut root = Vec::new();
+    let mut sections = Vec::new();
+
+    for (key, value) in config {
+        match (key.as_str(), value) {
+            ("settings" | "sandbox" | "vars", JsonValue::Object(table)) => {
+                sections.push(String::new());
+                sections.push(format!("[{key}]"));
+                for (name, value) in table {
+                    sections.push(format!("{name} = {}", toml_value(value)?));
+                }
+            }
+            ("hooks", JsonValue::Object(hooks)) => {
+                for (hook, spec) in hooks {
+                    let JsonValue::Object(spec) = spec else {
+                        return Err(anyhow!("\"hooks.{hook}\" must be an object"));
+                    };
+                    sections.push(String::new());
+                    sections.push(format!("[hooks.{hook}]"));
+                    for (name, value) in spec {
+                        sections.push(format!("{name} = {}", toml_value(value)?));
+                    }
+                }
+            }
+            _ => root.push(format!("{key} = {}", toml_value(value)?)),
+        }
+    }
+
+    root.extend(sections);
+    Ok(Some(root.join("\n") + "\n"))
+}
+
+/// Write a JSON value as a TOML value, with objects as inline tables
+fn toml_value(value: &JsonValue) -> Result<String> {
+    Ok(match value {
+        JsonValue::String(s) => quote(s),
+        JsonValue::Bool(b) => b.to_string(),
+        JsonValue::Number(n) => n
+            .as_i64()
+            .ok_or_else(|| anyhow!("Unsupported number {n}; only integers are allowed"))?
+            .to_string(),
+        JsonValue::Array(items) => {
+            let items: Result<Vec<String>> = items.iter().map(toml_value).collect();
+            format!("[{}]", items?.join(", "))
+        }
+        JsonValue::Object(fields) => {
+            let fields: Result<Vec<String>> = fields
+                .iter()
+                .map(|(key, value)| Ok(format!("{key} = {}", toml_value(value)?)))
+                .collect();
+            format!("{{ {} }}", fields?.join(", "))
+        }
+        JsonValue::Null => return Err(anyhow!("null values are not supported")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commit_msg::BranchSeparator;
+    use crate::config::GitHooksConfig;
+    use tempfile::TempDir;
+
+    const CARGO_TOML: &str = r#"
+[package]
+name = "example"
+version = "0.1.0"
+
+[package.metadata.hookmaster]
+pre-commit = "cargo fmt --check"
+pre-push = ["@pre-commit", "cargo test"]
+
+[package.metadata.hookmaster.settings]
+branch-separator = "_"
+
+[package.metadata.hookmaster.hooks.ci]
+parallel = true
+lint = { command = "cargo clippy" }
+
+[dependencies]
+anyhow = "1.0"
+
+[[bin]]
+name = "example"
+"#;
+
+    const PACKAGE_JSON: &str = r#"{
+  "name": "example",
+  "scripts": { "lint": "eslint ." },
+  "hookmaster": {
+    "pre-commit": { "command": "js:lint", "timeout": 30 },
+    "pre-push": ["@pre-commit", "npm test"],
+    "settings": { "branch-separator": "_" },
+    "hooks": {
+      "ci": { "parallel": true, "lint": { "command": "npm run lint" } }
+    }
+  }
+}"#;
+
+    const PYPROJECT_TOML: &str = r#"
+[project]
+name = "example"
+
+[tool.hookmaster]
+pre-commit = "black --check ."
+pre-push = ["@pre-commit", "pytest"]
+
+[tool.hookmaster.settings]
+branch-separator = "_"
+
+[tool.black]
+line-length = 100
+"#;
+
+    fn write(dir: &TempDir, name: &str, content: &str) {
+        fs::write(dir.path().join(name), content).unwrap();
+    }
+
+    fn discover(dir: &TempDir) -> Option<GitHooksConfig> {
+        GitHooksConfig::discover(dir.path(), None).unwrap()
+    }
+
+    #[test]
+    fn test_same_hooks_from_each_source() {
+        let sources = [
+            (
+                "githooks.toml",
+                "pre-commit = \"ruff check .\"\n\n[hooks.pre-push]\ntypes = { command = \"mypy .\", timeout = 60 }\ntests = { command = \"pytest\", depends_on = [\"types\"] }\n",
+            ),
+            (
+                "pyproject.toml",
+                "[project]\nname = \"example\"\n\n[tool.hookmaster]\npre-commit = \"ruff check .\"\n\n[tool.hookmaster.hooks.pre-push]\ntypes = { command = \"mypy .\", timeout = 60 }\ntests = { command = \"pytest\", depends_on = [\"types\"] }\n",
+            ),
+            (
+                "package.json",
+                r#"{
+  "name": "example",
+  "hookmaster": {
+    "pre-commit": "ruff check .",
+    "hooks": {
+      "pre-push": {
+        "types": { "command": "mypy .", "timeout": 60 },
+        "tests": { "command": "pytest", "depends_on": ["types"] }
+      }
+    }
+  }
+}"#,
+            ),
+        ];
+
+        let configs: Vec<GitHooksConfig> = sources
+            .iter()
+            .map(|(name, content)| {
+                let temp_dir = TempDir::new().unwrap();
+                write(&temp_dir, name, content);
+                let config = discover(&temp_dir).unwrap();
+                assert_eq!(config.source.as_ref().unwrap().file_name(), *name);
+                config
+            })
+            .collect();
+        let pre_push = &configs[0].hooks["pre-push"];
+        assert_eq!(pre_push.commands[0].timeout, Some(60));
+        assert_eq!(pre_push.commands[1].depends_on, vec!["types"]);
+        for config in &configs[1..] {
+            assert_eq!(config.hooks, configs[0].hooks);
+        }
+    }
+
+    #[test]
+    fn test_cargo_toml_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "Cargo.toml", CARGO_TOML);
+
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::CargoToml));
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("cargo fmt --check")
+        );
+        assert_eq!(config.hooks["pre-push"].commands.len(), 2);
+        assert_eq!(config.settings.branch_separator, BranchSeparator::Char('_'));
+        assert!(config.hooks["ci"].parallel);
+        assert_eq!(config.hooks["ci"].commands[0].command, "cargo clippy");
+        assert!(!config.hooks.contains_key("name"));
+    }
+
+    #[test]
+    fn test_package_json_key() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "package.json", PACKAGE_JSON);
+
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::PackageJson));
+        let pre_commit = &config.hooks["pre-commit"].commands[0];
+        assert_eq!(pre_commit.command, "js:lint");
+        assert_eq!(pre_commit.timeout, Some(30));
+        assert_eq!(config.hooks["pre-push"].commands[1].command, "npm test");
+        assert_eq!(config.settings.branch_separator, BranchSeparator::Char('_'));
+        assert!(config.hooks["ci"].parallel);
+        assert_eq!(config.hooks["ci"].commands[0].name, "lint");
+    }
+
+    #[test]
+    fn test_pyproject_toml_tool_table() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "pyproject.toml", PYPROJECT_TOML);
+
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::PyprojectToml));
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("black --check .")
+        );
+        assert_eq!(config.hooks.len(), 2);
+        assert_eq!(config.settings.branch_separator, BranchSeparator::Char('_'));
+    }
+
+    #[test]
+    fn test_files_without_hookmaster_section_are_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "Cargo.toml", "[package]\nname = \"example\"\n");
+        write(&temp_dir, "package.json", r#"{ "name": "example" }"#);
+        write(
+            &temp_dir,
+            "pyproject.toml",
+            "[tool.black]\nline-length = 100\n",
+        );
+        assert!(discover(&temp_dir).is_none());
+
+        write(&temp_dir, "pyproject.toml", PYPROJECT_TOML);
+        assert_eq!(
+            discover(&temp_dir).unwrap().source,
+            Some(ConfigSource::PyprojectToml)
+        );
+    }
+
+    #[test]
+    fn test_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "pyproject.toml", PYPROJECT_TOML);
+        write(&temp_dir, "package.json", PACKAGE_JSON);
+        assert_eq!(
+            discover(&temp_dir).unwrap().source,
+            Some(ConfigSource::PackageJson)
+        );
+
+        write(&temp_dir, "Cargo.toml", CARGO_TOML);
+        assert_eq!(
+            discover(&temp_dir).unwrap().source,
+            Some(ConfigSource::CargoToml)
+        );
+
+        write(&temp_dir, "githooks.toml", "pre-commit = \"make check\"\n");
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::GitHooksToml));
+        assert_eq!(config.get_hook_command("pre-commit"), Some("make check"));
+    }
+
+    #[test]
+    fn test_hookmaster_toml_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "githooks.toml", "pre-commit = \"legacy\"\n");
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::GitHooksToml));
+        assert_eq!(config.get_hook_command("pre-commit"), Some("legacy"));
+
+        // Both files: hookmaster.toml wins (with a warning)
+        write(&temp_dir, "hookmaster.toml", "pre-commit = \"current\"\n");
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::HookmasterToml));
+        assert_eq!(config.get_hook_command("pre-commit"), Some("current"));
+
+        fs::remove_file(temp_dir.path().join("githooks.toml")).unwrap();
+        let config = discover(&temp_dir).unwrap();
+        assert_eq!(config.source, Some(ConfigSource::HookmasterToml));
+    }
+
+    #[test]
+    fn test_explicit_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        write(&temp_dir, "hookmaster.toml", "pre-commit = \"current\"\n");
+        write(&temp_dir, "githooks.toml", "pre-commit = \"legacy\"\n");
+        write(&temp_dir, "custom.toml", "pre-commit = \"custom\"\n");
+
+        // An explicit file is used even when others exist, relative to the directory
+        let explicit = ConfigSource::File(PathBuf::from("custom.toml"));
+        let config = GitHooksConfig::discover(temp_dir.path(), Some(explicit.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.source, Some(explicit));
+        assert_eq!(config.get_hook_command("pre-commit"), Some("custom"));
+        assert_eq!(config.source.unwrap().to_string(), "custom.toml");
+
+        // ... and one that doesn't exist is an error rather than a fallback
+        let missing = ConfigSource::File(PathBuf::from("missing.toml"));
+        let err = GitHooksConfig::discover(temp_dir.path(), Some(missing)).unwrap_err();
+        assert!(err.to_string().starts_with("Config file not found:"));
+    }
+
+    #[test]
+    fn test_explicit_source_from_flag_and_env() {
+        let file = |path: &str| Some(ConfigSource::File(PathBuf::from(path)));
+        assert_eq!(ConfigSource::explicit(None, None), None);
+        assert_eq!(ConfigSource::explicit(None, Some("".into())), None);
+        assert_eq!(
+            ConfigSource::explicit(None, Some("env.toml".into())),
+            file("env.toml")
+        );
+        assert_eq!(
+            ConfigSource::explicit(Some(Path::new("flag.toml")), None),
+            file("flag.toml")
+        );
+        // The flag wins over the environment
+        assert_eq!(
+            ConfigSource::explicit(Some(Path::new("flag.toml")), Some("env.toml".into())),
+            file("flag.toml")
+        );
+    }
+
+    #[test]
+    fn test_invalid_package_json() {
+        let temp_dir = TempDir::new().unwrap();
+        write(
+            &temp_dir,
+            "package.json",
+            r#"{ "hookmaster": "cargo test" }"#,
+        );
+        let err = GitHooksConfig::discover(temp_dir.path(), None).unwrap_err();
+        assert!(format!("{err:#}").contains("\"hookmaster\" must be an object"));
+
+        write(
+            &temp_dir,
+            "package.json",
+            r#"{ "hookmaster": { "pre-commit": null } }"#,
+        );
+        assert!(GitHooksConfig::discover(temp_dir.path(), None).is_err());
+    }
+
+    #[test]
+    fn test_extract_toml_table() {
+        assert_eq!(
+            extract_toml_table("[package]\nname = \"x\"\n", "tool.hookmaster"),
+            None
+        );
+        assert_eq!(
+            extract_toml_table(
+                "[tool.hookmaster] # hooks\na = \"1\"\n[tool.\"hookmaster\".vars]\nb = \"2\"\n[tool.other]\nc = \"3\"\n",
+                "tool.hookmaster"
+            )
+            .as_deref(),
+            Some("a = \"1\"\n\n[vars]\nb = \"2\"\n")
+        );
+    }
+}
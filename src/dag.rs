@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Result};
+
+use crate::config::HookEntry;
+use crate::scheduler::{self, Task};
+
+/// Names of the commands each command of a hook waits for
+type Prerequisites = [Vec<String>];
+
+/// Commands of a hook in the order they run
+type Order<'a> = Vec<&'a HookEntry>;
+
+/// Names of the commands each of `entries` has to wait for: those in its
+/// `depends_on` and `after`, and every command that names it in `before`
+pub fn prerequisites(entries: &[HookEntry]) -> Vec<Vec<String>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let mut names: Vec<String> = Vec::new();
+            let declared = entry.depends_on.iter().chain(&entry.after).cloned();
+            let preceding = entries
+                .iter()
+                .filter(|other| other.before.contains(&entry.name))
+                .map(|other| other.name.clone());
+            for name in declared.chain(preceding) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            names
+        })
+        .collect()
+}
+
+/// `entries` as scheduler tasks waiting for their `prerequisites`, as
+/// returned by [`prerequisites`]
+pub fn tasks<'a>(entries: &'a [HookEntry], prerequisites: &'a Prerequisites) -> Vec<Task<'a>> {
+    entries
+        .iter()
+        .zip(prerequisites)
+        .map(|(entry, names)| Task {
+            name: &entry.name,
+            depends_on: names,
+        })
+        .collect()
+}
+
+/// `entries` in the order they run one at a time: in declaration order,
+/// with each command deferred until its prerequisites have run. Fails when a command
+/// names an unknown one or the ordering has a cycle.
+pub fn topological_sort(entries: &[HookEntry]) -> Result<Order<'_>> {
+    if let Some(entry) = entries
+        .iter()
+        .find(|entry| entry.before.iter().any(|name| !is_command(entries, name)))
+    {
+        let unknown = entry.before.iter().find(|name| !is_command(entries, name));
+        return Err(anyhow!(
+            "Command '{}' runs before unknown command '{}'",
+            entry.name,
+            unknown.map(String::as_str).unwrap_or_default()
+        ));
+    }
+
+    let prerequisites = prerequisites(entries);
+    let tasks = tasks(entries, &prerequisites);
+    if let Some(unknown) = scheduler::unknown_dependencies(&tasks).first() {
+        return Err(anyhow!(
+            "Command '{}' runs after unknown command '{}'",
+            unknown.task,
+            unknown.dependency
+        ));
+    }
+    if let Some(cycle) = scheduler::find_cycle(&tasks) {
+        return Err(anyhow!("Dependency cycle: {}", cycle.join(" -> ")));
+    }
+
+    Ok(scheduler::sequential_order(&tasks)
+        .into_iter()
+        .map(|index| &entries[index])
+        .collect())
+}
+
+/// Check if one of `entries` is named `name`
+fn is_command(entries: &[HookEntry], name: &str) -> bool {
+    entries.iter().any(|entry| entry.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, before: &[&str], after: &[&str]) -> HookEntry {
+        HookEntry {
+            name: name.to_string(),
+            command: format!("echo {name}"),
+            before: before.iter().map(|s| s.to_string()).collect(),
+            after: after.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(sorted: Vec<&HookEntry>) -> Vec<&str> {
+        sorted.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_topological_sort_orders_by_before_and_after() {
+        let entries = [
+            entry("lint", &[], &["format"]),
+            entry("test", &[], &[]),
+            entry("format", &[], &[]),
+            entry("generate", &["format"], &[]),
+        ];
+        assert_eq!(
+            names(topological_sort(&entries).unwrap()),
+            vec!["test", "generate", "format", "lint"]
+        );
+        assert_eq!(
+            prerequisites(&entries),
+            vec![
+                vec!["format".to_string()],
+                vec![],
+                vec!["generate".to_string()],
+                vec![]
+            ]
+        );
+
+        // Without ordering, commands keep their declaration order
+        let entries = [entry("b", &[], &[]), entry("a", &[], &[])];
+        assert_eq!(names(topological_sort(&entries).unwrap()), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_topological_sort_detects_cycles() {
+        let entries = [
+            entry("format", &["lint"], &[]),
+            entry("lint", &["format"], &[]),
+        ];
+        let err = topological_sort(&entries).unwrap_err();
+        assert!(err.to_string().starts_with("Dependency cycle: "), "{err}");
+
+        let entries = [
+            entry("a", &[], &["c"]),
+            entry("b", &[], &["a"]),
+            entry("c", &[], &["b"]),
+        ];
+        let err = topological_sort(&entries).unwrap_err();
+        assert_eq!(err.to_string(), "Dependency cycle: a -> c -> b -> a");
+    }
+
+    #[test]
+    fn test_topological_sort_rejects_unknown_commands() {
+        let err = topological_sort(&[entry("lint", &["fmt"], &[])]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command 'lint' runs before unknown command 'fmt'"
+        );
+        let err = topological_sort(&[entry("lint", &[], &["fmt"])]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command 'lint' runs after unknown command 'fmt'"
+        );
+    }
+}
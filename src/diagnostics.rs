@@ -0,0 +1,165 @@
+use crate::audit;
+use crate::process::find_executable;
+
+/// Exit code of `sh` when the command is not found
+const EXIT_NOT_FOUND: i32 = 127;
+/// Exit code of `sh` when the command was found but can't be executed
+const EXIT_NOT_EXECUTABLE: i32 = 126;
+/// Exit code of `cmd` when the command is not recognized
+const EXIT_NOT_RECOGNIZED_WINDOWS: i32 = 9009;
+
+/// Why the shell couldn't run a command's program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingProgram {
+    NotFound,
+    NotExecutable,
+}
+
+impl MissingProgram {
+    /// Recognize the shell's exit code for a program that couldn't be run
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        match code {
+            EXIT_NOT_FOUND | EXIT_NOT_RECOGNIZED_WINDOWS => Some(MissingProgram::NotFound),
+            EXIT_NOT_EXECUTABLE => Some(MissingProgram::NotExecutable),
+            _ => None,
+        }
+    }
+}
+
+/// Programs run by a shell command, in order: the first word of each simple
+/// command [`audit`] splits it into, skipping `VAR=value` assignments
+fn programs(command: &str) -> Vec<&str> {
+    audit::split_commands(command)
+        .into_iter()
+        .filter_map(|segment| {
+            segment
+                .text
+                .split_whitespace()
+                .find(|word| !audit::is_assignment(word))
+        })
+        .map(|word| word.trim_matches(|c| c == '"' || c == '\''))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The program that most likely failed: the first one that isn't on PATH,
+/// or else the command's first program
+pub fn failing_program(command: &str) -> Option<&str> {
+    let programs = programs(command);
+    programs
+        .iter()
+        .find(|program| !program.contains(['/', '\\']) && find_executable(program).is_none())
+        .or(programs.first())
+        .copied()
+}
+
+/// Message explaining that `label` (e.g. "Hook 'pre-commit'") couldn't run
+/// `program`, with the configured install hint if any
+pub fn missing_program_message(
+    label: &str,
+    program: &str,
+    missing: MissingProgram,
+    install_hint: Option<&str>,
+) -> String {
+    let mut message = match missing {
+        MissingProgram::NotFound => {
+            format!("{label} needs '{program}' which was not found on PATH")
+        }
+        MissingProgram::NotExecutable => {
+            format!("{label} needs '{program}' which was found but could not be executed")
+        }
+    };
+    if let Some(hint) = install_hint {
+        message.push_str(&format!("\nTo install it: {hint}"));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_exit_code() {
+        assert_eq!(
+            MissingProgram::from_exit_code(127),
+            Some(MissingProgram::NotFound)
+        );
+        assert_eq!(
+            MissingProgram::from_exit_code(9009),
+            Some(MissingProgram::NotFound)
+        );
+        assert_eq!(
+            MissingProgram::from_exit_code(126),
+            Some(MissingProgram::NotExecutable)
+        );
+        assert_eq!(MissingProgram::from_exit_code(1), None);
+    }
+
+    #[test]
+    fn test_failing_program() {
+        assert_eq!(
+            failing_program("RUST_LOG=debug cargo-nextest-missing run"),
+            Some("cargo-nextest-missing")
+        );
+        // The program that isn't on PATH, rather than the first one
+        assert_eq!(
+            failing_program("sh -c true && no-such-linter --fix | cat"),
+            Some("no-such-linter")
+        );
+        assert_eq!(
+            failing_program("./scripts/check.sh"),
+            Some("./scripts/check.sh")
+        );
+        // Command substitutions and subshells run programs too
+        assert_eq!(
+            failing_program("echo $(no-such-version-tool --version)"),
+            Some("no-such-version-tool")
+        );
+        assert_eq!(
+            failing_program("(git status && `no-such-generator`)"),
+            Some("no-such-generator")
+        );
+        assert_eq!(failing_program("  "), None);
+    }
+
+    #[test]
+    fn test_missing_program_message() {
+        assert_eq!(
+            missing_program_message(
+                "Hook 'pre-commit'",
+                "cargo-nextest",
+                MissingProgram::NotFound,
+                None
+            ),
+            "Hook 'pre-commit' needs 'cargo-nextest' which was not found on PATH"
+        );
+        assert_eq!(
+            missing_program_message(
+                "Command 'lint' in hook 'pre-commit'",
+                "./lint.sh",
+                MissingProgram::NotExecutable,
+                Some("chmod +x lint.sh")
+            ),
+            "Command 'lint' in hook 'pre-commit' needs './lint.sh' which was found but could not be executed\nTo install it: chmod +x lint.sh"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_nonexistent_binary_is_diagnosed() {
+        let command = "hookmaster-test-no-such-binary --version";
+        let status = std::process::Command::new("sh")
+            .args(["-c", command])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+
+        let missing = MissingProgram::from_exit_code(status.code().unwrap()).unwrap();
+        let program = failing_program(command).unwrap();
+        assert_eq!(
+            missing_program_message("Hook 'pre-commit'", program, missing, None),
+            "Hook 'pre-commit' needs 'hookmaster-test-no-such-binary' which was not found on PATH"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::env;
+use std::path::Path;
+
+use crate::process::shell_quote;
+
+/// Where the repository is mounted in the container
+const WORK_DIR: &str = "/work";
+
+/// Environment variables with this prefix are passed to the container
+const ENV_PREFIX: &str = "HOOKMASTER_";
+
+/// Arguments to `docker` that run `command` in a throwaway container of
+/// `image`, with the repository at `root` mounted at `/work` and `cwd`
+/// (inside the repository) as the working directory. `env_names` are passed
+/// through from hookmaster's environment. The command is split into words
+/// like a shell would, but no shell is run in the container.
+pub fn run_args(
+    root: &Path,
+    cwd: &Path,
+    image: &str,
+    command: &str,
+    env_names: &[String],
+) -> Vec<String> {
+    let mut workdir = WORK_DIR.to_string();
+    if let Ok(relative) = cwd.strip_prefix(root) {
+        for part in relative.components() {
+            workdir.push('/');
+            workdir.push_str(&part.as_os_str().to_string_lossy());
+        }
+    }
+
+    let mut args = vec![
+        "run".to_string(),
+        "--rm".to_string(),
+        "-v".to_string(),
+        format!("{}:{WORK_DIR}", root.display()),
+        "-w".to_string(),
+        workdir,
+    ];
+    for name in env_names {
+        args.push("-e".to_string());
+        args.push(name.clone());
+    }
+    args.push(image.to_string());
+    args.extend(split_command(command));
+    args
+}
+
+/// Names of the `HOOKMASTER_*` variables set in the environment, sorted
+pub fn passed_env() -> Vec<String> {
+    let mut names: Vec<String> = env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .filter(|name| name.starts_with(ENV_PREFIX))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Split a command into words: whitespace separates words, quotes group
+/// them and a backslash outside single quotes escapes the next character
+fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// A `docker` invocation as a shell command line, for `--dry-run`
+pub fn display(args: &[String]) -> String {
+    let mut line = String::from("docker");
+    for arg in args {
+        line.push(' ');
+        line.push_str(&shell_quote(arg));
+    }
+    line
+}
+
+/// Shell command line running `command` in `image` from a script run at the
+/// repository root, for `eject`
+pub fn script_line(image: &str, command: &str) -> String {
+    let mut line = format!("docker run --rm -v \"$PWD:{WORK_DIR}\" -w {WORK_DIR}");
+    for arg in [image.to_string()]
+        .into_iter()
+        .chain(split_command(command))
+    {
+        line.push(' ');
+        line.push_str(&shell_quote(&arg));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    #[test]
+    fn test_run_args() {
+        let root = PathBuf::from("/home/dev/project");
+        let args = run_args(
+            &root,
+            &root,
+            "ghcr.io/acme/licensecheck:1",
+            "licensecheck --deny 'GPL-3.0 only'",
+            &["HOOKMASTER_CONFIG".to_string()],
+        );
+        assert_eq!(
+            args,
+            vec![
+                "run",
+                "--rm",
+                "-v",
+                "/home/dev/project:/work",
+                "-w",
+                "/work",
+                "-e",
+                "HOOKMASTER_CONFIG",
+                "ghcr.io/acme/licensecheck:1",
+                "licensecheck",
+                "--deny",
+                "GPL-3.0 only",
+            ]
+        );
+        assert_eq!(
+            display(&args),
+            "docker run --rm -v /home/dev/project:/work -w /work -e HOOKMASTER_CONFIG \
+             ghcr.io/acme/licensecheck:1 licensecheck --deny 'GPL-3.0 only'"
+        );
+
+        // Fan-out commands work in their directory
+        let args = run_args(
+            &root,
+            &root.join("packages/web"),
+            "node:20",
+            "npm test",
+            &[],
+        );
+        assert_eq!(args[5], "/work/packages/web");
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("  cargo  test "), vec!["cargo", "test"]);
+        assert_eq!(
+            split_command(r#"echo "a b" 'c "d"' e\ f"g"h ''"#),
+            vec!["echo", "a b", "c \"d\"", "e fgh", ""]
+        );
+        assert_eq!(split_command(r#""\"quoted\"""#), vec!["\"quoted\""]);
+        assert!(split_command("").is_empty());
+    }
+
+    #[test]
+    fn test_display_quotes_arguments() {
+        let args = vec!["run".to_string(), "it's".to_string(), String::new()];
+        assert_eq!(display(&args), r"docker run 'it'\''s' ''");
+        assert_eq!(
+            script_line("node:20", "npm run 'lint:all'"),
+            r#"docker run --rm -v "$PWD:/work" -w /work node:20 npm run lint:all"#
+        );
+    }
+
+    /// Runs a real container; set HOOKMASTER_TEST_DOCKER=1 to enable
+    #[test]
+    fn test_run_in_container() {
+        if env::var_os("HOOKMASTER_TEST_DOCKER").is_none() {
+            return;
+        }
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("input.txt"), "hello").unwrap();
+
+        let args = run_args(root, root, "alpine:3", "sh -c 'cat input.txt; exit 3'", &[]);
+        let output = Command::new("docker").args(&args).output().unwrap();
+        assert_eq!(output.stdout, b"hello");
+        assert_eq!(output.status.code(), Some(3));
+    }
+}
@@ -0,0 +1,135 @@
+//! The check `forbid_duplicate_subject_within` under `[commit-msg]` makes
+//! of the subject the `commit-msg` hook is given: a second "fix tests" in a
+//! row makes the history confusing to rebase
+
+use regex::Regex;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Subject prefixes of the commits git writes the message of, such as
+/// merges and reverts, and of those `git rebase --autosquash` folds into
+/// others, which repeat subjects on purpose
+const AUTOMATIC_PREFIXES: [&str; 5] = ["Merge ", "Revert ", "fixup! ", "squash! ", "amend! "];
+
+/// The subject of the commit `message`: its first line that is neither
+/// blank nor a comment
+pub fn subject_of(message: &str) -> Option<&str> {
+    message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// `subject` as duplicates are compared: without the tickets
+/// `prepare-commit-msg` starts it with, such as `JIRA-123: `, with runs of
+/// whitespace as one space, and in lowercase
+pub fn normalize_subject(subject: &str) -> String {
+    static TICKET_PREFIX: OnceLock<Regex> = OnceLock::new();
+    let ticket_prefix = TICKET_PREFIX.get_or_init(|| {
+        Regex::new(r"^(?:[A-Z][A-Z0-9]+-\d+[\s,]*)+:\s*").expect("Invalid ticket prefix regex")
+    });
+    let without_tickets = ticket_prefix.replace(subject.trim(), "");
+    without_tickets
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Whether `subject` is one that repeats on purpose, such as of a merge
+fn is_automatic_subject(subject: &str) -> bool {
+    let subject = subject.trim_start();
+    AUTOMATIC_PREFIXES
+        .iter()
+        .any(|prefix| subject.starts_with(prefix))
+}
+
+/// The first of the `recent` subjects that `subject` duplicates, if it
+/// isn't an automatic one
+pub fn find_duplicate<'a>(subject: &str, recent: &'a [String]) -> Option<&'a str> {
+    let normalized = normalize_subject(subject);
+    if is_automatic_subject(subject) || normalized.is_empty() {
+        return None;
+    }
+    recent
+        .iter()
+        .find(|earlier| normalize_subject(earlier) == normalized)
+        .map(String::as_str)
+}
+
+/// The subjects of the last `count` commits on the current branch of the
+/// repository at `root`, newest first; none before the first commit
+pub fn recent_subjects(root: &Path, count: usize) -> Vec<String> {
+    let output = Command::new("git")
+        .args(["log", "--format=%s", "-n", &count.to_string()])
+        .current_dir(root)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_subject() {
+        let cases = [
+            ("Fix tests", "fix tests"),
+            ("  fix   TESTS ", "fix tests"),
+            ("JIRA-123: Fix tests", "fix tests"),
+            ("JIRA-1 ABC-22: Fix tests", "fix tests"),
+            ("JIRA-1, ABC-22: Fix tests", "fix tests"),
+            // Only a prefix of tickets followed by a colon is left out
+            ("Fix JIRA-123: tests", "fix jira-123: tests"),
+            ("JIRA-123 Fix tests", "jira-123 fix tests"),
+            ("fix(api): Add login", "fix(api): add login"),
+        ];
+        for (subject, expected) in cases {
+            assert_eq!(normalize_subject(subject), expected, "{subject}");
+        }
+    }
+
+    #[test]
+    fn test_find_duplicate() {
+        let recent: Vec<String> = [
+            "JIRA-7: Fix tests",
+            "Merge branch 'main'",
+            "Revert \"Add login\"",
+            "fixup! Add login",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        assert_eq!(
+            find_duplicate("fix  tests", &recent),
+            Some("JIRA-7: Fix tests")
+        );
+        assert_eq!(
+            find_duplicate("JIRA-8: FIX TESTS", &recent),
+            Some("JIRA-7: Fix tests")
+        );
+        assert_eq!(find_duplicate("Fix more tests", &recent), None);
+        // Automatic subjects repeat on purpose
+        assert_eq!(find_duplicate("Merge branch 'main'", &recent), None);
+        assert_eq!(find_duplicate("Revert \"Add login\"", &recent), None);
+        assert_eq!(find_duplicate("fixup! Add login", &recent), None);
+        assert_eq!(find_duplicate("JIRA-9: ", &recent), None);
+    }
+
+    #[test]
+    fn test_subject_of() {
+        assert_eq!(
+            subject_of("# Please enter\n\n Fix tests \n\nBody\n"),
+            Some("Fix tests")
+        );
+        assert_eq!(subject_of("# Only comments\n\n"), None);
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+use crate::config::{EnvPolicy, GitHooksConfig, HookEntry, HookSpec, Runner, Settings};
+use crate::dag;
+use crate::docker;
+use crate::git_hooks::GitHook;
+use crate::process::shell_quote;
+use crate::resolve;
+use crate::tool_paths;
+
+/// Environment variable that makes ejected scripts do nothing, and skips
+/// the checks of `run` it names
+pub(crate) const SKIP_ENV_VAR: &str = "HOOKMASTER_SKIP";
+
+/// Where the configuration of ejected scripts came from, for their header
+#[derive(Debug, Clone)]
+pub struct Origin {
+    /// Configuration file name, e.g. `hookmaster.toml`
+    pub source: String,
+    /// Short hash of the commit that last changed the file, if committed
+    pub revision: Option<String>,
+}
+
+/// A standalone hook script
+#[derive(Debug, Clone, PartialEq)]
+pub struct EjectedHook {
+    /// Hook file name, e.g. `pre-commit`
+    pub hook: String,
+    /// Content of the script
+    pub script: String,
+}
+
+/// Scripts for the configured hooks, and the configured behavior they
+/// can't reproduce without hookmaster
+#[derive(Debug, Default)]
+pub struct Ejection {
+    /// Scripts in the order of [`GitHook::standard_hooks`]
+    pub hooks: Vec<EjectedHook>,
+    /// Features that need hookmaster and are left out of the scripts
+    pub warnings: Vec<String>,
+}
+
+/// Generate POSIX shell scripts running the configured commands of the hooks
+/// `hookmaster add` installs, for the repository at `root`
+pub fn eject(config: &GitHooksConfig, root: &Path, origin: &Origin) -> Result<Ejection> {
+    let mut ejection = Ejection::default();
+    let tool_dirs = tool_paths::configured_dirs(&config.run, root);
+
+    if config.sandbox.enabled {
+        ejection
+            .warnings
+            .push("Sandboxing needs hookmaster; ejected commands run unsandboxed".to_string());
+    }
+
+    for hook in GitHook::standard_hooks() {
+        let hook_name = hook.to_filename();
+        if hook == GitHook::PrepareCommitMsg {
+            ejection.warnings.push(
+                "Hook 'prepare-commit-msg': adding the ticket from the branch name to commit messages needs hookmaster and is not ejected"
+                    .to_string(),
+            );
+            continue;
+        }
+        if !config.has_active_hook(&hook_name) {
+            continue;
+        }
+
+        let spec = resolve::resolve_hook(config, &hook_name, root)?;
+        let problems = spec.validate(&hook_name);
+        if !problems.is_empty() {
+            return Err(anyhow!(
+                "Invalid configuration for hook '{}':\n  {}",
+                hook_name,
+                problems.join("\n  ")
+            ));
+        }
+
+        let script = hook_script(&hook_name, &spec, &config.settings, &tool_dirs, origin)?;
+        ejection.warnings.extend(warnings(&hook_name, &spec));
+        ejection.hooks.push(EjectedHook {
+            hook: hook_name,
+            script,
+        });
+    }
+
+    Ok(ejection)
+}
+
+/// Script running the commands of a resolved hook one after another,
+/// stopping at the first failure
+fn hook_script(
+    hook_name: &str,
+    spec: &HookSpec,
+    settings: &Settings,
+    tool_dirs: &[String],
+    origin: &Origin,
+) -> Result<String> {
+    let revision = origin
+        .revision
+        .as_ref()
+        .map(|revision| format!(" at {revision}"))
+        .unwrap_or_default();
+    let mut lines = vec![
+        "#!/bin/sh".to_string(),
+        format!(
+            "# Generated by `hookmaster eject` (hookmaster {}) from {}{revision}.",
+            env!("CARGO_PKG_VERSION"),
+            origin.source
+        ),
+        "# Runs without hookmaster; change the configuration and eject again instead of editing."
+            .to_string(),
+        format!("# Set {SKIP_ENV_VAR}=1 to skip this hook."),
+        String::new(),
+        format!("if [ \"${{{SKIP_ENV_VAR}:-}}\" = \"1\" ]; then"),
+        "    exit 0".to_string(),
+        "fi".to_string(),
+    ];
+
+    if !tool_dirs.is_empty() {
+        lines.push(String::new());
+        for dir in tool_dirs {
+            let dir = dir.trim_end_matches('/');
+            lines.push(format!(
+                "if [ -d {} ]; then PATH=\"$PWD/{}:$PATH\"; fi",
+                shell_quote(dir),
+                dir.replace(['\\', '"', '$', '`'], "")
+            ));
+        }
+        lines.push("export PATH".to_string());
+    }
+
+    for entry in dag::topological_sort(&spec.commands)? {
+        if entry.foreach_dir.is_some() {
+            continue;
+        }
+        lines.push(String::new());
+        if entry.name != hook_name && entry.name != entry.command {
+            lines.push(format!("# {}", entry.name));
+        }
+        lines.push("(".to_string());
+        lines.push(command_line(entry, settings));
+        lines.push(") || exit $?".to_string());
+    }
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// The shell command line running an entry's command
+fn command_line(entry: &HookEntry, settings: &Settings) -> String {
+    if let (Runner::Docker, Some(image)) = (entry.runner, &entry.image) {
+        return docker::script_line(image, &entry.command);
+    }
+    if settings.shell.is_none() && settings.shell_args.is_empty() {
+        return entry.command.clone();
+    }
+
+    let mut words = vec![settings.shell.clone().unwrap_or_else(|| "sh".to_string())];
+    words.extend(settings.shell_args.iter().cloned());
+    words.extend(["-c".to_string(), entry.command.clone()]);
+    let words: Vec<String> = words.iter().map(|word| shell_quote(word)).collect();
+    words.join(" ")
+}
+
+/// Settings of a hook that ejected scripts don't reproduce
+fn warnings(hook_name: &str, spec: &HookSpec) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if spec.parallel && spec.commands.len() > 1 {
+        warnings.push(format!(
+            "Hook '{hook_name}': running commands in parallel needs hookmaster; they run one at a time"
+        ));
+    }
+    if spec.env_policy() != EnvPolicy::Inherit {
+        warnings.push(format!(
+            "Hook '{hook_name}': limiting the environment with 'env-inherit' or 'env-allow' needs hookmaster; commands get the whole environment"
+        ));
+    }
+    if spec.deny_network {
+        warnings.push(format!(
+            "Hook '{hook_name}': 'deny-network' needs hookmaster; commands get network access"
+        ));
+    }
+    if !spec.only_remotes.is_empty() || !spec.skip_remotes.is_empty() {
+        warnings.push(format!(
+            "Hook '{hook_name}': 'only-remotes' and 'skip-remotes' need hookmaster; the hook runs for every remote"
+        ));
+    }
+    if !spec.limits.is_empty() {
+        warnings.push(format!(
+            "Hook '{hook_name}': 'max-memory-mb' and 'max-cpu-percent' need hookmaster; commands run without limits"
+        ));
+    }
+    for entry in &spec.commands {
+        let label = if entry.name == hook_name {
+            format!("Hook '{hook_name}'")
+        } else {
+            format!("Hook '{hook_name}': command '{}'", entry.name)
+        };
+        if entry.foreach_dir.is_some() {
+            warnings.push(format!(
+                "{label}: running in each matching directory needs hookmaster; the command is left out"
+            ));
+        }
+        if !entry.only_remotes.is_empty() || !entry.skip_remotes.is_empty() {
+            warnings.push(format!(
+                "{label}: 'only_remotes' and 'skip_remotes' need hookmaster; the command runs for every remote"
+            ));
+        }
+        if entry.allow_failure || !entry.allow_exit_codes.is_empty() {
+            warnings.push(format!(
+                "{label}: allowed exit codes need hookmaster; any failure stops the hook"
+            ));
+        }
+        if entry.timeout.is_some() {
+            warnings.push(format!(
+                "{label}: timeouts need hookmaster; the command runs without one"
+            ));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Compare a script with a golden file in `tests/golden/eject`
+    fn assert_golden(script: &str, golden: &str) {
+        let script = script.replace(env!("CARGO_PKG_VERSION"), "VERSION");
+        assert_eq!(script, golden);
+    }
+
+    fn origin() -> Origin {
+        Origin {
+            source: "hookmaster.toml".to_string(),
+            revision: Some("1a2b3c4".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_eject_sequences_commands() {
+        let content = r#"
+pre-push = ["@pre-commit", "cargo test"]
+
+[hooks.pre-commit.clippy]
+command = "cargo clippy -- -D warnings"
+depends_on = ["fmt"]
+
+[hooks.pre-commit.fmt]
+command = "cargo fmt --check"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let ejection = eject(&config, temp_dir.path(), &origin()).unwrap();
+
+        let hooks: Vec<&str> = ejection.hooks.iter().map(|h| h.hook.as_str()).collect();
+        assert_eq!(hooks, vec!["pre-commit", "pre-push"]);
+        assert_golden(
+            &ejection.hooks[0].script,
+            include_str!("../tests/golden/eject/pre-commit.sh"),
+        );
+        assert_golden(
+            &ejection.hooks[1].script,
+            include_str!("../tests/golden/eject/pre-push.sh"),
+        );
+        assert_eq!(
+            ejection.warnings,
+            vec!["Hook 'prepare-commit-msg': adding the ticket from the branch name to commit messages needs hookmaster and is not ejected"]
+        );
+    }
+
+    #[test]
+    fn test_eject_inlines_runners_and_tool_paths() {
+        let content = r#"
+[hooks.pre-push]
+parallel = true
+licenses = { command = "licensecheck --deny GPL", runner = "docker", image = "acme/licensecheck:1" }
+test = { command = "npm test", timeout = 600 }
+packages = { command = "npm run build", foreach_dir = "packages/*/" }
+
+[settings]
+shell = "bash"
+shell-args = ["--noprofile", "--norc"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("package.json"), "{}").unwrap();
+        let origin = Origin {
+            source: "package.json".to_string(),
+            revision: None,
+        };
+        let ejection = eject(&config, temp_dir.path(), &origin).unwrap();
+
+        assert_eq!(ejection.hooks.len(), 1);
+        assert_golden(
+            &ejection.hooks[0].script,
+            include_str!("../tests/golden/eject/pre-push-docker.sh"),
+        );
+        assert_eq!(
+            &ejection.warnings[1..],
+            vec![
+                "Hook 'pre-push': running commands in parallel needs hookmaster; they run one at a time",
+                "Hook 'pre-push': command 'test': timeouts need hookmaster; the command runs without one",
+                "Hook 'pre-push': command 'packages': running in each matching directory needs hookmaster; the command is left out",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eject_rejects_invalid_configuration() {
+        let content = "[hooks.pre-commit.a]\ncommand = \"true\"\ndepends_on = [\"b\"]\n";
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let err = eject(&config, temp_dir.path(), &origin()).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid configuration for hook 'pre-commit'"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Environment variables from dotenv files, for `run --env-file`
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Names and values of environment variables, in the order they are set
+pub type EnvVars = Vec<(String, String)>;
+
+/// The variables of a dotenv file
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvFile {
+    /// Names and values in the order the file sets them, so the last one
+    /// wins for a name set twice
+    pub vars: EnvVars,
+}
+
+impl EnvFile {
+    /// Load the file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read env file: {}", path.display()))?;
+        let vars =
+            parse(&content).with_context(|| format!("Invalid env file {}", path.display()))?;
+        Ok(Self { vars })
+    }
+}
+
+/// The `NAME=value` lines of `content`. Blank lines and lines starting with
+/// `#` are skipped, and an `export ` before the name is allowed. Values in
+/// single quotes are taken as they are; in double quotes `\n`, `\"` and
+/// `\\` are escapes; unquoted ones end at a ` #` comment.
+fn parse(content: &str) -> Result<EnvVars> {
+    let mut vars = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line_num = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected NAME=value on line {line_num}"))?;
+        let name = name.trim();
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(anyhow!("Invalid variable name '{name}' on line {line_num}"));
+        }
+        vars.push((name.to_string(), parse_value(value.trim(), line_num)?));
+    }
+    Ok(vars)
+}
+
+/// The value after the `=` of line `line_num`
+fn parse_value(value: &str, line_num: usize) -> Result<String> {
+    let unterminated = || anyhow!("Unterminated quoted value on line {line_num}");
+    if let Some(rest) = value.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or_else(unterminated)?;
+        return Ok(rest[..end].to_string());
+    }
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Ok(result),
+                '\\' => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some(c @ ('"' | '\\')) => result.push(c),
+                    Some(c) => {
+                        result.push('\\');
+                        result.push(c);
+                    }
+                    None => return Err(unterminated()),
+                },
+                c => result.push(c),
+            }
+        }
+        return Err(unterminated());
+    }
+    let value = match value.find(" #") {
+        Some(comment) => &value[..comment],
+        None => value,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+# Local settings
+DATABASE_URL=postgres://localhost/dev
+export API_KEY = secret  # not for CI
+GREETING="Hello, \"world\"\nbye"
+RAW='$HOME and \n'
+EMPTY=
+"#;
+        let vars = parse(content).unwrap();
+        let expected = [
+            ("DATABASE_URL", "postgres://localhost/dev"),
+            ("API_KEY", "secret"),
+            ("GREETING", "Hello, \"world\"\nbye"),
+            ("RAW", "$HOME and \\n"),
+            ("EMPTY", ""),
+        ];
+        assert_eq!(
+            vars,
+            expected
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |content: &str| parse(content).unwrap_err().to_string();
+        assert_eq!(error("A=1\nB\n"), "Expected NAME=value on line 2");
+        assert_eq!(error("1A=x"), "Invalid variable name '1A' on line 1");
+        assert_eq!(error("A=\"open"), "Unterminated quoted value on line 1");
+    }
+
+    #[test]
+    fn test_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join(".env.local");
+        fs::write(&path, "A=1\nA=2\n").unwrap();
+        let env_file = EnvFile::load(&path).unwrap();
+        assert_eq!(env_file.vars.len(), 2);
+
+        let missing = EnvFile::load(&temp_dir.path().join(".env")).unwrap_err();
+        assert!(missing.to_string().starts_with("Failed to read env file"));
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Expand a directory glob such as `packages/*/` relative to `root`.
+///
+/// Each `/`-separated segment may contain `*` (any run of characters), `?`
+/// (a single character) and `[...]` (one of the characters listed); only
+/// directories are matched. Returned paths are
+/// relative to `root` and sorted.
+pub fn expand_dir_glob(root: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut matches = vec![PathBuf::new()];
+
+    for segment in pattern.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        let mut next = Vec::new();
+
+        for dir in &matches {
+            if !segment.contains(['*', '?', '[']) {
+                if root.join(dir).join(segment).is_dir() {
+                    next.push(dir.join(segment));
+                }
+                continue;
+            }
+
+            let entries = fs::read_dir(root.join(dir)).with_context(|| {
+                format!("Failed to read directory: {}", root.join(dir).display())
+            })?;
+            for entry in entries {
+                let entry = entry.with_context(|| "Failed to read directory entry")?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_dir() && wildcard_match(segment, &name) {
+                    next.push(dir.join(name));
+                }
+            }
+        }
+
+        matches = next;
+    }
+
+    matches.retain(|dir| !dir.as_os_str().is_empty());
+    matches.sort();
+    Ok(matches)
+}
+
+/// Match a single path segment against a glob pattern with `*`, `?` and
+/// `[...]` wildcards, as [`glob::Pattern`] does. Like shells do, the
+/// wildcards don't match a leading `.`. A pattern that isn't a valid glob,
+/// such as one with an unclosed `[`, only matches itself.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+    match Pattern::new(pattern) {
+        Ok(glob) => glob.matches_with(name, options),
+        Err(_) => pattern == name,
+    }
+}
+
+/// Keep only the directories that contain at least one of the changed files.
+/// Both are relative to the repository root.
+pub fn dirs_with_changes(dirs: &[PathBuf], changed_files: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .filter(|dir| changed_files.iter().any(|file| file.starts_with(dir)))
+        .cloned()
+        .collect()
+}
+
+/// Where the files a hook run checks come from: those in `only_changed`
+/// directories and in `{files}` placeholders
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum FileSource {
+    /// The files staged in the repository
+    #[default]
+    Staged,
+    /// Files given with `run --files`, relative to the repository root
+    Given(Vec<PathBuf>),
+}
+
+impl FileSource {
+    /// The files given with `run --files`, resolved against `root` when
+    /// relative. Each must exist and be inside the repository.
+    pub fn given(root: &Path, files: &[PathBuf]) -> Result<Self> {
+        let canonical_root = fs::canonicalize(root)
+            .with_context(|| format!("Failed to resolve {}", root.display()))?;
+        let mut given = Vec::new();
+        for file in files {
+            let path = fs::canonicalize(root.join(file))
+                .with_context(|| format!("File not found: {}", file.display()))?;
+            let relative = path.strip_prefix(&canonical_root).map_err(|_| {
+                anyhow!(
+                    "{} is outside the repository {}",
+                    file.display(),
+                    root.display()
+                )
+            })?;
+            given.push(relative.to_path_buf());
+        }
+        Ok(FileSource::Given(given))
+    }
+
+    /// The files, relative to the repository at `root`
+    pub fn files(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        match self {
+            FileSource::Staged => staged_files(root),
+            FileSource::Given(files) => Ok(files.clone()),
+        }
+    }
+}
+
+/// Get the files staged in the repository at `root`, relative to it, leaving
+/// out deleted ones, which there is nothing left of to check
+pub fn staged_files(root: &Path) -> Result<Vec<PathBuf>> {
+    // Without -z, git quotes names with unusual characters
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args([
+            "diff",
+            "--cached",
+            "--name-only",
+            "--diff-filter=ACMR",
+            "-z",
+        ])
+        .output()
+        .with_context(|| "Failed to execute git command")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Git command failed: {}", stderr));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "web"));
+        assert!(wildcard_match("pkg-*", "pkg-web"));
+        assert!(wildcard_match("pkg-?", "pkg-a"));
+        assert!(wildcard_match("*-lib-*", "core-lib-utils"));
+        assert!(wildcard_match("exact", "exact"));
+        assert!(!wildcard_match("pkg-?", "pkg-ab"));
+        assert!(!wildcard_match("pkg-*", "lib-web"));
+        assert!(!wildcard_match("*", ".hidden"));
+        assert!(wildcard_match(".*", ".hidden"));
+        assert!(wildcard_match("pkg-[ab]", "pkg-b"));
+        assert!(!wildcard_match("pkg-[ab]", "pkg-c"));
+        // Not a valid glob, so only the pattern itself matches
+        assert!(wildcard_match("pkg-[", "pkg-["));
+        assert!(!wildcard_match("pkg-[", "pkg-a"));
+    }
+
+    #[test]
+    fn test_expand_dir_glob() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("packages/web/src")).unwrap();
+        fs::create_dir_all(root.join("packages/api")).unwrap();
+        fs::create_dir_all(root.join("packages/.cache")).unwrap();
+        fs::write(root.join("packages/README.md"), "not a dir").unwrap();
+
+        assert_eq!(
+            expand_dir_glob(root, "packages/*/").unwrap(),
+            vec![PathBuf::from("packages/api"), PathBuf::from("packages/web")]
+        );
+        assert_eq!(
+            expand_dir_glob(root, "packages/*/src").unwrap(),
+            vec![PathBuf::from("packages/web/src")]
+        );
+        assert!(expand_dir_glob(root, "missing/*").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_given_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("repo");
+        fs::create_dir_all(root.join("packages/web")).unwrap();
+        fs::write(root.join("packages/web/index.js"), "").unwrap();
+        fs::write(root.join("README.md"), "").unwrap();
+        fs::write(temp_dir.path().join("outside.txt"), "").unwrap();
+
+        let source = FileSource::given(
+            &root,
+            &[
+                PathBuf::from("packages/web/index.js"),
+                root.join("README.md"),
+            ],
+        )
+        .unwrap();
+        let files = source.files(&root).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("packages/web/index.js"),
+                PathBuf::from("README.md")
+            ]
+        );
+
+        // The given files stand in for the staged ones without a git index
+        let dirs = expand_dir_glob(&root, "packages/*/").unwrap();
+        assert_eq!(
+            dirs_with_changes(&dirs, &files),
+            vec![PathBuf::from("packages/web")]
+        );
+
+        let err = FileSource::given(&root, &[PathBuf::from("missing.rs")]).unwrap_err();
+        assert_eq!(err.to_string(), "File not found: missing.rs");
+        let err = FileSource::given(&root, &[PathBuf::from("../outside.txt")]).unwrap_err();
+        assert!(
+            err.to_string().contains("is outside the repository"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_staged_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        fs::write(root.join("a.txt"), "a\n").unwrap();
+        git(&["add", "a.txt"]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+
+        git(&["rm", "-q", "a.txt"]);
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/käyttö \"ohje\".md"), "").unwrap();
+        git(&["add", "docs"]);
+        assert_eq!(
+            staged_files(root).unwrap(),
+            vec![PathBuf::from("docs/käyttö \"ohje\".md")]
+        );
+    }
+
+    #[test]
+    fn test_dirs_with_changes() {
+        let dirs = vec![
+            PathBuf::from("packages/a"),
+            PathBuf::from("packages/ab"),
+            PathBuf::from("packages/c"),
+        ];
+        let changed = vec![
+            PathBuf::from("packages/ab/index.js"),
+            PathBuf::from("README.md"),
+        ];
+
+        assert_eq!(
+            dirs_with_changes(&dirs, &changed),
+            vec![PathBuf::from("packages/ab")]
+        );
+    }
+}
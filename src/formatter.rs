@@ -0,0 +1,680 @@
+//! Formatting of the output of hook commands
+
+use regex::Regex;
+use serde_json::json;
+use std::env;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How `hookmaster run` prints the output of hook commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Output is passed through unchanged
+    #[default]
+    Raw,
+    /// ANSI codes stripped, repeated blank lines collapsed and each line
+    /// prefixed with the hook name
+    Compact,
+    /// One JSON object per line with the stream, the line and a timestamp
+    JsonLines,
+    /// Each line prefixed with the name of the command that printed it,
+    /// colored on a terminal. The default for hooks running commands in parallel.
+    Prefixed,
+}
+
+impl OutputFormat {
+    /// Parse from the `--format` option value
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "raw" => Some(OutputFormat::Raw),
+            "compact" => Some(OutputFormat::Compact),
+            "json-lines" => Some(OutputFormat::JsonLines),
+            "prefixed" => Some(OutputFormat::Prefixed),
+            _ => None,
+        }
+    }
+
+    /// Formatter for the lines the command `command_name` of `hook_name`
+    /// writes to `stream`
+    pub fn formatter(
+        self,
+        hook_name: &str,
+        command_name: &str,
+        stream: Stream,
+    ) -> Box<dyn OutputFormatter + Send> {
+        match self {
+            OutputFormat::Raw => Box::new(RawFormatter),
+            OutputFormat::Compact => Box::new(CompactFormatter::new(hook_name)),
+            OutputFormat::JsonLines => Box::new(JsonLinesFormatter::new(stream)),
+            OutputFormat::Prefixed => Box::new(PrefixedFormatter::new(
+                command_name,
+                stream.supports_color(),
+            )),
+        }
+    }
+}
+
+/// When `hookmaster run` prints what hook commands write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputBuffering {
+    /// Each line as soon as it is complete. Raw output isn't piped at all
+    /// and reaches the terminal as the command writes it.
+    #[default]
+    Line,
+    /// All of a command's output once it has finished, so that the output
+    /// of commands running in parallel doesn't interleave
+    Full,
+    /// Whatever the command writes as soon as it is read, even without a
+    /// newline, such as progress dots. Output in a format other than raw is
+    /// still printed a line at a time.
+    Char,
+}
+
+impl OutputBuffering {
+    /// Parse from the `--output-buffering` option value
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "line" => Some(OutputBuffering::Line),
+            "full" => Some(OutputBuffering::Full),
+            "char" => Some(OutputBuffering::Char),
+            _ => None,
+        }
+    }
+}
+
+/// Output stream of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// Standard output
+    Stdout,
+    /// Standard error
+    Stderr,
+}
+
+impl Stream {
+    /// Name used in machine-readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+
+    /// Whether this process's stream is a terminal and `NO_COLOR` is not set
+    pub fn supports_color(&self) -> bool {
+        let terminal = match self {
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        };
+        terminal && env::var_os("NO_COLOR").is_none()
+    }
+}
+
+/// Formats the output of hook commands line by line
+pub trait OutputFormatter {
+    /// Format a line of output, given without its line ending. Returns `None`
+    /// for lines that should be dropped.
+    fn format_line(&mut self, line: &str) -> Option<String>;
+}
+
+/// Passes lines through unchanged
+pub struct RawFormatter;
+
+impl OutputFormatter for RawFormatter {
+    fn format_line(&mut self, line: &str) -> Option<String> {
+        Some(line.to_string())
+    }
+}
+
+/// Strips ANSI escape codes, collapses runs of blank lines into one and
+/// prefixes each line with `[<hook>] `
+pub struct CompactFormatter {
+    prefix: String,
+    previous_blank: bool,
+}
+
+impl CompactFormatter {
+    /// Create a formatter prefixing lines with `[<hook_name>]`
+    pub fn new(hook_name: &str) -> Self {
+        Self {
+            prefix: format!("[{hook_name}] "),
+            previous_blank: false,
+        }
+    }
+}
+
+impl OutputFormatter for CompactFormatter {
+    fn format_line(&mut self, line: &str) -> Option<String> {
+        let line = strip_ansi(line);
+        let blank = line.trim().is_empty();
+        if blank && self.previous_blank {
+            return None;
+        }
+        self.previous_blank = blank;
+
+        let line = format!("{}{}", self.prefix, line.trim_end());
+        Some(line.trim_end().to_string())
+    }
+}
+
+/// Emits each line as `{"stream":"stdout","line":"...","ts":"..."}`
+pub struct JsonLinesFormatter {
+    stream: Stream,
+}
+
+impl JsonLinesFormatter {
+    /// Create a formatter for lines read from `stream`
+    pub fn new(stream: Stream) -> Self {
+        Self { stream }
+    }
+}
+
+impl OutputFormatter for JsonLinesFormatter {
+    fn format_line(&mut self, line: &str) -> Option<String> {
+        let record = json!({
+            "stream": self.stream.as_str(),
+            "line": line,
+            "ts": timestamp(SystemTime::now()),
+        });
+        Some(record.to_string())
+    }
+}
+
+/// Prefixes each line with `[<command>] `, so the output of commands running
+/// in parallel can be told apart
+pub struct PrefixedFormatter {
+    prefix: String,
+}
+
+impl PrefixedFormatter {
+    /// ANSI colors for the prefixes, picked by command name so a command keeps
+    /// its color from run to run
+    const COLORS: [u8; 6] = [36, 35, 33, 32, 34, 31];
+
+    /// Create a formatter prefixing lines with `[<command_name>]`, in color
+    /// if `color` is set
+    pub fn new(command_name: &str, color: bool) -> Self {
+        let prefix = if color {
+            let hash = command_name.bytes().fold(0usize, |hash, byte| {
+                hash.wrapping_mul(31).wrapping_add(byte.into())
+            });
+            let color = Self::COLORS[hash % Self::COLORS.len()];
+            format!("\x1b[{color}m[{command_name}]\x1b[0m ")
+        } else {
+            format!("[{command_name}] ")
+        };
+        Self { prefix }
+    }
+}
+
+impl OutputFormatter for PrefixedFormatter {
+    fn format_line(&mut self, line: &str) -> Option<String> {
+        Some(format!("{}{}", self.prefix, line))
+    }
+}
+
+/// How a finished command counts towards the hook's result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// Exited with code 0
+    Passed,
+    /// Exited with a non-zero code the command allows
+    Warning,
+    /// Exited with any other code
+    Failed,
+}
+
+impl CommandStatus {
+    /// Name used in json-lines output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandStatus::Passed => "passed",
+            CommandStatus::Warning => "warning",
+            CommandStatus::Failed => "failed",
+        }
+    }
+}
+
+/// `{"command":"lint","exit_code":2,"status":"warning","ts":"..."}`, the
+/// json-lines record of how a command finished
+pub fn json_result_line(command: &str, exit_code: i32, status: CommandStatus) -> String {
+    json!({
+        "command": command,
+        "exit_code": exit_code,
+        "status": status.as_str(),
+        "ts": timestamp(SystemTime::now()),
+    })
+    .to_string()
+}
+
+/// How long the start of a line without a newline, such as a progress bar,
+/// is held back before it is printed anyway
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Longest partial line held back waiting for its newline
+const MAX_PENDING: usize = 64 * 1024;
+
+/// Read lines from `reader` on a new thread, printing them through
+/// `formatter` to the same stream of this process
+pub fn forward<R: Read + Send + 'static>(
+    reader: R,
+    mut formatter: Box<dyn OutputFormatter + Send>,
+    stream: Stream,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        pump(reader, formatter.as_mut(), FLUSH_INTERVAL, |line| {
+            // A closed pipe shouldn't stop the command itself
+            let _ = match stream {
+                Stream::Stdout => writeln!(io::stdout().lock(), "{line}"),
+                Stream::Stderr => writeln!(io::stderr().lock(), "{line}"),
+            };
+        });
+    })
+}
+
+/// A formatter that can be moved to the thread forwarding the output
+type SendFormatter = Box<dyn OutputFormatter + Send>;
+
+/// Like [`forward`], but printing as `buffering` says. Without a
+/// `formatter`, the output is printed exactly as the command wrote it.
+pub fn forward_buffered<R: Read + Send + 'static>(
+    mut reader: R,
+    formatter: Option<SendFormatter>,
+    stream: Stream,
+    buffering: OutputBuffering,
+) -> JoinHandle<()> {
+    match (formatter, buffering) {
+        (Some(formatter), OutputBuffering::Line | OutputBuffering::Char) => {
+            forward(reader, formatter, stream)
+        }
+        (Some(mut formatter), OutputBuffering::Full) => thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = reader.read_to_end(&mut output);
+            let output = String::from_utf8_lossy(&output);
+            for line in output.lines() {
+                if let Some(formatted) = formatter.format_line(line) {
+                    let _ = match stream {
+                        Stream::Stdout => writeln!(io::stdout().lock(), "{formatted}"),
+                        Stream::Stderr => writeln!(io::stderr().lock(), "{formatted}"),
+                    };
+                }
+            }
+        }),
+        (None, buffering) => thread::spawn(move || {
+            let _ = match stream {
+                Stream::Stdout => copy_buffered(reader, io::stdout(), buffering),
+                Stream::Stderr => copy_buffered(reader, io::stderr(), buffering),
+            };
+        }),
+    }
+}
+
+/// Copy what `reader` produces to `writer` unchanged, writing and flushing
+/// whenever `buffering` says so
+fn copy_buffered(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    buffering: OutputBuffering,
+) -> io::Result<()> {
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buf = [0; 8192];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buf[..n]);
+        let ready = match buffering {
+            OutputBuffering::Char => pending.len(),
+            OutputBuffering::Line => pending
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |end| end + 1),
+            OutputBuffering::Full => 0,
+        };
+        if ready > 0 {
+            writer.write_all(&pending[..ready])?;
+            writer.flush()?;
+            pending.drain(..ready);
+        }
+    }
+    writer.write_all(&pending)?;
+    writer.flush()
+}
+
+/// Split what `reader` produces into lines, passing each through `formatter`
+/// to `write`. The start of a line that has waited `flush_interval` for its
+/// newline is written as a line of its own. Invalid UTF-8 is replaced rather
+/// than stopping the output.
+fn pump<R: Read + Send + 'static>(
+    mut reader: R,
+    formatter: &mut dyn OutputFormatter,
+    flush_interval: Duration,
+    mut write: impl FnMut(&str),
+) {
+    // Reads block, so they happen on their own thread and this one can wait
+    // for either more output or the flush deadline
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if sender.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    });
+
+    let mut emit = |bytes: &[u8]| {
+        let line = String::from_utf8_lossy(bytes);
+        if let Some(formatted) = formatter.format_line(line.trim_end_matches(['\n', '\r'])) {
+            write(&formatted);
+        }
+    };
+
+    let mut pending: Vec<u8> = Vec::new();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let received = match deadline {
+            Some(deadline) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(chunk) => pending.extend_from_slice(&chunk),
+            Err(RecvTimeoutError::Timeout) => {
+                emit(&pending);
+                pending.clear();
+                deadline = None;
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let mut start = 0;
+        while let Some(end) = pending[start..].iter().position(|&b| b == b'\n') {
+            emit(&pending[start..start + end + 1]);
+            start += end + 1;
+            deadline = None;
+        }
+        pending.drain(..start);
+
+        if pending.len() >= MAX_PENDING {
+            emit(&pending);
+            pending.clear();
+            deadline = None;
+        } else if !pending.is_empty() && deadline.is_none() {
+            deadline = Some(Instant::now() + flush_interval);
+        }
+    }
+
+    if !pending.is_empty() {
+        emit(&pending);
+    }
+}
+
+/// Remove ANSI escape sequences (colors, cursor movement, hyperlinks) from a line
+fn strip_ansi(line: &str) -> String {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    let ansi = ANSI.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-_]")
+            .unwrap()
+    });
+    ansi.replace_all(line, "").into_owned()
+}
+
+/// RFC 3339 UTC timestamp with millisecond precision
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn format_all(formatter: &mut dyn OutputFormatter, lines: &[&str]) -> Vec<String> {
+        lines
+            .iter()
+            .filter_map(|line| formatter.format_line(line))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(OutputFormat::parse("raw"), Some(OutputFormat::Raw));
+        assert_eq!(OutputFormat::parse("compact"), Some(OutputFormat::Compact));
+        assert_eq!(
+            OutputFormat::parse("json-lines"),
+            Some(OutputFormat::JsonLines)
+        );
+        assert_eq!(
+            OutputFormat::parse("prefixed"),
+            Some(OutputFormat::Prefixed)
+        );
+        assert_eq!(OutputFormat::parse("json"), None);
+    }
+
+    #[test]
+    fn test_parse_output_buffering() {
+        assert_eq!(OutputBuffering::parse("line"), Some(OutputBuffering::Line));
+        assert_eq!(OutputBuffering::parse("full"), Some(OutputBuffering::Full));
+        assert_eq!(OutputBuffering::parse("char"), Some(OutputBuffering::Char));
+        assert_eq!(OutputBuffering::parse("none"), None);
+    }
+
+    /// Hands out its chunks one read at a time, like a pipe the command
+    /// writes to bit by bit
+    struct Chunks(Vec<&'static str>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0).as_bytes();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    /// Records each write separately
+    #[derive(Default)]
+    struct Writes(Vec<String>);
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(String::from_utf8_lossy(buf).to_string());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_copy_buffered() {
+        let chunks = || Chunks(vec!["Testing", "...", "ok\nDone", " in 2s"]);
+        let writes = |buffering| {
+            let mut writes = Writes::default();
+            copy_buffered(chunks(), &mut writes, buffering).unwrap();
+            writes.0.retain(|write| !write.is_empty());
+            writes.0
+        };
+
+        // Partial lines get through as soon as they are read
+        assert_eq!(
+            writes(OutputBuffering::Char),
+            vec!["Testing", "...", "ok\nDone", " in 2s"]
+        );
+        assert_eq!(
+            writes(OutputBuffering::Line),
+            vec!["Testing...ok\n", "Done in 2s"]
+        );
+        assert_eq!(
+            writes(OutputBuffering::Full),
+            vec!["Testing...ok\nDone in 2s"]
+        );
+    }
+
+    #[test]
+    fn test_raw_formatter() {
+        let lines = ["\x1b[32mok\x1b[0m", "", ""];
+        assert_eq!(format_all(&mut RawFormatter, &lines), lines);
+    }
+
+    #[test]
+    fn test_compact_formatter() {
+        let mut formatter = CompactFormatter::new("pre-commit");
+        let lines = [
+            "\x1b[1m\x1b[32m   Compiling\x1b[0m app v0.1.0",
+            "",
+            "  ",
+            "",
+            "\x1b]8;;https://example.com\x07link\x1b]8;;\x07 done  ",
+            "",
+        ];
+        assert_eq!(
+            format_all(&mut formatter, &lines),
+            vec![
+                "[pre-commit]    Compiling app v0.1.0",
+                "[pre-commit]",
+                "[pre-commit] link done",
+                "[pre-commit]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefixed_formatter() {
+        let mut formatter = PrefixedFormatter::new("clippy", false);
+        assert_eq!(
+            format_all(&mut formatter, &["warning: unused", ""]),
+            vec!["[clippy] warning: unused", "[clippy] "]
+        );
+
+        let line = PrefixedFormatter::new("fmt", true)
+            .format_line("ok")
+            .unwrap();
+        assert!(line.starts_with("\x1b["), "{line:?}");
+        assert!(line.ends_with("m[fmt]\x1b[0m ok"), "{line:?}");
+    }
+
+    /// Gives out its chunks one at a time, pausing before each
+    struct SlowReader {
+        chunks: Vec<&'static str>,
+        pause: Duration,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.chunks.is_empty() {
+                return Ok(0);
+            }
+            thread::sleep(self.pause);
+            let chunk = self.chunks.remove(0).as_bytes();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    fn pump_all(reader: impl Read + Send + 'static, flush_interval: Duration) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut formatter = PrefixedFormatter::new("fmt", false);
+        pump(reader, &mut formatter, flush_interval, |line| {
+            lines.push(line.to_string())
+        });
+        lines
+    }
+
+    #[test]
+    fn test_pump_splits_lines() {
+        let reader = SlowReader {
+            chunks: vec!["one\ntw", "o\r\nthree"],
+            pause: Duration::ZERO,
+        };
+        assert_eq!(
+            pump_all(reader, Duration::from_secs(60)),
+            vec!["[fmt] one", "[fmt] two", "[fmt] three"]
+        );
+    }
+
+    #[test]
+    fn test_pump_flushes_partial_lines() {
+        let reader = SlowReader {
+            chunks: vec!["Checking", " done\n"],
+            pause: Duration::from_millis(300),
+        };
+        assert_eq!(
+            pump_all(reader, Duration::from_millis(20)),
+            vec!["[fmt] Checking", "[fmt]  done"]
+        );
+    }
+
+    #[test]
+    fn test_pump_passes_invalid_utf8() {
+        let reader = io::Cursor::new(b"\xff\xfebinary\n\xc3\xa4\n".to_vec());
+        assert_eq!(
+            pump_all(reader, Duration::from_secs(60)),
+            vec!["[fmt] \u{fffd}\u{fffd}binary", "[fmt] \u{e4}"]
+        );
+    }
+
+    #[test]
+    fn test_json_result_line() {
+        let line = json_result_line("lint", 2, CommandStatus::Warning);
+        assert!(
+            line.starts_with(r#"{"command":"lint","exit_code":2,"status":"warning","ts":""#),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_formatter() {
+        let mut formatter = JsonLinesFormatter::new(Stream::Stderr);
+        let line = formatter.format_line("error: \"oops\"").unwrap();
+        assert!(line.starts_with(r#"{"stream":"stderr","line":"error: \"oops\"","ts":"20"#));
+        assert!(line.ends_with("Z\"}"));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(timestamp(time), "2024-02-29T12:34:56.789Z");
+    }
+}
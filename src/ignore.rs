@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::UserConfig;
+use crate::fanout;
+
+/// File at the root of a repository that keeps `add` from installing hooks
+/// to it
+pub const IGNORE_MARKER: &str = ".hookmaster-ignore";
+
+/// Paths and globs of repositories `add` never installs hooks to, from the
+/// `ignore` file next to the user configuration
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IgnoreList {
+    patterns: Vec<String>,
+    /// Home directory that `~` in the patterns stands for
+    home: Option<String>,
+}
+
+impl IgnoreList {
+    /// Location of the ignore list: `ignore` in the directory of the user
+    /// configuration, such as `~/.config/hookmaster/ignore`
+    pub fn path() -> Option<PathBuf> {
+        Some(UserConfig::path()?.with_file_name("ignore"))
+    }
+
+    /// Load the ignore list, which is empty if there is no file
+    pub fn load() -> Result<Self> {
+        let home = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| home.to_string_lossy().to_string());
+        match Self::path().filter(|path| path.is_file()) {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Self::parse(&content, home)
+                    .with_context(|| format!("Failed to parse {}", path.display()))
+            }
+            None => Ok(Self {
+                patterns: Vec::new(),
+                home,
+            }),
+        }
+    }
+
+    /// Parse one absolute path or glob per line. `#` starts a comment line,
+    /// and `~/` stands for `home`.
+    fn parse(content: &str, home: Option<String>) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for (line_num, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !is_absolute(line) {
+                return Err(anyhow!(
+                    "Relative path '{}' on line {}; expected an absolute path or one starting with ~/",
+                    line,
+                    line_num + 1
+                ));
+            }
+            patterns.push(line.to_string());
+        }
+        Ok(Self { patterns, home })
+    }
+
+    /// Check if `add` should leave the repository at `repo` alone: it has an
+    /// [`IGNORE_MARKER`] file, or it or one of its parent directories matches
+    /// a pattern of the list
+    pub fn is_ignored(&self, repo: &Path) -> bool {
+        if repo.join(IGNORE_MARKER).exists() {
+            return true;
+        }
+        let repo = fs::canonicalize(repo).unwrap_or_else(|_| repo.to_path_buf());
+        let repo = repo.to_string_lossy();
+        self.patterns
+            .iter()
+            .any(|pattern| matches(pattern, &repo, self.home.as_deref()))
+    }
+}
+
+/// Check if `pattern` is an absolute path, home-relative or with a drive letter
+fn is_absolute(pattern: &str) -> bool {
+    let bytes = pattern.as_bytes();
+    pattern.starts_with(['/', '\\'])
+        || pattern == "~"
+        || pattern.starts_with("~/")
+        || pattern.starts_with("~\\")
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+/// The segments of `path`, taking both `/` and `\` as separators and
+/// dropping the `\\?\` prefix of canonical Windows paths
+fn segments(path: &str) -> Vec<&str> {
+    let path = path.strip_prefix(r"\\?\").unwrap_or(path);
+    path.split(['/', '\\']).filter(|s| !s.is_empty()).collect()
+}
+
+/// Check if `pattern` matches `path` or one of its parent directories. Each
+/// segment may contain `*` and `?`, `**` matches any number of segments, and
+/// a leading `~` stands for `home`.
+fn matches(pattern: &str, path: &str, home: Option<&str>) -> bool {
+    let mut pattern_segments = Vec::new();
+    if let Some(rest) = pattern.strip_prefix('~') {
+        let Some(home) = home else {
+            return false;
+        };
+        pattern_segments.extend(segments(home));
+        pattern_segments.extend(segments(rest));
+    } else {
+        pattern_segments.extend(segments(pattern));
+    }
+    matches_segments(&pattern_segments, &segments(path))
+}
+
+fn matches_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        // Whatever is left of the path is inside the matched directory
+        None => true,
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => {
+                fanout::wildcard_match(segment, name) && matches_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_matches_absolute_paths() {
+        assert!(matches("/srv/mirrors", "/srv/mirrors/linux", None));
+        assert!(matches("/srv/mirrors/*", "/srv/mirrors/linux", None));
+        assert!(matches("/srv/*/linu?", "/srv/mirrors/linux", None));
+        assert!(matches("/srv/**/linux", "/srv/a/b/linux", None));
+        assert!(matches("/srv/**/linux", "/srv/linux", None));
+        assert!(!matches("/srv/mirrors/*", "/srv/mirrors", None));
+        assert!(!matches("/srv/mirrors", "/srv/mirrors-old/linux", None));
+        assert!(!matches("/srv/*", "/srv/.hidden", None));
+
+        assert!(matches(r"C:\mirrors\*", r"C:\mirrors\linux", None));
+        assert!(matches(r"C:\mirrors\*", r"\\?\C:\mirrors\linux", None));
+        assert!(matches("C:/mirrors/*", r"C:\mirrors\linux", None));
+        assert!(!matches(r"C:\mirrors\*", r"D:\mirrors\linux", None));
+    }
+
+    #[test]
+    fn test_matches_home_relative_paths() {
+        let home = Some("/home/me");
+        assert!(matches("~/mirrors/*", "/home/me/mirrors/linux", home));
+        assert!(matches("~", "/home/me/src/app", home));
+        assert!(!matches("~/mirrors/*", "/home/other/mirrors/linux", home));
+        assert!(!matches("~/mirrors/*", "/home/me/mirrors/linux", None));
+
+        let home = Some(r"C:\Users\me");
+        assert!(matches(r"~\mirrors\*", r"C:\Users\me\mirrors\linux", home));
+        assert!(matches("~/mirrors/*", r"C:\Users\me\mirrors\linux", home));
+    }
+
+    #[test]
+    fn test_parse() {
+        let list = IgnoreList::parse(
+            "# read-only mirrors\n~/mirrors/*\n\n/srv/upstream\n",
+            Some("/home/me".to_string()),
+        )
+        .unwrap();
+        assert_eq!(list.patterns, vec!["~/mirrors/*", "/srv/upstream"]);
+
+        let err = IgnoreList::parse("mirrors/*\n", None).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        for repo in ["mirror", "marked", "app"] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+        }
+        fs::write(root.join("marked").join(IGNORE_MARKER), "").unwrap();
+
+        let list = IgnoreList {
+            patterns: vec![root.join("mirr*").to_string_lossy().to_string()],
+            home: None,
+        };
+        assert!(list.is_ignored(&root.join("mirror")));
+        assert!(list.is_ignored(&root.join("marked")));
+        assert!(!list.is_ignored(&root.join("app")));
+    }
+}
@@ -0,0 +1,269 @@
+//! Importing the hook scripts of Husky's `.husky` directory
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{GitHooksConfig, HookSpec, Settings};
+use crate::git_hooks::GIT_HOOK_NAMES;
+
+/// Directory Husky keeps its hook scripts in
+pub const HUSKY_DIR: &str = ".husky";
+
+/// First words of lines that only work as part of the whole script: control
+/// structures, and commands changing the state the following lines run in
+const STATEFUL_WORDS: [&str; 12] = [
+    "if", "for", "while", "until", "case", "function", "export", "cd", "set", "unset", "source",
+    ".",
+];
+
+/// Reads Husky hook scripts into a hookmaster configuration
+#[derive(Debug)]
+pub struct HuskyImporter;
+
+impl HuskyImporter {
+    /// Read the hook scripts in `husky_dir`. Each line of a script becomes a
+    /// command of the hook, without Husky's boilerplate; scripts with control
+    /// structures or commands such as `cd` and `export` become a single
+    /// command, as their lines depend on each other. `$HUSKY_GIT_PARAMS`
+    /// becomes `{args}`, and as in Husky, commands only get the hook's
+    /// arguments where they refer to them. Files that aren't named after a
+    /// git hook, such as Husky's own `_` directory, are ignored.
+    pub fn import(husky_dir: &Path) -> Result<GitHooksConfig> {
+        if !husky_dir.is_dir() {
+            return Err(anyhow!(
+                "Husky directory not found: {}",
+                husky_dir.display()
+            ));
+        }
+
+        let entries = fs::read_dir(husky_dir)
+            .with_context(|| format!("Failed to read {}", husky_dir.display()))?;
+        let mut hooks = HashMap::new();
+        for entry in entries {
+            let path = entry?.path();
+            let Some(hook_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !GIT_HOOK_NAMES.contains(&hook_name) || !path.is_file() {
+                continue;
+            }
+
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let commands: Vec<String> = script_commands(&content)
+                .iter()
+                .map(|command| with_args_placeholder(command))
+                .collect();
+            let spec = match commands.as_slice() {
+                [] => continue,
+                [command] => HookSpec::from_command(hook_name, command),
+                commands => HookSpec::from_commands(commands.to_vec()),
+            };
+            hooks.insert(hook_name.to_string(), spec);
+        }
+
+        Ok(GitHooksConfig {
+            hooks,
+            settings: Settings {
+                append_args: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    /// Imported commands that rely on Husky itself or on running as the
+    /// hook script, and so may not work under hookmaster
+    pub fn warnings(config: &GitHooksConfig) -> Vec<String> {
+        let mut hook_names: Vec<&String> = config.hooks.keys().collect();
+        hook_names.sort();
+
+        let mut warnings = Vec::new();
+        for hook_name in hook_names {
+            for entry in &config.hooks[hook_name].commands {
+                if let Some(problem) = husky_dependency(&entry.command) {
+                    warnings.push(format!("Hook '{hook_name}': '{}' {problem}", entry.command));
+                }
+            }
+        }
+        warnings
+    }
+}
+
+/// The commands a Husky hook script runs, without the shebang, comments and
+/// the sourcing of `husky.sh`
+fn script_commands(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut pending = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if pending.is_empty() && (line.is_empty() || line.starts_with('#') || sources_husky(line)) {
+            continue;
+        }
+        // A backslash at the end continues the command on the next line
+        match line.strip_suffix('\\') {
+            Some(start) => {
+                pending.push_str(start.trim_end());
+                pending.push(' ');
+            }
+            None => {
+                pending.push_str(line);
+                lines.push(std::mem::take(&mut pending));
+            }
+        }
+    }
+    if !pending.trim().is_empty() {
+        lines.push(pending.trim().to_string());
+    }
+
+    let stateful = lines.iter().any(|line| {
+        let first_word = line.split_whitespace().next().unwrap_or_default();
+        STATEFUL_WORDS.contains(&first_word) || line.ends_with('{') || line.contains("() ")
+    });
+    if stateful {
+        // Husky runs hook scripts with `sh -e`, stopping at the first failure
+        return vec![format!("set -e\n{}", lines.join("\n"))];
+    }
+    lines
+}
+
+/// Check if `line` is the `. "$(dirname "$0")/_/husky.sh"` of Husky 4 to 8
+fn sources_husky(line: &str) -> bool {
+    (line.starts_with(". ") || line.starts_with("source ")) && line.contains("husky.sh")
+}
+
+/// `command` with Husky 4's `$HUSKY_GIT_PARAMS` replaced by `{args}`
+fn with_args_placeholder(command: &str) -> String {
+    [
+        "\"$HUSKY_GIT_PARAMS\"",
+        "${HUSKY_GIT_PARAMS}",
+        "$HUSKY_GIT_PARAMS",
+    ]
+    .iter()
+    .fold(command.to_string(), |command, param| {
+        command.replace(param, "{args}")
+    })
+}
+
+/// Why `command` may not work outside a Husky hook script, if it may not
+fn husky_dependency(command: &str) -> Option<&'static str> {
+    // Such as `commitlint -E HUSKY_GIT_PARAMS`, reading the variable itself
+    if command.contains("HUSKY_GIT_PARAMS") {
+        Some("reads HUSKY_GIT_PARAMS, which hookmaster doesn't set; use {msg_file} or {args}")
+    } else if command.contains("$0") || command.contains("dirname") {
+        Some("refers to the location of the hook script")
+    } else if command.to_lowercase().contains("husky") {
+        Some("refers to Husky, which no longer runs the hooks")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_script_commands() {
+        let husky_8 = "#!/usr/bin/env sh\n. \"$(dirname -- \"$0\")/_/husky.sh\"\n\n# Lint staged files\nnpx lint-staged\n";
+        assert_eq!(script_commands(husky_8), vec!["npx lint-staged"]);
+
+        let husky_9 = "npm test\nnpm run build \\\n  --if-present\n";
+        assert_eq!(
+            script_commands(husky_9),
+            vec!["npm test", "npm run build --if-present"]
+        );
+
+        let stateful = "cd packages/app\nnpm test\n";
+        assert_eq!(
+            script_commands(stateful),
+            vec!["set -e\ncd packages/app\nnpm test"]
+        );
+        let control = "if [ -f yarn.lock ]; then\n  yarn test\nfi\n";
+        assert_eq!(
+            script_commands(control),
+            vec!["set -e\nif [ -f yarn.lock ]; then\nyarn test\nfi"]
+        );
+
+        assert!(script_commands("#!/bin/sh\n# nothing to do\n").is_empty());
+    }
+
+    #[test]
+    fn test_import() {
+        let temp_dir = TempDir::new().unwrap();
+        let husky_dir = temp_dir.path().join(HUSKY_DIR);
+        fs::create_dir_all(husky_dir.join("_")).unwrap();
+        fs::write(husky_dir.join("_/husky.sh"), "#!/usr/bin/env sh\n").unwrap();
+        fs::write(husky_dir.join(".gitignore"), "_\n").unwrap();
+        fs::write(
+            husky_dir.join("pre-commit"),
+            "#!/usr/bin/env sh\n. \"$(dirname -- \"$0\")/_/husky.sh\"\n\nnpx lint-staged\n",
+        )
+        .unwrap();
+        fs::write(husky_dir.join("pre-push"), "npm test\nnpm run build\n").unwrap();
+        fs::write(
+            husky_dir.join("commit-msg"),
+            "npx --no -- commitlint --edit \"$1\"\n",
+        )
+        .unwrap();
+        fs::write(
+            husky_dir.join("prepare-commit-msg"),
+            "node scripts/ticket.js \"$HUSKY_GIT_PARAMS\"\nnpx commitlint -E HUSKY_GIT_PARAMS\n",
+        )
+        .unwrap();
+        fs::write(husky_dir.join("post-merge"), "#!/bin/sh\n").unwrap();
+
+        let config = HuskyImporter::import(&husky_dir).unwrap();
+        let mut hook_names: Vec<&String> = config.hooks.keys().collect();
+        hook_names.sort();
+        assert_eq!(
+            hook_names,
+            vec!["commit-msg", "pre-commit", "pre-push", "prepare-commit-msg"]
+        );
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("npx lint-staged")
+        );
+        let pre_push: Vec<&str> = config.hooks["pre-push"]
+            .commands
+            .iter()
+            .map(|entry| entry.command.as_str())
+            .collect();
+        assert_eq!(pre_push, vec!["npm test", "npm run build"]);
+        assert!(config
+            .to_toml_string()
+            .contains("pre-push = [\"npm test\", \"npm run build\"]"));
+        // Husky scripts only get the arguments they refer to
+        assert!(!config.settings.append_args());
+        assert_eq!(
+            config.hooks["prepare-commit-msg"].commands[0].command,
+            "node scripts/ticket.js {args}"
+        );
+
+        assert_eq!(
+            HuskyImporter::warnings(&config),
+            vec!["Hook 'prepare-commit-msg': 'npx commitlint -E HUSKY_GIT_PARAMS' reads HUSKY_GIT_PARAMS, which hookmaster doesn't set; use {msg_file} or {args}"]
+        );
+
+        let err = HuskyImporter::import(&temp_dir.path().join("missing")).unwrap_err();
+        assert!(err.to_string().starts_with("Husky directory not found"));
+    }
+
+    #[test]
+    fn test_husky_dependency() {
+        assert_eq!(husky_dependency("npx lint-staged"), None);
+        assert_eq!(husky_dependency("npx commitlint --edit \"$1\""), None);
+        assert!(husky_dependency("commitlint -E HUSKY_GIT_PARAMS").is_some());
+        assert_eq!(
+            with_args_placeholder("echo $HUSKY_GIT_PARAMS ${HUSKY_GIT_PARAMS}"),
+            "echo {args} {args}"
+        );
+        assert!(husky_dependency("sh \"$(dirname \"$0\")/check.sh\"")
+            .unwrap()
+            .contains("location of the hook script"));
+        assert!(husky_dependency("npx husky run").unwrap().contains("Husky"));
+    }
+}
@@ -0,0 +1,25 @@
+//! Importing hook configurations from other hook managers
+
+pub mod husky;
+
+pub use husky::HuskyImporter;
+
+/// Hook manager whose configuration `hookmaster config import` reads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    /// Husky's `.husky` directory of hook scripts
+    Husky,
+}
+
+impl ImportFormat {
+    /// Names accepted by `--format`
+    pub const NAMES: [&'static str; 1] = ["husky"];
+
+    /// Parse a `--format` value
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "husky" => Some(ImportFormat::Husky),
+            _ => None,
+        }
+    }
+}
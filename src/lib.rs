@@ -0,0 +1,66 @@
+//! Git hook management: installs hookmaster hook scripts into repositories
+//! and runs the commands configured for them in `hookmaster.toml`.
+//!
+//! ```no_run
+//! use hookmaster::HookManager;
+//!
+//! # fn main() -> Result<(), hookmaster::HookError> {
+//! HookManager::new().run_hook("pre-commit", &[])?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod audit;
+pub mod audit_log;
+mod cgroup;
+pub mod ci;
+pub mod commit_msg;
+pub mod config;
+pub mod config_source;
+mod dag;
+mod diagnostics;
+mod docker;
+mod duplicate_subject;
+mod eject;
+mod env_file;
+mod fanout;
+pub mod formatter;
+pub mod git_hooks;
+pub mod hook_manager;
+mod ignore;
+pub mod importers;
+mod lock;
+pub mod manifest;
+pub mod output;
+mod partially_staged;
+mod placeholder;
+pub mod presets;
+mod process;
+pub mod protect;
+mod push_remote;
+mod rate_limit;
+pub mod registry;
+mod remote;
+mod remote_install;
+mod resolve;
+mod sandbox;
+mod scheduler;
+pub mod select;
+pub mod self_update;
+pub mod shutdown;
+mod stash;
+pub mod suggest;
+mod template;
+mod timings;
+mod tool_paths;
+mod upgrade;
+mod verify;
+mod watch;
+mod wrap;
+
+pub use commit_msg::CommitMessageProcessor;
+pub use config::GitHooksConfig;
+pub use git_hooks::GitHook;
+pub use hook_manager::{HookError, HookManager};
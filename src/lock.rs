@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// File in a repository's `.git` directory that `add` holds while writing
+/// hook files
+pub const LOCK_FILE: &str = "hookmaster.lock";
+
+/// How long `add` waits for another process to release the lock
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// First wait before trying to take a held lock again; each later wait is
+/// twice as long, up to [`MAX_RETRY_DELAY`]
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Longest wait between attempts to take a held lock
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Lock on a repository's hook files, released when dropped
+#[derive(Debug)]
+pub struct InstallLock {
+    path: PathBuf,
+}
+
+impl InstallLock {
+    /// Take the lock of the repository at `repo_path`, waiting up to
+    /// `timeout` while another process holds it. Like the hooks directory,
+    /// a missing `.git` directory is created.
+    pub fn acquire(repo_path: &Path, timeout: Duration) -> Result<Self> {
+        let git_dir = repo_path.join(".git");
+        fs::create_dir_all(&git_dir)
+            .with_context(|| format!("Failed to create {}", git_dir.display()))?;
+        Self::acquire_file(git_dir.join(LOCK_FILE), timeout)
+    }
+
+    /// Take the lock held by creating the file at `path`, waiting up to
+    /// `timeout` while another process holds it
+    pub fn acquire_file(path: PathBuf, timeout: Duration) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let mut delay = FIRST_RETRY_DELAY;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    // The process id helps find who left a stale lock behind
+                    let _ = writeln!(file, "{}", std::process::id());
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(anyhow!(
+                    "Timed out after {}s waiting for {}; another hookmaster add may be running, or remove the file if one was interrupted",
+                    timeout.as_secs(),
+                    path.display()
+                ));
+            }
+            thread::sleep(delay.min(deadline - now));
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn repo() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".git")).unwrap();
+        temp_dir
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let temp_dir = repo();
+        let repo = temp_dir.path();
+        let lock_path = repo.join(".git").join(LOCK_FILE);
+
+        let lock = InstallLock::acquire(repo, Duration::ZERO).unwrap();
+        assert!(lock_path.exists());
+        let err = InstallLock::acquire(repo, Duration::from_millis(120)).unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+
+        drop(lock);
+        assert!(!lock_path.exists());
+        InstallLock::acquire(repo, Duration::ZERO).unwrap();
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_acquire_waits_for_release() {
+        let temp_dir = repo();
+        let repo = temp_dir.path().to_path_buf();
+        let released = Arc::new(AtomicBool::new(false));
+
+        let lock = InstallLock::acquire(&repo, Duration::ZERO).unwrap();
+        let waiter = {
+            let repo = repo.clone();
+            let released = Arc::clone(&released);
+            thread::spawn(move || {
+                let _lock = InstallLock::acquire(&repo, Duration::from_secs(10)).unwrap();
+                // Only gets here once the first lock is gone
+                released.load(Ordering::SeqCst)
+            })
+        };
+        thread::sleep(Duration::from_millis(200));
+        released.store(true, Ordering::SeqCst);
+        drop(lock);
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn test_acquire_creates_git_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let _lock = InstallLock::acquire(temp_dir.path(), Duration::ZERO).unwrap();
+        assert!(temp_dir.path().join(".git").join(LOCK_FILE).exists());
+    }
+}
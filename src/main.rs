@@ -1,12 +1,23 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
-mod commit_msg;
-mod config;
-mod git_hooks;
-mod hook_manager;
-
-use hook_manager::HookManager;
+use hookmaster::ci::{self, CiProvider};
+use hookmaster::config::{GitHooksConfig, HashFormat, UserConfig};
+use hookmaster::formatter::{OutputBuffering, OutputFormat};
+use hookmaster::git_hooks::{Filesystems, Submodules};
+use hookmaster::hook_manager::{ExecutionMode, ForeignHooks, HookFilter, Signal};
+use hookmaster::importers::ImportFormat;
+use hookmaster::output::{ConsoleReporter, NdjsonReporter, Reporter};
+use hookmaster::registry::{self, Registry, Tags};
+use hookmaster::select::{self, KeepPreselection, RepoSelector};
+use hookmaster::self_update::{self, GitHubReleases};
+use hookmaster::shutdown;
+use hookmaster::suggest;
+use hookmaster::HookManager;
 
 const HELP: &str = "\
 hookmaster 0.1.0
@@ -16,34 +27,193 @@ USAGE:
     hookmaster [OPTIONS] <COMMAND> [ARGS]...
 
 OPTIONS:
-    -h, --help       Print help information
-    -V, --version    Print version information
-    -v, --verbose    Enable verbose output
+    -h, --help             Print help information
+    -V, --version          Print version information
+    -v, --verbose          Enable verbose output
+    -c, --config <FILE>    Read the configuration from FILE instead of searching for it
+                           (also set by the HOOKMASTER_CONFIG environment variable)
+
+Options can be given before or after the command.
 
 COMMANDS:
     add                 Add hookmaster hooks to all projects under the specified path
-    init                Initialize current repository with sample githooks.toml
+    init                Initialize current repository with sample hookmaster.toml
     run                 Run a specific hook command
+    watch               Run a hook again each time files change
+    list                List configured hooks and where the configuration came from
+    hook-script         Print the script 'add' installs for a hook
+    audit               Check hook scripts and commands for common security issues
+    diff-hooks          Compare the hook scripts installed in two repositories
+    eject               Write standalone hook scripts that don't need hookmaster
+    uninstall           Remove hookmaster hooks from all projects under the specified path
+    doctor              Check that the configuration and installed hooks are in order
+    verify              Check that every project's configured hooks are installed and current
     prepare-commit-msg  Process prepare-commit-msg hook
+    validate            Check the configuration for problems
+    upgrade-config      Move top-level hooks of the configuration into [hooks] tables
+    config              Manage the configuration, e.g. import it from Husky
+    ci                  Generate a CI workflow running the same hooks
+    registry            List or clean up the repositories 'add --tag' recorded
+    stats               Summarize how long hook commands took and how often they failed
+    version             Print the version, or check whether a newer one is out
+    self-update         Replace this hookmaster with the latest release
 
 Use 'hookmaster <command> --help' for more information on a specific command.
 ";
 
 const VERSION: &str = "hookmaster 0.1.0";
 
+/// Names of the commands, for suggestions when one is mistyped
+const COMMAND_NAMES: [&str; 21] = [
+    "add",
+    "init",
+    "run",
+    "watch",
+    "list",
+    "audit",
+    "diff-hooks",
+    "eject",
+    "hook-script",
+    "uninstall",
+    "doctor",
+    "verify",
+    "prepare-commit-msg",
+    "validate",
+    "upgrade-config",
+    "config",
+    "ci",
+    "registry",
+    "stats",
+    "version",
+    "self-update",
+];
+
+/// "Did you mean" hint for a mistyped command, with a leading blank line
+fn command_suggestion(command: &str) -> String {
+    suggest::closest(command, COMMAND_NAMES)
+        .map(|name| format!("\n\nDid you mean '{name}'?"))
+        .unwrap_or_default()
+}
+
+#[derive(Debug, PartialEq)]
 enum Command {
     Add {
-        path: PathBuf,
+        paths: Vec<PathBuf>,
+        stdin: bool,
+        ndjson: bool,
+        rate_limit: Option<f64>,
+        lock_timeout: Option<u64>,
+        link_config: Option<PathBuf>,
+        config_file: Option<PathBuf>,
+        interactive: bool,
+        yes: bool,
+        only_configured: bool,
+        foreign_hooks: ForeignHooks,
+        dry_run: bool,
+        verify: bool,
+        post_install_hook: Option<PathBuf>,
+        tags: Tags,
+        no_ignore: bool,
+        repos_file: Option<PathBuf>,
+        filesystems: Filesystems,
+        submodules: Submodules,
+    },
+    AddTemplate,
+    AddRemote {
+        url: String,
+    },
+    Init {
+        legacy_name: bool,
+        preset: Option<String>,
+        list_presets: bool,
     },
-    Init,
     Run {
         hook_name: String,
         args: Vec<String>,
+        dry_run: bool,
+        format: Option<OutputFormat>,
+        output_buffering: Option<OutputBuffering>,
+        input_file: Option<PathBuf>,
+        files: Option<Vec<PathBuf>>,
+        assert_exit: Option<i32>,
+        stash_unstaged: bool,
+        timeout_signal: Option<Signal>,
+        env_files: Vec<PathBuf>,
+        env_file_override: bool,
+    },
+    Watch {
+        hook_name: String,
     },
     PrepareCommitMsg {
-        commit_msg_file: PathBuf,
+        commit_msg_file: Option<PathBuf>,
         commit_source: Option<String>,
         commit_sha: Option<String>,
+        branch: Option<String>,
+        test_branch: Option<String>,
+    },
+    Validate {
+        refresh: bool,
+    },
+    UpgradeConfig {
+        check: bool,
+        force: bool,
+    },
+    RegistryList {
+        tags: Tags,
+    },
+    RegistryClean,
+    Stats {
+        hook: Option<String>,
+        since: Option<Duration>,
+        json: bool,
+    },
+    Version {
+        check: bool,
+    },
+    SelfUpdate,
+    ConfigImport {
+        format: ImportFormat,
+        dir: Option<PathBuf>,
+        legacy_name: bool,
+    },
+    ConfigHash {
+        format: HashFormat,
+    },
+    CiGenerate {
+        provider: CiProvider,
+        force: bool,
+        print: bool,
+    },
+    List {
+        filter: HookFilter,
+    },
+    Audit {
+        path: PathBuf,
+        filesystems: Filesystems,
+        submodules: Submodules,
+    },
+    Eject {
+        hooks_path: Option<PathBuf>,
+    },
+    HookScript {
+        hook_name: Option<String>,
+    },
+    Uninstall {
+        path: PathBuf,
+        filesystems: Filesystems,
+        submodules: Submodules,
+    },
+    UninstallTemplate,
+    Doctor,
+    Verify {
+        path: PathBuf,
+        fix: bool,
+        filesystems: Filesystems,
+        submodules: Submodules,
+    },
+    DiffHooks {
+        repo1: PathBuf,
+        repo2: PathBuf,
     },
 }
 
@@ -51,21 +221,85 @@ fn print_help_for_command(command: &str) {
     match command {
         "add" => println!(
             "\
-Add hookmaster hooks to all projects under the specified path
+Add hookmaster hooks to all projects under the specified paths
 
 USAGE:
-    hookmaster add <PATH>
+    hookmaster add [OPTIONS] <PATH>...
+    hookmaster add [OPTIONS] --stdin
+    hookmaster add [OPTIONS] --repos-file <FILE>
+    hookmaster add --template
+    hookmaster add --remote <URL>
 
 ARGS:
-    <PATH>    Path to add hooks to (searches recursively for git repositories)
+    <PATH>...    Paths to add hooks to (searches recursively for git repositories).
+                 - reads more paths from stdin, like --stdin
+
+OPTIONS:
+    --stdin                       Read paths to add hooks to from stdin, one per line
+    --template                    Install the hooks into git's template directory
+                                  (init.templateDir, set to ~/.config/hookmaster/git-template
+                                  if unset), so every future clone and git init gets them
+    --remote <URL>                Install the server-side hooks (pre-receive, update and
+                                  post-receive) to the repository of the SSH remote URL,
+                                  e.g. git@example.com:org/repo.git, with ssh and scp
+    --ndjson                      Print one JSON object per processed repository
+                                  instead of human-readable progress
+    --rate-limit <OPS_PER_SEC>    Write at most this many hook files per second, for
+                                  slow network filesystems (default: unlimited)
+    --lock-timeout <SECONDS>      How long to wait for another hookmaster add writing the
+                                  same repository's hooks to finish (default: 30)
+    --link-config <FILE>          Link each project's hookmaster.toml to FILE, a shared
+                                  configuration, so changes to FILE apply everywhere
+    --config-file <FILE>          Copy the configuration in FILE to each project's
+                                  hookmaster.toml and also install the hooks it defines
+    --interactive                 Choose which of the discovered repositories get hooks;
+                                  those with a hookmaster.toml or githooks.toml are preselected
+    -y, --yes                     With --interactive, install to the preselected
+                                  repositories without asking; otherwise install even when
+                                  more repositories are found than confirm-threshold under
+                                  [add] in the user configuration (default: 25), which
+                                  asks for confirmation or, without a terminal, fails
+    --only-configured             Only add hooks to projects with a configuration of their
+                                  own, and only the hooks it configures (default from
+                                  only-configured under [add] in the user configuration)
+    --force                       Replace hook scripts installed by Husky, the pre-commit
+                                  framework or lefthook, which add otherwise refuses
+    --chain                       Keep existing hook scripts as <hook>.chained and run
+                                  them before hookmaster's commands
+    --dry-run                     Print each file add would write or skip, and how many
+                                  hooks it would install, without writing anything
+    --verify                      Run each installed hook with 'hookmaster run --dry-run'
+                                  and fail if one of them can't find hookmaster or load the
+                                  configuration (server-side hooks are skipped)
+    --post-install-hook <SCRIPT>  Run SCRIPT with the repository path and the installed
+                                  hooks, separated by commas, after installing hooks to
+                                  each repository; failures are reported as warnings
+    --tag <KEY=VALUE>             Record each repository hooks were installed to in the
+                                  registry with this tag, e.g. --tag team=backend; may
+                                  be repeated. See 'hookmaster registry --help'
+    --all-filesystems             Also search directories on other filesystems mounted
+                                  under the paths, such as network mounts and external
+                                  drives, which are skipped by default
+    --include-submodules          Also search the submodules listed in the .gitmodules of
+                                  a repository given as a path, which are skipped by default
+    --repos-file <FILE>           Add hooks to the repositories listed in FILE, one path per
+                                  line (# starts a comment), without searching under them
+    --no-ignore                   Also add hooks to repositories on the ignore list
+                                  (~/.config/hookmaster/ignore) or with a .hookmaster-ignore file
 "
         ),
         "init" => println!(
             "\
-Initialize current repository with sample githooks.toml
+Initialize current repository with sample hookmaster.toml
 
 USAGE:
-    hookmaster init
+    hookmaster init [OPTIONS]
+
+OPTIONS:
+    --legacy-name      Name the file githooks.toml instead of hookmaster.toml
+    --preset <NAME>    Start from a preset: rust, node, python, or a custom
+                       preset from ~/.hookmaster/presets/<NAME>.toml
+    --list-presets     List the available presets
 "
         ),
         "run" => println!(
@@ -73,11 +307,70 @@ USAGE:
 Run a specific hook command
 
 USAGE:
-    hookmaster run <HOOK_NAME> [ARGS]...
+    hookmaster run [OPTIONS] <HOOK_NAME> [ARGS]...
+    hookmaster run [OPTIONS] <HOOK_NAME> --files <FILE>... [-- <ARGS>...]
 
 ARGS:
     <HOOK_NAME>    Hook name to run (e.g., pre-commit, commit-msg, etc.)
-    [ARGS]...      Additional arguments to pass to the hook
+    [ARGS]...      Additional arguments to pass to the hook; arguments after
+                   -- are passed as they are, even if they look like options
+
+Set strict = true in the [run] section of the user configuration
+(~/.config/hookmaster/config.toml, %APPDATA%\\hookmaster\\config.toml on Windows)
+or HOOKMASTER_STRICT=1 to fail when there is no configuration or the hook has
+no command.
+
+OPTIONS:
+    --dry-run            Print the commands that would run without running them
+    --input-file <PATH>  Give the commands the content of PATH as stdin, e.g.
+                         the ref updates a pre-receive hook reads
+    --files <FILE>...    Check these files instead of the staged ones, in
+                         only_changed directories and {{files}} placeholders.
+                         The arguments up to -- are the files.
+    --format <FORMAT>    How to print the output of the commands:
+                         raw: unchanged
+                         compact: without ANSI codes or repeated blank lines,
+                           each line prefixed with [<HOOK_NAME>]
+                         json-lines: one JSON object per line with the stream,
+                           the line and a timestamp
+                         prefixed: each line prefixed with the name of the
+                           command that printed it
+                         Defaults to prefixed for hooks running several
+                         commands in parallel and raw otherwise.
+    --output-buffering <STRATEGY>
+                         When to print the output of the commands:
+                         line: each line as soon as it is complete (default)
+                         full: all of a command's output when it has finished
+                         char: everything as soon as it is written, even
+                           without a newline, such as progress dots
+    --assert-exit <CODE> Succeed only if the hook exits with CODE, e.g. 1 to test
+                         that a commit-msg hook rejects a bad message. Prints
+                         [PASS] or [FAIL] with the exit code.
+    --stash-unstaged     Stash unstaged changes while the hook runs, so that it
+                         checks the files as they will be committed
+    --timeout-signal <SIGNAL>
+                         Signal sent to commands that run past their timeout:
+                         term (default), kill, int, quit or a signal number.
+                         Commands still running timeout-kill-grace seconds
+                         later (5 by default) are killed.
+    --env-file <PATH>    Give the commands the variables of the dotenv file
+                         PATH, such as .env.local; may be repeated, later files
+                         winning. Variables already set are kept.
+    --env-file-override  Let the --env-file variables replace those already set
+"
+        ),
+        "watch" => println!(
+            "\
+Run a hook, then run it again each time files in the working tree change, until
+Ctrl-C. Files git ignores and the .git, target and node_modules directories are
+not watched. Each run starts on a cleared screen and ends with a [PASS] or
+[FAIL] line naming the files whose changes started it.
+
+USAGE:
+    hookmaster watch <HOOK_NAME>
+
+ARGS:
+    <HOOK_NAME>    Hook name to run (e.g., pre-commit)
 "
         ),
         "prepare-commit-msg" => println!(
@@ -86,23 +379,426 @@ Process prepare-commit-msg hook
 
 USAGE:
     hookmaster prepare-commit-msg <COMMIT_MSG_FILE> [COMMIT_SOURCE] [COMMIT_SHA]
+    hookmaster prepare-commit-msg --branch <BRANCH> <COMMIT_MSG_FILE> [COMMIT_SOURCE] [COMMIT_SHA]
+    hookmaster prepare-commit-msg --test-branch <BRANCH> [-]
 
 ARGS:
     <COMMIT_MSG_FILE>    Path to the commit message file
     [COMMIT_SOURCE]      Commit source (optional)
     [COMMIT_SHA]         SHA1 of the commit (optional)
+
+OPTIONS:
+    --branch <BRANCH>         Process the commit message file as if committing on
+                              BRANCH instead of the current branch, without asking git
+    --test-branch <BRANCH>    Print the message commits on BRANCH would start with,
+                              e.g. for feature/JIRA-999-my-work, without changing any
+                              file. The commit message file may be left out or given as -
+"
+        ),
+        "list" => println!(
+            "\
+List configured hooks and where the configuration was loaded from
+
+Configuration is read from the first of these that has it: hookmaster.toml,
+githooks.toml, [package.metadata.hookmaster] in Cargo.toml, \"hookmaster\" in
+package.json, [tool.hookmaster] in pyproject.toml
+
+USAGE:
+    hookmaster list [OPTIONS]
+
+OPTIONS:
+    --installed        Only hooks whose script is installed in .git/hooks
+    --not-installed    Only hooks whose script is not installed
+    --stale            Only hooks whose installed script differs from the one
+                       'hookmaster add' writes
+
+The options can be combined; a hook is listed if it matches all of them.
+"
+        ),
+        "audit" => println!(
+            "\
+Check the hookmaster-managed hook scripts of all repositories under PATH, and
+the commands configured for them, for common security issues: code downloaded
+at runtime, eval of variable input, writes outside the repository and
+world-writable directories on PATH
+
+Exits with an error if any high-severity issue is found.
+
+USAGE:
+    hookmaster audit [OPTIONS] [PATH]
+
+ARGS:
+    [PATH]    Path to audit (searches recursively for git repositories) [default: .]
+
+OPTIONS:
+    --all-filesystems    Also search directories on other filesystems mounted under
+                         PATH, such as network mounts and external drives
+    --include-submodules Also search the submodules listed in the .gitmodules of
+                         PATH, which are skipped by default
+"
+        ),
+        "diff-hooks" => println!(
+            "\
+Compare the hook scripts installed in the .git/hooks directories of two
+repositories, printing a unified diff for each script that differs or is
+installed in only one of them. Git's sample scripts are left out.
+
+USAGE:
+    hookmaster diff-hooks <REPO1> <REPO2>
+
+ARGS:
+    <REPO1>    Repository whose scripts are the old side of the diff
+    <REPO2>    Repository whose scripts are the new side of the diff
+"
+        ),
+        "eject" => println!(
+            "\
+Write standalone POSIX shell scripts running the configured commands into
+.git/hooks, for repositories whose contributors may not have hookmaster
+installed. Features that need hookmaster, such as commit message formatting,
+are reported as warnings. The scripts do nothing when HOOKMASTER_SKIP=1.
+
+USAGE:
+    hookmaster eject [OPTIONS]
+
+OPTIONS:
+    --hooks-path <DIR>    Write the scripts to DIR instead of .git/hooks
+"
+        ),
+        "hook-script" => println!(
+            "\
+Print the script 'hookmaster add' installs for a hook to stdout, for tools that
+manage hook files themselves. Names that aren't standard hooks get the script
+that runs the hook's configured commands.
+
+USAGE:
+    hookmaster hook-script <HOOK_NAME>
+    hookmaster hook-script --all
+
+ARGS:
+    <HOOK_NAME>    Name of the hook, e.g. pre-commit
+
+OPTIONS:
+    --all    Print the scripts of all hooks 'add' installs, each after a
+             '==> <HOOK_NAME> <==' header line
+"
+        ),
+        "uninstall" => println!(
+            "\
+Remove the hook scripts written by 'hookmaster add' from all projects under the
+specified path. Hooks that hookmaster didn't write are left alone.
+
+USAGE:
+    hookmaster uninstall [OPTIONS] [PATH]
+    hookmaster uninstall --template
+
+ARGS:
+    [PATH]    Path to remove hooks from (searches recursively for git repositories) [default: .]
+
+OPTIONS:
+    --template           Remove the hooks from git's template directory instead, and
+                         unset init.templateDir if 'hookmaster add --template' set it
+    --all-filesystems    Also search directories on other filesystems mounted under
+                         PATH, such as network mounts and external drives
+    --include-submodules Also search the submodules listed in the .gitmodules of
+                         PATH, which are skipped by default
+"
+        ),
+        "doctor" => println!(
+            "\
+Check that the configuration loads, that the current repository's hooks are
+installed and current, and that the git template directory's hooks are current
+
+Exits with an error if any problem is found.
+
+USAGE:
+    hookmaster doctor
+"
+        ),
+        "verify" => println!(
+            "\
+Check every project under the specified path: each hook its configuration
+needs must have hookmaster's current, executable script, and hookmaster must be
+on PATH for the scripts to run it
+
+Exits with an error listing each problem and how to fix it.
+
+USAGE:
+    hookmaster verify [OPTIONS] [PATH]
+
+ARGS:
+    [PATH]    Path to check (searches recursively for git repositories) [default: .]
+
+OPTIONS:
+    --fix                Install missing and outdated scripts and make scripts
+                         executable; scripts edited by hand or installed by other
+                         tools are only reported
+    --all-filesystems    Also search directories on other filesystems mounted under
+                         PATH, such as network mounts and external drives
+    --include-submodules Also search the submodules listed in the .gitmodules of
+                         PATH, which are skipped by default
+"
+        ),
+        "upgrade-config" => println!(
+            "\
+Upgrade the configuration file to the structured format: hooks defined as
+top-level keys, such as pre-commit = \"cargo fmt --check\", move into
+[hooks.<hook>] tables of named commands. Comments above a hook move with it.
+Prints the change as a diff.
+
+USAGE:
+    hookmaster upgrade-config [OPTIONS]
+
+OPTIONS:
+    --check    Don't write anything; exit with an error if the file would change
+    --force    Upgrade a file that already has [hooks] tables, moving its
+               remaining top-level hooks
+"
+        ),
+        "stats" => println!(
+            "\
+Summarize the hook timings recorded in .git/hookmaster-timings.jsonl: for each
+command, how often it ran and failed, its median (P50) and 95th percentile (P95)
+durations, and the time spent in it altogether. Hooks record a timing for each
+command they run unless record_timings = false is set under [run].
+
+USAGE:
+    hookmaster stats [OPTIONS]
+
+OPTIONS:
+    --hook <HOOK>    Only include the commands of HOOK, e.g. pre-commit
+    --since <AGE>    Only include timings from the last AGE, a number followed
+                     by s, m, h, d or w, e.g. 30d
+    --json           Print the statistics as a JSON array
+"
+        ),
+        "version" => println!(
+            "\
+Print the version of hookmaster
+
+USAGE:
+    hookmaster version [OPTIONS]
+
+OPTIONS:
+    --check    Ask GitHub whether a newer release is out, and only report that
+
+add, init, list and doctor also check once a day and print a notice when a newer
+release is out; set HOOKMASTER_NO_UPDATE_CHECK=1 to turn that off.
+"
+        ),
+        "self-update" => println!(
+            "\
+Replace this hookmaster with the latest GitHub release for this platform. The
+download is checked against the release's SHA256SUMS before anything is
+replaced.
+
+USAGE:
+    hookmaster self-update
+"
+        ),
+        "config" => println!(
+            "\
+Manage the configuration
+
+USAGE:
+    hookmaster config import --format <FORMAT> [OPTIONS]
+    hookmaster config hash [--format <FORMAT>]
+
+COMMANDS:
+    import    Create hookmaster.toml from another hook manager's hooks, then check it
+              and warn about commands that may need changes
+    hash      Print a hash of the effective configuration, including what it extends
+              and includes, which changes only when the configuration does
+
+OPTIONS:
+    --format <FORMAT>    import: hook manager to import from: husky (reads .husky/<hook> scripts)
+                         hash: sha256 or md5 [default: sha256]
+    --dir <DIR>          Directory to read the hooks from [default: .husky]
+    --legacy-name        Name the file githooks.toml instead of hookmaster.toml
+"
+        ),
+        "registry" => println!(
+            "\
+List or clean up the registry of repositories 'hookmaster add --tag' installed
+hooks to, kept in registry.json next to the user configuration
+(~/.config/hookmaster/registry.json, %APPDATA%\\hookmaster\\registry.json on Windows)
+
+USAGE:
+    hookmaster registry list [--tag <KEY=VALUE>]...
+    hookmaster registry clean
+
+COMMANDS:
+    list     Print each recorded repository with its tags, hooks and install time
+    clean    Remove the entries of repositories that no longer exist
+
+OPTIONS:
+    --tag <KEY=VALUE>    Only list repositories with this tag; may be repeated
+"
+        ),
+        "ci" => println!(
+            "\
+Generate a CI workflow that runs the configured pre-commit and pre-push hooks
+on pull requests, one job for each, so CI checks the same as the local hooks
+
+USAGE:
+    hookmaster ci generate <PROVIDER> [OPTIONS]
+
+ARGS:
+    <PROVIDER>    github: writes .github/workflows/hookmaster.yml
+                  gitlab: writes .gitlab/hookmaster.yml for .gitlab-ci.yml to include
+
+OPTIONS:
+    --force    Overwrite an existing workflow file
+    --print    Print the workflow instead of writing it
+"
+        ),
+        "validate" => println!(
+            "\
+Check the configuration for problems such as unknown command dependencies and
+dependency cycles between named commands
+
+USAGE:
+    hookmaster validate [OPTIONS]
+
+OPTIONS:
+    --refresh    Fetch the configuration named by 'extends' again instead of
+                 using the copy cached in .git
 "
         ),
         _ => {
-            eprintln!("Unknown command: {command}");
+            eprintln!("Unknown command: {command}{}", command_suggestion(command));
             eprintln!("Run 'hookmaster --help' for usage information.");
         }
     }
 }
 
-#[allow(clippy::type_complexity)]
-fn parse_args() -> Result<(bool, Command)> {
-    let mut args = pico_args::Arguments::from_env();
+/// Options that apply to every command
+#[derive(Debug, PartialEq)]
+struct GlobalOptions {
+    verbose: bool,
+    /// Configuration file given with `--config`
+    config: Option<PathBuf>,
+}
+
+/// The global options and the command of a command line
+type ParsedArgs = (GlobalOptions, Command);
+
+/// Fail if arguments are left over after parsing those of `subcommand`,
+/// including any after `--`, which only `run` and `add` take
+fn finish(subcommand: &str, args: pico_args::Arguments, trailing: &[OsString]) -> Result<()> {
+    let unexpected: Vec<String> = args
+        .finish()
+        .iter()
+        .chain(trailing)
+        .map(|s| s.to_string_lossy().to_string())
+        .collect();
+    if unexpected.is_empty() {
+        return Ok(());
+    }
+    Err(unexpected_arguments(subcommand, &unexpected))
+}
+
+/// Error for arguments `subcommand` doesn't take
+fn unexpected_arguments(subcommand: &str, unexpected: &[String]) -> anyhow::Error {
+    anyhow!(
+        "Unexpected argument(s) for '{}': {}\n\nFor more information try 'hookmaster {} --help'",
+        subcommand,
+        unexpected.join(", "),
+        subcommand
+    )
+}
+
+/// Paths given one per line, ignoring blank lines and surrounding whitespace
+fn read_paths(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line.context("Failed to read paths from stdin")?;
+        let line = line.trim();
+        if !line.is_empty() {
+            paths.push(PathBuf::from(line));
+        }
+    }
+    Ok(paths)
+}
+
+/// Filesystems to search for repositories: only the starting path's unless
+/// `--all-filesystems` is given
+fn filesystems(args: &mut pico_args::Arguments) -> Filesystems {
+    if args.contains("--all-filesystems") {
+        Filesystems::All
+    } else {
+        Filesystems::One
+    }
+}
+
+/// Whether to search the submodules of a repository: not unless
+/// `--include-submodules` is given
+fn submodules(args: &mut pico_args::Arguments) -> Submodules {
+    if args.contains("--include-submodules") {
+        Submodules::Include
+    } else {
+        Submodules::Exclude
+    }
+}
+
+/// Tags given with `--tag KEY=VALUE`, which may be repeated
+fn tags(args: &mut pico_args::Arguments) -> Result<Tags> {
+    args.values_from_str::<_, String>("--tag")?
+        .iter()
+        .map(|tag| registry::parse_tag(tag))
+        .collect()
+}
+
+/// Parse an age such as `30d` for `stats --since`: a number followed by
+/// `s`, `m`, `h`, `d` or `w`
+fn parse_age(value: &str) -> Result<Duration> {
+    let invalid = || {
+        anyhow!(
+            "Invalid value for --since: '{value}' (expected a number followed by s, m, h, d or w, e.g. 30d)"
+        )
+    };
+    let unit_start = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let (count, unit) = value.split_at(unit_start);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(count.saturating_mul(seconds)))
+}
+
+/// Read the repositories of `add --repos-file`, one per line, skipping
+/// blank lines and `#` comments
+fn read_repo_list(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    let mut repos = Vec::new();
+    for line in reader.lines() {
+        let line = line.context("Failed to read repository list")?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            repos.push(PathBuf::from(line));
+        }
+    }
+    Ok(repos)
+}
+
+fn parse_args() -> Result<ParsedArgs> {
+    parse_args_from(std::env::args_os().skip(1).collect())
+}
+
+/// Parse the command line arguments `raw`, without the program name. Global
+/// options are accepted anywhere before `--`, and command options anywhere
+/// after the command.
+fn parse_args_from(raw: Vec<OsString>) -> Result<ParsedArgs> {
+    // Arguments after `--` are taken as they are
+    let (raw, trailing) = match raw.iter().position(|arg| arg == "--") {
+        Some(index) => (raw[..index].to_vec(), raw[index + 1..].to_vec()),
+        None => (raw, Vec::new()),
+    };
+    let mut args = pico_args::Arguments::from_vec(raw);
 
     // Handle version
     if args.contains(["-V", "--version"]) {
@@ -123,149 +819,1548 @@ fn parse_args() -> Result<(bool, Command)> {
 
     // Parse verbose flag
     let verbose = args.contains(["-v", "--verbose"]);
+    let config: Option<PathBuf> = args.opt_value_from_os_str(["-c", "--config"], |s| {
+        Ok::<_, std::convert::Infallible>(PathBuf::from(s))
+    })?;
 
-    // Get the subcommand
-    let subcommand: String = match args.free_from_str() {
-        Ok(cmd) => cmd,
-        Err(_) => {
-            return Err(anyhow!(
-                "No command specified. Run 'hookmaster --help' for usage information."
-            ));
+    // Get the subcommand; its options must come after it
+    let subcommand: String = match args.subcommand()? {
+        Some(cmd) => cmd,
+        None => {
+            let remaining = args.finish();
+            return Err(match remaining.first() {
+                Some(option) => anyhow!(
+                    "Unexpected argument '{}' before the command. Options of a command go after it.\n\nFor more information try --help",
+                    option.to_string_lossy()
+                ),
+                None => anyhow!(
+                    "No command specified. Run 'hookmaster --help' for usage information."
+                ),
+            });
         }
     };
 
+    // `add --remote` installs to a server and takes no other options
+    let remote_url: Option<String> = match subcommand.as_str() {
+        "add" => args.opt_value_from_str("--remote")?,
+        _ => None,
+    };
     let command = match subcommand.as_str() {
+        "add" if remote_url.is_some() => {
+            finish(&subcommand, args, &trailing)?;
+            Command::AddRemote {
+                url: remote_url.unwrap_or_default(),
+            }
+        }
+        "add" if args.contains("--template") => {
+            finish(&subcommand, args, &trailing)?;
+            Command::AddTemplate
+        }
         "add" => {
-            let path: String = args.free_from_str().map_err(|_| {
-                anyhow!("Missing required argument: PATH\n\nFor more information try --help")
-            })?;
-            // Check for unexpected arguments for add command
+            let ndjson = args.contains("--ndjson");
+            let rate_limit = match args.opt_value_from_str::<_, String>("--rate-limit")? {
+                Some(value) => Some(
+                    value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| rate.is_finite() && *rate > 0.0)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Invalid value for --rate-limit: '{value}' (expected a positive number of operations per second)"
+                            )
+                        })?,
+                ),
+                None => None,
+            };
+            let lock_timeout = match args.opt_value_from_str::<_, String>("--lock-timeout")? {
+                Some(value) => Some(value.parse::<u64>().map_err(|_| {
+                    anyhow!(
+                        "Invalid value for --lock-timeout: '{value}' (expected a number of seconds)"
+                    )
+                })?),
+                None => None,
+            };
+            let link_config: Option<PathBuf> = args.opt_value_from_str("--link-config")?;
+            let config_file: Option<PathBuf> = args.opt_value_from_str("--config-file")?;
+            if link_config.is_some() && config_file.is_some() {
+                return Err(anyhow!(
+                    "--config-file cannot be combined with --link-config\n\nFor more information try --help"
+                ));
+            }
+            let only_configured = args.contains("--only-configured");
+            if only_configured && (link_config.is_some() || config_file.is_some()) {
+                return Err(anyhow!(
+                    "--only-configured cannot be combined with --link-config or --config-file\n\nFor more information try --help"
+                ));
+            }
+            let foreign_hooks = match (args.contains("--force"), args.contains("--chain")) {
+                (true, true) => {
+                    return Err(anyhow!(
+                        "--force cannot be combined with --chain\n\nFor more information try --help"
+                    ))
+                }
+                (true, false) => ForeignHooks::Replace,
+                (false, true) => ForeignHooks::Chain,
+                (false, false) => ForeignHooks::Refuse,
+            };
+            let dry_run = args.contains("--dry-run");
+            let interactive = args.contains("--interactive");
+            if dry_run && interactive {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --interactive\n\nFor more information try --help"
+                ));
+            }
+            if dry_run && ndjson {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --ndjson\n\nFor more information try --help"
+                ));
+            }
+            let verify = args.contains("--verify");
+            if dry_run && verify {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --verify\n\nFor more information try --help"
+                ));
+            }
+            let post_install_hook: Option<PathBuf> =
+                args.opt_value_from_str("--post-install-hook")?;
+            if dry_run && post_install_hook.is_some() {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --post-install-hook\n\nFor more information try --help"
+                ));
+            }
+            let tags = tags(&mut args)?;
+            if dry_run && !tags.is_empty() {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --tag\n\nFor more information try --help"
+                ));
+            }
+            let no_ignore = args.contains("--no-ignore");
+            let repos_file: Option<PathBuf> = args.opt_value_from_str("--repos-file")?;
+            let filesystems = filesystems(&mut args);
+            let submodules = submodules(&mut args);
+            let yes = args.contains(["-y", "--yes"]);
+            let mut stdin = args.contains("--stdin");
+            // The remaining arguments and any after `--` are paths; `-` stands for stdin
+            let mut paths = Vec::new();
+            let mut unexpected = Vec::new();
             let remaining = args.finish();
-            if !remaining.is_empty() {
-                let unexpected: Vec<String> = remaining
-                    .into_iter()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .collect();
+            for arg in &remaining {
+                if arg == "-" {
+                    stdin = true;
+                } else if arg.to_string_lossy().starts_with('-') {
+                    unexpected.push(arg.to_string_lossy().to_string());
+                } else {
+                    paths.push(PathBuf::from(arg));
+                }
+            }
+            if !unexpected.is_empty() {
+                return Err(unexpected_arguments(&subcommand, &unexpected));
+            }
+            paths.extend(trailing.iter().map(PathBuf::from));
+            if repos_file.is_some() {
+                if !paths.is_empty() || stdin {
+                    return Err(anyhow!(
+                        "--repos-file cannot be combined with PATH or --stdin\n\nFor more information try --help"
+                    ));
+                }
+                for (given, option) in [
+                    (interactive, "--interactive"),
+                    (config_file.is_some(), "--config-file"),
+                    (dry_run, "--dry-run"),
+                ] {
+                    if given {
+                        return Err(anyhow!(
+                            "--repos-file cannot be combined with {option}\n\nFor more information try --help"
+                        ));
+                    }
+                }
+            } else if paths.is_empty() && !stdin {
+                return Err(anyhow!(
+                    "Missing required argument: PATH\n\nFor more information try --help"
+                ));
+            }
+            // The paths use up stdin, leaving nothing to answer the prompt with
+            if interactive && stdin && !yes {
                 return Err(anyhow!(
-                    "Unexpected argument(s): {}\n\nFor more information try --help",
-                    unexpected.join(", ")
+                    "--interactive can't prompt when paths are read from stdin; add --yes to install to the preselected repositories\n\nFor more information try --help"
                 ));
             }
             Command::Add {
-                path: PathBuf::from(path),
+                paths,
+                stdin,
+                ndjson,
+                rate_limit,
+                lock_timeout,
+                link_config,
+                config_file,
+                interactive,
+                yes,
+                only_configured,
+                foreign_hooks,
+                dry_run,
+                verify,
+                post_install_hook,
+                tags,
+                no_ignore,
+                repos_file,
+                filesystems,
+                submodules,
             }
         }
         "init" => {
-            // Check for unexpected arguments for init command
-            let remaining = args.finish();
-            if !remaining.is_empty() {
-                let unexpected: Vec<String> = remaining
-                    .into_iter()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .collect();
-                return Err(anyhow!(
-                    "Unexpected argument(s): {}\n\nFor more information try --help",
-                    unexpected.join(", ")
-                ));
+            let legacy_name = args.contains("--legacy-name");
+            let list_presets = args.contains("--list-presets");
+            let preset: Option<String> = args.opt_value_from_str("--preset")?;
+            finish(&subcommand, args, &trailing)?;
+            Command::Init {
+                legacy_name,
+                preset,
+                list_presets,
             }
-            Command::Init
         }
         "run" => {
+            let dry_run = args.contains("--dry-run");
+            let format = args
+                .opt_value_from_str::<_, String>("--format")?
+                .map(|value| {
+                    OutputFormat::parse(&value).ok_or_else(|| {
+                        anyhow!(
+                            "Invalid value for --format: '{value}' (expected raw, compact, json-lines or prefixed)"
+                        )
+                    })
+                })
+                .transpose()?;
+            let output_buffering = args
+                .opt_value_from_str::<_, String>("--output-buffering")?
+                .map(|value| {
+                    OutputBuffering::parse(&value).ok_or_else(|| {
+                        anyhow!(
+                            "Invalid value for --output-buffering: '{value}' (expected line, full or char)"
+                        )
+                    })
+                })
+                .transpose()?;
+            let input_file: Option<PathBuf> = args.opt_value_from_str("--input-file")?;
+            let assert_exit = args
+                .opt_value_from_str::<_, String>("--assert-exit")?
+                .map(|value| {
+                    value.parse::<i32>().map_err(|_| {
+                        anyhow!(
+                            "Invalid value for --assert-exit: '{value}' (expected an exit code)"
+                        )
+                    })
+                })
+                .transpose()?;
+            if dry_run && assert_exit.is_some() {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --assert-exit\n\nFor more information try --help"
+                ));
+            }
+            let stash_unstaged = args.contains("--stash-unstaged");
+            if dry_run && stash_unstaged {
+                return Err(anyhow!(
+                    "--dry-run cannot be combined with --stash-unstaged\n\nFor more information try --help"
+                ));
+            }
+            let timeout_signal = args
+                .opt_value_from_str::<_, String>("--timeout-signal")?
+                .map(|value| {
+                    Signal::parse(&value).ok_or_else(|| {
+                        anyhow!(
+                            "Invalid value for --timeout-signal: '{value}' (expected term, kill, int, quit or a signal number)"
+                        )
+                    })
+                })
+                .transpose()?;
+            let env_files: Vec<PathBuf> = args.values_from_str("--env-file")?;
+            let env_file_override = args.contains("--env-file-override");
+            if env_file_override && env_files.is_empty() {
+                return Err(anyhow!(
+                    "--env-file-override needs --env-file\n\nFor more information try --help"
+                ));
+            }
+            let has_files = args.contains("--files");
             let hook_name: String = args.free_from_str().map_err(|_| {
                 anyhow!("Missing required argument: HOOK_NAME\n\nFor more information try --help")
             })?;
-            // For run command, remaining args are passed to the hook
-            let remaining_args: Vec<String> = args
-                .finish()
-                .into_iter()
+            let remaining = args.finish();
+            // With --files the remaining args are the files, otherwise they
+            // are passed to the hook. Everything after `--` is passed to the hook.
+            let (files, remaining) = if has_files {
+                if remaining.is_empty() {
+                    return Err(anyhow!(
+                        "--files needs at least one file\n\nFor more information try --help"
+                    ));
+                }
+                (
+                    Some(remaining.into_iter().map(PathBuf::from).collect()),
+                    Vec::new(),
+                )
+            } else {
+                (None, remaining)
+            };
+            let remaining_args: Vec<String> = remaining
+                .iter()
+                .chain(&trailing)
                 .map(|s| s.to_string_lossy().to_string())
                 .collect();
             Command::Run {
                 hook_name,
                 args: remaining_args,
+                dry_run,
+                format,
+                output_buffering,
+                input_file,
+                files,
+                assert_exit,
+                stash_unstaged,
+                timeout_signal,
+                env_files,
+                env_file_override,
             }
         }
-        "prepare-commit-msg" => {
-            let commit_msg_file: String = args.free_from_str().map_err(|_| {
-                anyhow!(
-                    "Missing required argument: COMMIT_MSG_FILE\n\nFor more information try --help"
-                )
+        "watch" => {
+            let hook_name: String = args.free_from_str().map_err(|_| {
+                anyhow!("Missing required argument: HOOK_NAME\n\nFor more information try --help")
             })?;
-            let commit_source: Option<String> = args.free_from_str().ok();
-            let commit_sha: Option<String> = args.free_from_str().ok();
-            // Check for unexpected arguments for prepare-commit-msg command
-            let remaining = args.finish();
-            if !remaining.is_empty() {
-                let unexpected: Vec<String> = remaining
-                    .into_iter()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .collect();
+            finish(&subcommand, args, &trailing)?;
+            Command::Watch { hook_name }
+        }
+        "prepare-commit-msg" => {
+            let branch: Option<String> = args.opt_value_from_str("--branch")?;
+            let test_branch: Option<String> = args.opt_value_from_str("--test-branch")?;
+            if branch.is_some() && test_branch.is_some() {
                 return Err(anyhow!(
-                    "Unexpected argument(s): {}\n\nFor more information try --help",
-                    unexpected.join(", ")
+                    "--branch cannot be combined with --test-branch\n\nFor more information try --help"
                 ));
             }
+            let commit_msg_file: Option<String> = args.opt_free_from_str()?;
+            let commit_msg_file = match (commit_msg_file, &test_branch) {
+                // A preview writes no file; `-` stands in for one
+                (Some(file), Some(_)) if file != "-" => {
+                    return Err(anyhow!(
+                        "--test-branch cannot be combined with a COMMIT_MSG_FILE other than -\n\nFor more information try --help"
+                    ))
+                }
+                (_, Some(_)) => None,
+                (Some(file), None) => Some(PathBuf::from(file)),
+                (None, None) => {
+                    return Err(anyhow!(
+                        "Missing required argument: COMMIT_MSG_FILE\n\nFor more information try --help"
+                    ))
+                }
+            };
+            let commit_source: Option<String> = args.free_from_str().ok();
+            let commit_sha: Option<String> = args.free_from_str().ok();
+            finish(&subcommand, args, &trailing)?;
             Command::PrepareCommitMsg {
-                commit_msg_file: PathBuf::from(commit_msg_file),
+                commit_msg_file,
                 commit_source,
                 commit_sha,
+                branch,
+                test_branch,
+            }
+        }
+        "validate" => {
+            let refresh = args.contains("--refresh");
+            finish(&subcommand, args, &trailing)?;
+            Command::Validate { refresh }
+        }
+        "stats" => {
+            let hook: Option<String> = args.opt_value_from_str("--hook")?;
+            let since = match args.opt_value_from_str::<_, String>("--since")? {
+                Some(value) => Some(parse_age(&value)?),
+                None => None,
+            };
+            let json = args.contains("--json");
+            finish(&subcommand, args, &trailing)?;
+            Command::Stats { hook, since, json }
+        }
+        "upgrade-config" => {
+            let check = args.contains("--check");
+            let force = args.contains("--force");
+            finish(&subcommand, args, &trailing)?;
+            Command::UpgradeConfig { check, force }
+        }
+        "config" => {
+            let format: Option<String> = args.opt_value_from_str("--format")?;
+            let dir: Option<PathBuf> = args.opt_value_from_str("--dir")?;
+            let legacy_name = args.contains("--legacy-name");
+            let action: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            match action.as_deref() {
+                Some("import") => {
+                    let format = format.ok_or_else(|| {
+                        anyhow!("Missing required option: --format <FORMAT>\n\nFor more information try --help")
+                    })?;
+                    let format = ImportFormat::parse(&format).ok_or_else(|| {
+                        anyhow!(
+                            "Invalid value for --format: '{format}' (expected {})",
+                            ImportFormat::NAMES.join(", ")
+                        )
+                    })?;
+                    Command::ConfigImport {
+                        format,
+                        dir,
+                        legacy_name,
+                    }
+                }
+                Some("hash") => {
+                    let format = match format {
+                        Some(format) => HashFormat::parse(&format).ok_or_else(|| {
+                            anyhow!(
+                                "Invalid value for --format: '{format}' (expected {})",
+                                HashFormat::NAMES.join(", ")
+                            )
+                        })?,
+                        None => HashFormat::default(),
+                    };
+                    Command::ConfigHash { format }
+                }
+                Some(action) => {
+                    return Err(anyhow!(
+                        "Unknown config command: '{action}' (expected import or hash)\n\nFor more information try --help"
+                    ))
+                }
+                None => {
+                    return Err(anyhow!(
+                        "Missing required argument: COMMAND\n\nFor more information try --help"
+                    ))
+                }
+            }
+        }
+        "registry" => {
+            let tags = tags(&mut args)?;
+            let action: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            match action.as_deref() {
+                Some("list") => Command::RegistryList { tags },
+                Some("clean") if !tags.is_empty() => {
+                    return Err(anyhow!(
+                        "--tag cannot be combined with clean\n\nFor more information try --help"
+                    ))
+                }
+                Some("clean") => Command::RegistryClean,
+                Some(action) => {
+                    return Err(anyhow!(
+                        "Unknown registry command: '{action}' (expected list or clean)\n\nFor more information try --help"
+                    ))
+                }
+                None => {
+                    return Err(anyhow!(
+                        "Missing required argument: COMMAND\n\nFor more information try --help"
+                    ))
+                }
+            }
+        }
+        "ci" => {
+            let force = args.contains("--force");
+            let print = args.contains("--print");
+            let action: Option<String> = args.opt_free_from_str()?;
+            let provider: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            match action.as_deref() {
+                Some("generate") => {
+                    let provider = provider.ok_or_else(|| {
+                        anyhow!("Missing required argument: PROVIDER\n\nFor more information try --help")
+                    })?;
+                    let provider = CiProvider::parse(&provider).ok_or_else(|| {
+                        anyhow!(
+                            "Invalid provider: '{provider}' (expected {})",
+                            CiProvider::NAMES.join(" or ")
+                        )
+                    })?;
+                    if force && print {
+                        return Err(anyhow!(
+                            "--force cannot be combined with --print\n\nFor more information try --help"
+                        ));
+                    }
+                    Command::CiGenerate {
+                        provider,
+                        force,
+                        print,
+                    }
+                }
+                Some(action) => {
+                    return Err(anyhow!(
+                        "Unknown ci command: '{action}' (expected generate)\n\nFor more information try --help"
+                    ))
+                }
+                None => {
+                    return Err(anyhow!(
+                        "Missing required argument: COMMAND\n\nFor more information try --help"
+                    ))
+                }
+            }
+        }
+        "list" => {
+            let filter = HookFilter {
+                installed: args.contains("--installed"),
+                not_installed: args.contains("--not-installed"),
+                stale: args.contains("--stale"),
+            };
+            finish(&subcommand, args, &trailing)?;
+            Command::List { filter }
+        }
+        "audit" => {
+            let filesystems = filesystems(&mut args);
+            let submodules = submodules(&mut args);
+            let path: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            Command::Audit {
+                path: PathBuf::from(path.unwrap_or_else(|| ".".to_string())),
+                filesystems,
+                submodules,
+            }
+        }
+        "diff-hooks" => {
+            let missing = |name: &str| {
+                anyhow!("Missing required argument: {name}\n\nFor more information try --help")
+            };
+            let repo1: PathBuf = args.free_from_str().map_err(|_| missing("REPO1"))?;
+            let repo2: PathBuf = args.free_from_str().map_err(|_| missing("REPO2"))?;
+            finish(&subcommand, args, &trailing)?;
+            Command::DiffHooks { repo1, repo2 }
+        }
+        "eject" => {
+            let hooks_path: Option<PathBuf> = args.opt_value_from_str("--hooks-path")?;
+            finish(&subcommand, args, &trailing)?;
+            Command::Eject { hooks_path }
+        }
+        "hook-script" => {
+            let all = args.contains("--all");
+            let hook_name: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            match (all, hook_name) {
+                (true, Some(_)) => {
+                    return Err(anyhow!(
+                    "--all cannot be combined with a HOOK_NAME\n\nFor more information try --help"
+                ))
+                }
+                (false, None) => {
+                    return Err(anyhow!(
+                        "Missing required argument: HOOK_NAME\n\nFor more information try --help"
+                    ))
+                }
+                (_, hook_name) => Command::HookScript { hook_name },
+            }
+        }
+        "uninstall" => {
+            let template = args.contains("--template");
+            let filesystems = filesystems(&mut args);
+            let submodules = submodules(&mut args);
+            let path: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            match (template, path) {
+                (true, Some(_)) => {
+                    return Err(anyhow!(
+                    "--template cannot be combined with a PATH\n\nFor more information try --help"
+                ))
+                }
+                (true, None) => Command::UninstallTemplate,
+                (false, path) => Command::Uninstall {
+                    path: PathBuf::from(path.unwrap_or_else(|| ".".to_string())),
+                    filesystems,
+                    submodules,
+                },
+            }
+        }
+        "doctor" => {
+            finish(&subcommand, args, &trailing)?;
+            Command::Doctor
+        }
+        "version" => {
+            let check = args.contains("--check");
+            finish(&subcommand, args, &trailing)?;
+            Command::Version { check }
+        }
+        "self-update" => {
+            finish(&subcommand, args, &trailing)?;
+            Command::SelfUpdate
+        }
+        "verify" => {
+            let fix = args.contains("--fix");
+            let filesystems = filesystems(&mut args);
+            let submodules = submodules(&mut args);
+            let path: Option<String> = args.opt_free_from_str()?;
+            finish(&subcommand, args, &trailing)?;
+            Command::Verify {
+                path: PathBuf::from(path.unwrap_or_else(|| ".".to_string())),
+                fix,
+                filesystems,
+                submodules,
             }
         }
         _ => {
             return Err(anyhow!(
-                "Unknown command: '{}'\n\nFor more information try --help",
-                subcommand
+                "Unknown command: '{}'{}\n\nFor more information try --help",
+                subcommand,
+                command_suggestion(&subcommand)
             ));
         }
     };
 
-    Ok((verbose, command))
+    Ok((GlobalOptions { verbose, config }, command))
 }
 
 fn main() -> Result<()> {
-    let (verbose, command) = parse_args()?;
+    let (options, command) = parse_args()?;
+    let verbose = options.verbose;
+    let hook_manager = HookManager::new()
+        .with_config_path(options.config)
+        .with_verbose(verbose);
+    let update_notice = matches!(
+        command,
+        Command::Add { .. } | Command::Init { .. } | Command::List { .. } | Command::Doctor
+    );
 
     match command {
-        Command::Add { path } => {
-            if verbose {
-                println!(
-                    "Adding hookmaster hooks to repositories under: {}",
-                    path.display()
-                );
+        Command::Add {
+            mut paths,
+            stdin,
+            ndjson,
+            rate_limit,
+            lock_timeout,
+            link_config,
+            config_file,
+            interactive,
+            yes,
+            only_configured,
+            foreign_hooks,
+            dry_run,
+            verify,
+            post_install_hook,
+            tags,
+            no_ignore,
+            repos_file,
+            filesystems,
+            submodules,
+        } => {
+            if stdin {
+                paths.extend(read_paths(io::stdin().lock())?);
+            }
+            let execution_mode = if dry_run {
+                ExecutionMode::DryRun
+            } else {
+                ExecutionMode::Live
+            };
+            let hook_manager = hook_manager
+                .with_foreign_hooks(foreign_hooks)
+                .with_execution_mode(execution_mode)
+                .with_filesystems(filesystems)
+                .with_submodules(submodules);
+            if verbose && !ndjson {
+                for path in &paths {
+                    println!(
+                        "Adding hookmaster hooks to repositories under: {}",
+                        path.display()
+                    );
+                }
             }
-            let hook_manager = HookManager::new();
-            hook_manager.add_hooks_to_path(&path)?;
+            let mut reporter: Box<dyn Reporter> = if ndjson {
+                Box::new(NdjsonReporter::stdout())
+            } else {
+                Box::new(ConsoleReporter::new())
+            };
+            let hook_manager = match rate_limit {
+                Some(rate) => hook_manager.with_rate_limit(rate),
+                None => hook_manager,
+            };
+            let hook_manager = match lock_timeout {
+                Some(seconds) => hook_manager.with_lock_timeout(Duration::from_secs(seconds)),
+                None => hook_manager,
+            };
+            let user_config = UserConfig::load()?;
+            // The user configuration's default gives way to an explicitly given configuration
+            let only_configured = only_configured
+                || (link_config.is_none()
+                    && config_file.is_none()
+                    && user_config.add.only_configured);
+            let confirm_threshold = user_config
+                .add
+                .confirm_threshold
+                .unwrap_or(select::DEFAULT_CONFIRM_THRESHOLD);
+            let hook_manager = hook_manager
+                .with_confirm_threshold((!yes).then_some(confirm_threshold))
+                .with_shared_config(link_config)
+                .with_only_configured(only_configured)
+                .with_verify_installs(verify)
+                .with_post_install_hook(post_install_hook)
+                .with_step_summary(ci::github_step_summary())
+                .with_ignores(!no_ignore);
+            let hook_manager = if tags.is_empty() {
+                hook_manager
+            } else {
+                hook_manager.with_registry(Registry::open()?, tags)
+            };
+            let config = config_file
+                .map(|file| GitHooksConfig::load(Some(&file)))
+                .transpose()?;
+            if let Some(repos_file) = repos_file {
+                let file = File::open(&repos_file).with_context(|| {
+                    format!("Failed to read repository list: {}", repos_file.display())
+                })?;
+                let repos = read_repo_list(BufReader::new(file))?;
+                hook_manager.add_hooks_to_repos(&repos, reporter.as_mut())?;
+            } else if interactive {
+                let mut selector: Box<dyn RepoSelector> = if yes {
+                    Box::new(KeepPreselection)
+                } else {
+                    select::interactive_selector()
+                };
+                hook_manager.add_hooks_selecting(
+                    &paths,
+                    config.as_ref(),
+                    selector.as_mut(),
+                    reporter.as_mut(),
+                )?;
+            } else {
+                match &config {
+                    Some(config) => {
+                        hook_manager.add_hooks_with_config(&paths, config, reporter.as_mut())?
+                    }
+                    None => hook_manager.add_hooks_to_paths(&paths, reporter.as_mut())?,
+                }
+            }
+        }
+        Command::AddTemplate => {
+            hook_manager.install_template()?;
+        }
+        Command::AddRemote { url } => {
+            hook_manager.add_hooks_to_remote(&url)?;
         }
-        Command::Init => {
+        Command::Init {
+            legacy_name,
+            preset,
+            list_presets,
+        } => {
+            if list_presets {
+                hook_manager.list_presets();
+                return Ok(());
+            }
             if verbose {
-                println!("Initializing repository with sample githooks.toml");
+                println!("Initializing repository with sample configuration");
             }
-            let hook_manager = HookManager::new();
-            hook_manager.init_repository()?;
+            hook_manager.init_repository(legacy_name, preset.as_deref())?;
         }
-        Command::Run { hook_name, args } => {
-            if verbose {
-                println!("Running hook: {hook_name}");
+        Command::Run {
+            hook_name,
+            args,
+            dry_run,
+            format,
+            output_buffering,
+            input_file,
+            files,
+            assert_exit,
+            stash_unstaged,
+            timeout_signal,
+            env_files,
+            env_file_override,
+        } => {
+            let hook_manager = match format {
+                Some(format) => hook_manager.with_output_format(format),
+                None => hook_manager,
+            }
+            .with_output_buffering(output_buffering.unwrap_or_default())
+            .with_timeout_signal(timeout_signal.unwrap_or_default())
+            .with_stdin_file(input_file)
+            .with_files(files)
+            .with_stash_unstaged(stash_unstaged)
+            .with_env_files(env_files, env_file_override);
+            if dry_run {
+                hook_manager.dry_run_hook(&hook_name)?;
+            } else {
+                if verbose {
+                    println!("Running hook: {hook_name}");
+                }
+                let user_config = UserConfig::load()?;
+                let hook_manager = hook_manager.with_strict(user_config.run.strict);
+                // Stopping the hook stops its commands rather than leaving them running
+                shutdown::forward_signals();
+                let result = hook_manager.run_hook(&hook_name, &args);
+                shutdown::exit_if_stopped();
+                match assert_exit {
+                    Some(expected) => {
+                        let exit_code = match result {
+                            Ok(()) => 0,
+                            Err(error) => match error.exit_code() {
+                                Some(exit_code) => exit_code,
+                                // The hook didn't get to run, which is no pass
+                                None => return Err(error.into()),
+                            },
+                        };
+                        if exit_code == expected {
+                            println!("[PASS] Hook exited with expected code {expected}");
+                        } else {
+                            println!("[FAIL] Hook exited with {exit_code}, expected {expected}");
+                            std::process::exit(1);
+                        }
+                    }
+                    None => result?,
+                }
             }
-            let hook_manager = HookManager::new();
-            hook_manager.run_hook(&hook_name, &args)?;
+        }
+        Command::Watch { hook_name } => {
+            let user_config = UserConfig::load()?;
+            // Stopping watch stops the commands it is running too
+            shutdown::forward_signals();
+            hook_manager
+                .with_strict(user_config.run.strict)
+                .watch_hook(&hook_name)?;
+            shutdown::exit_if_stopped();
         }
         Command::PrepareCommitMsg {
             commit_msg_file,
             commit_source,
             commit_sha,
+            branch,
+            test_branch,
+        } => match (test_branch, commit_msg_file) {
+            (Some(branch), _) => hook_manager.preview_commit_msg(&branch)?,
+            (None, Some(commit_msg_file)) => {
+                if verbose {
+                    println!("Processing prepare-commit-msg hook");
+                }
+                hook_manager.prepare_commit_msg(
+                    &commit_msg_file,
+                    commit_source.as_deref(),
+                    commit_sha.as_deref(),
+                    branch.as_deref(),
+                )?;
+            }
+            (None, None) => unreachable!("parsing requires a commit message file"),
+        },
+        Command::Validate { refresh } => {
+            if verbose {
+                println!("Validating configuration");
+            }
+            if refresh {
+                hook_manager.refresh_remote_config()?;
+            }
+            hook_manager.validate_config()?;
+        }
+        Command::UpgradeConfig { check, force } => {
+            hook_manager.upgrade_config(check, force)?;
+        }
+        Command::RegistryList { tags } => {
+            let registry = Registry::open()?;
+            let entries = registry.list(&tags)?;
+            if entries.is_empty() {
+                println!("No repositories recorded in {}", registry.path().display());
+            }
+            for entry in entries {
+                println!("{}", entry.summary());
+            }
+        }
+        Command::RegistryClean => {
+            let registry = Registry::open()?;
+            let removed = registry.clean()?;
+            for entry in &removed {
+                println!("Removed {}", entry.path);
+            }
+            println!(
+                "Removed {} entr{} for repositories that no longer exist",
+                removed.len(),
+                if removed.len() == 1 { "y" } else { "ies" }
+            );
+        }
+        Command::Stats { hook, since, json } => {
+            hook_manager.print_stats(hook.as_deref(), since, json)?;
+        }
+        Command::Version { check: false } => {
+            println!("{VERSION}");
+        }
+        Command::Version { check: true } => {
+            let source = GitHubReleases::new(Duration::from_secs(10));
+            match self_update::check(&source, self_update::CURRENT_VERSION)? {
+                Some(release) => println!(
+                    "{}",
+                    self_update::newer_version_message(
+                        &release.version,
+                        self_update::CURRENT_VERSION
+                    )
+                ),
+                None => println!(
+                    "hookmaster {} is the latest version",
+                    self_update::CURRENT_VERSION
+                ),
+            }
+        }
+        Command::SelfUpdate => {
+            let exe = std::env::current_exe()
+                .with_context(|| "Failed to find the hookmaster executable")?;
+            let source = GitHubReleases::new(Duration::from_secs(120));
+            match self_update::update(
+                &source,
+                self_update::CURRENT_VERSION,
+                &exe,
+                std::env::consts::OS,
+                std::env::consts::ARCH,
+            )? {
+                Some(version) => println!(
+                    "Updated {} from {} to {version}",
+                    exe.display(),
+                    self_update::CURRENT_VERSION
+                ),
+                None => println!(
+                    "hookmaster {} is the latest version",
+                    self_update::CURRENT_VERSION
+                ),
+            }
+        }
+        Command::ConfigImport {
+            format,
+            dir,
+            legacy_name,
+        } => {
+            hook_manager.import_config(format, dir.as_deref(), legacy_name)?;
+        }
+        Command::ConfigHash { format } => {
+            hook_manager.print_config_hash(format)?;
+        }
+        Command::CiGenerate {
+            provider,
+            force,
+            print,
+        } => {
+            hook_manager.generate_ci(provider, force, print)?;
+        }
+        Command::List { filter } => {
+            hook_manager.list_hooks(filter)?;
+        }
+        Command::Audit {
+            path,
+            filesystems,
+            submodules,
+        } => {
+            if verbose {
+                println!("Auditing hooks under: {}", path.display());
+            }
+            hook_manager
+                .with_filesystems(filesystems)
+                .with_submodules(submodules)
+                .audit(&path)?;
+        }
+        Command::Eject { hooks_path } => {
+            hook_manager.eject(hooks_path.as_deref())?;
+        }
+        Command::HookScript { hook_name } => {
+            hook_manager.print_hook_script(hook_name.as_deref())?;
+        }
+        Command::Uninstall {
+            path,
+            filesystems,
+            submodules,
         } => {
             if verbose {
-                println!("Processing prepare-commit-msg hook");
+                println!(
+                    "Removing hookmaster hooks from repositories under: {}",
+                    path.display()
+                );
             }
-            let hook_manager = HookManager::new();
-            hook_manager.prepare_commit_msg(
-                &commit_msg_file,
-                commit_source.as_deref(),
-                commit_sha.as_deref(),
-            )?;
+            hook_manager
+                .with_filesystems(filesystems)
+                .with_submodules(submodules)
+                .uninstall(&path)?;
+        }
+        Command::UninstallTemplate => {
+            hook_manager.uninstall_template()?;
+        }
+        Command::Doctor => {
+            hook_manager.doctor()?;
+        }
+        Command::Verify {
+            path,
+            fix,
+            filesystems,
+            submodules,
+        } => {
+            hook_manager
+                .with_filesystems(filesystems)
+                .with_submodules(submodules)
+                .verify(&path, fix)?;
+        }
+        Command::DiffHooks { repo1, repo2 } => {
+            hook_manager.print_hook_diffs(&repo1, &repo2)?;
         }
     }
 
+    if update_notice {
+        print_update_notice();
+    }
     Ok(())
 }
+
+/// Print a notice to stderr if a newer hookmaster is out, for commands run
+/// by hand. Hooks never check, and neither does anything when stderr isn't
+/// a terminal or the check is turned off with HOOKMASTER_NO_UPDATE_CHECK.
+fn print_update_notice() {
+    if !io::stderr().is_terminal() || !self_update::automatic_checks_enabled() {
+        return;
+    }
+    let Some(check_file) = self_update::check_file() else {
+        return;
+    };
+    // A short timeout, as nobody asked for this check
+    let source = GitHubReleases::new(Duration::from_secs(3));
+    if let Some(notice) = self_update::newer_version_notice(
+        &source,
+        &check_file,
+        self_update::CURRENT_VERSION,
+        SystemTime::now(),
+    ) {
+        eprintln!("\n{notice}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command_line: &str) -> Result<ParsedArgs> {
+        parse_args_from(
+            command_line
+                .split_whitespace()
+                .map(OsString::from)
+                .collect(),
+        )
+    }
+
+    fn run(hook_name: &str, args: &[&str]) -> Command {
+        Command::Run {
+            hook_name: hook_name.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            dry_run: false,
+            format: None,
+            output_buffering: None,
+            input_file: None,
+            files: None,
+            assert_exit: None,
+            stash_unstaged: false,
+            timeout_signal: None,
+            env_files: Vec::new(),
+            env_file_override: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_command_lines() {
+        let quiet = GlobalOptions {
+            verbose: false,
+            config: None,
+        };
+        let verbose = GlobalOptions {
+            verbose: true,
+            config: None,
+        };
+        let add = Command::Add {
+            paths: vec![PathBuf::from("dev")],
+            stdin: false,
+            ndjson: true,
+            rate_limit: None,
+            lock_timeout: None,
+            link_config: None,
+            config_file: None,
+            interactive: false,
+            yes: false,
+            only_configured: false,
+            foreign_hooks: ForeignHooks::Refuse,
+            dry_run: false,
+            verify: false,
+            post_install_hook: None,
+            tags: Tags::new(),
+            no_ignore: false,
+            repos_file: None,
+            filesystems: Filesystems::One,
+            submodules: Submodules::Exclude,
+        };
+
+        let cases = [
+            ("run pre-commit", &quiet, run("pre-commit", &[])),
+            ("-v run pre-commit", &verbose, run("pre-commit", &[])),
+            ("run pre-commit -v", &verbose, run("pre-commit", &[])),
+            (
+                "run --verbose pre-commit a b",
+                &verbose,
+                run("pre-commit", &["a", "b"]),
+            ),
+            (
+                "run pre-commit -- -v --dry-run",
+                &quiet,
+                run("pre-commit", &["-v", "--dry-run"]),
+            ),
+            (
+                "run pre-commit a -- -- b",
+                &quiet,
+                run("pre-commit", &["a", "--", "b"]),
+            ),
+            ("add dev --ndjson", &quiet, add),
+            (
+                "list --stale -v",
+                &verbose,
+                Command::List {
+                    filter: HookFilter {
+                        stale: true,
+                        ..Default::default()
+                    },
+                },
+            ),
+        ];
+        for (command_line, options, command) in cases {
+            let parsed = parse(command_line).unwrap();
+            assert_eq!(&parsed.0, options, "{command_line}");
+            assert_eq!(parsed.1, command, "{command_line}");
+        }
+
+        let (_, command) = parse("add --remote git@example.com:org/repo.git").unwrap();
+        assert_eq!(
+            command,
+            Command::AddRemote {
+                url: "git@example.com:org/repo.git".to_string()
+            }
+        );
+
+        let (options, command) = parse("run --dry-run pre-push -c other.toml").unwrap();
+        assert_eq!(options.config, Some(PathBuf::from("other.toml")));
+        assert!(matches!(command, Command::Run { dry_run: true, .. }));
+
+        let (_, command) = parse("run pre-commit --files src/lib.rs src/config.rs -- -x").unwrap();
+        let Command::Run { args, files, .. } = command else {
+            panic!("{command:?}");
+        };
+        assert_eq!(args, vec!["-x"]);
+        assert_eq!(
+            files,
+            Some(vec![
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/config.rs")
+            ])
+        );
+
+        let (_, command) = parse("add one two --ndjson - -- -three").unwrap();
+        let Command::Add { paths, stdin, .. } = command else {
+            panic!("{command:?}");
+        };
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("one"),
+                PathBuf::from("two"),
+                PathBuf::from("-three")
+            ]
+        );
+        assert!(stdin);
+        let (_, command) = parse("add --stdin").unwrap();
+        assert!(matches!(command, Command::Add { stdin: true, .. }));
+
+        let (_, command) = parse("add --interactive -y dev").unwrap();
+        assert!(matches!(
+            command,
+            Command::Add {
+                interactive: true,
+                yes: true,
+                ..
+            }
+        ));
+
+        let (_, command) = parse("upgrade-config --check").unwrap();
+        assert_eq!(
+            command,
+            Command::UpgradeConfig {
+                check: true,
+                force: false,
+            }
+        );
+
+        let (_, command) = parse("run --output-buffering char pre-commit").unwrap();
+        assert!(matches!(
+            command,
+            Command::Run {
+                output_buffering: Some(OutputBuffering::Char),
+                ..
+            }
+        ));
+
+        let (_, command) = parse("run --assert-exit 1 commit-msg bad-message").unwrap();
+        assert_eq!(command, {
+            let mut expected = run("commit-msg", &["bad-message"]);
+            if let Command::Run { assert_exit, .. } = &mut expected {
+                *assert_exit = Some(1);
+            }
+            expected
+        });
+
+        let (_, command) = parse("run --stash-unstaged pre-commit").unwrap();
+        assert!(matches!(
+            command,
+            Command::Run {
+                stash_unstaged: true,
+                ..
+            }
+        ));
+
+        for (option, signal) in [
+            ("int", Signal::Int),
+            ("kill", Signal::Kill),
+            ("10", Signal::Number(10)),
+        ] {
+            let (_, command) = parse(&format!("run --timeout-signal {option} pre-push")).unwrap();
+            assert_eq!(command, {
+                let mut expected = run("pre-push", &[]);
+                if let Command::Run { timeout_signal, .. } = &mut expected {
+                    *timeout_signal = Some(signal);
+                }
+                expected
+            });
+        }
+
+        let (_, command) =
+            parse("run --env-file .env --env-file .env.local --env-file-override pre-commit")
+                .unwrap();
+        assert_eq!(command, {
+            let mut expected = run("pre-commit", &[]);
+            if let Command::Run {
+                env_files,
+                env_file_override,
+                ..
+            } = &mut expected
+            {
+                *env_files = vec![PathBuf::from(".env"), PathBuf::from(".env.local")];
+                *env_file_override = true;
+            }
+            expected
+        });
+
+        let (_, command) = parse("uninstall --all-filesystems /home").unwrap();
+        assert_eq!(
+            command,
+            Command::Uninstall {
+                path: PathBuf::from("/home"),
+                filesystems: Filesystems::All,
+                submodules: Submodules::Exclude,
+            }
+        );
+
+        let (_, command) = parse("verify --include-submodules").unwrap();
+        assert_eq!(
+            command,
+            Command::Verify {
+                path: PathBuf::from("."),
+                fix: false,
+                filesystems: Filesystems::One,
+                submodules: Submodules::Include,
+            }
+        );
+
+        let (_, command) =
+            parse("prepare-commit-msg --test-branch feature/JIRA-999-my-work -").unwrap();
+        assert_eq!(
+            command,
+            Command::PrepareCommitMsg {
+                commit_msg_file: None,
+                commit_source: None,
+                commit_sha: None,
+                branch: None,
+                test_branch: Some("feature/JIRA-999-my-work".to_string()),
+            }
+        );
+        let (_, command) = parse("prepare-commit-msg .git/COMMIT_EDITMSG message").unwrap();
+        assert_eq!(
+            command,
+            Command::PrepareCommitMsg {
+                commit_msg_file: Some(PathBuf::from(".git/COMMIT_EDITMSG")),
+                commit_source: Some("message".to_string()),
+                commit_sha: None,
+                branch: None,
+                test_branch: None,
+            }
+        );
+        let (_, command) =
+            parse("prepare-commit-msg --branch JIRA-1-fix .git/COMMIT_EDITMSG").unwrap();
+        assert_eq!(
+            command,
+            Command::PrepareCommitMsg {
+                commit_msg_file: Some(PathBuf::from(".git/COMMIT_EDITMSG")),
+                commit_source: None,
+                commit_sha: None,
+                branch: Some("JIRA-1-fix".to_string()),
+                test_branch: None,
+            }
+        );
+
+        let (_, command) = parse("stats --hook pre-commit --since 30d --json").unwrap();
+        assert_eq!(
+            command,
+            Command::Stats {
+                hook: Some("pre-commit".to_string()),
+                since: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+                json: true,
+            }
+        );
+
+        let (_, command) = parse("ci generate gitlab --print").unwrap();
+        assert_eq!(
+            command,
+            Command::CiGenerate {
+                provider: CiProvider::GitLab,
+                force: false,
+                print: true,
+            }
+        );
+
+        let (_, command) = parse("registry list --tag team=backend --tag env=prod").unwrap();
+        assert_eq!(
+            command,
+            Command::RegistryList {
+                tags: Tags::from([
+                    ("team".to_string(), "backend".to_string()),
+                    ("env".to_string(), "prod".to_string()),
+                ]),
+            }
+        );
+        assert_eq!(parse("registry clean").unwrap().1, Command::RegistryClean);
+
+        let (_, command) = parse("diff-hooks ../api ../web").unwrap();
+        assert_eq!(
+            command,
+            Command::DiffHooks {
+                repo1: PathBuf::from("../api"),
+                repo2: PathBuf::from("../web"),
+            }
+        );
+
+        let (_, command) = parse("watch pre-commit").unwrap();
+        assert_eq!(
+            command,
+            Command::Watch {
+                hook_name: "pre-commit".to_string()
+            }
+        );
+
+        let (_, command) = parse("version --check").unwrap();
+        assert_eq!(command, Command::Version { check: true });
+        let (_, command) = parse("self-update").unwrap();
+        assert_eq!(command, Command::SelfUpdate);
+
+        let (_, command) = parse("validate --refresh").unwrap();
+        assert_eq!(command, Command::Validate { refresh: true });
+
+        let (_, command) = parse("verify --fix repos").unwrap();
+        assert_eq!(
+            command,
+            Command::Verify {
+                path: PathBuf::from("repos"),
+                fix: true,
+                filesystems: Filesystems::One,
+                submodules: Submodules::Exclude,
+            }
+        );
+
+        let (_, command) = parse("add --chain --dry-run dev").unwrap();
+        assert!(matches!(
+            command,
+            Command::Add {
+                foreign_hooks: ForeignHooks::Chain,
+                dry_run: true,
+                ..
+            }
+        ));
+
+        let (_, command) = parse("config import --format husky --dir hooks").unwrap();
+        assert!(matches!(
+            command,
+            Command::ConfigImport {
+                format: ImportFormat::Husky,
+                dir: Some(dir),
+                legacy_name: false,
+            } if dir == std::path::Path::new("hooks")
+        ));
+
+        let (_, command) = parse("config hash").unwrap();
+        assert_eq!(
+            command,
+            Command::ConfigHash {
+                format: HashFormat::Sha256
+            }
+        );
+        let (_, command) = parse("config hash --format md5").unwrap();
+        assert_eq!(
+            command,
+            Command::ConfigHash {
+                format: HashFormat::Md5
+            }
+        );
+
+        let (_, command) = parse("add --post-install-hook ./register.sh dev").unwrap();
+        assert!(matches!(
+            command,
+            Command::Add { post_install_hook: Some(script), .. } if script == std::path::Path::new("./register.sh")
+        ));
+
+        let (_, command) = parse("add --config-file team.toml dev").unwrap();
+        assert!(matches!(
+            command,
+            Command::Add { config_file: Some(file), .. } if file == std::path::Path::new("team.toml")
+        ));
+    }
+
+    #[test]
+    fn test_read_paths() {
+        let input = "/work/api\n\n  /work/web  \r\n/work/api\n";
+        assert_eq!(
+            read_paths(input.as_bytes()).unwrap(),
+            vec![
+                PathBuf::from("/work/api"),
+                PathBuf::from("/work/web"),
+                PathBuf::from("/work/api")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_repo_list() {
+        let input = "# affected by this change\n/work/api\n\n  /work/web  \r\n  # /work/old\n";
+        assert_eq!(
+            read_repo_list(input.as_bytes()).unwrap(),
+            vec![PathBuf::from("/work/api"), PathBuf::from("/work/web")]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("", "No command specified"),
+            ("ci generate jenkins", "Invalid provider: 'jenkins'"),
+            ("add --tag team dev", "Invalid value for --tag: 'team'"),
+            ("add --dry-run --tag team=api dev", "--dry-run cannot be combined with --tag"),
+            ("add --dry-run --ndjson dev", "--dry-run cannot be combined with --ndjson"),
+            ("registry", "Missing required argument: COMMAND"),
+            ("diff-hooks ../api", "Missing required argument: REPO2"),
+            ("registry prune", "Unknown registry command: 'prune'"),
+            ("registry clean --tag team=api", "--tag cannot be combined with clean"),
+            ("ci generate github --force --print", "--force cannot be combined with --print"),
+            (
+                "run --assert-exit one commit-msg",
+                "Invalid value for --assert-exit: 'one'",
+            ),
+            (
+                "run --dry-run --assert-exit 1 commit-msg",
+                "--dry-run cannot be combined with --assert-exit",
+            ),
+            (
+                "run --dry-run --stash-unstaged pre-commit",
+                "--dry-run cannot be combined with --stash-unstaged",
+            ),
+            (
+                "run --timeout-signal hup pre-commit",
+                "Invalid value for --timeout-signal: 'hup'",
+            ),
+            (
+                "run --timeout-signal 0 pre-commit",
+                "Invalid value for --timeout-signal: '0'",
+            ),
+            (
+                "run --env-file-override pre-commit",
+                "--env-file-override needs --env-file",
+            ),
+            (
+                "--dry-run run pre-commit",
+                "Unexpected argument '--dry-run' before the command",
+            ),
+            (
+                "add dev --skip-ci",
+                "Unexpected argument(s) for 'add': --skip-ci",
+            ),
+            (
+                "add --remote git@example.com:repo.git dev",
+                "Unexpected argument(s) for 'add': dev",
+            ),
+            (
+                "list -- --stale",
+                "Unexpected argument(s) for 'list': --stale",
+            ),
+            (
+                "validate extra",
+                "Unexpected argument(s) for 'validate': extra",
+            ),
+            (
+                "add --config-file a.toml --link-config b.toml dev",
+                "--config-file cannot be combined with --link-config",
+            ),
+            (
+                "add --repos-file repos.txt dev",
+                "--repos-file cannot be combined with PATH or --stdin",
+            ),
+            (
+                "add --repos-file repos.txt --dry-run",
+                "--repos-file cannot be combined with --dry-run",
+            ),
+            ("run pre-commit --files", "--files needs at least one file"),
+            (
+                "stats --since 30",
+                "Invalid value for --since: '30' (expected a number followed by s, m, h, d or w, e.g. 30d)",
+            ),
+            ("stats --since d", "Invalid value for --since: 'd'"),
+            (
+                "prepare-commit-msg",
+                "Missing required argument: COMMIT_MSG_FILE",
+            ),
+            (
+                "prepare-commit-msg --test-branch main .git/COMMIT_EDITMSG",
+                "--test-branch cannot be combined with a COMMIT_MSG_FILE other than -",
+            ),
+            (
+                "prepare-commit-msg --branch main --test-branch main -",
+                "--branch cannot be combined with --test-branch",
+            ),
+            (
+                "run pre-commit --output-buffering block",
+                "Invalid value for --output-buffering: 'block' (expected line, full or char)",
+            ),
+            (
+                "add --force --chain dev",
+                "--force cannot be combined with --chain",
+            ),
+            (
+                "add --dry-run --interactive dev",
+                "--dry-run cannot be combined with --interactive",
+            ),
+            (
+                "add --dry-run --post-install-hook register.sh dev",
+                "--dry-run cannot be combined with --post-install-hook",
+            ),
+            (
+                "add --verify --dry-run dev",
+                "--dry-run cannot be combined with --verify",
+            ),
+            ("config import", "Missing required option: --format"),
+            (
+                "config import --format lefthook",
+                "Invalid value for --format: 'lefthook' (expected husky)",
+            ),
+            ("config export", "Unknown config command: 'export'"),
+            (
+                "config hash --format sha1",
+                "Invalid value for --format: 'sha1' (expected sha256, md5)",
+            ),
+            (
+                "add --only-configured --link-config a.toml dev",
+                "--only-configured cannot be combined with --link-config or --config-file",
+            ),
+            (
+                "add --interactive --stdin",
+                "--interactive can't prompt when paths are read from stdin",
+            ),
+            ("add --ndjson", "Missing required argument: PATH"),
+            (
+                "add dev --bogus",
+                "Unexpected argument(s) for 'add': --bogus",
+            ),
+            ("runn pre-commit", "Did you mean 'run'?"),
+        ];
+        for (command_line, message) in cases {
+            let err = parse(command_line).unwrap_err().to_string();
+            assert!(err.contains(message), "{command_line}: {err}");
+        }
+    }
+}
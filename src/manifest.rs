@@ -0,0 +1,310 @@
+//! The `.git/hookmaster.json` manifest recording what `add` installed in a
+//! repository, so later commands don't have to guess from file contents
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::formatter;
+
+/// File name of the manifest in a repository's `.git` directory
+pub const MANIFEST_FILE: &str = "hookmaster.json";
+
+/// Version of the manifest format written by this version of hookmaster
+pub const MANIFEST_VERSION: u64 = 1;
+
+/// A hook script `add` installed
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledHook {
+    /// Hook file name, e.g. `pre-commit`
+    pub name: String,
+    /// Hash of the script as written, from [`content_hash`]
+    pub hash: String,
+    /// Tool whose script was there before hookmaster's, e.g. `Husky`
+    pub previous: Option<String>,
+    /// The previous script was kept as `<hook>.chained` and runs first
+    pub chained: bool,
+}
+
+/// What `add` installed in a repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// Version of hookmaster that last installed hooks
+    pub hookmaster_version: String,
+    /// When hooks were last installed, as an RFC 3339 UTC timestamp
+    pub installed_at: String,
+    /// The installed hooks, in the order they were installed
+    pub hooks: Vec<InstalledHook>,
+}
+
+impl Manifest {
+    /// An empty manifest for hooks installed now by this version of hookmaster
+    pub fn new() -> Self {
+        Self {
+            hookmaster_version: env!("CARGO_PKG_VERSION").to_string(),
+            installed_at: formatter::timestamp(SystemTime::now()),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Path of the manifest of the repository at `repo`
+    pub fn path(repo: &Path) -> PathBuf {
+        repo.join(".git").join(MANIFEST_FILE)
+    }
+
+    /// Read the manifest of the repository at `repo`; `None` if there is
+    /// none, such as for hooks installed by older versions
+    pub fn load(repo: &Path) -> Result<Option<Self>> {
+        let path = Self::path(repo);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Self::from_json(&content)
+            .map(Some)
+            .with_context(|| format!("Invalid manifest {}", path.display()))
+    }
+
+    /// Like [`load`](Self::load), but a manifest that can't be read is
+    /// reported as a warning and treated as missing
+    pub fn load_or_warn(repo: &Path) -> Option<Self> {
+        Self::load(repo).unwrap_or_else(|e| {
+            eprintln!("Warning: {e:#}; ignoring it");
+            None
+        })
+    }
+
+    /// Write the manifest to the repository at `repo`
+    pub fn save(&self, repo: &Path) -> Result<()> {
+        let path = Self::path(repo);
+        fs::write(&path, self.to_json())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Remove the manifest of the repository at `repo`, if it has one
+    pub fn remove(repo: &Path) -> Result<()> {
+        let path = Self::path(repo);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The entry for the hook `name`
+    pub fn hook(&self, name: &str) -> Option<&InstalledHook> {
+        self.hooks.iter().find(|hook| hook.name == name)
+    }
+
+    /// Add or replace the entry for `hook`, keeping what an existing entry
+    /// records about the previous script, which only the first `add` saw
+    pub fn record(&mut self, mut hook: InstalledHook) {
+        match self.hooks.iter_mut().find(|entry| entry.name == hook.name) {
+            Some(entry) => {
+                if hook.previous.is_none() {
+                    hook.previous = entry.previous.take();
+                }
+                hook.chained |= entry.chained;
+                *entry = hook;
+            }
+            None => self.hooks.push(hook),
+        }
+    }
+
+    /// Hooks whose script in `hooks_dir` was changed or removed since it
+    /// was installed
+    pub fn drifted(&self, hooks_dir: &Path) -> Vec<&InstalledHook> {
+        self.hooks
+            .iter()
+            .filter(|hook| {
+                fs::read_to_string(hooks_dir.join(&hook.name))
+                    .map_or(true, |content| content_hash(&content) != hook.hash)
+            })
+            .collect()
+    }
+
+    /// The manifest as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        let hooks: Vec<JsonValue> = self
+            .hooks
+            .iter()
+            .map(|hook| {
+                json!({
+                    "name": hook.name,
+                    "hash": hook.hash,
+                    "previous": hook.previous,
+                    "chained": hook.chained,
+                })
+            })
+            .collect();
+        let manifest = json!({
+            "version": MANIFEST_VERSION,
+            "hookmaster_version": self.hookmaster_version,
+            "installed_at": self.installed_at,
+            "hooks": hooks,
+        });
+        // Serializing a `Value` can't fail
+        serde_json::to_string_pretty(&manifest).unwrap_or_default() + "\n"
+    }
+
+    /// Parse a manifest written by [`to_json`](Self::to_json)
+    pub fn from_json(content: &str) -> Result<Self> {
+        let json: JsonValue = serde_json::from_str(content).with_context(|| "Invalid JSON")?;
+        let version = json
+            .get("version")
+            .and_then(JsonValue::as_u64)
+            .ok_or_else(|| anyhow!("missing \"version\""))?;
+        if version > MANIFEST_VERSION {
+            return Err(anyhow!(
+                "manifest version {version} is newer than this hookmaster supports ({MANIFEST_VERSION})"
+            ));
+        }
+
+        let string = |value: &JsonValue, key: &str| {
+            value
+                .get(key)
+                .and_then(JsonValue::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("missing \"{key}\""))
+        };
+        let hooks = json
+            .get("hooks")
+            .and_then(JsonValue::as_array)
+            .ok_or_else(|| anyhow!("missing \"hooks\""))?
+            .iter()
+            .map(|hook| {
+                Ok(InstalledHook {
+                    name: string(hook, "name")?,
+                    hash: string(hook, "hash")?,
+                    previous: hook
+                        .get("previous")
+                        .and_then(JsonValue::as_str)
+                        .map(str::to_string),
+                    chained: hook
+                        .get("chained")
+                        .and_then(JsonValue::as_bool)
+                        .unwrap_or(false),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            hookmaster_version: string(&json, "hookmaster_version")?,
+            installed_at: string(&json, "installed_at")?,
+            hooks,
+        })
+    }
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hash identifying a script's content, as `sha256:<hex>`
+pub fn content_hash(content: &str) -> String {
+    format!("sha256:{:x}", Sha256::digest(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample() -> Manifest {
+        Manifest {
+            hookmaster_version: "0.1.0".to_string(),
+            installed_at: "2024-02-29T12:34:56.789Z".to_string(),
+            hooks: vec![
+                InstalledHook {
+                    name: "pre-commit".to_string(),
+                    hash: content_hash("#!/bin/sh\n"),
+                    previous: Some("Husky".to_string()),
+                    chained: true,
+                },
+                InstalledHook {
+                    name: "pre-push".to_string(),
+                    hash: content_hash(""),
+                    previous: None,
+                    chained: false,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let manifest = sample();
+        let json = manifest.to_json();
+        assert!(json.contains("\"version\": 1"));
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+
+        assert_eq!(
+            content_hash(""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(content_hash("a"), content_hash("b"));
+    }
+
+    #[test]
+    fn test_load_missing_and_corrupt() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        assert_eq!(Manifest::load(repo).unwrap(), None);
+
+        fs::write(Manifest::path(repo), "{ not json").unwrap();
+        let err = Manifest::load(repo).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid JSON"), "{err:#}");
+        assert_eq!(Manifest::load_or_warn(repo), None);
+
+        fs::write(Manifest::path(repo), "{\"version\": 2, \"hooks\": []}").unwrap();
+        let err = Manifest::load(repo).unwrap_err();
+        assert!(format!("{err:#}").contains("newer than"), "{err:#}");
+
+        fs::write(Manifest::path(repo), "{\"version\": 1}").unwrap();
+        assert!(Manifest::load(repo).is_err());
+
+        sample().save(repo).unwrap();
+        assert_eq!(Manifest::load(repo).unwrap(), Some(sample()));
+        Manifest::remove(repo).unwrap();
+        Manifest::remove(repo).unwrap();
+        assert_eq!(Manifest::load(repo).unwrap(), None);
+    }
+
+    #[test]
+    fn test_record_and_drift() {
+        let temp_dir = TempDir::new().unwrap();
+        let hooks_dir = temp_dir.path();
+        let mut manifest = sample();
+        manifest.record(InstalledHook {
+            name: "pre-commit".to_string(),
+            hash: content_hash("#!/bin/sh\nhookmaster run pre-commit\n"),
+            previous: None,
+            chained: false,
+        });
+        let pre_commit = manifest.hook("pre-commit").unwrap();
+        assert_eq!(pre_commit.previous.as_deref(), Some("Husky"));
+        assert!(pre_commit.chained);
+        assert_eq!(manifest.hooks.len(), 2);
+
+        fs::write(
+            hooks_dir.join("pre-commit"),
+            "#!/bin/sh\nhookmaster run pre-commit\n",
+        )
+        .unwrap();
+        fs::write(hooks_dir.join("pre-push"), "edited").unwrap();
+        let drifted: Vec<&str> = manifest
+            .drifted(hooks_dir)
+            .iter()
+            .map(|hook| hook.name.as_str())
+            .collect();
+        assert_eq!(drifted, vec!["pre-push"]);
+    }
+}
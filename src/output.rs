@@ -0,0 +1,685 @@
+//! Progress reporting for `hookmaster add`
+
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often a progress line on a terminal is redrawn at most
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a status line is printed when stderr isn't a terminal
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Frames of the spinner shown while searching for repositories
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Width of the bar shown while installing hooks
+const BAR_WIDTH: usize = 24;
+
+/// Outcome of installing hooks to a single repository
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepoStatus {
+    /// Hooks were installed
+    Installed,
+    /// The repository was left unchanged
+    Skipped,
+    /// Installing hooks failed
+    Error,
+}
+
+impl RepoStatus {
+    /// Name used in machine-readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepoStatus::Installed => "installed",
+            RepoStatus::Skipped => "skipped",
+            RepoStatus::Error => "error",
+        }
+    }
+}
+
+/// Result of processing a single repository during `add`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoResult {
+    /// Path to the repository
+    pub repo: PathBuf,
+    /// What happened to the repository
+    pub status: RepoStatus,
+    /// Names of the hooks that were installed
+    pub hooks: Vec<String>,
+    /// Error message when `status` is `Error`
+    pub error_message: Option<String>,
+    /// Whether each installed hook ran with `add --verify`, by hook name;
+    /// empty without `--verify`
+    pub verification: HashMap<String, bool>,
+}
+
+impl RepoResult {
+    /// Names of the hooks that failed verification, sorted
+    pub fn unverified_hooks(&self) -> Vec<&str> {
+        let mut hooks: Vec<&str> = self
+            .verification
+            .iter()
+            .filter(|(_, verified)| !**verified)
+            .map(|(hook, _)| hook.as_str())
+            .collect();
+        hooks.sort();
+        hooks
+    }
+}
+
+/// Receives progress events while hooks are added to repositories
+pub trait Reporter {
+    /// Repository discovery under `path` finished with `count` repositories
+    fn discovered(&mut self, path: &Path, count: usize);
+
+    /// `path` could not be searched for repositories and was skipped
+    fn path_skipped(&mut self, _path: &Path, _reason: &str) {}
+
+    /// Repository discovery is about to read `dir`, with `found`
+    /// repositories found so far
+    fn searching(&mut self, _dir: &Path, _found: usize) {}
+
+    /// Processing `repo`, the `current`th of `total` repositories, is about
+    /// to start
+    fn install_progress(&mut self, _repo: &Path, _current: usize, _total: usize) {}
+
+    /// Installation to a repository is about to start
+    fn repo_started(&mut self, repo: &Path);
+
+    /// Running `hook` in `repo` with `add --verify` failed with `error`
+    fn verification_failed(&mut self, _repo: &Path, _hook: &str, _error: &str) {}
+
+    /// The `add --post-install-hook` script failed for `repo` with `error`
+    fn post_install_hook_failed(&mut self, _repo: &Path, _error: &str) {}
+
+    /// A repository has been processed
+    fn repo_finished(&mut self, result: &RepoResult);
+
+    /// All repositories have been processed
+    fn finished(&mut self, results: &[RepoResult]);
+}
+
+/// How [`Progress`] shows itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressStyle {
+    /// Not at all
+    Hidden,
+    /// A spinner or bar redrawn in place, for a terminal
+    Terminal,
+    /// A status line every few seconds, for logs
+    Periodic,
+}
+
+/// A progress line on stderr while `add` searches for repositories and
+/// installs hooks to them
+pub struct Progress {
+    style: ProgressStyle,
+    out: Box<dyn Write>,
+    /// Columns the line is cut to, so it never wraps
+    width: usize,
+    /// Shortest time between two updates
+    interval: Duration,
+    /// When the line was last updated; the start for periodic status lines
+    last_update: Option<Instant>,
+    /// Next frame of the spinner
+    frame: usize,
+    /// The latest line, which may not be drawn yet
+    line: Option<String>,
+    /// Whether a line is drawn that must be cleared before other output
+    drawn: bool,
+}
+
+impl Progress {
+    /// Progress on stderr: redrawn in place on a terminal, and otherwise a
+    /// status line every few seconds
+    pub fn stderr() -> Self {
+        if io::stderr().is_terminal() {
+            Self::new(ProgressStyle::Terminal, Box::new(io::stderr()))
+        } else {
+            Self::new(ProgressStyle::Periodic, Box::new(io::stderr()))
+        }
+    }
+
+    /// Progress that shows nothing
+    pub fn hidden() -> Self {
+        Self::new(ProgressStyle::Hidden, Box::new(io::sink()))
+    }
+
+    /// Progress in `style` written to `out`
+    pub fn new(style: ProgressStyle, out: Box<dyn Write>) -> Self {
+        let (interval, last_update) = match style {
+            ProgressStyle::Periodic => (STATUS_INTERVAL, Some(Instant::now())),
+            _ => (REDRAW_INTERVAL, None),
+        };
+        let width = env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(80);
+        Self {
+            style,
+            out,
+            width,
+            interval,
+            last_update,
+            frame: 0,
+            line: None,
+            drawn: false,
+        }
+    }
+
+    /// Show that `dir` is being searched, with `found` repositories so far
+    pub fn searching(&mut self, dir: &Path, found: usize) {
+        let line = match self.style {
+            ProgressStyle::Terminal => {
+                let spinner = SPINNER[self.frame % SPINNER.len()];
+                format!(
+                    "{spinner} Searching ({found} repositories found) {}",
+                    dir.display()
+                )
+            }
+            _ => format!(
+                "Searching for repositories: {found} found so far, in {}",
+                dir.display()
+            ),
+        };
+        self.update(line);
+    }
+
+    /// Show that `repo`, the `current`th of `total` repositories, is being
+    /// processed
+    pub fn installing(&mut self, repo: &Path, current: usize, total: usize) {
+        let name = repo
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| repo.display().to_string());
+        let line = match self.style {
+            ProgressStyle::Terminal => {
+                let done = BAR_WIDTH * current.saturating_sub(1) / total.max(1);
+                format!(
+                    "[{}>{}] {current}/{total} {name}",
+                    "=".repeat(done),
+                    " ".repeat(BAR_WIDTH - done)
+                )
+            }
+            _ => format!("Installing hooks: {current} of {total} repositories, at {name}"),
+        };
+        self.update(line);
+    }
+
+    /// Remove the progress line from the terminal before other output,
+    /// to be drawn again by [`Progress::resume`]
+    pub fn suspend(&mut self) {
+        if self.drawn {
+            let _ = write!(self.out, "\r\x1b[K");
+            let _ = self.out.flush();
+            self.drawn = false;
+        }
+    }
+
+    /// Draw the progress line on the terminal again after other output
+    pub fn resume(&mut self) {
+        if self.style == ProgressStyle::Terminal && !self.drawn && self.line.is_some() {
+            self.draw();
+        }
+    }
+
+    /// Remove the progress line for good, as what it shows is done
+    pub fn finish(&mut self) {
+        self.suspend();
+        self.line = None;
+    }
+
+    fn update(&mut self, line: String) {
+        if self.style == ProgressStyle::Hidden {
+            return;
+        }
+        self.line = Some(line);
+        let now = Instant::now();
+        if self
+            .last_update
+            .map_or(true, |last| now.duration_since(last) >= self.interval)
+        {
+            self.draw();
+        }
+    }
+
+    fn draw(&mut self) {
+        let Some(line) = &self.line else {
+            return;
+        };
+        self.last_update = Some(Instant::now());
+        self.frame += 1;
+
+        let line: String = line.chars().take(self.width.saturating_sub(1)).collect();
+        // A closed stderr shouldn't abort the installation itself
+        let _ = match self.style {
+            ProgressStyle::Terminal => {
+                self.drawn = true;
+                write!(self.out, "\r\x1b[K{line}")
+            }
+            _ => writeln!(self.out, "{line}"),
+        };
+        let _ = self.out.flush();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Human-readable progress on stdout/stderr
+pub struct ConsoleReporter {
+    progress: Progress,
+}
+
+impl ConsoleReporter {
+    /// Create a reporter showing progress on stderr
+    pub fn new() -> Self {
+        Self::with_progress(Progress::stderr())
+    }
+
+    /// Create a reporter showing `progress`, such as [`Progress::hidden`]
+    pub fn with_progress(progress: Progress) -> Self {
+        Self { progress }
+    }
+}
+
+impl ConsoleReporter {
+    /// Run `print` without the progress line getting in the way
+    fn above_progress(&mut self, print: impl FnOnce()) {
+        self.progress.suspend();
+        print();
+        self.progress.resume();
+    }
+}
+
+impl Default for ConsoleReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reporter for ConsoleReporter {
+    fn discovered(&mut self, path: &Path, count: usize) {
+        self.progress.finish();
+        if count == 0 {
+            eprintln!("No git repositories found under: {}", path.display());
+        } else {
+            println!("Found {count} git repositories");
+        }
+    }
+
+    fn path_skipped(&mut self, path: &Path, reason: &str) {
+        self.above_progress(|| eprintln!("Skipping {}: {reason}", path.display()));
+    }
+
+    fn searching(&mut self, dir: &Path, found: usize) {
+        self.progress.searching(dir, found);
+    }
+
+    fn install_progress(&mut self, repo: &Path, current: usize, total: usize) {
+        self.progress.installing(repo, current, total);
+    }
+
+    fn repo_started(&mut self, repo: &Path) {
+        self.above_progress(|| println!("Installing hooks to: {}", repo.display()));
+    }
+
+    fn verification_failed(&mut self, repo: &Path, hook: &str, error: &str) {
+        self.above_progress(|| eprintln!("Verifying {hook} in {} failed: {error}", repo.display()));
+    }
+
+    fn post_install_hook_failed(&mut self, repo: &Path, error: &str) {
+        self.above_progress(|| {
+            eprintln!(
+                "Warning: post-install hook for {} failed: {error}",
+                repo.display()
+            )
+        });
+    }
+
+    fn repo_finished(&mut self, result: &RepoResult) {
+        self.above_progress(|| match (&result.status, &result.error_message) {
+            (RepoStatus::Skipped, Some(reason)) => {
+                println!("Skipping {}: {reason}", result.repo.display())
+            }
+            (_, Some(message)) => eprintln!(
+                "Failed to install hooks to {}: {message}",
+                result.repo.display()
+            ),
+            (_, None) => {}
+        });
+    }
+
+    fn finished(&mut self, results: &[RepoResult]) {
+        self.progress.finish();
+        if results.is_empty() {
+            return;
+        }
+
+        let installed = results
+            .iter()
+            .filter(|r| r.status == RepoStatus::Installed)
+            .count();
+        if installed == results.len() {
+            println!("Successfully installed hooks to all repositories");
+        } else {
+            println!("{}", summary(results, installed));
+        }
+    }
+}
+
+/// Summary line for `add` when not every repository got hooks, with the
+/// skipped repositories counted by reason
+fn summary(results: &[RepoResult], installed: usize) -> String {
+    let mut skipped: BTreeMap<&str, usize> = BTreeMap::new();
+    for result in results.iter().filter(|r| r.status == RepoStatus::Skipped) {
+        *skipped
+            .entry(result.error_message.as_deref().unwrap_or("skipped"))
+            .or_default() += 1;
+    }
+
+    let mut summary = format!(
+        "Installed hooks to {installed} of {} repositories",
+        results.len()
+    );
+    for (reason, count) in skipped {
+        summary.push_str(&format!(", skipped {count} ({reason})"));
+    }
+    summary
+}
+
+/// Machine-readable output: one JSON object per processed repository,
+/// flushed immediately so consumers can stream it
+pub struct NdjsonReporter<W: Write> {
+    out: W,
+}
+
+impl NdjsonReporter<io::Stdout> {
+    /// Create a reporter writing to stdout
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write> NdjsonReporter<W> {
+    /// Create a reporter writing to the given writer
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// Format a result as a single JSON line (without the newline)
+    pub fn format_result(result: &RepoResult) -> String {
+        let mut record = json!({
+            "repo": result.repo.display().to_string(),
+            "status": result.status.as_str(),
+            "hooks": result.hooks,
+            "error_message": result.error_message,
+        });
+        // Only `add --verify` adds the verification results
+        if !result.verification.is_empty() {
+            let verification: BTreeMap<&String, &bool> = result.verification.iter().collect();
+            record["verification"] = json!(verification);
+        }
+        record.to_string()
+    }
+}
+
+impl<W: Write> Reporter for NdjsonReporter<W> {
+    fn discovered(&mut self, _path: &Path, _count: usize) {}
+
+    fn path_skipped(&mut self, path: &Path, reason: &str) {
+        self.repo_finished(&RepoResult {
+            repo: path.to_path_buf(),
+            status: RepoStatus::Skipped,
+            hooks: Vec::new(),
+            error_message: Some(reason.to_string()),
+            verification: HashMap::new(),
+        });
+    }
+
+    fn repo_started(&mut self, _repo: &Path) {}
+
+    fn post_install_hook_failed(&mut self, repo: &Path, error: &str) {
+        // Warnings stay off stdout, which only has the JSON lines
+        eprintln!(
+            "Warning: post-install hook for {} failed: {error}",
+            repo.display()
+        );
+    }
+
+    fn repo_finished(&mut self, result: &RepoResult) {
+        // A closed pipe shouldn't abort the installation itself
+        let _ = writeln!(self.out, "{}", Self::format_result(result));
+        let _ = self.out.flush();
+    }
+
+    fn finished(&mut self, _results: &[RepoResult]) {}
+}
+
+/// The results of `add` as a Markdown table of each repository, its status
+/// and the hooks installed to it, for a GitHub Actions step summary
+pub fn markdown_table(results: &[RepoResult]) -> String {
+    let mut table =
+        String::from("| Repository | Status | Hooks Installed |\n| --- | --- | --- |\n");
+    for result in results {
+        let status = match (&result.status, &result.error_message) {
+            (RepoStatus::Installed, _) if !result.unverified_hooks().is_empty() => format!(
+                ":x: verification failed: {}",
+                result.unverified_hooks().join(", ")
+            ),
+            (RepoStatus::Installed, _) => ":white_check_mark: installed".to_string(),
+            (RepoStatus::Skipped, Some(reason)) => format!("skipped: {reason}"),
+            (RepoStatus::Skipped, None) => "skipped".to_string(),
+            (RepoStatus::Error, Some(message)) => format!(":x: {message}"),
+            (RepoStatus::Error, None) => ":x: error".to_string(),
+        };
+        let hooks: Vec<String> = result.hooks.iter().map(|h| format!("`{h}`")).collect();
+        table.push_str(&format!(
+            "| {} | {} | {} |\n",
+            markdown_cell(&result.repo.display().to_string()),
+            markdown_cell(&status),
+            hooks.join(", ")
+        ));
+    }
+    table
+}
+
+/// `s` as the content of a Markdown table cell, on one line and with its
+/// `|`s escaped
+fn markdown_cell(s: &str) -> String {
+    s.replace(['\r', '\n'], " ").replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Buffer = RefCell<Vec<u8>>;
+
+    /// A writer whose output stays readable after it's boxed
+    #[derive(Clone, Default)]
+    struct Shared(Rc<Buffer>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.borrow_mut())).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_terminal_progress_is_redrawn_in_place() {
+        let out = Shared::default();
+        let mut progress = Progress::new(ProgressStyle::Terminal, Box::new(out.clone()));
+        progress.width = 40;
+        progress.interval = Duration::ZERO;
+
+        progress.searching(Path::new("/projects"), 3);
+        assert_eq!(
+            out.take(),
+            "\r\x1b[K| Searching (3 repositories found) /pro"
+        );
+        progress.installing(Path::new("/projects/api"), 3, 4);
+        assert_eq!(out.take(), "\r\x1b[K[============>            ] 3/4 api");
+
+        // Other output goes above the line
+        progress.suspend();
+        assert_eq!(out.take(), "\r\x1b[K");
+        progress.resume();
+        assert_eq!(out.take(), "\r\x1b[K[============>            ] 3/4 api");
+
+        progress.finish();
+        assert_eq!(out.take(), "\r\x1b[K");
+        progress.resume();
+        drop(progress);
+        assert_eq!(out.take(), "");
+    }
+
+    #[test]
+    fn test_periodic_progress_prints_status_lines() {
+        let out = Shared::default();
+        let mut progress = Progress::new(ProgressStyle::Periodic, Box::new(out.clone()));
+        // Short runs print no status at all
+        progress.searching(Path::new("/projects"), 0);
+        assert_eq!(out.take(), "");
+
+        progress.interval = Duration::ZERO;
+        progress.installing(Path::new("/projects/api"), 1, 2);
+        progress.suspend();
+        progress.resume();
+        progress.finish();
+        assert_eq!(
+            out.take(),
+            "Installing hooks: 1 of 2 repositories, at api\n"
+        );
+    }
+
+    #[test]
+    fn test_hidden_progress_prints_nothing() {
+        let mut progress = Progress::hidden();
+        progress.interval = Duration::ZERO;
+        progress.searching(Path::new("/projects"), 1);
+        assert!(progress.line.is_none());
+        assert!(!progress.drawn);
+    }
+
+    #[test]
+    fn test_summary_counts_skipped_repositories() {
+        let result = |repo: &str, status, reason: Option<&str>| RepoResult {
+            repo: PathBuf::from(repo),
+            status,
+            hooks: Vec::new(),
+            error_message: reason.map(str::to_string),
+            verification: HashMap::new(),
+        };
+        let results = [
+            result("api", RepoStatus::Installed, None),
+            result("web", RepoStatus::Skipped, Some("no config")),
+            result("cli", RepoStatus::Error, Some("permission denied")),
+            result("docs", RepoStatus::Skipped, Some("no config")),
+        ];
+        assert_eq!(
+            summary(&results, 1),
+            "Installed hooks to 1 of 4 repositories, skipped 2 (no config)"
+        );
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let results = [
+            RepoResult {
+                repo: PathBuf::from("/projects/api"),
+                status: RepoStatus::Installed,
+                hooks: vec!["pre-commit".to_string(), "pre-push".to_string()],
+                error_message: None,
+                verification: HashMap::new(),
+            },
+            RepoResult {
+                repo: PathBuf::from("/projects/web"),
+                status: RepoStatus::Installed,
+                hooks: vec!["pre-commit".to_string()],
+                error_message: None,
+                verification: HashMap::from([("pre-commit".to_string(), false)]),
+            },
+            RepoResult {
+                repo: PathBuf::from("/projects/docs"),
+                status: RepoStatus::Skipped,
+                hooks: Vec::new(),
+                error_message: Some("no config".to_string()),
+                verification: HashMap::new(),
+            },
+            RepoResult {
+                repo: PathBuf::from("/projects/a|b"),
+                status: RepoStatus::Error,
+                hooks: Vec::new(),
+                error_message: Some("Permission denied\nwhile writing".to_string()),
+                verification: HashMap::new(),
+            },
+        ];
+        assert_eq!(
+            markdown_table(&results),
+            "| Repository | Status | Hooks Installed |\n\
+             | --- | --- | --- |\n\
+             | /projects/api | :white_check_mark: installed | `pre-commit`, `pre-push` |\n\
+             | /projects/web | :x: verification failed: pre-commit | `pre-commit` |\n\
+             | /projects/docs | skipped: no config |  |\n\
+             | /projects/a\\|b | :x: Permission denied while writing |  |\n"
+        );
+    }
+
+    #[test]
+    fn test_ndjson_reporter_writes_one_line_per_repo() {
+        let mut out = Vec::new();
+        {
+            let mut reporter = NdjsonReporter::new(&mut out);
+            reporter.discovered(Path::new("/projects"), 2);
+            reporter.repo_started(Path::new("/projects/a"));
+            reporter.repo_finished(&RepoResult {
+                repo: PathBuf::from("/projects/a"),
+                status: RepoStatus::Installed,
+                hooks: vec!["pre-commit".to_string(), "pre-push".to_string()],
+                error_message: None,
+                verification: HashMap::from([
+                    ("pre-push".to_string(), false),
+                    ("pre-commit".to_string(), true),
+                ]),
+            });
+            reporter.repo_finished(&RepoResult {
+                repo: PathBuf::from("/projects/b"),
+                status: RepoStatus::Error,
+                hooks: Vec::new(),
+                error_message: Some("Permission denied".to_string()),
+                verification: HashMap::new(),
+            });
+            reporter.finished(&[]);
+        }
+
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"repo":"/projects/a","status":"installed","hooks":["pre-commit","pre-push"],"error_message":null,"verification":{"pre-commit":true,"pre-push":false}}"#,
+                r#"{"repo":"/projects/b","status":"error","hooks":[],"error_message":"Permission denied"}"#,
+            ]
+        );
+    }
+}
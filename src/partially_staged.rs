@@ -0,0 +1,121 @@
+//! The check `pre-commit-partially-staged` makes before `pre-commit`: a file
+//! with changes both staged and not is checked as it is in the working
+//! tree, which isn't what gets committed
+
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Name of the check in `HOOKMASTER_SKIP`
+pub const CHECK_NAME: &str = "partially-staged";
+
+/// The files in `status`, the output of `git status --porcelain -z`, that
+/// have staged changes and further ones in the working tree
+fn parse_status(status: &str) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut entries = status.split('\0');
+    while let Some(entry) = entries.next() {
+        let mut chars = entry.chars();
+        let (Some(staged), Some(unstaged)) = (chars.next(), chars.next()) else {
+            continue;
+        };
+        // Renames and copies are followed by the path they came from
+        if matches!(staged, 'R' | 'C') {
+            entries.next();
+        }
+        if matches!(staged, 'M' | 'A' | 'R' | 'C') && matches!(unstaged, 'M' | 'D') {
+            files.push(entry[3..].to_string());
+        }
+    }
+    files
+}
+
+/// The files of the repository at `root` with changes both staged and not
+pub fn partially_staged_files(root: &Path) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "-z", "--untracked-files=no"])
+        .current_dir(root)
+        .output()
+        .context("Failed to run git status")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git status failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_status(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Whether `skip`, the value of `HOOKMASTER_SKIP`, skips the check: `1`,
+/// or a comma-separated list naming it
+pub fn is_skipped(skip: Option<&str>) -> bool {
+    skip.is_some_and(|skip| {
+        skip.trim() == "1" || skip.split(',').any(|name| name.trim() == CHECK_NAME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_status() {
+        let status = [
+            "MM src/lib.rs",
+            "M  src/main.rs",
+            " M README.md",
+            "AM new.rs",
+            "MD gone.rs",
+            "RM renamed.rs",
+            "old.rs",
+            "R  moved.rs",
+            "before.rs",
+            "A  added.rs",
+            "",
+        ]
+        .join("\0");
+        assert_eq!(
+            parse_status(&status),
+            ["src/lib.rs", "new.rs", "gone.rs", "renamed.rs"]
+        );
+    }
+
+    #[test]
+    fn test_is_skipped() {
+        assert!(is_skipped(Some("1")));
+        assert!(is_skipped(Some("partially-staged")));
+        assert!(is_skipped(Some("lint, partially-staged")));
+        assert!(!is_skipped(Some("lint")));
+        assert!(!is_skipped(Some("0")));
+        assert!(!is_skipped(None));
+    }
+
+    #[test]
+    fn test_partially_staged_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q"]);
+        fs::write(root.join("a.txt"), "one\n").unwrap();
+        fs::write(root.join("b.txt"), "one\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+
+        fs::write(root.join("a.txt"), "two\n").unwrap();
+        fs::write(root.join("b.txt"), "two\n").unwrap();
+        git(&["add", "."]);
+        fs::write(root.join("a.txt"), "three\n").unwrap();
+        fs::write(root.join("untracked.txt"), "x\n").unwrap();
+        assert_eq!(partially_staged_files(root).unwrap(), ["a.txt"]);
+    }
+}
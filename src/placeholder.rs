@@ -0,0 +1,172 @@
+use std::convert::Infallible;
+
+/// Replace `{name}` placeholders in `text`.
+///
+/// `lookup` is called with each placeholder name and returns `None` for names
+/// it doesn't handle, which are left as written so a later stage can replace
+/// them. `{{name}}` is an escape for a literal `{name}`; it is unescaped by the
+/// stage that handles `name`. Braces that don't form a placeholder, such as
+/// `{}` in `find -exec` or an awk program, are kept verbatim.
+pub fn substitute<E, F>(text: &str, mut lookup: F) -> Result<String, E>
+where
+    F: FnMut(&str) -> Option<Result<String, E>>,
+{
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(name) = escaped_name(rest) {
+            let len = name.len() + 4;
+            if lookup(name).is_some() {
+                result.push('{');
+                result.push_str(name);
+                result.push('}');
+            } else {
+                result.push_str(&rest[..len]);
+            }
+            rest = &rest[len..];
+        } else if let Some(name) = placeholder_name(rest) {
+            match lookup(name) {
+                Some(value) => result.push_str(&value?),
+                None => result.push_str(&rest[..name.len() + 2]),
+            }
+            rest = &rest[name.len() + 2..];
+        } else {
+            result.push('{');
+            rest = &rest[1..];
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Replace every `{name}` placeholder with `value`
+pub fn replace(text: &str, name: &str, value: &str) -> String {
+    let result: Result<String, Infallible> =
+        substitute(text, |n| (n == name).then(|| Ok(value.to_string())));
+    match result {
+        Ok(replaced) => replaced,
+        Err(never) => match never {},
+    }
+}
+
+/// Names of the (unescaped) placeholders in `text`, in order of appearance
+pub fn names(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start..];
+        if let Some(name) = escaped_name(rest) {
+            rest = &rest[name.len() + 4..];
+        } else if let Some(name) = placeholder_name(rest) {
+            names.push(name);
+            rest = &rest[name.len() + 2..];
+        } else {
+            rest = &rest[1..];
+        }
+    }
+
+    names
+}
+
+/// Name of the `{name}` placeholder at the start of `text`
+fn placeholder_name(text: &str) -> Option<&str> {
+    let inner = text.strip_prefix('{')?;
+    let end = inner.find('}')?;
+    let name = &inner[..end];
+    is_name(name).then_some(name)
+}
+
+/// Name of the `{{name}}` escape at the start of `text`
+fn escaped_name(text: &str) -> Option<&str> {
+    let name = placeholder_name(text.strip_prefix('{')?)?;
+    text[name.len() + 3..].starts_with('}').then_some(name)
+}
+
+/// Placeholder names start with a letter or `_` and may contain `.` and `-`
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Result of looking up a placeholder in the tests
+    type Lookup = Option<Result<String, String>>;
+
+    fn vars(name: &str) -> Lookup {
+        let var = name.strip_prefix("vars.")?;
+        Some(match var {
+            "flags" => Ok("--workspace --all-targets".to_string()),
+            _ => Err(format!("undefined variable '{var}'")),
+        })
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(
+            substitute("cargo clippy {vars.flags} -- -D warnings", vars).unwrap(),
+            "cargo clippy --workspace --all-targets -- -D warnings"
+        );
+        assert_eq!(
+            substitute("{vars.flags}{vars.flags}", vars).unwrap(),
+            "--workspace --all-targets--workspace --all-targets"
+        );
+        assert_eq!(
+            substitute("echo {vars.missing}", vars).unwrap_err(),
+            "undefined variable 'missing'"
+        );
+    }
+
+    #[test]
+    fn test_substitute_keeps_other_placeholders_and_braces() {
+        for text in [
+            "npm test {dir}",
+            "find . -exec rm {} \\;",
+            "awk '{print $1}'",
+            "echo ${HOME} {",
+            "echo }{ {{ }}",
+            "echo {{dir}}",
+        ] {
+            assert_eq!(substitute(text, vars).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_escaped_placeholders() {
+        assert_eq!(
+            substitute("echo {{vars.flags}} {vars.flags}", vars).unwrap(),
+            "echo {vars.flags} --workspace --all-targets"
+        );
+        // Escapes are left for the stage that handles them
+        let text = substitute("cd {{dir}} && make {vars.flags}", vars).unwrap();
+        assert_eq!(text, "cd {{dir}} && make --workspace --all-targets");
+        assert_eq!(
+            replace(&text, "dir", "packages/a"),
+            "cd {dir} && make --workspace --all-targets"
+        );
+        // Braces around a placeholder are kept
+        assert_eq!(
+            substitute("sh -c '{ make {vars.flags}; }'", vars).unwrap(),
+            "sh -c '{ make --workspace --all-targets; }'"
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            names("{vars.a} {{vars.b}} {dir} {} {vars.a}"),
+            vec!["vars.a", "dir", "vars.a"]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Starting configurations for `hookmaster init --preset`
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{GitHooksConfig, HookSpec};
+
+/// Starting configuration for `hookmaster init --preset <name>`
+#[derive(Debug)]
+pub enum Preset {
+    /// `cargo fmt` and `cargo clippy` before commit, `cargo test` before push
+    Rust,
+    /// `npm run lint` before commit, `npm test` before push
+    Node,
+    /// `black` and `flake8` before commit
+    Python,
+    /// A preset from `~/.hookmaster/presets/<name>.toml`
+    Custom {
+        /// File name of the preset without the extension
+        name: String,
+        /// Configuration read from the file
+        config: Box<GitHooksConfig>,
+    },
+}
+
+impl Preset {
+    /// Names of the built-in presets
+    pub const BUILT_IN: [&'static str; 3] = ["rust", "node", "python"];
+
+    /// Find a preset by name. Custom presets are looked up in `presets_dir`
+    /// and take precedence over built-in presets of the same name.
+    pub fn find(name: &str, presets_dir: Option<&Path>) -> Result<Self> {
+        if let Some(path) = presets_dir.map(|dir| dir.join(format!("{name}.toml"))) {
+            if path.is_file() {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read preset: {}", path.display()))?;
+                let config = GitHooksConfig::parse_toml(&content)
+                    .with_context(|| format!("Failed to parse preset: {}", path.display()))?;
+                return Ok(Preset::Custom {
+                    name: name.to_string(),
+                    config: Box::new(config),
+                });
+            }
+        }
+
+        match name {
+            "rust" => Ok(Preset::Rust),
+            "node" => Ok(Preset::Node),
+            "python" => Ok(Preset::Python),
+            _ => Err(anyhow!(
+                "Unknown preset '{}'. Run 'hookmaster init --list-presets' to see the available presets.",
+                name
+            )),
+        }
+    }
+
+    /// Directory custom presets are read from: `~/.hookmaster/presets`
+    pub fn custom_dir() -> Option<PathBuf> {
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+        Some(PathBuf::from(home).join(".hookmaster").join("presets"))
+    }
+
+    /// Names of the custom presets in `presets_dir`, sorted
+    pub fn custom_names(presets_dir: &Path) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(presets_dir) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Name of the preset
+    pub fn name(&self) -> &str {
+        match self {
+            Preset::Rust => "rust",
+            Preset::Node => "node",
+            Preset::Python => "python",
+            Preset::Custom { name, .. } => name,
+        }
+    }
+
+    /// The configuration `init` writes for this preset
+    pub fn to_config(&self) -> GitHooksConfig {
+        let commands = match self {
+            Preset::Rust => vec![
+                (
+                    "pre-commit",
+                    "cargo fmt --check && cargo clippy -- -D warnings",
+                ),
+                ("pre-push", "cargo test"),
+            ],
+            Preset::Node => vec![("pre-commit", "npm run lint"), ("pre-push", "npm test")],
+            Preset::Python => vec![("pre-commit", "black --check . && flake8")],
+            Preset::Custom { config, .. } => return config.as_ref().clone(),
+        };
+
+        let hooks: HashMap<String, HookSpec> = commands
+            .iter()
+            .map(|(hook, command)| (hook.to_string(), HookSpec::from_command(hook, command)))
+            .collect();
+        GitHooksConfig {
+            hooks,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_built_in_presets() {
+        let config = Preset::find("rust", None).unwrap().to_config();
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("cargo fmt --check && cargo clippy -- -D warnings")
+        );
+        assert_eq!(config.get_hook_command("pre-push"), Some("cargo test"));
+
+        let config = Preset::find("node", None).unwrap().to_config();
+        assert_eq!(config.get_hook_command("pre-commit"), Some("npm run lint"));
+        assert_eq!(config.get_hook_command("pre-push"), Some("npm test"));
+
+        let config = Preset::find("python", None).unwrap().to_config();
+        assert_eq!(
+            config.get_hook_command("pre-commit"),
+            Some("black --check . && flake8")
+        );
+        assert_eq!(config.hooks.len(), 1);
+
+        for name in Preset::BUILT_IN {
+            assert_eq!(Preset::find(name, None).unwrap().name(), name);
+        }
+    }
+
+    #[test]
+    fn test_custom_presets() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("go.toml"), "pre-commit = \"go vet ./...\"\n").unwrap();
+        fs::write(dir.join("rust.toml"), "pre-push = \"cargo nextest run\"\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a preset").unwrap();
+
+        assert_eq!(Preset::custom_names(dir), vec!["go", "rust"]);
+
+        let preset = Preset::find("go", Some(dir)).unwrap();
+        assert_eq!(preset.name(), "go");
+        assert_eq!(
+            preset.to_config().get_hook_command("pre-commit"),
+            Some("go vet ./...")
+        );
+
+        // A custom preset replaces the built-in one of the same name
+        let config = Preset::find("rust", Some(dir)).unwrap().to_config();
+        assert_eq!(
+            config.get_hook_command("pre-push"),
+            Some("cargo nextest run")
+        );
+        assert_eq!(config.get_hook_command("pre-commit"), None);
+
+        fs::write(dir.join("broken.toml"), "pre-commit = [").unwrap();
+        assert!(Preset::find("broken", Some(dir)).is_err());
+        let err = Preset::find("missing", Some(dir)).unwrap_err();
+        assert!(err.to_string().starts_with("Unknown preset 'missing'"));
+    }
+}
@@ -0,0 +1,274 @@
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
+#[cfg(unix)]
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hook_manager::Signal;
+
+/// How often a child with a timeout is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How a child process that runs past its timeout is stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeoutKill {
+    /// Signal sent when the timeout expires
+    pub signal: Signal,
+    /// How long the child has to exit after the signal before it is killed
+    pub grace: Duration,
+}
+
+/// Whether hookmaster has a controlling terminal. Commands then stay in
+/// its process group, where the terminal lets them read from it and
+/// Ctrl-C reaches them; in a group of their own, one reading from the
+/// terminal would be stopped for good.
+pub fn has_terminal() -> bool {
+    #[cfg(unix)]
+    {
+        static HAS_TERMINAL: OnceLock<bool> = OnceLock::new();
+        *HAS_TERMINAL.get_or_init(|| std::fs::File::open("/dev/tty").is_ok())
+    }
+    #[cfg(not(unix))]
+    false
+}
+
+/// Have `command` start in a process group of its own when hookmaster has
+/// no controlling terminal, so that signals for it reach the processes it
+/// starts as well, such as the commands a shell runs. Does nothing on
+/// Windows.
+pub fn own_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    if !has_terminal() {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// Wait for a child process, sending it `kill.signal` if it runs longer than
+/// `timeout` and killing it if it is still running `kill.grace` later. A
+/// child started with [`own_process_group`] gets the signals along with its
+/// process group. A timeout is reported as an error of kind
+/// [`io::ErrorKind::TimedOut`].
+pub fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    kill: TimeoutKill,
+) -> io::Result<ExitStatus> {
+    let Some(timeout) = timeout else {
+        return child.wait();
+    };
+
+    let deadline = Instant::now() + timeout;
+    let mut kill_at = None;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return match kill_at {
+                Some(_) => Err(timed_out(timeout)),
+                None => Ok(status),
+            };
+        }
+        let now = Instant::now();
+        match kill_at {
+            None if now >= deadline => {
+                if kill.signal == Signal::Kill || send_signal(child, kill.signal).is_err() {
+                    kill_child(child)?;
+                    return Err(timed_out(timeout));
+                }
+                kill_at = Some(now + kill.grace);
+            }
+            Some(kill_at) if now >= kill_at => {
+                kill_child(child)?;
+                return Err(timed_out(timeout));
+            }
+            _ => {}
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn timed_out(timeout: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("timed out after {} seconds", timeout.as_secs()),
+    )
+}
+
+/// Send `signal` to `child`, and to its process group if it leads one
+#[cfg(unix)]
+fn send_signal(child: &mut Child, signal: Signal) -> io::Result<()> {
+    signal_group(child.id(), signal.number())
+}
+
+/// Send `signal` to the process group led by `pid`, or to the process `pid`
+/// alone if it doesn't lead one
+#[cfg(unix)]
+pub(crate) fn signal_group(pid: u32, signal: i32) -> io::Result<()> {
+    let pid =
+        libc::pid_t::try_from(pid).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: kill has no memory-safety requirements
+    if unsafe { libc::kill(-pid, signal) } == 0 || unsafe { libc::kill(pid, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Windows has no signals, so `child` is terminated whatever `signal` is.
+/// Sending `CTRL_BREAK_EVENT` with `GenerateConsoleCtrlEvent` in place of
+/// the gentler signals would need commands started with
+/// `CREATE_NEW_PROCESS_GROUP`, which they aren't.
+#[cfg(not(unix))]
+fn send_signal(child: &mut Child, _signal: Signal) -> io::Result<()> {
+    child.kill()
+}
+
+/// Kill `child` along with its process group, and wait for it
+fn kill_child(child: &mut Child) -> io::Result<()> {
+    if send_signal(child, Signal::Kill).is_err() {
+        child.kill()?;
+    }
+    child.wait().map(drop)
+}
+
+/// Find an executable on PATH. On Windows, the extensions in `PATHEXT` are
+/// tried as well.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    let mut names = vec![OsString::from(name)];
+    if cfg!(target_os = "windows") {
+        let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        names.extend(
+            extensions
+                .split(';')
+                .filter(|ext| !ext.is_empty())
+                .map(|ext| OsString::from(format!("{name}{ext}"))),
+        );
+    }
+
+    env::split_paths(&path).find_map(|dir| {
+        names
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Quote `arg` for a POSIX shell, leaving it as is when that's safe
+pub fn shell_quote(arg: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn kill(signal: Signal, grace: Duration) -> TimeoutKill {
+        TimeoutKill { signal, grace }
+    }
+
+    #[test]
+    fn test_wait_without_timeout() {
+        let mut child = Command::new("sh").args(["-c", "exit 2"]).spawn().unwrap();
+        let status =
+            wait_with_timeout(&mut child, None, kill(Signal::Term, Duration::ZERO)).unwrap();
+        assert_eq!(status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_wait_kills_after_timeout() {
+        for signal in [Signal::Term, Signal::Kill, Signal::Number(9)] {
+            let mut child = Command::new("sh").args(["-c", "sleep 5"]).spawn().unwrap();
+            let started = Instant::now();
+            let err = wait_with_timeout(
+                &mut child,
+                Some(Duration::from_millis(100)),
+                kill(signal, Duration::from_secs(5)),
+            )
+            .unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert!(started.elapsed() < Duration::from_secs(5), "{signal:?}");
+        }
+    }
+
+    #[test]
+    fn test_wait_kills_process_group_after_timeout() {
+        use std::io::Read;
+        use std::sync::mpsc;
+
+        for signal in [Signal::Term, Signal::Kill] {
+            let mut command = Command::new("sh");
+            command
+                .args(["-c", "sleep 777 && true"])
+                .stdout(Stdio::piped())
+                .process_group(0);
+            let mut child = command.spawn().unwrap();
+            let mut stdout = child.stdout.take().unwrap();
+            let err = wait_with_timeout(
+                &mut child,
+                Some(Duration::from_millis(100)),
+                kill(signal, Duration::from_secs(5)),
+            )
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+            // The pipe closes once `sleep`, which holds it too, is gone
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || sender.send(stdout.read(&mut [0; 1]).unwrap()));
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)),
+                Ok(0),
+                "{signal:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wait_sends_signal_before_killing() {
+        let temp_dir = TempDir::new().unwrap();
+        let report = temp_dir.path().join("report");
+        // Writes a report on SIGINT, and ignores SIGTERM until killed
+        let script = format!(
+            "trap 'echo partial > {}; exit 1' INT; trap '' TERM; while :; do sleep 0.05; done",
+            report.display()
+        );
+
+        let mut child = Command::new("sh").args(["-c", &script]).spawn().unwrap();
+        let err = wait_with_timeout(
+            &mut child,
+            Some(Duration::from_millis(200)),
+            kill(Signal::Int, Duration::from_secs(5)),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(fs::read_to_string(&report).unwrap(), "partial\n");
+
+        let mut child = Command::new("sh").args(["-c", &script]).spawn().unwrap();
+        let started = Instant::now();
+        let err = wait_with_timeout(
+            &mut child,
+            Some(Duration::from_millis(100)),
+            kill(Signal::Term, Duration::from_millis(300)),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
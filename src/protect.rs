@@ -0,0 +1,391 @@
+//! The checks `[protect]` makes of what `pre-push` pushes to protected
+//! branches
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use std::path::Path;
+use std::process::Command;
+
+use crate::commit_msg::CommitMessageProcessor;
+use crate::config::ProtectSettings;
+use crate::fanout;
+
+/// Branches the checks apply to unless `branches` names others
+pub const DEFAULT_BRANCHES: [&str; 2] = ["main", "master"];
+
+/// Subject prefixes of the commits `git rebase --autosquash` folds into others
+const AUTOSQUASH_PREFIXES: [&str; 3] = ["fixup!", "squash!", "amend!"];
+
+/// SHA git gives for a ref that doesn't exist, such as a new remote branch
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// A commit `pre-push` pushes
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushedCommit {
+    /// Full SHA of the commit
+    pub sha: String,
+    /// First line of its message
+    pub subject: String,
+    /// Its whole message
+    pub message: String,
+}
+
+/// A ref `pre-push` pushes, from a line of its stdin
+struct PushedRef<'a> {
+    local_sha: &'a str,
+    remote_ref: &'a str,
+    remote_sha: &'a str,
+}
+
+/// The refs in `input`, what git gives `pre-push` on stdin: a
+/// `<local ref> <local sha> <remote ref> <remote sha>` line per ref.
+/// Deleted refs push no commits and are left out.
+fn pushed_refs(input: &str) -> Vec<PushedRef<'_>> {
+    input
+        .lines()
+        .filter_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [_, local_sha, remote_ref, remote_sha] if local_sha != NULL_SHA => {
+                    Some(PushedRef {
+                        local_sha,
+                        remote_ref,
+                        remote_sha,
+                    })
+                }
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// The commits pushed to protected branches whose subject marks them as
+/// unfinished, for `forbid_unfinished_commits`. `remote` is the name of
+/// the remote pushed to, and `input` what git gives `pre-push` on stdin.
+pub fn unfinished_commits(
+    root: &Path,
+    remote: &str,
+    input: &str,
+    settings: &ProtectSettings,
+) -> Result<Vec<PushedCommit>> {
+    let patterns = settings
+        .forbidden_subject_patterns
+        .iter()
+        .map(|pattern| Regex::new(pattern).with_context(|| format!("Invalid pattern '{pattern}'")))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut unfinished: Vec<PushedCommit> = Vec::new();
+    for pushed in pushed_refs(input) {
+        if !is_protected(pushed.remote_ref, settings) {
+            continue;
+        }
+        for commit in new_commits(root, remote, &pushed, false)? {
+            let is_unfinished = is_unfinished_subject(&commit.subject)
+                || patterns.iter().any(|re| re.is_match(&commit.subject));
+            if is_unfinished && !unfinished.contains(&commit) {
+                unfinished.push(commit);
+            }
+        }
+    }
+    Ok(unfinished)
+}
+
+/// The commits other than merges pushed to any branch whose message
+/// references no ticket, for `require_ticket_in_pushed_commits`
+pub fn commits_without_ticket(
+    root: &Path,
+    remote: &str,
+    input: &str,
+    processor: &CommitMessageProcessor,
+) -> Result<Vec<PushedCommit>> {
+    let mut missing: Vec<PushedCommit> = Vec::new();
+    for pushed in pushed_refs(input) {
+        if !pushed.remote_ref.starts_with("refs/heads/") {
+            continue;
+        }
+        for commit in new_commits(root, remote, &pushed, true)? {
+            if !processor.has_ticket(&commit.message) && !missing.contains(&commit) {
+                missing.push(commit);
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// Whether the checks apply to pushing to `remote_ref`
+fn is_protected(remote_ref: &str, settings: &ProtectSettings) -> bool {
+    let Some(branch) = remote_ref.strip_prefix("refs/heads/") else {
+        return false;
+    };
+    match &settings.branches {
+        Some(branches) => branches
+            .iter()
+            .any(|pattern| fanout::wildcard_match(pattern, branch)),
+        None => DEFAULT_BRANCHES.contains(&branch),
+    }
+}
+
+/// Whether `subject` is of a commit meant to be squashed or finished
+/// before it is shared
+fn is_unfinished_subject(subject: &str) -> bool {
+    AUTOSQUASH_PREFIXES
+        .iter()
+        .any(|prefix| subject.starts_with(prefix))
+        || subject
+            .strip_prefix("WIP")
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric()))
+}
+
+/// The commits `pushed` sends to `remote`, newest first: those since the
+/// remote branch or, for a new branch, since the remote's default branch,
+/// leaving out any the remote already has
+fn new_commits(
+    root: &Path,
+    remote: &str,
+    pushed: &PushedRef,
+    no_merges: bool,
+) -> Result<Vec<PushedCommit>> {
+    let mut args = vec![
+        "log".to_string(),
+        "-z".to_string(),
+        "--format=%H%n%B".to_string(),
+    ];
+    if no_merges {
+        args.push("--no-merges".to_string());
+    }
+    args.push(pushed.local_sha.to_string());
+    let known = pushed.remote_sha != NULL_SHA && is_commit(root, pushed.remote_sha);
+    if known {
+        args.push(format!("^{}", pushed.remote_sha));
+    } else if let Some(default) = default_branch(root, remote) {
+        args.push(format!("^{default}"));
+    }
+    args.extend(["--not".to_string(), format!("--remotes={remote}")]);
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let output = git(root, &args)?;
+    Ok(output
+        .split('\0')
+        .filter_map(|record| {
+            let (sha, message) = record.trim_start_matches('\n').split_once('\n')?;
+            Some(PushedCommit {
+                sha: sha.to_string(),
+                subject: message.lines().next().unwrap_or_default().to_string(),
+                message: message.trim_end().to_string(),
+            })
+        })
+        .collect())
+}
+
+fn is_commit(root: &Path, sha: &str) -> bool {
+    git(root, &["cat-file", "-e", &format!("{sha}^{{commit}}")]).is_ok()
+}
+
+/// The commit of the default branch of `remote`, from its `HEAD` or
+/// otherwise its `main` or `master` branch
+fn default_branch(root: &Path, remote: &str) -> Option<String> {
+    ["HEAD", "main", "master"].iter().find_map(|branch| {
+        let name = format!("refs/remotes/{remote}/{branch}");
+        let sha = git(root, &["rev-parse", "-q", "--verify", &name]).ok()?;
+        Some(sha.trim().to_string())
+    })
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        super::git(
+            repo,
+            &[
+                &["-c", "user.name=Test", "-c", "user.email=test@example.com"],
+                args,
+            ]
+            .concat(),
+        )
+        .unwrap()
+        .trim()
+        .to_string()
+    }
+
+    fn commit(repo: &Path, subject: &str) -> String {
+        fs::write(repo.join("file.txt"), subject).unwrap();
+        git(repo, &["add", "file.txt"]);
+        git(repo, &["commit", "-q", "-m", subject]);
+        git(repo, &["rev-parse", "HEAD"])
+    }
+
+    fn settings() -> ProtectSettings {
+        ProtectSettings {
+            forbid_unfinished_commits: true,
+            ..ProtectSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_unfinished_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        git(repo, &["init", "-q"]);
+        let base = commit(repo, "Add the parser");
+        let fixup = commit(repo, "fixup! Add the parser");
+        commit(repo, "Add the formatter");
+        let head = commit(repo, "Do not merge: debugging");
+
+        let push = |remote_ref: &str, remote_sha: &str| {
+            format!("refs/heads/feature {head} {remote_ref} {remote_sha}\n")
+        };
+        let unfinished =
+            unfinished_commits(repo, "origin", &push("refs/heads/main", &base), &settings())
+                .unwrap();
+        assert_eq!(
+            unfinished,
+            vec![PushedCommit {
+                sha: fixup.clone(),
+                subject: "fixup! Add the parser".to_string(),
+                message: "fixup! Add the parser".to_string(),
+            }]
+        );
+
+        // Feature branches are unrestricted
+        let input = push("refs/heads/feature", &base);
+        assert!(unfinished_commits(repo, "origin", &input, &settings())
+            .unwrap()
+            .is_empty());
+
+        // A new branch, with further patterns
+        let settings = ProtectSettings {
+            branches: Some(vec!["release/*".to_string()]),
+            forbidden_subject_patterns: vec!["(?i)^do not merge".to_string()],
+            ..settings()
+        };
+        let input = push("refs/heads/release/1.0", NULL_SHA);
+        let subjects: Vec<String> = unfinished_commits(repo, "origin", &input, &settings)
+            .unwrap()
+            .into_iter()
+            .map(|commit| commit.subject)
+            .collect();
+        assert_eq!(
+            subjects,
+            ["Do not merge: debugging", "fixup! Add the parser"]
+        );
+        assert!(
+            unfinished_commits(repo, "origin", &push("refs/heads/main", &base), &settings)
+                .unwrap()
+                .is_empty()
+        );
+
+        // Deleting a protected branch
+        let input = format!("(delete) {NULL_SHA} refs/heads/main {base}\n");
+        assert!(
+            unfinished_commits(repo, "origin", &input, &self::settings())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_commits_without_ticket() {
+        let temp_dir = TempDir::new().unwrap();
+        let remote = temp_dir.path().join("remote.git");
+        let repo = &temp_dir.path().join("repo");
+        git(temp_dir.path(), &["init", "-q", "--bare", "remote.git"]);
+        git(temp_dir.path(), &["init", "-q", "-b", "main", "repo"]);
+        git(
+            repo,
+            &["remote", "add", "origin", &remote.display().to_string()],
+        );
+        commit(repo, "Initial commit");
+        git(repo, &["push", "-q", "origin", "main"]);
+
+        git(repo, &["checkout", "-q", "-b", "side"]);
+        fs::write(repo.join("side.txt"), "side").unwrap();
+        git(repo, &["add", "side.txt"]);
+        git(repo, &["commit", "-q", "-m", "JIRA-3: Side work"]);
+        git(repo, &["checkout", "-q", "-b", "feature", "main"]);
+        commit(repo, "JIRA-2: Add the parser");
+        let typo = commit(repo, "Fix a typo");
+        fs::write(repo.join("other.txt"), "x").unwrap();
+        git(repo, &["add", "other.txt"]);
+        git(repo, &["commit", "-q", "-m", "Add other.txt\n\nFor JIRA-4"]);
+        // The merge commit has no ticket, but merges aren't checked
+        git(
+            repo,
+            &[
+                "merge",
+                "-q",
+                "--no-ff",
+                "side",
+                "-m",
+                "Merge branch 'side'",
+            ],
+        );
+        let head = git(repo, &["rev-parse", "HEAD"]);
+
+        // A new branch is checked from the default branch, which has the
+        // initial commit without a ticket
+        let processor = CommitMessageProcessor::new();
+        let input = format!("refs/heads/feature {head} refs/heads/feature {NULL_SHA}\n");
+        let missing = commits_without_ticket(repo, "origin", &input, &processor).unwrap();
+        let shas: Vec<&str> = missing.iter().map(|c| c.sha.as_str()).collect();
+        assert_eq!(shas, [typo.as_str()]);
+        assert_eq!(missing[0].subject, "Fix a typo");
+
+        // Commits the remote has aren't checked again
+        git(repo, &["push", "-q", "origin", "feature"]);
+        let untagged = commit(repo, "Tidy up");
+        let input = format!("refs/heads/feature {untagged} refs/heads/feature {head}\n");
+        let missing = commits_without_ticket(repo, "origin", &input, &processor).unwrap();
+        let shas: Vec<&str> = missing.iter().map(|c| c.sha.as_str()).collect();
+        assert_eq!(shas, [untagged.as_str()]);
+
+        // Tags aren't checked
+        let input = format!("refs/tags/v1 {untagged} refs/tags/v1 {NULL_SHA}\n");
+        assert!(commits_without_ticket(repo, "origin", &input, &processor)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_is_unfinished_subject() {
+        assert!(is_unfinished_subject("fixup! Add the parser"));
+        assert!(is_unfinished_subject("squash! Add the parser"));
+        assert!(is_unfinished_subject("amend! Add the parser"));
+        assert!(is_unfinished_subject("WIP"));
+        assert!(is_unfinished_subject("WIP: parser"));
+        assert!(is_unfinished_subject("WIP parser"));
+        assert!(!is_unfinished_subject("WIPE the cache"));
+        assert!(!is_unfinished_subject("Fix fixup! handling"));
+    }
+
+    #[test]
+    fn test_is_protected() {
+        let mut settings = settings();
+        assert!(is_protected("refs/heads/main", &settings));
+        assert!(is_protected("refs/heads/master", &settings));
+        assert!(!is_protected("refs/heads/feature/main", &settings));
+        assert!(!is_protected("refs/tags/main", &settings));
+        settings.branches = Some(vec!["release/*".to_string(), "develop".to_string()]);
+        assert!(is_protected("refs/heads/release/2.1", &settings));
+        assert!(is_protected("refs/heads/develop", &settings));
+        assert!(!is_protected("refs/heads/main", &settings));
+    }
+}
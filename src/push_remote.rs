@@ -0,0 +1,115 @@
+//! The remote `pre-push` pushes to, for the `only_remotes` and
+//! `skip_remotes` settings that pick the commands run for it
+
+use crate::fanout;
+
+/// The remote of a push, from the arguments git gives `pre-push`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PushRemote<'a> {
+    /// Name of the remote; pushing to a URL rather than to a configured
+    /// remote gives the URL here too
+    pub name: &'a str,
+    /// URL pushed to
+    pub url: &'a str,
+}
+
+impl<'a> PushRemote<'a> {
+    /// The remote in `args`, the name followed by the URL; none when
+    /// `pre-push` is run by hand without them
+    pub fn from_args(args: &'a [String]) -> Option<Self> {
+        match args {
+            [name, url, ..] => Some(Self { name, url }),
+            [name] => Some(Self { name, url: name }),
+            [] => None,
+        }
+    }
+
+    /// Whether `pattern` names the remote: a pattern with `*`, `?` or `[`
+    /// wildcards matches the whole name or URL, and one without them the
+    /// whole name or any part of the URL
+    pub fn matches(&self, pattern: &str) -> bool {
+        if pattern.contains(['*', '?', '[']) {
+            fanout::wildcard_match(pattern, self.name) || fanout::wildcard_match(pattern, self.url)
+        } else {
+            self.name == pattern || self.url.contains(pattern)
+        }
+    }
+
+    /// Whether something limited to the `only` remotes (if any are given)
+    /// and skipping the `skip` ones runs for this remote
+    pub fn is_selected(&self, only: &[String], skip: &[String]) -> bool {
+        (only.is_empty() || only.iter().any(|pattern| self.matches(pattern)))
+            && !skip.iter().any(|pattern| self.matches(pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_args() {
+        let args = strings(&["origin", "git@gitlab.example.com:team/app.git"]);
+        assert_eq!(
+            PushRemote::from_args(&args),
+            Some(PushRemote {
+                name: "origin",
+                url: "git@gitlab.example.com:team/app.git"
+            })
+        );
+        let args = strings(&["/srv/backup.git"]);
+        assert_eq!(
+            PushRemote::from_args(&args),
+            Some(PushRemote {
+                name: "/srv/backup.git",
+                url: "/srv/backup.git"
+            })
+        );
+        assert_eq!(PushRemote::from_args(&[]), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        let remote = PushRemote {
+            name: "origin",
+            url: "git@gitlab.example.com:team/app.git",
+        };
+        assert!(remote.matches("origin"));
+        assert!(remote.matches("gitlab.example.com"));
+        assert!(remote.matches("team/app"));
+        assert!(remote.matches("orig*"));
+        assert!(remote.matches("*@gitlab.*"));
+        assert!(remote.matches("origi?"));
+        // Names match as a whole, and so do patterns with wildcards
+        assert!(!remote.matches("orig"));
+        assert!(!remote.matches("gitlab.*"));
+        assert!(!remote.matches("github.com"));
+        assert!(!remote.matches("mirror"));
+    }
+
+    #[test]
+    fn test_is_selected() {
+        let origin = PushRemote {
+            name: "origin",
+            url: "git@gitlab.example.com:team/app.git",
+        };
+        let mirror = PushRemote {
+            name: "mirror",
+            url: "git@github.com:me/app.git",
+        };
+        let only = strings(&["origin"]);
+        let skip = strings(&["mirror", "gh-backup"]);
+        assert!(origin.is_selected(&[], &[]));
+        assert!(origin.is_selected(&only, &[]));
+        assert!(!mirror.is_selected(&only, &[]));
+        assert!(origin.is_selected(&[], &skip));
+        assert!(!mirror.is_selected(&[], &skip));
+        // Skipping wins over a remote also listed as the only one
+        assert!(!origin.is_selected(&only, &strings(&["*gitlab*"])));
+        assert!(mirror.is_selected(&strings(&["origin", "github.com"]), &[]));
+    }
+}
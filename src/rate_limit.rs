@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+/// Most operations allowed back to back before the rate applies. Slow network
+/// filesystems cope better with evenly spaced writes than with bursts.
+const BURST: f64 = 1.0;
+
+/// Token bucket limiting operations to `rate` per second
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    /// Tokens added per second
+    rate: f64,
+    /// Tokens available; negative when operations are waiting for tokens
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a bucket allowing `rate` operations per second
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: BURST,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Create a bucket that never waits
+    pub fn unlimited() -> Self {
+        Self::new(f64::MAX)
+    }
+
+    /// Take a token for the next operation, returning how long to sleep
+    /// before proceeding
+    pub fn acquire(&mut self) -> Duration {
+        self.acquire_at(Instant::now())
+    }
+
+    fn acquire_at(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(BURST);
+        self.last_refill = now;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_never_waits() {
+        let mut bucket = TokenBucket::unlimited();
+        for _ in 0..1000 {
+            assert_eq!(bucket.acquire(), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn test_operations_are_spaced_by_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(4.0);
+        bucket.last_refill = start;
+
+        assert_eq!(bucket.acquire_at(start), Duration::ZERO);
+        // Without sleeping, each further operation waits another 250ms
+        assert_eq!(bucket.acquire_at(start), Duration::from_millis(250));
+        assert_eq!(bucket.acquire_at(start), Duration::from_millis(500));
+
+        // After the waits have passed, the next operation is again 250ms out
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.acquire_at(later), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_idle_time_does_not_allow_bursts() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10.0);
+        bucket.last_refill = start;
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.acquire_at(later), Duration::ZERO);
+        assert_eq!(bucket.acquire_at(later), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,295 @@
+//! Record of the repositories `hookmaster add --tag` installed hooks to,
+//! for tracking them across a machine
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config::UserConfig;
+use crate::formatter;
+use crate::lock::{InstallLock, DEFAULT_LOCK_TIMEOUT};
+
+/// File next to the user configuration holding the registry
+pub const REGISTRY_FILE: &str = "registry.json";
+
+/// Tags of a repository, such as `team = backend`
+pub type Tags = BTreeMap<String, String>;
+
+/// A tag's key and value
+pub type Tag = (String, String);
+
+/// A repository hooks were installed to
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryEntry {
+    /// Path of the repository
+    pub path: String,
+    /// When hooks were last installed, as an RFC 3339 UTC timestamp
+    pub installed_at: String,
+    /// Tags given to `add`
+    pub tags: Tags,
+    /// Names of the installed hooks
+    pub hooks: Vec<String>,
+}
+
+impl RegistryEntry {
+    /// Entry for `hooks` installed to `repo` at `time`
+    pub fn new(repo: &Path, hooks: &[String], tags: &Tags, time: SystemTime) -> Self {
+        let repo = fs::canonicalize(repo).unwrap_or_else(|_| repo.to_path_buf());
+        Self {
+            path: repo.to_string_lossy().to_string(),
+            installed_at: formatter::timestamp(time),
+            tags: tags.clone(),
+            hooks: hooks.to_vec(),
+        }
+    }
+
+    fn to_json(&self) -> JsonValue {
+        json!({
+            "path": self.path,
+            "installed_at": self.installed_at,
+            "tags": self.tags,
+            "hooks": self.hooks,
+        })
+    }
+
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        Some(Self {
+            path: value["path"].as_str()?.to_string(),
+            installed_at: value["installed_at"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            tags: value["tags"]
+                .as_object()
+                .map(|tags| {
+                    tags.iter()
+                        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            hooks: value["hooks"]
+                .as_array()
+                .map(|hooks| {
+                    hooks
+                        .iter()
+                        .filter_map(|hook| hook.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Check if the entry has every tag of `tags`
+    pub fn matches(&self, tags: &Tags) -> bool {
+        tags.iter()
+            .all(|(key, value)| self.tags.get(key) == Some(value))
+    }
+
+    /// The entry on one line: path, tags, hooks and when they were installed
+    pub fn summary(&self) -> String {
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        format!(
+            "{}  [{}]  {}  ({})",
+            self.path,
+            tags.join(" "),
+            self.hooks.join(", "),
+            self.installed_at
+        )
+    }
+}
+
+/// Parse a `--tag` value written `KEY=VALUE`
+pub fn parse_tag(tag: &str) -> Result<Tag> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(anyhow!(
+            "Invalid value for --tag: '{tag}' (expected KEY=VALUE)"
+        )),
+    }
+}
+
+/// The registry file. Changes are made while holding a lock file next to
+/// it and written to a temporary file renamed over it, so concurrent `add`
+/// runs don't lose each other's entries and readers never see a partly
+/// written file.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    path: PathBuf,
+}
+
+impl Registry {
+    /// The registry in the file at `path`
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The registry next to the user configuration, such as
+    /// `~/.config/hookmaster/registry.json`
+    pub fn open() -> Result<Self> {
+        let path = UserConfig::path()
+            .map(|path| path.with_file_name(REGISTRY_FILE))
+            .ok_or_else(|| anyhow!("Can't find the registry without a home directory"))?;
+        Ok(Self::at(path))
+    }
+
+    /// Location of the registry file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The entries of the registry, which has none if there is no file
+    pub fn load(&self) -> Result<Vec<RegistryEntry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+        let value: JsonValue = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))?;
+        Ok(value
+            .as_array()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(RegistryEntry::from_json)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// The entries that have every tag of `tags`
+    pub fn list(&self, tags: &Tags) -> Result<Vec<RegistryEntry>> {
+        let mut entries = self.load()?;
+        entries.retain(|entry| entry.matches(tags));
+        Ok(entries)
+    }
+
+    /// Add `entries`, replacing the ones for the same repositories
+    pub fn record(&self, entries: Vec<RegistryEntry>) -> Result<()> {
+        self.update(|existing| {
+            existing.retain(|entry| !entries.iter().any(|new| new.path == entry.path));
+            existing.extend(entries);
+        })?;
+        Ok(())
+    }
+
+    /// Remove the entries of repositories that no longer exist, returning them
+    pub fn clean(&self) -> Result<Vec<RegistryEntry>> {
+        self.update(|entries| {
+            let (kept, removed) = std::mem::take(entries)
+                .into_iter()
+                .partition(|entry| Path::new(&entry.path).exists());
+            *entries = kept;
+            removed
+        })
+    }
+
+    /// Change the entries with `change` while holding the lock
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<RegistryEntry>) -> T) -> Result<T> {
+        let dir = self
+            .path
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory", self.path.display()))?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let _lock =
+            InstallLock::acquire_file(self.path.with_extension("json.lock"), DEFAULT_LOCK_TIMEOUT)?;
+
+        let mut entries = self.load()?;
+        let result = change(&mut entries);
+        let content: Vec<JsonValue> = entries.iter().map(RegistryEntry::to_json).collect();
+        let content = serde_json::to_string_pretty(&content).unwrap_or_default() + "\n";
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, content).with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn tags(tags: &[&str]) -> Tags {
+        tags.iter().map(|tag| parse_tag(tag).unwrap()).collect()
+    }
+
+    fn entry(path: &Path, team: &str) -> RegistryEntry {
+        RegistryEntry::new(
+            path,
+            &["pre-commit".to_string()],
+            &tags(&[&format!("team={team}"), "env=production"]),
+            SystemTime::UNIX_EPOCH,
+        )
+    }
+
+    #[test]
+    fn test_record_list_and_clean() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = Registry::at(temp_dir.path().join("hookmaster").join(REGISTRY_FILE));
+        assert!(registry.load().unwrap().is_empty());
+
+        let api = temp_dir.path().join("api");
+        let web = temp_dir.path().join("web");
+        fs::create_dir(&api).unwrap();
+        fs::create_dir(&web).unwrap();
+        registry
+            .record(vec![entry(&api, "backend"), entry(&web, "frontend")])
+            .unwrap();
+        // Installing again replaces the entry
+        registry.record(vec![entry(&api, "platform")]).unwrap();
+
+        let entries = registry.load().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].tags["team"], "platform");
+        assert_eq!(entries[0].installed_at, "1970-01-01T00:00:00.000Z");
+        let frontend = registry.list(&tags(&["team=frontend"])).unwrap();
+        assert_eq!(frontend, vec![entries[0].clone()]);
+        assert_eq!(registry.list(&Tags::new()).unwrap().len(), 2);
+        assert!(registry.list(&tags(&["env=dev"])).unwrap().is_empty());
+
+        fs::remove_dir(&web).unwrap();
+        let removed = registry.clean().unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].path.ends_with("web"));
+        assert_eq!(registry.load().unwrap(), vec![entries[1].clone()]);
+        assert!(!registry.path().with_extension("json.lock").exists());
+    }
+
+    #[test]
+    fn test_summary() {
+        let entry = RegistryEntry {
+            path: "/projects/api".to_string(),
+            installed_at: "2024-02-29T12:34:56.789Z".to_string(),
+            tags: tags(&["team=backend", "env=production"]),
+            hooks: vec!["pre-commit".to_string(), "pre-push".to_string()],
+        };
+        assert_eq!(
+            entry.summary(),
+            "/projects/api  [env=production team=backend]  pre-commit, pre-push  (2024-02-29T12:34:56.789Z)"
+        );
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(
+            parse_tag("team=backend").unwrap(),
+            ("team".to_string(), "backend".to_string())
+        );
+        assert_eq!(parse_tag("note=a=b").unwrap().1, "a=b");
+        assert!(parse_tag("team").is_err());
+        assert!(parse_tag("=backend").is_err());
+    }
+}
@@ -0,0 +1,510 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::GitHooksConfig;
+use crate::manifest;
+
+/// Directory in a repository's `.git` directory where configurations named
+/// by `extends` are cached
+pub const CACHE_DIR: &str = "hookmaster-remote-cache";
+
+/// How long a cached configuration is used before checking for a newer one
+pub const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Seconds curl may take to fetch a configuration
+const FETCH_TIMEOUT_SECS: &str = "10";
+
+/// Status code, headers and body of an HTTP response
+type Response<'a> = (Option<u16>, &'a str, &'a str);
+
+/// Where a configuration named by `extends` comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteSource {
+    /// A file served over http(s)
+    Http(String),
+    /// A file in a git repository, written `<repository>.git//<path>[@<rev>]`
+    Git {
+        /// URL of the repository, ending in `.git`
+        repo: String,
+        /// Path of the file in the repository
+        path: String,
+        /// Branch or tag to read the file from, instead of the default branch
+        rev: Option<String>,
+    },
+}
+
+impl RemoteSource {
+    /// Parse the value of `extends`
+    pub fn parse(extends: &str) -> Result<Self> {
+        if let Some((repo, rest)) = extends.split_once(".git//") {
+            let (path, rev) = match rest.rsplit_once('@') {
+                Some((path, rev)) => (path, Some(rev.to_string())),
+                None => (rest, None),
+            };
+            if path.is_empty() || rev.as_deref() == Some("") {
+                return Err(anyhow!(
+                    "Invalid extends '{extends}'; expected <repository>.git//<path>[@<rev>]"
+                ));
+            }
+            return Ok(RemoteSource::Git {
+                repo: format!("{repo}.git"),
+                path: path.to_string(),
+                rev,
+            });
+        }
+        if extends.starts_with("https://") || extends.starts_with("http://") {
+            return Ok(RemoteSource::Http(extends.to_string()));
+        }
+        Err(anyhow!(
+            "Invalid extends '{extends}'; expected an http(s) URL or <repository>.git//<path>[@<rev>]"
+        ))
+    }
+}
+
+/// Result of fetching a remote configuration
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fetched {
+    /// The configuration, with the ETag the server gave it
+    Modified {
+        /// Content of the file
+        content: String,
+        /// ETag to send with the next request
+        etag: Option<String>,
+    },
+    /// The server said the cached copy with the given ETag is current
+    NotModified,
+}
+
+/// The network access `extends` needs, so that merging and caching can be
+/// tested without it
+pub trait Fetcher {
+    /// Fetch the file of `source`. With the `etag` of a cached copy, the
+    /// fetcher may answer [`Fetched::NotModified`].
+    fn fetch(&self, source: &RemoteSource, etag: Option<&str>) -> Result<Fetched>;
+}
+
+/// Fetches with curl and git, so hookmaster needs no HTTP client of its own
+pub struct CommandFetcher;
+
+impl Fetcher for CommandFetcher {
+    fn fetch(&self, source: &RemoteSource, etag: Option<&str>) -> Result<Fetched> {
+        match source {
+            RemoteSource::Http(url) => fetch_http(url, etag),
+            RemoteSource::Git { repo, path, rev } => fetch_git(repo, path, rev.as_deref()),
+        }
+    }
+}
+
+/// Fetch `url` with curl, sending `etag` as `If-None-Match`
+fn fetch_http(url: &str, etag: Option<&str>) -> Result<Fetched> {
+    let mut command = Command::new("curl");
+    command.args([
+        "--silent",
+        "--show-error",
+        "--fail",
+        "--location",
+        "--include",
+        "--max-time",
+        FETCH_TIMEOUT_SECS,
+    ]);
+    if let Some(etag) = etag {
+        command.args(["--header", &format!("If-None-Match: {etag}")]);
+    }
+    let output = command
+        .arg(url)
+        .output()
+        .with_context(|| "Failed to run curl")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let response = String::from_utf8(output.stdout).with_context(|| "Invalid UTF-8 in response")?;
+    let (status, headers, body) = split_response(&response);
+    if status == Some(304) {
+        return Ok(Fetched::NotModified);
+    }
+    let etag = headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("etag")
+            .then(|| value.trim().to_string())
+    });
+    Ok(Fetched::Modified {
+        content: body.to_string(),
+        etag,
+    })
+}
+
+/// Split the output of `curl --include` into the status code and headers of
+/// the last response, after any redirects, and the body
+fn split_response(response: &str) -> Response<'_> {
+    let mut rest = response;
+    let mut headers = "";
+    while rest.starts_with("HTTP/") {
+        let (block, body) = rest
+            .split_once("\r\n\r\n")
+            .or_else(|| rest.split_once("\n\n"))
+            .unwrap_or((rest, ""));
+        headers = block;
+        rest = body;
+    }
+    let status = headers
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok());
+    (status, headers, rest)
+}
+
+/// Read `path` from a shallow clone of `repo` at `rev`
+fn fetch_git(repo: &str, path: &str, rev: Option<&str>) -> Result<Fetched> {
+    let temp = tempfile::Builder::new()
+        .prefix("hookmaster-extends-")
+        .tempdir()
+        .with_context(|| "Failed to create a temporary directory")?;
+    let dir = temp.path();
+    let mut command = Command::new("git");
+    command.args(["clone", "--quiet", "--depth", "1"]);
+    if let Some(rev) = rev {
+        command.args(["--branch", rev]);
+    }
+    let output = command
+        .arg("--")
+        .arg(repo)
+        .arg(dir)
+        .output()
+        .with_context(|| "Failed to run git")?;
+    let content = if output.status.success() {
+        fs::read_to_string(dir.join(path)).with_context(|| format!("Failed to read {path}"))
+    } else {
+        Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    };
+    Ok(Fetched::Modified {
+        content: content?,
+        etag: None,
+    })
+}
+
+/// A cached copy of a configuration named by `extends`
+#[derive(Debug, Clone, PartialEq)]
+struct CacheEntry {
+    content: String,
+    etag: Option<String>,
+    /// When it was last fetched or found current, in seconds since the epoch
+    fetched_at: u64,
+}
+
+/// Paths of the content and the metadata of the cached copy of `extends`
+fn cache_paths(cache_dir: &Path, extends: &str) -> (PathBuf, PathBuf) {
+    let hash = manifest::content_hash(extends);
+    let name = hash.rsplit(':').next().unwrap_or(&hash);
+    (
+        cache_dir.join(format!("{name}.toml")),
+        cache_dir.join(format!("{name}.json")),
+    )
+}
+
+/// Read the cached copy of `extends`, if there is a usable one
+fn read_cache(cache_dir: &Path, extends: &str) -> Option<CacheEntry> {
+    let (content_path, meta_path) = cache_paths(cache_dir, extends);
+    let meta: JsonValue = serde_json::from_str(&fs::read_to_string(meta_path).ok()?).ok()?;
+    Some(CacheEntry {
+        content: fs::read_to_string(content_path).ok()?,
+        etag: meta
+            .get("etag")
+            .and_then(JsonValue::as_str)
+            .map(str::to_string),
+        fetched_at: meta
+            .get("fetched_at")
+            .and_then(JsonValue::as_u64)
+            .unwrap_or(0),
+    })
+}
+
+/// Write `entry` as the cached copy of `extends`
+fn write_cache(cache_dir: &Path, extends: &str, entry: &CacheEntry) -> Result<()> {
+    fs::create_dir_all(cache_dir)
+        .with_context(|| format!("Failed to create {}", cache_dir.display()))?;
+    let (content_path, meta_path) = cache_paths(cache_dir, extends);
+    let meta = json!({
+        "extends": extends,
+        "etag": entry.etag,
+        "fetched_at": entry.fetched_at,
+    });
+    fs::write(&content_path, &entry.content)
+        .with_context(|| format!("Failed to write {}", content_path.display()))?;
+    // Serializing a `Value` can't fail
+    fs::write(
+        &meta_path,
+        serde_json::to_string_pretty(&meta).unwrap_or_default() + "\n",
+    )
+    .with_context(|| format!("Failed to write {}", meta_path.display()))
+}
+
+/// Seconds since the epoch at `time`
+fn epoch_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The configuration `extends` names, for the configuration in `dir`. A copy
+/// cached in the repository's `.git` directory is used for [`CACHE_TTL`];
+/// after that it is fetched again, and if that fails the cached copy is
+/// used with a warning, so commits keep working offline. A configuration
+/// that doesn't parse counts as a failed fetch. The `extends` of the fetched
+/// configuration itself is not followed.
+pub fn load_base(
+    dir: &Path,
+    extends: &str,
+    fetcher: &dyn Fetcher,
+    now: SystemTime,
+) -> Result<GitHooksConfig> {
+    let source = RemoteSource::parse(extends)?;
+    let git_dir = dir.join(".git");
+    let cache_dir = git_dir.is_dir().then(|| git_dir.join(CACHE_DIR));
+    let cached = cache_dir
+        .as_deref()
+        .and_then(|cache_dir| read_cache(cache_dir, extends));
+    let now = epoch_secs(now);
+
+    if let Some(cached) = &cached {
+        if now.saturating_sub(cached.fetched_at) < CACHE_TTL.as_secs() {
+            return GitHooksConfig::parse_toml(&cached.content);
+        }
+    }
+
+    let fetched = fetcher
+        .fetch(
+            &source,
+            cached.as_ref().and_then(|cached| cached.etag.as_deref()),
+        )
+        .and_then(|fetched| match (fetched, &cached) {
+            (Fetched::Modified { content, etag }, _) => {
+                GitHooksConfig::parse_toml(&content)?;
+                Ok(CacheEntry {
+                    content,
+                    etag,
+                    fetched_at: now,
+                })
+            }
+            (Fetched::NotModified, Some(cached)) => Ok(CacheEntry {
+                fetched_at: now,
+                ..cached.clone()
+            }),
+            (Fetched::NotModified, None) => Err(anyhow!("Not modified, but nothing is cached")),
+        });
+    let entry = match (fetched, cached) {
+        (Ok(entry), _) => {
+            if let Some(cache_dir) = &cache_dir {
+                if let Err(e) = write_cache(cache_dir, extends, &entry) {
+                    eprintln!("Warning: {e:#}");
+                }
+            }
+            entry
+        }
+        (Err(e), Some(cached)) => {
+            eprintln!("Warning: Failed to fetch {extends}: {e:#}; using the cached copy");
+            cached
+        }
+        (Err(e), None) => return Err(e),
+    };
+    GitHooksConfig::parse_toml(&entry.content)
+}
+
+/// Have the next load fetch every configuration cached for the repository
+/// at `repo` again, keeping the cached copies in case that fails
+pub fn expire_cache(repo: &Path) -> Result<()> {
+    let cache_dir = repo.join(".git").join(CACHE_DIR);
+    let Ok(entries) = fs::read_dir(&cache_dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let Ok(mut meta) = serde_json::from_str::<JsonValue>(&content) else {
+                continue;
+            };
+            meta["fetched_at"] = json!(0);
+            fs::write(
+                &path,
+                serde_json::to_string_pretty(&meta).unwrap_or_default() + "\n",
+            )
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use tempfile::TempDir;
+
+    const URL: &str = "https://example.com/hooks/base.toml";
+
+    /// Results a fake fetcher answers with, in order
+    type Queue = RefCell<Vec<Result<Fetched>>>;
+
+    /// ETags a fake fetcher was given, in order
+    type SeenEtags = RefCell<Vec<Option<String>>>;
+
+    /// Answers with queued results, recording the ETags it was given
+    struct FakeFetcher {
+        results: Queue,
+        etags: SeenEtags,
+    }
+
+    impl FakeFetcher {
+        fn new(results: Vec<Result<Fetched>>) -> Self {
+            Self {
+                results: RefCell::new(results),
+                etags: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Fetcher for FakeFetcher {
+        fn fetch(&self, _source: &RemoteSource, etag: Option<&str>) -> Result<Fetched> {
+            self.etags.borrow_mut().push(etag.map(str::to_string));
+            self.results.borrow_mut().remove(0)
+        }
+    }
+
+    fn modified(content: &str, etag: &str) -> Result<Fetched> {
+        Ok(Fetched::Modified {
+            content: content.to_string(),
+            etag: Some(etag.to_string()),
+        })
+    }
+
+    fn command(config: &GitHooksConfig) -> Option<&str> {
+        config.get_hook_command("pre-commit")
+    }
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(
+            RemoteSource::parse(URL).unwrap(),
+            RemoteSource::Http(URL.to_string())
+        );
+        assert_eq!(
+            RemoteSource::parse("git@github.com:acme/hooks.git//base.toml@v2").unwrap(),
+            RemoteSource::Git {
+                repo: "git@github.com:acme/hooks.git".to_string(),
+                path: "base.toml".to_string(),
+                rev: Some("v2".to_string()),
+            }
+        );
+        assert_eq!(
+            RemoteSource::parse("https://example.com/hooks.git//dir/base.toml").unwrap(),
+            RemoteSource::Git {
+                repo: "https://example.com/hooks.git".to_string(),
+                path: "dir/base.toml".to_string(),
+                rev: None,
+            }
+        );
+        assert!(RemoteSource::parse("base.toml").is_err());
+        assert!(RemoteSource::parse("git@github.com:acme/hooks.git//").is_err());
+    }
+
+    #[test]
+    fn test_load_base_caches() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let later = start + CACHE_TTL + Duration::from_secs(1);
+
+        let fetcher = FakeFetcher::new(vec![
+            modified("pre-commit = \"make lint\"\n", "\"v1\""),
+            Ok(Fetched::NotModified),
+            modified("pre-commit = \"make check\"\n", "\"v2\""),
+        ]);
+        let config = load_base(repo, URL, &fetcher, start).unwrap();
+        assert_eq!(command(&config), Some("make lint"));
+        assert!(repo.join(".git").join(CACHE_DIR).is_dir());
+
+        // Within the TTL the cached copy is used without fetching
+        let config = load_base(repo, URL, &fetcher, start).unwrap();
+        assert_eq!(command(&config), Some("make lint"));
+        assert_eq!(fetcher.etags.borrow().len(), 1);
+
+        // After it, the cached copy's ETag is sent
+        let config = load_base(repo, URL, &fetcher, later).unwrap();
+        assert_eq!(command(&config), Some("make lint"));
+        assert_eq!(fetcher.etags.borrow()[1].as_deref(), Some("\"v1\""));
+
+        // Expiring the cache fetches again even within the TTL
+        expire_cache(repo).unwrap();
+        let config = load_base(repo, URL, &fetcher, later).unwrap();
+        assert_eq!(command(&config), Some("make check"));
+        assert_eq!(fetcher.etags.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_load_base_falls_back_to_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        // Nothing to fall back on
+        let fetcher = FakeFetcher::new(vec![Err(anyhow!("Could not resolve host"))]);
+        let err = load_base(repo, URL, &fetcher, now).unwrap_err();
+        assert!(err.to_string().contains("Could not resolve host"));
+
+        let fetcher = FakeFetcher::new(vec![
+            modified("pre-commit = \"make lint\"\n", "\"v1\""),
+            Err(anyhow!("Could not resolve host")),
+            modified("pre-commit = [unclosed\n", "\"v2\""),
+        ]);
+        load_base(repo, URL, &fetcher, now).unwrap();
+        expire_cache(repo).unwrap();
+        let config = load_base(repo, URL, &fetcher, now).unwrap();
+        assert_eq!(command(&config), Some("make lint"));
+
+        // A configuration that doesn't parse doesn't replace the cached one
+        let config = load_base(repo, URL, &fetcher, now).unwrap();
+        assert_eq!(command(&config), Some("make lint"));
+    }
+
+    #[test]
+    fn test_load_base_without_git_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let fetcher = FakeFetcher::new(vec![
+            modified("pre-commit = \"make lint\"\n", "\"v1\""),
+            modified("pre-commit = \"make lint\"\n", "\"v1\""),
+        ]);
+        load_base(temp_dir.path(), URL, &fetcher, now).unwrap();
+        load_base(temp_dir.path(), URL, &fetcher, now).unwrap();
+        // Nothing is cached, so each load fetches
+        assert_eq!(*fetcher.etags.borrow(), vec![None, None]);
+    }
+
+    #[test]
+    fn test_split_response() {
+        let response = "HTTP/1.1 301 Moved\r\nLocation: /b\r\n\r\nHTTP/2 200\r\netag: \"abc\"\r\n\r\npre-commit = \"x\"\n";
+        let (status, headers, body) = split_response(response);
+        assert_eq!(status, Some(200));
+        assert!(headers.contains("etag"));
+        assert_eq!(body, "pre-commit = \"x\"\n");
+
+        let (status, _, body) = split_response("HTTP/1.1 304 Not Modified\r\n\r\n");
+        assert_eq!(status, Some(304));
+        assert_eq!(body, "");
+    }
+}
@@ -0,0 +1,289 @@
+//! Installing hooks to a repository on a server over SSH, for the hooks git
+//! runs where pushes are received
+
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::git_hooks::{self, GitHook, ScriptFormat};
+use crate::process::{find_executable, shell_quote};
+
+/// A repository on a server, from an SSH remote URL
+#[derive(Debug, Clone, PartialEq)]
+struct RemoteRepo {
+    /// Host to connect to, with the user if the URL names one
+    host: String,
+    /// SSH port, if not the default
+    port: Option<u16>,
+    /// Path of the repository on the host; a relative one is taken from the
+    /// home directory of the user
+    path: String,
+}
+
+impl RemoteRepo {
+    /// Parse `user@host:path` or `ssh://user@host:port/path`
+    fn parse(url: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!("Not an SSH remote: '{url}' (expected user@host:path or ssh://user@host/path)")
+        };
+        let (host, port, path) = match url.strip_prefix("ssh://") {
+            Some(rest) => {
+                let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+                // `ssh://host/~/repo` is relative to the home directory
+                let path = match path.strip_prefix("~/") {
+                    Some(relative) => relative.to_string(),
+                    None => format!("/{path}"),
+                };
+                match authority.rsplit_once(':') {
+                    Some((host, port)) => (
+                        host,
+                        Some(port.parse::<u16>().map_err(|_| invalid())?),
+                        path,
+                    ),
+                    None => (authority, None, path),
+                }
+            }
+            None => {
+                let (host, path) = url.split_once(':').ok_or_else(invalid)?;
+                // A slash before the colon makes it a local path, as for git
+                if host.contains('/') || url.contains("://") {
+                    return Err(invalid());
+                }
+                (host, None, path.trim_start_matches("~/").to_string())
+            }
+        };
+        // `ssh` and `scp` would take a host starting with `-` for an option
+        if host.is_empty() || host.starts_with('-') || host.ends_with('@') || path.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// `ssh` running the shell command `command` on the host
+    fn ssh(&self, command: &str) -> Command {
+        let mut ssh = Command::new("ssh");
+        if let Some(port) = self.port {
+            ssh.arg("-p").arg(port.to_string());
+        }
+        ssh.arg("--").arg(&self.host).arg(command);
+        ssh
+    }
+
+    /// `scp` copying `files` into the directory `dir` on the host, keeping
+    /// their modes so the scripts stay executable
+    fn scp(&self, files: &[PathBuf], dir: &str) -> Command {
+        let mut scp = Command::new("scp");
+        scp.args(["-q", "-p"]);
+        if let Some(port) = self.port {
+            scp.arg("-P").arg(port.to_string());
+        }
+        scp.arg("--")
+            .args(files)
+            .arg(format!("{}:{dir}/", self.host));
+        scp
+    }
+
+    /// The hooks directory of the repository, as git on the host reports it:
+    /// `core.hooksPath` if set, or else the repository's `hooks`
+    fn hooks_dir(&self) -> Result<String> {
+        let command = format!(
+            "git -C {} rev-parse --git-path hooks",
+            shell_quote(&self.path)
+        );
+        let output = self
+            .ssh(&command)
+            .output()
+            .with_context(|| format!("Failed to run ssh {}", self.host))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to find the hooks directory of {} on {}: {}",
+                self.path,
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(self.resolve(String::from_utf8_lossy(&output.stdout).trim()))
+    }
+
+    /// `git -C` prints paths relative to the repository, while `scp` takes
+    /// them from the home directory
+    fn resolve(&self, hooks_dir: &str) -> String {
+        if hooks_dir.starts_with('/') {
+            hooks_dir.to_string()
+        } else {
+            format!("{}/{hooks_dir}", self.path.trim_end_matches('/'))
+        }
+    }
+}
+
+/// What installing to a remote repository did
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteInstall {
+    /// The hooks directory, as `host:path`
+    pub target: String,
+    /// Names of the installed hooks
+    pub hooks: Vec<String>,
+}
+
+/// Installs hook scripts to the repository of an SSH remote, with the `ssh`
+/// and `scp` of the system so its SSH configuration and agent apply
+pub struct RemoteHookInstaller;
+
+impl RemoteHookInstaller {
+    /// Write the scripts of `hooks` to a temporary directory and copy them
+    /// into the hooks directory of the repository at `remote_url`, which
+    /// needs git on the host. Scripts already there are replaced.
+    pub fn install(remote_url: &str, hooks: &[GitHook]) -> Result<RemoteInstall> {
+        let remote = RemoteRepo::parse(remote_url)?;
+        for program in ["ssh", "scp"] {
+            if find_executable(program).is_none() {
+                return Err(anyhow!(
+                    "Installing to a remote repository needs {program}, which is not on the PATH"
+                ));
+            }
+        }
+        let hooks_dir = remote.hooks_dir()?;
+
+        let staging = tempfile::Builder::new()
+            .prefix("hookmaster-remote-hooks-")
+            .tempdir()
+            .with_context(|| "Failed to create a temporary directory")?;
+        Self::copy_scripts(&remote, hooks, staging.path(), &hooks_dir)?;
+
+        Ok(RemoteInstall {
+            target: format!("{}:{hooks_dir}", remote.host),
+            hooks: hooks.iter().map(GitHook::to_filename).collect(),
+        })
+    }
+
+    /// Write the scripts to the directory `staging` and copy them to `hooks_dir` on the host
+    fn copy_scripts(
+        remote: &RemoteRepo,
+        hooks: &[GitHook],
+        staging: &Path,
+        hooks_dir: &str,
+    ) -> Result<()> {
+        let mut files = Vec::new();
+        for hook in hooks {
+            let file = staging.join(hook.to_filename());
+            git_hooks::write_hook_file(&file, &hook.script_content(ScriptFormat::Sh))?;
+            files.push(file);
+        }
+        let output = remote
+            .scp(&files, hooks_dir)
+            .output()
+            .with_context(|| format!("Failed to run scp to {}", remote.host))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to copy the hooks to {}:{hooks_dir}: {}",
+                remote.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(host: &str, port: Option<u16>, path: &str) -> RemoteRepo {
+        RemoteRepo {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let cases = [
+            (
+                "git@example.com:org/repo.git",
+                remote("git@example.com", None, "org/repo.git"),
+            ),
+            (
+                "example.com:/srv/git/repo.git",
+                remote("example.com", None, "/srv/git/repo.git"),
+            ),
+            (
+                "git@example.com:~/repo.git",
+                remote("git@example.com", None, "repo.git"),
+            ),
+            (
+                "ssh://git@example.com/srv/repo.git",
+                remote("git@example.com", None, "/srv/repo.git"),
+            ),
+            (
+                "ssh://git@example.com:2222/srv/repo.git",
+                remote("git@example.com", Some(2222), "/srv/repo.git"),
+            ),
+            (
+                "ssh://example.com/~/repo.git",
+                remote("example.com", None, "repo.git"),
+            ),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(RemoteRepo::parse(url).unwrap(), expected, "{url}");
+        }
+
+        for url in [
+            "https://example.com/org/repo.git",
+            "/srv/git/repo.git",
+            "./repo:name",
+            "-oProxyCommand=x:repo.git",
+            "ssh://-oProxyCommand=x/repo.git",
+            "git@example.com:",
+            "ssh://git@example.com:port/repo.git",
+            "ssh://example.com",
+        ] {
+            let error = RemoteRepo::parse(url).unwrap_err().to_string();
+            assert!(error.starts_with("Not an SSH remote"), "{url}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let repo = remote("git@example.com", None, "org/repo.git/");
+        assert_eq!(repo.resolve("hooks"), "org/repo.git/hooks");
+        assert_eq!(repo.resolve("/etc/git/hooks"), "/etc/git/hooks");
+    }
+
+    #[test]
+    fn test_commands() {
+        let repo = remote("git@example.com", Some(2222), "/srv/my repo.git");
+        let args = |command: &Command| -> Vec<String> {
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect()
+        };
+        assert_eq!(
+            args(&repo.ssh("git -C '/srv/my repo.git' rev-parse --git-path hooks")),
+            [
+                "-p",
+                "2222",
+                "--",
+                "git@example.com",
+                "git -C '/srv/my repo.git' rev-parse --git-path hooks"
+            ]
+        );
+        assert_eq!(
+            args(&repo.scp(&[PathBuf::from("pre-receive")], "/srv/hooks")),
+            [
+                "-q",
+                "-p",
+                "-P",
+                "2222",
+                "--",
+                "pre-receive",
+                "git@example.com:/srv/hooks/"
+            ]
+        );
+    }
+}
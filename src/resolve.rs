@@ -0,0 +1,558 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::config::{GitHooksConfig, HookEntry, HookSpec, Runner};
+use crate::dag;
+use crate::docker;
+use crate::placeholder;
+
+/// How deeply `@hook` references may nest before resolution gives up
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+/// JavaScript package manager, detected from lockfiles for `js:` commands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+}
+
+impl PackageManager {
+    /// Detect the package manager used in `root` from its lockfile, defaulting to npm
+    pub fn detect(root: &Path) -> Self {
+        if root.join("pnpm-lock.yaml").exists() {
+            PackageManager::Pnpm
+        } else if root.join("yarn.lock").exists() {
+            PackageManager::Yarn
+        } else {
+            PackageManager::Npm
+        }
+    }
+
+    /// Command that runs a package.json script quietly
+    fn run_script(&self, script: &str) -> String {
+        match self {
+            PackageManager::Npm => format!("npm run --silent {script}"),
+            PackageManager::Pnpm => format!("pnpm run --silent {script}"),
+            PackageManager::Yarn => format!("yarn --silent run {script}"),
+        }
+    }
+}
+
+/// Expand shorthand command forms into the real invocation:
+///
+/// - `npm:lint`, `pnpm:lint`, `yarn:lint` run a package.json script with that tool
+/// - `js:lint` runs a package.json script with the package manager detected in `root`
+/// - `cargo:clippy -- -D warnings` runs `cargo clippy -- -D warnings`
+///
+/// Anything else is returned unchanged.
+pub fn expand_shorthand(command: &str, root: &Path) -> String {
+    let trimmed = command.trim_start();
+    let Some((prefix, rest)) = trimmed.split_once(':') else {
+        return command.to_string();
+    };
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        return command.to_string();
+    }
+
+    match prefix {
+        "npm" => PackageManager::Npm.run_script(rest),
+        "pnpm" => PackageManager::Pnpm.run_script(rest),
+        "yarn" => PackageManager::Yarn.run_script(rest),
+        "js" => PackageManager::detect(root).run_script(rest),
+        "cargo" => format!("cargo {rest}"),
+        _ => command.to_string(),
+    }
+}
+
+/// Resolve a hook into the commands that will actually run, with `@hook`
+/// references, `{vars.<name>}` placeholders and shorthand forms expanded for
+/// the repository at `root`
+pub fn resolve_hook(config: &GitHooksConfig, hook_name: &str, root: &Path) -> Result<HookSpec> {
+    let mut resolved = expand_references(config, hook_name)?;
+    for entry in &mut resolved.commands {
+        let command = substitute_vars(&entry.command, &config.vars, hook_name)?;
+        entry.command = expand_shorthand(&command, root);
+    }
+
+    Ok(resolved)
+}
+
+/// Replace `{vars.<name>}` placeholders with the values from `[vars]`
+pub fn substitute_vars(
+    command: &str,
+    vars: &BTreeMap<String, String>,
+    hook_name: &str,
+) -> Result<String> {
+    placeholder::substitute(command, |name| {
+        let var = name.strip_prefix("vars.")?;
+        Some(
+            vars.get(var)
+                .cloned()
+                .ok_or_else(|| anyhow!("Hook '{}' uses undefined variable '{}'", hook_name, var)),
+        )
+    })
+}
+
+/// Expand `@hook` entries into the referenced hook's commands, recursively.
+///
+/// Expanded commands keep their settings and are named `<hook>/<command>`
+/// (or just `<hook>` for a hook defined as a plain string). Commands that
+/// depended on the reference wait for all of the expanded commands instead.
+pub fn expand_references(config: &GitHooksConfig, hook_name: &str) -> Result<HookSpec> {
+    let spec = config
+        .hooks
+        .get(hook_name)
+        .ok_or_else(|| anyhow!("Hook '{}' not found in configuration", hook_name))?;
+
+    expand(config, hook_name, spec, &mut Vec::new())
+}
+
+fn expand(
+    config: &GitHooksConfig,
+    hook_name: &str,
+    spec: &HookSpec,
+    stack: &mut Vec<String>,
+) -> Result<HookSpec> {
+    if let Some(start) = stack.iter().position(|h| h == hook_name) {
+        let cycle: Vec<String> = stack[start..]
+            .iter()
+            .chain(std::iter::once(&hook_name.to_string()))
+            .map(|h| format!("@{h}"))
+            .collect();
+        return Err(anyhow!("Hook reference cycle: {}", cycle.join(" -> ")));
+    }
+    if stack.len() > MAX_REFERENCE_DEPTH {
+        return Err(anyhow!(
+            "Hook references from '{}' are nested more than {} levels deep",
+            stack[0],
+            MAX_REFERENCE_DEPTH
+        ));
+    }
+
+    stack.push(hook_name.to_string());
+    let mut resolved = HookSpec {
+        commands: Vec::new(),
+        parallel: spec.parallel,
+        env_inherit: spec.env_inherit,
+        env_allow: spec.env_allow.clone(),
+        limits: spec.limits.clone(),
+        deny_network: spec.deny_network,
+        only_remotes: spec.only_remotes.clone(),
+        skip_remotes: spec.skip_remotes.clone(),
+    };
+    let mut replaced = HashMap::new();
+
+    for entry in &spec.commands {
+        let Some(target) = entry.reference() else {
+            resolved.commands.push(entry.clone());
+            continue;
+        };
+
+        let target_spec = config
+            .hooks
+            .get(target)
+            .ok_or_else(|| anyhow!("Hook '{}' references unknown hook '@{}'", hook_name, target))?;
+        let expanded = expand(config, target, target_spec, stack)?;
+
+        let mut names = Vec::new();
+        for mut child in expanded.commands {
+            if child.command.trim().is_empty() {
+                continue;
+            }
+            child.name = qualify(target, &child.name);
+            child.depends_on = child
+                .depends_on
+                .iter()
+                .map(|dep| qualify(target, dep))
+                .chain(entry.depends_on.iter().cloned())
+                .collect();
+            child.before = child
+                .before
+                .iter()
+                .map(|name| qualify(target, name))
+                .chain(entry.before.iter().cloned())
+                .collect();
+            child.after = child
+                .after
+                .iter()
+                .map(|name| qualify(target, name))
+                .chain(entry.after.iter().cloned())
+                .collect();
+            names.push(child.name.clone());
+            resolved.commands.push(child);
+        }
+        replaced.insert(entry.name.as_str(), names);
+    }
+
+    // Commands ordered relative to a reference are ordered relative to all
+    // of the commands it expanded to
+    let expand_names = |names: &[String]| -> Vec<String> {
+        names
+            .iter()
+            .flat_map(|name| {
+                replaced
+                    .get(name.as_str())
+                    .cloned()
+                    .unwrap_or_else(|| vec![name.clone()])
+            })
+            .collect()
+    };
+    for entry in &mut resolved.commands {
+        entry.depends_on = expand_names(&entry.depends_on);
+        entry.before = expand_names(&entry.before);
+        entry.after = expand_names(&entry.after);
+    }
+
+    stack.pop();
+    Ok(resolved)
+}
+
+/// Name of a command expanded from a reference to `hook_name`
+fn qualify(hook_name: &str, name: &str) -> String {
+    if name == hook_name {
+        name.to_string()
+    } else {
+        format!("{hook_name}/{name}")
+    }
+}
+
+/// Describe a resolved hook of the repository at `root` for `--dry-run`,
+/// one line per command. Commands run in containers are shown as the full
+/// `docker` command.
+pub fn describe_hook(hook_name: &str, spec: &HookSpec, root: &Path) -> Vec<String> {
+    let mut lines = Vec::new();
+    let invocation = |entry: &HookEntry| match (entry.runner, &entry.image) {
+        (Runner::Docker, Some(image)) => {
+            let args = docker::run_args(root, root, image, &entry.command, &docker::passed_env());
+            docker::display(&args)
+        }
+        _ => entry.command.clone(),
+    };
+
+    if let [entry] = spec.commands.as_slice() {
+        if entry.name == hook_name
+            && entry.foreach_dir.is_none()
+            && entry.timeout.is_none()
+            && entry.only_remotes.is_empty()
+            && entry.skip_remotes.is_empty()
+        {
+            lines.push(format!("Hook '{hook_name}': {}", invocation(entry)));
+            return lines;
+        }
+    }
+
+    let mode = if spec.parallel { " (parallel)" } else { "" };
+    lines.push(format!("Hook '{hook_name}'{mode}:"));
+    let prerequisites = dag::prerequisites(&spec.commands);
+    for (entry, prerequisites) in spec.commands.iter().zip(&prerequisites) {
+        let mut notes = Vec::new();
+        if !prerequisites.is_empty() {
+            notes.push(format!("after {}", prerequisites.join(", ")));
+        }
+        if let Some(pattern) = &entry.foreach_dir {
+            let changed = if entry.only_changed {
+                " with staged changes"
+            } else {
+                ""
+            };
+            notes.push(format!("in each of {pattern}{changed}"));
+        }
+        if let Some(timeout) = entry.timeout {
+            notes.push(format!("timeout {timeout}s"));
+        }
+        if !entry.only_remotes.is_empty() {
+            notes.push(format!("only for {}", entry.only_remotes.join(", ")));
+        }
+        if !entry.skip_remotes.is_empty() {
+            notes.push(format!("not for {}", entry.skip_remotes.join(", ")));
+        }
+
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", notes.join("; "))
+        };
+        if entry.name == entry.command {
+            lines.push(format!("  {}{notes}", invocation(entry)));
+        } else {
+            lines.push(format!("  {}: {}{notes}", entry.name, invocation(entry)));
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect_package_manager() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        assert_eq!(PackageManager::detect(root), PackageManager::Npm);
+
+        fs::write(root.join("package-lock.json"), "{}").unwrap();
+        assert_eq!(PackageManager::detect(root), PackageManager::Npm);
+
+        fs::write(root.join("yarn.lock"), "").unwrap();
+        assert_eq!(PackageManager::detect(root), PackageManager::Yarn);
+
+        fs::write(root.join("pnpm-lock.yaml"), "").unwrap();
+        assert_eq!(PackageManager::detect(root), PackageManager::Pnpm);
+    }
+
+    #[test]
+    fn test_expand_explicit_shorthand() {
+        let root = Path::new(".");
+        assert_eq!(expand_shorthand("npm:lint", root), "npm run --silent lint");
+        assert_eq!(
+            expand_shorthand("pnpm:lint", root),
+            "pnpm run --silent lint"
+        );
+        assert_eq!(
+            expand_shorthand("yarn:lint", root),
+            "yarn --silent run lint"
+        );
+        assert_eq!(
+            expand_shorthand("cargo:clippy -- -D warnings", root),
+            "cargo clippy -- -D warnings"
+        );
+    }
+
+    #[test]
+    fn test_expand_js_shorthand_uses_lockfile() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        assert_eq!(expand_shorthand("js:test", root), "npm run --silent test");
+
+        fs::write(root.join("yarn.lock"), "").unwrap();
+        assert_eq!(expand_shorthand("js:test", root), "yarn --silent run test");
+
+        fs::write(root.join("pnpm-lock.yaml"), "").unwrap();
+        assert_eq!(expand_shorthand("js:test", root), "pnpm run --silent test");
+    }
+
+    #[test]
+    fn test_expand_leaves_other_commands_alone() {
+        let root = Path::new(".");
+        for command in [
+            "cargo test",
+            "echo a:b",
+            "npm: lint",
+            "unknown:thing",
+            "sh -c 'x:y'",
+            "",
+        ] {
+            assert_eq!(expand_shorthand(command, root), command);
+        }
+    }
+
+    #[test]
+    fn test_resolve_hook_expands_commands() {
+        let content = r#"
+pre-push = "cargo:test --workspace"
+
+[hooks.pre-commit.lint]
+command = "npm:lint"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let root = Path::new(".");
+
+        let resolved = resolve_hook(&config, "pre-push", root).unwrap();
+        assert_eq!(resolved.commands[0].command, "cargo test --workspace");
+        assert_eq!(
+            describe_hook("pre-push", &resolved, root),
+            vec!["Hook 'pre-push': cargo test --workspace".to_string()]
+        );
+
+        let resolved = resolve_hook(&config, "pre-commit", root).unwrap();
+        assert_eq!(
+            describe_hook("pre-commit", &resolved, root),
+            vec![
+                "Hook 'pre-commit':".to_string(),
+                "  lint: npm run --silent lint".to_string(),
+            ]
+        );
+
+        assert!(resolve_hook(&config, "missing", root).is_err());
+    }
+
+    #[test]
+    fn test_describe_docker_command() {
+        let content = r#"
+pre-push = { command = "licensecheck .", runner = "docker", image = "acme/licensecheck:1" }
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let root = Path::new("/src/app");
+
+        let resolved = resolve_hook(&config, "pre-push", root).unwrap();
+        let lines = describe_hook("pre-push", &resolved, root);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("Hook 'pre-push': docker run --rm -v /src/app:/work -w /work"));
+        assert!(lines[0].ends_with(" acme/licensecheck:1 licensecheck ."));
+    }
+
+    #[test]
+    fn test_expand_nested_references() {
+        let content = r#"
+fmt = "cargo fmt --check"
+pre-commit = ["@fmt", "cargo:clippy -- -D warnings"]
+pre-push = ["@pre-commit", "cargo test"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+
+        let resolved = resolve_hook(&config, "pre-push", Path::new(".")).unwrap();
+        let names: Vec<&str> = resolved.commands.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "pre-commit/fmt",
+                "pre-commit/cargo:clippy -- -D warnings",
+                "cargo test"
+            ]
+        );
+        assert_eq!(
+            describe_hook("pre-push", &resolved, Path::new(".")),
+            vec![
+                "Hook 'pre-push':".to_string(),
+                "  pre-commit/fmt: cargo fmt --check".to_string(),
+                "  pre-commit/cargo:clippy -- -D warnings: cargo clippy -- -D warnings".to_string(),
+                "  cargo test".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_reference_keeps_settings_and_dependencies() {
+        let content = r#"
+[hooks.pre-commit.generate]
+command = "make generate"
+
+[hooks.pre-commit.test]
+command = "npm test"
+depends_on = ["generate"]
+foreach_dir = "packages/*/"
+only_changed = true
+
+[hooks.pre-push]
+parallel = true
+
+[hooks.pre-push.checks]
+command = "@pre-commit"
+
+[hooks.pre-push.build]
+command = "make build"
+depends_on = ["checks"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let resolved = expand_references(&config, "pre-push").unwrap();
+
+        assert!(resolved.parallel);
+        let test = &resolved.commands[1];
+        assert_eq!(test.name, "pre-commit/test");
+        assert_eq!(test.depends_on, vec!["pre-commit/generate"]);
+        assert_eq!(test.foreach_dir.as_deref(), Some("packages/*/"));
+        assert!(test.only_changed);
+        assert_eq!(
+            resolved.commands[2].depends_on,
+            vec!["pre-commit/generate", "pre-commit/test"]
+        );
+        assert!(resolved.validate("pre-push").is_empty());
+    }
+
+    #[test]
+    fn test_expand_reference_to_empty_hook() {
+        let content = r#"
+commit-msg = ""
+post-commit = []
+pre-push = ["@commit-msg", "@post-commit", "cargo test"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let resolved = expand_references(&config, "pre-push").unwrap();
+
+        assert_eq!(resolved.commands.len(), 1);
+        assert_eq!(resolved.commands[0].command, "cargo test");
+    }
+
+    #[test]
+    fn test_expand_reference_errors() {
+        let content = r#"
+a = ["@b"]
+b = ["echo b", "@a"]
+c = ["@missing"]
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+
+        let err = expand_references(&config, "a").unwrap_err();
+        assert_eq!(err.to_string(), "Hook reference cycle: @a -> @b -> @a");
+        let err = expand_references(&config, "c").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Hook 'c' references unknown hook '@missing'"
+        );
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[2].contains("unknown hook '@missing'"));
+    }
+
+    #[test]
+    fn test_expand_reference_depth_limit() {
+        let mut content = String::from("h0 = \"true\"\n");
+        for i in 1..=MAX_REFERENCE_DEPTH + 2 {
+            content.push_str(&format!("h{i} = [\"@h{}\"]\n", i - 1));
+        }
+        let config = GitHooksConfig::parse_toml(&content).unwrap();
+
+        let ok = format!("h{MAX_REFERENCE_DEPTH}");
+        assert_eq!(expand_references(&config, &ok).unwrap().commands.len(), 1);
+
+        let too_deep = format!("h{}", MAX_REFERENCE_DEPTH + 2);
+        let err = expand_references(&config, &too_deep).unwrap_err();
+        assert!(err.to_string().contains("nested more than"));
+    }
+
+    #[test]
+    fn test_resolve_substitutes_vars_before_shorthand() {
+        let content = r#"
+pre-push = ["cargo:test {vars.flags}", "echo {{vars.flags}}"]
+
+[hooks.pre-commit.test]
+command = "npm test -- {vars.reporter} {dir}"
+foreach_dir = "packages/*/"
+
+[vars]
+flags = "--workspace --locked"
+reporter = "--reporter=dot"
+"#;
+        let config = GitHooksConfig::parse_toml(content).unwrap();
+        let root = Path::new(".");
+
+        let resolved = resolve_hook(&config, "pre-push", root).unwrap();
+        assert_eq!(
+            resolved.commands[0].command,
+            "cargo test --workspace --locked"
+        );
+        assert_eq!(resolved.commands[1].command, "echo {vars.flags}");
+
+        // `{dir}` is left for fan-out
+        let resolved = resolve_hook(&config, "pre-commit", root).unwrap();
+        assert_eq!(
+            resolved.commands[0].command,
+            "npm test -- --reporter=dot {dir}"
+        );
+
+        let mut config = config;
+        config.vars.remove("flags");
+        let err = resolve_hook(&config, "pre-push", root).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Hook 'pre-push' uses undefined variable 'flags'"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::SandboxSettings;
+use crate::process::find_executable;
+
+/// How hook commands are isolated from the rest of the system
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sandbox {
+    /// Commands run with the user's full permissions
+    None,
+    /// Linux: bubblewrap with a read-only view of the system and a writable repository
+    Bwrap { root: PathBuf, allow_network: bool },
+    /// macOS: `sandbox-exec` with a profile that denies writes outside the repository
+    Seatbelt { profile: String },
+    /// Linux: `unshare` with a network namespace of its own, for `deny-network`
+    /// without the sandbox
+    Unshare,
+}
+
+impl Sandbox {
+    /// Choose the sandbox for the repository at `root`. When sandboxing is
+    /// enabled but this platform has no supported mechanism, a warning is
+    /// printed and commands run unsandboxed.
+    pub fn for_settings(settings: &SandboxSettings, root: &Path) -> Self {
+        if !settings.enabled {
+            return Sandbox::None;
+        }
+
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        match Self::detect(&root, settings.allow_network) {
+            Ok(sandbox) => sandbox,
+            Err(reason) => {
+                eprintln!(
+                    "Warning: sandboxing is unavailable ({reason}); running hooks unsandboxed"
+                );
+                Sandbox::None
+            }
+        }
+    }
+
+    fn detect(root: &Path, allow_network: bool) -> Result<Self, String> {
+        if cfg!(target_os = "linux") {
+            find_executable("bwrap")
+                .map(|_| Sandbox::Bwrap {
+                    root: root.to_path_buf(),
+                    allow_network,
+                })
+                .ok_or_else(|| "bwrap not found on PATH".to_string())
+        } else if cfg!(target_os = "macos") {
+            find_executable("sandbox-exec")
+                .map(|_| Sandbox::Seatbelt {
+                    profile: macos_profile(root, allow_network),
+                })
+                .ok_or_else(|| "sandbox-exec not found on PATH".to_string())
+        } else if cfg!(windows) {
+            Err("restricted tokens are not implemented on Windows".to_string())
+        } else {
+            Err(format!("not supported on {}", env::consts::OS))
+        }
+    }
+
+    /// This sandbox with network access denied, for hooks with `deny-network`.
+    /// When this platform has no way to deny it, a warning is printed and
+    /// commands keep network access.
+    pub fn without_network(self, hook_name: &str) -> Self {
+        match self {
+            Sandbox::None => match Self::detect_network_isolation() {
+                Ok(sandbox) => sandbox,
+                Err(reason) => {
+                    eprintln!(
+                        "Warning: denying network access is unavailable ({reason}); hook '{hook_name}' runs with network access"
+                    );
+                    Sandbox::None
+                }
+            },
+            Sandbox::Bwrap { root, .. } => Sandbox::Bwrap {
+                root,
+                allow_network: false,
+            },
+            Sandbox::Seatbelt { profile } if !profile.contains(DENY_NETWORK) => Sandbox::Seatbelt {
+                profile: format!("{profile}\n{DENY_NETWORK}"),
+            },
+            sandbox => sandbox,
+        }
+    }
+
+    fn detect_network_isolation() -> Result<Self, String> {
+        if cfg!(target_os = "linux") {
+            find_executable("unshare")
+                .map(|_| Sandbox::Unshare)
+                .ok_or_else(|| "unshare not found on PATH".to_string())
+        } else if cfg!(target_os = "macos") {
+            find_executable("sandbox-exec")
+                .map(|_| Sandbox::Seatbelt {
+                    profile: ["(version 1)", "(allow default)", DENY_NETWORK].join("\n"),
+                })
+                .ok_or_else(|| "sandbox-exec not found on PATH".to_string())
+        } else {
+            Err(format!("not supported on {}", env::consts::OS))
+        }
+    }
+
+    /// Whether commands run without network access
+    pub fn denies_network(&self) -> bool {
+        match self {
+            Sandbox::None => false,
+            Sandbox::Bwrap { allow_network, .. } => !allow_network,
+            Sandbox::Seatbelt { profile } => profile.contains(DENY_NETWORK),
+            Sandbox::Unshare => true,
+        }
+    }
+
+    /// Build a command that runs `program` with `args` inside the sandbox
+    pub fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut command = match self {
+            Sandbox::None => Command::new(program),
+            Sandbox::Bwrap {
+                root,
+                allow_network,
+            } => {
+                let mut command = Command::new("bwrap");
+                command
+                    .args(bwrap_args(root, *allow_network))
+                    .arg("--")
+                    .arg(program);
+                command
+            }
+            Sandbox::Seatbelt { profile } => {
+                let mut command = Command::new("sandbox-exec");
+                command.arg("-p").arg(profile).arg(program);
+                command
+            }
+            // Mapping the user to root in a user namespace lets unprivileged
+            // users create the network namespace
+            Sandbox::Unshare => {
+                let mut command = Command::new("unshare");
+                command
+                    .args(["--map-root-user", "--net", "--"])
+                    .arg(program);
+                command
+            }
+        };
+        command.args(args);
+        command
+    }
+}
+
+/// Seatbelt rule denying all network access
+const DENY_NETWORK: &str = "(deny network*)";
+
+/// Arguments for bubblewrap: the whole filesystem read-only, the repository
+/// writable, a private `/tmp`, and no network unless allowed
+fn bwrap_args(root: &Path, allow_network: bool) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+        "--die-with-parent",
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+
+    args.push("--bind".into());
+    args.push(root.into());
+    args.push(root.into());
+
+    if !allow_network {
+        args.push("--unshare-net".into());
+    }
+
+    args
+}
+
+/// Seatbelt profile for `sandbox-exec`: writes are only allowed in the
+/// repository (and to the standard devices), network only if allowed
+fn macos_profile(root: &Path, allow_network: bool) -> String {
+    let root = root
+        .display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+
+    let mut profile = vec![
+        "(version 1)".to_string(),
+        "(allow default)".to_string(),
+        "(deny file-write*)".to_string(),
+        format!(
+            "(allow file-write* (subpath \"{root}\") (literal \"/dev/null\") (literal \"/dev/tty\") (subpath \"/dev/fd\"))"
+        ),
+    ];
+    if !allow_network {
+        profile.push(DENY_NETWORK.to_string());
+    }
+
+    profile.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_sandbox_runs_directly() {
+        let sandbox = Sandbox::for_settings(&SandboxSettings::default(), Path::new("."));
+        assert_eq!(sandbox, Sandbox::None);
+
+        let command = sandbox.command("sh", &["-c", "true"]);
+        assert_eq!(command.get_program(), "sh");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, vec!["-c", "true"]);
+    }
+
+    #[test]
+    fn test_bwrap_command() {
+        let sandbox = Sandbox::Bwrap {
+            root: PathBuf::from("/work/repo"),
+            allow_network: false,
+        };
+        let command = sandbox.command("sh", &["-c", "cargo test"]);
+        assert_eq!(command.get_program(), "bwrap");
+
+        let args: Vec<String> = command
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect();
+        let args = args.join(" ");
+        assert!(args.starts_with("--ro-bind / / "));
+        assert!(args.contains("--bind /work/repo /work/repo"));
+        assert!(args.contains("--unshare-net"));
+        assert!(args.ends_with("-- sh -c cargo test"));
+
+        let args = bwrap_args(Path::new("/work/repo"), true);
+        assert!(!args.contains(&OsString::from("--unshare-net")));
+    }
+
+    #[test]
+    fn test_without_network() {
+        let sandbox = Sandbox::Bwrap {
+            root: PathBuf::from("/work/repo"),
+            allow_network: true,
+        };
+        assert!(!sandbox.denies_network());
+        assert!(sandbox.without_network("pre-commit").denies_network());
+
+        let sandbox = Sandbox::Seatbelt {
+            profile: macos_profile(Path::new("/repo"), true),
+        };
+        let Sandbox::Seatbelt { profile } = sandbox.without_network("pre-commit") else {
+            panic!("expected a seatbelt sandbox");
+        };
+        assert!(profile.ends_with("\n(deny network*)"));
+
+        let command = Sandbox::Unshare.command("sh", &["-c", "true"]);
+        assert_eq!(command.get_program(), "unshare");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            vec!["--map-root-user", "--net", "--", "sh", "-c", "true"]
+        );
+    }
+
+    #[test]
+    fn test_macos_profile() {
+        let profile = macos_profile(Path::new("/Users/me/my \"repo\""), false);
+        assert!(profile.contains("(deny file-write*)"));
+        assert!(profile.contains("(subpath \"/Users/me/my \\\"repo\\\"\")"));
+        assert!(profile.contains("(deny network*)"));
+
+        let profile = macos_profile(Path::new("/repo"), true);
+        assert!(!profile.contains("network"));
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// A unit of work that may depend on other tasks by name
+#[derive(Debug, Clone, Copy)]
+pub struct Task<'a> {
+    /// Name other tasks refer to in their dependencies
+    pub name: &'a str,
+    /// Names of the tasks that must succeed before this one starts
+    pub depends_on: &'a [String],
+}
+
+/// Outcome of a scheduled task
+#[derive(Debug)]
+pub enum TaskOutcome<E> {
+    /// The task ran and succeeded
+    Succeeded,
+    /// The task ran and failed
+    Failed(E),
+    /// The task was not run because a dependency (or, when running
+    /// sequentially, an earlier task) failed
+    NotRun,
+}
+
+impl<E> TaskOutcome<E> {
+    /// Check if the task ran and succeeded
+    pub fn is_success(&self) -> bool {
+        matches!(self, TaskOutcome::Succeeded)
+    }
+}
+
+/// A dependency that doesn't name any task
+#[derive(Debug, PartialEq)]
+pub struct UnknownDependency<'a> {
+    /// Task declaring the dependency
+    pub task: &'a str,
+    /// Name that doesn't match any task
+    pub dependency: &'a str,
+}
+
+/// Find dependencies that don't name any task
+pub fn unknown_dependencies<'a>(tasks: &[Task<'a>]) -> Vec<UnknownDependency<'a>> {
+    tasks
+        .iter()
+        .flat_map(|task| {
+            task.depends_on
+                .iter()
+                .filter(|dep| !tasks.iter().any(|t| t.name == dep.as_str()))
+                .map(move |dep| UnknownDependency {
+                    task: task.name,
+                    dependency: dep.as_str(),
+                })
+        })
+        .collect()
+}
+
+/// Find a dependency cycle, returned as the path of task names that leads
+/// back to its start (e.g. `["a", "b", "a"]`)
+pub fn find_cycle(tasks: &[Task]) -> Option<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        tasks: &[Task],
+        by_name: &HashMap<&str, usize>,
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<String>> {
+        marks[index] = Mark::InProgress;
+        path.push(index);
+
+        for dep in tasks[index].depends_on {
+            let Some(&dep_index) = by_name.get(dep.as_str()) else {
+                continue;
+            };
+            match marks[dep_index] {
+                Mark::InProgress => {
+                    let start = path.iter().position(|&i| i == dep_index).unwrap_or(0);
+                    let mut cycle: Vec<String> = path[start..]
+                        .iter()
+                        .map(|&i| tasks[i].name.to_string())
+                        .collect();
+                    cycle.push(tasks[dep_index].name.to_string());
+                    return Some(cycle);
+                }
+                Mark::Unvisited => {
+                    if let Some(cycle) = visit(dep_index, tasks, by_name, marks, path) {
+                        return Some(cycle);
+                    }
+                }
+                Mark::Done => {}
+            }
+        }
+
+        path.pop();
+        marks[index] = Mark::Done;
+        None
+    }
+
+    let by_name: HashMap<&str, usize> = tasks
+        .iter()
+        .enumerate()
+        .map(|(i, task)| (task.name, i))
+        .collect();
+    let mut marks = vec![Mark::Unvisited; tasks.len()];
+    let mut path = Vec::new();
+
+    for index in 0..tasks.len() {
+        if marks[index] == Mark::Unvisited {
+            if let Some(cycle) = visit(index, tasks, &by_name, &mut marks, &mut path) {
+                return Some(cycle);
+            }
+        }
+    }
+
+    None
+}
+
+/// Run tasks in dependency order, calling `execute` with the index of each task.
+///
+/// With `parallel`, every task whose dependencies have succeeded runs concurrently
+/// and a failure only stops the tasks that (transitively) depend on it. Without it,
+/// tasks run one at a time in declaration order, deferred until their dependencies
+/// are done, and the first failure stops everything that hasn't started yet.
+///
+/// Tasks whose dependencies can never be satisfied (unknown names or cycles) are
+/// reported as [`TaskOutcome::NotRun`].
+pub fn run<E, F>(tasks: &[Task], parallel: bool, execute: F) -> Vec<TaskOutcome<E>>
+where
+    E: Send,
+    F: Fn(usize) -> Result<(), E> + Sync,
+{
+    /// Progress of a single task
+    enum State<E> {
+        Pending,
+        Running,
+        Done(TaskOutcome<E>),
+    }
+
+    impl<E> State<E> {
+        fn succeeded(&self) -> bool {
+            matches!(self, State::Done(TaskOutcome::Succeeded))
+        }
+
+        fn stopped(&self) -> bool {
+            matches!(
+                self,
+                State::Done(TaskOutcome::Failed(_)) | State::Done(TaskOutcome::NotRun)
+            )
+        }
+    }
+
+    let by_name: HashMap<&str, usize> = tasks
+        .iter()
+        .enumerate()
+        .map(|(i, task)| (task.name, i))
+        .collect();
+    let mut states: Vec<State<E>> = tasks.iter().map(|_| State::Pending).collect();
+    let execute = &execute;
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        let mut running = 0;
+
+        loop {
+            let any_stopped = states.iter().any(State::stopped);
+
+            // Mark tasks that can no longer run
+            for index in 0..tasks.len() {
+                if !matches!(states[index], State::Pending) {
+                    continue;
+                }
+                let blocked = tasks[index].depends_on.iter().any(|dep| {
+                    by_name
+                        .get(dep.as_str())
+                        .is_some_and(|&i| states[i].stopped())
+                });
+                if blocked || (!parallel && any_stopped) {
+                    states[index] = State::Done(TaskOutcome::NotRun);
+                }
+            }
+
+            // Start every task whose dependencies have all succeeded
+            for index in 0..tasks.len() {
+                if !matches!(states[index], State::Pending) || (!parallel && running > 0) {
+                    continue;
+                }
+                let ready = tasks[index].depends_on.iter().all(|dep| {
+                    by_name
+                        .get(dep.as_str())
+                        .is_some_and(|&i| states[i].succeeded())
+                });
+                if ready {
+                    states[index] = State::Running;
+                    running += 1;
+                    let tx = tx.clone();
+                    scope.spawn(move || {
+                        // The receiver outlives all workers, so sending cannot fail
+                        let _ = tx.send((index, execute(index)));
+                    });
+                }
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let Ok((index, result)) = rx.recv() else {
+                break;
+            };
+            running -= 1;
+            states[index] = State::Done(match result {
+                Ok(()) => TaskOutcome::Succeeded,
+                Err(e) => TaskOutcome::Failed(e),
+            });
+        }
+    });
+
+    states
+        .into_iter()
+        .map(|state| match state {
+            State::Done(outcome) => outcome,
+            State::Pending | State::Running => TaskOutcome::NotRun,
+        })
+        .collect()
+}
+
+/// Indices of `tasks` in the order [`run`] starts them when running
+/// sequentially and every task succeeds
+pub fn sequential_order(tasks: &[Task]) -> Vec<usize> {
+    let order = Mutex::new(Vec::new());
+    run::<(), _>(tasks, false, |index| {
+        order.lock().unwrap().push(index);
+        Ok(())
+    });
+    order.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn deps(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sequential_respects_dependencies() {
+        let typecheck_deps = deps(&["generate-code"]);
+        let tasks = [
+            Task {
+                name: "typecheck",
+                depends_on: &typecheck_deps,
+            },
+            Task {
+                name: "generate-code",
+                depends_on: &[],
+            },
+            Task {
+                name: "lint",
+                depends_on: &[],
+            },
+        ];
+
+        let order = Mutex::new(Vec::new());
+        let outcomes = run::<(), _>(&tasks, false, |i| {
+            order.lock().unwrap().push(tasks[i].name);
+            Ok(())
+        });
+
+        assert!(outcomes.iter().all(|o| o.is_success()));
+        assert_eq!(
+            order.into_inner().unwrap(),
+            vec!["generate-code", "typecheck", "lint"]
+        );
+        assert_eq!(sequential_order(&tasks), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_parallel_waits_for_dependencies() {
+        let typecheck_deps = deps(&["generate-code"]);
+        let tasks = [
+            Task {
+                name: "generate-code",
+                depends_on: &[],
+            },
+            Task {
+                name: "typecheck",
+                depends_on: &typecheck_deps,
+            },
+            Task {
+                name: "lint",
+                depends_on: &[],
+            },
+        ];
+
+        let events = Mutex::new(Vec::new());
+        let outcomes = run::<(), _>(&tasks, true, |i| {
+            events
+                .lock()
+                .unwrap()
+                .push(format!("start {}", tasks[i].name));
+            if tasks[i].name == "generate-code" {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            events
+                .lock()
+                .unwrap()
+                .push(format!("end {}", tasks[i].name));
+            Ok(())
+        });
+
+        assert!(outcomes.iter().all(|o| o.is_success()));
+        let events = events.into_inner().unwrap();
+        let position = |event: &str| events.iter().position(|e| e == event).unwrap();
+
+        assert!(position("end generate-code") < position("start typecheck"));
+        // lint runs alongside generate-code rather than after it
+        assert!(position("start lint") < position("end generate-code"));
+    }
+
+    #[test]
+    fn test_parallel_failure_propagates_to_dependents_only() {
+        let b_deps = deps(&["a"]);
+        let c_deps = deps(&["b"]);
+        let tasks = [
+            Task {
+                name: "a",
+                depends_on: &[],
+            },
+            Task {
+                name: "b",
+                depends_on: &b_deps,
+            },
+            Task {
+                name: "c",
+                depends_on: &c_deps,
+            },
+            Task {
+                name: "independent",
+                depends_on: &[],
+            },
+        ];
+
+        let outcomes = run(&tasks, true, |i| {
+            if tasks[i].name == "a" {
+                Err("boom")
+            } else {
+                Ok(())
+            }
+        });
+
+        assert!(matches!(outcomes[0], TaskOutcome::Failed("boom")));
+        assert!(matches!(outcomes[1], TaskOutcome::NotRun));
+        assert!(matches!(outcomes[2], TaskOutcome::NotRun));
+        assert!(outcomes[3].is_success());
+    }
+
+    #[test]
+    fn test_sequential_failure_stops_remaining_tasks() {
+        let tasks = [
+            Task {
+                name: "first",
+                depends_on: &[],
+            },
+            Task {
+                name: "second",
+                depends_on: &[],
+            },
+            Task {
+                name: "third",
+                depends_on: &[],
+            },
+        ];
+
+        let outcomes = run(&tasks, false, |i| if i == 1 { Err(()) } else { Ok(()) });
+
+        assert!(outcomes[0].is_success());
+        assert!(matches!(outcomes[1], TaskOutcome::Failed(())));
+        assert!(matches!(outcomes[2], TaskOutcome::NotRun));
+    }
+
+    #[test]
+    fn test_unsatisfiable_dependencies_are_not_run() {
+        let a_deps = deps(&["b"]);
+        let b_deps = deps(&["a"]);
+        let c_deps = deps(&["missing"]);
+        let tasks = [
+            Task {
+                name: "a",
+                depends_on: &a_deps,
+            },
+            Task {
+                name: "b",
+                depends_on: &b_deps,
+            },
+            Task {
+                name: "c",
+                depends_on: &c_deps,
+            },
+        ];
+
+        let outcomes = run::<(), _>(&tasks, true, |_| Ok(()));
+        assert!(outcomes.iter().all(|o| matches!(o, TaskOutcome::NotRun)));
+    }
+
+    #[test]
+    fn test_find_cycle() {
+        let a_deps = deps(&["b"]);
+        let b_deps = deps(&["c"]);
+        let c_deps = deps(&["a"]);
+        let tasks = [
+            Task {
+                name: "a",
+                depends_on: &a_deps,
+            },
+            Task {
+                name: "b",
+                depends_on: &b_deps,
+            },
+            Task {
+                name: "c",
+                depends_on: &c_deps,
+            },
+        ];
+        assert_eq!(find_cycle(&tasks), Some(deps(&["a", "b", "c", "a"])));
+
+        let self_deps = deps(&["self"]);
+        let tasks = [Task {
+            name: "self",
+            depends_on: &self_deps,
+        }];
+        assert_eq!(find_cycle(&tasks), Some(deps(&["self", "self"])));
+
+        let b_deps = deps(&["a"]);
+        let tasks = [
+            Task {
+                name: "a",
+                depends_on: &[],
+            },
+            Task {
+                name: "b",
+                depends_on: &b_deps,
+            },
+        ];
+        assert_eq!(find_cycle(&tasks), None);
+    }
+
+    #[test]
+    fn test_unknown_dependencies() {
+        let b_deps = deps(&["a", "missing"]);
+        let tasks = [
+            Task {
+                name: "a",
+                depends_on: &[],
+            },
+            Task {
+                name: "b",
+                depends_on: &b_deps,
+            },
+        ];
+        assert_eq!(
+            unknown_dependencies(&tasks),
+            vec![UnknownDependency {
+                task: "b",
+                dependency: "missing"
+            }]
+        );
+    }
+}
@@ -0,0 +1,529 @@
+//! Choosing which discovered repositories `hookmaster add --interactive`
+//! installs hooks to, and confirming runs that would touch many of them
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config_source::ConfigSource;
+
+/// A repository found by `add`, offered for selection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// Path to the repository
+    pub repo: PathBuf,
+    /// Whether the repository has a hookmaster.toml or githooks.toml
+    pub has_config: bool,
+}
+
+impl Candidate {
+    /// Describe the repository at `repo`
+    pub fn new(repo: &Path) -> Self {
+        let has_config = [ConfigSource::HookmasterToml, ConfigSource::GitHooksToml]
+            .iter()
+            .any(|source| source.path(repo).is_file());
+        Self {
+            repo: repo.to_path_buf(),
+            has_config,
+        }
+    }
+}
+
+/// Lets the user change which repositories are selected. Implementations
+/// only present the choice; the initial selection and what is done with the
+/// result are decided by [`select_repositories`].
+pub trait RepoSelector {
+    /// Update `selected`, which has an entry for each of `candidates`.
+    /// Fails if the user cancels.
+    fn select(&mut self, candidates: &[Candidate], selected: &mut [bool]) -> Result<()>;
+}
+
+/// Repositories selected before the user changes anything: those that
+/// already have a configuration
+pub fn preselection(candidates: &[Candidate]) -> Vec<bool> {
+    candidates
+        .iter()
+        .map(|candidate| candidate.has_config)
+        .collect()
+}
+
+/// Let `selector` choose among `repositories`, returning the chosen ones in
+/// their original order
+pub fn select_repositories(
+    repositories: Vec<PathBuf>,
+    selector: &mut dyn RepoSelector,
+) -> Result<Vec<PathBuf>> {
+    let candidates: Vec<Candidate> = repositories
+        .iter()
+        .map(|repo| Candidate::new(repo))
+        .collect();
+    let mut selected = preselection(&candidates);
+    selector.select(&candidates, &mut selected)?;
+    Ok(repositories
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(repo, selected)| selected.then_some(repo))
+        .collect())
+}
+
+/// Keeps the preselection without asking, for `--yes`
+#[derive(Debug, Default)]
+pub struct KeepPreselection;
+
+impl RepoSelector for KeepPreselection {
+    fn select(&mut self, _candidates: &[Candidate], _selected: &mut [bool]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The selector for the current terminal: the checkbox list where stdin and
+/// stderr are terminals on Unix, otherwise a numbered prompt on stdin
+pub fn interactive_selector() -> Box<dyn RepoSelector> {
+    if cfg!(unix) && io::stdin().is_terminal() && io::stderr().is_terminal() {
+        Box::new(CheckboxList)
+    } else {
+        Box::new(NumberedPrompt::new(io::stdin().lock(), io::stderr()))
+    }
+}
+
+/// Most repositories `add` installs to without asking for confirmation,
+/// unless the user configuration sets `confirm-threshold`
+pub const DEFAULT_CONFIRM_THRESHOLD: usize = 25;
+
+/// Repositories listed when asking to confirm a large run
+const CONFIRM_SAMPLE: usize = 5;
+
+/// Ask on `output` whether to install hooks to `repositories` when there
+/// are more than `threshold` of them, reading the answer from `input`. When
+/// there is no one to ask (`interactive` is false), a large run fails, so
+/// that scripts have to confirm it with `--yes`.
+pub fn confirm_large_run(
+    repositories: &[PathBuf],
+    threshold: usize,
+    interactive: bool,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<()> {
+    let count = repositories.len();
+    if count <= threshold {
+        return Ok(());
+    }
+    if !interactive {
+        return Err(anyhow!(
+            "Found {count} repositories, more than the confirmation threshold of {threshold}; pass --yes to install hooks to all of them"
+        ));
+    }
+
+    writeln!(output, "Found {count} repositories, including:")?;
+    for repo in repositories.iter().take(CONFIRM_SAMPLE) {
+        writeln!(output, "  {}", repo.display())?;
+    }
+    if count > CONFIRM_SAMPLE {
+        writeln!(output, "  ... and {} more", count - CONFIRM_SAMPLE)?;
+    }
+    write!(output, "Install hooks to all {count} repositories? [y/N] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err(anyhow!("Cancelled; no hooks were installed"))
+    }
+}
+
+/// [`confirm_large_run`] on the terminal, asking only when both stdin and
+/// stdout are one
+pub fn confirm_on_terminal(repositories: &[PathBuf], threshold: usize) -> Result<()> {
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    confirm_large_run(
+        repositories,
+        threshold,
+        interactive,
+        io::stdin().lock(),
+        io::stdout(),
+    )
+}
+
+/// Asks for the numbers of repositories to toggle, one line at a time, until
+/// an empty line or the end of input
+pub struct NumberedPrompt<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> NumberedPrompt<R, W> {
+    /// Prompt on `output` and read the answers from `input`
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+}
+
+impl<R: BufRead, W: Write> RepoSelector for NumberedPrompt<R, W> {
+    fn select(&mut self, candidates: &[Candidate], selected: &mut [bool]) -> Result<()> {
+        loop {
+            writeln!(self.output)?;
+            for (i, candidate) in candidates.iter().enumerate() {
+                let mark = if selected[i] { 'x' } else { ' ' };
+                writeln!(
+                    self.output,
+                    "{:>3}. [{mark}] {}",
+                    i + 1,
+                    candidate.repo.display()
+                )?;
+            }
+            write!(
+                self.output,
+                "Toggle repositories by number (e.g. 1 3-5), 'a' for all, 'n' for none, Enter to install: "
+            )?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                writeln!(self.output)?;
+                return Ok(());
+            }
+            if let Err(err) = toggle(line.trim(), selected) {
+                writeln!(self.output, "{err}")?;
+            }
+        }
+    }
+}
+
+/// Apply an answer to the numbered prompt: `a`, `n`, or numbers and ranges
+/// of repositories to toggle. Nothing changes if any part is invalid.
+fn toggle(answer: &str, selected: &mut [bool]) -> Result<()> {
+    match answer {
+        "a" => selected.fill(true),
+        "n" => selected.fill(false),
+        _ => {
+            let mut indexes = Vec::new();
+            for part in answer.split(|c: char| c == ',' || c.is_whitespace()) {
+                if part.is_empty() {
+                    continue;
+                }
+                let (start, end) = part.split_once('-').unwrap_or((part, part));
+                let range = start.parse::<usize>().ok().zip(end.parse::<usize>().ok());
+                match range {
+                    Some((start, end)) if 1 <= start && start <= end && end <= selected.len() => {
+                        indexes.extend(start - 1..end)
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "Invalid selection '{part}': expected numbers from 1 to {}",
+                            selected.len()
+                        ))
+                    }
+                }
+            }
+            for i in indexes {
+                selected[i] = !selected[i];
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A key pressed in the checkbox list
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Toggle,
+    All,
+    Confirm,
+    Cancel,
+    Other,
+}
+
+/// Read one key press from terminal input without line buffering
+fn read_key(input: &mut impl Iterator<Item = io::Result<u8>>) -> io::Result<Key> {
+    let Some(byte) = input.next().transpose()? else {
+        return Ok(Key::Cancel);
+    };
+    Ok(match byte {
+        b'\r' | b'\n' => Key::Confirm,
+        b' ' => Key::Toggle,
+        b'a' => Key::All,
+        b'k' => Key::Up,
+        b'j' => Key::Down,
+        b'q' | 3 => Key::Cancel,
+        // Arrow keys arrive as `ESC [ A` and `ESC [ B`
+        0x1b => match (input.next().transpose()?, input.next().transpose()?) {
+            (Some(b'['), Some(b'A')) => Key::Up,
+            (Some(b'['), Some(b'B')) => Key::Down,
+            _ => Key::Other,
+        },
+        _ => Key::Other,
+    })
+}
+
+/// Selection state of the checkbox list
+#[derive(Debug)]
+struct Checkboxes<'a> {
+    selected: &'a mut [bool],
+    cursor: usize,
+}
+
+impl Checkboxes<'_> {
+    /// Handle a key, returning `Some` when the user confirms or cancels
+    fn press(&mut self, key: Key) -> Option<bool> {
+        match key {
+            Key::Up => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down => self.cursor = (self.cursor + 1).min(self.selected.len() - 1),
+            Key::Toggle => self.selected[self.cursor] = !self.selected[self.cursor],
+            // Selects everything, or clears everything when all are selected
+            Key::All => {
+                let all = self.selected.iter().all(|selected| *selected);
+                self.selected.fill(!all);
+            }
+            Key::Confirm => return Some(true),
+            Key::Cancel => return Some(false),
+            Key::Other => {}
+        }
+        None
+    }
+
+    fn render(&self, candidates: &[Candidate]) -> String {
+        // Kept short, as a wrapped line would throw off redrawing
+        let mut text = String::from(
+            "Select repositories (space: toggle, a: all, enter: install, q: cancel)\n",
+        );
+        for (i, candidate) in candidates.iter().enumerate() {
+            let pointer = if i == self.cursor { '>' } else { ' ' };
+            let mark = if self.selected[i] { 'x' } else { ' ' };
+            text.push_str(&format!(
+                "{pointer} [{mark}] {}\n",
+                candidate.repo.display()
+            ));
+        }
+        text
+    }
+}
+
+/// Checkbox list on the terminal, moved through with the arrow keys
+struct CheckboxList;
+
+impl RepoSelector for CheckboxList {
+    fn select(&mut self, candidates: &[Candidate], selected: &mut [bool]) -> Result<()> {
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        let tty = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .context("Failed to open the terminal")?;
+        let _mode = TerminalMode::keypresses(&tty)?;
+        let mut input = io::Read::bytes(tty.try_clone()?);
+        let mut output = tty;
+
+        let mut checkboxes = Checkboxes {
+            selected,
+            cursor: 0,
+        };
+        let lines = candidates.len() + 1;
+        write!(output, "{}", checkboxes.render(candidates))?;
+        loop {
+            if let Some(confirmed) = checkboxes.press(read_key(&mut input)?) {
+                return if confirmed {
+                    Ok(())
+                } else {
+                    Err(anyhow!("Selection cancelled"))
+                };
+            }
+            // Move back to the top of the list and draw it again
+            write!(
+                output,
+                "\x1b[{lines}A\r\x1b[J{}",
+                checkboxes.render(candidates)
+            )?;
+        }
+    }
+}
+
+/// Terminal switched to reading single key presses without echo, restored
+/// to its previous settings when dropped
+struct TerminalMode {
+    tty: std::fs::File,
+    saved: String,
+}
+
+impl TerminalMode {
+    fn keypresses(tty: &std::fs::File) -> Result<Self> {
+        let saved = stty(tty, &["-g"])?;
+        stty(tty, &["-icanon", "-echo", "-isig", "min", "1"])?;
+        Ok(Self {
+            tty: tty.try_clone()?,
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for TerminalMode {
+    fn drop(&mut self) {
+        let _ = stty(&self.tty, &[&self.saved]);
+    }
+}
+
+/// Run `stty` on the terminal `tty`, returning its output
+fn stty(tty: &std::fs::File, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(tty.try_clone()?)
+        .output()
+        .context("Failed to run stty")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "stty failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn candidates(count: usize) -> Vec<Candidate> {
+        (1..=count)
+            .map(|i| Candidate {
+                repo: PathBuf::from(format!("repo{i}")),
+                has_config: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_preselects_repositories_with_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let repos: Vec<PathBuf> = ["configured", "legacy", "plain"]
+            .iter()
+            .map(|name| temp_dir.path().join(name))
+            .collect();
+        for repo in &repos {
+            fs::create_dir(repo).unwrap();
+        }
+        fs::write(repos[0].join("hookmaster.toml"), "").unwrap();
+        fs::write(repos[1].join("githooks.toml"), "").unwrap();
+
+        let candidates: Vec<Candidate> = repos.iter().map(|repo| Candidate::new(repo)).collect();
+        assert_eq!(preselection(&candidates), vec![true, true, false]);
+        assert_eq!(
+            select_repositories(repos.clone(), &mut KeepPreselection).unwrap(),
+            repos[..2]
+        );
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut selected = vec![false; 5];
+        toggle("1 3-4", &mut selected).unwrap();
+        assert_eq!(selected, vec![true, false, true, true, false]);
+        toggle("1,5", &mut selected).unwrap();
+        assert_eq!(selected, vec![false, false, true, true, true]);
+        toggle("a", &mut selected).unwrap();
+        assert_eq!(selected, vec![true; 5]);
+        toggle("n", &mut selected).unwrap();
+        assert_eq!(selected, vec![false; 5]);
+
+        for answer in ["0", "6", "2-1", "x", "1 x"] {
+            assert!(toggle(answer, &mut selected).is_err(), "{answer}");
+            assert_eq!(selected, vec![false; 5], "{answer}");
+        }
+    }
+
+    #[test]
+    fn test_numbered_prompt() {
+        let candidates = candidates(3);
+        let mut selected = vec![true, false, false];
+        let mut output = Vec::new();
+        NumberedPrompt::new(&b"2\n9\n1\n\n"[..], &mut output)
+            .select(&candidates, &mut selected)
+            .unwrap();
+        assert_eq!(selected, vec![false, true, false]);
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("  1. [x] repo1"));
+        assert!(output.contains("  2. [x] repo2"));
+        assert!(output.contains("Invalid selection '9'"));
+
+        // The end of input accepts the selection
+        NumberedPrompt::new(&b"3"[..], io::sink())
+            .select(&candidates, &mut selected)
+            .unwrap();
+        assert_eq!(selected, vec![false, true, true]);
+    }
+
+    #[test]
+    fn test_confirm_large_run() {
+        let repos: Vec<PathBuf> = (1..=7).map(|i| PathBuf::from(format!("repo{i}"))).collect();
+
+        // Up to the threshold nothing is asked, even without a terminal
+        confirm_large_run(&repos, 7, false, io::empty(), io::sink()).unwrap();
+
+        let mut output = Vec::new();
+        confirm_large_run(&repos, 6, true, &b"y\n"[..], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Found 7 repositories, including:\n  repo1\n"));
+        assert!(output.contains("  repo5\n  ... and 2 more\n"));
+        assert!(!output.contains("repo6"));
+        assert!(output.ends_with("Install hooks to all 7 repositories? [y/N] "));
+
+        for answer in ["YES\n", " y \n"] {
+            confirm_large_run(&repos, 6, true, answer.as_bytes(), io::sink()).unwrap();
+        }
+        for answer in ["n\n", "\n", ""] {
+            let err =
+                confirm_large_run(&repos, 6, true, answer.as_bytes(), io::sink()).unwrap_err();
+            assert_eq!(err.to_string(), "Cancelled; no hooks were installed");
+        }
+    }
+
+    #[test]
+    fn test_confirm_large_run_without_terminal() {
+        let repos: Vec<PathBuf> = (1..=3).map(|i| PathBuf::from(format!("repo{i}"))).collect();
+        let mut output = Vec::new();
+        let err = confirm_large_run(&repos, 2, false, &b"y\n"[..], &mut output).unwrap_err();
+        assert!(err.to_string().contains("pass --yes"));
+        assert!(err.to_string().contains("threshold of 2"));
+        // Nothing is asked that no one could answer
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_checkbox_keys() {
+        let mut input = b"\x1b[B \x1b[Aaq\r".iter().map(|b| Ok(*b));
+        let keys: Vec<Key> = (0..5).map(|_| read_key(&mut input).unwrap()).collect();
+        assert_eq!(
+            keys,
+            vec![Key::Down, Key::Toggle, Key::Up, Key::All, Key::Cancel]
+        );
+        assert_eq!(read_key(&mut input).unwrap(), Key::Confirm);
+        assert_eq!(read_key(&mut input).unwrap(), Key::Cancel);
+
+        let candidates = candidates(3);
+        let mut selected = vec![false; 3];
+        let mut checkboxes = Checkboxes {
+            selected: &mut selected,
+            cursor: 0,
+        };
+        for key in [Key::Up, Key::Down, Key::Toggle, Key::Down, Key::Down] {
+            assert_eq!(checkboxes.press(key), None);
+        }
+        assert_eq!(checkboxes.cursor, 2);
+        assert!(checkboxes
+            .render(&candidates)
+            .ends_with("  [ ] repo1\n  [x] repo2\n> [ ] repo3\n"));
+        assert_eq!(checkboxes.press(Key::All), None);
+        assert_eq!(checkboxes.selected, [true; 3]);
+        assert_eq!(checkboxes.press(Key::All), None);
+        assert_eq!(checkboxes.selected, [false; 3]);
+        assert_eq!(checkboxes.press(Key::Confirm), Some(true));
+        assert_eq!(checkboxes.press(Key::Cancel), Some(false));
+    }
+}
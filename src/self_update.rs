@@ -0,0 +1,589 @@
+//! Updating hookmaster itself from its GitHub releases, and the notice that
+//! a newer version is out.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::UserConfig;
+
+/// The version of this hookmaster
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Environment variable that turns off the automatic check for a newer
+/// version when set to anything but an empty string or `0`
+pub const NO_UPDATE_CHECK_ENV: &str = "HOOKMASTER_NO_UPDATE_CHECK";
+
+/// Release asset listing the SHA-256 checksums of the other assets
+pub const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// The GitHub API endpoint describing the latest release
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/vivainio/hookmaster-rs/releases/latest";
+
+/// How long the automatic check trusts the latest version it last saw
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// File next to the user configuration remembering the last automatic check
+const CHECK_FILE: &str = "update-check.json";
+
+/// A published release of hookmaster
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    /// Version of the release, without the `v` of its tag
+    pub version: String,
+    /// Files attached to the release
+    pub assets: Vec<Asset>,
+}
+
+/// A file attached to a release
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    /// File name, such as `hookmaster-linux.tar.gz`
+    pub name: String,
+    /// Where the file can be downloaded from
+    pub url: String,
+}
+
+/// The network access updating needs, so that choosing, verifying and
+/// installing a release can be tested without it
+pub trait ReleaseSource {
+    /// The latest published release
+    fn latest_release(&self) -> Result<Release>;
+
+    /// The content of the file at `url`
+    fn download(&self, url: &str) -> Result<Vec<u8>>;
+}
+
+/// Asks the GitHub API with curl, so hookmaster needs no HTTP client of its
+/// own
+pub struct GitHubReleases {
+    timeout: Duration,
+}
+
+impl GitHubReleases {
+    /// Releases fetched with requests that give up after `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    fn curl(&self, url: &str, accept: &str) -> Result<Vec<u8>> {
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args(["--max-time", &self.timeout.as_secs().max(1).to_string()])
+            .args(["--header", &format!("Accept: {accept}")])
+            .args(["--user-agent", &format!("hookmaster/{CURRENT_VERSION}")])
+            .arg(url)
+            .output()
+            .with_context(|| "Failed to run curl")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to fetch {url}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl ReleaseSource for GitHubReleases {
+    fn latest_release(&self) -> Result<Release> {
+        let body = self.curl(LATEST_RELEASE_URL, "application/vnd.github+json")?;
+        let body = String::from_utf8(body).with_context(|| "Invalid UTF-8 in response")?;
+        parse_release(&body)
+    }
+
+    fn download(&self, url: &str) -> Result<Vec<u8>> {
+        self.curl(url, "application/octet-stream")
+    }
+}
+
+/// Parse a release as the GitHub releases API describes it
+pub fn parse_release(json: &str) -> Result<Release> {
+    let value: JsonValue =
+        serde_json::from_str(json).with_context(|| "Failed to parse the release")?;
+    let tag = value["tag_name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("The release has no tag_name"))?;
+    let assets = value["assets"]
+        .as_array()
+        .map(|assets| {
+            assets
+                .iter()
+                .filter_map(|asset| {
+                    Some(Asset {
+                        name: asset["name"].as_str()?.to_string(),
+                        url: asset["browser_download_url"].as_str()?.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Release {
+        version: tag.trim_start_matches('v').to_string(),
+        assets,
+    })
+}
+
+/// The numbers of `version`, such as `[0, 2, 1]` for `0.2.1`; anything
+/// after a `-` or `+` is ignored
+fn version_numbers(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next().unwrap_or(version);
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Check if `latest` is a newer version than `current`. A version that
+/// doesn't parse is never newer.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    match (version_numbers(latest), version_numbers(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
+
+/// Name of the release archive for the platform `os` and `arch`, as named
+/// in `std::env::consts`
+fn asset_name(os: &str, arch: &str) -> Option<&'static str> {
+    match (os, arch) {
+        ("linux", "x86_64") => Some("hookmaster-linux.tar.gz"),
+        ("macos", "x86_64") => Some("hookmaster-macos.tar.gz"),
+        ("macos", "aarch64") => Some("hookmaster-macos-arm.tar.gz"),
+        ("windows", "x86_64") => Some("hookmaster-windows.zip"),
+        _ => None,
+    }
+}
+
+/// The archive of `release` for the platform `os` and `arch`
+pub fn select_asset<'r>(release: &'r Release, os: &str, arch: &str) -> Result<&'r Asset> {
+    let name = asset_name(os, arch)
+        .ok_or_else(|| anyhow!("hookmaster publishes no binaries for {os}/{arch}"))?;
+    find_asset(release, name)
+}
+
+fn find_asset<'r>(release: &'r Release, name: &str) -> Result<&'r Asset> {
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name == name)
+        .ok_or_else(|| anyhow!("hookmaster {} has no {name}", release.version))
+}
+
+/// Check `content` against the checksum `sums` lists for `name`, in the
+/// `<hex>  <name>` format of `sha256sum`
+pub fn verify_checksum(content: &[u8], sums: &str, name: &str) -> Result<()> {
+    let expected = sums
+        .lines()
+        .find_map(|line| {
+            let (hash, file) = line.trim().split_once(char::is_whitespace)?;
+            // `sha256sum --binary` marks the file name with `*`
+            let file = file.trim_start().trim_start_matches('*');
+            (file == name).then(|| hash.to_ascii_lowercase())
+        })
+        .ok_or_else(|| anyhow!("{name} is not listed in {CHECKSUMS_ASSET}"))?;
+    let actual = format!("{:x}", Sha256::digest(content));
+    if actual != expected {
+        return Err(anyhow!(
+            "Checksum mismatch for {name}: {CHECKSUMS_ASSET} lists {expected}, the download has {actual}"
+        ));
+    }
+    Ok(())
+}
+
+/// File name of the hookmaster executable in release archives
+fn binary_name() -> &'static str {
+    if cfg!(windows) {
+        "hookmaster.exe"
+    } else {
+        "hookmaster"
+    }
+}
+
+/// Unpack `archive` into `dir` with tar, which also reads the zip archives
+/// of Windows releases there, and return the hookmaster executable in it
+fn extract_binary(archive: &Path, dir: &Path) -> Result<PathBuf> {
+    let output = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(dir)
+        .output()
+        .with_context(|| "Failed to run tar")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to unpack {}: {}",
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let binary = dir.join(binary_name());
+    if !binary.is_file() {
+        return Err(anyhow!("{} has no {}", archive.display(), binary_name()));
+    }
+    Ok(binary)
+}
+
+/// Replace the executable at `current` with `binary`. The new executable is
+/// written next to it and renamed over it, so that the path always holds a
+/// complete executable. Windows doesn't allow replacing a running
+/// executable, but does allow renaming it, so there the current one is
+/// moved aside to `.old` first and removed by the next update.
+pub fn replace_executable(current: &Path, binary: &[u8]) -> Result<()> {
+    let dir = current
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", current.display()))?;
+    let staged = dir.join(format!(".hookmaster-update-{}", std::process::id()));
+    fs::write(&staged, binary).with_context(|| format!("Failed to write {}", staged.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make {} executable", staged.display()))?;
+    }
+
+    let result = if cfg!(windows) {
+        let old = current.with_extension("old");
+        let _ = fs::remove_file(&old);
+        fs::rename(current, &old)
+            .with_context(|| format!("Failed to move {} aside", current.display()))
+            .and_then(|()| {
+                fs::rename(&staged, current).or_else(|e| {
+                    // Put the current executable back rather than leave none
+                    let _ = fs::rename(&old, current);
+                    Err(e).with_context(|| format!("Failed to replace {}", current.display()))
+                })
+            })
+    } else {
+        fs::rename(&staged, current)
+            .with_context(|| format!("Failed to replace {}", current.display()))
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
+
+/// The latest release from `source` if it is newer than `current`
+pub fn check(source: &dyn ReleaseSource, current: &str) -> Result<Option<Release>> {
+    let release = source.latest_release()?;
+    Ok(is_newer(&release.version, current).then_some(release))
+}
+
+/// Update the executable at `exe` of version `current` to the latest
+/// release from `source` for the platform `os` and `arch`, returning the
+/// version installed, or `None` if `current` is the latest. The archive is
+/// checked against the release's [`CHECKSUMS_ASSET`] before anything is
+/// replaced.
+pub fn update(
+    source: &dyn ReleaseSource,
+    current: &str,
+    exe: &Path,
+    os: &str,
+    arch: &str,
+) -> Result<Option<String>> {
+    let Some(release) = check(source, current)? else {
+        return Ok(None);
+    };
+    let asset = select_asset(&release, os, arch)?;
+    let sums = find_asset(&release, CHECKSUMS_ASSET)
+        .with_context(|| "Not updating without checksums to verify the download")?;
+    let sums = String::from_utf8(source.download(&sums.url)?)
+        .with_context(|| format!("Invalid UTF-8 in {CHECKSUMS_ASSET}"))?;
+    let archive = source.download(&asset.url)?;
+    verify_checksum(&archive, &sums, &asset.name)?;
+
+    let exe_dir = exe
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", exe.display()))?;
+    // Unpack next to the executable, so the final rename stays on one filesystem
+    let work_dir = exe_dir.join(format!(".hookmaster-update-{}.d", std::process::id()));
+    fs::create_dir_all(&work_dir)
+        .with_context(|| format!("Failed to create {}", work_dir.display()))?;
+    let result = (|| {
+        let archive_path = work_dir.join(&asset.name);
+        fs::write(&archive_path, &archive)
+            .with_context(|| format!("Failed to write {}", archive_path.display()))?;
+        let binary = extract_binary(&archive_path, &work_dir)?;
+        let binary =
+            fs::read(&binary).with_context(|| format!("Failed to read {}", binary.display()))?;
+        replace_executable(exe, &binary)
+    })();
+    let _ = fs::remove_dir_all(&work_dir);
+    result.map(|()| Some(release.version))
+}
+
+/// Check if the automatic check for a newer version is on, which it is
+/// unless [`NO_UPDATE_CHECK_ENV`] is set
+pub fn automatic_checks_enabled() -> bool {
+    env::var(NO_UPDATE_CHECK_ENV).map_or(true, |value| value.is_empty() || value == "0")
+}
+
+/// Location of the file remembering the last automatic check, next to the
+/// user configuration
+pub fn check_file() -> Option<PathBuf> {
+    Some(UserConfig::path()?.with_file_name(CHECK_FILE))
+}
+
+/// A notice that a version newer than `current` is out, for the automatic
+/// check. The latest version seen is remembered in `check_file` for
+/// [`CHECK_INTERVAL`], so that `source` is asked at most once a day; a
+/// failed check counts too, so being offline doesn't slow every command.
+pub fn newer_version_notice(
+    source: &dyn ReleaseSource,
+    check_file: &Path,
+    current: &str,
+    now: SystemTime,
+) -> Option<String> {
+    let now_secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let cached: Option<JsonValue> = fs::read_to_string(check_file)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    let fresh = cached.as_ref().and_then(|cached| {
+        let checked = cached["checked"].as_u64()?;
+        (now_secs.saturating_sub(checked) < CHECK_INTERVAL.as_secs())
+            .then(|| cached["latest"].as_str().map(str::to_string))
+    });
+    let latest = match fresh {
+        Some(latest) => latest,
+        None => {
+            let latest = source.latest_release().ok().map(|release| release.version);
+            if let Some(dir) = check_file.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::write(
+                check_file,
+                json!({ "checked": now_secs, "latest": latest }).to_string(),
+            );
+            latest
+        }
+    };
+    latest
+        .filter(|latest| is_newer(latest, current))
+        .map(|latest| newer_version_message(&latest, current))
+}
+
+/// The message telling that `latest` is newer than `current`
+pub fn newer_version_message(latest: &str, current: &str) -> String {
+    format!("hookmaster {latest} is available (this is {current}); run 'hookmaster self-update' to install it")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use tempfile::TempDir;
+
+    const RELEASE_JSON: &str = r#"{
+        "tag_name": "v0.3.0",
+        "assets": [
+            {"name": "hookmaster-linux.tar.gz", "browser_download_url": "https://example.com/linux"},
+            {"name": "hookmaster-macos-arm.tar.gz", "browser_download_url": "https://example.com/macos-arm"},
+            {"name": "SHA256SUMS", "browser_download_url": "https://example.com/sums"}
+        ]
+    }"#;
+
+    /// URL and content of a file a fake source serves
+    type File = (String, Vec<u8>);
+
+    /// A release and the files it serves, recording what was downloaded
+    struct FakeSource {
+        release: Release,
+        files: Vec<File>,
+        downloads: RefCell<Vec<String>>,
+    }
+
+    impl ReleaseSource for FakeSource {
+        fn latest_release(&self) -> Result<Release> {
+            Ok(self.release.clone())
+        }
+
+        fn download(&self, url: &str) -> Result<Vec<u8>> {
+            self.downloads.borrow_mut().push(url.to_string());
+            self.files
+                .iter()
+                .find(|(file_url, _)| file_url == url)
+                .map(|(_, content)| content.clone())
+                .ok_or_else(|| anyhow!("404 for {url}"))
+        }
+    }
+
+    fn fake_source(files: Vec<File>) -> FakeSource {
+        FakeSource {
+            release: parse_release(RELEASE_JSON).unwrap(),
+            files,
+            downloads: RefCell::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn test_parse_release() {
+        let release = parse_release(RELEASE_JSON).unwrap();
+        assert_eq!(release.version, "0.3.0");
+        assert_eq!(release.assets.len(), 3);
+        assert_eq!(release.assets[0].url, "https://example.com/linux");
+        assert!(parse_release("{}").is_err());
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.0"));
+        assert!(is_newer("v0.10.0", "0.9.3"));
+        assert!(is_newer("1.0", "0.9.9"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.1.0", "0.2.0"));
+        assert!(!is_newer("0.2.0-rc.1", "0.2.0"));
+        assert!(!is_newer("latest", "0.1.0"));
+    }
+
+    #[test]
+    fn test_select_asset() {
+        let release = parse_release(RELEASE_JSON).unwrap();
+        assert_eq!(
+            select_asset(&release, "linux", "x86_64").unwrap().name,
+            "hookmaster-linux.tar.gz"
+        );
+        assert_eq!(
+            select_asset(&release, "macos", "aarch64").unwrap().name,
+            "hookmaster-macos-arm.tar.gz"
+        );
+        let err = select_asset(&release, "windows", "x86_64").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "hookmaster 0.3.0 has no hookmaster-windows.zip"
+        );
+        assert!(select_asset(&release, "freebsd", "x86_64").is_err());
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let sums = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  a.tar.gz\n\
+                    BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD *b.zip\n";
+        verify_checksum(b"abc", sums, "a.tar.gz").unwrap();
+        verify_checksum(b"abc", sums, "b.zip").unwrap();
+        let err = verify_checksum(b"abd", sums, "a.tar.gz").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Checksum mismatch for a.tar.gz"));
+        let err = verify_checksum(b"abc", sums, "c.zip").unwrap_err();
+        assert_eq!(err.to_string(), "c.zip is not listed in SHA256SUMS");
+    }
+
+    #[test]
+    fn test_replace_executable() {
+        let temp_dir = TempDir::new().unwrap();
+        let exe = temp_dir.path().join(binary_name());
+        fs::write(&exe, "old").unwrap();
+
+        replace_executable(&exe, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "new");
+        // Nothing is left behind next to it but the moved-aside one on Windows
+        let names: Vec<String> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| !name.ends_with(".old"))
+            .collect();
+        assert_eq!(names, vec![binary_name()]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&exe).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_update_verifies_and_replaces() {
+        let temp_dir = TempDir::new().unwrap();
+        let packaging = temp_dir.path().join("packaging");
+        fs::create_dir(&packaging).unwrap();
+        fs::write(packaging.join("hookmaster"), "#!/bin/sh\necho 0.3.0\n").unwrap();
+        let status = Command::new("tar")
+            .args(["-czf", "hookmaster-linux.tar.gz", "hookmaster"])
+            .current_dir(&packaging)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let archive = fs::read(packaging.join("hookmaster-linux.tar.gz")).unwrap();
+        let sums = format!("{:x}  hookmaster-linux.tar.gz\n", Sha256::digest(&archive));
+
+        let bin = temp_dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let exe = bin.join("hookmaster");
+        fs::write(&exe, "old").unwrap();
+
+        // A tampered download leaves the executable alone
+        let source = fake_source(vec![
+            (
+                "https://example.com/sums".to_string(),
+                sums.clone().into_bytes(),
+            ),
+            (
+                "https://example.com/linux".to_string(),
+                b"tampered".to_vec(),
+            ),
+        ]);
+        let err = update(&source, "0.1.0", &exe, "linux", "x86_64").unwrap_err();
+        assert!(err.to_string().starts_with("Checksum mismatch"));
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "old");
+
+        let source = fake_source(vec![
+            ("https://example.com/sums".to_string(), sums.into_bytes()),
+            ("https://example.com/linux".to_string(), archive),
+        ]);
+        assert_eq!(
+            update(&source, "0.3.0", &exe, "linux", "x86_64").unwrap(),
+            None
+        );
+        assert!(source.downloads.borrow().is_empty());
+        assert_eq!(
+            update(&source, "0.1.0", &exe, "linux", "x86_64").unwrap(),
+            Some("0.3.0".to_string())
+        );
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "#!/bin/sh\necho 0.3.0\n");
+        assert_eq!(fs::read_dir(&bin).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_update_needs_checksums() {
+        let temp_dir = TempDir::new().unwrap();
+        let exe = temp_dir.path().join("hookmaster");
+        fs::write(&exe, "old").unwrap();
+        let mut source = fake_source(Vec::new());
+        source
+            .release
+            .assets
+            .retain(|asset| asset.name != CHECKSUMS_ASSET);
+        let err = update(&source, "0.1.0", &exe, "linux", "x86_64").unwrap_err();
+        assert!(err.to_string().contains("without checksums"));
+        assert!(source.downloads.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_newer_version_notice_checks_once_a_day() {
+        let temp_dir = TempDir::new().unwrap();
+        let check_file = temp_dir.path().join("hookmaster").join(CHECK_FILE);
+        let source = fake_source(Vec::new());
+        let now = SystemTime::now();
+
+        assert_eq!(
+            newer_version_notice(&source, &check_file, "0.1.0", now).unwrap(),
+            "hookmaster 0.3.0 is available (this is 0.1.0); run 'hookmaster self-update' to install it"
+        );
+        assert!(newer_version_notice(&source, &check_file, "0.3.0", now).is_none());
+
+        // The remembered version is used until it is a day old
+        let mut newer = fake_source(Vec::new());
+        newer.release.version = "0.4.0".to_string();
+        let notice = newer_version_notice(&newer, &check_file, "0.1.0", now).unwrap();
+        assert!(notice.starts_with("hookmaster 0.3.0 "));
+        let tomorrow = now + CHECK_INTERVAL;
+        let notice = newer_version_notice(&newer, &check_file, "0.1.0", tomorrow).unwrap();
+        assert!(notice.starts_with("hookmaster 0.4.0 "));
+    }
+}
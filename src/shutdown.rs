@@ -0,0 +1,185 @@
+//! Passing on the signals that stop `hookmaster run` to the commands it
+//! runs, so that stopping a hook stops its commands too instead of leaving
+//! them running on their own
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How often the signal is checked for, and then whether the commands
+/// have exited
+#[cfg(unix)]
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A command started by a hook
+struct RunningCommand {
+    pid: u32,
+    /// How long it has to exit after the signal before it is killed
+    grace: Duration,
+}
+
+/// Commands running, which get the signal hookmaster gets
+static RUNNING: Mutex<Vec<RunningCommand>> = Mutex::new(Vec::new());
+
+fn running() -> MutexGuard<'static, Vec<RunningCommand>> {
+    RUNNING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Marks a command as running until dropped, which should be once it has
+/// been waited for
+pub struct Running {
+    pid: u32,
+}
+
+impl Running {
+    /// Mark the process `pid` as running, with `grace` to exit after a
+    /// signal is passed on to it
+    pub fn start(pid: u32, grace: Duration) -> Self {
+        let mut running = running();
+        running.push(RunningCommand { pid, grace });
+        // Started just as hookmaster was stopped, after the signal was
+        // passed on to the others
+        #[cfg(unix)]
+        unix::forward_late(pid);
+        Self { pid }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        running().retain(|command| command.pid != self.pid);
+    }
+}
+
+/// Catch `SIGTERM`, `SIGHUP` and `SIGINT` for the rest of the process. On
+/// one, the running commands get it too. Without a controlling terminal
+/// each runs in a process group of its own, which the processes they start
+/// get it along with; with one they share hookmaster's, and `SIGINT` isn't
+/// passed on, as Ctrl-C reaches them already. Commands still running after
+/// their grace period are killed.
+/// hookmaster itself carries on, so that what it set up for the hook, such
+/// as stashed changes, is put back; it starts no more commands, and
+/// [`exit_if_stopped`] then exits as the signal would have. On Windows,
+/// this does nothing.
+pub fn forward_signals() {
+    #[cfg(unix)]
+    unix::install();
+}
+
+/// Whether hookmaster got one of the signals [`forward_signals`] catches
+pub fn stopped() -> bool {
+    #[cfg(unix)]
+    let stopped = unix::received().is_some();
+    #[cfg(not(unix))]
+    let stopped = false;
+    stopped
+}
+
+/// Exit with 128 plus the signal number if hookmaster got one of the
+/// signals [`forward_signals`] catches
+pub fn exit_if_stopped() {
+    #[cfg(unix)]
+    if let Some(signal) = unix::received() {
+        std::process::exit(128 + signal);
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use crate::process;
+    use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+    use std::thread;
+    use std::time::Instant;
+
+    /// The signal hookmaster got, or 0 until it gets one
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    /// Whether the signal was passed on to the commands running then
+    static FORWARDED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        RECEIVED.store(signal, Ordering::SeqCst);
+    }
+
+    pub(super) fn install() {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in [libc::SIGTERM, libc::SIGHUP, libc::SIGINT] {
+            // SAFETY: the handler only stores to an atomic, which is
+            // async-signal-safe
+            unsafe {
+                libc::signal(signal, handler);
+            }
+        }
+        thread::spawn(watch);
+    }
+
+    pub(super) fn received() -> Option<i32> {
+        match RECEIVED.load(Ordering::SeqCst) {
+            0 => None,
+            signal => Some(signal),
+        }
+    }
+
+    /// Wait for a signal, then stop the running commands
+    fn watch() {
+        let signal = loop {
+            match received() {
+                None => thread::sleep(POLL_INTERVAL),
+                Some(signal) => break signal,
+            }
+        };
+
+        let grace = {
+            let running = running();
+            if signal != libc::SIGINT || !process::has_terminal() {
+                for command in running.iter() {
+                    let _ = process::signal_group(command.pid, signal);
+                }
+            }
+            FORWARDED.store(true, Ordering::SeqCst);
+            running.iter().map(|c| c.grace).max().unwrap_or_default()
+        };
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline && !running().is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        for command in running().iter() {
+            let _ = process::signal_group(command.pid, libc::SIGKILL);
+        }
+    }
+
+    /// Pass the signal on to `pid` if it was already passed on to the
+    /// commands running when it came, with the lock on them held
+    pub(super) fn forward_late(pid: u32) {
+        if let (true, Some(signal)) = (FORWARDED.load(Ordering::SeqCst), received()) {
+            if signal != libc::SIGINT || !process::has_terminal() {
+                let _ = process::signal_group(pid, signal);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running() {
+        let first = Running::start(u32::MAX - 1, Duration::from_secs(1));
+        let second = Running::start(u32::MAX - 2, Duration::from_secs(2));
+        let pids = || -> Vec<u32> {
+            running()
+                .iter()
+                .map(|c| c.pid)
+                .filter(|pid| *pid > u32::MAX - 3)
+                .collect()
+        };
+        assert_eq!(pids(), [u32::MAX - 1, u32::MAX - 2]);
+        drop(first);
+        assert_eq!(pids(), [u32::MAX - 2]);
+        drop(second);
+        assert!(pids().is_empty());
+    }
+}
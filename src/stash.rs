@@ -0,0 +1,196 @@
+//! Setting unstaged changes aside while a hook runs, so that it checks the
+//! files as they will be committed
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Message of the stash entries hookmaster makes
+const STASH_MESSAGE: &str = "hookmaster: unstaged changes";
+
+/// Unstaged changes of tracked files, stashed until the guard is dropped.
+///
+/// `git stash --keep-index` leaves the staged content in the working tree.
+/// Dropping the guard writes the stashed working tree back and drops the
+/// entry: a `git stash pop` would conflict on files with both staged and
+/// unstaged changes. Changes the hook made to files in the meantime, such
+/// as by formatting, are discarded with a warning, as they were made to
+/// content the unstaged changes replace.
+#[derive(Debug)]
+pub struct StashGuard {
+    root: PathBuf,
+    /// Commit of the stash entry, if one was made
+    stash: Option<String>,
+}
+
+impl StashGuard {
+    /// Stash the unstaged changes of the repository at `root`, keeping the
+    /// index. Nothing is stashed when there are no unstaged changes or
+    /// `git stash` fails; untracked files are left in place.
+    pub fn stash_unstaged(root: &Path) -> Self {
+        let mut guard = Self {
+            root: root.to_path_buf(),
+            stash: None,
+        };
+        if guard
+            .git(&["diff", "--quiet"])
+            .is_some_and(|o| o.status.success())
+        {
+            return guard;
+        }
+
+        let before = guard.stash_head();
+        let stashed = guard.git(&[
+            "stash",
+            "push",
+            "--keep-index",
+            "--quiet",
+            "-m",
+            STASH_MESSAGE,
+        ]);
+        let after = guard.stash_head();
+        if !stashed.is_some_and(|o| o.status.success()) || after == before {
+            eprintln!("Warning: could not stash unstaged changes; the hook checks them too");
+            return guard;
+        }
+        guard.stash = after;
+        guard
+    }
+
+    /// Whether unstaged changes were stashed
+    #[cfg(test)]
+    pub fn is_stashed(&self) -> bool {
+        self.stash.is_some()
+    }
+
+    /// Commit of the newest stash entry
+    fn stash_head(&self) -> Option<String> {
+        let output = self.git(&["rev-parse", "-q", "--verify", "refs/stash"])?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn git(&self, args: &[&str]) -> Option<Output> {
+        Command::new("git")
+            .args(args)
+            .current_dir(&self.root)
+            .output()
+            .ok()
+    }
+
+    /// Write the stashed working tree back and drop the stash entry
+    fn restore(&self, stash: &str) -> Result<(), String> {
+        if !self
+            .git(&["diff", "--quiet"])
+            .is_some_and(|o| o.status.success())
+        {
+            eprintln!(
+                "Warning: discarding the changes the hook made to files, to restore the unstaged changes"
+            );
+        }
+        let source = format!("--source={stash}");
+        let restored = self
+            .git(&["restore", &source, "--worktree", "--", ":/"])
+            .ok_or("git could not be run")?;
+        if !restored.status.success() {
+            return Err(String::from_utf8_lossy(&restored.stderr).trim().to_string());
+        }
+        // Only the entry made here is dropped, should another have come on top
+        if self.stash_head().as_deref() == Some(stash) {
+            self.git(&["stash", "drop", "--quiet"]);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StashGuard {
+    fn drop(&mut self) {
+        if let Some(stash) = self.stash.take() {
+            if let Err(e) = self.restore(&stash) {
+                eprintln!(
+                    "Warning: failed to restore the unstaged changes: {e}\nThey are kept in stash entry {stash}; restore them with 'git stash apply {stash}'"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    fn repo() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        git(repo, &["init", "-q"]);
+        fs::write(repo.join("lib.txt"), "one\n").unwrap();
+        fs::write(repo.join("other.txt"), "x\n").unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-q", "-m", "Initial commit"]);
+        temp_dir
+    }
+
+    #[test]
+    fn test_stash_unstaged_keeps_index_and_restores() {
+        let temp_dir = repo();
+        let repo = temp_dir.path();
+        fs::write(repo.join("lib.txt"), "one\ntwo\n").unwrap();
+        git(repo, &["add", "lib.txt"]);
+        // Unstaged changes on top of staged ones in the same file
+        fs::write(repo.join("lib.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(repo.join("other.txt"), "y\n").unwrap();
+        fs::write(repo.join("new.txt"), "untracked\n").unwrap();
+
+        let guard = StashGuard::stash_unstaged(repo);
+        assert!(guard.is_stashed());
+        assert_eq!(
+            fs::read_to_string(repo.join("lib.txt")).unwrap(),
+            "one\ntwo\n"
+        );
+        assert_eq!(fs::read_to_string(repo.join("other.txt")).unwrap(), "x\n");
+        assert!(repo.join("new.txt").exists());
+        drop(guard);
+
+        assert_eq!(
+            fs::read_to_string(repo.join("lib.txt")).unwrap(),
+            "one\ntwo\nthree\n"
+        );
+        assert_eq!(fs::read_to_string(repo.join("other.txt")).unwrap(), "y\n");
+        assert_eq!(git(repo, &["diff", "--cached", "--name-only"]), "lib.txt\n");
+        assert_eq!(git(repo, &["stash", "list"]), "");
+    }
+
+    #[test]
+    fn test_nothing_to_stash() {
+        let temp_dir = repo();
+        let repo = temp_dir.path();
+        fs::write(repo.join("lib.txt"), "staged\n").unwrap();
+        git(repo, &["add", "lib.txt"]);
+
+        // An older stash entry is left alone
+        fs::write(repo.join("other.txt"), "older\n").unwrap();
+        git(repo, &["stash", "push", "-q", "--", "other.txt"]);
+        let guard = StashGuard::stash_unstaged(repo);
+        assert!(!guard.is_stashed());
+        drop(guard);
+        assert_eq!(git(repo, &["stash", "list"]).lines().count(), 1);
+        assert_eq!(
+            fs::read_to_string(repo.join("lib.txt")).unwrap(),
+            "staged\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! "Did you mean" suggestions for mistyped command and hook names
+
+/// Edit distance between `a` and `b`: the number of single character
+/// insertions, deletions and substitutions turning one into the other
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Most edits for `candidate` to count as a likely typo of `input`: one per
+/// three characters of the longer name, and at least one
+fn max_distance(input: &str, candidate: &str) -> usize {
+    let len = input.chars().count().max(candidate.chars().count());
+    (len / 3).max(1)
+}
+
+/// The candidate closest to `input`, if it is close enough to be a likely
+/// typo. Ties go to the earlier candidate; an exact match is not a suggestion.
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|candidate| (levenshtein(input, candidate), candidate))
+        .filter(|(distance, candidate)| {
+            *distance > 0 && *distance <= max_distance(input, candidate)
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("run", "run"), 0);
+        assert_eq!(levenshtein("runn", "run"), 1);
+        assert_eq!(levenshtein("pre-comit", "pre-commit"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "add"), 3);
+        assert_eq!(levenshtein("ädd", "add"), 1);
+    }
+
+    #[test]
+    fn test_closest() {
+        let commands = ["add", "init", "run", "list", "validate"];
+        assert_eq!(closest("runn", commands), Some("run"));
+        assert_eq!(closest("lst", commands), Some("list"));
+        assert_eq!(closest("valdiate", commands), Some("validate"));
+        assert_eq!(
+            closest("pre-comit", ["pre-push", "pre-commit"]),
+            Some("pre-commit")
+        );
+    }
+
+    #[test]
+    fn test_closest_ignores_unrelated_input() {
+        let commands = ["add", "init", "run", "list", "validate"];
+        assert_eq!(closest("run", commands), None);
+        assert_eq!(closest("xyz", commands), None);
+        assert_eq!(closest("deploy", commands), None);
+        assert_eq!(closest("lint", ["pre-commit", "pre-push"]), None);
+        assert_eq!(closest("", commands), None);
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::UserConfig;
+use crate::git_hooks::{self, is_hookmaster_script, GitHook};
+
+/// Git setting naming the directory copied into new repositories
+const TEMPLATE_DIR_KEY: &str = "init.templateDir";
+
+/// Git's template directory, which `git init` and `git clone` copy into each
+/// new repository, so hooks installed there reach every future clone
+#[derive(Debug, Clone)]
+pub struct GitTemplate {
+    /// Global git configuration file to use instead of the user's, for tests
+    global_config: Option<PathBuf>,
+    /// Template directory created when `init.templateDir` isn't set
+    default_dir: PathBuf,
+}
+
+/// What installing to the template directory did
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateInstall {
+    /// The template directory
+    pub dir: PathBuf,
+    /// `init.templateDir` was set to `dir`
+    pub configured: bool,
+    /// Names of the installed hooks
+    pub hooks: Vec<String>,
+}
+
+/// What removing hooks from the template directory did
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateRemoval {
+    /// The template directory, if one is configured
+    pub dir: Option<PathBuf>,
+    /// `init.templateDir` was unset because hookmaster had set it
+    pub unconfigured: bool,
+    /// Names of the removed hooks
+    pub hooks: Vec<String>,
+}
+
+/// State of hookmaster's scripts in the template directory
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateStatus {
+    /// The template directory has no hookmaster scripts
+    NotInstalled,
+    /// All hooks are installed and match this version
+    Current,
+    /// Hooks that are missing or differ from what this version installs
+    Outdated(Vec<String>),
+}
+
+impl GitTemplate {
+    /// The user's template directory: `init.templateDir`, or else
+    /// `git-template` next to the user configuration
+    pub fn new() -> Result<Self> {
+        let user_config = UserConfig::path()
+            .ok_or_else(|| anyhow!("Cannot find the user configuration directory"))?;
+        let config_dir = user_config.parent().unwrap_or(Path::new("."));
+        Ok(Self {
+            global_config: None,
+            default_dir: config_dir.join("git-template"),
+        })
+    }
+
+    /// Use `global_config` as git's global configuration file
+    #[cfg(test)]
+    fn with_global_config(global_config: &Path, default_dir: &Path) -> Self {
+        Self {
+            global_config: Some(global_config.to_path_buf()),
+            default_dir: default_dir.to_path_buf(),
+        }
+    }
+
+    /// A `git config --global` command
+    fn git_config(&self) -> Command {
+        let mut command = Command::new("git");
+        if let Some(file) = &self.global_config {
+            command.env("GIT_CONFIG_GLOBAL", file);
+        }
+        command.args(["config", "--global"]);
+        command
+    }
+
+    /// The configured template directory, with `~` expanded
+    pub fn configured_dir(&self) -> Result<Option<PathBuf>> {
+        let output = self
+            .git_config()
+            .args(["--type=path", "--get", TEMPLATE_DIR_KEY])
+            .output()
+            .with_context(|| "Failed to execute git command")?;
+        // Exit code 1 means the key isn't set
+        match output.status.code() {
+            Some(0) => {
+                let dir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                Ok(Some(PathBuf::from(dir)))
+            }
+            Some(1) => Ok(None),
+            _ => Err(anyhow!(
+                "Git command failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )),
+        }
+    }
+
+    /// Run `git config --global` with `args`, failing if git does
+    fn set_config(&self, args: &[&str]) -> Result<()> {
+        let output = self
+            .git_config()
+            .args(args)
+            .output()
+            .with_context(|| "Failed to execute git command")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Git command failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Install the hook scripts into the template directory, configuring the
+    /// default one if `init.templateDir` isn't set
+    pub fn install(&self) -> Result<TemplateInstall> {
+        let (dir, configured) = match self.configured_dir()? {
+            Some(dir) => (dir, false),
+            None => (self.default_dir.clone(), true),
+        };
+
+        let hooks_dir = dir.join("hooks");
+        fs::create_dir_all(&hooks_dir).with_context(|| {
+            format!("Failed to create hooks directory: {}", hooks_dir.display())
+        })?;
+        let mut hooks = Vec::new();
+        for hook in GitHook::standard_hooks() {
+            let hook_file = hooks_dir.join(hook.to_filename());
+            git_hooks::write_hook_file(&hook_file, &hook.generate_script_content())?;
+            hooks.push(hook.to_filename());
+        }
+
+        if configured {
+            self.set_config(&[TEMPLATE_DIR_KEY, &dir.to_string_lossy()])?;
+        }
+
+        Ok(TemplateInstall {
+            dir,
+            configured,
+            hooks,
+        })
+    }
+
+    /// Remove hookmaster's scripts from the template directory, and unset
+    /// `init.templateDir` if it points to the directory `install` created
+    pub fn uninstall(&self) -> Result<TemplateRemoval> {
+        let Some(dir) = self.configured_dir()? else {
+            return Ok(TemplateRemoval {
+                dir: None,
+                unconfigured: false,
+                hooks: Vec::new(),
+            });
+        };
+
+        let mut hooks = Vec::new();
+        for hook in GitHook::standard_hooks() {
+            let hook_file = dir.join("hooks").join(hook.to_filename());
+            if is_hookmaster_script(&hook_file) {
+                fs::remove_file(&hook_file).with_context(|| {
+                    format!("Failed to remove hook file: {}", hook_file.display())
+                })?;
+                hooks.push(hook.to_filename());
+            }
+        }
+
+        let unconfigured = dir == self.default_dir;
+        if unconfigured {
+            self.set_config(&["--unset", TEMPLATE_DIR_KEY])?;
+            // Leave the directory if something else was put there
+            let _ = fs::remove_dir(dir.join("hooks"));
+            let _ = fs::remove_dir(&dir);
+        }
+
+        Ok(TemplateRemoval {
+            dir: Some(dir),
+            unconfigured,
+            hooks,
+        })
+    }
+
+    /// Compare hookmaster's scripts in the template directory with what this
+    /// version installs
+    pub fn check(&self) -> Result<TemplateStatus> {
+        let Some(dir) = self.configured_dir()? else {
+            return Ok(TemplateStatus::NotInstalled);
+        };
+        let hooks_dir = dir.join("hooks");
+        let installed = fs::read_dir(&hooks_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .any(|entry| is_hookmaster_script(&entry.path()))
+            })
+            .unwrap_or(false);
+        if !installed {
+            return Ok(TemplateStatus::NotInstalled);
+        }
+
+        let mut problems = Vec::new();
+        for hook in GitHook::standard_hooks() {
+            let hook_file = hooks_dir.join(hook.to_filename());
+            match fs::read_to_string(&hook_file) {
+                Ok(content) if content == hook.generate_script_content() => {}
+                Ok(_) => problems.push(format!("{} is out of date", hook_file.display())),
+                Err(_) => problems.push(format!("{} is missing", hook_file.display())),
+            }
+        }
+        if problems.is_empty() {
+            Ok(TemplateStatus::Current)
+        } else {
+            Ok(TemplateStatus::Outdated(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Template in a temporary directory, with its own global git configuration
+    fn template(temp_dir: &TempDir) -> GitTemplate {
+        let global_config = temp_dir.path().join("gitconfig");
+        fs::write(&global_config, "").unwrap();
+        GitTemplate::with_global_config(&global_config, &temp_dir.path().join("git-template"))
+    }
+
+    #[test]
+    fn test_install_configures_template_for_new_repositories() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = template(&temp_dir);
+        assert_eq!(template.check().unwrap(), TemplateStatus::NotInstalled);
+
+        let install = template.install().unwrap();
+        assert!(install.configured);
+        assert_eq!(install.dir, temp_dir.path().join("git-template"));
+        assert_eq!(template.configured_dir().unwrap(), Some(install.dir));
+        assert_eq!(template.check().unwrap(), TemplateStatus::Current);
+
+        let repo = temp_dir.path().join("repo");
+        let status = Command::new("git")
+            .env("GIT_CONFIG_GLOBAL", temp_dir.path().join("gitconfig"))
+            .args(["init", "-q"])
+            .arg(&repo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        for hook in GitHook::standard_hooks() {
+            let content = fs::read_to_string(repo.join(".git/hooks").join(hook.to_filename()));
+            assert_eq!(content.unwrap(), hook.generate_script_content());
+        }
+
+        // Outdated scripts are reported
+        let pre_commit = temp_dir.path().join("git-template/hooks/pre-commit");
+        fs::write(&pre_commit, "#!/bin/sh\nhookmaster run pre-commit\n").unwrap();
+        assert_eq!(
+            template.check().unwrap(),
+            TemplateStatus::Outdated(vec![format!("{} is out of date", pre_commit.display())])
+        );
+
+        let removal = template.uninstall().unwrap();
+        assert!(removal.unconfigured);
+        assert_eq!(removal.hooks.len(), GitHook::standard_hooks().len());
+        assert_eq!(template.configured_dir().unwrap(), None);
+        assert!(!temp_dir.path().join("git-template").exists());
+    }
+
+    #[test]
+    fn test_existing_template_dir_is_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = template(&temp_dir);
+        let own_dir = temp_dir.path().join("my-template");
+        fs::create_dir_all(own_dir.join("hooks")).unwrap();
+        fs::write(own_dir.join("hooks/post-checkout"), "#!/bin/sh\nmake\n").unwrap();
+        template
+            .set_config(&[TEMPLATE_DIR_KEY, &own_dir.to_string_lossy()])
+            .unwrap();
+
+        let install = template.install().unwrap();
+        assert!(!install.configured);
+        assert_eq!(install.dir, own_dir);
+
+        let removal = template.uninstall().unwrap();
+        assert!(!removal.unconfigured);
+        assert_eq!(template.configured_dir().unwrap(), Some(own_dir.clone()));
+        assert!(own_dir.join("hooks/post-checkout").exists());
+        assert!(!own_dir.join("hooks/pre-commit").exists());
+    }
+}
@@ -0,0 +1,386 @@
+//! The `.git/hookmaster-timings.jsonl` log of how long hook commands took,
+//! and the statistics `hookmaster stats` prints from it
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value as JsonValue};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::formatter::CommandStatus;
+
+/// File name of the log in a repository's `.git` directory
+pub const TIMINGS_FILE: &str = "hookmaster-timings.jsonl";
+
+/// Size in kilobytes the log grows to before it is rotated, unless
+/// `timings_max_kb` under `[run]` says otherwise
+pub const DEFAULT_MAX_KB: u64 = 1024;
+
+/// How long one command of a hook took
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timing {
+    /// When the hook ran, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Name of the hook, e.g. `pre-commit`
+    pub hook: String,
+    /// Name of the command
+    pub command: String,
+    /// How long the command ran, in milliseconds
+    pub duration_ms: u64,
+    /// How the command finished: `passed`, `warning` or `failed`
+    pub status: String,
+}
+
+impl Timing {
+    /// A timing of `command` in `hook`, which finished with `status`
+    pub fn new(
+        timestamp: u64,
+        hook: &str,
+        command: &str,
+        duration_ms: u64,
+        status: CommandStatus,
+    ) -> Self {
+        Self {
+            timestamp,
+            hook: hook.to_string(),
+            command: command.to_string(),
+            duration_ms,
+            status: status.as_str().to_string(),
+        }
+    }
+
+    fn to_json_line(&self) -> String {
+        json!({
+            "timestamp": self.timestamp,
+            "hook": self.hook,
+            "command": self.command,
+            "duration_ms": self.duration_ms,
+            "status": self.status,
+        })
+        .to_string()
+    }
+
+    fn from_json_line(line: &str) -> Option<Self> {
+        let json: JsonValue = serde_json::from_str(line).ok()?;
+        Some(Self {
+            timestamp: json["timestamp"].as_u64()?,
+            hook: json["hook"].as_str()?.to_string(),
+            command: json["command"].as_str()?.to_string(),
+            duration_ms: json["duration_ms"].as_u64()?,
+            status: json["status"].as_str()?.to_string(),
+        })
+    }
+}
+
+/// Path of the log in the `.git` directory `git_dir`
+pub fn path(git_dir: &Path) -> PathBuf {
+    git_dir.join(TIMINGS_FILE)
+}
+
+/// Path the log is moved to when it is rotated
+fn rotated_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(format!("{TIMINGS_FILE}.1"))
+}
+
+/// Append `timings` to the log in `git_dir`. A log that has grown to
+/// `max_bytes` is first moved aside, replacing the one moved aside before,
+/// so the two together never take much more than twice that.
+pub fn append(git_dir: &Path, timings: &[Timing], max_bytes: u64) -> Result<()> {
+    if timings.is_empty() {
+        return Ok(());
+    }
+    let path = path(git_dir);
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.len() >= max_bytes) {
+        fs::rename(&path, rotated_path(git_dir))
+            .with_context(|| format!("Failed to rotate {}", path.display()))?;
+    }
+
+    let lines: String = timings
+        .iter()
+        .map(|timing| timing.to_json_line() + "\n")
+        .collect();
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The timings logged in `git_dir`, oldest first, including those of the
+/// log moved aside by the last rotation. Lines that aren't timings, such as
+/// one cut short by a full disk, are skipped.
+pub fn load(git_dir: &Path) -> Result<Vec<Timing>> {
+    let mut timings = Vec::new();
+    for path in [rotated_path(git_dir), path(git_dir)] {
+        if !path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        timings.extend(content.lines().filter_map(Timing::from_json_line));
+    }
+    Ok(timings)
+}
+
+/// Statistics of one command of a hook
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandStats {
+    /// Name of the hook
+    pub hook: String,
+    /// Name of the command
+    pub command: String,
+    /// How many times the command ran
+    pub runs: usize,
+    /// How many of those it failed
+    pub failures: usize,
+    /// Median duration in milliseconds
+    pub p50_ms: u64,
+    /// 95th percentile duration in milliseconds
+    pub p95_ms: u64,
+    /// Time spent in the command altogether, in milliseconds
+    pub total_ms: u64,
+}
+
+impl CommandStats {
+    /// Share of the runs that failed, from 0 to 1
+    pub fn failure_rate(&self) -> f64 {
+        self.failures as f64 / self.runs.max(1) as f64
+    }
+}
+
+/// Timings grouped by hook and command name
+type ByCommand<'a> = BTreeMap<(&'a str, &'a str), Vec<&'a Timing>>;
+
+/// Statistics of each command in `timings`, only of `hook` if given and of
+/// timings from `since` on, with the commands that took the most time first
+pub fn aggregate(timings: &[Timing], hook: Option<&str>, since: Option<u64>) -> Vec<CommandStats> {
+    let mut by_command: ByCommand = BTreeMap::new();
+    for timing in timings {
+        if hook.is_some_and(|hook| timing.hook != hook)
+            || since.is_some_and(|since| timing.timestamp < since)
+        {
+            continue;
+        }
+        by_command
+            .entry((&timing.hook, &timing.command))
+            .or_default()
+            .push(timing);
+    }
+
+    let mut stats: Vec<CommandStats> = by_command
+        .into_iter()
+        .map(|((hook, command), timings)| {
+            let mut durations: Vec<u64> = timings.iter().map(|t| t.duration_ms).collect();
+            durations.sort_unstable();
+            CommandStats {
+                hook: hook.to_string(),
+                command: command.to_string(),
+                runs: timings.len(),
+                failures: timings
+                    .iter()
+                    .filter(|t| t.status == CommandStatus::Failed.as_str())
+                    .count(),
+                p50_ms: percentile(&durations, 50),
+                p95_ms: percentile(&durations, 95),
+                total_ms: durations.iter().sum(),
+            }
+        })
+        .collect();
+    // Stable, so commands that took as long stay ordered by hook and name
+    stats.sort_by_key(|s| Reverse(s.total_ms));
+    stats
+}
+
+/// The `p`th percentile of the non-empty `sorted` by the nearest-rank method
+fn percentile(sorted: &[u64], p: usize) -> u64 {
+    let rank = ((p * sorted.len() + 99) / 100).max(1);
+    sorted[rank - 1]
+}
+
+/// `stats` as a table with a header line
+pub fn table(stats: &[CommandStats]) -> Vec<String> {
+    let hook_width = stats.iter().map(|s| s.hook.len()).fold(4, usize::max);
+    let command_width = stats.iter().map(|s| s.command.len()).fold(7, usize::max);
+
+    let mut lines = vec![format!(
+        "{:<hook_width$}  {:<command_width$}  {:>6}  {:>7}  {:>8}  {:>8}  {:>8}",
+        "HOOK", "COMMAND", "RUNS", "FAILED", "P50", "P95", "TOTAL"
+    )];
+    for s in stats {
+        lines.push(format!(
+            "{:<hook_width$}  {:<command_width$}  {:>6}  {:>6.1}%  {:>8}  {:>8}  {:>8}",
+            s.hook,
+            s.command,
+            s.runs,
+            s.failure_rate() * 100.0,
+            format_duration(s.p50_ms),
+            format_duration(s.p95_ms),
+            format_duration(s.total_ms)
+        ));
+    }
+    lines
+}
+
+/// `stats` as a JSON array
+pub fn to_json(stats: &[CommandStats]) -> String {
+    let stats: Vec<JsonValue> = stats
+        .iter()
+        .map(|s| {
+            json!({
+                "hook": s.hook,
+                "command": s.command,
+                "runs": s.runs,
+                "failures": s.failures,
+                "failure_rate": s.failure_rate(),
+                "p50_ms": s.p50_ms,
+                "p95_ms": s.p95_ms,
+                "total_ms": s.total_ms,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&stats).unwrap_or_default()
+}
+
+/// A duration in milliseconds for people, such as `450ms`, `2.3s` or `4m 05s`
+pub fn format_duration(ms: u64) -> String {
+    match ms {
+        0..=999 => format!("{ms}ms"),
+        1_000..=59_999 => format!("{:.1}s", ms as f64 / 1000.0),
+        _ => {
+            let seconds = ms / 1000;
+            format!("{}m {:02}s", seconds / 60, seconds % 60)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+    use tempfile::TempDir;
+
+    fn timing(timestamp: u64, hook: &str, command: &str, ms: u64, status: CommandStatus) -> Timing {
+        Timing::new(timestamp, hook, command, ms, status)
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut timings = Vec::new();
+        for ms in 1..=20 {
+            let status = if ms % 5 == 0 {
+                CommandStatus::Failed
+            } else {
+                CommandStatus::Passed
+            };
+            timings.push(timing(1_000 + ms, "pre-commit", "clippy", ms * 100, status));
+        }
+        timings.push(timing(900, "pre-commit", "fmt", 50, CommandStatus::Passed));
+        timings.push(timing(
+            1_000,
+            "pre-commit",
+            "fmt",
+            70,
+            CommandStatus::Warning,
+        ));
+        timings.push(timing(
+            1_000,
+            "pre-push",
+            "test",
+            60_000,
+            CommandStatus::Passed,
+        ));
+
+        let stats = aggregate(&timings, None, None);
+        let names: Vec<String> = stats
+            .iter()
+            .map(|s| format!("{} {}", s.hook, s.command))
+            .collect();
+        assert_eq!(
+            names,
+            vec!["pre-push test", "pre-commit clippy", "pre-commit fmt"]
+        );
+        assert_eq!(
+            stats[1],
+            CommandStats {
+                hook: "pre-commit".to_string(),
+                command: "clippy".to_string(),
+                runs: 20,
+                failures: 4,
+                p50_ms: 1_000,
+                p95_ms: 1_900,
+                total_ms: 21_000,
+            }
+        );
+        assert_eq!(stats[1].failure_rate(), 0.2);
+        // A warning isn't a failure
+        assert_eq!(stats[2].failures, 0);
+
+        let stats = aggregate(&timings, Some("pre-commit"), Some(1_000));
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[1].runs, stats[1].total_ms), (1, 70));
+        assert!(aggregate(&timings, Some("commit-msg"), None).is_empty());
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[1, 2], 50), 1);
+        assert_eq!(percentile(&[1, 2], 95), 2);
+        let hundred: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&hundred, 50), 50);
+        assert_eq!(percentile(&hundred, 95), 95);
+    }
+
+    #[test]
+    fn test_table_and_json() {
+        let stats = aggregate(
+            &[
+                timing(1, "pre-commit", "clippy", 2_340, CommandStatus::Failed),
+                timing(2, "pre-commit", "clippy", 250_000, CommandStatus::Passed),
+            ],
+            None,
+            None,
+        );
+        assert_eq!(
+            table(&stats),
+            vec![
+                "HOOK        COMMAND    RUNS   FAILED       P50       P95     TOTAL",
+                "pre-commit  clippy        2    50.0%      2.3s    4m 10s    4m 12s",
+            ]
+        );
+
+        let json: JsonValue = serde_json::from_str(&to_json(&stats)).unwrap();
+        assert_eq!(json[0]["command"], "clippy");
+        assert_eq!(json[0]["failure_rate"], 0.5);
+        assert_eq!(json[0]["total_ms"], 252_340);
+    }
+
+    #[test]
+    fn test_append_rotates_and_load_reads_both_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let git_dir = temp_dir.path();
+        let first = timing(1, "pre-commit", "fmt", 10, CommandStatus::Passed);
+        let second = timing(2, "pre-commit", "fmt", 20, CommandStatus::Failed);
+        let third = timing(3, "pre-commit", "fmt", 30, CommandStatus::Passed);
+
+        append(git_dir, slice::from_ref(&first), 1).unwrap();
+        append(git_dir, slice::from_ref(&second), 1).unwrap();
+        assert_eq!(load(git_dir).unwrap(), vec![first, second.clone()]);
+
+        // Each rotation drops the log moved aside before
+        fs::write(
+            path(git_dir),
+            fs::read_to_string(path(git_dir)).unwrap() + "{\"cut\": \n",
+        )
+        .unwrap();
+        append(git_dir, slice::from_ref(&third), 1).unwrap();
+        assert_eq!(load(git_dir).unwrap(), vec![second, third]);
+
+        let empty = TempDir::new().unwrap();
+        assert!(load(empty.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Context;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::ProjectRunSettings;
+
+/// File in the repository root listing more directories for `PATH`, one per line
+pub const PATH_FILE: &str = ".hookmaster-path";
+
+/// A tool directory added to `PATH` by default
+struct DefaultDir {
+    /// File showing that the project uses the directory
+    marker: &'static str,
+    dir: &'static str,
+}
+
+const DEFAULT_DIRS: [DefaultDir; 3] = [
+    DefaultDir {
+        marker: "package.json",
+        dir: "node_modules/.bin",
+    },
+    DefaultDir {
+        marker: ".venv/pyvenv.cfg",
+        dir: ".venv/bin",
+    },
+    DefaultDir {
+        marker: "venv/pyvenv.cfg",
+        dir: "venv/bin",
+    },
+];
+
+/// Tool directories of the repository at `root`, relative to it: the
+/// configured `extra_paths`, or the default directories the project uses
+pub fn configured_dirs(settings: &ProjectRunSettings, root: &Path) -> Vec<String> {
+    match &settings.extra_paths {
+        Some(dirs) => dirs.clone(),
+        None => DEFAULT_DIRS
+            .iter()
+            .filter(|default| root.join(default.marker).is_file())
+            .map(|default| default.dir.to_string())
+            .collect(),
+    }
+}
+
+/// Directories to prepend to `PATH` for hook commands in the repository at
+/// `root`, from [`configured_dirs`]. Directories that don't exist are skipped.
+pub fn extra_dirs(settings: &ProjectRunSettings, root: &Path) -> Vec<PathBuf> {
+    configured_dirs(settings, root)
+        .iter()
+        .map(|dir| for_platform(root.join(dir), cfg!(windows)))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// Directories listed in the `.hookmaster-path` file of the repository at
+/// `root`, relative ones resolved against it. Blank lines and `#` comments
+/// are ignored; directories that don't exist are skipped with a warning.
+pub fn path_file_dirs(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let path_file = root.join(PATH_FILE);
+    let content = match fs::read_to_string(&path_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path_file.display())),
+    };
+
+    let mut dirs = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let dir = root.join(line);
+        if dir.is_dir() {
+            dirs.push(dir);
+        } else {
+            eprintln!("Warning: directory '{line}' listed in {PATH_FILE} does not exist");
+        }
+    }
+    Ok(dirs)
+}
+
+/// Python virtual environments keep their executables in `Scripts` rather
+/// than `bin` on Windows
+fn for_platform(dir: PathBuf, windows: bool) -> PathBuf {
+    let is_venv_bin = dir.file_name() == Some(OsStr::new("bin"))
+        && dir
+            .parent()
+            .is_some_and(|venv| venv.join("pyvenv.cfg").is_file());
+    if windows && is_venv_bin {
+        dir.with_file_name("Scripts")
+    } else {
+        dir
+    }
+}
+
+/// `PATH` with `dirs` in front of the entries of `path`, joined with the
+/// platform's separator
+pub fn prepend(dirs: &[PathBuf], path: Option<&OsStr>) -> Result<OsString, env::JoinPathsError> {
+    let existing: Vec<PathBuf> = path
+        .map(|p| env::split_paths(p).collect())
+        .unwrap_or_default();
+    env::join_paths(dirs.iter().chain(&existing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_default_dirs_follow_marker_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let settings = ProjectRunSettings::default();
+        fs::create_dir_all(root.join("node_modules/.bin")).unwrap();
+        fs::create_dir_all(root.join(".venv/bin")).unwrap();
+
+        // Directories are only added for projects that use them
+        assert!(extra_dirs(&settings, root).is_empty());
+
+        fs::write(root.join("package.json"), "{}").unwrap();
+        assert_eq!(
+            extra_dirs(&settings, root),
+            vec![root.join("node_modules/.bin")]
+        );
+
+        fs::write(root.join(".venv/pyvenv.cfg"), "").unwrap();
+        if cfg!(windows) {
+            fs::create_dir_all(root.join(".venv/Scripts")).unwrap();
+        }
+        assert_eq!(extra_dirs(&settings, root).len(), 2);
+    }
+
+    #[test]
+    fn test_configured_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("tools/bin")).unwrap();
+        fs::write(root.join("package.json"), "{}").unwrap();
+        fs::create_dir_all(root.join("node_modules/.bin")).unwrap();
+
+        // Configured directories replace the defaults; missing ones are skipped
+        let settings = ProjectRunSettings {
+            extra_paths: Some(vec!["tools/bin".to_string(), "missing".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(extra_dirs(&settings, root), vec![root.join("tools/bin")]);
+
+        let settings = ProjectRunSettings {
+            extra_paths: Some(Vec::new()),
+            ..Default::default()
+        };
+        assert!(extra_dirs(&settings, root).is_empty());
+    }
+
+    #[test]
+    fn test_venv_scripts_on_windows() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join(".venv")).unwrap();
+        fs::write(root.join(".venv/pyvenv.cfg"), "").unwrap();
+
+        let bin = root.join(".venv/bin");
+        assert_eq!(for_platform(bin.clone(), true), root.join(".venv/Scripts"));
+        assert_eq!(for_platform(bin.clone(), false), bin);
+        assert_eq!(
+            for_platform(root.join("tools/bin"), true),
+            root.join("tools/bin")
+        );
+    }
+
+    #[test]
+    fn test_prepend() {
+        let dirs = vec![PathBuf::from("/repo/node_modules/.bin")];
+        let path = env::join_paths(["/usr/bin", "/bin"]).unwrap();
+        let joined = prepend(&dirs, Some(&path)).unwrap();
+        assert_eq!(
+            env::split_paths(&joined).collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/repo/node_modules/.bin"),
+                PathBuf::from("/usr/bin"),
+                PathBuf::from("/bin"),
+            ]
+        );
+        assert_eq!(prepend(&dirs, None).unwrap(), dirs[0].as_os_str());
+    }
+
+    #[test]
+    fn test_path_file_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        assert!(path_file_dirs(root).unwrap().is_empty());
+
+        let absolute = TempDir::new().unwrap();
+        fs::create_dir_all(root.join("tools/bin")).unwrap();
+        fs::write(
+            root.join(PATH_FILE),
+            format!(
+                "# Tools for the hooks\ntools/bin\n\n  missing/bin  \n{}\n",
+                absolute.path().display()
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            path_file_dirs(root).unwrap(),
+            vec![root.join("tools/bin"), absolute.path().to_path_buf()]
+        );
+    }
+}
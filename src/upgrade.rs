@@ -0,0 +1,294 @@
+use anyhow::Result;
+
+use crate::config::{GitHooksConfig, HookSpec};
+
+/// Lines of unchanged context around each change in a diff
+const DIFF_CONTEXT: usize = 3;
+
+/// A top-level hook moved to the end: the comments above it and its table
+type MovedHook<'a> = (Vec<&'a str>, Vec<String>);
+
+/// A line of a diff: its prefix, text, and the line numbers before it in
+/// the old and new file
+type DiffLine<'a> = (char, &'a str, usize, usize);
+
+/// Check if `content` already defines hooks as `[hooks.<name>]` tables
+pub fn is_structured(content: &str) -> bool {
+    content.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with('[') && line.ends_with(']') && line[1..].trim().starts_with("hooks.")
+    })
+}
+
+/// Rewrite the hooks `content` defines as top-level keys, such as
+/// `pre-commit = "cargo fmt --check"` or `pre-push = ["make lint", "make test"]`,
+/// as `[hooks.<name>]` tables of named commands at the end of the file. A
+/// comment block right above such a hook moves with it; the rest of the
+/// file is kept as it is.
+pub fn upgrade(content: &str) -> Result<String> {
+    GitHooksConfig::parse_toml(content)?;
+
+    let mut kept: Vec<&str> = Vec::new();
+    let mut comments: Vec<&str> = Vec::new();
+    let mut moved: Vec<MovedHook> = Vec::new();
+    let mut in_root = true;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if !in_root {
+            kept.push(line);
+        } else if trimmed.starts_with('#') {
+            comments.push(line);
+        } else if trimmed.is_empty() {
+            kept.append(&mut comments);
+            // Removed hooks don't leave runs of blank lines behind
+            if kept.last().is_some_and(|last| !last.trim().is_empty()) {
+                kept.push(line);
+            }
+        } else if trimmed.starts_with('[') {
+            in_root = false;
+            kept.append(&mut comments);
+            kept.push(line);
+        } else {
+            // Keys such as `extends` that aren't hooks stay where they are
+            let mut config = GitHooksConfig::parse_toml(trimmed)?;
+            let Some((key, spec)) = config.hooks.drain().next() else {
+                kept.append(&mut comments);
+                kept.push(line);
+                continue;
+            };
+            moved.push((
+                std::mem::take(&mut comments),
+                named_commands(spec).table_lines(&key),
+            ));
+        }
+    }
+    kept.append(&mut comments);
+
+    while kept.last().is_some_and(|line| line.trim().is_empty()) {
+        kept.pop();
+    }
+    let mut lines: Vec<String> = kept.into_iter().map(str::to_string).collect();
+    for (comments, table) in moved {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(comments.into_iter().map(str::to_string));
+        lines.extend(table);
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+/// `spec` with its commands renamed after what they run, as top-level hooks
+/// name them after the hook or the whole command text, which can't be a key
+fn named_commands(mut spec: HookSpec) -> HookSpec {
+    let mut names: Vec<String> = Vec::new();
+    for entry in &mut spec.commands {
+        let base = command_name(&entry.command);
+        let mut name = base.clone();
+        let mut n = 1;
+        while names.contains(&name) {
+            n += 1;
+            name = format!("{base}-{n}");
+        }
+        names.push(name.clone());
+        entry.name = name;
+    }
+    spec
+}
+
+/// A key naming `command`: its program and first argument, such as
+/// `cargo-fmt` for `cargo fmt --check`
+fn command_name(command: &str) -> String {
+    let words: Vec<String> = command
+        .split_whitespace()
+        .filter(|word| !word.starts_with('-') && !word.contains('='))
+        .take(2)
+        .map(|word| {
+            let word = word.rsplit('/').next().unwrap_or(word);
+            let word: String = word
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                    _ => '-',
+                })
+                .collect();
+            word.trim_matches('-').to_string()
+        })
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        "command".to_string()
+    } else {
+        words.join("-")
+    }
+}
+
+/// The changes from `old` to `new` as the hunks of a unified diff
+pub fn diff(old: &str, new: &str) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence lengths of the suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops: Vec<DiffLine> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            ops.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut index = 0;
+    while let Some(first) = (index..ops.len()).find(|&k| ops[k].0 != ' ') {
+        let start = first.saturating_sub(DIFF_CONTEXT);
+        // Extend the hunk while the next change is within reach of its context
+        let mut end = first;
+        while let Some(next) = (end + 1..ops.len()).find(|&k| ops[k].0 != ' ') {
+            if next - end > 2 * DIFF_CONTEXT {
+                break;
+            }
+            end = next;
+        }
+        let end = (end + DIFF_CONTEXT + 1).min(ops.len());
+
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|op| op.0 != '+').count();
+        let new_count = hunk.iter().filter(|op| op.0 != '-').count();
+        lines.push(format!(
+            "@@ -{},{old_count} +{},{new_count} @@",
+            hunk[0].2 + 1,
+            hunk[0].3 + 1
+        ));
+        lines.extend(
+            hunk.iter()
+                .map(|(prefix, line, _, _)| format!("{prefix}{line}")),
+        );
+        index = end;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name, input and expected output of an upgrade fixture
+    type Fixture = (&'static str, &'static str, &'static str);
+
+    /// The fixture pairs in tests/fixtures/upgrade
+    const FIXTURES: [Fixture; 4] = [
+        (
+            "flat",
+            include_str!("../tests/fixtures/upgrade/flat.toml"),
+            include_str!("../tests/fixtures/upgrade/flat.expected.toml"),
+        ),
+        (
+            "comments",
+            include_str!("../tests/fixtures/upgrade/comments.toml"),
+            include_str!("../tests/fixtures/upgrade/comments.expected.toml"),
+        ),
+        (
+            "sections",
+            include_str!("../tests/fixtures/upgrade/sections.toml"),
+            include_str!("../tests/fixtures/upgrade/sections.expected.toml"),
+        ),
+        (
+            "mixed",
+            include_str!("../tests/fixtures/upgrade/mixed.toml"),
+            include_str!("../tests/fixtures/upgrade/mixed.expected.toml"),
+        ),
+    ];
+
+    #[test]
+    fn test_upgrade_fixtures() {
+        for (name, input, expected) in FIXTURES {
+            let upgraded = upgrade(input).unwrap();
+            assert_eq!(upgraded, expected, "fixture {name}");
+            // Upgrading again changes nothing
+            assert_eq!(upgrade(&upgraded).unwrap(), upgraded, "fixture {name}");
+
+            // The commands stay the same
+            let before = GitHooksConfig::parse_toml(input).unwrap();
+            let after = GitHooksConfig::parse_toml(&upgraded).unwrap();
+            for (hook, spec) in &before.hooks {
+                let commands = |spec: &HookSpec| -> Vec<String> {
+                    spec.commands.iter().map(|e| e.command.clone()).collect()
+                };
+                assert_eq!(
+                    commands(&after.hooks[hook]),
+                    commands(spec),
+                    "{name}: {hook}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_structured() {
+        assert!(!is_structured(
+            "pre-commit = \"make\"\n[settings]\nshell = \"bash\"\n"
+        ));
+        assert!(is_structured(
+            "[hooks.pre-commit]\nlint = { command = \"make\" }\n"
+        ));
+    }
+
+    #[test]
+    fn test_command_name() {
+        assert_eq!(command_name("cargo fmt --check"), "cargo-fmt");
+        assert_eq!(command_name("./scripts/check.sh --all"), "check-sh");
+        assert_eq!(command_name("RUST_LOG=debug cargo test"), "cargo-test");
+        assert_eq!(command_name("@pre-commit"), "pre-commit");
+        assert_eq!(command_name("--"), "command");
+
+        let spec = named_commands(HookSpec::from_commands(vec![
+            "make lint".to_string(),
+            "make lint --fix".to_string(),
+        ]));
+        let names: Vec<&str> = spec.commands.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["make-lint", "make-lint-2"]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(
+            diff(old, new),
+            vec![
+                "@@ -1,5 +1,5 @@",
+                " a",
+                "-b",
+                "+B",
+                " c",
+                " d",
+                " e",
+                "@@ -8,3 +8,4 @@",
+                " h",
+                " i",
+                " j",
+                "+k",
+            ]
+        );
+        assert!(diff(old, old).is_empty());
+    }
+}
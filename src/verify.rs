@@ -0,0 +1,284 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::GitHooksConfig;
+use crate::git_hooks::{self, GitHook, HookOwner, ScriptFormat};
+use crate::hook_manager::HookStatus;
+use crate::manifest::{self, Manifest};
+
+/// Something wrong with the installed script of a hook the configuration needs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Discrepancy {
+    /// There is no script for the hook
+    NotInstalled,
+    /// The script was installed by another tool
+    Foreign(HookOwner),
+    /// hookmaster's script, as written by another version
+    OutOfDate,
+    /// hookmaster's script, edited after `add` wrote it
+    Edited,
+    /// The script isn't executable, so git skips it
+    NotExecutable,
+    /// The script runs the `<hook>.chained` script `add --chain` kept, which is missing
+    MissingChained,
+}
+
+impl Discrepancy {
+    /// What is wrong, to follow the hook name
+    pub fn describe(&self) -> String {
+        match self {
+            Discrepancy::NotInstalled => "not installed".to_string(),
+            Discrepancy::Foreign(owner) => format!("installed by {}", owner.name()),
+            Discrepancy::OutOfDate => "installed by another version of hookmaster".to_string(),
+            Discrepancy::Edited => "edited after hookmaster installed it".to_string(),
+            Discrepancy::NotExecutable => "not executable".to_string(),
+            Discrepancy::MissingChained => format!(
+                "the chained script ({}) is missing",
+                git_hooks::CHAINED_SUFFIX
+            ),
+        }
+    }
+
+    /// How to repair it in the repository at `repo`
+    pub fn suggestion(&self, repo: &Path) -> String {
+        let repo = repo.display();
+        match self {
+            Discrepancy::Foreign(_) => {
+                format!("run 'hookmaster add --chain {repo}' or 'hookmaster add --force {repo}'")
+            }
+            Discrepancy::MissingChained => {
+                format!("restore it, or run 'hookmaster add --force {repo}'")
+            }
+            _ => format!("run 'hookmaster add {repo}'"),
+        }
+    }
+
+    /// Whether `verify --fix` repairs it
+    pub fn is_fixable(&self) -> bool {
+        matches!(
+            self,
+            Discrepancy::NotInstalled | Discrepancy::OutOfDate | Discrepancy::NotExecutable
+        )
+    }
+}
+
+/// The git hooks `config` gives something to run, sorted by name
+pub fn required_hooks(config: &GitHooksConfig) -> Vec<GitHook> {
+    let mut names: Vec<&str> = config
+        .hooks
+        .keys()
+        .map(String::as_str)
+        .filter(|name| git_hooks::GIT_HOOK_NAMES.contains(name) && config.has_active_hook(name))
+        .collect();
+    names.sort();
+    names.into_iter().map(GitHook::from_filename).collect()
+}
+
+/// What is wrong with the script of `hook` in the repository at `repo`, if
+/// anything. The `manifest` tells scripts edited by hand from older ones.
+pub fn check_hook(repo: &Path, hook: &GitHook, manifest: Option<&Manifest>) -> Option<Discrepancy> {
+    let hooks_dir = repo.join(".git").join("hooks");
+    let name = hook.to_filename();
+    let status = HookStatus::check(&hooks_dir, &name);
+    if !status.installed {
+        return Some(Discrepancy::NotInstalled);
+    }
+    match status.owner {
+        Some(HookOwner::Hookmaster) | None => {}
+        Some(owner) => return Some(Discrepancy::Foreign(owner)),
+    }
+
+    let hook_file = hooks_dir.join(&name);
+    let content = fs::read_to_string(&hook_file).unwrap_or_default();
+    if status.stale {
+        let edited = manifest
+            .and_then(|manifest| manifest.hook(&name))
+            .is_some_and(|installed| manifest::content_hash(&content) != installed.hash);
+        return Some(if edited {
+            Discrepancy::Edited
+        } else {
+            Discrepancy::OutOfDate
+        });
+    }
+    if !is_executable(&hook_file) {
+        return Some(Discrepancy::NotExecutable);
+    }
+    let chains = ScriptFormat::ALL
+        .iter()
+        .any(|format| content == hook.chained_script_content(*format));
+    if chains && !git_hooks::chained_file(&hook_file).is_file() {
+        return Some(Discrepancy::MissingChained);
+    }
+    None
+}
+
+/// Run `hookmaster run <hook> --dry-run` in `repo` the way the hook script
+/// would, finding hookmaster on PATH, so that it fails if the script
+/// couldn't find hookmaster or the configuration doesn't load
+pub fn dry_run(repo: &Path, hook: &str) -> Result<()> {
+    let output = Command::new("hookmaster")
+        .args(["run", hook, "--dry-run"])
+        .current_dir(repo)
+        .output()
+        .with_context(|| "Failed to run hookmaster from PATH")?;
+    if output.status.success() {
+        return Ok(());
+    }
+    match error_message(&String::from_utf8_lossy(&output.stderr)) {
+        Some(message) => Err(anyhow!("{message}")),
+        None => Err(anyhow!("hookmaster exited with {}", output.status)),
+    }
+}
+
+/// The error hookmaster printed to `stderr`, on one line: the causes anyhow
+/// lists under "Caused by:" follow the message, and a backtrace is dropped
+fn error_message(stderr: &str) -> Option<String> {
+    let error = stderr
+        .split("\nStack backtrace:")
+        .next()
+        .unwrap_or_default();
+    let error = error.trim().strip_prefix("Error: ").unwrap_or(error.trim());
+    let parts: Vec<&str> = error
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && *line != "Caused by:")
+        .map(|line| match line.split_once(": ") {
+            Some((index, cause)) if index.parse::<usize>().is_ok() => cause,
+            _ => line,
+        })
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(": "))
+}
+
+/// Check if git can run the script at `path`
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Check if git can run the script at `path`
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// Make the script at `path` executable
+pub fn make_executable(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(path)?.permissions();
+        perms.set_mode(perms.mode() | 0o755);
+        fs::set_permissions(path, perms)
+            .with_context(|| format!("Failed to make hook executable: {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::InstalledHook;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_required_hooks() {
+        let config = GitHooksConfig::parse_toml(
+            "pre-push = \"cargo test\"\npre-commit = \"cargo fmt --check\"\ncommit-msg = \"\"\nlint = \"not a git hook\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            required_hooks(&config),
+            vec![GitHook::PreCommit, GitHook::PrePush]
+        );
+    }
+
+    #[test]
+    fn test_check_hook_discrepancies() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let hooks_dir = repo.join(".git/hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        let hook = GitHook::PreCommit;
+        let hook_file = hooks_dir.join("pre-commit");
+        let check = |manifest: Option<&Manifest>| check_hook(repo, &hook, manifest);
+
+        assert_eq!(check(None), Some(Discrepancy::NotInstalled));
+
+        fs::write(&hook_file, include_str!("../tests/fixtures/hooks/lefthook")).unwrap();
+        assert_eq!(check(None), Some(Discrepancy::Foreign(HookOwner::Lefthook)));
+
+        hook.install_to_repo(repo).unwrap();
+        assert_eq!(check(None), None);
+
+        // An older hookmaster script matches the hash recorded when it was written
+        let old_script = "#!/bin/sh\nhookmaster run pre-commit\n";
+        fs::write(&hook_file, old_script).unwrap();
+        let mut manifest = Manifest::new();
+        manifest.record(InstalledHook {
+            name: "pre-commit".to_string(),
+            hash: manifest::content_hash(old_script),
+            previous: None,
+            chained: false,
+        });
+        assert_eq!(check(Some(&manifest)), Some(Discrepancy::OutOfDate));
+        fs::write(&hook_file, format!("{old_script}echo extra\n")).unwrap();
+        assert_eq!(check(Some(&manifest)), Some(Discrepancy::Edited));
+        assert_eq!(check(None), Some(Discrepancy::OutOfDate));
+
+        hook.install_to_repo(repo).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&hook_file, fs::Permissions::from_mode(0o644)).unwrap();
+            assert_eq!(check(None), Some(Discrepancy::NotExecutable));
+            make_executable(&hook_file).unwrap();
+            assert_eq!(check(None), None);
+        }
+
+        fs::write(
+            &hook_file,
+            hook.chained_script_content(ScriptFormat::default()),
+        )
+        .unwrap();
+        make_executable(&hook_file).unwrap();
+        assert_eq!(check(None), Some(Discrepancy::MissingChained));
+        fs::write(hooks_dir.join("pre-commit.chained"), "#!/bin/sh\n").unwrap();
+        assert_eq!(check(None), None);
+    }
+
+    #[test]
+    fn test_error_message() {
+        let stderr = "Error: Failed to load configuration\n\nCaused by:\n    0: Failed to parse hookmaster.toml\n    1: Invalid value on line 1\n\nStack backtrace:\n   0: anyhow::error\n";
+        assert_eq!(
+            error_message(stderr).unwrap(),
+            "Failed to load configuration: Failed to parse hookmaster.toml: Invalid value on line 1"
+        );
+        assert_eq!(
+            error_message("Error: Hook failed\n").unwrap(),
+            "Hook failed"
+        );
+        assert_eq!(error_message("\n"), None);
+    }
+
+    #[test]
+    fn test_fixable_discrepancies() {
+        assert!(Discrepancy::NotInstalled.is_fixable());
+        assert!(Discrepancy::NotExecutable.is_fixable());
+        assert!(!Discrepancy::Edited.is_fixable());
+        assert!(!Discrepancy::Foreign(HookOwner::Husky).is_fixable());
+        assert_eq!(
+            Discrepancy::Foreign(HookOwner::Husky).describe(),
+            "installed by Husky"
+        );
+        assert_eq!(
+            Discrepancy::OutOfDate.suggestion(Path::new(".")),
+            "run 'hookmaster add .'"
+        );
+    }
+}
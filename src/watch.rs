@@ -0,0 +1,396 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::timings;
+
+/// How often the working tree is checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// How long the working tree must stay unchanged before the hook runs, so
+/// that saving several files or a formatter rewriting them runs it once
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Directories never watched, even outside a git repository or when they
+/// aren't ignored
+const EXCLUDED_DIRS: [&str; 3] = [".git", "target", "node_modules"];
+
+/// Modification time and size of a watched file
+type Stamp = (Option<SystemTime>, u64);
+
+/// The watched files and their stamps, relative to the watched directory
+type Stamps = HashMap<PathBuf, Stamp>;
+
+/// Where changes to the working tree come from, so that debouncing can be
+/// tested with synthetic changes
+pub trait ChangeSource {
+    /// Paths changed since the last call, relative to the watched directory
+    fn changes(&mut self) -> Result<Vec<PathBuf>>;
+}
+
+/// Finds changes by comparing the modification times and sizes of the
+/// files in a directory between calls. Needs no platform file watching
+/// API; a scan of the files git knows about is cheap enough at the poll
+/// interval.
+pub struct PollingWatcher {
+    root: PathBuf,
+    stamps: Stamps,
+}
+
+impl PollingWatcher {
+    /// Watch the files under `root`, taking their current state as unchanged
+    pub fn new(root: &Path) -> Result<Self> {
+        let mut watcher = Self {
+            root: root.to_path_buf(),
+            stamps: HashMap::new(),
+        };
+        watcher.stamps = watcher.scan()?;
+        Ok(watcher)
+    }
+
+    /// The watched files and their stamps
+    fn scan(&self) -> Result<Stamps> {
+        let files = match git_files(&self.root) {
+            Some(files) => files,
+            None => {
+                let mut files = Vec::new();
+                walk(&self.root, Path::new(""), &mut files)?;
+                files
+            }
+        };
+        Ok(files
+            .into_iter()
+            .filter(|path| !is_excluded(path))
+            .filter_map(|path| {
+                let metadata = fs::metadata(self.root.join(&path)).ok()?;
+                let stamp = (metadata.modified().ok(), metadata.len());
+                Some((path, stamp))
+            })
+            .collect())
+    }
+}
+
+impl ChangeSource for PollingWatcher {
+    fn changes(&mut self) -> Result<Vec<PathBuf>> {
+        let stamps = self.scan()?;
+        let mut changed: BTreeSet<PathBuf> = stamps
+            .iter()
+            .filter(|(path, stamp)| self.stamps.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.stamps
+                .keys()
+                .filter(|path| !stamps.contains_key(*path))
+                .cloned(),
+        );
+        self.stamps = stamps;
+        Ok(changed.into_iter().collect())
+    }
+}
+
+/// The files git tracks or would track in the repository at `root`, which
+/// leaves out ignored ones; `None` outside a repository
+fn git_files(root: &Path) -> Option<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args([
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ])
+        .current_dir(root)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let files = String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect();
+    Some(files)
+}
+
+/// Collect the files under `root.join(dir)` into `files`, relative to
+/// `root`, skipping [`EXCLUDED_DIRS`]
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let full = root.join(dir);
+    let entries =
+        fs::read_dir(&full).with_context(|| format!("Failed to read {}", full.display()))?;
+    for entry in entries.flatten() {
+        let path = dir.join(entry.file_name());
+        if is_excluded(&path) {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk(root, &path, files)?,
+            Ok(_) => files.push(path),
+            Err(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Check if `path` is inside one of the [`EXCLUDED_DIRS`]
+fn is_excluded(path: &Path) -> bool {
+    path.components().any(|component| {
+        EXCLUDED_DIRS
+            .iter()
+            .any(|dir| component.as_os_str() == *dir)
+    })
+}
+
+/// Gathers changes until none have come for a while
+#[derive(Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    pending: BTreeSet<PathBuf>,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    /// Gather changes until none have come for `delay`
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            pending: BTreeSet::new(),
+            last_change: None,
+        }
+    }
+
+    /// Record the paths that changed at `now`
+    pub fn record(&mut self, paths: Vec<PathBuf>, now: Instant) {
+        if !paths.is_empty() {
+            self.pending.extend(paths);
+            self.last_change = Some(now);
+        }
+    }
+
+    /// The gathered paths, once nothing has changed for the delay by `now`
+    pub fn take_ready(&mut self, now: Instant) -> Option<Vec<PathBuf>> {
+        let last_change = self.last_change?;
+        if now.duration_since(last_change) < self.delay {
+            return None;
+        }
+        self.last_change = None;
+        Some(std::mem::take(&mut self.pending).into_iter().collect())
+    }
+}
+
+/// Paths changed together, which a [`Watcher`] returns at once
+pub type Batch = Vec<PathBuf>;
+
+/// Waits for debounced batches of changes from a [`ChangeSource`]
+pub struct Watcher<S> {
+    source: S,
+    debouncer: Debouncer,
+    interval: Duration,
+}
+
+impl<S: ChangeSource> Watcher<S> {
+    /// Check `source` every `interval`, gathering its changes until none
+    /// have come for `debounce`
+    pub fn new(source: S, interval: Duration, debounce: Duration) -> Self {
+        Self {
+            source,
+            debouncer: Debouncer::new(debounce),
+            interval,
+        }
+    }
+
+    /// Wait for changes and return the paths changed, once they have
+    /// settled; `None` if `stop` returns true first
+    pub fn next_batch(&mut self, stop: impl Fn() -> bool) -> Result<Option<Batch>> {
+        loop {
+            if stop() {
+                return Ok(None);
+            }
+            thread::sleep(self.interval);
+            let now = Instant::now();
+            self.debouncer.record(self.source.changes()?, now);
+            if let Some(paths) = self.debouncer.take_ready(now) {
+                return Ok(Some(paths));
+            }
+        }
+    }
+
+    /// Forget the changes made so far, such as the files a hook's
+    /// formatter rewrote, so they don't run the hook again
+    pub fn skip_changes(&mut self) -> Result<()> {
+        self.source.changes()?;
+        self.debouncer = Debouncer::new(self.debouncer.delay);
+        Ok(())
+    }
+}
+
+/// Most changed paths a banner names
+const BANNER_PATHS: usize = 3;
+
+/// The line summing up a run of `hook` that took `elapsed`, such as
+/// `[PASS] pre-commit in 1.2s after changes to src/lib.rs`
+pub fn banner(hook: &str, passed: bool, elapsed: Duration, changed: &[PathBuf]) -> String {
+    let status = if passed { "[PASS]" } else { "[FAIL]" };
+    let mut line = format!(
+        "{status} {hook} in {}",
+        timings::format_duration(elapsed.as_millis() as u64)
+    );
+    if !changed.is_empty() {
+        let mut names: Vec<String> = changed
+            .iter()
+            .take(BANNER_PATHS)
+            .map(|path| path.display().to_string())
+            .collect();
+        if changed.len() > BANNER_PATHS {
+            names.push(format!("{} more", changed.len() - BANNER_PATHS));
+        }
+        line.push_str(&format!(" after changes to {}", names.join(", ")));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use tempfile::TempDir;
+
+    /// The paths changed in one poll
+    type Poll = Vec<&'static str>;
+
+    /// Answers with scripted changes, then with none
+    struct ScriptedChanges(VecDeque<Poll>);
+
+    impl ChangeSource for ScriptedChanges {
+        fn changes(&mut self) -> Result<Vec<PathBuf>> {
+            let paths = self.0.pop_front().unwrap_or_default();
+            Ok(paths.into_iter().map(PathBuf::from).collect())
+        }
+    }
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_debouncer_waits_for_quiet() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+        assert_eq!(debouncer.take_ready(ms(0)), None);
+
+        debouncer.record(paths(&["b.rs"]), ms(0));
+        assert_eq!(debouncer.take_ready(ms(300)), None);
+        // Another change restarts the wait
+        debouncer.record(paths(&["a.rs", "b.rs"]), ms(400));
+        debouncer.record(Vec::new(), ms(700));
+        assert_eq!(debouncer.take_ready(ms(800)), None);
+        assert_eq!(
+            debouncer.take_ready(ms(900)),
+            Some(paths(&["a.rs", "b.rs"]))
+        );
+        assert_eq!(debouncer.take_ready(ms(2000)), None);
+    }
+
+    #[test]
+    fn test_watcher_batches_changes() {
+        let source = ScriptedChanges(VecDeque::from([
+            vec![],
+            vec!["src/lib.rs"],
+            vec!["src/main.rs"],
+            vec![],
+            vec!["formatted.rs"],
+            vec![],
+            vec!["README.md"],
+        ]));
+        // Changes in consecutive polls always gather; with a debounce no
+        // longer than the interval, the first quiet poll ends the batch
+        let mut watcher = Watcher::new(source, Duration::from_millis(2), Duration::from_millis(1));
+        assert_eq!(
+            watcher.next_batch(|| false).unwrap().unwrap(),
+            paths(&["src/lib.rs", "src/main.rs"])
+        );
+        watcher.skip_changes().unwrap();
+        assert_eq!(
+            watcher.next_batch(|| false).unwrap().unwrap(),
+            paths(&["README.md"])
+        );
+        assert_eq!(watcher.next_batch(|| true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_polling_watcher_outside_git() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for dir in ["src", "target", "node_modules/pkg", ".git"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("src/lib.rs"), "a").unwrap();
+        fs::write(root.join("notes.md"), "a").unwrap();
+
+        let mut watcher = PollingWatcher::new(root).unwrap();
+        assert!(watcher.changes().unwrap().is_empty());
+
+        fs::write(root.join("src/lib.rs"), "ab").unwrap();
+        fs::write(root.join("src/new.rs"), "").unwrap();
+        fs::remove_file(root.join("notes.md")).unwrap();
+        fs::write(root.join("target/out"), "").unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "").unwrap();
+        fs::write(root.join(".git/index"), "").unwrap();
+        assert_eq!(
+            watcher.changes().unwrap(),
+            vec![
+                PathBuf::from("notes.md"),
+                Path::new("src").join("lib.rs"),
+                Path::new("src").join("new.rs"),
+            ]
+        );
+        assert!(watcher.changes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_polling_watcher_skips_gitignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(root)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"]);
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("app.py"), "").unwrap();
+
+        let mut watcher = PollingWatcher::new(root).unwrap();
+        fs::write(root.join("debug.log"), "noise").unwrap();
+        fs::write(root.join("app.py"), "print()").unwrap();
+        assert_eq!(watcher.changes().unwrap(), paths(&["app.py"]));
+    }
+
+    #[test]
+    fn test_banner() {
+        assert_eq!(
+            banner("pre-commit", true, Duration::from_millis(1234), &[]),
+            "[PASS] pre-commit in 1.2s"
+        );
+        assert_eq!(
+            banner(
+                "pre-commit",
+                false,
+                Duration::from_millis(80),
+                &paths(&["a.rs", "b.rs", "c.rs", "d.rs", "e.rs"])
+            ),
+            "[FAIL] pre-commit in 80ms after changes to a.rs, b.rs, c.rs, 2 more"
+        );
+    }
+}
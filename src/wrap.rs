@@ -0,0 +1,373 @@
+//! Rewrapping the body of commit messages to a width
+
+/// Line git puts above the diff of `git commit --verbose`; it and what
+/// follows are not part of the message
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// Markers of fenced code blocks
+const FENCES: [&str; 2] = ["```", "~~~"];
+
+/// `message` with the paragraphs and list items of its body rewrapped to
+/// `width` characters. The subject, comment lines, the trailers at the end,
+/// fenced and indented blocks, and lines with URLs or table cells are kept
+/// as they are, and so are words longer than `width`. Wrapping a wrapped
+/// message again leaves it unchanged.
+pub fn wrap_body(message: &str, width: usize) -> String {
+    let lines: Vec<&str> = message.lines().collect();
+    let end = lines
+        .iter()
+        .position(|line| line.starts_with(SCISSORS))
+        .unwrap_or(lines.len());
+    let body_start = subject_end(&lines[..end]);
+    let body_end = trailers_start(&lines[body_start..end]) + body_start;
+
+    let mut wrapped: Vec<String> = lines[..body_start].iter().map(|l| l.to_string()).collect();
+    wrapped.extend(wrap_lines(&lines[body_start..body_end], width));
+    wrapped.extend(lines[body_end..].iter().map(|l| l.to_string()));
+
+    let mut result = wrapped.join("\n");
+    if message.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+/// Index of the first line after the subject paragraph, skipping blank and
+/// comment lines before it
+fn subject_end(lines: &[&str]) -> usize {
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty() && !is_comment(line))
+        .unwrap_or(lines.len());
+    lines[start..]
+        .iter()
+        .position(|line| line.trim().is_empty() || is_comment(line))
+        .map_or(lines.len(), |len| start + len)
+}
+
+/// Index of the first line of the trailer block ending the body, or the
+/// length of `lines` if the last paragraph isn't one
+fn trailers_start(lines: &[&str]) -> usize {
+    let Some(last) = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty() && !is_comment(line))
+    else {
+        return lines.len();
+    };
+    let start = lines[..last]
+        .iter()
+        .rposition(|line| line.trim().is_empty() || is_comment(line))
+        .map_or(0, |i| i + 1);
+    let paragraph = &lines[start..=last];
+    // Continuation lines of a trailer are indented
+    let is_trailer_block = is_trailer(paragraph[0])
+        && paragraph
+            .iter()
+            .all(|line| is_trailer(line) || line.starts_with([' ', '\t']));
+    if is_trailer_block {
+        start
+    } else {
+        lines.len()
+    }
+}
+
+/// Rewrap the paragraphs and list items of body `lines`
+fn wrap_lines(lines: &[&str], width: usize) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(fence) = fence(line) {
+            // The block runs to its closing fence, or to the end of the body
+            let len = lines[i + 1..]
+                .iter()
+                .position(|l| l.trim_start().starts_with(fence))
+                .map_or(lines.len() - i, |len| len + 2);
+            wrapped.extend(lines[i..i + len].iter().map(|l| l.to_string()));
+            i += len;
+        } else if line.trim().is_empty() || is_comment(line) || is_verbatim(line) {
+            wrapped.push(line.to_string());
+            i += 1;
+        } else if let Some(marker_len) = list_marker_len(line) {
+            let indent = " ".repeat(marker_len);
+            let mut words: Vec<&str> = line[marker_len..].split_whitespace().collect();
+            i += 1;
+            while let Some(next) = lines.get(i).and_then(|l| l.strip_prefix(&indent)) {
+                if next.starts_with(char::is_whitespace) || is_part_of_paragraph(next) != Some(true)
+                {
+                    break;
+                }
+                words.extend(next.split_whitespace());
+                i += 1;
+            }
+            let filled = fill(&words, width.saturating_sub(marker_len).max(1));
+            for (n, text) in filled.into_iter().enumerate() {
+                let prefix = if n == 0 { &line[..marker_len] } else { &indent };
+                wrapped.push(format!("{prefix}{text}"));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            wrapped.push(line.to_string());
+            i += 1;
+        } else {
+            let mut words = Vec::new();
+            while let Some(next) = lines.get(i) {
+                if next.starts_with([' ', '\t']) || is_part_of_paragraph(next) != Some(true) {
+                    break;
+                }
+                words.extend(next.split_whitespace());
+                i += 1;
+            }
+            wrapped.extend(fill(&words, width));
+        }
+    }
+    wrapped
+}
+
+/// Whether `line` continues a paragraph of prose: `None` for blank lines,
+/// `Some(false)` for lines that start something else
+fn is_part_of_paragraph(line: &str) -> Option<bool> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    Some(
+        !is_comment(line)
+            && !is_verbatim(line)
+            && fence(line).is_none()
+            && list_marker_len(line).is_none(),
+    )
+}
+
+/// `words` joined into lines of at most `width` characters; a word longer
+/// than that gets a line of its own
+fn fill(words: &[&str], width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut current_width = 0;
+    for word in words {
+        let word_width = word.chars().count();
+        if current.is_empty() {
+            current.push_str(word);
+            current_width = word_width;
+        } else if current_width + 1 + word_width <= width {
+            current.push(' ');
+            current.push_str(word);
+            current_width += 1 + word_width;
+        } else {
+            lines.push(std::mem::take(&mut current));
+            current.push_str(word);
+            current_width = word_width;
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn is_comment(line: &str) -> bool {
+    line.starts_with('#')
+}
+
+/// The fence `line` opens a code block with, if any
+fn fence(line: &str) -> Option<&'static str> {
+    let trimmed = line.trim_start();
+    FENCES.into_iter().find(|fence| trimmed.starts_with(fence))
+}
+
+/// Whether `line` has a URL or is a row of a table, which wrapping would
+/// break up
+fn is_verbatim(line: &str) -> bool {
+    let trimmed = line.trim();
+    line.split_whitespace().any(|word| word.contains("://"))
+        || trimmed.starts_with('|')
+        || trimmed.contains(" | ")
+        || (trimmed.contains('|') && trimmed.chars().all(|c| matches!(c, '|' | '-' | ':' | ' ')))
+}
+
+/// Length of the list marker starting `line`, such as `- ` or `  2. `,
+/// including the indentation before it and the spaces after it
+fn list_marker_len(line: &str) -> Option<usize> {
+    let rest = line.trim_start_matches(' ');
+    let bullet_len = if rest.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        let numbered = (1..=3).contains(&digits) && rest[digits..].starts_with(['.', ')']);
+        if !numbered {
+            return None;
+        }
+        digits + 1
+    };
+    let after = &rest[bullet_len..];
+    let text = after.trim_start_matches(' ');
+    if text.len() == after.len() || text.is_empty() {
+        return None;
+    }
+    Some(line.len() - text.len())
+}
+
+/// Whether `line` is a trailer such as `Signed-off-by: A U Thor <a@example.com>`
+pub(crate) fn is_trailer(line: &str) -> bool {
+    let Some((token, value)) = line.split_once(':') else {
+        return false;
+    };
+    !token.is_empty()
+        && !token.starts_with('-')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && value.starts_with(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wrap `message` to `width`, checking that wrapping it again changes nothing
+    fn wrap(message: &str, width: usize) -> String {
+        let wrapped = wrap_body(message, width);
+        assert_eq!(wrap_body(&wrapped, width), wrapped, "not idempotent");
+        wrapped
+    }
+
+    #[test]
+    fn test_wraps_body_paragraphs() {
+        let message = "Fix the frobnicator\n\nThe frobnicator broke when the widget count went above the limit, so it is now checked first.\nShort line.\n\nSecond paragraph.\n";
+        assert_eq!(
+            wrap(message, 40),
+            "Fix the frobnicator\n\nThe frobnicator broke when the widget\ncount went above the limit, so it is now\nchecked first. Short line.\n\nSecond paragraph.\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_subject() {
+        let subject = "A subject line that is much longer than the width it wraps the body to";
+        assert_eq!(wrap(subject, 20), subject);
+        let message = format!("{subject}\n\nBody text here.");
+        assert_eq!(wrap(&message, 20), message);
+        // Comment lines before the subject
+        let message = format!("# Please enter the message\n{subject}\n");
+        assert_eq!(wrap(&message, 20), message);
+    }
+
+    #[test]
+    fn test_keeps_comments_and_verbose_diff() {
+        let message = "Subject\n\nOne two three four five six\n# Please enter the commit message for your changes. Lines starting\n# with '#' will be ignored.\n# ------------------------ >8 ------------------------\n# Do not modify or remove the line above.\ndiff --git a/x b/x\n+a long added line in the diff that must not be wrapped at all\n";
+        assert_eq!(
+            wrap(message, 15),
+            "Subject\n\nOne two three\nfour five six\n# Please enter the commit message for your changes. Lines starting\n# with '#' will be ignored.\n# ------------------------ >8 ------------------------\n# Do not modify or remove the line above.\ndiff --git a/x b/x\n+a long added line in the diff that must not be wrapped at all\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_trailers() {
+        let message = "Subject\n\nA body paragraph long enough to be wrapped.\n\nSigned-off-by: Some Person With A Long Name <some.person@example.com>\nCo-authored-by: Another Person <another@example.com>\nReviewed-by: Someone\n  continued on the next line\n";
+        assert_eq!(
+            wrap(message, 20),
+            "Subject\n\nA body paragraph\nlong enough to be\nwrapped.\n\nSigned-off-by: Some Person With A Long Name <some.person@example.com>\nCo-authored-by: Another Person <another@example.com>\nReviewed-by: Someone\n  continued on the next line\n"
+        );
+    }
+
+    #[test]
+    fn test_trailer_like_lines_in_the_middle_are_prose() {
+        let message = "Subject\n\nNote: this paragraph is not the last\none.\n\nFixes: #12\n";
+        assert_eq!(
+            wrap(message, 72),
+            "Subject\n\nNote: this paragraph is not the last one.\n\nFixes: #12\n"
+        );
+        // A last paragraph that isn't all trailers is prose
+        let message = "Subject\n\nSee: the docs\nfor details\n";
+        assert_eq!(wrap(message, 72), "Subject\n\nSee: the docs for details\n");
+    }
+
+    #[test]
+    fn test_keeps_long_urls() {
+        let url = "https://example.com/a/very/long/path/that/goes/on/and/on?with=query&and=more";
+        let message =
+            format!("Subject\n\nThis paragraph refers to a page\n{url}\nand continues after it for a while.\n");
+        assert_eq!(
+            wrap(&message, 20),
+            format!("Subject\n\nThis paragraph\nrefers to a page\n{url}\nand continues after\nit for a while.\n")
+        );
+        let message = format!("Subject\n\nSee {url} for more.\n");
+        assert_eq!(wrap(&message, 20), message);
+    }
+
+    #[test]
+    fn test_long_words_get_their_own_line() {
+        let message = "Subject\n\nCall Frobnicator::frobnicate_all_the_widgets_at_once instead.\n";
+        assert_eq!(
+            wrap(message, 20),
+            "Subject\n\nCall\nFrobnicator::frobnicate_all_the_widgets_at_once\ninstead.\n"
+        );
+    }
+
+    #[test]
+    fn test_wraps_bullet_lists() {
+        let message = "Subject\n\nChanges:\n- first item that is long enough to wrap around\n- second\n* starred item that is also rather long\n  with a continuation line\n10. numbered item with a wide marker to wrap\n";
+        assert_eq!(
+            wrap(message, 24),
+            "Subject\n\nChanges:\n- first item that is\n  long enough to wrap\n  around\n- second\n* starred item that is\n  also rather long with\n  a continuation line\n10. numbered item with a\n    wide marker to wrap\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_bullet_lists() {
+        let message = "Subject\n\n- outer item that needs wrapping here\n  - inner item that needs wrapping too\n- next\n";
+        assert_eq!(
+            wrap(message, 20),
+            "Subject\n\n- outer item that\n  needs wrapping\n  here\n  - inner item that\n    needs wrapping\n    too\n- next\n"
+        );
+    }
+
+    #[test]
+    fn test_text_after_a_list_is_a_paragraph() {
+        let message = "Subject\n\n- an item\nThen some text after\nthe list.\n";
+        assert_eq!(
+            wrap(message, 72),
+            "Subject\n\n- an item\nThen some text after the list.\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_code_blocks() {
+        let message = "Subject\n\nRun this:\n\n    cargo test --workspace --all-features -- --nocapture --test-threads 1\n\n```\nlet x = some_function(with, quite, a, lot, of, arguments, that, is, long);\n- not a list\n```\n\nDone.\n";
+        assert_eq!(wrap(message, 20), message);
+        // An unclosed fence runs to the end of the body
+        let message = "Subject\n\n```\nlet x = some_function(with, many, arguments);\n";
+        assert_eq!(wrap(message, 20), message);
+    }
+
+    #[test]
+    fn test_keeps_tables() {
+        let message = "Subject\n\n| Benchmark | Before | After |\n|-----------|--------|-------|\n| parse     | 12ms   | 8ms   |\n\nname | value\n---|---\n";
+        assert_eq!(wrap(message, 10), message);
+    }
+
+    #[test]
+    fn test_unicode_width_and_line_endings() {
+        let message = "Subject\n\nÄäkköset öö ää åå";
+        assert_eq!(wrap(message, 11), "Subject\n\nÄäkköset öö\nää åå");
+        assert_eq!(wrap("", 72), "");
+        assert_eq!(wrap("Subject only\n", 72), "Subject only\n");
+    }
+
+    #[test]
+    fn test_list_marker_len() {
+        assert_eq!(list_marker_len("- item"), Some(2));
+        assert_eq!(list_marker_len("  *   item"), Some(6));
+        assert_eq!(list_marker_len("12) item"), Some(4));
+        assert_eq!(list_marker_len("-item"), None);
+        assert_eq!(list_marker_len("- "), None);
+        assert_eq!(list_marker_len("3.14 is pi"), None);
+        assert_eq!(list_marker_len("2024. was a year"), None);
+    }
+
+    #[test]
+    fn test_is_trailer() {
+        assert!(is_trailer("Signed-off-by: A U Thor <a@example.com>"));
+        assert!(is_trailer("Fixes: #123"));
+        assert!(!is_trailer("https://example.com"));
+        assert!(!is_trailer("Not a trailer: has spaces in token"));
+        assert!(!is_trailer("-x: y"));
+        assert!(!is_trailer("Token:no-space"));
+    }
+}
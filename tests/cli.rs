@@ -0,0 +1,1247 @@
+use std::fs;
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+/// PATH with the built hookmaster first, as the hook scripts run it from PATH
+fn path_with_hookmaster() -> std::ffi::OsString {
+    let bin = assert_cmd::cargo::cargo_bin("hookmaster");
+    std::env::join_paths(std::iter::once(bin.parent().unwrap().to_path_buf()).chain(
+        std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+    ))
+    .unwrap()
+}
+
+/// `hookmaster` in a session of its own, without the controlling terminal
+/// the tests may have, as when an IDE or CI runs the hooks
+#[cfg(unix)]
+fn headless_hookmaster() -> std::process::Command {
+    use std::os::unix::process::CommandExt;
+
+    let mut command = std::process::Command::new(assert_cmd::cargo::cargo_bin("hookmaster"));
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    command
+}
+
+#[test]
+fn test_parallel_output_is_prefixed_with_command_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        r#"
+[hooks.pre-commit]
+parallel = true
+
+[hooks.pre-commit.fmt]
+command = "echo formatted && echo checked"
+
+[hooks.pre-commit.clippy]
+command = "echo linted >&2"
+"#,
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["run", "pre-commit"])
+        .current_dir(repo)
+        .env("NO_COLOR", "1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[fmt] formatted\n"))
+        .stdout(predicate::str::contains("[fmt] checked\n"))
+        .stderr(predicate::str::contains("[clippy] linted\n"));
+}
+
+#[test]
+fn test_format_option_overrides_prefixing() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        r#"
+[hooks.pre-commit]
+parallel = true
+
+[hooks.pre-commit.fmt]
+command = "echo formatted"
+
+[hooks.pre-commit.clippy]
+command = "echo linted"
+"#,
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["run", "pre-commit", "--format", "raw"])
+        .current_dir(repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("formatted\n"))
+        .stdout(predicate::str::contains("linted\n"))
+        .stdout(predicate::str::contains("[fmt]").not());
+}
+
+#[test]
+fn test_output_buffering_keeps_partial_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        "pre-commit = \"printf 'Testing' && sleep 1 && printf '...ok'\"\n",
+    )
+    .unwrap();
+
+    for buffering in ["char", "line", "full"] {
+        Command::cargo_bin("hookmaster")
+            .unwrap()
+            .args(["run", "pre-commit", "--output-buffering", buffering])
+            .current_dir(repo)
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("Testing...ok"));
+    }
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["run", "pre-commit", "--output-buffering", "full"])
+        .args(["--format", "compact"])
+        .current_dir(repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("[pre-commit] Testing...ok\n"));
+}
+
+#[test]
+fn test_add_reads_paths_from_stdin() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    for repo in ["api", "web", "cli"] {
+        fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+    }
+    let stdin = format!(
+        "{}\n\n{}\n{}\n",
+        root.join("api").display(),
+        root.join("web").display(),
+        root.join("missing").display()
+    );
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("add")
+        .arg(root.join("cli"))
+        .arg("-")
+        .write_stdin(stdin)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("Successfully installed hooks to all repositories").count(1),
+        )
+        .stderr(predicate::str::contains("missing: not a directory"));
+
+    for repo in ["api", "web", "cli"] {
+        assert!(
+            root.join(repo).join(".git/hooks/pre-commit").is_file(),
+            "{repo}"
+        );
+    }
+}
+
+#[test]
+fn test_add_writes_github_step_summary() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("repos/api/.git")).unwrap();
+    let summary = root.join("step-summary.md");
+    fs::write(&summary, "Earlier step output\n").unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("add")
+        .arg(root.join("repos"))
+        .env("GITHUB_STEP_SUMMARY", &summary)
+        .assert()
+        .success();
+
+    let summary = fs::read_to_string(&summary).unwrap();
+    assert!(summary.starts_with("Earlier step output\n### hookmaster add\n\n"));
+    assert!(summary.contains("| Repository | Status | Hooks Installed |\n"));
+    assert!(summary.contains(&format!(
+        "| {} | :white_check_mark: installed | `pre-commit`",
+        root.join("repos/api").display()
+    )));
+}
+
+#[test]
+fn test_add_repos_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    for repo in ["api", "web", "nested/lib"] {
+        fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+    }
+    fs::create_dir_all(root.join("docs")).unwrap();
+    let repos_file = root.join("affected-repos.txt");
+    fs::write(
+        &repos_file,
+        format!(
+            "# affected by this change\n{}\n\n{}\n{}\n{}\n",
+            root.join("api").display(),
+            root.join("docs").display(),
+            root.join("gone").display(),
+            root.join("web").display(),
+        ),
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--repos-file"])
+        .arg(&repos_file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("docs: not a git repository"))
+        .stderr(predicate::str::contains("gone: does not exist"))
+        .stderr(predicate::str::contains(
+            "Failed to install hooks to 2 of 4 repositories",
+        ));
+    assert!(root.join("api/.git/hooks/pre-commit").exists());
+    assert!(root.join("web/.git/hooks/pre-commit").exists());
+    // Listed repositories aren't searched
+    assert!(!root.join("nested/lib/.git/hooks/pre-commit").exists());
+}
+
+#[test]
+fn test_add_interactive_prompts_for_repositories() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    for repo in ["api", "web"] {
+        fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+    }
+    fs::write(root.join("api/hookmaster.toml"), "pre-commit = \"true\"\n").unwrap();
+
+    // Without a terminal the repositories are toggled by number
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--interactive"])
+        .arg(root.join("api"))
+        .arg(root.join("web"))
+        .write_stdin("1 2\n\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("  1. [x] ").and(predicate::str::contains("  2. [ ] ")));
+
+    assert!(!root.join("api/.git/hooks/pre-commit").exists());
+    assert!(root.join("web/.git/hooks/pre-commit").is_file());
+}
+
+#[test]
+fn test_config_import_husky() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join(".husky/_")).unwrap();
+    fs::write(
+        root.join(".husky/pre-commit"),
+        "#!/usr/bin/env sh\n. \"$(dirname -- \"$0\")/_/husky.sh\"\n\nnpx lint-staged\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join(".husky/commit-msg"),
+        "npx --no -- commitlint --edit $1\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["config", "import", "--format", "husky"])
+        .current_dir(root)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Imported 2 hook(s) from .husky into hookmaster.toml",
+        ))
+        // `$1` reaches the command as in Husky, so nothing to warn about
+        .stderr(predicate::str::is_empty());
+
+    let config = fs::read_to_string(root.join("hookmaster.toml")).unwrap();
+    assert!(config.contains("pre-commit = \"npx lint-staged\""));
+    assert!(config.contains("append-args = false"));
+
+    // An existing configuration is not overwritten
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["config", "import", "--format", "husky"])
+        .current_dir(root)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("hookmaster.toml already exists"));
+}
+
+#[test]
+fn test_config_hash_follows_the_effective_configuration() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    let config_hash = |args: &[&str]| {
+        let output = Command::cargo_bin("hookmaster")
+            .unwrap()
+            .args(["config", "hash"])
+            .args(args)
+            .current_dir(root)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+    fs::write(
+        root.join("hookmaster.toml"),
+        "includes = [\"ci.toml\"]\npre-commit = \"cargo fmt --check\"\npre-push = \"cargo test\"\n",
+    )
+    .unwrap();
+    fs::write(root.join("ci.toml"), "pre-push = \"cargo test --all\"\n").unwrap();
+    let hash = config_hash(&[]);
+    assert_eq!(hash.len(), 64);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(config_hash(&["--format", "md5"]).len(), 32);
+
+    // Comments and the order of the hooks don't change it
+    fs::write(
+        root.join("hookmaster.toml"),
+        "# Checks\nincludes = [\"ci.toml\"]\npre-push = \"cargo test\"\npre-commit = \"cargo fmt --check\"\n",
+    )
+    .unwrap();
+    assert_eq!(config_hash(&[]), hash);
+
+    // An included file does
+    fs::write(root.join("ci.toml"), "pre-push = \"cargo nextest run\"\n").unwrap();
+    assert_ne!(config_hash(&[]), hash);
+}
+
+#[test]
+fn test_add_refuses_hooks_of_other_managers() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    let hooks_dir = root.join("app/.git/hooks");
+    fs::create_dir_all(&hooks_dir).unwrap();
+    fs::copy(
+        "tests/fixtures/hooks/lefthook",
+        hooks_dir.join("pre-commit"),
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--dry-run"])
+        .arg(root)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("[DRY-RUN] would skip")
+                .and(predicate::str::contains(
+                    "pre-commit (not hookmaster-managed)",
+                ))
+                .and(predicate::str::contains(
+                    "would install 0 hooks to 0 repositories, skip 1 existing",
+                )),
+        );
+    assert!(!hooks_dir.join("pre-push").exists());
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--dry-run", "--chain"])
+        .arg(root)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("pre-commit.chained (").and(predicate::str::contains(
+                "would install 5 hooks to 1 repositories",
+            )),
+        );
+    assert!(!hooks_dir.join("pre-commit.chained").exists());
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("add")
+        .arg(root)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pre-commit by lefthook"));
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--chain"])
+        .arg(root)
+        .assert()
+        .success();
+    assert!(hooks_dir.join("pre-commit.chained").exists());
+}
+
+#[test]
+fn test_add_waits_for_lock_of_another_process() {
+    let temp_dir = TempDir::new().unwrap();
+    let git_dir = temp_dir.path().join("app/.git");
+    fs::create_dir_all(git_dir.join("hooks")).unwrap();
+    let lock_path = git_dir.join("hookmaster.lock");
+    fs::write(&lock_path, "12345\n").unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--lock-timeout", "1"])
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Timed out after 1s waiting for"));
+    assert!(!git_dir.join("hooks/pre-commit").exists());
+    assert!(lock_path.exists());
+
+    // The other process finishes while add waits
+    let release = {
+        let lock_path = lock_path.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            fs::remove_file(lock_path).unwrap();
+        })
+    };
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("add")
+        .arg(temp_dir.path())
+        .assert()
+        .success();
+    release.join().unwrap();
+    assert!(git_dir.join("hooks/pre-commit").exists());
+    assert!(!lock_path.exists());
+}
+
+#[test]
+fn test_add_skips_ignored_repositories() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().join("workspace");
+    for repo in ["mirrors/linux", "vendor", "app"] {
+        fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+    }
+    fs::write(root.join("vendor/.hookmaster-ignore"), "").unwrap();
+    let config_dir = temp_dir.path().join("config");
+    fs::create_dir_all(config_dir.join("hookmaster")).unwrap();
+    let mirrors = fs::canonicalize(&root).unwrap().join("mirrors").join("*");
+    fs::write(
+        config_dir.join("hookmaster/ignore"),
+        format!("# read-only mirrors\n{}\n", mirrors.display()),
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .arg("add")
+        .arg(&root)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Installed hooks to 1 of 3 repositories, skipped 2 (ignored)",
+        ));
+    assert!(root.join("app/.git/hooks/pre-commit").exists());
+    assert!(!root.join("mirrors/linux/.git/hooks/pre-commit").exists());
+    assert!(!root.join("vendor/.git/hooks/pre-commit").exists());
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .args(["add", "--no-ignore"])
+        .arg(&root)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Successfully installed hooks to all repositories",
+        ));
+    assert!(root.join("mirrors/linux/.git/hooks/pre-commit").exists());
+    assert!(root.join("vendor/.git/hooks/pre-commit").exists());
+}
+
+#[test]
+fn test_add_to_many_repositories_needs_yes_without_terminal() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().join("workspace");
+    for repo in ["one", "two", "three"] {
+        fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+    }
+    let config_dir = temp_dir.path().join("config");
+    fs::create_dir_all(config_dir.join("hookmaster")).unwrap();
+    fs::write(
+        config_dir.join("hookmaster/config.toml"),
+        "[add]\nconfirm-threshold = 2\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .arg("add")
+        .arg(&root)
+        .write_stdin("y\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Found 3 repositories, more than the confirmation threshold of 2; pass --yes",
+        ));
+    assert!(!root.join("one/.git/hooks/pre-commit").exists());
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .env("XDG_CONFIG_HOME", &config_dir)
+        .args(["add", "--yes"])
+        .arg(&root)
+        .assert()
+        .success();
+    assert!(root.join("one/.git/hooks/pre-commit").exists());
+}
+
+#[test]
+fn test_verify_reports_and_fixes_missing_hooks() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path().join("app");
+    fs::create_dir_all(repo.join(".git/hooks")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        "pre-commit = \"cargo fmt --check\"\npre-push = \"cargo test\"\n",
+    )
+    .unwrap();
+
+    let path = path_with_hookmaster();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("verify")
+        .arg(temp_dir.path())
+        .env("PATH", &path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "pre-commit: not installed; run 'hookmaster add",
+        ))
+        .stderr(predicate::str::contains(
+            "Found 2 problem(s) with installed hooks",
+        ));
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["verify", "--fix"])
+        .arg(temp_dir.path())
+        .env("PATH", &path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("pre-push: fixed (not installed)"));
+    assert!(repo.join(".git/hooks/pre-commit").exists());
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("verify")
+        .arg(temp_dir.path())
+        .env("PATH", &path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 hook(s) verified"));
+}
+
+#[test]
+fn test_add_verify_runs_installed_hooks() {
+    let temp_dir = TempDir::new().unwrap();
+    let good = temp_dir.path().join("good");
+    let broken = temp_dir.path().join("broken");
+    for repo in [&good, &broken] {
+        fs::create_dir_all(repo.join(".git/hooks")).unwrap();
+    }
+    fs::write(
+        good.join("hookmaster.toml"),
+        "pre-commit = \"cargo fmt --check\"\npre-receive = \"check-refs\"\n",
+    )
+    .unwrap();
+    fs::write(broken.join("hookmaster.toml"), "pre-commit = [unclosed\n").unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--verify", "--only-configured", "--ndjson"])
+        .arg(&good)
+        .env("PATH", path_with_hookmaster())
+        .assert()
+        .success()
+        // Server-side hooks aren't run
+        .stdout(predicate::str::contains(
+            "\"verification\":{\"pre-commit\":true}}",
+        ));
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .args(["add", "--verify"])
+        .arg(&broken)
+        .env("PATH", path_with_hookmaster())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Verifying pre-commit in"))
+        .stderr(predicate::str::contains("failed verification"));
+}
+
+#[test]
+fn test_upgrade_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = temp_dir.path().join("hookmaster.toml");
+    fs::write(
+        &config,
+        "# Checked before every commit\npre-commit = \"cargo fmt --check\"\n",
+    )
+    .unwrap();
+    let upgrade_config = |args: &[&str]| {
+        let mut command = Command::cargo_bin("hookmaster").unwrap();
+        command
+            .arg("upgrade-config")
+            .args(args)
+            .current_dir(temp_dir.path());
+        command.assert()
+    };
+
+    upgrade_config(&["--check"])
+        .failure()
+        .stdout(predicate::str::contains("+[hooks.pre-commit]"))
+        .stderr(predicate::str::contains("uses the old format"));
+
+    upgrade_config(&[])
+        .success()
+        .stdout(predicate::str::contains(
+            "-pre-commit = \"cargo fmt --check\"",
+        ))
+        .stdout(predicate::str::contains("Upgraded hookmaster.toml"));
+    assert_eq!(
+        fs::read_to_string(&config).unwrap(),
+        "# Checked before every commit\n[hooks.pre-commit]\ncargo-fmt = { command = \"cargo fmt --check\" }\n"
+    );
+
+    upgrade_config(&["--check"]).success();
+    upgrade_config(&[])
+        .failure()
+        .stderr(predicate::str::contains("already uses [hooks] tables"));
+    upgrade_config(&["--force"])
+        .success()
+        .stdout(predicate::str::contains("is up to date"));
+}
+
+#[test]
+fn test_prepare_commit_msg_test_branch() {
+    let temp_dir = TempDir::new().unwrap();
+    let preview = |branch: &str| {
+        let mut command = Command::cargo_bin("hookmaster").unwrap();
+        command
+            .args(["prepare-commit-msg", "--test-branch", branch, "-"])
+            .current_dir(temp_dir.path());
+        command.assert().success()
+    };
+
+    preview("feature/JIRA-999-my-work").stdout("JIRA-999: My Work\n");
+    preview("JIRA-999").stdout("JIRA-999: \n");
+    preview("main")
+        .stdout("")
+        .stderr(predicate::str::contains("No ticket in branch name 'main'"));
+
+    // The settings of the configuration apply
+    fs::write(
+        temp_dir.path().join("hookmaster.toml"),
+        "[settings]\nmulti-ticket = true\n",
+    )
+    .unwrap();
+    preview("feature/JIRA-1-JIRA-2-both").stdout("JIRA-1 JIRA-2: Both\n");
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_prepare_commit_msg_branch() {
+    let temp_dir = TempDir::new().unwrap();
+    let msg_file = temp_dir.path().join("COMMIT_EDITMSG");
+    let prepare = |branch: &str, args: &[&str]| {
+        let mut command = Command::cargo_bin("hookmaster").unwrap();
+        command
+            .args(["prepare-commit-msg", "--branch", branch])
+            .arg(&msg_file)
+            .args(args)
+            .current_dir(temp_dir.path());
+        command.assert().success();
+        fs::read_to_string(&msg_file).unwrap()
+    };
+
+    // Not a repository, so the branch can only come from --branch
+    fs::write(&msg_file, "# Please enter the commit message\n").unwrap();
+    assert_eq!(
+        prepare("bugfix/JIRA-7-fix-crash", &[]),
+        "JIRA-7: Fix Crash\n\n# Please enter the commit message\n"
+    );
+    fs::write(&msg_file, "Merge branch 'main'\n").unwrap();
+    assert_eq!(
+        prepare("JIRA-7-fix-crash", &["merge"]),
+        "Merge branch 'main'\n"
+    );
+}
+
+#[test]
+fn test_prepare_commit_msg_uses_repository_of_message_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let git = |repo: &std::path::Path, args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}");
+    };
+    let [repo_a, repo_b] = ["a", "b"].map(|name| temp_dir.path().join(name));
+    for (repo, branch) in [(&repo_a, "feature/AAA-1-in-a"), (&repo_b, "BBB-2_in_b")] {
+        fs::create_dir(repo).unwrap();
+        git(repo, &["init", "-q"]);
+        git(
+            repo,
+            &["commit", "-q", "--allow-empty", "-m", "Initial commit"],
+        );
+        git(repo, &["checkout", "-q", "-b", branch]);
+    }
+    // Only repository B's configuration splits its branch names at `_`
+    fs::write(
+        repo_b.join("hookmaster.toml"),
+        "[settings]\nbranch-separator = \"_\"\n",
+    )
+    .unwrap();
+    git(&repo_b, &["add", "hookmaster.toml"]);
+    git(&repo_b, &["commit", "-q", "-m", "Add configuration"]);
+    git(
+        &repo_b,
+        &["worktree", "add", "-q", "-b", "BBB-3_in_worktree", "../wt"],
+    );
+
+    let prepare = |msg_file: std::path::PathBuf| {
+        fs::write(&msg_file, "# Please enter the commit message\n").unwrap();
+        Command::cargo_bin("hookmaster")
+            .unwrap()
+            .arg("prepare-commit-msg")
+            .arg(&msg_file)
+            .current_dir(&repo_a)
+            .assert()
+            .success();
+        fs::read_to_string(&msg_file).unwrap()
+    };
+
+    assert!(prepare(repo_b.join(".git/COMMIT_EDITMSG")).starts_with("BBB-2: In B\n\n"));
+    assert!(prepare(repo_b.join(".git/worktrees/wt/COMMIT_EDITMSG"))
+        .starts_with("BBB-3: In Worktree\n\n"));
+    assert!(prepare(repo_a.join(".git/COMMIT_EDITMSG")).starts_with("AAA-1: In A\n\n"));
+}
+
+#[test]
+fn test_run_records_timings_for_stats() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        r#"
+[hooks.pre-commit.fmt]
+command = "true"
+
+[hooks.pre-commit.lint]
+command = "false"
+"#,
+    )
+    .unwrap();
+    let hookmaster = |args: &[&str]| {
+        let mut command = Command::cargo_bin("hookmaster").unwrap();
+        command.args(args).current_dir(repo);
+        command.assert()
+    };
+
+    hookmaster(&["stats"])
+        .success()
+        .stdout(predicate::str::contains("No hook timings recorded"));
+    hookmaster(&["run", "pre-commit"]).failure();
+    hookmaster(&["run", "pre-commit"]).failure();
+
+    let log = fs::read_to_string(repo.join(".git/hookmaster-timings.jsonl")).unwrap();
+    assert_eq!(log.lines().count(), 4);
+    hookmaster(&["stats", "--hook", "pre-commit", "--since", "1d"])
+        .success()
+        .stdout(predicate::str::is_match(r"pre-commit\s+lint\s+2\s+100\.0%").unwrap())
+        .stdout(predicate::str::is_match(r"pre-commit\s+fmt\s+2\s+0\.0%").unwrap());
+    hookmaster(&["stats", "--json", "--hook", "pre-push"])
+        .success()
+        .stdout("[]\n");
+
+    // Recording can be turned off
+    fs::remove_file(repo.join(".git/hookmaster-timings.jsonl")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        "pre-commit = \"true\"\n\n[run]\nrecord_timings = false\n",
+    )
+    .unwrap();
+    hookmaster(&["run", "pre-commit"]).success();
+    assert!(!repo.join(".git/hookmaster-timings.jsonl").exists());
+}
+
+#[test]
+fn test_run_assert_exit() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("hookmaster.toml"),
+        "commit-msg = \"exit 1\"\npre-commit = \"exit 0\"\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let mut command = Command::cargo_bin("hookmaster").unwrap();
+        command.arg("run").args(args).current_dir(temp_dir.path());
+        command.assert()
+    };
+
+    run(&["--assert-exit", "1", "commit-msg", "bad-message"])
+        .success()
+        .stdout(predicate::str::contains(
+            "[PASS] Hook exited with expected code 1",
+        ));
+    run(&["--assert-exit", "1", "pre-commit"])
+        .failure()
+        .stdout(predicate::str::contains(
+            "[FAIL] Hook exited with 0, expected 1",
+        ));
+    // A hook without commands exits with 0, as it does in git
+    run(&["--assert-exit", "1", "pre-push"])
+        .failure()
+        .stdout(predicate::str::contains(
+            "[FAIL] Hook exited with 0, expected 1",
+        ));
+}
+
+#[test]
+fn test_ci_generate() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("hookmaster.toml"),
+        "pre-commit = \"cargo fmt --check\"\n",
+    )
+    .unwrap();
+    let ci = |args: &[&str]| {
+        let mut command = Command::cargo_bin("hookmaster").unwrap();
+        command
+            .args(["ci", "generate"])
+            .args(args)
+            .current_dir(temp_dir.path());
+        command.assert()
+    };
+    let workflow = temp_dir.path().join(".github/workflows/hookmaster.yml");
+
+    ci(&["github", "--print"])
+        .success()
+        .stdout(predicate::str::contains("hook: [pre-commit]"));
+    assert!(!workflow.exists());
+
+    ci(&["github"])
+        .success()
+        .stdout("Wrote .github/workflows/hookmaster.yml\n");
+    assert!(fs::read_to_string(&workflow)
+        .unwrap()
+        .contains("hook: [pre-commit]"));
+
+    fs::write(&workflow, "edited").unwrap();
+    ci(&["github"])
+        .failure()
+        .stderr(predicate::str::contains("use --force to overwrite it"));
+    assert_eq!(fs::read_to_string(&workflow).unwrap(), "edited");
+    ci(&["github", "--force"]).success();
+    assert_ne!(fs::read_to_string(&workflow).unwrap(), "edited");
+}
+
+#[test]
+fn test_init_on_ci_leaves_out_local_hooks() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    assert!(std::process::Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(repo)
+        .status()
+        .unwrap()
+        .success());
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("init")
+        .current_dir(repo)
+        .env("GITHUB_ACTIONS", "true")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Created sample hookmaster.toml for GitHub Actions\n",
+        ));
+
+    let config = fs::read_to_string(repo.join("hookmaster.toml")).unwrap();
+    assert!(config.contains("pre-commit"));
+    assert!(!config.contains("commit-msg"));
+    let hooks = repo.join(".git/hooks");
+    assert!(hooks.join("pre-commit").exists());
+    assert!(hooks.join("pre-push").exists());
+    assert!(!hooks.join("prepare-commit-msg").exists());
+}
+
+#[test]
+fn test_add_tag_records_repositories_in_registry() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().join("workspace");
+    for repo in ["api", "web"] {
+        fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+    }
+    let config_dir = temp_dir.path().join("config");
+    let hookmaster = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("hookmaster").unwrap();
+        cmd.env("XDG_CONFIG_HOME", &config_dir).args(args);
+        cmd
+    };
+
+    hookmaster(&["add", "--tag", "team=backend"])
+        .arg(root.join("api"))
+        .assert()
+        .success();
+    hookmaster(&["add", "--tag", "team=frontend"])
+        .arg(root.join("web"))
+        .assert()
+        .success();
+    assert!(config_dir.join("hookmaster/registry.json").exists());
+
+    hookmaster(&["registry", "list", "--tag", "team=backend"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("api  [team=backend]"))
+        .stdout(predicate::str::contains("web").not());
+
+    fs::remove_dir_all(root.join("web")).unwrap();
+    hookmaster(&["registry", "clean"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Removed 1 entry for repositories that no longer exist",
+        ));
+    hookmaster(&["registry", "list", "--tag", "team=frontend"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("No repositories recorded in"));
+}
+
+#[test]
+fn test_push_requires_ticket_in_pushed_commits() {
+    let temp_dir = TempDir::new().unwrap();
+    let remote = temp_dir.path().join("remote.git");
+    let repo = temp_dir.path().join("repo");
+    let git = |dir: &std::path::Path, args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .env("PATH", path_with_hookmaster())
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
+    };
+    assert!(git(temp_dir.path(), &["init", "-q", "--bare", "remote.git"]).0);
+    assert!(git(temp_dir.path(), &["init", "-q", "-b", "main", "repo"]).0);
+    fs::write(
+        repo.join("hookmaster.toml"),
+        "[protect]\nrequire_ticket_in_pushed_commits = true\n",
+    )
+    .unwrap();
+    let remote_url = remote.display().to_string();
+    for args in [
+        &["remote", "add", "origin", &remote_url][..],
+        &["add", "hookmaster.toml"],
+        &["commit", "-q", "-m", "JIRA-1: Add hookmaster configuration"],
+        &["push", "-q", "origin", "main"],
+    ] {
+        assert!(git(&repo, args).0, "git {args:?}");
+    }
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("add")
+        .arg(&repo)
+        .assert()
+        .success();
+
+    for args in [
+        &["checkout", "-q", "-b", "feature"][..],
+        &[
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "JIRA-2: Add the parser",
+        ],
+        &["commit", "-q", "--allow-empty", "-m", "Quick fix"],
+    ] {
+        assert!(git(&repo, args).0, "git {args:?}");
+    }
+    let (pushed, stderr) = git(&repo, &["push", "-q", "origin", "feature"]);
+    assert!(!pushed);
+    assert!(stderr.contains("Refusing to push commits that reference no ticket"));
+    assert!(stderr.contains(" Quick fix\n"));
+    assert!(!stderr.contains("Add the parser"));
+    assert!(stderr.contains("git rebase -i"));
+
+    let amend = [
+        "commit",
+        "-q",
+        "--amend",
+        "--allow-empty",
+        "-m",
+        "JIRA-2: Quick fix",
+    ];
+    assert!(git(&repo, &amend).0);
+    let (pushed, stderr) = git(&repo, &["push", "-q", "origin", "feature"]);
+    assert!(pushed, "{stderr}");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_add_remote_copies_server_hooks_over_ssh() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let remote = temp_dir.path().join("server.git");
+    assert!(std::process::Command::new("git")
+        .args(["init", "-q", "--bare"])
+        .arg(&remote)
+        .status()
+        .unwrap()
+        .success());
+
+    // Stand-ins for ssh and scp that run on this machine instead of a host
+    let bin = temp_dir.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let scripts = [
+        ("ssh", "#!/bin/sh\nshift 2\nexec sh -c \"$1\"\n"),
+        (
+            "scp",
+            "#!/bin/sh\nshift 3\nfor last; do :; done\nwhile [ $# -gt 1 ]; do cp \"$1\" \"${last#*:}\"; shift; done\n",
+        ),
+    ];
+    for (name, script) in scripts {
+        let file = bin.join(name);
+        fs::write(&file, script).unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = std::env::join_paths(
+        std::iter::once(bin).chain(std::env::split_paths(&path_with_hookmaster())),
+    )
+    .unwrap();
+
+    Command::cargo_bin("hookmaster")
+        .unwrap()
+        .arg("add")
+        .arg("--remote")
+        .arg(format!("git@example.com:{}", remote.display()))
+        .env("PATH", path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Installed 3 hooks to git@example.com:{}/hooks: pre-receive, update, post-receive",
+            remote.display()
+        )));
+    let hooks = remote.join("hooks");
+    assert_eq!(
+        fs::read_to_string(hooks.join("pre-receive")).unwrap(),
+        "#!/bin/sh\nhookmaster run pre-receive \"$@\"\n"
+    );
+    assert!(hooks.join("update").is_file());
+    assert!(hooks.join("post-receive").is_file());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_passes_sigterm_on_to_commands() {
+    use std::time::{Duration, Instant};
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    let terminate = |signal: &str, config: &str| {
+        let _ = fs::remove_file(repo.join("started"));
+        fs::write(repo.join("hookmaster.toml"), config).unwrap();
+        let mut hookmaster = headless_hookmaster()
+            .args(["run", "pre-commit"])
+            .current_dir(repo)
+            .spawn()
+            .unwrap();
+        while !repo.join("started").exists() {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let started = Instant::now();
+        assert!(std::process::Command::new("kill")
+            .args([signal, &hookmaster.id().to_string()])
+            .status()
+            .unwrap()
+            .success());
+        let status = hookmaster.wait().unwrap();
+        (status.code(), started.elapsed())
+    };
+
+    // The command cleans up and exits, and hookmaster with it
+    let (code, elapsed) = terminate(
+        "-TERM",
+        "pre-commit = \"trap 'echo stopped > stopped.txt; exit 1' TERM; touch started; sleep 10 & wait\"\n",
+    );
+    assert_eq!(code, Some(143));
+    assert!(elapsed < Duration::from_secs(5));
+    assert_eq!(
+        fs::read_to_string(repo.join("stopped.txt")).unwrap(),
+        "stopped\n"
+    );
+
+    // A command ignoring the signal is killed after the grace period
+    let (code, elapsed) = terminate(
+        "-TERM",
+        "pre-commit = \"trap '' TERM; touch started; while :; do sleep 0.1; done\"\n[settings]\ntimeout-kill-grace = 1\n",
+    );
+    assert_eq!(code, Some(143));
+    assert!(elapsed >= Duration::from_secs(1));
+    assert!(elapsed < Duration::from_secs(5));
+
+    // Ctrl-C doesn't reach commands in their own process group, so
+    // hookmaster passes SIGINT on as well
+    let (code, elapsed) = terminate(
+        "-INT",
+        "pre-commit = \"trap 'echo interrupted > stopped.txt; exit 1' INT; touch started; sleep 10 & wait\"\n",
+    );
+    assert_eq!(code, Some(130));
+    assert!(elapsed < Duration::from_secs(5));
+    assert_eq!(
+        fs::read_to_string(repo.join("stopped.txt")).unwrap(),
+        "interrupted\n"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_stopped_by_signal_restores_stashed_changes() {
+    use std::time::Duration;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    git(&["init", "-q"]);
+    fs::write(repo.join("lib.rs"), "committed\n").unwrap();
+    git(&["add", "lib.rs"]);
+    git(&["commit", "-q", "-m", "Initial commit"]);
+    fs::write(repo.join("lib.rs"), "unstaged edit\n").unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        "pre-commit = \"trap '' TERM; touch started; while :; do sleep 0.1; done\"\n[settings]\ntimeout-kill-grace = 1\n",
+    )
+    .unwrap();
+
+    // The command ignores the signal, so it is killed after the grace period
+    let mut hookmaster = headless_hookmaster()
+        .args(["run", "--stash-unstaged", "pre-commit"])
+        .current_dir(repo)
+        .spawn()
+        .unwrap();
+    while !repo.join("started").exists() {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // The hook sees the committed content while the edit is stashed
+    assert_eq!(
+        fs::read_to_string(repo.join("lib.rs")).unwrap(),
+        "committed\n"
+    );
+    assert!(std::process::Command::new("kill")
+        .args(["-TERM", &hookmaster.id().to_string()])
+        .status()
+        .unwrap()
+        .success());
+
+    assert_eq!(hookmaster.wait().unwrap().code(), Some(143));
+    assert_eq!(
+        fs::read_to_string(repo.join("lib.rs")).unwrap(),
+        "unstaged edit\n"
+    );
+    assert_eq!(git(&["stash", "list"]), "");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_run_commands_read_the_terminal() {
+    use std::io::Write;
+    use std::os::fd::FromRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::time::{Duration, Instant};
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::write(
+        repo.join("hookmaster.toml"),
+        "pre-commit = \"read x < /dev/tty; echo got=$x > got.txt\"\n",
+    )
+    .unwrap();
+
+    // SAFETY: plain calls setting up a pseudo-terminal owned by the test
+    let (master, slave) = unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(master >= 0);
+        assert_eq!(libc::grantpt(master), 0);
+        assert_eq!(libc::unlockpt(master), 0);
+        let mut name = [0 as libc::c_char; 128];
+        assert_eq!(libc::ptsname_r(master, name.as_mut_ptr(), name.len()), 0);
+        let slave = std::ffi::CStr::from_ptr(name.as_ptr()).to_owned();
+        (std::fs::File::from_raw_fd(master), slave)
+    };
+    let mut command = std::process::Command::new(assert_cmd::cargo::cargo_bin("hookmaster"));
+    command.args(["run", "pre-commit"]).current_dir(repo);
+    // SAFETY: setsid and open are async-signal-safe
+    unsafe {
+        // A session of its own, whose controlling terminal is the pty
+        command.pre_exec(move || {
+            if libc::setsid() < 0 || libc::open(slave.as_ptr(), libc::O_RDWR) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut hookmaster = command.spawn().unwrap();
+    (&master).write_all(b"typed\n").unwrap();
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = hookmaster.try_wait().unwrap() {
+            break status;
+        }
+        if started.elapsed() > Duration::from_secs(10) {
+            hookmaster.kill().unwrap();
+            panic!("the command reading the terminal never finished");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    assert!(status.success());
+    assert_eq!(
+        fs::read_to_string(repo.join("got.txt")).unwrap(),
+        "got=typed\n"
+    );
+}
@@ -0,0 +1,18 @@
+#!/bin/sh
+# Generated by `hookmaster eject` (hookmaster VERSION) from hookmaster.toml at 1a2b3c4.
+# Runs without hookmaster; change the configuration and eject again instead of editing.
+# Set HOOKMASTER_SKIP=1 to skip this hook.
+
+if [ "${HOOKMASTER_SKIP:-}" = "1" ]; then
+    exit 0
+fi
+
+# fmt
+(
+cargo fmt --check
+) || exit $?
+
+# clippy
+(
+cargo clippy -- -D warnings
+) || exit $?
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Generated by `hookmaster eject` (hookmaster VERSION) from package.json.
+# Runs without hookmaster; change the configuration and eject again instead of editing.
+# Set HOOKMASTER_SKIP=1 to skip this hook.
+
+if [ "${HOOKMASTER_SKIP:-}" = "1" ]; then
+    exit 0
+fi
+
+if [ -d node_modules/.bin ]; then PATH="$PWD/node_modules/.bin:$PATH"; fi
+export PATH
+
+# licenses
+(
+docker run --rm -v "$PWD:/work" -w /work acme/licensecheck:1 licensecheck --deny GPL
+) || exit $?
+
+# test
+(
+bash --noprofile --norc -c 'npm test'
+) || exit $?
@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::Path;
+
+use hookmaster::output::{RepoResult, RepoStatus, Reporter};
+use hookmaster::{CommitMessageProcessor, GitHook, GitHooksConfig, HookError, HookManager};
+use tempfile::TempDir;
+
+/// Reporter collecting the results of `add`
+#[derive(Default)]
+struct Collect(Vec<RepoResult>);
+
+impl Reporter for Collect {
+    fn discovered(&mut self, _path: &Path, _count: usize) {}
+
+    fn repo_started(&mut self, _repo: &Path) {}
+
+    fn repo_finished(&mut self, result: &RepoResult) {
+        self.0.push(result.clone());
+    }
+
+    fn finished(&mut self, _results: &[RepoResult]) {}
+}
+
+#[test]
+fn test_add_hooks_to_path() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path().join("project");
+    fs::create_dir_all(repo.join(".git")).unwrap();
+
+    let mut reporter = Collect::default();
+    HookManager::new()
+        .add_hooks_to_path(temp_dir.path(), &mut reporter)
+        .unwrap();
+
+    assert_eq!(reporter.0.len(), 1);
+    assert_eq!(reporter.0[0].status, RepoStatus::Installed);
+    let hook_file = repo
+        .join(".git/hooks")
+        .join(GitHook::PreCommit.to_filename());
+    assert_eq!(
+        fs::read_to_string(hook_file).unwrap(),
+        GitHook::PreCommit.generate_script_content()
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_hook_with_config_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let marker = temp_dir.path().join("ran");
+    let config_path = temp_dir.path().join("hookmaster.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "pre-commit = \"touch '{}'\"\npre-push = \"exit 4\"\n",
+            marker.display()
+        ),
+    )
+    .unwrap();
+
+    let config = GitHooksConfig::load_from_file(&config_path).unwrap();
+    assert!(config.has_active_hook("pre-commit"));
+    assert!(config.validate().is_empty());
+
+    let hook_manager = HookManager::new().with_config_path(Some(config_path));
+    hook_manager.run_hook("pre-commit", &[]).unwrap();
+    assert!(marker.exists());
+
+    match hook_manager.run_hook("pre-push", &[]) {
+        Err(HookError::ExecutionFailed { hook, exit_code }) => {
+            assert_eq!(hook, "pre-push");
+            assert_eq!(exit_code, 4);
+        }
+        other => panic!("Expected ExecutionFailed, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_commit_message_from_branch() {
+    let processor = CommitMessageProcessor::new();
+    assert_eq!(
+        processor.format_commit_message_from_branch("feature/PROJ-42-add-library-api"),
+        Some("PROJ-42: Add Library Api".to_string())
+    );
+}